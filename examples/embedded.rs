@@ -0,0 +1,87 @@
+// Runs the bot from code against a local stand-in for the game server:
+//
+//     cargo run --example embedded
+//
+// The server plays one short game; the bot trades it with its own strategy, reports the
+// finish through a hook and is shut down once the game is over.
+#[allow(dead_code)]
+#[path = "../tests/common/server.rs"]
+mod server;
+
+use futures::future::BoxFuture;
+use optiva_ws::bot::TradingBot;
+use optiva_ws::config::Config;
+use optiva_ws::hooks::Hooks;
+use optiva_ws::strategy::{MarketContext, Strategy};
+use optiva_ws::summary::GameSummary;
+use server::{MockServer, Scenario};
+
+// Leans the way the server's forecast points, sized by how sure it is
+struct FollowForecast;
+
+impl Strategy for FollowForecast {
+    fn name(&self) -> &'static str {
+        "follow_forecast"
+    }
+
+    fn signal(&self, ctx: &MarketContext) -> f64 {
+        ctx.forecast.clamp(-1.0, 1.0)
+    }
+
+    fn scale(&self, _ctx: &MarketContext) -> f64 {
+        1.0
+    }
+}
+
+struct PrintFinishes;
+
+impl Hooks for PrintFinishes {
+    fn on_finish<'a>(&'a mut self, summary: &'a GameSummary) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            println!(
+                "Game over on connection {}: {:?}",
+                summary.conn_id, summary.final_pnl
+            );
+        })
+    }
+}
+
+// Run on the crate's runtime. An application already inside one awaits run() there
+// instead, with a matching runtime feature.
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    optiva_ws::rt::block_on(run())
+}
+
+async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let server = MockServer::bind().await;
+
+    // Nothing written to disk, and trading from the first update
+    let mut config = Config::default();
+    config.transcript.enabled = false;
+    config.summary.enabled = false;
+    config.curve.enabled = false;
+    config.persist.enabled = false;
+    config.warm_up.updates = 0;
+
+    let handle = TradingBot::from_config(config)
+        .url(server.url.clone())
+        .player_id("embedded-example")
+        .connections(1)
+        .strategy(Box::new(FollowForecast))
+        .hooks(Box::new(PrintFinishes))
+        .run()
+        .await?;
+
+    let mut game = server.accept().await;
+    game.play(&Scenario::load("bot_game")).await;
+
+    let snapshot = handle.snapshot().await;
+    println!("Total PnL while running: {:.2}", snapshot.total_pnl);
+
+    for summary in handle.shutdown().await {
+        for game in &summary.games {
+            println!("{}", game);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,346 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use crate::calibration::{format_calibration, Calibration};
+use crate::journal::{analyze, JournalEntry, JournalStats, LoadedJournal};
+use crate::state::StrategyParams;
+use crate::strategy::{correlation, DecisionMode, Driver};
+use crate::summary::Attribution;
+
+// Trades are bucketed by |combined_signal| this wide, the last bucket taking the rest
+pub const SIGNAL_BUCKET_WIDTH: f64 = 0.25;
+const SIGNAL_BUCKETS: usize = 4;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ConnectionPnl {
+    pub account: String,
+    pub conn_id: usize,
+    #[serde(flatten)]
+    pub stats: JournalStats,
+}
+
+// PnL changes over one wall clock hour, across every connection
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct HourPnl {
+    // Unix time the hour starts at
+    pub hour: i64,
+    pub decisions: usize,
+    pub trades_sent: usize,
+    pub pnl_change: f64,
+}
+
+// Sent trades with a signal strength in [min, max), judged by the PnL change after them
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SignalBucket {
+    pub min: f64,
+    // None for the last bucket, which has no upper bound
+    pub max: Option<f64>,
+    pub trades: usize,
+    pub wins: usize,
+    pub win_rate: Option<f64>,
+}
+
+// The PnL change at the state update after a kind of trade
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct FollowUp {
+    pub trades: usize,
+    pub mean_pnl_change: Option<f64>,
+}
+
+// Everything `analyze` reports about a journal
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct JournalReport {
+    pub decisions: usize,
+    // Lines skipped because they couldn't be parsed
+    pub malformed: usize,
+    pub connections: Vec<ConnectionPnl>,
+    pub hours: Vec<HourPnl>,
+    pub signal_buckets: Vec<SignalBucket>,
+    pub after_puzzle_trades: FollowUp,
+    pub after_regular_trades: FollowUp,
+    // Each PnL change credited to the decision it was made on, by what drove that
+    pub drivers: Attribution,
+    // Of each strategy decision's combined_signal against the PnL change that followed
+    pub signal_correlation: Option<f64>,
+    // Spread of the strategy decisions' inputs and signals. The journal doesn't keep
+    // the scaling, so saturation is judged at the default params.
+    pub calibration: Calibration,
+}
+
+// A decision paired with the PnL change reported at the next state update on its
+// connection. Puzzle and manual entries have no PnL change of their own, so they only
+// wait; a state update without one starts a new session, and whatever waited is dropped.
+fn followed_by(entries: &[JournalEntry]) -> Vec<(&JournalEntry, f64)> {
+    let mut waiting: HashMap<(&str, usize), Vec<&JournalEntry>> = HashMap::new();
+    let mut pairs = Vec::new();
+    for entry in entries {
+        let key = (entry.account.as_str(), entry.conn_id);
+        let waiting = waiting.entry(key).or_default();
+        if entry.mode.is_strategy() {
+            match entry.pnl_change {
+                Some(change) => pairs.extend(waiting.drain(..).map(|earlier| (earlier, change))),
+                None => waiting.clear(),
+            }
+        }
+        waiting.push(entry);
+    }
+    pairs
+}
+
+// Each PnL change paired with the one decision it's credited to, the latest on its
+// connection before it, as the bot credits it live
+fn credited_to(entries: &[JournalEntry]) -> Vec<(&JournalEntry, f64)> {
+    let mut latest: HashMap<(&str, usize), &JournalEntry> = HashMap::new();
+    let mut pairs = Vec::new();
+    for entry in entries {
+        let key = (entry.account.as_str(), entry.conn_id);
+        if entry.mode.is_strategy() {
+            if let (Some(change), Some(earlier)) = (entry.pnl_change, latest.get(&key)) {
+                pairs.push((*earlier, change));
+            }
+        }
+        latest.insert(key, entry);
+    }
+    pairs
+}
+
+fn follow_up(changes: impl Iterator<Item = f64>) -> FollowUp {
+    let changes: Vec<f64> = changes.collect();
+    FollowUp {
+        trades: changes.len(),
+        mean_pnl_change: (!changes.is_empty())
+            .then(|| changes.iter().sum::<f64>() / changes.len() as f64),
+    }
+}
+
+pub fn analyze_journal(journal: &LoadedJournal) -> JournalReport {
+    let entries = &journal.entries;
+    let connections = analyze(entries)
+        .into_iter()
+        .map(|((account, conn_id), stats)| ConnectionPnl {
+            account,
+            conn_id,
+            stats,
+        })
+        .collect();
+
+    let mut hours: BTreeMap<i64, HourPnl> = BTreeMap::new();
+    for entry in entries {
+        let hour = (entry.timestamp / 3600.0).floor() as i64 * 3600;
+        let row = hours.entry(hour).or_insert(HourPnl {
+            hour,
+            decisions: 0,
+            trades_sent: 0,
+            pnl_change: 0.0,
+        });
+        row.decisions += 1;
+        row.trades_sent += usize::from(entry.sent);
+        row.pnl_change += entry.pnl_change.unwrap_or(0.0);
+    }
+
+    let followed = followed_by(entries);
+    let mut signal_buckets: Vec<SignalBucket> = (0..SIGNAL_BUCKETS)
+        .map(|bucket| SignalBucket {
+            min: bucket as f64 * SIGNAL_BUCKET_WIDTH,
+            max: (bucket + 1 < SIGNAL_BUCKETS).then(|| (bucket + 1) as f64 * SIGNAL_BUCKET_WIDTH),
+            trades: 0,
+            wins: 0,
+            win_rate: None,
+        })
+        .collect();
+    let regular_trades = || {
+        followed
+            .iter()
+            .filter(|(entry, _)| entry.sent && entry.mode.is_strategy())
+    };
+    for (entry, change) in regular_trades() {
+        let bucket =
+            ((entry.combined_signal.abs() / SIGNAL_BUCKET_WIDTH) as usize).min(SIGNAL_BUCKETS - 1);
+        signal_buckets[bucket].trades += 1;
+        signal_buckets[bucket].wins += usize::from(*change > 0.0);
+    }
+    for bucket in &mut signal_buckets {
+        bucket.win_rate = (bucket.trades > 0).then(|| bucket.wins as f64 / bucket.trades as f64);
+    }
+
+    let signals: Vec<(f64, f64)> = followed
+        .iter()
+        .filter(|(entry, _)| entry.mode.is_strategy())
+        .map(|(entry, change)| (entry.combined_signal, *change))
+        .collect();
+
+    // Manual trades and older journals have no driver to credit
+    let mut drivers = Attribution::default();
+    for (entry, change) in credited_to(entries) {
+        if let Some(driver) = entry.driver {
+            drivers.credit(driver, change, entry.sent);
+        }
+    }
+
+    let params = StrategyParams::default();
+    let mut calibration = Calibration::default();
+    for entry in entries.iter().filter(|entry| entry.mode.is_strategy()) {
+        calibration.observe(
+            entry.momentum,
+            entry.forecast,
+            entry.combined_signal,
+            &params,
+        );
+    }
+
+    JournalReport {
+        decisions: entries.len(),
+        malformed: journal.malformed,
+        connections,
+        hours: hours.into_values().collect(),
+        signal_buckets,
+        after_puzzle_trades: follow_up(
+            followed
+                .iter()
+                .filter(|(entry, _)| entry.sent && entry.mode == DecisionMode::Puzzle)
+                .map(|(_, change)| *change),
+        ),
+        after_regular_trades: follow_up(regular_trades().map(|(_, change)| *change)),
+        drivers,
+        signal_correlation: correlation(&signals),
+        calibration,
+    }
+}
+
+// Unix time as "YYYY-MM-DD HH:00" in UTC
+fn utc_hour(time: i64) -> String {
+    // Days to a civil date, after Howard Hinnant's days_from_civil inverse
+    let days = time.div_euclid(86_400);
+    let hour = time.rem_euclid(86_400) / 3600;
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02} {:02}:00", year, month, day, hour)
+}
+
+fn percent(rate: Option<f64>) -> String {
+    rate.map_or("n/a".to_string(), |rate| format!("{:.0}%", rate * 100.0))
+}
+
+fn dollars(value: Option<f64>) -> String {
+    value.map_or("n/a".to_string(), |value| format!("${:.2}", value))
+}
+
+pub fn format_report(report: &JournalReport) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{} decisions, {} malformed lines skipped",
+        report.decisions, report.malformed
+    );
+
+    let _ = writeln!(out, "\nPnL by connection:");
+    let _ = writeln!(
+        out,
+        "  {:<12} {:>5} {:>9} {:>7} {:>7} {:>11} {:>10} {:>9}",
+        "account", "conn", "decisions", "trades", "volume", "PnL change", "last PnL", "|signal|"
+    );
+    for connection in &report.connections {
+        let stats = &connection.stats;
+        let _ = writeln!(
+            out,
+            "  {:<12} {:>5} {:>9} {:>7} {:>7} {:>11.2} {:>10} {:>9.2}",
+            connection.account,
+            connection.conn_id,
+            stats.decisions,
+            stats.trades_sent,
+            stats.volume_sent,
+            stats.total_pnl_change,
+            dollars(stats.last_pnl),
+            stats.mean_abs_signal
+        );
+    }
+
+    let _ = writeln!(out, "\nPnL by hour (UTC):");
+    let _ = writeln!(
+        out,
+        "  {:<16} {:>9} {:>7} {:>11}",
+        "hour", "decisions", "trades", "PnL change"
+    );
+    for hour in &report.hours {
+        let _ = writeln!(
+            out,
+            "  {:<16} {:>9} {:>7} {:>11.2}",
+            utc_hour(hour.hour),
+            hour.decisions,
+            hour.trades_sent,
+            hour.pnl_change
+        );
+    }
+
+    let _ = writeln!(out, "\nWin rate by |signal|:");
+    let _ = writeln!(out, "  {:<11} {:>7} {:>6}", "|signal|", "trades", "win%");
+    for bucket in &report.signal_buckets {
+        let range = match bucket.max {
+            Some(max) => format!("{:.2}-{:.2}", bucket.min, max),
+            None => format!("{:.2}+", bucket.min),
+        };
+        let _ = writeln!(
+            out,
+            "  {:<11} {:>7} {:>6}",
+            range,
+            bucket.trades,
+            percent(bucket.win_rate)
+        );
+    }
+
+    let _ = writeln!(out, "\nPnL change at the next update:");
+    for (kind, follow_up) in [
+        ("puzzle trades", &report.after_puzzle_trades),
+        ("regular trades", &report.after_regular_trades),
+    ] {
+        let _ = writeln!(
+            out,
+            "  {:<15} {} over {} trades",
+            kind,
+            dollars(follow_up.mean_pnl_change),
+            follow_up.trades
+        );
+    }
+    let _ = writeln!(out, "\nPnL by driver:");
+    let _ = writeln!(
+        out,
+        "  {:<9} {:>7} {:>6} {:>11}",
+        "driver", "trades", "win%", "PnL change"
+    );
+    for driver in [Driver::Momentum, Driver::Forecast, Driver::Puzzle] {
+        let stats = report.drivers.get(driver);
+        let _ = writeln!(
+            out,
+            "  {:<9} {:>7} {:>6} {:>11.2}",
+            driver,
+            stats.trades,
+            percent(stats.win_rate()),
+            stats.pnl
+        );
+    }
+    let _ = writeln!(
+        out,
+        "\nCorrelation of combined_signal with the next PnL change: {}",
+        report
+            .signal_correlation
+            .map_or("n/a".to_string(), |correlation| format!(
+                "{:.3}",
+                correlation
+            ))
+    );
+    let _ = write!(out, "\n{}", format_calibration(&report.calibration));
+    out
+}
@@ -0,0 +1,481 @@
+use rand::distributions::Distribution;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Deserialize;
+use statrs::distribution::Normal;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::clock::{Clock, ManualClock};
+use crate::config::Config;
+use crate::handler::ConnectionHandler;
+#[cfg(feature = "auto-optimize")]
+use crate::optimizer::optimize_strategy;
+use crate::paper::CostConfig;
+use crate::protocol::{
+    ClientEvent, ClientMessage, ConnectionAck, FinishData, PuzzleData, ServerError, ServerEvent,
+    StateUpdate,
+};
+use crate::risk::RiskEvent;
+use crate::state::SharedState;
+use crate::strategy::StrategyKind;
+
+// Price changes summed into the synthetic momentum, and what they're scaled by so it
+// lands around the strategy's momentum thresholds
+const MOMENTUM_WINDOW: usize = 5;
+const MOMENTUM_SCALE: f64 = 5.0;
+// Updates a puzzle's impact is spread over
+const PUZZLE_SPREAD: usize = 3;
+// Seconds of simulated time per state update
+const UPDATE_SECS: f64 = 1.0;
+
+// Synthetic games for comparing strategies offline
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct BacktestConfig {
+    pub games: usize,
+    pub updates_per_game: usize,
+    // Same seed, same games
+    pub seed: u64,
+    pub start_price: f64,
+    // Mean price change per update
+    pub drift: f64,
+    // Standard deviation of the price change per update
+    pub volatility: f64,
+    pub position_limit: i32,
+    // Chance of a puzzle before each update
+    pub puzzle_chance: f64,
+    // Typical size of a puzzle's price move, up or down
+    pub puzzle_impact: f64,
+    // Standard deviation of the forecast's error about the next price change
+    pub forecast_noise: f64,
+    // Each is run over the same games
+    pub strategies: Vec<StrategyKind>,
+}
+
+impl Default for BacktestConfig {
+    fn default() -> Self {
+        BacktestConfig {
+            games: 1000,
+            updates_per_game: 100,
+            seed: 42,
+            start_price: 100.0,
+            drift: 0.0,
+            volatility: 0.5,
+            position_limit: 3,
+            puzzle_chance: 0.02,
+            puzzle_impact: 3.0,
+            forecast_noise: 0.5,
+            strategies: vec![
+                StrategyKind::Blend,
+                StrategyKind::ForecastOnly,
+                StrategyKind::MeanReversion,
+                StrategyKind::BlendFade,
+            ],
+        }
+    }
+}
+
+// One update of a synthetic game, with the puzzle announced just before it if any
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tick {
+    pub price: f64,
+    pub forecast: f64,
+    pub momentum: f64,
+    pub puzzle: Option<f64>,
+}
+
+// A game's whole price path, fixed before any strategy sees it so every strategy
+// trades the same market
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntheticGame {
+    pub seed: u64,
+    pub ticks: Vec<Tick>,
+}
+
+impl SyntheticGame {
+    pub fn generate(config: &BacktestConfig, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let noise = Normal::new(0.0, 1.0).expect("unit normal");
+
+        // Price changes first, changes[t] taking tick t's price to the next one, so each
+        // forecast can be about the change that follows it
+        let mut puzzles = vec![None; config.updates_per_game];
+        let mut changes = vec![0.0; config.updates_per_game];
+        for t in 0..config.updates_per_game {
+            changes[t] += config.drift + config.volatility * noise.sample(&mut rng);
+            if rng.gen_bool(config.puzzle_chance.clamp(0.0, 1.0)) {
+                let direction = if rng.gen_bool(0.5) { 1.0 } else { -1.0 };
+                let impact = direction * config.puzzle_impact * rng.gen_range(0.5..1.5);
+                puzzles[t] = Some(impact);
+                for change in changes.iter_mut().skip(t).take(PUZZLE_SPREAD) {
+                    *change += impact / PUZZLE_SPREAD as f64;
+                }
+            }
+        }
+
+        let mut price = config.start_price;
+        let mut recent = VecDeque::with_capacity(MOMENTUM_WINDOW);
+        let mut ticks = Vec::with_capacity(config.updates_per_game);
+        for (&change, &puzzle) in changes.iter().zip(&puzzles) {
+            ticks.push(Tick {
+                price,
+                forecast: change + config.forecast_noise * noise.sample(&mut rng),
+                momentum: recent.iter().sum::<f64>() * MOMENTUM_SCALE,
+                puzzle,
+            });
+            if recent.len() == MOMENTUM_WINDOW {
+                recent.pop_front();
+            }
+            recent.push_back(change);
+            price += change;
+        }
+        SyntheticGame { seed, ticks }
+    }
+}
+
+// Seeds for each game, drawn from the run's seed
+pub fn game_seeds(config: &BacktestConfig) -> Vec<u64> {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    (0..config.games).map(|_| rng.gen()).collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameResult {
+    pub strategy: &'static str,
+    pub game: usize,
+    pub seed: u64,
+    // After the config's trading costs
+    pub final_pnl: f64,
+    pub costs: f64,
+    pub trades: usize,
+    // Trades the simulated exchange turned down for breaking the limit
+    pub rejected: usize,
+}
+
+// Distribution of final PnL for one strategy
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrategyReport {
+    pub strategy: &'static str,
+    pub games: usize,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub min: f64,
+    pub p5: f64,
+    pub median: f64,
+    pub p95: f64,
+    pub max: f64,
+    // Share of games that ended up
+    pub win_rate: f64,
+    pub mean_trades: f64,
+}
+
+impl StrategyReport {
+    pub fn from_results(strategy: &'static str, results: &[GameResult]) -> Self {
+        let mut pnls: Vec<f64> = results.iter().map(|result| result.final_pnl).collect();
+        pnls.sort_by(f64::total_cmp);
+        let games = pnls.len();
+        let n = games.max(1) as f64;
+        let mean = pnls.iter().sum::<f64>() / n;
+        let variance = pnls.iter().map(|pnl| (pnl - mean).powi(2)).sum::<f64>() / n;
+        let percentile = |p: f64| {
+            pnls.get(((games.saturating_sub(1)) as f64 * p).round() as usize)
+                .copied()
+                .unwrap_or(0.0)
+        };
+        StrategyReport {
+            strategy,
+            games,
+            mean,
+            std_dev: variance.sqrt(),
+            min: percentile(0.0),
+            p5: percentile(0.05),
+            median: percentile(0.5),
+            p95: percentile(0.95),
+            max: percentile(1.0),
+            win_rate: pnls.iter().filter(|pnl| **pnl > 0.0).count() as f64 / n,
+            mean_trades: results.iter().map(|result| result.trades).sum::<usize>() as f64 / n,
+        }
+    }
+}
+
+// Run every configured strategy over the same seeded games, each with its own shared
+// state so the optimizer learns from that strategy's games alone
+pub async fn run_backtest(config: &Config) -> (Vec<StrategyReport>, Vec<GameResult>) {
+    let backtest = &config.backtest;
+    let games: Vec<SyntheticGame> = game_seeds(backtest)
+        .into_iter()
+        .map(|seed| SyntheticGame::generate(backtest, seed))
+        .collect();
+
+    let mut reports = Vec::with_capacity(backtest.strategies.len());
+    let mut results = Vec::new();
+    for &kind in &backtest.strategies {
+        let mut config = config.clone();
+        config.strategies = vec![kind];
+        let strategy_results = play_games(Arc::new(config), &games, true).await;
+        reports.push(StrategyReport::from_results(
+            kind.build().name(),
+            &strategy_results,
+        ));
+        results.extend(strategy_results);
+    }
+    (reports, results)
+}
+
+// Play the games in order on one connection running the config's first strategy, with
+// the optimizer adjusting params between updates as it does live unless told not to
+pub async fn play_games(
+    config: Arc<Config>,
+    games: &[SyntheticGame],
+    optimize: bool,
+) -> Vec<GameResult> {
+    play(config, games, optimize)
+        .await
+        .into_iter()
+        .map(|(result, _)| result)
+        .collect()
+}
+
+// Play the games as play_games does, keeping every decision's price, PnL and trade so
+// each game can be replayed through the risk checks
+pub async fn record_games(
+    config: Arc<Config>,
+    games: &[SyntheticGame],
+    optimize: bool,
+) -> Vec<Vec<RiskEvent>> {
+    play(config, games, optimize)
+        .await
+        .into_iter()
+        .map(|(_, events)| events)
+        .collect()
+}
+
+async fn play(
+    config: Arc<Config>,
+    games: &[SyntheticGame],
+    optimize: bool,
+) -> Vec<(GameResult, Vec<RiskEvent>)> {
+    let mut config = Arc::unwrap_or_clone(config);
+    config.dry_run = false;
+    let optimize = optimize && config.optimizer_enabled();
+    // Nor the pass the handler runs after each game
+    config.optimizer.enabled = optimize;
+    // Any exploration repeats with the games
+    config.optimizer.seed.get_or_insert(config.backtest.seed);
+    let config = Arc::new(config);
+    let position_limit = config.backtest.position_limit;
+    let clock = Arc::new(ManualClock::new(0.0));
+    let shared_state = Arc::new(SharedState::with_clock(&config, clock.clone()));
+    let mut handler = ConnectionHandler::new(0, Arc::clone(&config), Arc::clone(&shared_state));
+
+    let mut results = Vec::with_capacity(games.len());
+    for (index, game) in games.iter().enumerate() {
+        let mut exchange = Exchange::new(position_limit, config.costs.clone(), optimize);
+        exchange
+            .play(&mut handler, &shared_state, &clock, game, &config.player_id)
+            .await;
+        let result = GameResult {
+            strategy: handler.strategy_name(),
+            game: index,
+            seed: game.seed,
+            final_pnl: exchange.pnl - exchange.costs,
+            costs: exchange.costs,
+            trades: exchange.trades,
+            rejected: exchange.rejected,
+        };
+        results.push((result, exchange.events));
+    }
+    results
+}
+
+// Stands in for the game server: sends the handler the same events the live game
+// would and fills its trades at the current price. Like the server, the PnL it reports
+// leaves out the modelled costs, which are kept aside for the result.
+struct Exchange {
+    position_limit: i32,
+    cost_model: CostConfig,
+    // Run the optimizer after every update
+    #[cfg_attr(not(feature = "auto-optimize"), allow(dead_code))]
+    optimize: bool,
+    position: i32,
+    cash: f64,
+    price: f64,
+    pnl: f64,
+    costs: f64,
+    trades: usize,
+    rejected: usize,
+    // Each state update and puzzle, and where the position went from it
+    events: Vec<RiskEvent>,
+}
+
+impl Exchange {
+    fn new(position_limit: i32, cost_model: CostConfig, optimize: bool) -> Self {
+        Exchange {
+            position_limit,
+            cost_model,
+            optimize,
+            position: 0,
+            cash: 0.0,
+            price: 0.0,
+            pnl: 0.0,
+            costs: 0.0,
+            trades: 0,
+            rejected: 0,
+            events: Vec::new(),
+        }
+    }
+
+    #[cfg_attr(not(feature = "auto-optimize"), allow(unused_variables))]
+    async fn play(
+        &mut self,
+        handler: &mut ConnectionHandler,
+        shared_state: &SharedState,
+        clock: &ManualClock,
+        game: &SyntheticGame,
+        player_id: &str,
+    ) {
+        handler.start_session().await;
+        self.send(
+            handler,
+            ServerEvent::Connection(ConnectionAck::accepting(player_id)),
+        )
+        .await;
+
+        let updates = game.ticks.len();
+        for (t, tick) in game.ticks.iter().enumerate() {
+            clock.advance(UPDATE_SECS);
+            if let Some(impact) = tick.puzzle {
+                let position = self.position;
+                self.send(
+                    handler,
+                    ServerEvent::Puzzle(PuzzleData {
+                        impact: Some(impact),
+                        stage: None,
+                        question: None,
+                        id: None,
+                    }),
+                )
+                .await;
+                self.record(clock, None, position);
+            }
+
+            self.price = tick.price;
+            self.pnl = self.cash + self.position as f64 * self.price;
+            let update = StateUpdate {
+                price: tick.price,
+                price_forecast: tick.forecast,
+                momentum: tick.momentum,
+                position: self.position,
+                position_limit: self.position_limit,
+                pnl: self.pnl,
+                updates_remaining: Some((updates - t - 1) as u32),
+                instrument: None,
+                stage: None,
+                sequence: None,
+            };
+            let position = self.position;
+            self.send(handler, ServerEvent::State(update)).await;
+            self.record(clock, Some(self.pnl), position);
+            #[cfg(feature = "auto-optimize")]
+            if self.optimize {
+                optimize_strategy(shared_state).await;
+            }
+        }
+
+        self.pnl = self.cash + self.position as f64 * self.price;
+        self.send(
+            handler,
+            ServerEvent::Finish(FinishData {
+                pnl: Some(self.pnl),
+                ..FinishData::default()
+            }),
+        )
+        .await;
+        // Only wanted by the live loop
+        handler.take_summary();
+    }
+
+    fn record(&mut self, clock: &ManualClock, pnl: Option<f64>, position: i32) {
+        self.events.push(RiskEvent {
+            timestamp: clock.now(),
+            price: self.price,
+            pnl,
+            position,
+            target: self.position,
+        });
+    }
+
+    // Hand the handler an event, then fill whatever it trades in reply. Trades past
+    // the limit are refused with an error event, as the live server does.
+    async fn send(&mut self, handler: &mut ConnectionHandler, event: ServerEvent) {
+        let mut events = VecDeque::from([event]);
+        while let Some(event) = events.pop_front() {
+            let mut outbox: Vec<ClientMessage> = Vec::new();
+            handler.handle_event(event, &mut outbox).await;
+            for message in outbox {
+                let ClientEvent::Trade(trade) = message.event else {
+                    continue;
+                };
+                if (self.position + trade.volume).abs() > self.position_limit {
+                    self.rejected += 1;
+                    events.push_back(ServerEvent::Error(ServerError {
+                        message: "volume exceeds position limit".to_string(),
+                    }));
+                    continue;
+                }
+                self.position += trade.volume;
+                self.cash -= trade.volume as f64 * self.price;
+                self.costs += self.cost_model.cost(trade.volume);
+                self.trades += 1;
+            }
+        }
+    }
+}
+
+// Aligned table of the reports, one strategy per row
+pub fn format_reports(reports: &[StrategyReport]) -> String {
+    let mut table = format!(
+        "  {:<16} {:>9} {:>9} {:>9} {:>9} {:>9} {:>9} {:>9} {:>6} {:>7}\n",
+        "strategy", "mean", "std dev", "min", "p5", "median", "p95", "max", "win%", "trades"
+    );
+    for report in reports {
+        let _ = writeln!(
+            table,
+            "  {:<16} {:>9.2} {:>9.2} {:>9.2} {:>9.2} {:>9.2} {:>9.2} {:>9.2} {:>6.1} {:>7.1}",
+            report.strategy,
+            report.mean,
+            report.std_dev,
+            report.min,
+            report.p5,
+            report.median,
+            report.p95,
+            report.max,
+            report.win_rate * 100.0,
+            report.mean_trades
+        );
+    }
+    table
+}
+
+// One row per strategy per game
+pub fn write_results_csv(path: &Path, results: &[GameResult]) -> io::Result<()> {
+    let mut csv = String::from("strategy,game,seed,final_pnl,costs,trades,rejected\n");
+    for result in results {
+        let _ = writeln!(
+            csv,
+            "{},{},{},{},{},{},{}",
+            result.strategy,
+            result.game,
+            result.seed,
+            result.final_pnl,
+            result.costs,
+            result.trades,
+            result.rejected
+        );
+    }
+    std::fs::write(path, csv)
+}
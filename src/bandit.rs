@@ -0,0 +1,238 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use statrs::distribution::{Beta, Normal};
+use std::collections::BTreeMap;
+use tracing::info;
+
+use crate::state::{Sizing, StrategyParams};
+
+// Spread of a pnl_change assumed before any arm has two of them
+const PRIOR_VARIANCE: f64 = 1.0;
+
+// Discrete strategy configurations handed out to connections by Thompson sampling, in
+// place of the optimizer nudging the weights
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct BanditConfig {
+    pub posterior: Posterior,
+    // No arms leaves the bandit out and the optimizer nudging the weights as before
+    pub arms: Vec<ArmConfig>,
+}
+
+// What an arm's rewards are taken to be
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Posterior {
+    // The mean pnl_change, with a Normal posterior around what's been seen
+    #[default]
+    Normal,
+    // Whether a pnl_change was a gain, with a Beta posterior on the chance of one
+    Beta,
+}
+
+// One configuration: the global params with whichever of these are set replaced
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArmConfig {
+    pub name: String,
+    pub momentum_weight: Option<f64>,
+    pub forecast_weight: Option<f64>,
+    pub aggressive_factor: Option<f64>,
+    pub deadband: Option<f64>,
+    pub strong_momentum_threshold: Option<f64>,
+    pub medium_momentum_threshold: Option<f64>,
+    pub sizing: Option<Sizing>,
+}
+
+impl ArmConfig {
+    pub fn apply(&self, params: &mut StrategyParams) {
+        let overrides = [
+            (self.momentum_weight, &mut params.momentum_weight),
+            (self.forecast_weight, &mut params.forecast_weight),
+            (self.aggressive_factor, &mut params.aggressive_factor),
+            (self.deadband, &mut params.deadband),
+            (
+                self.strong_momentum_threshold,
+                &mut params.strong_momentum_threshold,
+            ),
+            (
+                self.medium_momentum_threshold,
+                &mut params.medium_momentum_threshold,
+            ),
+        ];
+        for (value, param) in overrides {
+            if let Some(value) = value {
+                *param = value;
+            }
+        }
+        if let Some(sizing) = self.sizing {
+            params.sizing = sizing;
+        }
+    }
+}
+
+// The pnl_changes seen under one arm, which is all either posterior needs
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct ArmStats {
+    pub count: usize,
+    pub mean: f64,
+    // Sum of squared differences from the mean
+    pub m2: f64,
+    pub wins: usize,
+}
+
+impl ArmStats {
+    pub fn observe(&mut self, pnl_change: f64) {
+        self.count += 1;
+        let delta = pnl_change - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (pnl_change - self.mean);
+        if pnl_change > 0.0 {
+            self.wins += 1;
+        }
+    }
+
+    pub fn variance(&self) -> Option<f64> {
+        (self.count >= 2).then(|| self.m2 / (self.count - 1) as f64)
+    }
+}
+
+// Which arm each connection trades and how every arm has paid so far
+#[derive(Debug)]
+pub struct Bandit {
+    rng: StdRng,
+    posterior: Posterior,
+    arms: Vec<ArmConfig>,
+    stats: Vec<ArmStats>,
+    assignments: BTreeMap<usize, usize>,
+}
+
+impl Bandit {
+    // Seeded like the explorer, so the draws can be repeated
+    pub fn new(config: &BanditConfig, seed: Option<u64>) -> Self {
+        Bandit {
+            rng: match seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            },
+            posterior: config.posterior,
+            arms: config.arms.clone(),
+            stats: vec![ArmStats::default(); config.arms.len()],
+            assignments: BTreeMap::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.arms.is_empty()
+    }
+
+    // The connection's arm, drawn on first asking
+    pub fn arm_for(&mut self, conn_id: usize) -> Option<&ArmConfig> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let index = match self.assignments.get(&conn_id) {
+            Some(&index) => index,
+            None => {
+                let index = self.draw();
+                self.assignments.insert(conn_id, index);
+                info!(conn_id, arm = %self.arms[index].name, "Drew a strategy arm");
+                index
+            }
+        };
+        Some(&self.arms[index])
+    }
+
+    // The connection's arm, without drawing one
+    pub fn assigned(&self, conn_id: usize) -> Option<&ArmConfig> {
+        let index = *self.assignments.get(&conn_id)?;
+        Some(&self.arms[index])
+    }
+
+    // Credit a pnl_change to the arm the connection was trading
+    pub fn observe(&mut self, conn_id: usize, pnl_change: f64) {
+        if let Some(&index) = self.assignments.get(&conn_id) {
+            self.stats[index].observe(pnl_change);
+        }
+    }
+
+    // Draw again for every connection that has an arm. Returns each connection's
+    // new arm.
+    pub fn redraw(&mut self) -> Vec<(usize, String)> {
+        let conn_ids: Vec<usize> = self.assignments.keys().copied().collect();
+        conn_ids
+            .into_iter()
+            .map(|conn_id| {
+                let index = self.draw();
+                self.assignments.insert(conn_id, index);
+                (conn_id, self.arms[index].name.clone())
+            })
+            .collect()
+    }
+
+    // One sample from each arm's posterior; the best sample wins
+    fn draw(&mut self) -> usize {
+        let pooled = self.pooled_variance();
+        let mut best = (0, f64::NEG_INFINITY);
+        for (index, stats) in self.stats.iter().enumerate() {
+            let sample = match self.posterior {
+                Posterior::Normal => {
+                    // One pseudo-observation of zero, so an unplayed arm sits at zero
+                    // with the widest spread
+                    let weight = stats.count as f64 + 1.0;
+                    let std_dev = (stats.variance().unwrap_or(pooled) / weight).sqrt();
+                    let mean = stats.mean * stats.count as f64 / weight;
+                    match Normal::new(mean, std_dev) {
+                        Ok(normal) => self.rng.sample(normal),
+                        Err(_) => mean,
+                    }
+                }
+                Posterior::Beta => {
+                    let losses = stats.count - stats.wins;
+                    let beta = Beta::new(stats.wins as f64 + 1.0, losses as f64 + 1.0)
+                        .expect("shape parameters are at least one");
+                    self.rng.sample(beta)
+                }
+            };
+            if sample > best.1 {
+                best = (index, sample);
+            }
+        }
+        best.0
+    }
+
+    // Spread of a pnl_change within arms, for arms with too few of their own
+    fn pooled_variance(&self) -> f64 {
+        let (m2, dof) = self
+            .stats
+            .iter()
+            .filter(|stats| stats.count >= 2)
+            .fold((0.0, 0), |(m2, dof), stats| {
+                (m2 + stats.m2, dof + stats.count - 1)
+            });
+        if dof > 0 && m2 > 0.0 {
+            m2 / dof as f64
+        } else {
+            PRIOR_VARIANCE
+        }
+    }
+
+    // Each arm's stats by name, to be saved with the params
+    pub fn posteriors(&self) -> BTreeMap<String, ArmStats> {
+        self.arms
+            .iter()
+            .zip(&self.stats)
+            .map(|(arm, stats)| (arm.name.clone(), *stats))
+            .collect()
+    }
+
+    // Pick up saved stats for the arms still configured under the same name
+    pub fn restore(&mut self, saved: &BTreeMap<String, ArmStats>) {
+        for (arm, stats) in self.arms.iter().zip(self.stats.iter_mut()) {
+            if let Some(saved) = saved.get(&arm.name) {
+                *stats = *saved;
+            }
+        }
+    }
+}
@@ -0,0 +1,478 @@
+use futures::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, info_span, warn, Instrument};
+
+use crate::caps::CapHits;
+use crate::clock::timestamp;
+use crate::config::{Config, ConfigError};
+use crate::connection::{handle_connection, Sinks};
+use crate::console::{run_console, Console};
+use crate::control::Control;
+use crate::divergence::run_divergence_monitor;
+use crate::hooks::{FileLogger, HookRunner, Hooks};
+use crate::journal::Journal;
+use crate::notify::Notifier;
+#[cfg(feature = "auto-optimize")]
+use crate::optimizer::run_optimizer;
+use crate::persist::{restore_params, save_params, SavedParams};
+use crate::reload::{run_reloader, Reloader, SetLogFilter};
+use crate::rt::net::TcpListener;
+use crate::rt::{self, Mutex};
+use crate::shutdown::Shutdown;
+use crate::state::{ConnectionPerformance, SharedState, StateSnapshot, StrategyParams};
+use crate::strategy::Strategy;
+use crate::summary::{FinishedGames, GameSummary};
+use crate::supervisor::supervise;
+use crate::transcript::Transcript;
+
+// The live bot, set up from code. Starts from the config's defaults (or a loaded
+// config) and runs every account's connections, optimizer and side tasks until the
+// handle it returns is shut down:
+//
+//     let handle = TradingBot::new()
+//         .url("ws://localhost:9000")
+//         .player_id("me")
+//         .connections(2)
+//         .run()
+//         .await?;
+//     let summaries = handle.shutdown().await;
+pub struct TradingBot {
+    config: Config,
+    strategy: Option<Arc<dyn Strategy>>,
+    hooks: Vec<Box<dyn Hooks>>,
+    // Watched for live settings when the config came from a file
+    config_file: Option<PathBuf>,
+    log_filter: Option<SetLogFilter>,
+}
+
+impl Default for TradingBot {
+    fn default() -> Self {
+        TradingBot::from_config(Config::default())
+    }
+}
+
+impl TradingBot {
+    pub fn new() -> Self {
+        TradingBot::default()
+    }
+
+    pub fn from_config(config: Config) -> Self {
+        TradingBot {
+            config,
+            strategy: None,
+            hooks: Vec::new(),
+            config_file: None,
+            log_filter: None,
+        }
+    }
+
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.config.url = url.into();
+        self.config.urls.clear();
+        self
+    }
+
+    pub fn player_id(mut self, player_id: impl Into<String>) -> Self {
+        self.config.player_id = player_id.into();
+        self
+    }
+
+    pub fn connections(mut self, connections: usize) -> Self {
+        self.config.connections = connections;
+        self
+    }
+
+    // Every connection trades with this, in place of the config's strategies
+    pub fn strategy(mut self, strategy: Box<dyn Strategy>) -> Self {
+        self.strategy = Some(Arc::from(strategy));
+        self
+    }
+
+    // Called at the points each connection reaches, after any the config switches on
+    pub fn hooks(mut self, hooks: Box<dyn Hooks>) -> Self {
+        self.hooks.push(hooks);
+        self
+    }
+
+    // Apply edits to this file's live settings while running, when [reload] allows
+    pub fn config_file(mut self, path: &Path) -> Self {
+        self.config_file = Some(path.to_path_buf());
+        self
+    }
+
+    // How a reloaded log_level is put into effect
+    pub fn log_filter(mut self, log_filter: SetLogFilter) -> Self {
+        self.log_filter = Some(log_filter);
+        self
+    }
+
+    // Start every connection. Fails before connecting if the config isn't valid.
+    pub async fn run(self) -> Result<BotHandle, ConfigError> {
+        self.config.validate()?;
+        // One random tag per live run for "{run}" in alias templates
+        let config = Arc::new(self.config.with_run_tag());
+        info!(
+            accounts = config.accounts.len().max(1),
+            connections = config.connections,
+            "Starting trading bot"
+        );
+        if config.dry_run {
+            warn!("DRY RUN: trades are simulated on a paper book and never sent");
+        }
+        if !config.puzzles_enabled() {
+            info!("Puzzles are off, puzzle events will be logged and skipped");
+        }
+        if !config.optimizer_enabled() {
+            info!("Optimizer is off, the strategy params stay as they started");
+        }
+        if !config.trading_enabled {
+            warn!("Kill switch on from the config, nothing will trade until it's resumed");
+        }
+
+        // Record every frame so the session can be replayed later
+        let transcript = if config.transcript.enabled {
+            match Transcript::create(&config.transcript.dir, timestamp()).await {
+                Ok(transcript) => {
+                    info!(path = %transcript.path().display(), "Recording transcript");
+                    Some(Arc::new(transcript))
+                }
+                Err(e) => {
+                    warn!(error = %e, "Not recording transcript");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        // Whatever is dropped to keep memory bounded is counted here, for every account
+        let cap_hits = Arc::new(CapHits::default());
+
+        // Every decision goes to the journal writer task
+        let (journal, journal_writer) = if config.journal.enabled {
+            match Journal::spawn(&config.journal).await {
+                Ok((journal, writer)) => {
+                    info!(path = %config.journal.path.display(), "Journaling decisions");
+                    (
+                        Some(journal.with_cap_hits(Arc::clone(&cap_hits))),
+                        Some(writer),
+                    )
+                }
+                Err(e) => {
+                    warn!(error = %e, "Not journaling decisions");
+                    (None, None)
+                }
+            }
+        } else {
+            (None, None)
+        };
+
+        // Big events go to the webhook from the notifier task
+        let (notifier, notifier_task) = match Notifier::spawn(&config.notify) {
+            Some((notifier, task)) => {
+                info!("Posting alerts to webhook");
+                (
+                    Some(notifier.with_cap_hits(Arc::clone(&cap_hits))),
+                    Some(task),
+                )
+            }
+            None => (None, None),
+        };
+
+        // Hooks run on a task of their own, so none of them can hold up a trade
+        let mut registered: Vec<Box<dyn Hooks>> = Vec::new();
+        if let Some(path) = &config.hooks.log_path {
+            match FileLogger::open(path).await {
+                Ok(logger) => {
+                    info!(path = %path.display(), "Logging hook events");
+                    registered.push(Box::new(logger));
+                }
+                Err(e) => warn!(path = %path.display(), error = %e, "Not logging hook events"),
+            }
+        }
+        registered.extend(self.hooks);
+        let (hooks, hooks_task) = match HookRunner::spawn(registered) {
+            Some((hooks, task)) => (Some(hooks.with_cap_hits(Arc::clone(&cap_hits))), Some(task)),
+            None => (None, None),
+        };
+
+        // State per account, kept apart so one player's params never steer another's.
+        // The kill switch and the cap counts are all they share.
+        let mut accounts: Vec<(Arc<Config>, Arc<SharedState>)> = Vec::new();
+        for account in config.accounts() {
+            let mut shared_state = SharedState::new(&account).with_cap_hits(Arc::clone(&cap_hits));
+            if let Some((_, first)) = accounts.first() {
+                shared_state = shared_state.with_kill_switch(first.kill_switch());
+            }
+            // Start from what a previous run's optimizer learned
+            if account.persist.enabled {
+                if let Some(saved) = restore_params(&account.persist, shared_state.now()) {
+                    *shared_state.strategy_params.write().await = saved.params;
+                    shared_state.bandit.lock().await.restore(&saved.arms);
+                }
+            }
+            accounts.push((Arc::new(account), Arc::new(shared_state)));
+        }
+
+        let shutdown = Shutdown::new();
+        let games: FinishedGames = Arc::new(Mutex::new(Vec::new()));
+
+        // Start each account's connections in parallel, each restarted by a supervisor
+        // if it panics, with the account's optimizer on its own timer rather than after
+        // every state update. Everything they log carries the account. Connections
+        // start a stagger apart across all accounts, so the server doesn't see them all
+        // at once.
+        let mut connections = Vec::new();
+        let mut background = Vec::new();
+        let mut started = 0;
+        for (account, account_state) in &accounts {
+            let span = info_span!("account", account = %account.account);
+            for i in 0..account.connections {
+                let delay = config.startup.delay(started, rand::random::<f64>());
+                started += 1;
+                let config_clone = Arc::clone(account);
+                let state_clone = Arc::clone(account_state);
+                let shutdown_clone = shutdown.clone();
+                let sinks = Sinks {
+                    transcript: transcript.clone(),
+                    journal: journal.clone(),
+                    notifier: notifier.clone(),
+                    hooks: hooks.clone(),
+                    games: Some(Arc::clone(&games)),
+                    strategy: self.strategy.clone(),
+                };
+                let supervised = supervise(
+                    i,
+                    account.supervisor.clone(),
+                    Arc::clone(account_state),
+                    shutdown.clone(),
+                    move || {
+                        handle_connection(
+                            i,
+                            Arc::clone(&config_clone),
+                            Arc::clone(&state_clone),
+                            shutdown_clone.clone(),
+                            sinks.clone(),
+                        )
+                    },
+                );
+                let waiting = shutdown.clone();
+                let handle = rt::spawn(
+                    async move {
+                        // Only the first start waits; a restart after a panic goes
+                        // straight in
+                        if !delay.is_zero() {
+                            debug!(
+                                conn_id = i,
+                                delay_ms = delay.as_millis() as u64,
+                                "Staggering start"
+                            );
+                            let _ = rt::timeout(delay, waiting.wait()).await;
+                            if waiting.is_triggered() {
+                                return;
+                            }
+                        }
+                        supervised.await
+                    }
+                    .instrument(span.clone()),
+                );
+                connections.push(handle);
+            }
+
+            // Connections of the same account play the same game, so they're checked
+            // against each other
+            if account.divergence.enabled {
+                background.push(rt::spawn(
+                    run_divergence_monitor(
+                        Arc::clone(account_state),
+                        account.divergence.clone(),
+                        shutdown.clone(),
+                    )
+                    .instrument(span.clone()),
+                ));
+            }
+
+            #[cfg(feature = "auto-optimize")]
+            if config.optimizer_enabled() {
+                background.push(rt::spawn(
+                    run_optimizer(
+                        Arc::clone(account_state),
+                        account.persist.clone(),
+                        notifier.clone(),
+                        journal
+                            .clone()
+                            .map(|journal| journal.for_account(&account.account)),
+                        shutdown.clone(),
+                    )
+                    .instrument(span),
+                ));
+            }
+        }
+
+        // Apply edits to the config file's live settings without reconnecting
+        if let Some(path) = self.config_file.filter(|_| config.reload.enabled) {
+            match Reloader::new(&path, &accounts) {
+                Ok(mut reloader) => {
+                    info!(path = %path.display(), "Watching config for changes");
+                    if let Some(log_filter) = self.log_filter {
+                        reloader = reloader.with_log_filter(log_filter);
+                    }
+                    background.push(rt::spawn(run_reloader(
+                        reloader,
+                        Duration::from_secs_f64(config.reload.poll_secs),
+                        shutdown.clone(),
+                    )));
+                }
+                Err(e) => {
+                    warn!(path = %path.display(), error = %e, "Not watching config for changes")
+                }
+            }
+        }
+
+        // Look at and steer the running bot over HTTP. It watches the first account.
+        if config.control.enabled {
+            match TcpListener::bind(&config.control.bind).await {
+                Ok(listener) => {
+                    info!(bind = %config.control.bind, "Control endpoint listening");
+                    let mut control =
+                        Control::new(Arc::clone(&accounts[0].1), config.control.token.clone());
+                    if let Some(notifier) = &notifier {
+                        control = control.with_notifier(notifier.clone());
+                    }
+                    if !config.optimizer_enabled() {
+                        control = control.with_fixed_params();
+                    }
+                    background.push(rt::spawn(control.serve(listener, shutdown.clone())));
+                }
+                Err(e) => {
+                    warn!(bind = %config.control.bind, error = %e, "Not starting control endpoint")
+                }
+            }
+        }
+
+        // Manual trades and the kill switch from stdin, for the first account as well
+        if config.console.enabled {
+            info!("Console reading commands from stdin, type help for the list");
+            background.push(rt::spawn(run_console(
+                Console::new(Arc::clone(&accounts[0].1)),
+                BufReader::new(rt::stdin()),
+                rt::stdout(),
+                shutdown.clone(),
+            )));
+        }
+
+        Ok(BotHandle {
+            accounts,
+            shutdown,
+            connections,
+            background,
+            journal,
+            notifier,
+            hooks,
+            games,
+            writers: [journal_writer, notifier_task, hooks_task],
+        })
+    }
+}
+
+// A running bot. Dropping it leaves the bot running until the process ends; shut it
+// down to stop it cleanly.
+pub struct BotHandle {
+    accounts: Vec<(Arc<Config>, Arc<SharedState>)>,
+    shutdown: Shutdown,
+    connections: Vec<rt::JoinHandle<()>>,
+    // The optimizers, reloader, control endpoint and console, which stop on shutdown
+    background: Vec<rt::JoinHandle<()>>,
+    journal: Option<Journal>,
+    notifier: Option<Notifier>,
+    hooks: Option<HookRunner>,
+    games: FinishedGames,
+    // The journal writer, notifier and hook tasks, each finishing once its sink is gone
+    writers: [Option<rt::JoinHandle<()>>; 3],
+}
+
+// How one account's run went, as the handle reports it once the bot has stopped
+#[derive(Debug, Clone)]
+pub struct AccountSummary {
+    pub account: String,
+    // By conn_id
+    pub connections: Vec<(usize, ConnectionPerformance)>,
+    pub params: StrategyParams,
+    // Every game its connections finished, in the order they finished
+    pub games: Vec<GameSummary>,
+}
+
+impl BotHandle {
+    // Triggering it stops the bot, as shutdown does
+    pub fn shutdown_signal(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    // The first account's state, which the control endpoint and dashboard watch
+    pub fn shared_state(&self) -> Arc<SharedState> {
+        Arc::clone(&self.accounts[0].1)
+    }
+
+    pub async fn snapshot(&self) -> StateSnapshot {
+        self.accounts[0].1.snapshot().await
+    }
+
+    // Stop every connection and wait for everything to wind down
+    pub async fn shutdown(self) -> Vec<AccountSummary> {
+        self.shutdown.trigger();
+        self.wait().await
+    }
+
+    // Wait for the bot to stop, from a shutdown or from every connection giving up.
+    // Anything queued for the journal, webhook and hooks is sent first, and params are
+    // saved where [persist] says.
+    pub async fn wait(self) -> Vec<AccountSummary> {
+        futures::future::join_all(self.connections).await;
+        // Connections can also stop by giving up, so make sure the rest stops too
+        self.shutdown.trigger();
+        futures::future::join_all(self.background).await;
+
+        // The writers flush and stop once the last handle to them is gone
+        drop((self.journal, self.notifier, self.hooks));
+        for writer in self.writers.into_iter().flatten() {
+            writer.await;
+        }
+
+        let games = std::mem::take(&mut *self.games.lock().await);
+        let mut summaries = Vec::with_capacity(self.accounts.len());
+        for (account, account_state) in &self.accounts {
+            if account.persist.enabled {
+                let saved = SavedParams::snapshot(account_state).await;
+                match save_params(&account.persist.path, &saved).await {
+                    Ok(()) => {
+                        info!(path = %account.persist.path.display(), "Saved strategy params")
+                    }
+                    Err(e) => {
+                        warn!(path = %account.persist.path.display(), error = %e, "Error saving params")
+                    }
+                }
+            }
+            let mut connections: Vec<_> = account_state
+                .connection_performance
+                .lock()
+                .await
+                .iter()
+                .map(|(conn_id, perf)| (*conn_id, perf.clone()))
+                .collect();
+            connections.sort_unstable_by_key(|(conn_id, _)| *conn_id);
+            summaries.push(AccountSummary {
+                account: account.account.clone(),
+                connections,
+                params: account_state.strategy_params.read().await.clone(),
+                games: games
+                    .iter()
+                    .filter(|game| game.account == account.account)
+                    .cloned()
+                    .collect(),
+            });
+        }
+        summaries
+    }
+}
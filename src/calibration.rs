@@ -0,0 +1,168 @@
+use serde::Serialize;
+use std::fmt::Write;
+
+use crate::state::StrategyParams;
+use crate::strategy::{forecast_signal, momentum_signal};
+
+// A squashed signal past this is as good as maxed out
+pub const SATURATION: f64 = 0.95;
+
+// Upper edges of the buckets for each series, by absolute value. Anything past the
+// last edge lands in one open bucket, so memory stays fixed however long the session.
+const MOMENTUM_EDGES: [f64; 8] = [1.0, 2.0, 3.0, 5.0, 10.0, 20.0, 30.0, 50.0];
+const FORECAST_EDGES: [f64; 7] = [0.05, 0.1, 0.25, 0.5, 1.0, 1.5, 2.0];
+const SIGNAL_EDGES: [f64; 6] = [0.1, 0.25, 0.5, 0.75, 0.9, SATURATION];
+
+// Counts of |value| per bucket
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub edges: Vec<f64>,
+    // One more than the edges, the last for values past every edge
+    pub counts: Vec<usize>,
+    pub total: usize,
+    pub max_abs: f64,
+    sum_abs: f64,
+}
+
+impl Histogram {
+    pub fn new(edges: &[f64]) -> Self {
+        Histogram {
+            edges: edges.to_vec(),
+            counts: vec![0; edges.len() + 1],
+            total: 0,
+            max_abs: 0.0,
+            sum_abs: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+        let value = value.abs();
+        let bucket = self
+            .edges
+            .iter()
+            .position(|&edge| value < edge)
+            .unwrap_or(self.edges.len());
+        self.counts[bucket] += 1;
+        self.total += 1;
+        self.max_abs = self.max_abs.max(value);
+        self.sum_abs += value;
+    }
+
+    pub fn mean_abs(&self) -> Option<f64> {
+        (self.total > 0).then(|| self.sum_abs / self.total as f64)
+    }
+}
+
+// How raw momentum and forecast, and the signal made of them, were spread over a
+// session, to tell whether the tanh scaling fits this game's ranges
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Calibration {
+    pub decisions: usize,
+    pub momentum: Histogram,
+    pub forecast: Histogram,
+    pub combined_signal: Histogram,
+    // Decisions whose squashed momentum, squashed forecast or combined signal was past
+    // SATURATION, each judged with the scaling in use at the time
+    pub momentum_saturated: usize,
+    pub forecast_saturated: usize,
+    pub signal_saturated: usize,
+    // The scaling at the last decision
+    pub momentum_divisor: f64,
+    pub forecast_multiplier: f64,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        let params = StrategyParams::default();
+        Calibration {
+            decisions: 0,
+            momentum: Histogram::new(&MOMENTUM_EDGES),
+            forecast: Histogram::new(&FORECAST_EDGES),
+            combined_signal: Histogram::new(&SIGNAL_EDGES),
+            momentum_saturated: 0,
+            forecast_saturated: 0,
+            signal_saturated: 0,
+            momentum_divisor: params.momentum_divisor,
+            forecast_multiplier: params.forecast_multiplier,
+        }
+    }
+}
+
+impl Calibration {
+    pub fn observe(&mut self, momentum: f64, forecast: f64, signal: f64, params: &StrategyParams) {
+        self.decisions += 1;
+        self.momentum.add(momentum);
+        self.forecast.add(forecast);
+        self.combined_signal.add(signal);
+        self.momentum_saturated +=
+            usize::from(momentum_signal(momentum, params).abs() > SATURATION);
+        self.forecast_saturated +=
+            usize::from(forecast_signal(forecast, params).abs() > SATURATION);
+        self.signal_saturated += usize::from(signal.abs() > SATURATION);
+        self.momentum_divisor = params.momentum_divisor;
+        self.forecast_multiplier = params.forecast_multiplier;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.decisions == 0
+    }
+
+    fn share(&self, count: usize) -> String {
+        if self.decisions == 0 {
+            return "n/a".to_string();
+        }
+        format!("{:.0}%", count as f64 * 100.0 / self.decisions as f64)
+    }
+}
+
+fn format_histogram(out: &mut String, name: &str, histogram: &Histogram) {
+    let _ = writeln!(
+        out,
+        "  |{}|: mean {}, max {:.3}",
+        name,
+        histogram
+            .mean_abs()
+            .map_or("n/a".to_string(), |mean| format!("{:.3}", mean)),
+        histogram.max_abs
+    );
+    let mut lower = 0.0;
+    for (bucket, &count) in histogram.counts.iter().enumerate() {
+        let range = match histogram.edges.get(bucket) {
+            Some(upper) => format!("{}-{}", lower, upper),
+            None => format!("{}+", lower),
+        };
+        let share = if histogram.total == 0 {
+            0.0
+        } else {
+            count as f64 * 100.0 / histogram.total as f64
+        };
+        let _ = writeln!(out, "    {:<10} {:>7} {:>5.1}%", range, count, share);
+        if let Some(&upper) = histogram.edges.get(bucket) {
+            lower = upper;
+        }
+    }
+}
+
+pub fn format_calibration(calibration: &Calibration) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "Signal calibration over {} decisions (momentum / {}, forecast * {}):",
+        calibration.decisions, calibration.momentum_divisor, calibration.forecast_multiplier
+    );
+    format_histogram(&mut out, "momentum", &calibration.momentum);
+    format_histogram(&mut out, "forecast", &calibration.forecast);
+    format_histogram(&mut out, "combined_signal", &calibration.combined_signal);
+    let _ = writeln!(
+        out,
+        "  Saturated past |{}|: momentum {}, forecast {}, combined_signal {}",
+        SATURATION,
+        calibration.share(calibration.momentum_saturated),
+        calibration.share(calibration.forecast_saturated),
+        calibration.share(calibration.signal_saturated)
+    );
+    out
+}
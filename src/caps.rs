@@ -0,0 +1,85 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Finished games kept in memory for the summary at shutdown
+pub const MAX_FINISHED_GAMES: usize = 1000;
+
+// Everything held in memory that would otherwise grow with the length of the run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cap {
+    TradeHistory,
+    PerformanceHistory,
+    ForecastSamples,
+    GameLatencies,
+    FinishedGames,
+    // Records waiting on the journal writer
+    Journal,
+    // Events waiting on the hooks
+    Hooks,
+    // Alerts waiting on the webhook
+    Alerts,
+}
+
+// How many entries each cap has dropped, oldest first for the buffers and newest for
+// the queues, so a long session stays bounded without losing anything unnoticed.
+// Shared by every account, like the kill switch.
+#[derive(Debug, Default)]
+pub struct CapHits {
+    trade_history: AtomicUsize,
+    performance_history: AtomicUsize,
+    forecast_samples: AtomicUsize,
+    game_latencies: AtomicUsize,
+    finished_games: AtomicUsize,
+    journal: AtomicUsize,
+    hooks: AtomicUsize,
+    alerts: AtomicUsize,
+}
+
+impl CapHits {
+    pub fn hit(&self, cap: Cap) {
+        self.counter(cap).fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self, cap: Cap) -> usize {
+        self.counter(cap).load(Ordering::Relaxed)
+    }
+
+    pub fn counts(&self) -> CapCounts {
+        CapCounts {
+            trade_history: self.get(Cap::TradeHistory),
+            performance_history: self.get(Cap::PerformanceHistory),
+            forecast_samples: self.get(Cap::ForecastSamples),
+            game_latencies: self.get(Cap::GameLatencies),
+            finished_games: self.get(Cap::FinishedGames),
+            journal: self.get(Cap::Journal),
+            hooks: self.get(Cap::Hooks),
+            alerts: self.get(Cap::Alerts),
+        }
+    }
+
+    fn counter(&self, cap: Cap) -> &AtomicUsize {
+        match cap {
+            Cap::TradeHistory => &self.trade_history,
+            Cap::PerformanceHistory => &self.performance_history,
+            Cap::ForecastSamples => &self.forecast_samples,
+            Cap::GameLatencies => &self.game_latencies,
+            Cap::FinishedGames => &self.finished_games,
+            Cap::Journal => &self.journal,
+            Cap::Hooks => &self.hooks,
+            Cap::Alerts => &self.alerts,
+        }
+    }
+}
+
+// Entries dropped by each cap over the run, in the snapshot
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CapCounts {
+    pub trade_history: usize,
+    pub performance_history: usize,
+    pub forecast_samples: usize,
+    pub game_latencies: usize,
+    pub finished_games: usize,
+    pub journal: usize,
+    pub hooks: usize,
+    pub alerts: usize,
+}
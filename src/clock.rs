@@ -0,0 +1,150 @@
+use serde::Serialize;
+use std::ops::{Add, Sub};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+// Seconds on a clock that never goes backwards. Only the difference between two of
+// them means anything, so they're kept apart from wall-clock timestamps, which are
+// for display and records.
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Monotonic(pub f64);
+
+impl Monotonic {
+    pub fn secs(self) -> f64 {
+        self.0
+    }
+}
+
+// Seconds from one to the other
+impl Sub for Monotonic {
+    type Output = f64;
+
+    fn sub(self, earlier: Monotonic) -> f64 {
+        self.0 - earlier.0
+    }
+}
+
+impl Add<f64> for Monotonic {
+    type Output = Monotonic;
+
+    fn add(self, seconds: f64) -> Monotonic {
+        Monotonic(self.0 + seconds)
+    }
+}
+
+// Source of the current time, injectable so the strategy code can be tested
+pub trait Clock: Send + Sync {
+    // Wall-clock seconds since the epoch, for display, the journal and saved files
+    fn now(&self) -> f64;
+    // For measuring intervals: rate limits, cooldowns, the optimizer's timer
+    fn monotonic(&self) -> Monotonic;
+}
+
+// The highest value seen so far, so a source that jumps back reads as standing still
+#[derive(Debug)]
+pub struct NonDecreasing {
+    bits: AtomicU64,
+}
+
+impl NonDecreasing {
+    pub fn new(start: f64) -> Self {
+        NonDecreasing {
+            bits: AtomicU64::new(start.to_bits()),
+        }
+    }
+
+    // Note a reading, returning it or the last seen value if that was later
+    pub fn observe(&self, value: f64) -> f64 {
+        let previous = self
+            .bits
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |bits| {
+                (value > f64::from_bits(bits)).then_some(value.to_bits())
+            });
+        match previous {
+            Ok(_) => value,
+            Err(bits) => f64::from_bits(bits),
+        }
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.bits.load(Ordering::SeqCst))
+    }
+}
+
+// Wall clock backed by SystemTime, with intervals measured on Instant so an NTP
+// correction can't shrink or stretch them
+#[derive(Debug)]
+pub struct SystemClock {
+    started: Instant,
+    wall: NonDecreasing,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        SystemClock {
+            started: Instant::now(),
+            wall: NonDecreasing::new(f64::NEG_INFINITY),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> f64 {
+        self.wall.observe(timestamp())
+    }
+
+    fn monotonic(&self) -> Monotonic {
+        Monotonic(self.started.elapsed().as_secs_f64())
+    }
+}
+
+// Clock that only moves when told to, for tests and offline runs. Set back, the wall
+// clock follows but intervals hold where they were.
+#[derive(Debug)]
+pub struct ManualClock {
+    bits: AtomicU64,
+    latest: NonDecreasing,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        ManualClock::new(0.0)
+    }
+}
+
+impl ManualClock {
+    pub fn new(start: f64) -> Self {
+        ManualClock {
+            bits: AtomicU64::new(start.to_bits()),
+            latest: NonDecreasing::new(start),
+        }
+    }
+
+    pub fn set(&self, time: f64) {
+        self.bits.store(time.to_bits(), Ordering::SeqCst);
+        self.latest.observe(time);
+    }
+
+    pub fn advance(&self, seconds: f64) {
+        self.set(self.now() + seconds);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> f64 {
+        f64::from_bits(self.bits.load(Ordering::SeqCst))
+    }
+
+    fn monotonic(&self) -> Monotonic {
+        Monotonic(self.latest.get())
+    }
+}
+
+// Helper function for current wall-clock time. A system clock set before 1970 gives
+// negative seconds rather than a panic.
+pub fn timestamp() -> f64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(since_the_epoch) => since_the_epoch.as_secs_f64(),
+        Err(e) => -e.duration().as_secs_f64(),
+    }
+}
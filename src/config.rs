@@ -0,0 +1,891 @@
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing_subscriber::EnvFilter;
+
+use crate::backtest::BacktestConfig;
+use crate::bandit::BanditConfig;
+use crate::console::ConsoleConfig;
+use crate::control::ControlConfig;
+use crate::divergence::DivergenceConfig;
+use crate::forecast::ForecastConfig;
+use crate::history::HistoryConfig;
+use crate::hooks::HooksConfig;
+use crate::journal::JournalConfig;
+use crate::latency::LatencyConfig;
+use crate::lifecycle::HandshakeConfig;
+use crate::notify::NotifyConfig;
+use crate::paper::CostConfig;
+use crate::persist::PersistConfig;
+use crate::reload::ReloadConfig;
+use crate::risk::{
+    DuplicateConfig, ExposureConfig, HoldConfig, OutboundConfig, QuarantineConfig, RateLimitConfig,
+    RiskConfig, StalenessConfig, TradeBudgetConfig, WarmUpConfig, WindDownConfig,
+    RECENT_TRADE_OUTCOMES,
+};
+use crate::risk_eval::RiskEvalConfig;
+use crate::schedule::ScheduleConfig;
+use crate::state::StrategyParams;
+use crate::strategy::{
+    AgreementConfig, EnsembleConfig, OptimizerConfig, SignalMode, StrategyKind,
+    MIN_OPTIMIZER_SAMPLES,
+};
+use crate::supervisor::SupervisorConfig;
+use crate::tune::{TuneConfig, MAX_COMBINATIONS};
+
+pub const DEFAULT_CONFIG_PATH: &str = "bot.toml";
+// More than this and the game server starts dropping us
+pub const MAX_CONNECTIONS: usize = 32;
+
+// Settings loaded from the TOML config file at startup
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub url: String,
+    // Several endpoints instead of one url, spread across connections, each failing
+    // over to the next
+    #[serde(default)]
+    pub urls: Vec<String>,
+    // Required unless [[accounts]] are listed
+    #[serde(default)]
+    pub player_id: String,
+    // Several players run from one process, each with its own connections and state
+    #[serde(default)]
+    pub accounts: Vec<AccountConfig>,
+    // Which account a derived config belongs to; set by Config::accounts
+    #[serde(skip)]
+    pub account: String,
+    // What "{run}" in the alias template becomes; set by with_run_tag for live runs
+    #[serde(skip)]
+    pub run_tag: String,
+    #[serde(default = "default_alias_prefix")]
+    pub alias_prefix: String,
+    // Alias with "{id}" replaced by the connection number, and "{run}" by a random tag
+    // picked once per run so parallel runs don't collide; overrides alias_prefix
+    #[serde(default)]
+    pub alias_template: Option<String>,
+    // Aliases for the first connections, in order. The rest fall back to the template.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default = "default_connections")]
+    pub connections: usize,
+    // Simulate fills locally instead of sending trades (also set by --dry-run)
+    #[serde(default)]
+    pub dry_run: bool,
+    // Trade on puzzles (also turned off by --no-puzzles); a build without the puzzles
+    // feature never does
+    #[serde(default = "default_puzzles")]
+    pub puzzles: bool,
+    // Start with the kill switch off; the control endpoint can turn it on and off
+    #[serde(default = "default_trading_enabled")]
+    pub trading_enabled: bool,
+    // Log filter in RUST_LOG syntax, e.g. "info" or "info,optiva_ws::handler=debug".
+    // Takes over from RUST_LOG and the default level when set.
+    #[serde(default)]
+    pub log_level: Option<String>,
+    // Strategy per connection, repeated in order when there are more connections
+    #[serde(default = "default_strategies")]
+    pub strategies: Vec<StrategyKind>,
+    #[serde(default)]
+    pub strategy: StrategyParams,
+    #[serde(default)]
+    pub history: HistoryConfig,
+    #[serde(default)]
+    pub optimizer: OptimizerConfig,
+    #[serde(default)]
+    pub bandit: BanditConfig,
+    #[serde(default)]
+    pub schedule: ScheduleConfig,
+    #[serde(default)]
+    pub ensemble: EnsembleConfig,
+    #[serde(default)]
+    pub agreement: AgreementConfig,
+    #[serde(default)]
+    pub forecast: ForecastConfig,
+    #[serde(default)]
+    pub risk: RiskConfig,
+    #[serde(default)]
+    pub quarantine: QuarantineConfig,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    #[serde(default)]
+    pub outbound: OutboundConfig,
+    #[serde(default)]
+    pub exposure: ExposureConfig,
+    #[serde(default)]
+    pub trade_budget: TradeBudgetConfig,
+    #[serde(default)]
+    pub costs: CostConfig,
+    #[serde(default)]
+    pub warm_up: WarmUpConfig,
+    #[serde(default)]
+    pub wind_down: WindDownConfig,
+    #[serde(default)]
+    pub staleness: StalenessConfig,
+    #[serde(default)]
+    pub duplicates: DuplicateConfig,
+    #[serde(default)]
+    pub hold: HoldConfig,
+    #[serde(default)]
+    pub startup: StartupConfig,
+    #[serde(default)]
+    pub watchdog: WatchdogConfig,
+    #[serde(default)]
+    pub frames: FrameConfig,
+    #[serde(default)]
+    pub handshake: HandshakeConfig,
+    #[serde(default)]
+    pub supervisor: SupervisorConfig,
+    #[serde(default)]
+    pub divergence: DivergenceConfig,
+    #[serde(default)]
+    pub latency: LatencyConfig,
+    #[serde(default)]
+    pub transcript: TranscriptConfig,
+    #[serde(default)]
+    pub summary: SummaryConfig,
+    #[serde(default)]
+    pub curve: CurveConfig,
+    #[serde(default)]
+    pub journal: JournalConfig,
+    #[serde(default)]
+    pub persist: PersistConfig,
+    #[serde(default)]
+    pub notify: NotifyConfig,
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    #[serde(default)]
+    pub control: ControlConfig,
+    #[serde(default)]
+    pub console: ConsoleConfig,
+    #[serde(default)]
+    pub reload: ReloadConfig,
+    #[serde(default)]
+    pub backtest: BacktestConfig,
+    #[serde(default)]
+    pub tune: TuneConfig,
+    #[serde(default)]
+    pub risk_eval: RiskEvalConfig,
+}
+
+// One player run alongside the others, on its own url if it has one
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AccountConfig {
+    pub player_id: String,
+    // Labels the account's logs, journal rows and summaries, and prefixes its aliases
+    pub alias: String,
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+// Where per-game summaries are appended for comparing games
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SummaryConfig {
+    pub enabled: bool,
+    pub path: PathBuf,
+}
+
+impl Default for SummaryConfig {
+    fn default() -> Self {
+        SummaryConfig {
+            enabled: true,
+            path: PathBuf::from("summaries.jsonl"),
+        }
+    }
+}
+
+// Where each game's PnL curve is written, one CSV per game and connection
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CurveConfig {
+    pub enabled: bool,
+    pub dir: PathBuf,
+}
+
+impl Default for CurveConfig {
+    fn default() -> Self {
+        CurveConfig {
+            enabled: true,
+            dir: PathBuf::from("curves"),
+        }
+    }
+}
+
+// Where raw message transcripts for replay are written
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TranscriptConfig {
+    pub enabled: bool,
+    pub dir: PathBuf,
+}
+
+impl Default for TranscriptConfig {
+    fn default() -> Self {
+        TranscriptConfig {
+            enabled: true,
+            dir: PathBuf::from("transcripts"),
+        }
+    }
+}
+
+// How long a connection may stay silent before we ping it, then give up on it
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchdogConfig {
+    pub read_timeout_secs: f64,
+    pub ping_grace_secs: f64,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        WatchdogConfig {
+            read_timeout_secs: 30.0,
+            ping_grace_secs: 10.0,
+        }
+    }
+}
+
+impl WatchdogConfig {
+    pub fn read_timeout(&self) -> Duration {
+        Duration::from_secs_f64(self.read_timeout_secs)
+    }
+
+    pub fn ping_grace(&self) -> Duration {
+        Duration::from_secs_f64(self.ping_grace_secs)
+    }
+}
+
+// Frames a connection won't read, and how many of them it takes before the session is
+// given up on as poisoned
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct FrameConfig {
+    // Text frames longer than this are dropped unparsed
+    pub max_bytes: usize,
+    // Frames dropped or failing to parse over the last minute before reconnecting
+    pub max_errors_per_minute: usize,
+}
+
+impl Default for FrameConfig {
+    fn default() -> Self {
+        FrameConfig {
+            max_bytes: 64 * 1024,
+            max_errors_per_minute: 20,
+        }
+    }
+}
+
+// Connections are started one after another rather than all at once, which the
+// server can take for a flood
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StartupConfig {
+    // Between one connection's start and the next
+    pub stagger_ms: f64,
+    // Up to this much more, at random, for each connection
+    pub jitter_ms: f64,
+}
+
+impl Default for StartupConfig {
+    fn default() -> Self {
+        StartupConfig {
+            stagger_ms: 300.0,
+            jitter_ms: 200.0,
+        }
+    }
+}
+
+impl StartupConfig {
+    // For the index-th connection started, given a draw between 0 and 1
+    pub fn delay(&self, index: usize, draw: f64) -> Duration {
+        Duration::from_secs_f64((index as f64 * self.stagger_ms + draw * self.jitter_ms) / 1000.0)
+    }
+}
+
+fn default_puzzles() -> bool {
+    true
+}
+
+fn default_alias_prefix() -> String {
+    "Aegizz".to_string()
+}
+
+fn default_connections() -> usize {
+    5
+}
+
+fn default_trading_enabled() -> bool {
+    true
+}
+
+fn default_strategies() -> Vec<StrategyKind> {
+    vec![StrategyKind::Blend]
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "could not read config: {}", e),
+            ConfigError::Parse(e) => write!(f, "could not parse config: {}", e),
+            ConfigError::Invalid(reason) => write!(f, "invalid config: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// Every setting at its default. The url and player_id are left empty, so it won't
+// validate until they're filled in.
+impl Default for Config {
+    fn default() -> Self {
+        toml::from_str("").expect("every setting has a default")
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(ConfigError::Io)?;
+        Config::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Config, ConfigError> {
+        let config: Config = toml::from_str(text).map_err(ConfigError::Parse)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // Catch settings that would otherwise only fail once we try to connect. Run again
+    // after applying command line overrides.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        match (self.url.is_empty(), self.urls.is_empty()) {
+            (true, true) => {
+                return Err(ConfigError::Invalid("url or urls must be set".to_string()))
+            }
+            (false, false) => {
+                return Err(ConfigError::Invalid(
+                    "set either url or urls, not both".to_string(),
+                ))
+            }
+            _ => {}
+        }
+        for url in self.urls() {
+            if !url.starts_with("ws://") && !url.starts_with("wss://") {
+                return Err(ConfigError::Invalid(format!(
+                    "url must start with ws:// or wss://, got '{}'",
+                    url
+                )));
+            }
+        }
+        if self.accounts.is_empty() {
+            if self.player_id.trim().is_empty() {
+                return Err(ConfigError::Invalid(
+                    "player_id must not be empty".to_string(),
+                ));
+            }
+        } else {
+            self.validate_accounts()?;
+        }
+        if let Err(e) = self
+            .strategy
+            .validate()
+            .and_then(|()| self.strategy.within_bounds())
+        {
+            return Err(ConfigError::Invalid(format!("strategy: {}", e)));
+        }
+        if self.risk.max_drawdown.is_some_and(|max| max <= 0.0)
+            || self.risk.max_drawdown_pct.is_some_and(|max| max <= 0.0)
+        {
+            return Err(ConfigError::Invalid(
+                "risk drawdown limits must be positive".to_string(),
+            ));
+        }
+        let quarantine = &self.quarantine;
+        if !((1..=RECENT_TRADE_OUTCOMES).contains(&quarantine.trades)
+            && quarantine.cooldown_secs >= 0.0
+            && quarantine.reduced_size > 0.0
+            && quarantine.reduced_size <= 1.0)
+        {
+            return Err(ConfigError::Invalid(format!(
+                "quarantine trades must be 1 to {}, cooldown_secs not negative and \
+                 reduced_size above 0 and at most 1",
+                RECENT_TRADE_OUTCOMES
+            )));
+        }
+        if self.rate_limit.enabled
+            && (self.rate_limit.interval_secs.is_nan()
+                || self.rate_limit.interval_secs <= 0.0
+                || self.rate_limit.burst == 0)
+        {
+            return Err(ConfigError::Invalid(
+                "rate_limit interval_secs and burst must be positive".to_string(),
+            ));
+        }
+        if self.outbound.max_per_minute == Some(0) {
+            return Err(ConfigError::Invalid(
+                "outbound max_per_minute must be positive; leave it unset for no ceiling"
+                    .to_string(),
+            ));
+        }
+        if self.exposure.max_net_position == Some(0) {
+            return Err(ConfigError::Invalid(
+                "exposure max_net_position must be positive; leave it unset for no ceiling"
+                    .to_string(),
+            ));
+        }
+        let budgets = [
+            self.trade_budget.trades_per_game,
+            self.trade_budget.puzzle_trades_per_game,
+        ];
+        if budgets.contains(&Some(0)) {
+            return Err(ConfigError::Invalid(
+                "trade_budget trades_per_game and puzzle_trades_per_game must be positive; leave them unset for no budget"
+                    .to_string(),
+            ));
+        }
+        let costs = [self.costs.fee_per_unit, self.costs.half_spread];
+        if costs.iter().any(|cost| !cost.is_finite() || *cost < 0.0) {
+            return Err(ConfigError::Invalid(
+                "costs fee_per_unit and half_spread must be finite and not negative".to_string(),
+            ));
+        }
+        if self.staleness.max_age_secs.is_nan() || self.staleness.max_age_secs <= 0.0 {
+            return Err(ConfigError::Invalid(format!(
+                "staleness max_age_secs must be positive, got {}",
+                self.staleness.max_age_secs
+            )));
+        }
+        if self.duplicates.max_skips == 0 || self.duplicates.warn_every == 0 {
+            return Err(ConfigError::Invalid(
+                "duplicates max_skips and warn_every must be at least 1".to_string(),
+            ));
+        }
+        if self
+            .hold
+            .max_secs
+            .is_some_and(|max_secs| max_secs.is_nan() || max_secs <= 0.0)
+            || !(0.0..=1.0).contains(&self.hold.reconfirm_signal)
+        {
+            return Err(ConfigError::Invalid(
+                "hold max_secs must be positive and reconfirm_signal between 0 and 1".to_string(),
+            ));
+        }
+        if self.wind_down.game_length == Some(0) {
+            return Err(ConfigError::Invalid(
+                "wind_down game_length must be positive".to_string(),
+            ));
+        }
+        if !(self.startup.stagger_ms >= 0.0 && self.startup.jitter_ms >= 0.0) {
+            return Err(ConfigError::Invalid(
+                "startup stagger_ms and jitter_ms must not be negative".to_string(),
+            ));
+        }
+        if !(self.watchdog.read_timeout_secs > 0.0 && self.watchdog.ping_grace_secs > 0.0) {
+            return Err(ConfigError::Invalid(
+                "watchdog timeouts must be positive".to_string(),
+            ));
+        }
+        if self.frames.max_bytes == 0 || self.frames.max_errors_per_minute == 0 {
+            return Err(ConfigError::Invalid(
+                "frames max_bytes and max_errors_per_minute must be positive".to_string(),
+            ));
+        }
+        if !(self.handshake.ack_timeout_secs > 0.0 && self.handshake.start_timeout_secs > 0.0) {
+            return Err(ConfigError::Invalid(
+                "handshake timeouts must be positive".to_string(),
+            ));
+        }
+        if self.latency.budget_ms.is_nan() || self.latency.budget_ms <= 0.0 {
+            return Err(ConfigError::Invalid(format!(
+                "latency budget_ms must be positive, got {}",
+                self.latency.budget_ms
+            )));
+        }
+        let supervisor = &self.supervisor;
+        if supervisor.max_restarts == 0
+            || !(supervisor.window_secs > 0.0
+                && supervisor.restart_delay_secs >= 0.0
+                && supervisor.quarantine_secs >= supervisor.restart_delay_secs)
+        {
+            return Err(ConfigError::Invalid(
+                "supervisor needs max_restarts and window_secs positive, and a quarantine no shorter than the restart delay".to_string(),
+            ));
+        }
+        let divergence = &self.divergence;
+        if divergence.checks == 0
+            || !(divergence.interval_secs > 0.0
+                && divergence.max_price_gap > 0.0
+                && divergence.max_pnl_change_gap.is_none_or(|gap| gap > 0.0))
+        {
+            return Err(ConfigError::Invalid(
+                "divergence needs checks, interval_secs, max_price_gap and max_pnl_change_gap positive".to_string(),
+            ));
+        }
+        if !(1..=MAX_CONNECTIONS).contains(&self.connections) {
+            return Err(ConfigError::Invalid(format!(
+                "connections must be between 1 and {}, got {}",
+                MAX_CONNECTIONS, self.connections
+            )));
+        }
+        let mut aliases = HashSet::new();
+        for conn_id in 0..self.connections {
+            let alias = self.alias(conn_id);
+            if alias.trim().is_empty() {
+                return Err(ConfigError::Invalid(format!(
+                    "alias for connection {} is empty",
+                    conn_id
+                )));
+            }
+            if !aliases.insert(alias.clone()) {
+                return Err(ConfigError::Invalid(format!(
+                    "alias '{}' is used by more than one connection",
+                    alias
+                )));
+            }
+        }
+        if self.journal.flush_secs.is_nan() || self.journal.flush_secs <= 0.0 {
+            return Err(ConfigError::Invalid(
+                "journal flush_secs must be positive".to_string(),
+            ));
+        }
+        if self.persist.max_age_secs.is_nan() || self.persist.max_age_secs <= 0.0 {
+            return Err(ConfigError::Invalid(
+                "persist max_age_secs must be positive".to_string(),
+            ));
+        }
+        if self.history.size < MIN_OPTIMIZER_SAMPLES {
+            return Err(ConfigError::Invalid(format!(
+                "history size must be at least {}, the samples the optimizer needs",
+                MIN_OPTIMIZER_SAMPLES
+            )));
+        }
+        if self.optimizer.bad_sharpe >= self.optimizer.good_sharpe {
+            return Err(ConfigError::Invalid(
+                "optimizer bad_sharpe must be below good_sharpe".to_string(),
+            ));
+        }
+        if !(self.optimizer.half_life_secs > 0.0 && self.optimizer.max_age_secs > 0.0) {
+            return Err(ConfigError::Invalid(
+                "optimizer half_life_secs and max_age_secs must be positive".to_string(),
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.optimizer.epsilon) {
+            return Err(ConfigError::Invalid(format!(
+                "optimizer epsilon must be between 0 and 1, got {}",
+                self.optimizer.epsilon
+            )));
+        }
+        if !(self.optimizer.outlier_mads.is_finite() && self.optimizer.outlier_mads >= 0.0) {
+            return Err(ConfigError::Invalid(format!(
+                "optimizer outlier_mads must be finite and not negative, got {}",
+                self.optimizer.outlier_mads
+            )));
+        }
+        if !(self.optimizer.max_weight_step > 0.0 && self.optimizer.max_weight_step <= 1.0) {
+            return Err(ConfigError::Invalid(format!(
+                "optimizer max_weight_step must be above 0 and at most 1, got {}",
+                self.optimizer.max_weight_step
+            )));
+        }
+        if !(0.0..1.0).contains(&self.optimizer.freeze_fraction) {
+            return Err(ConfigError::Invalid(format!(
+                "optimizer freeze_fraction must be at least 0 and below 1, got {}",
+                self.optimizer.freeze_fraction
+            )));
+        }
+        let mut arm_names = HashSet::new();
+        for arm in &self.bandit.arms {
+            if arm.name.trim().is_empty() || !arm_names.insert(arm.name.as_str()) {
+                return Err(ConfigError::Invalid(format!(
+                    "bandit arms each need a name of their own, got '{}'",
+                    arm.name
+                )));
+            }
+            let mut params = self.strategy.clone();
+            arm.apply(&mut params);
+            if let Err(e) = params.validate() {
+                return Err(ConfigError::Invalid(format!(
+                    "bandit arm '{}': {}",
+                    arm.name, e
+                )));
+            }
+        }
+        let mut phase_names = HashSet::new();
+        for phase in &self.schedule.phases {
+            if phase.name.trim().is_empty() || !phase_names.insert(phase.name.as_str()) {
+                return Err(ConfigError::Invalid(format!(
+                    "schedule phases each need a name of their own, got '{}'",
+                    phase.name
+                )));
+            }
+            match (phase.from_fraction, phase.from_secs) {
+                (Some(from), None) if (0.0..1.0).contains(&from) => {}
+                (None, Some(from)) if from.is_finite() && from >= 0.0 => {}
+                _ => {
+                    return Err(ConfigError::Invalid(format!(
+                        "schedule phase '{}' needs either from_fraction (at least 0 and below 1) or from_secs (not negative)",
+                        phase.name
+                    )))
+                }
+            }
+            let mut params = self.strategy.clone();
+            phase.apply(&mut params);
+            if let Err(e) = params.validate() {
+                return Err(ConfigError::Invalid(format!(
+                    "schedule phase '{}': {}",
+                    phase.name, e
+                )));
+            }
+        }
+        if self.ensemble.mode == SignalMode::Ensemble
+            && !(1..=self.connections).contains(&self.ensemble.quorum)
+        {
+            return Err(ConfigError::Invalid(format!(
+                "ensemble quorum must be between 1 and connections ({}), got {}",
+                self.connections, self.ensemble.quorum
+            )));
+        }
+        if !(0.0..1.0).contains(&self.agreement.negligible) {
+            return Err(ConfigError::Invalid(format!(
+                "agreement negligible must be at least 0 and below 1, got {}",
+                self.agreement.negligible
+            )));
+        }
+        if self.ensemble.max_age_secs.is_nan() || self.ensemble.max_age_secs <= 0.0 {
+            return Err(ConfigError::Invalid(
+                "ensemble max_age_secs must be positive".to_string(),
+            ));
+        }
+        if self.forecast.horizon == 0 || self.forecast.window == 0 {
+            return Err(ConfigError::Invalid(
+                "forecast horizon and window must be positive".to_string(),
+            ));
+        }
+        if self.forecast.min_samples > self.forecast.window {
+            return Err(ConfigError::Invalid(format!(
+                "forecast min_samples ({}) can't be more than the window ({})",
+                self.forecast.min_samples, self.forecast.window
+            )));
+        }
+        let notify = &self.notify;
+        if let Some(url) = &notify.webhook_url {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(ConfigError::Invalid(format!(
+                    "notify webhook_url must start with http:// or https://, got '{}'",
+                    url
+                )));
+            }
+        }
+        if !(notify.min_interval_secs >= 0.0
+            && notify.connection_down_secs >= 0.0
+            && notify.param_change > 0.0
+            && notify.timeout_secs > 0.0)
+        {
+            return Err(ConfigError::Invalid(
+                "notify needs non-negative min_interval_secs and connection_down_secs, and \
+                 param_change and timeout_secs above zero"
+                    .to_string(),
+            ));
+        }
+        let control = &self.control;
+        let Ok(bind) = control.bind.parse::<SocketAddr>() else {
+            return Err(ConfigError::Invalid(format!(
+                "control bind must be an address and port, got '{}'",
+                control.bind
+            )));
+        };
+        if control.token.as_ref().is_some_and(|token| token.is_empty()) {
+            return Err(ConfigError::Invalid(
+                "control token must not be empty".to_string(),
+            ));
+        }
+        if !bind.ip().is_loopback() && control.token.is_none() {
+            return Err(ConfigError::Invalid(format!(
+                "control needs a token to bind {}, which isn't loopback",
+                bind
+            )));
+        }
+        if let Some(level) = &self.log_level {
+            if let Err(e) = EnvFilter::try_new(level) {
+                return Err(ConfigError::Invalid(format!(
+                    "log_level '{}' isn't a log filter: {}",
+                    level, e
+                )));
+            }
+        }
+        if !(self.reload.poll_secs > 0.0 && self.reload.poll_secs.is_finite()) {
+            return Err(ConfigError::Invalid(format!(
+                "reload poll_secs must be positive, got {}",
+                self.reload.poll_secs
+            )));
+        }
+        let backtest = &self.backtest;
+        if backtest.updates_per_game == 0
+            || backtest.position_limit <= 0
+            || !(backtest.volatility >= 0.0 && backtest.forecast_noise >= 0.0)
+            || !(0.0..=1.0).contains(&backtest.puzzle_chance)
+        {
+            return Err(ConfigError::Invalid(
+                "backtest needs updates_per_game and position_limit above zero, non-negative \
+                 volatility and forecast_noise, and a puzzle_chance between 0 and 1"
+                    .to_string(),
+            ));
+        }
+        let tune = &self.tune;
+        if tune.games == 0 || tune.top == 0 {
+            return Err(ConfigError::Invalid(
+                "tune games and top must be positive".to_string(),
+            ));
+        }
+        if let Some(name) = tune.grid.invalid_range() {
+            return Err(ConfigError::Invalid(format!(
+                "tune grid {} needs a positive step and min no greater than max",
+                name
+            )));
+        }
+        if tune.grid.size() > MAX_COMBINATIONS {
+            return Err(ConfigError::Invalid(format!(
+                "tune grid has {} combinations, more than the {} allowed",
+                tune.grid.size(),
+                MAX_COMBINATIONS
+            )));
+        }
+        if !self.risk_eval.is_valid() {
+            return Err(ConfigError::Invalid(
+                "risk_eval needs games and drawdown limits above zero, and no negative \
+                 cooldown_secs"
+                    .to_string(),
+            ));
+        }
+        if self.strategies.is_empty() {
+            return Err(ConfigError::Invalid(
+                "strategies must list at least one strategy".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn validate_accounts(&self) -> Result<(), ConfigError> {
+        if !self.player_id.is_empty() || !self.aliases.is_empty() || self.alias_template.is_some() {
+            return Err(ConfigError::Invalid(
+                "player_id, aliases and alias_template go in each account when accounts are listed"
+                    .to_string(),
+            ));
+        }
+        let mut aliases = HashSet::new();
+        for account in &self.accounts {
+            if account.player_id.trim().is_empty() || account.alias.trim().is_empty() {
+                return Err(ConfigError::Invalid(
+                    "every account needs a player_id and an alias".to_string(),
+                ));
+            }
+            if !aliases.insert(account.alias.as_str()) {
+                return Err(ConfigError::Invalid(format!(
+                    "account alias '{}' is used more than once",
+                    account.alias
+                )));
+            }
+            if let Some(url) = &account.url {
+                if !url.starts_with("ws://") && !url.starts_with("wss://") {
+                    return Err(ConfigError::Invalid(format!(
+                        "url for account '{}' must start with ws:// or wss://, got '{}'",
+                        account.alias, url
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // A config per account to run, labelled with its alias. Without [[accounts]] that's
+    // just this one, labelled with the alias prefix.
+    pub fn accounts(&self) -> Vec<Config> {
+        if self.accounts.is_empty() {
+            let mut config = self.clone();
+            config.account = self.alias_prefix.clone();
+            return vec![config];
+        }
+        self.accounts
+            .iter()
+            .map(|account| {
+                let mut config = self.clone();
+                config.accounts = Vec::new();
+                config.account = account.alias.clone();
+                config.player_id = account.player_id.clone();
+                config.alias_prefix = account.alias.clone();
+                if let Some(url) = &account.url {
+                    config.url = url.clone();
+                    config.urls = Vec::new();
+                }
+                config.persist.path = account_path(&self.persist.path, &account.alias);
+                config
+            })
+            .collect()
+    }
+
+    // The endpoints to connect to, in failover order
+    pub fn urls(&self) -> Vec<String> {
+        if self.urls.is_empty() {
+            vec![self.url.clone()]
+        } else {
+            self.urls.clone()
+        }
+    }
+
+    // Whether puzzles are traded, which takes the feature and the setting
+    pub fn puzzles_enabled(&self) -> bool {
+        cfg!(feature = "puzzles") && self.puzzles
+    }
+
+    // Whether the optimizer runs, which takes the feature and the setting
+    pub fn optimizer_enabled(&self) -> bool {
+        cfg!(feature = "auto-optimize") && self.optimizer.enabled
+    }
+
+    // A random tag for "{run}" in the alias template, shared by every connection
+    pub fn with_run_tag(mut self) -> Self {
+        self.run_tag = format!("{:04x}", rand::random::<u16>());
+        self
+    }
+
+    pub fn strategy_for(&self, conn_id: usize) -> StrategyKind {
+        self.strategies[conn_id % self.strategies.len()]
+    }
+
+    pub fn alias(&self, conn_id: usize) -> String {
+        if let Some(alias) = self.aliases.get(conn_id) {
+            return alias.clone();
+        }
+        match &self.alias_template {
+            Some(template) => template
+                .replace("{id}", &conn_id.to_string())
+                .replace("{run}", &self.run_tag),
+            None => format!("{}-{}", self.alias_prefix, conn_id),
+        }
+    }
+}
+
+// "params.json" becomes "params-<alias>.json", so accounts don't share learned params
+fn account_path(path: &Path, alias: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+    let mut name = format!("{}-{}", stem, alias);
+    if let Some(extension) = path.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+    path.with_file_name(name)
+}
@@ -0,0 +1,1009 @@
+use async_channel::{Receiver, Sender};
+use async_tungstenite::tungstenite::{Error as WsError, Message};
+use futures::future::{self, Either};
+use futures::stream::{SplitSink, StreamExt};
+use futures::FutureExt;
+use futures::SinkExt;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, instrument, warn, Instrument, Span};
+
+use crate::clock::Monotonic;
+use crate::config::Config;
+use crate::curve::write_curve;
+use crate::error::{BotError, DisconnectReason};
+use crate::handler::{read_frame, ConnectionHandler, Flow, Frame, Receipt};
+use crate::hooks::HookRunner;
+use crate::journal::{ConnectionEvent, ConnectionEventKind, Journal};
+use crate::notify::{Alert, Notifier, Outage};
+use crate::protocol::{ClientEvent, ClientMessage, ServerEvent};
+use crate::risk::OutboundKind;
+use crate::rt::{self, WsStream};
+use crate::shutdown::Shutdown;
+use crate::state::{EndpointEvent, Health, SharedState};
+use crate::strategy::Strategy;
+use crate::summary::{append_summary, FinishedGames};
+use crate::transcript::{Direction, Transcript, TranscriptEntry};
+
+// Attempts at sending one frame before the writer gives up on the connection
+const SEND_ATTEMPTS: u32 = 3;
+const SEND_RETRY_DELAY: Duration = Duration::from_millis(200);
+// Frames waiting on the writer before new ones are dropped, wherever they wait
+const OUTGOING_QUEUE: usize = 32;
+// A trade queued longer than this was decided on a market that has since moved
+const MAX_TRADE_AGE: Duration = Duration::from_secs(2);
+// Most frames read ahead after each one, to see whether a newer state is waiting
+const MAX_READ_AHEAD: usize = 64;
+// Connect failures in a row on one url before a connection moves to the next
+pub const URL_FAILURES_BEFORE_FAILOVER: u32 = 3;
+
+type WsSink = SplitSink<WsStream, Message>;
+// A frame already read off the socket, None once the server has closed it, with when
+// it arrived. Text is parsed as it's read, so the backlog can be looked through without
+// parsing it again.
+struct ReadAhead {
+    message: Option<Result<Message, WsError>>,
+    frame: Option<Frame>,
+    received: Monotonic,
+    arrived: Instant,
+}
+
+impl ReadAhead {
+    fn read(
+        message: Option<Result<Message, WsError>>,
+        received: Monotonic,
+        max_bytes: usize,
+    ) -> Self {
+        let frame = match &message {
+            Some(Ok(Message::Text(text))) => Some(read_frame(text, max_bytes)),
+            _ => None,
+        };
+        ReadAhead {
+            message,
+            frame,
+            received,
+            arrived: Instant::now(),
+        }
+    }
+}
+
+// A frame on its way to the writer, with how soon it should go out
+type Queued = (SendPriority, Outgoing);
+
+// The reader's end of the writer's queue. Frames wait in a batch until flushed, so
+// everything a burst of frames led to reaches the writer at once and the most urgent
+// of it goes first. A frame counts against OUTGOING_QUEUE from being queued until the
+// writer takes it to send, whether it's in the batch, the channel or the writer's
+// backlog.
+struct Outbox {
+    sender: Sender<Vec<Queued>>,
+    waiting: Arc<AtomicUsize>,
+    batch: Vec<Queued>,
+}
+
+// The writer's end
+struct Inbox {
+    receiver: Receiver<Vec<Queued>>,
+    waiting: Arc<AtomicUsize>,
+}
+
+// The channel itself needn't be bounded, the frames in it are
+fn send_queue() -> (Outbox, Inbox) {
+    let (sender, receiver) = async_channel::unbounded();
+    let waiting = Arc::new(AtomicUsize::new(0));
+    let outbox = Outbox {
+        sender,
+        waiting: Arc::clone(&waiting),
+        batch: Vec::new(),
+    };
+    (outbox, Inbox { receiver, waiting })
+}
+
+impl Outbox {
+    // A full queue means sends are backing up, and the frame would only go out late. A
+    // close still goes in, so the session ends cleanly however far behind it is.
+    fn push(&mut self, priority: SendPriority, frame: Outgoing) {
+        if self.len() >= OUTGOING_QUEUE && !matches!(frame, Outgoing::Close) {
+            warn!(
+                ?frame,
+                queued = OUTGOING_QUEUE,
+                "Outgoing queue full, dropping frame"
+            );
+            return;
+        }
+        self.waiting.fetch_add(1, Ordering::AcqRel);
+        self.batch.push((priority, frame));
+    }
+
+    // The writer only goes away after a failed send, which it reports itself
+    fn flush(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+        let frames = self.batch.len();
+        if self
+            .sender
+            .try_send(std::mem::take(&mut self.batch))
+            .is_err()
+        {
+            self.waiting.fetch_sub(frames, Ordering::AcqRel);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.waiting.load(Ordering::Acquire)
+    }
+}
+
+// What the reader asks the writer to put on the socket
+#[derive(Debug)]
+enum Outgoing {
+    // With when it was queued, so stale trades can be dropped, and when the frame it
+    // answers arrived, to time trades against the latency budget
+    Client(ClientMessage, Instant, Option<Instant>),
+    Ping,
+    Pong(Vec<u8>),
+    Close,
+}
+
+// Which of the frames waiting on the writer goes out first, most urgent first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SendPriority {
+    // Trades and answers for a puzzle, whose impact is already on its way. An answer
+    // goes out ahead of the skip queued after it.
+    Puzzle,
+    Trade,
+    // Skips and the handshake
+    Session,
+    // Pings, pongs and the close, which goes out once everything else has
+    Other,
+}
+
+impl SendPriority {
+    // `puzzle` when the message answers a puzzle event
+    pub fn of(event: &ClientEvent, puzzle: bool) -> SendPriority {
+        match event {
+            ClientEvent::Trade(_) if puzzle => SendPriority::Puzzle,
+            ClientEvent::Answer(_) => SendPriority::Puzzle,
+            ClientEvent::Trade(_) => SendPriority::Trade,
+            ClientEvent::Connection(_) | ClientEvent::Start(_) | ClientEvent::Skip(_) => {
+                SendPriority::Session
+            }
+        }
+    }
+}
+
+const PRIORITIES: usize = 4;
+
+// The writer's backlog: the most urgent frame first, in the order queued within a
+// priority. It belongs to one session, and whatever is left when that ends is dropped
+// with it rather than sent into the next game.
+#[derive(Debug)]
+pub struct SendQueue<T> {
+    queues: [VecDeque<T>; PRIORITIES],
+}
+
+impl<T> Default for SendQueue<T> {
+    fn default() -> Self {
+        SendQueue {
+            queues: std::array::from_fn(|_| VecDeque::new()),
+        }
+    }
+}
+
+impl<T> SendQueue<T> {
+    pub fn push(&mut self, priority: SendPriority, frame: T) {
+        self.queues[priority as usize].push_back(frame);
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.queues.iter_mut().find_map(VecDeque::pop_front)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Empty it at the end of a session, returning how many frames never went out
+    pub fn discard(&mut self) -> usize {
+        let discarded = self.len();
+        self.queues.iter_mut().for_each(VecDeque::clear);
+        discarded
+    }
+}
+
+impl Outgoing {
+    fn priority(&self, puzzle: bool) -> SendPriority {
+        match self {
+            Outgoing::Client(message, _, _) => SendPriority::of(&message.event, puzzle),
+            Outgoing::Ping | Outgoing::Pong(_) | Outgoing::Close => SendPriority::Other,
+        }
+    }
+}
+
+// Log a frame to the transcript, if one is being kept
+async fn record(
+    transcript: &Option<Arc<Transcript>>,
+    shared_state: &SharedState,
+    account: &str,
+    conn_id: usize,
+    direction: Direction,
+    frame: &str,
+) {
+    if let Some(transcript) = transcript {
+        let entry = TranscriptEntry {
+            timestamp: shared_state.now(),
+            account: account.to_string(),
+            conn_id,
+            direction,
+            frame: frame.to_string(),
+        };
+        transcript.record(&entry).await;
+    }
+}
+
+// Which of the configured urls a connection uses. Connections start spread across them
+// by conn_id and stay on whichever last worked, only moving on to the next after
+// repeated failures to connect.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UrlRotation {
+    urls: Vec<String>,
+    current: usize,
+    failures: u32,
+}
+
+impl UrlRotation {
+    pub fn new(urls: Vec<String>, conn_id: usize) -> Self {
+        let current = conn_id % urls.len().max(1);
+        UrlRotation {
+            urls,
+            current,
+            failures: 0,
+        }
+    }
+
+    // Start from a url that has worked before, when it's still one of ours
+    pub fn prefer(&mut self, url: &str) {
+        if let Some(index) = self.urls.iter().position(|candidate| candidate == url) {
+            self.current = index;
+            self.failures = 0;
+        }
+    }
+
+    pub fn current(&self) -> &str {
+        &self.urls[self.current]
+    }
+
+    pub fn connected(&mut self) {
+        self.failures = 0;
+    }
+
+    // Returns the next url when this failure is one too many for the current one
+    pub fn connect_failed(&mut self) -> Option<&str> {
+        self.failures += 1;
+        if self.failures < URL_FAILURES_BEFORE_FAILOVER || self.urls.len() < 2 {
+            return None;
+        }
+        self.failures = 0;
+        self.current = (self.current + 1) % self.urls.len();
+        Some(self.current())
+    }
+}
+
+// Where a connection passes on what it sees, besides the shared state. Each one is
+// off unless set.
+#[derive(Clone, Default)]
+pub struct Sinks {
+    pub transcript: Option<Arc<Transcript>>,
+    pub journal: Option<Journal>,
+    pub notifier: Option<Notifier>,
+    pub hooks: Option<HookRunner>,
+    pub games: Option<FinishedGames>,
+    // Traded with in place of the strategy the config picks
+    pub strategy: Option<Arc<dyn Strategy>>,
+}
+
+// Handle single connection, reconnecting as the policy allows. Everything logged from
+// here, the handler and the writer task is inside a span carrying the conn_id.
+#[instrument(name = "connection", skip_all, fields(conn_id = conn_id))]
+pub async fn handle_connection(
+    conn_id: usize,
+    config: Arc<Config>,
+    shared_state: Arc<SharedState>,
+    shutdown: Shutdown,
+    sinks: Sinks,
+) {
+    let Sinks {
+        transcript,
+        journal,
+        notifier,
+        hooks,
+        games,
+        strategy,
+    } = sinks;
+    let mut handler =
+        ConnectionHandler::new(conn_id, Arc::clone(&config), Arc::clone(&shared_state))
+            .with_persist(config.persist.clone());
+    if let Some(strategy) = strategy {
+        handler = handler.with_strategy(Box::new(strategy));
+    }
+    if let Some(games) = games {
+        handler = handler.with_finished_games(games);
+    }
+    if let Some(journal) = journal {
+        handler = handler.with_journal(journal);
+    }
+    if let Some(notifier) = &notifier {
+        handler = handler.with_notifier(notifier.clone());
+    }
+    if let Some(hooks) = &hooks {
+        handler = handler.with_hooks(hooks.clone());
+    }
+    info!(strategy = handler.strategy_name(), "Starting connection");
+
+    // Back on the url that last worked, when a restarted task picks up from a panic
+    let mut urls = UrlRotation::new(config.urls(), conn_id);
+    if let Some(url) = shared_state.connection_url(conn_id).await {
+        urls.prefer(&url);
+    }
+    // Sessions in a row that ended in an error
+    let mut failures = 0;
+    // From the end of the last session until the next one connects
+    let mut outage = Outage::default();
+    // Seeded per connection, so connections that drop together don't draw the same
+    // jitter and come back in lockstep
+    let mut jitter = StdRng::from_entropy();
+    while !shutdown.is_triggered() {
+        shared_state.set_health(conn_id, Health::Connecting).await;
+        let outcome = run_session(
+            &mut handler,
+            &config,
+            &shared_state,
+            &shutdown,
+            &transcript,
+            &mut outage,
+            &mut urls,
+        )
+        .await;
+        match &outcome {
+            Ok(reason) => {
+                info!(?reason, "Session ended");
+                failures = if reason.is_fault() { failures + 1 } else { 0 };
+                journal_connection(
+                    &handler,
+                    &config,
+                    &shared_state,
+                    ConnectionEventKind::Disconnected,
+                    format!("{:?}", reason),
+                );
+            }
+            Err(e) => {
+                warn!(error = %e, "Session failed");
+                failures += 1;
+                journal_connection(
+                    &handler,
+                    &config,
+                    &shared_state,
+                    ConnectionEventKind::Error,
+                    e.to_string(),
+                );
+            }
+        }
+
+        shared_state.record_disconnect(conn_id, &outcome).await;
+        let policy = reconnect_policy(&outcome, failures);
+        // Back for the next game when the server said it starts, rather than on a guess
+        let restart_hint = matches!(outcome, Ok(DisconnectReason::GameFinished))
+            .then(|| handler.take_restart_hint())
+            .flatten();
+        if let Err(e) = outcome {
+            if policy == Reconnect::GiveUp {
+                error!(error = %e, "Giving up on connection");
+            }
+            if let Some(hooks) = &hooks {
+                hooks.error(conn_id, e);
+            }
+        }
+        let delay = match policy {
+            Reconnect::After(delay) => delay,
+            Reconnect::GiveUp => break,
+        };
+
+        shared_state.set_health(conn_id, Health::Backoff).await;
+        outage.down(shared_state.monotonic().secs());
+        if let Some(notifier) = &notifier {
+            let threshold = notifier.config().connection_down_secs;
+            if let Some(secs) = outage.due(shared_state.monotonic().secs(), threshold) {
+                notifier.notify(Alert::ConnectionDown { conn_id, secs });
+            }
+        }
+
+        // Jittered so connections that dropped together don't all come back at once
+        let delay = restart_hint
+            .unwrap_or_else(|| delay + Duration::from_millis(jitter.gen_range(0..1000)));
+        info!(?delay, "Preparing to reconnect");
+        future::select(Box::pin(rt::sleep(delay)), Box::pin(shutdown.wait())).await;
+    }
+    shared_state.set_health(conn_id, Health::Dead).await;
+}
+
+fn journal_connection(
+    handler: &ConnectionHandler,
+    config: &Config,
+    shared_state: &SharedState,
+    kind: ConnectionEventKind,
+    detail: String,
+) {
+    if let Some(journal) = handler.journal() {
+        journal.connection(ConnectionEvent {
+            timestamp: shared_state.now(),
+            account: config.account.clone(),
+            conn_id: handler.conn_id(),
+            kind,
+            detail: Some(detail),
+        });
+    }
+}
+
+// One websocket session, from connecting until it ends one way or another
+async fn run_session(
+    handler: &mut ConnectionHandler,
+    config: &Arc<Config>,
+    shared_state: &Arc<SharedState>,
+    shutdown: &Shutdown,
+    transcript: &Option<Arc<Transcript>>,
+    outage: &mut Outage,
+    urls: &mut UrlRotation,
+) -> Result<DisconnectReason, BotError> {
+    let conn_id = handler.conn_id();
+    let url = urls.current().to_string();
+    info!(%url, "Connecting to WebSocket");
+    let ws_stream = match rt::connect_async(url.as_str()).await {
+        Ok((ws_stream, _)) => ws_stream,
+        Err(e) => {
+            shared_state
+                .record_endpoint(&url, EndpointEvent::ConnectFailed)
+                .await;
+            if let Some(next) = urls.connect_failed() {
+                warn!(
+                    from = %url,
+                    to = %next,
+                    failures = URL_FAILURES_BEFORE_FAILOVER,
+                    "Can't connect, failing over to the next url"
+                );
+                shared_state
+                    .record_endpoint(&url, EndpointEvent::FailedOver)
+                    .await;
+            }
+            return Err(BotError::from_connect(e));
+        }
+    };
+    info!("Connected to WebSocket");
+    urls.connected();
+    journal_connection(
+        handler,
+        config,
+        shared_state,
+        ConnectionEventKind::Connected,
+        url.clone(),
+    );
+    shared_state
+        .record_endpoint(&url, EndpointEvent::Connected { conn_id })
+        .await;
+    outage.up();
+    shared_state.set_health(conn_id, Health::Live).await;
+    let (sink, mut stream) = ws_stream.split();
+
+    // Everything outgoing goes through the writer task, so the read loop never waits
+    // on the socket. Enqueueing doesn't wait either; a full queue drops the frame. The
+    // queue is the session's own, so nothing queued for this session is ever sent into
+    // the next.
+    let (mut outgoing, queued) = send_queue();
+    // Triggered by the writer when it can no longer send
+    let writer_failed = Shutdown::new();
+    let writer = rt::spawn(
+        write_frames(
+            conn_id,
+            sink,
+            queued,
+            writer_failed.clone(),
+            Arc::clone(shared_state),
+            transcript.clone(),
+            Arc::clone(config),
+        )
+        .instrument(Span::current()),
+    );
+
+    // Orders typed while we were away were meant for a market that has since moved
+    let stale_orders = shared_state.manual.clear(conn_id);
+    if stale_orders > 0 {
+        warn!(
+            orders = stale_orders,
+            "Dropping manual orders queued while disconnected"
+        );
+    }
+
+    // Likewise a reconnect asked for before this session began
+    if shared_state.reconnects.clear(conn_id) {
+        debug!("Dropping a reconnect asked for while disconnected");
+    }
+
+    // Send connection message
+    for message in handler.start_session().await {
+        enqueue(
+            &mut outgoing,
+            Outgoing::Client(message, Instant::now(), None),
+        );
+    }
+
+    // Set once the read timeout has fired and we're waiting to hear back from a ping
+    let mut awaiting_pong = false;
+
+    // Frames that arrived while the last one was being handled
+    let mut backlog: VecDeque<ReadAhead> = VecDeque::new();
+
+    // Message handling loop. Shutdown is only checked while waiting for the
+    // next message, so frames already queued are still handed to the writer.
+    let max_bytes = config.frames.max_bytes;
+    let ended = loop {
+        let read = match backlog.pop_front() {
+            Some(read) => read,
+            None => {
+                // Nothing more read ahead, so what it all led to goes out
+                outgoing.flush();
+                let watchdog = if awaiting_pong {
+                    config.watchdog.ping_grace()
+                } else {
+                    config.watchdog.read_timeout()
+                };
+                // A handshake step due to time out sooner wakes the loop first
+                let handshake = handler.handshake_deadline().map(|deadline| {
+                    Duration::from_secs_f64((deadline - shared_state.monotonic()).max(0.0))
+                });
+                let wait = handshake.map_or(watchdog, |handshake| handshake.min(watchdog));
+                let stopped =
+                    future::select(Box::pin(shutdown.wait()), Box::pin(writer_failed.wait()));
+                let manual = Box::pin(shared_state.manual.next(conn_id));
+                let diverged = Box::pin(shared_state.reconnects.next(conn_id));
+                let next = rt::timeout(
+                    wait,
+                    future::select(
+                        stream.next(),
+                        future::select(stopped, future::select(manual, diverged)),
+                    ),
+                )
+                .await;
+
+                match next {
+                    Ok(Either::Left((next, _))) => {
+                        ReadAhead::read(next, shared_state.monotonic(), max_bytes)
+                    }
+                    // An order from the console goes out through the writer like any trade
+                    Ok(Either::Right((Either::Right((Either::Left((order, _)), _)), _))) => {
+                        let mut outbox = Vec::new();
+                        handler.handle_manual(order, &mut outbox).await;
+                        for message in outbox {
+                            enqueue(
+                                &mut outgoing,
+                                Outgoing::Client(message, Instant::now(), None),
+                            );
+                        }
+                        continue;
+                    }
+                    Ok(Either::Right((Either::Right((Either::Right(_), _)), _))) => {
+                        warn!("Out of line with the other connections, reconnecting");
+                        enqueue(&mut outgoing, Outgoing::Close);
+                        break Ok(DisconnectReason::Diverged);
+                    }
+                    Ok(Either::Right(_)) if shutdown.is_triggered() => {
+                        info!("Shutting down");
+                        enqueue(&mut outgoing, Outgoing::Close);
+                        break Ok(DisconnectReason::Shutdown);
+                    }
+                    // The writer's own error is picked up below
+                    Ok(Either::Right(_)) => break Ok(DisconnectReason::ServerClosed),
+                    Err(_) if handler.tick() == Flow::Retry => {
+                        enqueue(&mut outgoing, Outgoing::Close);
+                        break Ok(DisconnectReason::HandshakeTimedOut);
+                    }
+                    Err(_) if handshake.is_some_and(|handshake| handshake < watchdog) => continue,
+                    Err(_) if awaiting_pong => {
+                        warn!(?wait, "Watchdog: no reply to ping, reconnecting");
+                        break Ok(DisconnectReason::Unresponsive);
+                    }
+                    Err(_) => {
+                        debug!(?wait, "Watchdog: no message, sending ping");
+                        enqueue(&mut outgoing, Outgoing::Ping);
+                        awaiting_pong = true;
+                        continue;
+                    }
+                }
+            }
+        };
+
+        let ReadAhead {
+            message,
+            frame,
+            received,
+            arrived,
+        } = read;
+        let Some(msg_result) = message else {
+            break Ok(DisconnectReason::ServerClosed);
+        };
+
+        // Anything from the server shows the connection is alive
+        if awaiting_pong {
+            info!("Watchdog: connection alive");
+            awaiting_pong = false;
+        }
+
+        let text = match msg_result {
+            Ok(Message::Text(text)) => text,
+            // Some proxies cut off a client that doesn't answer their pings
+            Ok(Message::Ping(payload)) => {
+                debug!(bytes = payload.len(), "Ping from server, sending pong");
+                enqueue(&mut outgoing, Outgoing::Pong(payload));
+                continue;
+            }
+            // Tungstenite answers the close itself, so there's nothing left to send
+            Ok(Message::Close(frame)) => {
+                match frame {
+                    Some(frame) => info!(
+                        code = u16::from(frame.code),
+                        reason = %frame.reason,
+                        "Server closed the connection"
+                    ),
+                    None => info!("Server closed the connection"),
+                }
+                break Ok(DisconnectReason::ServerClosed);
+            }
+            Ok(_) => continue,
+            Err(e) => break Err(BotError::from_read(e)),
+        };
+        record(
+            transcript,
+            shared_state,
+            &config.account,
+            conn_id,
+            Direction::In,
+            &text,
+        )
+        .await;
+
+        // Whatever else has already arrived, so a backlog of state updates only
+        // trades on the newest
+        while backlog.len() < MAX_READ_AHEAD {
+            match stream.next().now_or_never() {
+                Some(next) => {
+                    let closed = next.is_none();
+                    backlog.push_back(ReadAhead::read(next, shared_state.monotonic(), max_bytes));
+                    if closed {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+        // A newer state update makes this one moot, so a burst is decided on once,
+        // on the freshest data
+        let frame = frame.unwrap_or_else(|| read_frame(&text, max_bytes));
+        if matches!(frame, Ok(ServerEvent::State(_))) && newer_state_queued(&backlog) {
+            shared_state.record_coalesced(conn_id).await;
+            continue;
+        }
+        let receipt = Receipt { received, arrived };
+
+        let puzzle = matches!(frame, Ok(ServerEvent::Puzzle(_)));
+        let mut outbox = Vec::new();
+        let flow = handler.handle_frame(frame, receipt, &mut outbox).await;
+        for message in outbox {
+            let frame = Outgoing::Client(message, Instant::now(), Some(receipt.arrived));
+            outgoing.push(frame.priority(puzzle), frame);
+        }
+        shared_state.set_queue_depth(conn_id, outgoing.len()).await;
+
+        if let Some(summary) = handler.take_summary() {
+            if config.summary.enabled {
+                if let Err(e) = append_summary(&config.summary.path, &summary).await {
+                    error!(
+                        path = %config.summary.path.display(),
+                        error = %e,
+                        "Error writing summary"
+                    );
+                }
+            }
+            if config.curve.enabled && !summary.curve.is_empty() {
+                if let Err(e) = write_curve(
+                    &config.curve.dir,
+                    &summary.account,
+                    summary.conn_id,
+                    summary.started_at,
+                    &summary.curve,
+                )
+                .await
+                {
+                    error!(
+                        dir = %config.curve.dir.display(),
+                        error = %e,
+                        "Error writing PnL curve"
+                    );
+                }
+            }
+        }
+
+        match flow {
+            Flow::Continue => {}
+            Flow::Disconnect => {
+                info!("Will reconnect shortly");
+                enqueue(&mut outgoing, Outgoing::Close);
+                break Ok(DisconnectReason::GameFinished);
+            }
+            Flow::Reauthenticate => {
+                enqueue(&mut outgoing, Outgoing::Close);
+                break Ok(DisconnectReason::TokenRejected);
+            }
+            Flow::Retry => {
+                enqueue(&mut outgoing, Outgoing::Close);
+                break Ok(DisconnectReason::HandshakeTimedOut);
+            }
+            Flow::Unreadable => {
+                enqueue(&mut outgoing, Outgoing::Close);
+                break Ok(DisconnectReason::ParseStorm);
+            }
+            Flow::Reconnect => {
+                enqueue(&mut outgoing, Outgoing::Close);
+                break Err(BotError::Auth(
+                    "server kept reporting auth errors".to_string(),
+                ));
+            }
+        }
+    };
+
+    // Let the writer flush what's queued before the socket goes away. A failed send
+    // is what ended the session, whatever the read loop saw.
+    outgoing.flush();
+    drop(outgoing);
+    let outcome = writer.await.and(ended);
+    shared_state
+        .record_endpoint(
+            &url,
+            EndpointEvent::Disconnected {
+                failed: outcome.is_err(),
+            },
+        )
+        .await;
+    outcome
+}
+
+// Whether a state update is waiting in the backlog for the same game. One past a
+// finish is for the next game, and leaves the last of this one to be handled.
+fn newer_state_queued(backlog: &VecDeque<ReadAhead>) -> bool {
+    for read in backlog {
+        match read.frame {
+            Some(Ok(ServerEvent::State(_))) => return true,
+            Some(Ok(ServerEvent::Finish(_))) => return false,
+            _ => {}
+        }
+    }
+    false
+}
+
+// Sent along with the rest of the batch, once nothing more is read ahead
+fn enqueue(outgoing: &mut Outbox, frame: Outgoing) {
+    outgoing.push(frame.priority(false), frame);
+}
+
+// Only trades go stale; anything else is still worth sending late
+pub fn is_stale_trade(message: &ClientMessage, age: Duration) -> bool {
+    matches!(message.event, ClientEvent::Trade(_)) && age > MAX_TRADE_AGE
+}
+
+// Writer task: sends queued frames, most urgent first, until the reader hangs up or a
+// send keeps failing
+async fn write_frames(
+    conn_id: usize,
+    mut sink: WsSink,
+    queued: Inbox,
+    failed: Shutdown,
+    shared_state: Arc<SharedState>,
+    transcript: Option<Arc<Transcript>>,
+    config: Arc<Config>,
+) -> Result<(), BotError> {
+    let latency_budget = config.latency.budget();
+    let mut backlog = SendQueue::default();
+    loop {
+        // Take in everything already waiting, so a trade queued behind skips and
+        // pings still goes first
+        if backlog.is_empty() {
+            match queued.receiver.recv().await {
+                Ok(batch) => take_in(&mut backlog, batch),
+                Err(_) => break,
+            }
+        }
+        while let Ok(batch) = queued.receiver.try_recv() {
+            take_in(&mut backlog, batch);
+        }
+        let Some(frame) = backlog.pop() else {
+            break;
+        };
+        let depth = queued.waiting.fetch_sub(1, Ordering::AcqRel) - 1;
+        shared_state.set_queue_depth(conn_id, depth).await;
+        let message = match &frame {
+            Outgoing::Client(message, queued_at, _) => {
+                let age = queued_at.elapsed();
+                if is_stale_trade(message, age) {
+                    warn!(
+                        ?message,
+                        ?age,
+                        "Dropping stale trade rather than sending it into a market that has moved"
+                    );
+                    shared_state.record_stale_trade(conn_id).await;
+                    continue;
+                }
+                let kind = OutboundKind::of(&message.event);
+                if !shared_state.admit_outbound(conn_id, kind).await {
+                    warn!(
+                        ?message,
+                        ceiling = ?shared_state.outbound.max_per_minute,
+                        "Outbound ceiling reached, dropping a message rather than flooding the server"
+                    );
+                    continue;
+                }
+                let json = message.to_json();
+                record(
+                    &transcript,
+                    &shared_state,
+                    &config.account,
+                    conn_id,
+                    Direction::Out,
+                    &json,
+                )
+                .await;
+                Message::Text(json)
+            }
+            Outgoing::Ping => Message::Ping(Vec::new()),
+            Outgoing::Pong(payload) => Message::Pong(payload.clone()),
+            Outgoing::Close => {
+                if let Err(e) = sink.close().await {
+                    warn!(error = %e, "Error closing WebSocket");
+                }
+                discard(&mut backlog, &queued);
+                return Ok(());
+            }
+        };
+
+        if let Err(e) = send_with_retry(&mut sink, message, &frame).await {
+            failed.trigger();
+            discard(&mut backlog, &queued);
+            return Err(BotError::Send(Box::new(e)));
+        }
+
+        if let Outgoing::Client(message, queued_at, Some(arrived)) = &frame {
+            if let ClientEvent::Trade(trade) = &message.event {
+                let latency = arrived.elapsed();
+                shared_state.record_latency(conn_id, latency).await;
+                // Time in the queue against the total shows whether deciding or
+                // sending was slow
+                if latency > latency_budget {
+                    warn!(
+                        volume = trade.volume,
+                        ?latency,
+                        queued = ?queued_at.elapsed(),
+                        budget = ?latency_budget,
+                        "Trade decision over its latency budget"
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn take_in(backlog: &mut SendQueue<Outgoing>, batch: Vec<Queued>) {
+    for (priority, frame) in batch {
+        backlog.push(priority, frame);
+    }
+}
+
+// The session is over, so whatever it queued goes nowhere
+fn discard(backlog: &mut SendQueue<Outgoing>, queued: &Inbox) {
+    while let Ok(batch) = queued.receiver.try_recv() {
+        take_in(backlog, batch);
+    }
+    let frames = backlog.discard();
+    if frames > 0 {
+        warn!(frames, "Dropping frames queued for a session that's over");
+    }
+}
+
+// The last attempt's error once every attempt has failed
+async fn send_with_retry(
+    sink: &mut WsSink,
+    message: Message,
+    frame: &Outgoing,
+) -> Result<(), WsError> {
+    let mut attempt = 1;
+    loop {
+        match sink.send(message.clone()).await {
+            Ok(()) => return Ok(()),
+            Err(e) => {
+                warn!(
+                    ?frame,
+                    attempt,
+                    attempts = SEND_ATTEMPTS,
+                    error = %e,
+                    "Error sending"
+                );
+                if attempt == SEND_ATTEMPTS {
+                    return Err(e);
+                }
+                rt::sleep(SEND_RETRY_DELAY * attempt).await;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+// Backoff caps for each class of failure
+const MAX_NETWORK_BACKOFF: Duration = Duration::from_secs(30);
+// A server that keeps breaking the protocol is unlikely to be fixed in seconds
+const PROTOCOL_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_PROTOCOL_BACKOFF: Duration = Duration::from_secs(300);
+const AUTH_BACKOFF: Duration = Duration::from_secs(60);
+const MAX_AUTH_BACKOFF: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reconnect {
+    After(Duration),
+    GiveUp,
+}
+
+// How long to wait before the next session, given how the last one ended and how many
+// sessions in a row have now failed. Jitter is added by the caller.
+pub fn reconnect_policy(outcome: &Result<DisconnectReason, BotError>, failures: u32) -> Reconnect {
+    match outcome {
+        Ok(DisconnectReason::Shutdown) => Reconnect::GiveUp,
+        // The server ended it cleanly and will take us back straight away, and the
+        // next game may be starting now
+        Ok(DisconnectReason::ServerClosed | DisconnectReason::GameFinished) => {
+            Reconnect::After(Duration::ZERO)
+        }
+        // A connection poisoned with unreadable frames is given a while from the first
+        Ok(DisconnectReason::ParseStorm) => Reconnect::After(backoff(
+            PROTOCOL_BACKOFF,
+            failures + 1,
+            MAX_PROTOCOL_BACKOFF,
+        )),
+        Err(BotError::Protocol(_)) => {
+            Reconnect::After(backoff(PROTOCOL_BACKOFF, failures, MAX_PROTOCOL_BACKOFF))
+        }
+        Ok(_) => Reconnect::After(Duration::from_secs(1)),
+        // A blip is retried straight away, a network that stays down backs off
+        Err(BotError::Connect(_) | BotError::Send(_)) => Reconnect::After(backoff(
+            Duration::from_secs(1),
+            failures,
+            MAX_NETWORK_BACKOFF,
+        )),
+        Err(BotError::Auth(_)) => {
+            Reconnect::After(backoff(AUTH_BACKOFF, failures + 1, MAX_AUTH_BACKOFF))
+        }
+        Err(BotError::Config(_)) => Reconnect::GiveUp,
+    }
+}
+
+// Nothing for the first failure, then base doubling with each further one up to max
+fn backoff(base: Duration, failures: u32, max: Duration) -> Duration {
+    if failures <= 1 {
+        return Duration::ZERO;
+    }
+    let doublings = (failures - 2).min(16);
+    (base * 2u32.pow(doublings)).min(max)
+}
@@ -0,0 +1,255 @@
+use async_channel::{Receiver, Sender, TrySendError};
+use futures::future::{self, Either};
+use futures::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+use futures::StreamExt;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::shutdown::Shutdown;
+use crate::state::SharedState;
+
+// Manual orders waiting on a connection before more are turned away
+const MANUAL_QUEUE: usize = 8;
+
+const HELP: &str = "commands: buy <n> [conn], sell <n> [conn], flat [conn], halt, resume, params";
+
+// Trade by hand from stdin, alongside the bot
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConsoleConfig {
+    pub enabled: bool,
+}
+
+// One line typed at the console. Trades without a connection go to every one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Trade { volume: i32, conn_id: Option<usize> },
+    Flat { conn_id: Option<usize> },
+    Halt,
+    Resume,
+    Params,
+    Help,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let conn_id = |word: Option<&&str>| -> Result<Option<usize>, String> {
+            word.map(|word| {
+                word.parse()
+                    .map_err(|_| format!("'{}' isn't a connection number", word))
+            })
+            .transpose()
+        };
+        let volume = |word: Option<&&str>| -> Result<i32, String> {
+            match word.map(|word| word.parse::<i32>()) {
+                Some(Ok(volume)) if volume > 0 => Ok(volume),
+                Some(_) => Err("the volume must be a whole number above 0".to_string()),
+                None => Err("missing volume".to_string()),
+            }
+        };
+        let command = match words.first().copied() {
+            Some("buy") if words.len() <= 3 => Command::Trade {
+                volume: volume(words.get(1))?,
+                conn_id: conn_id(words.get(2))?,
+            },
+            Some("sell") if words.len() <= 3 => Command::Trade {
+                volume: -volume(words.get(1))?,
+                conn_id: conn_id(words.get(2))?,
+            },
+            Some("flat") if words.len() <= 2 => Command::Flat {
+                conn_id: conn_id(words.get(1))?,
+            },
+            Some("halt") if words.len() == 1 => Command::Halt,
+            Some("resume") if words.len() == 1 => Command::Resume,
+            Some("params") if words.len() == 1 => Command::Params,
+            Some("help") if words.len() == 1 => Command::Help,
+            Some("buy" | "sell" | "flat" | "halt" | "resume" | "params" | "help") => {
+                return Err(format!("too many arguments; {}", HELP))
+            }
+            Some(other) => return Err(format!("unknown command '{}'; {}", other, HELP)),
+            None => return Err(HELP.to_string()),
+        };
+        Ok(command)
+    }
+}
+
+// What a connection is told to do from the console
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManualOrder {
+    // Signed, buying when positive
+    Trade(i32),
+    // Close out, and hand the connection back to the strategy
+    Flat,
+}
+
+impl fmt::Display for ManualOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ManualOrder::Trade(volume) if *volume > 0 => write!(f, "buy {}", volume),
+            ManualOrder::Trade(volume) => write!(f, "sell {}", -volume),
+            ManualOrder::Flat => f.write_str("flat"),
+        }
+    }
+}
+
+// A queue of manual orders per connection, which each connection's read loop takes
+// from alongside the socket. Orders wait there while a connection is between sessions.
+pub struct ManualDesk {
+    queues: Vec<(Sender<ManualOrder>, Receiver<ManualOrder>)>,
+}
+
+impl ManualDesk {
+    pub fn new(connections: usize) -> Self {
+        ManualDesk {
+            queues: (0..connections)
+                .map(|_| async_channel::bounded(MANUAL_QUEUE))
+                .collect(),
+        }
+    }
+
+    pub fn connections(&self) -> usize {
+        self.queues.len()
+    }
+
+    // Queue an order without waiting, refused when the connection is behind on them
+    pub fn send(&self, conn_id: usize, order: ManualOrder) -> Result<(), String> {
+        let (orders, _) = self
+            .queues
+            .get(conn_id)
+            .ok_or_else(|| format!("no connection {}", conn_id))?;
+        orders.try_send(order).map_err(|e| match e {
+            TrySendError::Full(_) => format!("connection {} has too many orders queued", conn_id),
+            TrySendError::Closed(_) => format!("connection {} is gone", conn_id),
+        })
+    }
+
+    // The connection's next order. Never resolves for a connection the desk doesn't have.
+    pub async fn next(&self, conn_id: usize) -> ManualOrder {
+        match self.queues.get(conn_id) {
+            Some((_, orders)) => match orders.recv().await {
+                Ok(order) => order,
+                Err(_) => future::pending().await,
+            },
+            None => future::pending().await,
+        }
+    }
+
+    // Throw away what's queued for a connection, returning how many orders there were
+    pub fn clear(&self, conn_id: usize) -> usize {
+        self.queues.get(conn_id).map_or(0, |(_, orders)| {
+            std::iter::from_fn(|| orders.try_recv().ok()).count()
+        })
+    }
+}
+
+// Reads commands and answers each with a line. It steers the first account, as the
+// control endpoint does.
+#[derive(Clone)]
+pub struct Console {
+    shared_state: Arc<SharedState>,
+}
+
+impl Console {
+    pub fn new(shared_state: Arc<SharedState>) -> Self {
+        Console { shared_state }
+    }
+
+    // The reply to one line of input
+    pub async fn handle(&self, line: &str) -> String {
+        let command = match line.parse::<Command>() {
+            Ok(command) => command,
+            Err(e) => return e,
+        };
+        match command {
+            Command::Trade { volume, conn_id } => self.send(ManualOrder::Trade(volume), conn_id),
+            Command::Flat { conn_id } => self.send(ManualOrder::Flat, conn_id),
+            Command::Halt => {
+                if self.shared_state.set_trading_enabled(false) {
+                    warn!("Trading halted from the console");
+                }
+                "trading halted".to_string()
+            }
+            Command::Resume => {
+                if !self.shared_state.set_trading_enabled(true) {
+                    info!("Trading resumed from the console");
+                }
+                "trading resumed".to_string()
+            }
+            Command::Params => {
+                let params = self.shared_state.strategy_params.read().await.clone();
+                match serde_json::to_value(&params) {
+                    Ok(Value::Object(fields)) => fields
+                        .iter()
+                        .map(|(name, value)| format!("{} = {}", name, value))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                    Ok(other) => other.to_string(),
+                    Err(e) => e.to_string(),
+                }
+            }
+            Command::Help => HELP.to_string(),
+        }
+    }
+
+    fn send(&self, order: ManualOrder, conn_id: Option<usize>) -> String {
+        let desk = &self.shared_state.manual;
+        let conn_ids: Vec<usize> = match conn_id {
+            Some(conn_id) => vec![conn_id],
+            None => (0..desk.connections()).collect(),
+        };
+        conn_ids
+            .into_iter()
+            .map(|conn_id| match desk.send(conn_id, order) {
+                Ok(()) => {
+                    info!(conn_id, %order, "Manual order from the console");
+                    format!("{} sent to connection {}", order, conn_id)
+                }
+                Err(e) => e,
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+// Answer each line of input until it ends or shutdown. Waiting on input holds up
+// nothing else.
+pub async fn run_console(
+    console: Console,
+    input: impl AsyncBufRead + Unpin,
+    mut output: impl AsyncWrite + Unpin,
+    shutdown: Shutdown,
+) {
+    let mut lines = input.lines();
+    loop {
+        let line = match future::select(lines.next(), Box::pin(shutdown.wait())).await {
+            Either::Left((Some(Ok(line)), _)) => line,
+            Either::Left((Some(Err(e)), _)) => {
+                warn!(error = %e, "Error reading the console, closing it");
+                return;
+            }
+            Either::Left((None, _)) | Either::Right(_) => return,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = console.handle(&line).await;
+        if let Err(e) = write_reply(&mut output, &reply).await {
+            warn!(error = %e, "Error writing to the console, closing it");
+            return;
+        }
+    }
+}
+
+async fn write_reply(output: &mut (impl AsyncWrite + Unpin), reply: &str) -> io::Result<()> {
+    output.write_all(reply.as_bytes()).await?;
+    output.write_all(b"\n").await?;
+    output.flush().await
+}
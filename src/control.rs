@@ -0,0 +1,305 @@
+use futures::future::{self, Either};
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::notify::Notifier;
+use crate::rt;
+use crate::rt::net::{TcpListener, TcpStream};
+use crate::shutdown::Shutdown;
+use crate::state::{SharedState, StrategyParams};
+
+// Largest request head and body we'll read
+const MAX_HEAD_BYTES: usize = 8 * 1024;
+const MAX_BODY_BYTES: usize = 64 * 1024;
+// A client that hasn't sent its whole request by then is dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Small HTTP server for looking at and steering a running bot
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ControlConfig {
+    pub enabled: bool,
+    // Loopback only unless a token is set
+    pub bind: String,
+    // Required as "Authorization: Bearer <token>" when set
+    pub token: Option<String>,
+}
+
+impl Default for ControlConfig {
+    fn default() -> Self {
+        ControlConfig {
+            enabled: false,
+            bind: "127.0.0.1:8787".to_string(),
+            token: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: String,
+    // Without any query string
+    pub path: String,
+    // Bearer token from the Authorization header
+    pub token: Option<String>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl Response {
+    fn ok(body: Value) -> Self {
+        Response { status: 200, body }
+    }
+
+    fn error(status: u16, message: impl fmt::Display) -> Self {
+        Response {
+            status,
+            body: json!({ "error": message.to_string() }),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let body = self.body.to_string();
+        format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            reason(self.status),
+            body.len(),
+            body
+        )
+        .into_bytes()
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        _ => "Internal Server Error",
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// One HTTP/1.1 request, read up to the end of its body
+pub async fn read_request(stream: &mut (impl AsyncRead + Unpin)) -> io::Result<Request> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 1024];
+    let head_end = loop {
+        if let Some(at) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break at;
+        }
+        if buffer.len() > MAX_HEAD_BYTES {
+            return Err(invalid("request head too large"));
+        }
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(invalid("connection closed mid-request"));
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+    let mut body = buffer.split_off(head_end + 4);
+
+    let head = std::str::from_utf8(&buffer[..head_end]).map_err(|_| invalid("head isn't UTF-8"))?;
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else {
+        return Err(invalid("malformed request line"));
+    };
+    let path = target.split('?').next().unwrap_or(target).to_string();
+
+    let mut content_length = 0;
+    let mut token = None;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(invalid("malformed header"));
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value.parse().map_err(|_| invalid("bad content-length"))?;
+        } else if name.eq_ignore_ascii_case("authorization") {
+            token = value.strip_prefix("Bearer ").map(str::to_string);
+        }
+    }
+    if content_length > MAX_BODY_BYTES {
+        return Err(invalid("request body too large"));
+    }
+
+    body.truncate(content_length);
+    let mut rest = vec![0; content_length - body.len()];
+    stream.read_exact(&mut rest).await?;
+    body.extend_from_slice(&rest);
+
+    Ok(Request {
+        method: method.to_string(),
+        path,
+        token,
+        body,
+    })
+}
+
+// Merge a partial JSON object of params over the current ones. Fields left out keep
+// their value and unknown fields are refused.
+pub fn apply_update(params: &StrategyParams, update: &[u8]) -> Result<StrategyParams, String> {
+    let update: Value = serde_json::from_slice(update).map_err(|e| e.to_string())?;
+    let Value::Object(fields) = update else {
+        return Err("expected a JSON object of params".to_string());
+    };
+    let mut merged = serde_json::to_value(params).map_err(|e| e.to_string())?;
+    if let Value::Object(current) = &mut merged {
+        current.extend(fields);
+    }
+    let updated: StrategyParams = serde_json::from_value(merged).map_err(|e| e.to_string())?;
+    updated.validate()?;
+    updated.within_bounds()?;
+    Ok(updated)
+}
+
+// Name, old and new value of every param that differs
+pub fn changed_params(
+    before: &StrategyParams,
+    after: &StrategyParams,
+) -> Vec<(String, Value, Value)> {
+    let (Ok(Value::Object(before)), Ok(Value::Object(after))) =
+        (serde_json::to_value(before), serde_json::to_value(after))
+    else {
+        return Vec::new();
+    };
+    after
+        .into_iter()
+        .filter_map(|(name, new)| {
+            let old = before.get(&name).cloned().unwrap_or(Value::Null);
+            (old != new).then_some((name, old, new))
+        })
+        .collect()
+}
+
+// GET /state, POST /params, POST /halt and POST /resume
+#[derive(Clone)]
+pub struct Control {
+    shared_state: Arc<SharedState>,
+    token: Option<String>,
+    notifier: Option<Notifier>,
+    fixed_params: bool,
+}
+
+impl Control {
+    pub fn new(shared_state: Arc<SharedState>, token: Option<String>) -> Self {
+        Control {
+            shared_state,
+            token,
+            notifier: None,
+            fixed_params: false,
+        }
+    }
+
+    // Alert on big param changes, as for the optimizer's
+    pub fn with_notifier(mut self, notifier: Notifier) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    // Refuse POST /params, for a fixed strategy run without the optimizer
+    pub fn with_fixed_params(mut self) -> Self {
+        self.fixed_params = true;
+        self
+    }
+
+    pub async fn handle(&self, request: &Request) -> Response {
+        if let Some(token) = &self.token {
+            if request.token.as_ref() != Some(token) {
+                return Response::error(401, "missing or wrong bearer token");
+            }
+        }
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/state") => match serde_json::to_value(self.shared_state.snapshot().await) {
+                Ok(snapshot) => Response::ok(snapshot),
+                Err(e) => Response::error(500, e),
+            },
+            ("POST", "/params") => self.update_params(&request.body).await,
+            ("POST", "/halt") => self.set_trading(false),
+            ("POST", "/resume") => self.set_trading(true),
+            (_, "/state" | "/params" | "/halt" | "/resume") => {
+                Response::error(405, "method not allowed")
+            }
+            _ => Response::error(404, "not found"),
+        }
+    }
+
+    // Applied under the write lock, so nothing the optimizer does in between is lost
+    async fn update_params(&self, body: &[u8]) -> Response {
+        if self.fixed_params {
+            return Response::error(409, "params are fixed for this run");
+        }
+        let mut params = self.shared_state.strategy_params.write().await;
+        let updated = match apply_update(&params, body) {
+            Ok(updated) => updated,
+            Err(e) => return Response::error(400, e),
+        };
+        for (param, old, new) in changed_params(&params, &updated) {
+            info!(param, %old, %new, "Strategy param changed from the control endpoint");
+        }
+        if let Some(notifier) = &self.notifier {
+            notifier.params_changed(&params, &updated);
+        }
+        *params = updated;
+        match serde_json::to_value(&*params) {
+            Ok(params) => Response::ok(params),
+            Err(e) => Response::error(500, e),
+        }
+    }
+
+    fn set_trading(&self, enabled: bool) -> Response {
+        let was = self.shared_state.set_trading_enabled(enabled);
+        match (was, enabled) {
+            (true, false) => warn!("Trading halted from the control endpoint"),
+            (false, true) => info!("Trading resumed from the control endpoint"),
+            _ => {}
+        }
+        Response::ok(json!({ "trading_enabled": enabled }))
+    }
+
+    // Accept requests until shutdown, each on its own task
+    pub async fn serve(self, listener: TcpListener, shutdown: Shutdown) {
+        loop {
+            let accepted =
+                future::select(Box::pin(listener.accept()), Box::pin(shutdown.wait())).await;
+            match accepted {
+                Either::Left((Ok((stream, peer)), _)) => {
+                    debug!(%peer, "Control request");
+                    rt::spawn(self.clone().respond(stream));
+                }
+                Either::Left((Err(e), _)) => warn!(error = %e, "Error accepting control request"),
+                Either::Right(_) => return,
+            }
+        }
+    }
+
+    async fn respond(self, mut stream: TcpStream) {
+        let response = match rt::timeout(REQUEST_TIMEOUT, read_request(&mut stream)).await {
+            Ok(Ok(request)) => self.handle(&request).await,
+            Ok(Err(e)) => Response::error(400, e),
+            Err(_) => return,
+        };
+        if let Err(e) = stream.write_all(&response.to_bytes()).await {
+            debug!(error = %e, "Error writing control response");
+        }
+    }
+}
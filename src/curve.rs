@@ -0,0 +1,120 @@
+use std::fmt::Write;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::clock::Monotonic;
+use crate::rt::fs;
+
+// Points kept per game before every other one is dropped to make room
+pub const MAX_CURVE_POINTS: usize = 2048;
+// Columns in the sparkline printed with the summary
+pub const SPARKLINE_WIDTH: usize = 40;
+// Lowest to highest
+const SPARK_LEVELS: &[u8] = b"_.:-=+*#%@";
+
+// PnL against seconds since the game's first state update, kept on the connection's
+// shared state so a reconnect mid-game carries on the same curve
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PnlCurve {
+    started: Option<Monotonic>,
+    points: Vec<(f32, f32)>,
+    // Every stride-th update is kept, doubled whenever the buffer fills
+    stride: usize,
+    skipped: usize,
+}
+
+impl PnlCurve {
+    pub fn record(&mut self, now: Monotonic, pnl: f64) {
+        let started = *self.started.get_or_insert(now);
+        if self.skipped + 1 < self.stride {
+            self.skipped += 1;
+            return;
+        }
+        self.skipped = 0;
+        if self.points.len() >= MAX_CURVE_POINTS {
+            let mut index = 0;
+            self.points.retain(|_| {
+                index += 1;
+                index % 2 == 1
+            });
+            self.stride = self.stride.max(1) * 2;
+        }
+        self.points
+            .push(((now - started).max(0.0) as f32, pnl as f32));
+    }
+
+    pub fn points(&self) -> &[(f32, f32)] {
+        &self.points
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    // The curve so far, leaving an empty one for the next game
+    pub fn take(&mut self) -> Vec<(f32, f32)> {
+        let points = std::mem::take(&mut self.points);
+        *self = PnlCurve::default();
+        points
+    }
+}
+
+// The last PnL in each of `width` equal slices of the game, scaled from the low to
+// the peak. Slices with no update repeat the one before.
+pub fn sparkline(points: &[(f32, f32)], width: usize) -> String {
+    let Some(&(end, _)) = points.last() else {
+        return String::new();
+    };
+    let width = width.max(1);
+    let mut columns: Vec<Option<f32>> = vec![None; width];
+    for &(elapsed, pnl) in points {
+        let column = if end > 0.0 {
+            ((elapsed / end) * width as f32) as usize
+        } else {
+            0
+        };
+        columns[column.min(width - 1)] = Some(pnl);
+    }
+    let low = points.iter().map(|&(_, pnl)| pnl).fold(f32::MAX, f32::min);
+    let high = points.iter().map(|&(_, pnl)| pnl).fold(f32::MIN, f32::max);
+    let top = SPARK_LEVELS.len() - 1;
+    let mut last = points[0].1;
+    columns
+        .into_iter()
+        .map(|pnl| {
+            last = pnl.unwrap_or(last);
+            let level = if high > low {
+                (((last - low) / (high - low)) * top as f32).round() as usize
+            } else {
+                top / 2
+            };
+            SPARK_LEVELS[level.min(top)] as char
+        })
+        .collect()
+}
+
+// One CSV per game and connection, named after when the game started
+pub async fn write_curve(
+    dir: &Path,
+    account: &str,
+    conn_id: usize,
+    started_at: f64,
+    points: &[(f32, f32)],
+) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir).await?;
+    let account = if account.is_empty() {
+        String::new()
+    } else {
+        format!("{}-", account)
+    };
+    let path = dir.join(format!(
+        "curve-{}conn{}-{}.csv",
+        account, conn_id, started_at as u64
+    ));
+    let mut csv = String::from("elapsed_secs,pnl\n");
+    for (elapsed, pnl) in points {
+        let _ = writeln!(csv, "{:.3},{}", elapsed, pnl);
+    }
+    fs::write(&path, csv).await?;
+    Ok(path)
+}
@@ -0,0 +1,223 @@
+use async_channel::{Receiver, Sender};
+use futures::future::{self, Either};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::rt;
+use crate::shutdown::Shutdown;
+use crate::state::{Health, SharedState};
+use crate::strategy::median;
+
+// Connections trading at once before any of them can be told apart from the rest.
+// With two, each is as far from the median as the other.
+pub const MIN_PEERS: usize = 3;
+
+// Every connection plays the same game, so one whose price or PnL has wandered off
+// from the others' is most likely desynced or stuck
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DivergenceConfig {
+    pub enabled: bool,
+    pub interval_secs: f64,
+    // Furthest a connection's price may be from the median price, as a fraction of it
+    pub max_price_gap: f64,
+    // Furthest its latest PnL change may be from the median change. Off by default,
+    // since connections holding different positions see different changes.
+    pub max_pnl_change_gap: Option<f64>,
+    // Checks in a row a connection has to be out of line before it's suspect
+    pub checks: u32,
+    // Drop a suspect connection's session and connect again
+    pub reconnect: bool,
+}
+
+impl Default for DivergenceConfig {
+    fn default() -> Self {
+        DivergenceConfig {
+            enabled: true,
+            interval_secs: 5.0,
+            max_price_gap: 0.05,
+            max_pnl_change_gap: None,
+            checks: 3,
+            reconnect: false,
+        }
+    }
+}
+
+// One connection as a check sees it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reading {
+    pub conn_id: usize,
+    pub price: f64,
+    // None on the first update of a session
+    pub pnl_change: Option<f64>,
+}
+
+// Connections out of line with the median of all of them, by conn_id. Nothing is
+// out of line with fewer than MIN_PEERS readings.
+pub fn outliers(readings: &[Reading], config: &DivergenceConfig) -> Vec<usize> {
+    if readings.len() < MIN_PEERS {
+        return Vec::new();
+    }
+    let mut prices: Vec<f64> = readings.iter().map(|reading| reading.price).collect();
+    let median_price = median(&mut prices).unwrap_or_default();
+    let mut changes: Vec<f64> = readings
+        .iter()
+        .filter_map(|reading| reading.pnl_change)
+        .collect();
+    let median_change = (changes.len() >= MIN_PEERS)
+        .then(|| median(&mut changes))
+        .flatten();
+
+    let mut out: Vec<usize> = readings
+        .iter()
+        .filter(|reading| {
+            let price_out =
+                (reading.price - median_price).abs() > config.max_price_gap * median_price.abs();
+            let change_out = match (config.max_pnl_change_gap, median_change, reading.pnl_change) {
+                (Some(gap), Some(median), Some(change)) => (change - median).abs() > gap,
+                _ => false,
+            };
+            price_out || change_out
+        })
+        .map(|reading| reading.conn_id)
+        .collect();
+    out.sort_unstable();
+    out
+}
+
+// Counts the checks in a row each connection has been out of line
+#[derive(Debug, Default)]
+pub struct DivergenceMonitor {
+    strikes: HashMap<usize, u32>,
+}
+
+impl DivergenceMonitor {
+    // Take one check's readings, returning the suspect connections by conn_id. A
+    // connection missing from the readings starts its count afresh.
+    pub fn check(&mut self, readings: &[Reading], config: &DivergenceConfig) -> Vec<usize> {
+        let out = outliers(readings, config);
+        self.strikes.retain(|conn_id, _| out.contains(conn_id));
+        for &conn_id in &out {
+            *self.strikes.entry(conn_id).or_default() += 1;
+        }
+        let mut suspects: Vec<usize> = self
+            .strikes
+            .iter()
+            .filter(|(_, &strikes)| strikes >= config.checks)
+            .map(|(&conn_id, _)| conn_id)
+            .collect();
+        suspects.sort_unstable();
+        suspects
+    }
+
+    pub fn forget(&mut self, conn_id: usize) {
+        self.strikes.remove(&conn_id);
+    }
+
+    // Checks in a row the connection has been out of line
+    pub fn strikes(&self, conn_id: usize) -> u32 {
+        self.strikes.get(&conn_id).copied().unwrap_or_default()
+    }
+}
+
+// A request per connection to drop its session and connect again, which each
+// connection's read loop takes alongside the socket
+pub struct Reconnects {
+    requests: Vec<(Sender<()>, Receiver<()>)>,
+}
+
+impl Reconnects {
+    pub fn new(connections: usize) -> Self {
+        Reconnects {
+            requests: (0..connections)
+                .map(|_| async_channel::bounded(1))
+                .collect(),
+        }
+    }
+
+    // False when the connection doesn't exist or is already asked to
+    pub fn request(&self, conn_id: usize) -> bool {
+        self.requests
+            .get(conn_id)
+            .is_some_and(|(requests, _)| requests.try_send(()).is_ok())
+    }
+
+    // Resolves when the connection is asked to reconnect
+    pub async fn next(&self, conn_id: usize) {
+        match self.requests.get(conn_id) {
+            Some((_, requests)) if requests.recv().await.is_ok() => {}
+            _ => future::pending().await,
+        }
+    }
+
+    // Forget a request made before the session it was meant for ended
+    pub fn clear(&self, conn_id: usize) -> bool {
+        self.requests
+            .get(conn_id)
+            .is_some_and(|(_, requests)| requests.try_recv().is_ok())
+    }
+}
+
+// Readings of the connections live and trading. One in the lobby or past the finish
+// may be in another game altogether, so it isn't compared.
+pub async fn readings(shared_state: &SharedState) -> Vec<Reading> {
+    let performances = shared_state.connection_performance.lock().await;
+    let mut readings: Vec<Reading> = performances
+        .iter()
+        .filter(|(_, perf)| perf.health == Health::Live && perf.phase.is_trading())
+        .filter_map(|(&conn_id, perf)| {
+            Some(Reading {
+                conn_id,
+                price: perf.session.last_price?,
+                pnl_change: perf.last_pnl_change,
+            })
+        })
+        .collect();
+    readings.sort_unstable_by_key(|reading| reading.conn_id);
+    readings
+}
+
+// Check the account's connections against each other until shutdown
+pub async fn run_divergence_monitor(
+    shared_state: Arc<SharedState>,
+    config: DivergenceConfig,
+    shutdown: Shutdown,
+) {
+    let mut monitor = DivergenceMonitor::default();
+    let mut suspects: Vec<usize> = Vec::new();
+    loop {
+        let woken = future::select(
+            Box::pin(rt::sleep(Duration::from_secs_f64(config.interval_secs))),
+            Box::pin(shutdown.wait()),
+        )
+        .await;
+        if let Either::Right(_) = woken {
+            return;
+        }
+
+        let readings = readings(&shared_state).await;
+        let now_suspect = monitor.check(&readings, &config);
+        for reading in readings.iter().filter(|reading| {
+            now_suspect.contains(&reading.conn_id) && !suspects.contains(&reading.conn_id)
+        }) {
+            warn!(
+                conn_id = reading.conn_id,
+                price = reading.price,
+                pnl_change = reading.pnl_change,
+                checks = monitor.strikes(reading.conn_id),
+                "Connection out of line with the others, it may be desynced"
+            );
+            // A fresh session gets a fresh count, so it's asked again if it comes back
+            // out of line
+            if config.reconnect && shared_state.reconnects.request(reading.conn_id) {
+                info!(conn_id = reading.conn_id, "Asking it to reconnect");
+                monitor.forget(reading.conn_id);
+            }
+        }
+        suspects = now_suspect;
+        shared_state.set_suspects(&suspects).await;
+    }
+}
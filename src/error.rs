@@ -0,0 +1,154 @@
+use async_tungstenite::tungstenite::{self, http::StatusCode};
+use serde::Serialize;
+use std::fmt;
+use thiserror::Error;
+
+// Why a session ended without anything going wrong
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectReason {
+    // The game finished and the handler asked to start a fresh session
+    GameFinished,
+    // The server closed the socket
+    ServerClosed,
+    // The watchdog gave up waiting for the server
+    Unresponsive,
+    // The server turned down our session token, so the handshake is done again
+    TokenRejected,
+    // Out of line with the other connections for long enough to look desynced
+    Diverged,
+    // The server didn't accept the connection or start the game in time
+    HandshakeTimedOut,
+    // The server kept sending frames we couldn't read
+    ParseStorm,
+    Shutdown,
+}
+
+impl DisconnectReason {
+    // The server misbehaved, so it counts towards the backoff like an error would
+    pub fn is_fault(self) -> bool {
+        matches!(self, DisconnectReason::ParseStorm)
+    }
+}
+
+// Tungstenite errors are boxed, they'd make every Result carrying one huge
+#[derive(Debug, Error)]
+pub enum BotError {
+    // Couldn't connect, or the connection dropped while reading
+    #[error("connection failed: {0}")]
+    Connect(#[source] Box<tungstenite::Error>),
+    #[error("server rejected our credentials: {0}")]
+    Auth(String),
+    #[error("send failed: {0}")]
+    Send(#[source] Box<tungstenite::Error>),
+    // The server broke the websocket protocol
+    #[error("protocol error: {0}")]
+    Protocol(String),
+    // Nothing will change by retrying, e.g. an unusable url
+    #[error("configuration error: {0}")]
+    Config(String),
+}
+
+impl BotError {
+    // Sort out why connect_async failed
+    pub fn from_connect(error: tungstenite::Error) -> BotError {
+        match error {
+            tungstenite::Error::Http(response)
+                if matches!(
+                    response.status(),
+                    StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+                ) =>
+            {
+                BotError::Auth(format!("HTTP {}", response.status()))
+            }
+            tungstenite::Error::Url(e) => BotError::Config(e.to_string()),
+            error => BotError::Connect(Box::new(error)),
+        }
+    }
+
+    // Sort out why reading from an open socket failed
+    pub fn from_read(error: tungstenite::Error) -> BotError {
+        match error {
+            tungstenite::Error::Protocol(_)
+            | tungstenite::Error::Utf8
+            | tungstenite::Error::Capacity(_) => BotError::Protocol(error.to_string()),
+            error => BotError::Connect(Box::new(error)),
+        }
+    }
+}
+
+// How each of a connection's sessions has ended, over the run
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DisconnectCounts {
+    pub game_finished: usize,
+    pub server_closed: usize,
+    pub unresponsive: usize,
+    pub token_rejected: usize,
+    pub diverged: usize,
+    pub handshake_timed_out: usize,
+    pub parse_storm: usize,
+    pub shutdown: usize,
+    // Couldn't connect, or the socket failed while reading
+    pub ws_error: usize,
+    pub send_failed: usize,
+    pub protocol_error: usize,
+    pub auth_failed: usize,
+    pub config_error: usize,
+}
+
+impl DisconnectCounts {
+    pub fn record(&mut self, outcome: &Result<DisconnectReason, BotError>) {
+        let count = match outcome {
+            Ok(DisconnectReason::GameFinished) => &mut self.game_finished,
+            Ok(DisconnectReason::ServerClosed) => &mut self.server_closed,
+            Ok(DisconnectReason::Unresponsive) => &mut self.unresponsive,
+            Ok(DisconnectReason::TokenRejected) => &mut self.token_rejected,
+            Ok(DisconnectReason::Diverged) => &mut self.diverged,
+            Ok(DisconnectReason::HandshakeTimedOut) => &mut self.handshake_timed_out,
+            Ok(DisconnectReason::ParseStorm) => &mut self.parse_storm,
+            Ok(DisconnectReason::Shutdown) => &mut self.shutdown,
+            Err(BotError::Connect(_)) => &mut self.ws_error,
+            Err(BotError::Send(_)) => &mut self.send_failed,
+            Err(BotError::Protocol(_)) => &mut self.protocol_error,
+            Err(BotError::Auth(_)) => &mut self.auth_failed,
+            Err(BotError::Config(_)) => &mut self.config_error,
+        };
+        *count += 1;
+    }
+
+    fn named(&self) -> [(&'static str, usize); 13] {
+        [
+            ("game finished", self.game_finished),
+            ("server closed", self.server_closed),
+            ("unresponsive", self.unresponsive),
+            ("token rejected", self.token_rejected),
+            ("diverged", self.diverged),
+            ("handshake timed out", self.handshake_timed_out),
+            ("parse storm", self.parse_storm),
+            ("shutdown", self.shutdown),
+            ("websocket error", self.ws_error),
+            ("send failed", self.send_failed),
+            ("protocol error", self.protocol_error),
+            ("auth failed", self.auth_failed),
+            ("config error", self.config_error),
+        ]
+    }
+
+    pub fn total(&self) -> usize {
+        self.named().iter().map(|(_, count)| count).sum()
+    }
+}
+
+// Only the reasons seen, e.g. "game finished=3, server closed=1"
+impl fmt::Display for DisconnectCounts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut seen = self.named().into_iter().filter(|(_, count)| *count > 0);
+        match seen.next() {
+            Some((name, count)) => write!(f, "{}={}", name, count)?,
+            None => return write!(f, "none"),
+        }
+        for (name, count) in seen {
+            write!(f, ", {}={}", name, count)?;
+        }
+        Ok(())
+    }
+}
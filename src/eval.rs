@@ -0,0 +1,174 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::io;
+use std::path::Path;
+
+use crate::history::Indicators;
+use crate::persist::SavedParams;
+use crate::state::{Sizing, StrategyParams};
+use crate::strategy::{
+    forecast_signal, momentum_signal, refine_decision, AgreementConfig, BlendStrategy,
+    MarketContext, Strategy,
+};
+
+// Every way a signal can be sized, in the order they're shown
+const SIZINGS: [Sizing; 3] = [Sizing::AllIn, Sizing::Proportional, Sizing::Kelly];
+
+// One state update to work a decision out for, as typed at the command line
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EvalInput {
+    pub momentum: f64,
+    pub forecast: f64,
+    pub position: i32,
+    pub position_limit: i32,
+}
+
+// The trade one sizing mode comes to
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct SizedTrade {
+    pub sizing: Sizing,
+    pub volume: i32,
+    pub position_after: i32,
+}
+
+// How the blend strategy reads one state update, from the tanh signals to the volume
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct EvalReport {
+    pub momentum_signal: f64,
+    pub forecast_signal: f64,
+    pub combined_signal: f64,
+    // Whether the combined signal is past the deadband, which a live connection needs
+    // before it trades on it
+    pub actionable: bool,
+    // Share of the size the momentum band takes
+    pub scale: f64,
+    pub signals_agree: bool,
+    pub sizes: Vec<SizedTrade>,
+}
+
+// Run the decision math on plain inputs. There's no history, so Kelly sizes as
+// proportional does until it has some, and nothing is recorded.
+pub fn evaluate(input: EvalInput, params: &StrategyParams) -> EvalReport {
+    let agreement = AgreementConfig::default();
+    let ctx = context(input, params);
+    let refined = refine_decision(BlendStrategy.decide(&ctx), &ctx, &[], &agreement);
+
+    let sizes = SIZINGS
+        .iter()
+        .map(|&sizing| {
+            let params = StrategyParams {
+                sizing,
+                ..params.clone()
+            };
+            let ctx = context(input, &params);
+            let decision = refine_decision(BlendStrategy.decide(&ctx), &ctx, &[], &agreement);
+            SizedTrade {
+                sizing,
+                volume: decision.decision.volume,
+                position_after: input.position + decision.decision.volume,
+            }
+        })
+        .collect();
+    EvalReport {
+        momentum_signal: momentum_signal(input.momentum, params),
+        forecast_signal: forecast_signal(input.forecast, params),
+        combined_signal: refined.decision.signal,
+        actionable: refined.decision.signal.abs() > params.deadband,
+        scale: BlendStrategy.scale(&ctx),
+        signals_agree: refined.signals_agree,
+        sizes,
+    }
+}
+
+// No prices behind the inputs, so no indicators of our own either
+fn context(input: EvalInput, params: &StrategyParams) -> MarketContext<'_> {
+    MarketContext {
+        forecast: input.forecast,
+        momentum: input.momentum,
+        position: input.position,
+        position_limit: input.position_limit,
+        recent_prices: &[],
+        indicators: Indicators::default(),
+        params,
+    }
+}
+
+// A params file as the bot or `tune --out` saves it, or the params on their own
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ParamsFile {
+    Saved(SavedParams),
+    Bare(StrategyParams),
+}
+
+pub fn load_eval_params(path: &Path) -> io::Result<StrategyParams> {
+    let text = std::fs::read_to_string(path)?;
+    let params = match serde_json::from_str(&text) {
+        Ok(ParamsFile::Saved(saved)) => saved.params,
+        Ok(ParamsFile::Bare(params)) => params,
+        Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+    };
+    params
+        .validate()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(params)
+}
+
+fn sizing_name(sizing: Sizing) -> &'static str {
+    match sizing {
+        Sizing::AllIn => "all_in",
+        Sizing::Proportional => "proportional",
+        Sizing::Kelly => "kelly",
+    }
+}
+
+pub fn format_eval(input: EvalInput, params: &StrategyParams, report: &EvalReport) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "Momentum {:>8} -> tanh signal {:+.4} (weight {})",
+        input.momentum, report.momentum_signal, params.momentum_weight
+    );
+    let _ = writeln!(
+        out,
+        "Forecast {:>8} -> tanh signal {:+.4} (weight {})",
+        input.forecast, report.forecast_signal, params.forecast_weight
+    );
+    let _ = writeln!(
+        out,
+        "Combined signal {:+.4} ({} the deadband of {}), momentum band scale {}, signals {}",
+        report.combined_signal,
+        if report.actionable { "past" } else { "within" },
+        params.deadband,
+        report.scale,
+        if report.signals_agree {
+            "agree"
+        } else {
+            "disagree"
+        }
+    );
+    let _ = writeln!(
+        out,
+        "\nFrom position {} of limit {}:",
+        input.position, input.position_limit
+    );
+    let _ = writeln!(
+        out,
+        "  {:<14} {:>6} {:>14}",
+        "sizing", "volume", "position after"
+    );
+    for size in &report.sizes {
+        let _ = writeln!(
+            out,
+            "  {:<14} {:>+6} {:>14}",
+            sizing_name(size.sizing),
+            size.volume,
+            size.position_after
+        );
+    }
+    let _ = writeln!(
+        out,
+        "  (kelly sizes as proportional until there's history to estimate it from)"
+    );
+    out
+}
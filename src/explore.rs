@@ -0,0 +1,147 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::optimizer::{MAX_WEIGHT, MIN_WEIGHT};
+use crate::state::StrategyParams;
+use crate::strategy::OptimizerConfig;
+
+// A param exploration may move, how far it may go and the most it moves at once
+struct Bounds {
+    name: &'static str,
+    min: f64,
+    max: f64,
+    max_step: f64,
+    // One of the two weights, whose steps are held to the optimizer's weight step too
+    weight: bool,
+    get: fn(&StrategyParams) -> f64,
+    set: fn(&mut StrategyParams, f64),
+}
+
+macro_rules! bounds {
+    ($field:ident, $min:expr, $max:expr, $max_step:expr) => {
+        Bounds {
+            name: stringify!($field),
+            min: $min,
+            max: $max,
+            max_step: $max_step,
+            weight: false,
+            get: |params| params.$field,
+            set: |params, value| params.$field = value,
+        }
+    };
+}
+
+// A weight moves the other with it, so the two keep summing to 1
+macro_rules! weight {
+    ($field:ident, $other:ident) => {
+        Bounds {
+            name: stringify!($field),
+            min: MIN_WEIGHT,
+            max: MAX_WEIGHT,
+            max_step: 0.2,
+            weight: true,
+            get: |params| params.$field,
+            set: |params, value| {
+                params.$field = value;
+                params.$other = 1.0 - value;
+            },
+        }
+    };
+}
+
+// Kept to the range the correlation-based update works in
+fn explorable() -> [Bounds; 4] {
+    [
+        weight!(momentum_weight, forecast_weight),
+        weight!(forecast_weight, momentum_weight),
+        bounds!(aggressive_factor, 1.0, 2.0, 0.3),
+        bounds!(deadband, 0.0, 0.5, 0.1),
+    ]
+}
+
+// One exploratory change, waiting to be judged at the next optimization
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exploration {
+    pub param: &'static str,
+    pub from: f64,
+    pub to: f64,
+    // Windowed Sharpe when the change was made
+    pub sharpe_before: f64,
+}
+
+impl Exploration {
+    // Put the param back as it was, leaving anything changed since alone
+    pub fn revert(&self, params: &mut StrategyParams) {
+        if let Some(bounds) = explorable().iter().find(|bounds| bounds.name == self.param) {
+            (bounds.set)(params, self.from);
+        }
+    }
+}
+
+// Decides when the optimizer tries a random change instead of its usual update, and
+// remembers the change until it's been judged
+#[derive(Debug)]
+pub struct Explorer {
+    rng: StdRng,
+    epsilon: f64,
+    max_weight_step: f64,
+    pending: Option<Exploration>,
+}
+
+impl Explorer {
+    // Seeded from the config when it has a seed, so runs can be repeated
+    pub fn new(config: &OptimizerConfig) -> Self {
+        Explorer {
+            rng: match config.seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            },
+            epsilon: config.epsilon,
+            max_weight_step: config.max_weight_step,
+            pending: None,
+        }
+    }
+
+    // Whether this cycle explores, with probability epsilon
+    pub fn roll(&mut self) -> bool {
+        self.epsilon > 0.0 && self.rng.gen_bool(self.epsilon)
+    }
+
+    // Move one param, picked at random, by a random amount within its bounds
+    pub fn explore(&mut self, params: &mut StrategyParams, sharpe: f64) -> Exploration {
+        let all = explorable();
+        let bounds = &all[self.rng.gen_range(0..all.len())];
+        let from = (bounds.get)(params);
+        let max_step = if bounds.weight {
+            bounds.max_step.min(self.max_weight_step)
+        } else {
+            bounds.max_step
+        };
+        let step = self.rng.gen_range(max_step / 4.0..=max_step);
+        // Away from whichever bound is closer if the random direction would hit it
+        let up = self.rng.gen_bool(0.5);
+        let step = if (up && from + step <= bounds.max) || from - step < bounds.min {
+            step
+        } else {
+            -step
+        };
+        let to = (from + step).clamp(bounds.min, bounds.max);
+        (bounds.set)(params, to);
+        let exploration = Exploration {
+            param: bounds.name,
+            from,
+            to,
+            sharpe_before: sharpe,
+        };
+        self.pending = Some(exploration.clone());
+        exploration
+    }
+
+    pub fn take_pending(&mut self) -> Option<Exploration> {
+        self.pending.take()
+    }
+
+    pub fn pending(&self) -> Option<&Exploration> {
+        self.pending.as_ref()
+    }
+}
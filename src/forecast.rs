@@ -0,0 +1,102 @@
+use serde::Deserialize;
+use std::collections::VecDeque;
+
+use crate::strategy::correlation;
+
+// How the server's price_forecast is checked against what the price actually did
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ForecastConfig {
+    // Updates after a forecast that the price change is measured over
+    pub horizon: usize,
+    // Most recent samples, across all connections, that accuracy is judged on
+    pub window: usize,
+    // Until there are this many samples the configured forecast_weight is used as is
+    pub min_samples: usize,
+}
+
+impl Default for ForecastConfig {
+    fn default() -> Self {
+        ForecastConfig {
+            horizon: 3,
+            window: 200,
+            min_samples: 30,
+        }
+    }
+}
+
+// A forecast and the price change that followed it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ForecastSample {
+    pub forecast: f64,
+    pub change: f64,
+}
+
+// Forecasts seen by one connection that are still waiting on their horizon
+#[derive(Debug, Clone, Default)]
+pub struct ForecastTracker {
+    pending: VecDeque<(f64, f64)>,
+}
+
+impl ForecastTracker {
+    // Note this update's price and forecast, returning the sample for the forecast
+    // made `horizon` updates ago once there is one
+    pub fn observe(&mut self, price: f64, forecast: f64, horizon: usize) -> Option<ForecastSample> {
+        self.pending.push_back((price, forecast));
+        if self.pending.len() <= horizon {
+            return None;
+        }
+        let (then, forecast) = self.pending.pop_front()?;
+        Some(ForecastSample {
+            forecast,
+            change: price - then,
+        })
+    }
+
+    // Price changes across games mean nothing
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ForecastAccuracy {
+    pub samples: usize,
+    // Share of forecasts that called the direction of a move, ignoring flat ones
+    pub hit_rate: Option<f64>,
+    // Pearson correlation of forecast against the change, None without variance
+    pub correlation: Option<f64>,
+}
+
+impl ForecastAccuracy {
+    pub fn from_samples<'a>(samples: impl IntoIterator<Item = &'a ForecastSample>) -> Self {
+        let samples: Vec<&ForecastSample> = samples.into_iter().collect();
+        let called: Vec<_> = samples
+            .iter()
+            .filter(|sample| sample.forecast != 0.0 && sample.change != 0.0)
+            .collect();
+        let hits = called
+            .iter()
+            .filter(|sample| sample.forecast.signum() == sample.change.signum())
+            .count();
+        ForecastAccuracy {
+            samples: samples.len(),
+            hit_rate: (!called.is_empty()).then(|| hits as f64 / called.len() as f64),
+            correlation: correlation(
+                &samples
+                    .iter()
+                    .map(|sample| (sample.forecast, sample.change))
+                    .collect::<Vec<_>>(),
+            ),
+        }
+    }
+
+    // Multiplier on forecast_weight: 1 during cold start, then the correlation,
+    // so a forecast no better than noise (or worse) is weighted close to zero
+    pub fn weight_scale(&self, config: &ForecastConfig) -> f64 {
+        if self.samples < config.min_samples {
+            return 1.0;
+        }
+        self.correlation.unwrap_or(0.0).clamp(0.0, 1.0)
+    }
+}
@@ -0,0 +1,2004 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use crate::calibration::{format_calibration, Calibration};
+use crate::caps::{Cap, MAX_FINISHED_GAMES};
+use crate::clock::Monotonic;
+use crate::config::Config;
+use crate::console::ManualOrder;
+use crate::forecast::ForecastTracker;
+use crate::history::PriceHistory;
+use crate::hooks::HookRunner;
+use crate::journal::{Journal, JournalEntry};
+use crate::lifecycle::{Lifecycle, LifecycleEvent, SessionLifecycle};
+#[cfg(feature = "auto-optimize")]
+use crate::notify::param_changes;
+use crate::notify::{Alert, Notifier};
+#[cfg(feature = "auto-optimize")]
+use crate::optimizer::{optimize_after_game, publish_params};
+use crate::paper::PaperBook;
+use crate::persist::PersistConfig;
+use crate::protocol::{
+    outgoing, ClientEvent, ClientMessage, ConnectionAck, FinishData, ParseError, PuzzleData,
+    ServerError, ServerErrorKind, ServerEvent, Session, StateUpdate, Token,
+};
+use crate::risk::{
+    clamp_to_limit, BreakerEvent, DuplicateFilter, ExposureRequest, QuarantineEvent,
+};
+use crate::schedule::GameClock;
+#[cfg(feature = "puzzles")]
+use crate::solver::{default_solvers, PuzzleSolver};
+use crate::state::{
+    others_profitable, ConnectionPerformance, GamePhase, LastTrade, PendingTrade, PerformanceData,
+    Reconciliation, SessionContext, Settlement, SharedState,
+};
+#[cfg(feature = "puzzles")]
+use crate::strategy::handle_puzzle_impact;
+use crate::strategy::{
+    determine_trade_volume, dominant_driver, DecisionMode, Driver, MarketContext, Stance, Strategy,
+};
+#[cfg(feature = "auto-optimize")]
+use crate::summary::ParamDiff;
+use crate::summary::{FinishedGames, GameAccumulator, GameSummary};
+
+// What the caller should do with the connection after an event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Disconnect,
+    // The server won't accept us as we are, so start over with a new session
+    Reconnect,
+    // The server turned down our token, so reconnect straight away without it
+    Reauthenticate,
+    // The handshake stalled, so connect again from scratch
+    Retry,
+    // Nothing the server sends can be read, so try a fresh socket
+    Unreadable,
+}
+
+// When a frame came off the socket
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Receipt {
+    pub received: Monotonic,
+    // Off the high-resolution timer, for how long the trade it leads to takes to go out
+    pub arrived: Instant,
+}
+
+impl Receipt {
+    pub fn at(received: Monotonic) -> Self {
+        Receipt {
+            received,
+            arrived: Instant::now(),
+        }
+    }
+}
+
+// Auth errors in one session before giving up on it
+const AUTH_ERRORS_BEFORE_RECONNECT: usize = 3;
+
+// A text frame there's nothing to be made of
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameError {
+    // Its size in bytes, left unread
+    Oversized(usize),
+    Malformed(ParseError),
+}
+
+// A text frame as read off the socket, parsed once for whoever looks at it first
+pub type Frame = Result<ServerEvent, FrameError>;
+
+// Parse a text frame, leaving anything over the size limit unread
+pub fn read_frame(text: &str, max_bytes: usize) -> Frame {
+    if text.len() > max_bytes {
+        return Err(FrameError::Oversized(text.len()));
+    }
+    ServerEvent::parse(text).map_err(FrameError::Malformed)
+}
+
+// Over which unreadable frames are counted against `max_errors_per_minute`
+const FRAME_ERROR_WINDOW_SECS: f64 = 60.0;
+
+// When this session's recent frames were dropped or failed to parse, oldest first
+#[derive(Debug, Default)]
+struct FrameErrors {
+    times: VecDeque<Monotonic>,
+}
+
+impl FrameErrors {
+    // Count one, returning how many there have been over the window
+    fn record(&mut self, now: Monotonic) -> usize {
+        while self
+            .times
+            .front()
+            .is_some_and(|&time| now - time >= FRAME_ERROR_WINDOW_SECS)
+        {
+            self.times.pop_front();
+        }
+        self.times.push_back(now);
+        self.times.len()
+    }
+}
+
+// State updates a trade has to show up in the position before it's given up on
+const PENDING_TRADE_UPDATES: usize = 2;
+
+// The decision the next PnL change is credited to, and the inputs it was made on
+#[derive(Debug, Clone, Copy, Default)]
+struct HeldSignal {
+    decision_id: u64,
+    driver: Driver,
+    // Whether a trade went out on it, rather than it only holding what was there
+    sent: bool,
+    signal: f64,
+    momentum: f64,
+    forecast: f64,
+}
+
+// The latest trade sent, in case the server rejects it
+#[derive(Debug, Clone, Copy)]
+struct SentTrade {
+    volume: i32,
+    // Already a clamped resend, so it isn't resent again
+    resend: bool,
+}
+
+// Whether someone at the console has taken the connection's trading over. It's handed
+// back once they flatten it, after one more state update so the PnL change of the
+// position they held isn't credited to the strategy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Manual {
+    #[default]
+    Off,
+    On,
+    Releasing,
+}
+
+// What's tracked per instrument when a game has more than one. The handler's own
+// fields hold the instrument of the latest state update; the others wait here.
+#[derive(Default)]
+struct Market {
+    price_history: PriceHistory,
+    forecasts: ForecastTracker,
+    pending_trade: Option<PendingTrade>,
+    held_signal: HeldSignal,
+    sent_trade: Option<SentTrade>,
+    paper: Option<PaperBook>,
+    session: SessionContext,
+}
+
+// Per-connection event handling, independent of where messages come from or go to.
+// The live loop feeds it frames from the websocket and sends what it queues in the
+// outbox; replay feeds it a recorded transcript and just prints the outbox.
+pub struct ConnectionHandler {
+    conn_id: usize,
+    config: Arc<Config>,
+    shared_state: Arc<SharedState>,
+    strategy: Box<dyn Strategy>,
+    // Tried in order on each puzzle's question
+    #[cfg(feature = "puzzles")]
+    solvers: Vec<Box<dyn PuzzleSolver>>,
+    // Prices seen this game
+    price_history: PriceHistory,
+    // Forecasts waiting to be checked against the price
+    forecasts: ForecastTracker,
+    // Trades not yet reflected in the reported position, which decisions size around
+    pending_trade: Option<PendingTrade>,
+    // The last decision, which the next PnL change is credited to
+    held_signal: HeldSignal,
+    // State updates still to watch before the strategy trades
+    warm_up_left: u32,
+    // Kept across reconnects mid-game, where a replayed update is most likely
+    duplicates: DuplicateFilter,
+    manual: Manual,
+    game: GameAccumulator,
+    // How this game's inputs and signals were spread, reported at the finish
+    calibration: Calibration,
+    journal: Option<Journal>,
+    notifier: Option<Notifier>,
+    // Where the optimizer's params are kept for the next run. Only a live connection
+    // has one, so offline games never touch the bot's saved params.
+    #[cfg_attr(not(feature = "auto-optimize"), allow(dead_code))]
+    persist: Option<PersistConfig>,
+    hooks: Option<HookRunner>,
+    games: Option<FinishedGames>,
+    // Shadow position and PnL when trades are simulated rather than sent
+    paper: Option<PaperBook>,
+    // Summary of the last finished game, until the caller takes it
+    summary: Option<GameSummary>,
+    // Between the first state update of a game and its finish, so a new session
+    // is a reconnect that has to pick the game back up
+    mid_game: bool,
+    // When the game's first state update came, for the optimizer's pass over the game
+    // at its finish
+    game_started: Option<Monotonic>,
+    // Counting down through a game; one that goes up means the next game has begun
+    updates_remaining: Option<u32>,
+    // How long until the next game, when the last finish said
+    restart_after: Option<f64>,
+    // For context when the server reports an error
+    last_sent: Option<ClientMessage>,
+    sent_trade: Option<SentTrade>,
+    auth_errors: usize,
+    // Frames this session dropped or couldn't parse, for telling a poisoned connection
+    frame_errors: FrameErrors,
+    // Credentials from the last connection ack, kept across reconnects
+    session: Option<Session>,
+    // Where the session is in the handshake and the game
+    lifecycle: SessionLifecycle,
+    // The instrument the fields above are for, from the game's first state update on.
+    // Only a game with several has any parked.
+    instrument: Option<Option<String>>,
+    parked: HashMap<Option<String>, Market>,
+}
+
+// Limit assumed for puzzle trades before the first state update of a session
+const DEFAULT_POSITION_LIMIT: i32 = 3;
+// Longest a finish's hint about the next game is taken at its word
+const MAX_RESTART_WAIT_SECS: f64 = 300.0;
+
+impl ConnectionHandler {
+    pub fn new(conn_id: usize, config: Arc<Config>, shared_state: Arc<SharedState>) -> Self {
+        let strategy = config.strategy_for(conn_id).build();
+        let paper = config.dry_run.then(PaperBook::default);
+        let warm_up_left = config.warm_up.updates;
+        let lifecycle = SessionLifecycle::new(
+            &config.alias(conn_id),
+            &config.player_id,
+            config.handshake.clone(),
+        );
+        ConnectionHandler {
+            conn_id,
+            config,
+            shared_state,
+            strategy,
+            #[cfg(feature = "puzzles")]
+            solvers: default_solvers(),
+            price_history: PriceHistory::default(),
+            forecasts: ForecastTracker::default(),
+            pending_trade: None,
+            held_signal: HeldSignal::default(),
+            warm_up_left,
+            duplicates: DuplicateFilter::default(),
+            manual: Manual::Off,
+            game: GameAccumulator::default(),
+            calibration: Calibration::default(),
+            journal: None,
+            notifier: None,
+            persist: None,
+            hooks: None,
+            games: None,
+            paper,
+            summary: None,
+            mid_game: false,
+            game_started: None,
+            updates_remaining: None,
+            restart_after: None,
+            last_sent: None,
+            sent_trade: None,
+            auth_errors: 0,
+            frame_errors: FrameErrors::default(),
+            session: None,
+            lifecycle,
+            instrument: None,
+            parked: HashMap::new(),
+        }
+    }
+
+    // Trade with this rather than the strategy the config picks for the connection
+    pub fn with_strategy(mut self, strategy: Box<dyn Strategy>) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    // Try this on puzzle questions ahead of the solvers already there
+    #[cfg(feature = "puzzles")]
+    pub fn with_puzzle_solver(mut self, solver: Box<dyn PuzzleSolver>) -> Self {
+        self.solvers.insert(0, solver);
+        self
+    }
+
+    // Add every finished game's summary to the list
+    pub fn with_finished_games(mut self, games: FinishedGames) -> Self {
+        self.games = Some(games);
+        self
+    }
+
+    // Record every state update's decision to the journal
+    pub fn with_journal(mut self, journal: Journal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    // Post finished games and breaker trips to the webhook
+    pub fn with_notifier(mut self, notifier: Notifier) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    // Save the params each game's optimizer pass settles on
+    pub fn with_persist(mut self, persist: PersistConfig) -> Self {
+        self.persist = Some(persist);
+        self
+    }
+
+    // Pass updates, decisions, trades and finishes on to the hook task
+    pub fn with_hooks(mut self, hooks: HookRunner) -> Self {
+        self.hooks = Some(hooks);
+        self
+    }
+
+    pub fn conn_id(&self) -> usize {
+        self.conn_id
+    }
+
+    pub fn journal(&self) -> Option<&Journal> {
+        self.journal.as_ref()
+    }
+
+    pub fn strategy_name(&self) -> &'static str {
+        self.strategy.name()
+    }
+
+    pub fn price_history(&self) -> &PriceHistory {
+        &self.price_history
+    }
+
+    pub fn take_summary(&mut self) -> Option<GameSummary> {
+        self.summary.take()
+    }
+
+    pub fn paper_book(&self) -> Option<&PaperBook> {
+        self.paper.as_ref()
+    }
+
+    // When to reconnect for the next game, if the last finish said
+    pub fn take_restart_hint(&mut self) -> Option<Duration> {
+        self.restart_after
+            .take()
+            .map(|secs| Duration::from_secs_f64(secs.min(MAX_RESTART_WAIT_SECS)))
+    }
+
+    pub fn lifecycle(&self) -> Lifecycle {
+        self.lifecycle.state()
+    }
+
+    // When the handshake step under way gives up, for the read loop to wake by
+    pub fn handshake_deadline(&self) -> Option<Monotonic> {
+        self.lifecycle.deadline()
+    }
+
+    // Reset per-session state and build the connection message that opens a session
+    pub async fn start_session(&mut self) -> Vec<ClientMessage> {
+        // Only a game we were in the middle of is picked back up
+        if !self.mid_game {
+            self.clear_game();
+        }
+        let pending_trade = self.pending_trade.take();
+        self.price_history.clear();
+        self.forecasts.clear();
+        self.held_signal = HeldSignal::default();
+        self.sent_trade = None;
+        self.auth_errors = 0;
+        self.frame_errors = FrameErrors::default();
+        self.warm_up_left = self.config.warm_up.updates;
+
+        // PnL reported after a reconnect includes what was made while we were away.
+        // Mid-game, what we knew about the position is kept for the first state
+        // update to check against; otherwise the new game starts from nothing.
+        {
+            let mut performances = self.shared_state.connection_performance.lock().await;
+            let perf = performances.entry(self.conn_id).or_default();
+            if self.mid_game {
+                perf.reset_pnl_baseline();
+                perf.session.begin_resync(pending_trade.as_ref());
+                for market in self.parked.values_mut() {
+                    let pending_trade = market.pending_trade.take();
+                    market.price_history.clear();
+                    market.forecasts.clear();
+                    market.held_signal = HeldSignal::default();
+                    market.sent_trade = None;
+                    market.session.begin_resync(pending_trade.as_ref());
+                }
+            } else {
+                perf.end_game();
+            }
+            // A game cut short by a disconnect isn't summarized
+            perf.latency.start_game();
+            self.game = GameAccumulator::start(self.shared_state.now(), perf);
+            enter_phase(perf, GamePhase::Lobby, "connected");
+        }
+
+        // A reconnect hands back the token we were given, so the server can resume us
+        let token = self
+            .session
+            .as_ref()
+            .map_or_else(Token::default, |session| session.token.clone());
+        let (_, events) = self.lifecycle.handle(LifecycleEvent::Opened {
+            token,
+            now: self.shared_state.monotonic(),
+        });
+        self.authenticate(events)
+    }
+
+    // Give up on a handshake that has taken too long
+    pub fn tick(&mut self) -> Flow {
+        let before = self.lifecycle.state();
+        let (after, _) = self.lifecycle.handle(LifecycleEvent::Tick {
+            now: self.shared_state.monotonic(),
+        });
+        if before.is_handshake() && after == Lifecycle::Connecting {
+            Flow::Retry
+        } else {
+            Flow::Continue
+        }
+    }
+
+    // Parse a text frame and handle it, ignoring anything oversized or malformed
+    pub async fn handle_text(&mut self, text: &str, outbox: &mut Vec<ClientMessage>) -> Flow {
+        let receipt = Receipt::at(self.shared_state.monotonic());
+        self.handle_received(text, receipt, outbox).await
+    }
+
+    // A frame off the live socket, judged for staleness by when it arrived
+    pub async fn handle_received(
+        &mut self,
+        text: &str,
+        receipt: Receipt,
+        outbox: &mut Vec<ClientMessage>,
+    ) -> Flow {
+        let frame = read_frame(text, self.config.frames.max_bytes);
+        self.handle_frame(frame, receipt, outbox).await
+    }
+
+    // As handle_received, for a frame the connection has already parsed
+    pub async fn handle_frame(
+        &mut self,
+        frame: Frame,
+        receipt: Receipt,
+        outbox: &mut Vec<ClientMessage>,
+    ) -> Flow {
+        let unreadable = match frame {
+            Ok(event) => return self.dispatch(event, receipt, outbox).await,
+            Err(unreadable) => unreadable,
+        };
+        {
+            let mut performances = self.shared_state.connection_performance.lock().await;
+            let perf = performances.entry(self.conn_id).or_default();
+            match unreadable {
+                FrameError::Oversized(bytes) => {
+                    warn!(
+                        bytes,
+                        max_bytes = self.config.frames.max_bytes,
+                        "Dropping an oversized frame unread"
+                    );
+                    perf.oversized_frames += 1;
+                }
+                FrameError::Malformed(e) => {
+                    warn!(error = %e, "Ignoring message");
+                    perf.malformed_frames += 1;
+                }
+            }
+        }
+        let errors = self.frame_errors.record(receipt.received);
+        let max_errors = self.config.frames.max_errors_per_minute;
+        if errors >= max_errors {
+            warn!(
+                errors,
+                max_errors, "Too many unreadable frames in a minute, reconnecting"
+            );
+            return Flow::Unreadable;
+        }
+        Flow::Continue
+    }
+
+    pub async fn handle_event(
+        &mut self,
+        event: ServerEvent,
+        outbox: &mut Vec<ClientMessage>,
+    ) -> Flow {
+        let receipt = Receipt::at(self.shared_state.monotonic());
+        self.dispatch(event, receipt, outbox).await
+    }
+
+    // An order typed at the console. It goes out like any other trade, inside the
+    // position limit, and the strategy stays out of the way until a flat hands the
+    // connection back.
+    pub async fn handle_manual(&mut self, order: ManualOrder, outbox: &mut Vec<ClientMessage>) {
+        let conn_id = self.conn_id;
+        let mut performances = self.shared_state.connection_performance.lock().await;
+        let perf = performances.entry(conn_id).or_default();
+        let Some(reported) = perf.session.position else {
+            warn!(%order, "No position reported yet, ignoring the manual order");
+            return;
+        };
+        let position = self
+            .pending_trade
+            .map_or(reported, |pending| pending.expected_position());
+        let position_limit = perf
+            .session
+            .position_limit
+            .unwrap_or(DEFAULT_POSITION_LIMIT);
+        let wanted = match order {
+            ManualOrder::Trade(volume) => volume,
+            ManualOrder::Flat => -position,
+        };
+        let mut volume = clamp_to_limit(position, wanted, position_limit);
+        if volume != wanted {
+            warn!(
+                wanted,
+                volume, position, position_limit, "Manual trade clamped to the position limit"
+            );
+        }
+        // Flattening is always let through, adding to the position isn't
+        let halted = !self.shared_state.trading_enabled() || perf.breaker.is_halted();
+        if volume != 0 && halted && order != ManualOrder::Flat {
+            warn!(%order, "Trading halted, not sending the manual trade");
+            volume = 0;
+        } else if volume != 0 && !perf.phase.is_trading() {
+            warn!(%order, phase = %perf.phase, "Not in a trading stage, not sending the manual trade");
+            volume = 0;
+        } else if volume != 0 && perf.session.is_resyncing() {
+            warn!(%order, "Position not yet confirmed after reconnect, not sending the manual trade");
+            volume = 0;
+        }
+        // Held to the account's exposure ceiling like any other trade, at full strength
+        let mut exposure_book = self.shared_state.exposure_book.lock().await;
+        let exposure = ExposureRequest {
+            position,
+            position_limit,
+            volume,
+            signal: f64::from(volume.signum()),
+        };
+        let allowed = exposure_book.fit(&self.shared_state.exposure, conn_id, exposure);
+        if allowed != volume {
+            warn!(
+                %order,
+                wanted = volume,
+                volume = allowed,
+                "Manual trade shrunk to stay under the account's exposure ceiling"
+            );
+            volume = allowed;
+        }
+
+        let sent = volume != 0
+            && execute_trade(
+                &mut self.paper,
+                &self.config.player_id,
+                self.session.as_ref(),
+                self.instrument.as_ref().and_then(Option::as_deref),
+                volume,
+                outbox,
+            );
+        // Nothing sent leaves the connection's claim as it was
+        if sent {
+            exposure_book.book(conn_id, exposure, allowed, volume);
+        }
+        drop(exposure_book);
+        if let Some(journal) = &self.journal {
+            journal.record(JournalEntry {
+                timestamp: self.shared_state.now(),
+                conn_id,
+                strategy: self.strategy.name().to_string(),
+                price: perf.session.last_price.unwrap_or_default(),
+                forecast: 0.0,
+                momentum: 0.0,
+                combined_signal: 0.0,
+                position_before: position,
+                position_after: if sent { position + volume } else { position },
+                volume,
+                sent,
+                pnl: perf.last_pnl,
+                pnl_change: None,
+                mode: DecisionMode::Manual,
+                account: self.config.account.clone(),
+                arm: self.shared_state.arm_name(conn_id).await,
+                decision_id: None,
+                driver: None,
+            });
+        }
+        match order {
+            ManualOrder::Trade(_) if sent => {
+                if self.manual == Manual::Off {
+                    info!("Under manual control until flattened from the console");
+                }
+                self.manual = Manual::On;
+            }
+            ManualOrder::Flat if sent || self.manual == Manual::On => {
+                self.manual = Manual::Releasing;
+            }
+            _ => {}
+        }
+        if !sent {
+            return;
+        }
+        self.game.charge(self.config.costs.cost(volume));
+        info!(
+            %order,
+            volume,
+            position,
+            "{}",
+            if self.paper.is_some() {
+                "Simulated manual trade"
+            } else {
+                "Manual trade"
+            }
+        );
+        track_sent(
+            &mut self.pending_trade,
+            &mut self.sent_trade,
+            perf,
+            self.shared_state.monotonic(),
+            volume,
+        );
+        if let Some(hooks) = &self.hooks {
+            hooks.trade_sent(conn_id, volume);
+        }
+        self.last_sent = outbox.last().cloned();
+    }
+
+    async fn dispatch(
+        &mut self,
+        event: ServerEvent,
+        receipt: Receipt,
+        outbox: &mut Vec<ClientMessage>,
+    ) -> Flow {
+        // Settings a config reload changed apply from the next event on
+        if let Some(config) = self.shared_state.reloaded_config.read().await.as_ref() {
+            self.config = Arc::clone(config);
+        }
+        // A stream of frames out of order mustn't keep a stalled handshake alive
+        if self.tick() == Flow::Retry {
+            return Flow::Retry;
+        }
+        let queued = outbox.len();
+        let flow = match event {
+            ServerEvent::Connection(ack) => {
+                self.handle_ack(ack, outbox);
+                Flow::Continue
+            }
+            ServerEvent::State(update) => {
+                if self.in_game() {
+                    self.handle_state(update, receipt, outbox).await;
+                }
+                Flow::Continue
+            }
+            ServerEvent::Puzzle(puzzle) => {
+                if self.in_game() {
+                    self.handle_puzzle(puzzle, outbox).await;
+                }
+                Flow::Continue
+            }
+            ServerEvent::Finish(finish) => {
+                let before = self.lifecycle.state();
+                let (after, _) = self.lifecycle.handle(LifecycleEvent::Finish);
+                if before != after {
+                    self.handle_finish(finish).await
+                } else {
+                    Flow::Continue
+                }
+            }
+            ServerEvent::Error(error) => self.handle_error(error, outbox).await,
+            ServerEvent::Unknown => Flow::Continue,
+        };
+        if let Some(message) = outbox.last() {
+            self.last_sent = Some(message.clone());
+        }
+        if let Some(hooks) = &self.hooks {
+            for message in &outbox[queued..] {
+                if let ClientEvent::Trade(trade) = &message.event {
+                    hooks.trade_sent(self.conn_id, trade.volume);
+                }
+            }
+        }
+        flow
+    }
+
+    // Whether game traffic belongs to a game under way, rather than coming before it
+    fn in_game(&mut self) -> bool {
+        let (state, _) = self.lifecycle.handle(LifecycleEvent::Game);
+        state == Lifecycle::InGame
+    }
+
+    // Put what the lifecycle sends into messages, with any credentials we hold
+    fn authenticate(&self, events: Vec<ClientEvent>) -> Vec<ClientMessage> {
+        events
+            .into_iter()
+            .map(|event| ClientMessage::new("", event).authenticated(self.session.as_ref()))
+            .collect()
+    }
+
+    // Handle connection establishment
+    fn handle_ack(&mut self, ack: ConnectionAck, outbox: &mut Vec<ClientMessage>) {
+        let (_, events) = self.lifecycle.handle(LifecycleEvent::Ack {
+            player_id: &ack.player_id,
+            now: self.shared_state.monotonic(),
+        });
+        if events.is_empty() {
+            return;
+        }
+        // A server that didn't hand out anything this time keeps what it gave before
+        if let Some(session) = ack.session() {
+            info!(
+                token = %session.token,
+                session_id = session.session_id.as_deref(),
+                "Server issued session credentials"
+            );
+            self.session = Some(session);
+        }
+        info!("Established, sending start event");
+
+        outbox.extend(self.authenticate(events));
+    }
+
+    // Park the last instrument's state and take up the one this update is for. A game
+    // with a single instrument, named or not, never switches.
+    async fn switch_instrument(&mut self, instrument: &Option<String>) {
+        let current = match self.instrument.take() {
+            Some(current) if current != *instrument => current,
+            _ => {
+                self.instrument = Some(instrument.clone());
+                return;
+            }
+        };
+        let mut next = self.parked.remove(instrument).unwrap_or_else(|| Market {
+            paper: self.config.dry_run.then(PaperBook::default),
+            ..Market::default()
+        });
+        {
+            let mut performances = self.shared_state.connection_performance.lock().await;
+            let perf = performances.entry(self.conn_id).or_default();
+            std::mem::swap(&mut perf.session, &mut next.session);
+        }
+        std::mem::swap(&mut self.price_history, &mut next.price_history);
+        std::mem::swap(&mut self.forecasts, &mut next.forecasts);
+        std::mem::swap(&mut self.pending_trade, &mut next.pending_trade);
+        std::mem::swap(&mut self.held_signal, &mut next.held_signal);
+        std::mem::swap(&mut self.sent_trade, &mut next.sent_trade);
+        std::mem::swap(&mut self.paper, &mut next.paper);
+        debug!(from = ?current, to = ?instrument, "Switching instrument");
+        self.parked.insert(current, next);
+        self.instrument = Some(instrument.clone());
+    }
+
+    // Where a state update about to be decided on falls in its game, counting it as
+    // seen, for the strategy schedule
+    async fn game_clock(&self, reported: Option<u32>) -> GameClock {
+        if !self.config.schedule.is_enabled() {
+            return GameClock::default();
+        }
+        let seen = self
+            .shared_state
+            .connection_performance
+            .lock()
+            .await
+            .get(&self.conn_id)
+            .map_or(0, |perf| perf.session.updates_seen)
+            + 1;
+        GameClock {
+            fraction: self
+                .config
+                .wind_down
+                .remaining(reported, seen)
+                .map(|remaining| f64::from(seen) / f64::from(seen + remaining)),
+            elapsed_secs: self
+                .game_started
+                .map(|started| self.shared_state.monotonic() - started),
+        }
+    }
+
+    // What the dry run has made on the instruments not being traded right now
+    fn parked_paper_pnl(&self) -> f64 {
+        self.parked
+            .values()
+            .filter_map(|market| market.paper.as_ref())
+            .map(PaperBook::pnl)
+            .sum()
+    }
+
+    // Handle state updates
+    async fn handle_state(
+        &mut self,
+        mut update: StateUpdate,
+        receipt: Receipt,
+        outbox: &mut Vec<ClientMessage>,
+    ) {
+        if let Err(e) = update.validate() {
+            let mut performances = self.shared_state.connection_performance.lock().await;
+            let perf = performances.entry(self.conn_id).or_default();
+            perf.invalid_states += 1;
+            warn!(
+                error = %e,
+                dropped = perf.invalid_states,
+                "Dropping invalid state update"
+            );
+            return;
+        }
+        if self
+            .duplicates
+            .is_repeat(&self.config.duplicates, update.fingerprint())
+        {
+            let mut performances = self.shared_state.connection_performance.lock().await;
+            let perf = performances.entry(self.conn_id).or_default();
+            perf.duplicate_states += 1;
+            if perf.duplicate_states % self.config.duplicates.warn_every == 0 {
+                warn!(
+                    skipped = perf.duplicate_states,
+                    "Skipping repeated state updates often, is the server replaying them?"
+                );
+            } else {
+                debug!(
+                    skipped = perf.duplicate_states,
+                    "Repeated state update skipped"
+                );
+            }
+            return;
+        }
+        // Counting back up means we were away for the finish and this is the next game
+        if self.mid_game
+            && self
+                .updates_remaining
+                .zip(update.updates_remaining)
+                .is_some_and(|(last, now)| now > last)
+        {
+            warn!(
+                last = self.updates_remaining,
+                now = update.updates_remaining,
+                "Next game under way without a finish, closing out the last one"
+            );
+            // The last PnL the server reported for it is all there is to go on
+            let last_pnl = self
+                .shared_state
+                .connection_performance
+                .lock()
+                .await
+                .get(&self.conn_id)
+                .map(|perf| perf.last_pnl);
+            self.end_game(last_pnl).await;
+        }
+        self.updates_remaining = update.updates_remaining;
+        if let Some(hooks) = &self.hooks {
+            hooks.state(self.conn_id, &update);
+        }
+        self.switch_instrument(&update.instrument).await;
+        // The prices come in and signals are recorded as usual, the strategy just
+        // doesn't trade on them yet
+        let warming_up = self.warm_up_left > 0;
+        if warming_up {
+            if self.warm_up_left == self.config.warm_up.updates {
+                info!(
+                    updates = self.warm_up_left,
+                    "Warming up, not trading until the indicators have some prices"
+                );
+            }
+            self.warm_up_left -= 1;
+        }
+        let conn_id = self.conn_id;
+        let shared_state = &self.shared_state;
+        self.mid_game = true;
+        self.game_started.get_or_insert(shared_state.monotonic());
+
+        // In a dry run the strategy, history and optimizer all see the shadow book
+        let parked_pnl = self.parked_paper_pnl();
+        if let Some(paper) = self.paper.as_mut() {
+            paper.mark(update.price);
+            update.position = paper.position;
+            update.pnl = paper.pnl() + parked_pnl;
+        }
+
+        self.price_history.push(update.price);
+        let indicators = self.price_history.indicators();
+        if let Some(sample) = self.forecasts.observe(
+            update.price,
+            update.price_forecast,
+            self.config.forecast.horizon,
+        ) {
+            shared_state.record_forecast(sample).await;
+        }
+
+        // Check whether the trades we sent have shown up in the position. Until they
+        // have, everything below sizes from where they'll leave it, so only the
+        // difference to the target goes out.
+        let settlement = self
+            .pending_trade
+            .take()
+            .map(|pending| pending.settle(update.position, PENDING_TRADE_UPDATES));
+        if let Some(Settlement::Waiting(pending)) = settlement {
+            self.pending_trade = Some(pending);
+        }
+        let position = self
+            .pending_trade
+            .map_or(update.position, |pending| pending.expected_position());
+        shared_state
+            .exposure_book
+            .lock()
+            .await
+            .observe(conn_id, position, update.position_limit);
+
+        // Calculate trade volume against a snapshot of the current params, as the
+        // schedule has them at this point in the game
+        let clock = self.game_clock(update.updates_remaining).await;
+        let params = shared_state.scheduled_params(conn_id, clock).await;
+        let ctx = MarketContext {
+            forecast: update.price_forecast,
+            momentum: update.momentum,
+            position,
+            position_limit: update.position_limit,
+            recent_prices: self.price_history.prices(),
+            indicators,
+            params: &params,
+        };
+        let decision =
+            determine_trade_volume(self.strategy.as_ref(), &ctx, conn_id, shared_state).await;
+        if let Some(hooks) = &self.hooks {
+            if let Some(signal) = shared_state.last_decision(conn_id).await {
+                hooks.decision(signal);
+            }
+        }
+        self.calibration.observe(
+            update.momentum,
+            update.price_forecast,
+            decision.signal,
+            &params,
+        );
+        let mut trade_volume = decision.volume;
+        // Too late to act on, though the update still counts for everything else
+        let age = shared_state.monotonic() - receipt.received;
+        let stale = (age > self.config.staleness.max_age_secs)
+            .then_some("decided too long after it arrived");
+        // Track PnL changes
+        let win_rate;
+        let pnl_change;
+        let decision_id;
+        let driver;
+        {
+            let mut performances = shared_state.connection_performance.lock().await;
+            let quarantine = &self.config.quarantine;
+            let others_profitable =
+                quarantine.enabled && others_profitable(&performances, conn_id, quarantine.trades);
+            // Normally created by start_session, but nothing should depend on that ordering
+            let perf = performances.entry(conn_id).or_default();
+            // A state update means trading, unless the server says otherwise
+            let phase = match update.stage.as_deref() {
+                None => GamePhase::Trading,
+                Some(stage) => stage_phase(stage),
+            };
+            enter_phase(perf, phase, "state update");
+            if let Some(reconciliation) = perf.session.reconcile(update.position) {
+                log_reconciliation(&reconciliation);
+            }
+            let raw_change = perf.track_pnl(update.pnl);
+            self.game.observe(update.position, update.pnl, raw_change);
+            // What our trades cost comes out of the change they're credited with
+            pnl_change = raw_change.map(|change| change - self.game.bill());
+            perf.curve.record(shared_state.monotonic(), update.pnl);
+
+            // Credit this tick to the last filled trade
+            if let Some(pnl_change) = pnl_change {
+                perf.observe_pnl(pnl_change, update.position);
+            }
+
+            perf.session.position = Some(update.position);
+            if let Some(previous) = perf.session.observe_limit(update.position_limit) {
+                // Everything below works off this update's limit, a held puzzle included
+                info!(
+                    from = previous,
+                    to = update.position_limit,
+                    position = update.position,
+                    "Position limit changed"
+                );
+            }
+            perf.session.last_price = Some(update.price);
+            perf.session.updates_seen += 1;
+            let seen = perf.session.updates_seen;
+            perf.session.progress = self
+                .config
+                .wind_down
+                .remaining(update.updates_remaining, seen)
+                .map(|remaining| f64::from(seen) / f64::from(seen + remaining));
+
+            // Ignore weak signals, and don't flip sides without a clear signal the other way.
+            // A stale decision doesn't commit to a side.
+            let (stance, act) = if stale.is_some() {
+                (perf.session.stance, false)
+            } else {
+                perf.session.stance.next(decision.signal, params.deadband)
+            };
+            if stance != perf.session.stance && perf.session.stance != Stance::Flat {
+                info!(
+                    signal = decision.signal,
+                    from = ?perf.session.stance,
+                    to = ?stance,
+                    "Signal flips stance"
+                );
+            }
+            perf.session.stance = stance;
+            if !act {
+                trade_volume = 0;
+            }
+
+            // A position the signal has stopped backing goes back to flat in the end
+            if let Some(held_secs) = perf.session.hold.update(
+                &self.config.hold,
+                shared_state.monotonic().secs(),
+                update.position,
+                decision.signal,
+            ) {
+                perf.holds_expired += 1;
+                info!(
+                    held_secs,
+                    position = update.position,
+                    signal = decision.signal,
+                    "Held too long without the signal re-confirming, reducing to flat"
+                );
+                trade_volume = -position;
+            }
+
+            if warming_up && trade_volume != 0 {
+                debug!(volume = trade_volume, "Warming up, not trading");
+                trade_volume = 0;
+            }
+
+            // A held puzzle position takes priority over the strategy until it plays out
+            #[cfg(feature = "puzzles")]
+            let puzzle_volume =
+                perf.session
+                    .puzzle
+                    .on_state(update.price, position, update.position_limit);
+            #[cfg(not(feature = "puzzles"))]
+            let puzzle_volume: Option<i32> = None;
+            // Puzzle trades are time-critical, so they skip the rate limiter
+            let puzzle_driven = puzzle_volume.is_some();
+            decision_id = perf.decisions;
+            driver = if puzzle_driven {
+                Driver::Puzzle
+            } else {
+                dominant_driver(update.price_forecast, update.momentum, &params)
+            };
+            if let Some(puzzle_volume) = puzzle_volume {
+                #[cfg(feature = "puzzles")]
+                if !perf.session.puzzle.is_holding() {
+                    info!(
+                        position = update.position,
+                        "Puzzle impact played out, unwinding"
+                    );
+                }
+                trade_volume = puzzle_volume;
+            }
+
+            // Under manual control only the risk checks below trade
+            if self.manual != Manual::Off && trade_volume != 0 {
+                debug!(volume = trade_volume, "Under manual control, not trading");
+                trade_volume = 0;
+            }
+
+            // Drawdown circuit breaker
+            let (volume, event) = perf.breaker.check(
+                &self.config.risk,
+                shared_state.monotonic().secs(),
+                update.pnl,
+                position,
+                trade_volume,
+            );
+            trade_volume = volume;
+            match event {
+                Some(BreakerEvent::Tripped { peak, drawdown }) => {
+                    warn!(
+                        pnl = update.pnl,
+                        peak, drawdown, "Drawdown breaker tripped, halting trading"
+                    );
+                    if let Some(notifier) = &self.notifier {
+                        notifier.notify(Alert::BreakerTripped {
+                            conn_id,
+                            pnl: update.pnl,
+                            drawdown,
+                        });
+                    }
+                    if self.config.risk.kill_switch_on_trip
+                        && shared_state.set_trading_enabled(false)
+                    {
+                        warn!("Kill switch on for every connection after the drawdown trip");
+                    }
+                }
+                Some(BreakerEvent::Lifted) => info!("Drawdown cooldown over, resuming trading"),
+                None => {}
+            }
+
+            // Losing on this connection alone looks like its fills, so it sits out a while
+            match perf.quarantine.update(
+                quarantine,
+                shared_state.monotonic().secs(),
+                others_profitable,
+            ) {
+                Some(QuarantineEvent::Started { rolling_pnl }) => warn!(
+                    rolling_pnl,
+                    trades = quarantine.trades,
+                    cooldown_secs = quarantine.cooldown_secs,
+                    "Losing while the other connections make money, observing only"
+                ),
+                Some(QuarantineEvent::Probation) => info!(
+                    reduced_size = quarantine.reduced_size,
+                    "Quarantine over, trading at reduced size"
+                ),
+                Some(QuarantineEvent::Cleared) => info!("Back to full size after quarantine"),
+                None => {}
+            }
+            if perf.quarantine.is_observing() {
+                trade_volume = 0;
+            } else if perf.quarantine.is_on_probation() {
+                trade_volume = clamp_to_limit(
+                    position,
+                    trade_volume,
+                    quarantine.reduced_limit(update.position_limit),
+                );
+            }
+
+            // Nothing new near the finish, just close out what's held
+            let wind_down = &self.config.wind_down;
+            let remaining =
+                wind_down.remaining(update.updates_remaining, perf.session.updates_seen);
+            if wind_down.is_due(remaining) {
+                if !perf.session.winding_down {
+                    info!(
+                        remaining,
+                        position = update.position,
+                        "Near the end of the game, winding down"
+                    );
+                    perf.session.winding_down = true;
+                }
+                trade_volume = -position;
+            }
+
+            // Whatever was decided stays inside the limit. If it came down below what we
+            // hold, the excess goes straight away. A pending trade that would take us past
+            // it is left to the server to turn down, and nothing else goes until it has.
+            let over_limit = update.position.abs() > update.position_limit;
+            trade_volume = clamp_to_limit(position, trade_volume, update.position_limit);
+            if !over_limit && position.abs() > update.position_limit {
+                debug!(
+                    position = update.position,
+                    expected_position = position,
+                    position_limit = update.position_limit,
+                    "Pending trade goes past the limit, waiting on it"
+                );
+                trade_volume = 0;
+            } else if over_limit {
+                info!(
+                    position = update.position,
+                    position_limit = update.position_limit,
+                    volume = trade_volume,
+                    "Over the position limit, reducing"
+                );
+            }
+
+            // Kill switch from the control endpoint, which beats everything above
+            if !shared_state.trading_enabled() && trade_volume != 0 {
+                debug!(volume = trade_volume, "Trading halted, not trading");
+                trade_volume = 0;
+            }
+
+            // Between stages a trade only earns a server error
+            if !perf.phase.is_trading() && trade_volume != 0 {
+                debug!(
+                    volume = trade_volume,
+                    phase = %perf.phase,
+                    "Not in a trading stage, not trading"
+                );
+                perf.held_out_of_phase += 1;
+                trade_volume = 0;
+            }
+
+            if let Some(reason) = stale {
+                perf.stale_states += 1;
+                info!(
+                    reason,
+                    age,
+                    volume = trade_volume,
+                    skipped = perf.stale_states,
+                    "Stale state skipped"
+                );
+                trade_volume = 0;
+            }
+
+            // The account's connections together stay under the exposure ceiling. The
+            // book is held until what goes out is known, so only that takes room.
+            let mut exposure_book = shared_state.exposure_book.lock().await;
+            let exposure = ExposureRequest {
+                position,
+                position_limit: update.position_limit,
+                volume: trade_volume,
+                signal: decision.signal,
+            };
+            let allowed = exposure_book.fit(&shared_state.exposure, conn_id, exposure);
+            if allowed != trade_volume {
+                debug!(
+                    wanted = trade_volume,
+                    volume = allowed,
+                    "Trade shrunk to stay under the account's exposure ceiling"
+                );
+                trade_volume = allowed;
+            }
+
+            // Closing out and getting back inside the limit are time-critical too
+            let rate_limited = !puzzle_driven && !perf.session.winding_down && !over_limit;
+            trade_volume = spend_on_trade(
+                &self.config,
+                perf,
+                shared_state.monotonic().secs(),
+                position,
+                trade_volume,
+                puzzle_driven,
+                rate_limited,
+            );
+            exposure_book.book(conn_id, exposure, allowed, trade_volume);
+            drop(exposure_book);
+
+            let rejected = match settlement {
+                Some(Settlement::Filled(pending)) => {
+                    perf.trades_made += pending.trades;
+                    perf.open_trade(update.position);
+                    false
+                }
+                Some(Settlement::Waiting(pending)) => {
+                    debug!(
+                        volume = pending.volume,
+                        position = update.position,
+                        updates_waited = pending.updates_waited,
+                        "Trade not in the position yet"
+                    );
+                    false
+                }
+                Some(Settlement::Unfilled(pending)) => {
+                    perf.rejected_trades += pending.trades;
+                    warn!(
+                        volume = pending.volume,
+                        position_before = pending.position_before,
+                        position = update.position,
+                        updates_waited = pending.updates_waited,
+                        "Trade not filled, no longer waiting on it"
+                    );
+                    true
+                }
+                None => false,
+            };
+
+            // Record performance data if we've made trades, leaving out
+            // the tick after a rejected trade since nothing happened,
+            // the first tick of a session which only sets the baseline,
+            // ticks spent observing in quarantine, and ticks under manual
+            // control, which the strategy didn't decide
+            let recordable = perf.trades_made > 0
+                && !rejected
+                && !perf.quarantine.is_observing()
+                && self.manual == Manual::Off;
+            if let Some(pnl_change) = pnl_change.filter(|_| recordable) {
+                let perf_data = PerformanceData {
+                    conn_id,
+                    timestamp: shared_state.monotonic(),
+                    momentum: self.held_signal.momentum,
+                    forecast: self.held_signal.forecast,
+                    position: update.position,
+                    trade_volume,
+                    pnl_change,
+                    price: update.price,
+                    total_pnl: update.pnl,
+                    signal: self.held_signal.signal,
+                    decision_id: self.held_signal.decision_id,
+                    driver: self.held_signal.driver,
+                };
+
+                self.game
+                    .credit(self.held_signal.driver, pnl_change, self.held_signal.sent);
+                shared_state.record_performance(perf_data).await;
+            }
+
+            win_rate = perf.win_rate();
+        }
+        if self.manual == Manual::Releasing {
+            info!("Flat after manual trading, handing back to the strategy");
+            self.manual = Manual::Off;
+        }
+
+        debug!(
+            instrument = update.instrument.as_deref(),
+            price = update.price,
+            forecast = update.price_forecast,
+            momentum = update.momentum,
+            position = update.position,
+            position_limit = update.position_limit,
+            pnl = update.pnl,
+            win_rate,
+            signal = decision.signal,
+            kelly = decision.kelly_fraction,
+            "State update"
+        );
+
+        // Execute trade if needed
+        let sent = trade_volume != 0
+            && execute_trade(
+                &mut self.paper,
+                &self.config.player_id,
+                self.session.as_ref(),
+                update.instrument.as_deref(),
+                trade_volume,
+                outbox,
+            );
+        self.held_signal = HeldSignal {
+            decision_id,
+            driver,
+            sent,
+            signal: decision.signal,
+            momentum: update.momentum,
+            forecast: update.price_forecast,
+        };
+        if sent {
+            self.game.charge(self.config.costs.cost(trade_volume));
+            info!(
+                instrument = update.instrument.as_deref(),
+                volume = trade_volume,
+                position = update.position,
+                pnl = update.pnl,
+                signal = decision.signal,
+                kelly = decision.kelly_fraction,
+                mode = %decision.mode,
+                "{}",
+                if self.paper.is_some() {
+                    "Simulated trade"
+                } else {
+                    "Trade"
+                }
+            );
+
+            // Confirmed against the state updates to come
+            let mut performances = shared_state.connection_performance.lock().await;
+            track_sent(
+                &mut self.pending_trade,
+                &mut self.sent_trade,
+                performances.entry(conn_id).or_default(),
+                shared_state.monotonic(),
+                trade_volume,
+            );
+        }
+
+        if let Some(journal) = &self.journal {
+            journal.record(JournalEntry {
+                timestamp: shared_state.now(),
+                conn_id,
+                strategy: self.strategy.name().to_string(),
+                price: update.price,
+                forecast: update.price_forecast,
+                momentum: update.momentum,
+                combined_signal: decision.signal,
+                position_before: update.position,
+                position_after: if sent {
+                    update.position + trade_volume
+                } else {
+                    update.position
+                },
+                volume: decision.volume,
+                sent,
+                pnl: update.pnl,
+                pnl_change,
+                mode: decision.mode,
+                account: self.config.account.clone(),
+                arm: shared_state.arm_name(conn_id).await,
+                decision_id: Some(decision_id),
+                driver: Some(driver),
+            });
+        }
+    }
+
+    // Log and count what the server complained about. A trade too big for the limit
+    // is resent once, clamped; auth errors that keep coming end the session.
+    async fn handle_error(&mut self, error: ServerError, outbox: &mut Vec<ClientMessage>) -> Flow {
+        let kind = error.kind();
+        warn!(
+            conn_id = self.conn_id,
+            message = %error.message,
+            ?kind,
+            last_sent = self.last_sent.as_ref().map(ClientMessage::to_log_json),
+            "Server error"
+        );
+
+        let conn_id = self.conn_id;
+        let mut performances = self.shared_state.connection_performance.lock().await;
+        let perf = performances.entry(self.conn_id).or_default();
+        perf.server_errors += 1;
+
+        // Turned down while we thought we were trading, so we can't say where the game
+        // is until the next state update
+        let after_trade = self
+            .last_sent
+            .as_ref()
+            .is_some_and(|message| matches!(message.event, ClientEvent::Trade(_)));
+        let rejected =
+            kind == ServerErrorKind::Stage || (kind == ServerErrorKind::Other && after_trade);
+        if rejected && perf.phase.is_trading() {
+            enter_phase(perf, GamePhase::Unknown, "trade rejected");
+        }
+
+        match kind {
+            ServerErrorKind::Volume => {
+                let Some(rejected) = self.sent_trade.take() else {
+                    return Flow::Continue;
+                };
+                // Where the position stands without the rejected trade
+                let position = match self.pending_trade {
+                    Some(pending) => pending.expected_position() - rejected.volume,
+                    None => perf.session.position.unwrap_or(0),
+                };
+                let position_limit = perf
+                    .session
+                    .position_limit
+                    .unwrap_or(DEFAULT_POSITION_LIMIT);
+                let mut clamped = if rejected.resend {
+                    0
+                } else {
+                    clamp_to_limit(position, rejected.volume, position_limit)
+                };
+                if clamped == rejected.volume {
+                    clamped = 0;
+                }
+                // The resend is a trade like any other, through the same gates
+                let halted = !self.shared_state.trading_enabled() || perf.breaker.is_halted();
+                if clamped != 0 && (halted || !perf.phase.is_trading()) {
+                    debug!(volume = clamped, "Not trading just now, not resending");
+                    clamped = 0;
+                }
+                let mut exposure_book = self.shared_state.exposure_book.lock().await;
+                let exposure = ExposureRequest {
+                    position,
+                    position_limit,
+                    volume: clamped,
+                    signal: self.held_signal.signal,
+                };
+                let allowed = exposure_book.fit(&self.shared_state.exposure, conn_id, exposure);
+                if allowed != clamped {
+                    debug!(
+                        wanted = clamped,
+                        volume = allowed,
+                        "Resend shrunk to stay under the account's exposure ceiling"
+                    );
+                    clamped = allowed;
+                }
+                clamped = spend_on_trade(
+                    &self.config,
+                    perf,
+                    self.shared_state.monotonic().secs(),
+                    position,
+                    clamped,
+                    false,
+                    true,
+                );
+                let resent = clamped != 0
+                    && execute_trade(
+                        &mut self.paper,
+                        &self.config.player_id,
+                        self.session.as_ref(),
+                        self.instrument.as_ref().and_then(Option::as_deref),
+                        clamped,
+                        outbox,
+                    );
+                if resent {
+                    exposure_book.book(conn_id, exposure, allowed, clamped);
+                }
+                drop(exposure_book);
+                let resent_volume = if resent { clamped } else { 0 };
+                // The rejected trade cost nothing, the clamped one it was swapped for does
+                let refund =
+                    self.config.costs.cost(rejected.volume) - self.config.costs.cost(resent_volume);
+                self.game.charge(-refund);
+
+                // The rejected trade will never show up in the position. Once a state
+                // update has passed it has already been counted as not filled.
+                match self.pending_trade.as_mut() {
+                    Some(pending) => {
+                        perf.rejected_trades += 1;
+                        pending.revise(rejected.volume, resent_volume);
+                        if pending.trades == 0 {
+                            self.pending_trade = None;
+                        }
+                    }
+                    None if resent => {
+                        self.pending_trade = Some(PendingTrade::new(position, clamped));
+                    }
+                    None => {}
+                }
+                if resent {
+                    info!(
+                        rejected = rejected.volume,
+                        volume = clamped,
+                        position,
+                        position_limit,
+                        "Resending trade clamped to the position limit"
+                    );
+                    self.sent_trade = Some(SentTrade {
+                        volume: clamped,
+                        resend: true,
+                    });
+                }
+            }
+            // A token the server has gone off is dropped and a fresh one asked for
+            ServerErrorKind::Auth if self.session.is_some() => {
+                self.session = None;
+                warn!("Server rejected our session token, redoing the handshake");
+                return Flow::Reauthenticate;
+            }
+            ServerErrorKind::Auth => {
+                self.auth_errors += 1;
+                if self.auth_errors >= AUTH_ERRORS_BEFORE_RECONNECT {
+                    warn!(
+                        auth_errors = self.auth_errors,
+                        "Server keeps rejecting us, reconnecting"
+                    );
+                    return Flow::Reconnect;
+                }
+            }
+            ServerErrorKind::Stage | ServerErrorKind::Other => {}
+        }
+        Flow::Continue
+    }
+
+    // Handle game end
+    async fn handle_finish(&mut self, finish: FinishData) -> Flow {
+        self.restart_after = finish.next_game_after(self.shared_state.now());
+        self.end_game(finish.pnl).await;
+        Flow::Disconnect
+    }
+
+    // Close out the game: summarize it, pass the summary on, then reset everything kept
+    // for it so none of it carries into the next game
+    async fn end_game(&mut self, mut final_pnl: Option<f64>) {
+        let conn_id = self.conn_id;
+        // The server only knows about real trades, so a dry run reports its own PnL
+        if self.paper.is_some() {
+            let parked_pnl = self.parked_paper_pnl();
+            final_pnl = self.paper.as_ref().map(|paper| paper.pnl() + parked_pnl);
+        }
+        let params = self.shared_state.params_for(conn_id).await;
+        let now = self.shared_state.now();
+        #[cfg_attr(not(feature = "auto-optimize"), allow(unused_mut))]
+        let mut summary = {
+            let mut performances = self.shared_state.connection_performance.lock().await;
+            let perf = performances.entry(conn_id).or_default();
+            if perf.breaker.reset().is_some() {
+                info!("Game over, lifting drawdown halt");
+            }
+
+            let mut summary =
+                self.game
+                    .finish(conn_id, self.strategy.name(), now, final_pnl, perf, params);
+            enter_phase(perf, GamePhase::Finished, "finish");
+            summary.simulated = self.paper.is_some();
+            summary.account = self.config.account.clone();
+            summary.curve = perf.curve.take();
+            summary.trade_budget_left = perf.budget.trades_left(&self.config.trade_budget);
+            summary.puzzle_budget_left = perf.budget.puzzle_left(&self.config.trade_budget);
+            perf.end_game();
+            perf.latency.start_game();
+            self.game = GameAccumulator::start(now, perf);
+            summary
+        };
+        self.shared_state.exposure_book.lock().await.close(conn_id);
+        #[cfg(feature = "auto-optimize")]
+        if let Some(started) = self.game_started {
+            summary.param_changes = self.optimize_game(started).await;
+        }
+        if let Some(hooks) = &self.hooks {
+            hooks.finish(&summary);
+        }
+        if let Some(journal) = &self.journal {
+            journal.game(&summary);
+        }
+        if let Some(games) = &self.games {
+            let mut games = games.lock().await;
+            if games.len() >= MAX_FINISHED_GAMES {
+                games.remove(0);
+                self.shared_state.cap_hits.hit(Cap::FinishedGames);
+            }
+            games.push(summary.clone());
+        }
+        info!(
+            pnl = final_pnl,
+            trades = summary.trades,
+            win_rate = summary.win_rate,
+            "Game over\n{}",
+            summary
+        );
+        if !self.calibration.is_empty() {
+            info!("{}", format_calibration(&self.calibration));
+        }
+        if let Some(notifier) = &self.notifier {
+            notifier.notify(Alert::GameFinished {
+                conn_id,
+                strategy: summary.strategy.clone(),
+                pnl: summary.final_pnl,
+                trades: summary.trades,
+                simulated: summary.simulated,
+            });
+        }
+        self.summary = Some(summary);
+        self.clear_game();
+    }
+
+    // The optimizer's pass over the game just finished, which its last stretch was held
+    // back for
+    #[cfg(feature = "auto-optimize")]
+    async fn optimize_game(&self, started: Monotonic) -> Vec<ParamDiff> {
+        if !self.config.optimizer_enabled() {
+            return Vec::new();
+        }
+        let Some((before, after)) = optimize_after_game(&self.shared_state, started).await else {
+            return Vec::new();
+        };
+        publish_params(
+            &self.shared_state,
+            self.persist.as_ref(),
+            self.notifier.as_ref(),
+            self.journal.as_ref(),
+            &before,
+            &after,
+        )
+        .await;
+        param_changes(&before, &after)
+            .into_iter()
+            .map(|change| {
+                info!(
+                    param = change.name,
+                    before = change.before,
+                    after = change.after,
+                    "Tuned after the game"
+                );
+                ParamDiff::from(change)
+            })
+            .collect()
+    }
+
+    // Everything the handler keeps for one game: prices and forecasts, trades still to
+    // show up in the position, the held signal, warm-up and the paper book
+    fn clear_game(&mut self) {
+        self.price_history.clear();
+        self.forecasts.clear();
+        self.pending_trade = None;
+        self.held_signal = HeldSignal::default();
+        self.sent_trade = None;
+        self.warm_up_left = self.config.warm_up.updates;
+        self.duplicates = DuplicateFilter::default();
+        self.manual = Manual::Off;
+        self.mid_game = false;
+        self.game_started = None;
+        self.updates_remaining = None;
+        self.instrument = None;
+        self.parked.clear();
+        if let Some(paper) = self.paper.as_mut() {
+            paper.reset();
+        }
+        self.calibration = Calibration::default();
+    }
+
+    // Handle puzzles. Turned off, or not built in, they're only logged.
+    async fn handle_puzzle(&mut self, puzzle: PuzzleData, outbox: &mut Vec<ClientMessage>) {
+        {
+            let mut performances = self.shared_state.connection_performance.lock().await;
+            let perf = performances.entry(self.conn_id).or_default();
+            let phase = puzzle
+                .stage
+                .as_deref()
+                .map_or(GamePhase::Puzzle, stage_phase);
+            enter_phase(perf, phase, "puzzle");
+        }
+        if self.config.puzzles_enabled() {
+            #[cfg(feature = "puzzles")]
+            self.solve_or_trade_puzzle(puzzle, outbox).await;
+        } else {
+            info!(impact = ?puzzle.impact, "Puzzles are off, skipping the puzzle");
+            self.count_puzzle(false).await;
+        }
+
+        // Skip to next stage
+        outbox.push(outgoing::skip().authenticated(self.session.as_ref()));
+    }
+
+    async fn count_puzzle(&self, solved: bool) {
+        let mut performances = self.shared_state.connection_performance.lock().await;
+        let perf = performances.entry(self.conn_id).or_default();
+        if solved {
+            perf.puzzles_solved += 1;
+        } else {
+            perf.puzzles_skipped += 1;
+        }
+    }
+
+    // Answer the puzzle's question when a solver can, and otherwise trade on its impact
+    #[cfg(feature = "puzzles")]
+    async fn solve_or_trade_puzzle(&mut self, puzzle: PuzzleData, outbox: &mut Vec<ClientMessage>) {
+        match self.solvers.iter().find_map(|solver| solver.solve(&puzzle)) {
+            Some(answer) => {
+                info!(question = ?puzzle.question, %answer, "Answering the puzzle");
+                outbox.push(
+                    outgoing::answer(answer.to_value(), puzzle.id.clone())
+                        .authenticated(self.session.as_ref()),
+                );
+                self.count_puzzle(true).await;
+            }
+            None => {
+                if let Some(question) = &puzzle.question {
+                    info!(question, "No solver for the puzzle, trading on its impact");
+                }
+                self.count_puzzle(false).await;
+                self.trade_puzzle(puzzle, outbox).await;
+            }
+        }
+    }
+
+    #[cfg(feature = "puzzles")]
+    async fn trade_puzzle(&mut self, puzzle: PuzzleData, outbox: &mut Vec<ClientMessage>) {
+        let conn_id = self.conn_id;
+        let puzzle_impact = handle_puzzle_impact(&puzzle);
+
+        // Trade based on puzzle impact
+        if let Some(impact) = puzzle_impact {
+            let mut performances = self.shared_state.connection_performance.lock().await;
+            let perf = performances.entry(conn_id).or_default();
+
+            // Before the first state update we can only assume flat at the default limit.
+            // Trades still on their way count as held.
+            let position = match self.pending_trade {
+                Some(pending) => pending.expected_position(),
+                None => perf.session.position.unwrap_or(0),
+            };
+            let position_limit = perf
+                .session
+                .position_limit
+                .unwrap_or(DEFAULT_POSITION_LIMIT);
+            let volume = if perf.breaker.is_halted() {
+                0
+            } else if perf.quarantine.is_observing() {
+                info!("Observing only in quarantine, not trading the puzzle");
+                0
+            } else if !matches!(perf.phase, GamePhase::Puzzle | GamePhase::Trading) {
+                info!(phase = %perf.phase, "Not in a puzzle or trading stage, not trading the puzzle");
+                perf.held_out_of_phase += 1;
+                0
+            } else if !self.shared_state.trading_enabled() {
+                info!("Trading halted, not trading the puzzle");
+                0
+            } else if perf.session.is_resyncing() {
+                info!("Position not yet confirmed after reconnect, not trading the puzzle");
+                0
+            } else if perf.session.winding_down {
+                info!("Winding down for the end of the game, not trading the puzzle");
+                0
+            } else if self.manual != Manual::Off {
+                info!("Under manual control, not trading the puzzle");
+                0
+            } else {
+                perf.session.puzzle.on_puzzle(
+                    impact,
+                    position,
+                    position_limit,
+                    perf.session.last_price,
+                )
+            };
+            // Nothing to hold once the budget has turned the trade down
+            let budget = &self.config.trade_budget;
+            let volume = if volume != 0 && !perf.budget.allows(budget, position, volume, true) {
+                info!(
+                    volume,
+                    "Trade budget for the game spent, not trading the puzzle"
+                );
+                perf.over_budget += 1;
+                perf.session.puzzle.reset();
+                0
+            } else {
+                volume
+            };
+            // The same puzzle reaches every connection at once, and together they stay
+            // under the account's exposure ceiling. A puzzle trade claims room as the
+            // strongest signal would.
+            let mut exposure_book = self.shared_state.exposure_book.lock().await;
+            let exposure = ExposureRequest {
+                position,
+                position_limit,
+                volume,
+                signal: f64::from(volume.signum()),
+            };
+            let allowed = exposure_book.fit(&self.shared_state.exposure, conn_id, exposure);
+            if allowed != volume {
+                info!(
+                    wanted = volume,
+                    volume = allowed,
+                    "Puzzle trade shrunk to stay under the account's exposure ceiling"
+                );
+                // Nothing to hold if none of it fits
+                if allowed == 0 {
+                    perf.session.puzzle.reset();
+                }
+            }
+            let volume = allowed;
+
+            let sent = volume != 0
+                && execute_trade(
+                    &mut self.paper,
+                    &self.config.player_id,
+                    self.session.as_ref(),
+                    self.instrument.as_ref().and_then(Option::as_deref),
+                    volume,
+                    outbox,
+                );
+            if sent {
+                exposure_book.book(conn_id, exposure, allowed, volume);
+            }
+            drop(exposure_book);
+            let decision_id = perf.next_decision_id();
+            // Journaled so the analysis can tell how puzzle trades paid off
+            if let Some(journal) = &self.journal {
+                journal.record(JournalEntry {
+                    timestamp: self.shared_state.now(),
+                    conn_id,
+                    strategy: self.strategy.name().to_string(),
+                    price: perf.session.last_price.unwrap_or_default(),
+                    forecast: 0.0,
+                    momentum: 0.0,
+                    combined_signal: 0.0,
+                    position_before: position,
+                    position_after: if sent { position + volume } else { position },
+                    volume,
+                    sent,
+                    pnl: perf.last_pnl,
+                    pnl_change: None,
+                    mode: DecisionMode::Puzzle,
+                    account: self.config.account.clone(),
+                    arm: self.shared_state.arm_name(conn_id).await,
+                    decision_id: Some(decision_id),
+                    driver: Some(Driver::Puzzle),
+                });
+            }
+            if sent {
+                perf.budget.spend(&self.config.trade_budget, true);
+                self.game.charge(self.config.costs.cost(volume));
+                info!(
+                    volume,
+                    direction = impact.direction,
+                    magnitude = impact.magnitude,
+                    "{}",
+                    if self.paper.is_some() {
+                        "Simulated puzzle trade"
+                    } else {
+                        "Puzzle trade"
+                    }
+                );
+                track_sent(
+                    &mut self.pending_trade,
+                    &mut self.sent_trade,
+                    perf,
+                    self.shared_state.monotonic(),
+                    volume,
+                );
+                // The position is the puzzle's now, so what it makes is credited there
+                self.held_signal = HeldSignal {
+                    decision_id,
+                    driver: Driver::Puzzle,
+                    sent: true,
+                    ..self.held_signal
+                };
+            }
+        }
+    }
+}
+
+// Watch for a trade just sent to show up in the position, and keep it in case the
+// server rejects it. Without a known position there is nothing to check the fill
+// against, so it counts as made.
+fn track_sent(
+    pending_trade: &mut Option<PendingTrade>,
+    sent_trade: &mut Option<SentTrade>,
+    perf: &mut ConnectionPerformance,
+    now: Monotonic,
+    volume: i32,
+) {
+    perf.last_trade = Some(LastTrade {
+        timestamp: now,
+        volume,
+    });
+    *sent_trade = Some(SentTrade {
+        volume,
+        resend: false,
+    });
+    match (pending_trade.as_mut(), perf.session.position) {
+        (Some(pending), _) => pending.add(volume),
+        (None, Some(position)) => *pending_trade = Some(PendingTrade::new(position, volume)),
+        (None, None) => perf.trades_made += 1,
+    }
+}
+
+// The game's trade budget, then the rate limiter, for a trade already inside the
+// exposure ceiling. Once the budget is spent, only trades back toward flat go out.
+// Returns what may go, with both spent on it.
+fn spend_on_trade(
+    config: &Config,
+    perf: &mut ConnectionPerformance,
+    now: f64,
+    position: i32,
+    volume: i32,
+    puzzle_driven: bool,
+    rate_limited: bool,
+) -> i32 {
+    if volume == 0 {
+        return 0;
+    }
+    let budget = &config.trade_budget;
+    if !perf.budget.allows(budget, position, volume, puzzle_driven) {
+        perf.over_budget += 1;
+        info!(
+            volume,
+            puzzle = puzzle_driven,
+            "Trade budget for the game spent, not trading away from flat"
+        );
+        return 0;
+    }
+    if rate_limited && !perf.limiter.try_take(&config.rate_limit, now) {
+        perf.rate_limited += 1;
+        info!(volume, "Rate limited, not trading");
+        return 0;
+    }
+    perf.budget.spend(budget, puzzle_driven);
+    volume
+}
+
+// Queue a trade for the server, or fill it on the paper book in a dry run.
+// Returns whether the trade went anywhere.
+fn execute_trade(
+    paper: &mut Option<PaperBook>,
+    player_id: &str,
+    session: Option<&Session>,
+    instrument: Option<&str>,
+    volume: i32,
+    outbox: &mut Vec<ClientMessage>,
+) -> bool {
+    match paper {
+        Some(paper) => paper.fill(volume),
+        None => {
+            outbox.push(
+                outgoing::trade(player_id, volume)
+                    .for_instrument(instrument)
+                    .authenticated(session),
+            );
+            true
+        }
+    }
+}
+
+// A stage we can't read is treated as one we can't trade in
+fn stage_phase(stage: &str) -> GamePhase {
+    GamePhase::from_stage(stage).unwrap_or_else(|| {
+        debug!(stage, "Unrecognized game stage");
+        GamePhase::Unknown
+    })
+}
+
+fn enter_phase(perf: &mut ConnectionPerformance, phase: GamePhase, cause: &str) {
+    if perf.phase != phase {
+        info!(from = %perf.phase, to = %phase, cause, "Game phase changed");
+        perf.phase = phase;
+    }
+}
+
+fn log_reconciliation(reconciliation: &Reconciliation) {
+    info!(
+        expected_position = reconciliation.expected_position,
+        unconfirmed_volume = reconciliation.unconfirmed_volume,
+        reported_position = reconciliation.reported_position,
+        dropped_puzzle = reconciliation.dropped_puzzle,
+        dropped_stance = ?reconciliation.dropped_stance,
+        "Reconciled after reconnect: PnL baseline, pending trades, puzzle and stance reset"
+    );
+    if reconciliation.contradicts() {
+        warn!(
+            expected_position = reconciliation.expected_position,
+            reported_position = reconciliation.reported_position,
+            "Server position contradicts what we held before reconnecting"
+        );
+    }
+}
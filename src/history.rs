@@ -0,0 +1,245 @@
+use serde::{Deserialize, Serialize};
+use statrs::statistics::Statistics;
+use std::collections::VecDeque;
+
+use crate::strategy::MIN_PNL_STD_DEV;
+
+// Prices kept per connection for our own indicators
+pub const PRICE_HISTORY_SIZE: usize = 20;
+// Span of the exponential moving average, in updates
+pub const EMA_SPAN: usize = 10;
+
+// Indicators computed from our own price history rather than taken from the server.
+// Each is None until there are enough prices for it to mean anything.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Indicators {
+    // Mean per-update return over the window
+    pub momentum: Option<f64>,
+    pub ema: Option<f64>,
+    // Standard deviation of per-update returns over the window
+    pub volatility: Option<f64>,
+}
+
+// Ring buffer of the most recent prices, oldest first
+#[derive(Debug, Clone)]
+pub struct PriceHistory {
+    prices: VecDeque<f64>,
+    capacity: usize,
+}
+
+impl Default for PriceHistory {
+    fn default() -> Self {
+        PriceHistory::new(PRICE_HISTORY_SIZE)
+    }
+}
+
+impl PriceHistory {
+    pub fn new(capacity: usize) -> Self {
+        PriceHistory {
+            prices: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, price: f64) {
+        if self.prices.len() == self.capacity {
+            self.prices.pop_front();
+        }
+        self.prices.push_back(price);
+    }
+
+    pub fn clear(&mut self) {
+        self.prices.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.prices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prices.is_empty()
+    }
+
+    pub fn prices(&mut self) -> &[f64] {
+        self.prices.make_contiguous()
+    }
+
+    // Simple returns between consecutive prices, skipping any from a zero price
+    pub fn returns(&self) -> Vec<f64> {
+        self.prices
+            .iter()
+            .zip(self.prices.iter().skip(1))
+            .filter(|(previous, _)| **previous != 0.0)
+            .map(|(previous, price)| price / previous - 1.0)
+            .collect()
+    }
+
+    pub fn momentum(&self) -> Option<f64> {
+        let returns = self.returns();
+        (!returns.is_empty()).then(|| returns.mean())
+    }
+
+    // Seeded with the oldest price
+    pub fn ema(&self, span: usize) -> Option<f64> {
+        let alpha = 2.0 / (span as f64 + 1.0);
+        let mut prices = self.prices.iter();
+        let first = *prices.next()?;
+        Some(prices.fold(first, |ema, price| alpha * price + (1.0 - alpha) * ema))
+    }
+
+    // Sample standard deviation, so it needs at least two returns
+    pub fn volatility(&self) -> Option<f64> {
+        let returns = self.returns();
+        (returns.len() >= 2).then(|| returns.std_dev())
+    }
+
+    pub fn indicators(&self) -> Indicators {
+        Indicators {
+            momentum: self.momentum(),
+            ema: self.ema(EMA_SPAN),
+            volatility: self.volatility(),
+        }
+    }
+}
+
+// How much of the shared decision and performance history is kept
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    // Entries in each ring buffer, across all connections
+    pub size: usize,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig { size: 500 }
+    }
+}
+
+// Count, mean and M2 (sum of squared deviations) kept up to date one value at a time
+// with Welford's algorithm, plus how many values were positive
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct RunningStats {
+    pub count: usize,
+    pub mean: f64,
+    pub m2: f64,
+    pub wins: usize,
+}
+
+impl RunningStats {
+    pub fn add(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+        if value > 0.0 {
+            self.wins += 1;
+        }
+    }
+
+    // Undo an earlier add of the same value
+    pub fn remove(&mut self, value: f64) {
+        if self.count <= 1 {
+            *self = RunningStats::default();
+            return;
+        }
+        let mean = (self.mean * self.count as f64 - value) / (self.count - 1) as f64;
+        // Rounding can take it a hair below zero
+        self.m2 = (self.m2 - (value - self.mean) * (value - mean)).max(0.0);
+        self.mean = mean;
+        self.count -= 1;
+        if value > 0.0 {
+            self.wins -= 1;
+        }
+    }
+
+    // Sample variance, so it needs at least two values
+    pub fn variance(&self) -> Option<f64> {
+        (self.count >= 2).then(|| self.m2 / (self.count - 1) as f64)
+    }
+
+    pub fn std_dev(&self) -> Option<f64> {
+        self.variance().map(f64::sqrt)
+    }
+
+    // Mean over std dev, as sharpe_ratio gives for the same values
+    pub fn sharpe(&self) -> Option<f64> {
+        self.std_dev()
+            .filter(|std_dev| *std_dev > MIN_PNL_STD_DEV)
+            .map(|std_dev| self.mean / std_dev)
+    }
+
+    pub fn win_rate(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.wins as f64 / self.count as f64)
+    }
+}
+
+// The number a history entry is summarized by
+pub trait Sampled {
+    fn sample(&self) -> f64;
+}
+
+// Ring buffer of the most recent entries, oldest first, with running stats over
+// whatever it holds so they never need a scan
+#[derive(Debug, Clone)]
+pub struct BoundedHistory<T> {
+    entries: VecDeque<T>,
+    capacity: usize,
+    stats: RunningStats,
+}
+
+impl<T: Sampled> BoundedHistory<T> {
+    pub fn new(capacity: usize) -> Self {
+        BoundedHistory {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+            stats: RunningStats::default(),
+        }
+    }
+
+    // Drops the oldest entry once full, handing it back
+    pub fn push(&mut self, entry: T) -> Option<T> {
+        let evicted = if self.entries.len() == self.capacity {
+            self.entries.pop_front()
+        } else {
+            None
+        };
+        if let Some(oldest) = &evicted {
+            self.stats.remove(oldest.sample());
+        }
+        self.stats.add(entry.sample());
+        self.entries.push_back(entry);
+        evicted
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(&T) -> bool) {
+        let stats = &mut self.stats;
+        self.entries.retain(|entry| {
+            let kept = keep(entry);
+            if !kept {
+                stats.remove(entry.sample());
+            }
+            kept
+        });
+    }
+
+    pub fn stats(&self) -> &RunningStats {
+        &self.stats
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        self.entries.back()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> {
+        self.entries.iter()
+    }
+}
@@ -0,0 +1,221 @@
+use async_channel::{Receiver, Sender, TrySendError};
+use futures::future::BoxFuture;
+use futures::io::AsyncWriteExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+use crate::caps::{Cap, CapHits};
+use crate::error::BotError;
+use crate::protocol::StateUpdate;
+use crate::rt::{self, fs};
+use crate::state::SignalData;
+use crate::summary::GameSummary;
+
+// Events waiting on slow hooks past this are dropped rather than queued
+pub const HOOK_QUEUE: usize = 256;
+
+// Hooks that ship with the bot, switched on from the config
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct HooksConfig {
+    // Every hook event is appended here as a JSON line; off when unset
+    pub log_path: Option<PathBuf>,
+}
+
+// Callbacks at the points a connection reaches. Every method does nothing unless
+// overridden. They run on the hook task, never on the trade path, so a slow one only
+// costs events it falls behind on.
+pub trait Hooks: Send {
+    // A state update that passed validation, as the server sent it
+    fn on_state<'a>(&'a mut self, _conn_id: usize, _update: &'a StateUpdate) -> BoxFuture<'a, ()> {
+        Box::pin(async {})
+    }
+
+    // What the strategy decided about a state update, traded or not
+    fn on_decision<'a>(&'a mut self, _signal: &'a SignalData) -> BoxFuture<'a, ()> {
+        Box::pin(async {})
+    }
+
+    // A trade queued to go out, puzzle trades included
+    fn on_trade_sent(&mut self, _conn_id: usize, _volume: i32) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+
+    fn on_finish<'a>(&'a mut self, _summary: &'a GameSummary) -> BoxFuture<'a, ()> {
+        Box::pin(async {})
+    }
+
+    // A session that ended in an error
+    fn on_error<'a>(&'a mut self, _conn_id: usize, _error: &'a BotError) -> BoxFuture<'a, ()> {
+        Box::pin(async {})
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopHooks;
+
+impl Hooks for NoopHooks {}
+
+// Appends every event to a file as a JSON line
+pub struct FileLogger {
+    path: PathBuf,
+    file: fs::File,
+}
+
+impl FileLogger {
+    pub async fn open(path: &Path) -> io::Result<FileLogger> {
+        let file = fs::append(path).await?;
+        Ok(FileLogger {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    async fn write(&mut self, line: Value) {
+        let mut line = line.to_string();
+        line.push('\n');
+        let written = match self.file.write_all(line.as_bytes()).await {
+            Ok(()) => self.file.flush().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            warn!(path = %self.path.display(), error = %e, "Error writing hook log");
+        }
+    }
+}
+
+impl Hooks for FileLogger {
+    fn on_state<'a>(&'a mut self, conn_id: usize, update: &'a StateUpdate) -> BoxFuture<'a, ()> {
+        let line = json!({ "hook": "state", "conn_id": conn_id, "update": update });
+        Box::pin(self.write(line))
+    }
+
+    fn on_decision<'a>(&'a mut self, signal: &'a SignalData) -> BoxFuture<'a, ()> {
+        Box::pin(self.write(json!({ "hook": "decision", "signal": signal })))
+    }
+
+    fn on_trade_sent(&mut self, conn_id: usize, volume: i32) -> BoxFuture<'_, ()> {
+        let line = json!({ "hook": "trade_sent", "conn_id": conn_id, "volume": volume });
+        Box::pin(self.write(line))
+    }
+
+    fn on_finish<'a>(&'a mut self, summary: &'a GameSummary) -> BoxFuture<'a, ()> {
+        Box::pin(self.write(json!({ "hook": "finish", "summary": summary })))
+    }
+
+    fn on_error<'a>(&'a mut self, conn_id: usize, error: &'a BotError) -> BoxFuture<'a, ()> {
+        let line = json!({ "hook": "error", "conn_id": conn_id, "error": error.to_string() });
+        Box::pin(self.write(line))
+    }
+}
+
+#[derive(Debug)]
+pub enum HookEvent {
+    State { conn_id: usize, update: StateUpdate },
+    Decision(SignalData),
+    TradeSent { conn_id: usize, volume: i32 },
+    Finish(Box<GameSummary>),
+    Error { conn_id: usize, error: BotError },
+}
+
+impl HookEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            HookEvent::State { .. } => "state",
+            HookEvent::Decision(_) => "decision",
+            HookEvent::TradeSent { .. } => "trade_sent",
+            HookEvent::Finish(_) => "finish",
+            HookEvent::Error { .. } => "error",
+        }
+    }
+
+    async fn deliver(&self, hook: &mut dyn Hooks) {
+        match self {
+            HookEvent::State { conn_id, update } => hook.on_state(*conn_id, update).await,
+            HookEvent::Decision(signal) => hook.on_decision(signal).await,
+            HookEvent::TradeSent { conn_id, volume } => hook.on_trade_sent(*conn_id, *volume).await,
+            HookEvent::Finish(summary) => hook.on_finish(summary).await,
+            HookEvent::Error { conn_id, error } => hook.on_error(*conn_id, error).await,
+        }
+    }
+}
+
+// Handle for passing events to the hook task. Cheap to clone; the task finishes
+// once every handle has been dropped.
+#[derive(Clone)]
+pub struct HookRunner {
+    sender: Sender<HookEvent>,
+    cap_hits: Arc<CapHits>,
+}
+
+impl HookRunner {
+    // Start the hook task, if there are any hooks to run
+    pub fn spawn(hooks: Vec<Box<dyn Hooks>>) -> Option<(HookRunner, rt::JoinHandle<()>)> {
+        if hooks.is_empty() {
+            return None;
+        }
+        let (sender, receiver) = async_channel::bounded(HOOK_QUEUE);
+        let handle = rt::spawn(run_hooks(receiver, hooks));
+        let runner = HookRunner {
+            sender,
+            cap_hits: Arc::default(),
+        };
+        Some((runner, handle))
+    }
+
+    // Count events dropped on a full queue there
+    pub fn with_cap_hits(mut self, cap_hits: Arc<CapHits>) -> HookRunner {
+        self.cap_hits = cap_hits;
+        self
+    }
+
+    pub fn state(&self, conn_id: usize, update: &StateUpdate) {
+        self.send(HookEvent::State {
+            conn_id,
+            update: update.clone(),
+        });
+    }
+
+    pub fn decision(&self, signal: SignalData) {
+        self.send(HookEvent::Decision(signal));
+    }
+
+    pub fn trade_sent(&self, conn_id: usize, volume: i32) {
+        self.send(HookEvent::TradeSent { conn_id, volume });
+    }
+
+    pub fn finish(&self, summary: &GameSummary) {
+        self.send(HookEvent::Finish(Box::new(summary.clone())));
+    }
+
+    pub fn error(&self, conn_id: usize, error: BotError) {
+        self.send(HookEvent::Error { conn_id, error });
+    }
+
+    // Never waits on the hooks; dropped if the queue is full or the task has stopped
+    fn send(&self, event: HookEvent) {
+        match self.sender.try_send(event) {
+            Ok(()) => {}
+            Err(TrySendError::Full(event)) => {
+                self.cap_hits.hit(Cap::Hooks);
+                debug!(event = event.name(), "Hook queue full, dropping event");
+            }
+            Err(TrySendError::Closed(event)) => {
+                debug!(event = event.name(), "Hook task stopped, dropping event")
+            }
+        }
+    }
+}
+
+// Each event goes to every hook in turn, in the order they were registered
+async fn run_hooks(receiver: Receiver<HookEvent>, mut hooks: Vec<Box<dyn Hooks>>) {
+    while let Ok(event) = receiver.recv().await {
+        for hook in hooks.iter_mut() {
+            event.deliver(hook.as_mut()).await;
+        }
+    }
+}
@@ -0,0 +1,625 @@
+use async_channel::{Receiver, Sender, TrySendError};
+use futures::io::{AsyncWriteExt, BufWriter};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, warn};
+
+use crate::caps::{Cap, CapHits};
+use crate::notify::param_changes;
+use crate::rt::{self, fs};
+use crate::state::StrategyParams;
+#[cfg(feature = "sqlite")]
+use crate::store::SqliteStore;
+use crate::strategy::{DecisionMode, Driver};
+use crate::summary::GameSummary;
+
+// Records waiting on the writer before new ones are dropped and counted
+pub const JOURNAL_QUEUE: usize = 10_000;
+
+// Where every decision point is written for post-mortems
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct JournalConfig {
+    pub enabled: bool,
+    pub path: PathBuf,
+    pub format: JournalFormat,
+    // Buffered entries, or a database's batch, are written out at least this often
+    pub flush_secs: f64,
+}
+
+impl Default for JournalConfig {
+    fn default() -> Self {
+        JournalConfig {
+            enabled: true,
+            path: PathBuf::from("journal.jsonl"),
+            format: JournalFormat::Jsonl,
+            flush_secs: 2.0,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalFormat {
+    #[default]
+    Jsonl,
+    Csv,
+    // Also keeps game summaries, param changes and connection events. Needs the sqlite
+    // feature; without it, or with a file that won't open, the journal goes to JSONL.
+    Sqlite,
+}
+
+impl JournalFormat {
+    // Guess from the extension when reading a journal back
+    pub fn for_path(path: &Path) -> JournalFormat {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("csv") => JournalFormat::Csv,
+            Some("db" | "sqlite" | "sqlite3") => JournalFormat::Sqlite,
+            _ => JournalFormat::Jsonl,
+        }
+    }
+}
+
+// Where a database journal goes instead when it can't be used
+pub fn fallback_path(path: &Path) -> PathBuf {
+    path.with_extension("jsonl")
+}
+
+// One state update and what we did about it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    pub timestamp: f64,
+    pub conn_id: usize,
+    pub strategy: String,
+    pub price: f64,
+    pub forecast: f64,
+    pub momentum: f64,
+    pub combined_signal: f64,
+    pub position_before: i32,
+    // Position we'd be at if the trade sent (if any) fills
+    pub position_after: i32,
+    // What the strategy asked for, before puzzle and risk overrides
+    pub volume: i32,
+    pub sent: bool,
+    pub pnl: f64,
+    // None on the first update of a session
+    pub pnl_change: Option<f64>,
+    // Missing from journals written before fading was added
+    #[serde(default)]
+    pub mode: DecisionMode,
+    // Which of the process's accounts decided; empty in older journals
+    #[serde(default)]
+    pub account: String,
+    // Bandit arm the connection was trading, so results can be put down to it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arm: Option<String>,
+    // The connection's number for the decision and what drove it; None for manual
+    // trades and in older journals
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decision_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub driver: Option<Driver>,
+}
+
+const CSV_HEADER: &str = "timestamp,conn_id,strategy,price,forecast,momentum,combined_signal,position_before,position_after,volume,sent,pnl,pnl_change,mode,account,arm,decision_id,driver";
+
+impl JournalEntry {
+    pub fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.timestamp,
+            self.conn_id,
+            self.strategy,
+            self.price,
+            self.forecast,
+            self.momentum,
+            self.combined_signal,
+            self.position_before,
+            self.position_after,
+            self.volume,
+            self.sent,
+            self.pnl,
+            self.pnl_change
+                .map_or(String::new(), |change| change.to_string()),
+            self.mode,
+            self.account,
+            self.arm.as_deref().unwrap_or_default(),
+            self.decision_id.map_or(String::new(), |id| id.to_string()),
+            self.driver
+                .map_or(String::new(), |driver| driver.to_string())
+        )
+    }
+
+    pub fn from_csv_row(row: &str) -> Result<JournalEntry, String> {
+        let fields: Vec<&str> = row.split(',').collect();
+        // The mode, account, arm, decision_id and driver columns came later
+        if !(13..=18).contains(&fields.len()) {
+            return Err(format!("expected 13 to 18 columns, got {}", fields.len()));
+        }
+        fn parse<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+            value
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid {} '{}'", name, value))
+        }
+        Ok(JournalEntry {
+            timestamp: parse("timestamp", fields[0])?,
+            conn_id: parse("conn_id", fields[1])?,
+            strategy: fields[2].to_string(),
+            price: parse("price", fields[3])?,
+            forecast: parse("forecast", fields[4])?,
+            momentum: parse("momentum", fields[5])?,
+            combined_signal: parse("combined_signal", fields[6])?,
+            position_before: parse("position_before", fields[7])?,
+            position_after: parse("position_after", fields[8])?,
+            volume: parse("volume", fields[9])?,
+            sent: parse("sent", fields[10])?,
+            pnl: parse("pnl", fields[11])?,
+            pnl_change: match fields[12].trim() {
+                "" => None,
+                change => Some(parse("pnl_change", change)?),
+            },
+            mode: match fields.get(13) {
+                Some(mode) => parse("mode", mode)?,
+                None => DecisionMode::Follow,
+            },
+            account: fields
+                .get(14)
+                .map_or(String::new(), |account| account.to_string()),
+            arm: fields
+                .get(15)
+                .map(|arm| arm.trim())
+                .filter(|arm| !arm.is_empty())
+                .map(str::to_string),
+            decision_id: match fields.get(16).map(|id| id.trim()) {
+                None | Some("") => None,
+                Some(id) => Some(parse("decision_id", id)?),
+            },
+            driver: match fields.get(17).map(|driver| driver.trim()) {
+                None | Some("") => None,
+                Some(driver) => Some(parse("driver", driver)?),
+            },
+        })
+    }
+
+    fn to_line(&self, format: JournalFormat) -> String {
+        let mut line = match format {
+            JournalFormat::Csv => self.to_csv_row(),
+            _ => serde_json::to_string(self).expect("journal entries always serialize"),
+        };
+        line.push('\n');
+        line
+    }
+}
+
+// A tuned param the optimizer moved
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ParamChangeEntry {
+    pub timestamp: f64,
+    pub account: String,
+    pub param: String,
+    pub before: f64,
+    pub after: f64,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionEventKind {
+    Connected,
+    Disconnected,
+    Error,
+}
+
+impl fmt::Display for ConnectionEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ConnectionEventKind::Connected => "connected",
+            ConnectionEventKind::Disconnected => "disconnected",
+            ConnectionEventKind::Error => "error",
+        })
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ConnectionEvent {
+    pub timestamp: f64,
+    pub account: String,
+    pub conn_id: usize,
+    pub kind: ConnectionEventKind,
+    // The url connected to, why the session ended, or the error
+    pub detail: Option<String>,
+}
+
+// Everything the writer task is sent. The file formats only keep decisions; summaries
+// already have their own file and the rest is in the log.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Record {
+    Decision(JournalEntry),
+    Game(Box<GameSummary>),
+    ParamChange(ParamChangeEntry),
+    Connection(ConnectionEvent),
+}
+
+// Handle for sending records to the journal writer task. Cheap to clone; the writer
+// finishes once every handle has been dropped.
+#[derive(Clone)]
+pub struct Journal {
+    sender: Sender<Record>,
+    // Stamped on param changes, which don't otherwise say whose they were
+    account: String,
+    cap_hits: Arc<CapHits>,
+}
+
+impl Journal {
+    // Open the journal file or database and start the writer task
+    pub async fn spawn(config: &JournalConfig) -> io::Result<(Journal, rt::JoinHandle<()>)> {
+        let sink = match config.format {
+            JournalFormat::Sqlite => Sink::database(&config.path).await?,
+            format => Sink::file(&config.path, format).await?,
+        };
+        let (sender, receiver) = async_channel::bounded(JOURNAL_QUEUE);
+        let handle = rt::spawn(write_records(
+            sink,
+            receiver,
+            Duration::from_secs_f64(config.flush_secs),
+        ));
+        let journal = Journal {
+            sender,
+            account: String::new(),
+            cap_hits: Arc::default(),
+        };
+        Ok((journal, handle))
+    }
+
+    // Count records dropped on a full queue there
+    pub fn with_cap_hits(mut self, cap_hits: Arc<CapHits>) -> Journal {
+        self.cap_hits = cap_hits;
+        self
+    }
+
+    // The same journal, for one account's optimizer
+    pub fn for_account(mut self, account: &str) -> Journal {
+        self.account = account.to_string();
+        self
+    }
+
+    // Never waits on the file; records are dropped if the writer is too far behind or
+    // has already stopped
+    pub fn record(&self, entry: JournalEntry) {
+        self.send(Record::Decision(entry));
+    }
+
+    pub fn game(&self, summary: &GameSummary) {
+        self.send(Record::Game(Box::new(summary.clone())));
+    }
+
+    // One record per tuned param that moved
+    pub fn params_changed(&self, timestamp: f64, before: &StrategyParams, after: &StrategyParams) {
+        for change in param_changes(before, after) {
+            self.send(Record::ParamChange(ParamChangeEntry {
+                timestamp,
+                account: self.account.clone(),
+                param: change.name.to_string(),
+                before: change.before,
+                after: change.after,
+            }));
+        }
+    }
+
+    pub fn connection(&self, event: ConnectionEvent) {
+        self.send(Record::Connection(event));
+    }
+
+    fn send(&self, record: Record) {
+        if let Err(TrySendError::Full(_)) = self.sender.try_send(record) {
+            self.cap_hits.hit(Cap::Journal);
+        }
+    }
+}
+
+// Where the writer task puts records
+enum Sink {
+    File {
+        writer: BufWriter<fs::File>,
+        path: PathBuf,
+        format: JournalFormat,
+    },
+    // Batched, and written a transaction at a time on each flush
+    #[cfg(feature = "sqlite")]
+    Database {
+        store: SqliteStore,
+        path: PathBuf,
+        batch: Vec<Record>,
+    },
+}
+
+impl Sink {
+    async fn file(path: &Path, format: JournalFormat) -> io::Result<Sink> {
+        let mut writer = BufWriter::new(fs::append(path).await?);
+        let is_new = fs::metadata(path).await?.len() == 0;
+        if format == JournalFormat::Csv && is_new {
+            writer.write_all(CSV_HEADER.as_bytes()).await?;
+            writer.write_all(b"\n").await?;
+        }
+        Ok(Sink::File {
+            writer,
+            path: path.to_path_buf(),
+            format,
+        })
+    }
+
+    // A database that won't open doesn't stop the bot, it journals to JSONL instead
+    #[cfg(feature = "sqlite")]
+    async fn database(path: &Path) -> io::Result<Sink> {
+        let opened = {
+            let path = path.to_path_buf();
+            rt::spawn_blocking(move || SqliteStore::open(&path)).await
+        };
+        match opened {
+            Ok(store) => Ok(Sink::Database {
+                store,
+                path: path.to_path_buf(),
+                batch: Vec::new(),
+            }),
+            Err(e) => {
+                let fallback = fallback_path(path);
+                warn!(
+                    path = %path.display(),
+                    fallback = %fallback.display(),
+                    error = %e,
+                    "Can't open the journal database, journaling to JSONL instead"
+                );
+                Sink::file(&fallback, JournalFormat::Jsonl).await
+            }
+        }
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    async fn database(path: &Path) -> io::Result<Sink> {
+        let fallback = fallback_path(path);
+        warn!(
+            path = %path.display(),
+            fallback = %fallback.display(),
+            "Built without the sqlite feature, journaling to JSONL instead"
+        );
+        Sink::file(&fallback, JournalFormat::Jsonl).await
+    }
+
+    async fn write(&mut self, record: Record) {
+        match self {
+            Sink::File {
+                writer,
+                path,
+                format,
+            } => {
+                let Record::Decision(entry) = record else {
+                    return;
+                };
+                if let Err(e) = writer.write_all(entry.to_line(*format).as_bytes()).await {
+                    error!(path = %path.display(), error = %e, "Error writing journal");
+                }
+            }
+            #[cfg(feature = "sqlite")]
+            Sink::Database { batch, .. } => batch.push(record),
+        }
+    }
+
+    // Taken and handed back, since a database that starts failing is swapped for a file
+    async fn flush(self) -> Sink {
+        match self {
+            Sink::File {
+                mut writer,
+                path,
+                format,
+            } => {
+                if let Err(e) = writer.flush().await {
+                    error!(path = %path.display(), error = %e, "Error flushing journal");
+                }
+                Sink::File {
+                    writer,
+                    path,
+                    format,
+                }
+            }
+            #[cfg(feature = "sqlite")]
+            Sink::Database { store, path, batch } if batch.is_empty() => {
+                Sink::Database { store, path, batch }
+            }
+            #[cfg(feature = "sqlite")]
+            Sink::Database {
+                mut store,
+                path,
+                batch,
+            } => {
+                let (store, batch, written) = rt::spawn_blocking(move || {
+                    let written = store.write(&batch);
+                    (store, batch, written)
+                })
+                .await;
+                let e = match written {
+                    Ok(()) => {
+                        return Sink::Database {
+                            store,
+                            path,
+                            batch: Vec::new(),
+                        }
+                    }
+                    Err(e) => e,
+                };
+                let fallback = fallback_path(&path);
+                warn!(
+                    path = %path.display(),
+                    fallback = %fallback.display(),
+                    error = %e,
+                    "Error writing the journal database, journaling to JSONL instead"
+                );
+                match Sink::file(&fallback, JournalFormat::Jsonl).await {
+                    Ok(mut sink) => {
+                        for record in batch {
+                            sink.write(record).await;
+                        }
+                        Box::pin(sink.flush()).await
+                    }
+                    Err(e) => {
+                        error!(
+                            path = %fallback.display(),
+                            error = %e,
+                            dropped = batch.len(),
+                            "Can't open the fallback journal either, dropping records"
+                        );
+                        Sink::Database {
+                            store,
+                            path,
+                            batch: Vec::new(),
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn write_records(mut sink: Sink, receiver: Receiver<Record>, flush_interval: Duration) {
+    let mut last_flush = Instant::now();
+    loop {
+        let next = rt::timeout(flush_interval, receiver.recv()).await;
+        match next {
+            Ok(Ok(record)) => sink.write(record).await,
+            // Every handle is gone, so this is the last chance to flush
+            Ok(Err(_)) => break,
+            Err(_) => {}
+        }
+
+        if last_flush.elapsed() >= flush_interval {
+            sink = sink.flush().await;
+            last_flush = Instant::now();
+        }
+    }
+    sink.flush().await;
+}
+
+// Each non-blank line of a journal with its line number, parsed by the format its
+// extension says it's in
+fn parse_lines(path: &Path, text: &str) -> Vec<(usize, Result<JournalEntry, String>)> {
+    let format = JournalFormat::for_path(path);
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter(|(_, line)| !(format == JournalFormat::Csv && *line == CSV_HEADER))
+        .map(|(number, line)| {
+            let entry = match format {
+                JournalFormat::Csv => JournalEntry::from_csv_row(line),
+                _ => serde_json::from_str(line).map_err(|e| e.to_string()),
+            };
+            (number + 1, entry)
+        })
+        .collect()
+}
+
+// A database journal's decisions, with the rows that couldn't be read counted
+#[cfg(feature = "sqlite")]
+fn load_database(path: &Path) -> io::Result<LoadedJournal> {
+    let (entries, malformed) = crate::store::read_decisions(path)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(LoadedJournal { entries, malformed })
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn load_database(path: &Path) -> io::Result<LoadedJournal> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "{} is a SQLite journal, which needs the sqlite feature",
+            path.display()
+        ),
+    ))
+}
+
+// Load a journal written in any format, failing on the first bad line
+pub fn read_journal(path: &Path) -> io::Result<Vec<JournalEntry>> {
+    if JournalFormat::for_path(path) == JournalFormat::Sqlite {
+        let loaded = load_database(path)?;
+        if loaded.malformed > 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} decisions can't be read", loaded.malformed),
+            ));
+        }
+        return Ok(loaded.entries);
+    }
+    let text = std::fs::read_to_string(path)?;
+    parse_lines(path, &text)
+        .into_iter()
+        .map(|(number, entry)| {
+            entry.map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {}", number, e),
+                )
+            })
+        })
+        .collect()
+}
+
+// A journal loaded for analysis, where a bad line shouldn't lose the rest
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LoadedJournal {
+    pub entries: Vec<JournalEntry>,
+    pub malformed: usize,
+}
+
+// Like read_journal, but lines that don't parse are logged, counted and skipped
+pub fn load_journal(path: &Path) -> io::Result<LoadedJournal> {
+    if JournalFormat::for_path(path) == JournalFormat::Sqlite {
+        return load_database(path);
+    }
+    let text = std::fs::read_to_string(path)?;
+    let mut loaded = LoadedJournal::default();
+    for (number, entry) in parse_lines(path, &text) {
+        match entry {
+            Ok(entry) => loaded.entries.push(entry),
+            Err(e) => {
+                debug!(path = %path.display(), line = number, error = %e, "Skipping malformed journal line");
+                loaded.malformed += 1;
+            }
+        }
+    }
+    Ok(loaded)
+}
+
+// Per-connection totals over a journal
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct JournalStats {
+    pub decisions: usize,
+    pub trades_sent: usize,
+    pub volume_sent: i32,
+    pub total_pnl_change: f64,
+    pub last_pnl: Option<f64>,
+    // Mean absolute signal, a rough measure of how convinced the strategy was
+    pub mean_abs_signal: f64,
+}
+
+pub fn analyze(entries: &[JournalEntry]) -> BTreeMap<(String, usize), JournalStats> {
+    let mut stats: BTreeMap<(String, usize), JournalStats> = BTreeMap::new();
+    for entry in entries {
+        let conn = stats
+            .entry((entry.account.clone(), entry.conn_id))
+            .or_default();
+        conn.decisions += 1;
+        if entry.sent {
+            conn.trades_sent += 1;
+            conn.volume_sent += (entry.position_after - entry.position_before).abs();
+        }
+        conn.total_pnl_change += entry.pnl_change.unwrap_or(0.0);
+        conn.last_pnl = Some(entry.pnl);
+        // Running mean so there's no second pass
+        conn.mean_abs_signal +=
+            (entry.combined_signal.abs() - conn.mean_abs_signal) / conn.decisions as f64;
+    }
+    stats
+}
@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+// Latest decisions kept per connection for the snapshot
+pub const LATENCY_WINDOW: usize = 500;
+// Decisions kept for a game's summary; a longer game is summed up over its latest
+pub const MAX_GAME_LATENCIES: usize = 10_000;
+
+// Time from a frame arriving to the trade it led to going out on the socket
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LatencyConfig {
+    // Slower decisions are logged as warnings
+    pub budget_ms: f64,
+}
+
+impl Default for LatencyConfig {
+    fn default() -> Self {
+        LatencyConfig { budget_ms: 20.0 }
+    }
+}
+
+impl LatencyConfig {
+    pub fn budget(&self) -> Duration {
+        Duration::from_secs_f64(self.budget_ms / 1000.0)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct LatencySummary {
+    pub samples: usize,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub max_ms: f64,
+}
+
+impl LatencySummary {
+    // Nearest-rank percentiles, None with nothing to go on
+    pub fn of(samples: impl IntoIterator<Item = f64>) -> Option<Self> {
+        let mut sorted: Vec<f64> = samples.into_iter().collect();
+        if sorted.is_empty() {
+            return None;
+        }
+        sorted.sort_by(f64::total_cmp);
+        let rank = |p: f64| sorted[((p * sorted.len() as f64).ceil() as usize).max(1) - 1];
+        Some(LatencySummary {
+            samples: sorted.len(),
+            p50_ms: rank(0.5),
+            p95_ms: rank(0.95),
+            max_ms: sorted[sorted.len() - 1],
+        })
+    }
+}
+
+// A connection's decision latencies in milliseconds: a rolling window, and everything
+// since the current game started
+#[derive(Debug, Clone, Default)]
+pub struct DecisionLatency {
+    recent: VecDeque<f64>,
+    game: VecDeque<f64>,
+}
+
+impl DecisionLatency {
+    // Returns whether the game's oldest latency was dropped to make room
+    pub fn record(&mut self, latency: Duration) -> bool {
+        let ms = latency.as_secs_f64() * 1000.0;
+        if self.recent.len() == LATENCY_WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(ms);
+        let full = self.game.len() == MAX_GAME_LATENCIES;
+        if full {
+            self.game.pop_front();
+        }
+        self.game.push_back(ms);
+        full
+    }
+
+    pub fn start_game(&mut self) {
+        self.game.clear();
+    }
+
+    pub fn recent(&self) -> Option<LatencySummary> {
+        LatencySummary::of(self.recent.iter().copied())
+    }
+
+    pub fn game(&self) -> Option<LatencySummary> {
+        LatencySummary::of(self.game.iter().copied())
+    }
+}
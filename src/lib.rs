@@ -0,0 +1,51 @@
+pub mod analysis;
+pub mod backtest;
+pub mod bandit;
+pub mod bot;
+pub mod calibration;
+pub mod caps;
+pub mod clock;
+pub mod config;
+pub mod connection;
+pub mod console;
+pub mod control;
+pub mod curve;
+pub mod divergence;
+pub mod error;
+pub mod eval;
+#[cfg(feature = "auto-optimize")]
+pub mod explore;
+pub mod forecast;
+pub mod handler;
+pub mod history;
+pub mod hooks;
+pub mod journal;
+pub mod latency;
+pub mod lifecycle;
+pub mod notify;
+#[cfg(feature = "auto-optimize")]
+pub mod optimizer;
+pub mod paper;
+pub mod persist;
+pub mod protocol;
+#[cfg(feature = "puzzles")]
+pub mod puzzle;
+pub mod reload;
+pub mod replay;
+pub mod risk;
+pub mod risk_eval;
+pub mod rt;
+pub mod schedule;
+pub mod shutdown;
+#[cfg(feature = "puzzles")]
+pub mod solver;
+pub mod state;
+#[cfg(feature = "sqlite")]
+pub mod store;
+pub mod strategy;
+pub mod summary;
+pub mod supervisor;
+pub mod transcript;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod tune;
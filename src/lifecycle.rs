@@ -0,0 +1,159 @@
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use crate::clock::Monotonic;
+use crate::protocol::{ClientEvent, ConnectionData, StartData, Token};
+
+// How long each step of the handshake may take before the session is started over
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct HandshakeConfig {
+    // From sending the connection message to the server accepting it
+    pub ack_timeout_secs: f64,
+    // From sending start to the game's first state update
+    pub start_timeout_secs: f64,
+}
+
+impl Default for HandshakeConfig {
+    fn default() -> Self {
+        HandshakeConfig {
+            ack_timeout_secs: 10.0,
+            start_timeout_secs: 30.0,
+        }
+    }
+}
+
+// Where a connection's session is, from the socket opening to the game's finish
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Lifecycle {
+    // No session yet, or one that stalled and has to be started over
+    #[default]
+    Connecting,
+    // The connection message is out, waiting for the server to accept it
+    AwaitingAck {
+        since: Monotonic,
+    },
+    // Start is out, waiting for the game's first state update
+    Starting {
+        since: Monotonic,
+    },
+    InGame,
+    Finished,
+}
+
+impl Lifecycle {
+    // Still in the handshake, where a stall sends the session back to Connecting
+    pub fn is_handshake(self) -> bool {
+        matches!(
+            self,
+            Lifecycle::AwaitingAck { .. } | Lifecycle::Starting { .. }
+        )
+    }
+}
+
+// What the lifecycle is told about
+#[derive(Debug, Clone, PartialEq)]
+pub enum LifecycleEvent<'a> {
+    // The socket is open. Carries the token to hand back, empty on a first session.
+    Opened { token: Token, now: Monotonic },
+    // The server accepted a connection message for this player
+    Ack { player_id: &'a str, now: Monotonic },
+    // A state update or puzzle, which only belong to a game under way
+    Game,
+    Finish,
+    // Time passing, for the handshake's timeouts
+    Tick { now: Monotonic },
+}
+
+// One connection's lifecycle. Pure, so every transition can be tested without a
+// socket: it takes what happened and says what state follows and what to send.
+#[derive(Debug, Clone)]
+pub struct SessionLifecycle {
+    alias: String,
+    player_id: String,
+    config: HandshakeConfig,
+    state: Lifecycle,
+}
+
+impl SessionLifecycle {
+    pub fn new(alias: &str, player_id: &str, config: HandshakeConfig) -> Self {
+        SessionLifecycle {
+            alias: alias.to_string(),
+            player_id: player_id.to_string(),
+            config,
+            state: Lifecycle::Connecting,
+        }
+    }
+
+    pub fn state(&self) -> Lifecycle {
+        self.state
+    }
+
+    // When the handshake step under way times out
+    pub fn deadline(&self) -> Option<Monotonic> {
+        match self.state {
+            Lifecycle::AwaitingAck { since } => Some(since + self.config.ack_timeout_secs),
+            Lifecycle::Starting { since } => Some(since + self.config.start_timeout_secs),
+            _ => None,
+        }
+    }
+
+    // Move on from what happened. Anything out of order is logged and leaves the
+    // state alone, so a state update before the ack is never traded on.
+    pub fn handle(&mut self, event: LifecycleEvent) -> (Lifecycle, Vec<ClientEvent>) {
+        let mut outgoing = Vec::new();
+        self.state = match (self.state, event) {
+            // A new socket starts from the top, wherever the last one left off
+            (_, LifecycleEvent::Opened { token, now }) => {
+                outgoing.push(ClientEvent::Connection(ConnectionData {
+                    alias: self.alias.clone(),
+                    player_id: self.player_id.clone(),
+                    token,
+                }));
+                Lifecycle::AwaitingAck { since: now }
+            }
+
+            (Lifecycle::AwaitingAck { .. }, LifecycleEvent::Ack { player_id, now })
+                if player_id == self.player_id =>
+            {
+                outgoing.push(ClientEvent::Start(StartData {
+                    player_id: self.player_id.clone(),
+                }));
+                Lifecycle::Starting { since: now }
+            }
+            (state, LifecycleEvent::Ack { player_id, .. }) if player_id != self.player_id => {
+                debug!(player_id, "Ignoring an ack for another player");
+                state
+            }
+            (state, LifecycleEvent::Ack { .. }) => {
+                warn!(?state, "Ignoring a connection ack out of turn");
+                state
+            }
+
+            (Lifecycle::Starting { .. } | Lifecycle::InGame, LifecycleEvent::Game) => {
+                Lifecycle::InGame
+            }
+            (state, LifecycleEvent::Game) => {
+                warn!(?state, "Ignoring game traffic outside a game");
+                state
+            }
+
+            (Lifecycle::Starting { .. } | Lifecycle::InGame, LifecycleEvent::Finish) => {
+                Lifecycle::Finished
+            }
+            (state, LifecycleEvent::Finish) => {
+                warn!(?state, "Ignoring a finish outside a game");
+                state
+            }
+
+            (state, LifecycleEvent::Tick { now }) => match self.deadline() {
+                Some(deadline) if now >= deadline => {
+                    warn!(?state, "Handshake timed out, starting the session over");
+                    Lifecycle::Connecting
+                }
+                _ => state,
+            },
+        };
+        (self.state, outgoing)
+    }
+}
@@ -1,549 +1,500 @@
-use async_std::sync::{Arc, Mutex, RwLock};
-use futures::stream::StreamExt;
-use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
-use std::collections::VecDeque;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use async_std::task;
-use async_tungstenite::{async_std::connect_async, tungstenite::Message};
-use futures::SinkExt;
-use rand::Rng;
-use statrs::statistics::Statistics;
-use std::f64;
-
-// Constants
-const URL: &str = "wss://vega-apac.optibook.net/ws/e65ed16e-1042-4aac-8327-e6f972d120d5";
-const PLAYER_ID: &str = "50cc97f7-e061-519e-862d-25c882cab50b";
-const NUM_CONNECTIONS: usize = 5;
-const HISTORY_SIZE: usize = 20;
-
-// Message structures
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct ConnectionMessage {
-    event: String,
-    player_id: String,
-    data: ConnectionData,
-}
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::{info, warn};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::EnvFilter;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct ConnectionData {
-    alias: String,
-    player_id: String,
-    token: String,
-}
+use optiva_ws::analysis::{analyze_journal, format_report};
+use optiva_ws::backtest::{format_reports, run_backtest, write_results_csv};
+use optiva_ws::bot::{AccountSummary, TradingBot};
+use optiva_ws::clock::timestamp;
+use optiva_ws::config::{Config, DEFAULT_CONFIG_PATH};
+use optiva_ws::eval::{evaluate, format_eval, load_eval_params, EvalInput};
+use optiva_ws::journal::load_journal;
+use optiva_ws::persist::save_params;
+use optiva_ws::reload::SetLogFilter;
+use optiva_ws::replay::replay;
+use optiva_ws::risk_eval::{
+    evaluate_risk, format_outcomes, journal_games, synthetic_games, write_outcomes_csv,
+};
+use optiva_ws::rt;
+use optiva_ws::state::StrategyParams;
+use optiva_ws::summary::account_label;
+use optiva_ws::transcript::read_transcript;
+use optiva_ws::tune::{format_results, run_tune, TuneSource};
+
+#[derive(Parser, Debug)]
+#[command(about = "Optiver trading game bot")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Path to the TOML config file
+    #[arg(long, default_value = DEFAULT_CONFIG_PATH)]
+    config: PathBuf,
+
+    /// Replay a recorded transcript through the current strategy instead of connecting
+    #[arg(long, value_name = "FILE")]
+    replay: Option<PathBuf>,
+
+    /// Number of connections, overriding the config file
+    #[arg(long, value_name = "N")]
+    connections: Option<usize>,
+
+    /// Run a single connection with debug logging, for debugging
+    #[arg(long, conflicts_with = "connections")]
+    single: bool,
+
+    /// Ignore puzzle events, overriding the config file
+    #[arg(long)]
+    no_puzzles: bool,
+
+    /// Keep the strategy params fixed instead of running the optimizer
+    #[arg(long)]
+    no_optimize: bool,
+
+    /// Simulate fills locally instead of sending trades
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Log as JSON lines instead of human readable text
+    #[arg(long)]
+    log_json: bool,
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct StartMessage {
-    event: String,
-    player_id: String,
-    data: StartData,
+    /// Plain logging to the terminal instead of the dashboard (when built with the tui feature)
+    #[arg(long)]
+    no_tui: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct StartData {
-    player_id: String,
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Compare strategies over seeded synthetic games (see [backtest]) instead of connecting
+    Backtest(BacktestArgs),
+    /// Grid search the strategy params (see [tune]) and print the best combinations
+    Tune(TuneArgs),
+    /// Analyze a decision journal: PnL by connection and hour, win rate by signal strength,
+    /// how puzzle trades paid off. Needs no config.
+    Analyze(AnalyzeArgs),
+    /// Show how one state update would be traded: the tanh signals, the combined signal
+    /// and the volume under each sizing mode. Needs no config and sends nothing.
+    Eval(EvalArgs),
+    /// Replay recorded journals, or synthetic games (see [backtest]), through the drawdown
+    /// breaker under each setting in [risk_eval], and compare how often it trips and what
+    /// it costs. Sends nothing.
+    EvaluateRisk(EvaluateRiskArgs),
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct SkipMessage {
-    event: String,
-    player_id: String,
-    data: Value,
+#[derive(Args, Debug)]
+struct BacktestArgs {
+    /// Number of games, overriding the config file
+    #[arg(long, value_name = "N")]
+    games: Option<usize>,
+
+    /// Seed, overriding the config file
+    #[arg(long, value_name = "N")]
+    seed: Option<u64>,
+
+    /// Also write every game's result to a CSV file
+    #[arg(long, value_name = "FILE")]
+    csv: Option<PathBuf>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct TradeMessage {
-    event: String,
-    player_id: String,
-    data: TradeData,
+#[derive(Args, Debug)]
+struct TuneArgs {
+    /// Score the combinations on a recorded transcript instead of synthetic games
+    #[arg(long, value_name = "FILE")]
+    transcript: Option<PathBuf>,
+
+    /// Write the best params to a file the bot can restore them from (see [persist])
+    #[arg(long, value_name = "FILE")]
+    out: Option<PathBuf>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct TradeData {
-    volume: i32,
+#[derive(Args, Debug)]
+struct AnalyzeArgs {
+    /// The journal (JSONL, or CSV or SQLite by extension)
+    #[arg(value_name = "FILE")]
+    path: PathBuf,
+
+    /// Print the analysis as JSON, for scripting
+    #[arg(long)]
+    json: bool,
 }
 
-// State structures
-#[derive(Debug, Clone)]
-struct SignalData {
-    conn_id: usize,
-    timestamp: f64,
-    momentum: f64,
-    forecast: f64,
-    combined_signal: f64,
-    trade_volume: i32,
-    position: i32,
+#[derive(Args, Debug)]
+struct EvaluateRiskArgs {
+    /// Decision journal to replay (JSONL, or CSV or SQLite by extension), as many times as
+    /// there are journals. Synthetic games without one.
+    #[arg(long, value_name = "FILE")]
+    journal: Vec<PathBuf>,
+
+    /// Also write the comparison to a CSV file
+    #[arg(long, value_name = "FILE")]
+    csv: Option<PathBuf>,
 }
 
-#[derive(Debug, Clone)]
-struct PerformanceData {
-    conn_id: usize,
-    timestamp: f64,
+#[derive(Args, Debug)]
+struct EvalArgs {
+    /// Momentum as the server reports it
+    #[arg(long, allow_negative_numbers = true)]
     momentum: f64,
+
+    /// Price forecast as the server reports it
+    #[arg(long, allow_negative_numbers = true)]
     forecast: f64,
+
+    /// Position held before the trade
+    #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
     position: i32,
-    trade_volume: i32,
-    pnl_change: f64,
-    price: f64,
-    total_pnl: f64,
-}
 
-#[derive(Debug, Clone)]
-struct ConnectionPerformance {
-    last_pnl: f64,
-    trades_made: usize,
-    successful_trades: usize,
-}
+    /// Position limit
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(i32).range(1..))]
+    limit: i32,
 
-#[derive(Debug, Clone)]
-struct StrategyParams {
-    momentum_weight: f64,
-    forecast_weight: f64,
-    strong_momentum_threshold: f64,
-    medium_momentum_threshold: f64,
-    aggressive_factor: f64,
-}
+    /// Params saved by the bot or `tune --out`, or the [strategy] params alone as JSON.
+    /// The defaults without it.
+    #[arg(long, value_name = "FILE")]
+    params: Option<PathBuf>,
 
-// Shared state
-struct SharedState {
-    strategy_params: RwLock<StrategyParams>,
-    trade_history: Mutex<VecDeque<SignalData>>,
-    performance_history: Mutex<VecDeque<PerformanceData>>,
-    connection_performance: Mutex<std::collections::HashMap<usize, ConnectionPerformance>>,
-    last_optimization: RwLock<f64>,
-    optimization_interval: f64,
+    /// Print the evaluation as JSON, for scripting
+    #[arg(long)]
+    json: bool,
 }
 
-impl SharedState {
-    fn new() -> Self {
-        SharedState {
-            strategy_params: RwLock::new(StrategyParams {
-                momentum_weight: 0.6,
-                forecast_weight: 0.4,
-                strong_momentum_threshold: 10.0,
-                medium_momentum_threshold: 5.0,
-                aggressive_factor: 1.5,
-            }),
-            trade_history: Mutex::new(VecDeque::with_capacity(HISTORY_SIZE)),
-            performance_history: Mutex::new(VecDeque::with_capacity(HISTORY_SIZE)),
-            connection_performance: Mutex::new(std::collections::HashMap::new()),
-            last_optimization: RwLock::new(timestamp()),
-            optimization_interval: 30.0,
-        }
-    }
-}
+// Logs go here while the dashboard has the terminal
+const TUI_LOG_PATH: &str = "bot.log";
 
-// Helper function for current time
-fn timestamp() -> f64 {
-    let start = SystemTime::now();
-    let since_the_epoch = start
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards");
-    since_the_epoch.as_secs_f64()
+// Filtered by RUST_LOG, defaulting to the given level. Spans let one connection be
+// turned up, e.g. RUST_LOG='info,[connection{conn_id=2}]=debug'. Returns what swaps
+// the filter for the config's log_level.
+fn init_logging(
+    json: bool,
+    default_level: &str,
+    file: Option<&str>,
+) -> std::io::Result<SetLogFilter> {
+    let default_level = default_level.to_string();
+    let startup_filter = move || {
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&default_level))
+    };
+    let filter = startup_filter();
+    let writer = match file {
+        Some(path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            BoxMakeWriter::new(Mutex::new(file))
+        }
+        None => BoxMakeWriter::new(std::io::stdout),
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        .with_ansi(file.is_none());
+    let pick = move |level: Option<&str>| match level {
+        Some(level) => EnvFilter::try_new(level).map_err(|e| e.to_string()),
+        None => Ok(startup_filter()),
+    };
+    // The handle's type depends on the format, so each gets its own
+    let set_log_filter: SetLogFilter = if json {
+        let builder = builder.json().with_filter_reloading();
+        let handle = builder.reload_handle();
+        builder.init();
+        Box::new(move |level| handle.reload(pick(level)?).map_err(|e| e.to_string()))
+    } else {
+        let builder = builder.with_filter_reloading();
+        let handle = builder.reload_handle();
+        builder.init();
+        Box::new(move |level| handle.reload(pick(level)?).map_err(|e| e.to_string()))
+    };
+    Ok(set_log_filter)
 }
 
-// Handle puzzle impact
-fn handle_puzzle_impact(puzzle_data: &Value) -> i32 {
-    let impact = puzzle_data["impact"].as_f64().unwrap_or(0.0);
-    if impact > 0.0 {
-        println!("The stock will increase by ${}", impact);
-        return impact.abs() as i32; // Buy signal
-    } else if impact < 0.0 {
-        println!("The stock will decrease by ${}", impact.abs());
-        return -(impact.abs() as i32); // Sell signal
-    }
-    0 // No trade
+// Entry point, on whichever runtime the crate was built with
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    rt::block_on(run())
 }
 
-async fn determine_trade_volume(
-    forecast: f64,
-    momentum: f64,
-    position: i32,
-    position_limit: i32,
-    conn_id: usize,
-    shared_state: &Arc<SharedState>,
-) -> i32 {
-    // Get current strategy parameters (unused in volume calculation here,
-    // but still used for signal weightings, if needed)
-    let params = shared_state.strategy_params.read().await.clone();
-
-    // Calculate signals with tanh smoothing (values in (-1, 1))
-    let momentum_signal = f64::tanh(momentum / 10.0);
-    let forecast_signal = f64::tanh(forecast * 2.0);
-
-    // Weighted combination
-    let combined_signal = (momentum_signal * params.momentum_weight)
-        + (forecast_signal * params.forecast_weight);
-
-    // In risky mode: if there is any signal, go all-in.
-    // - If the signal is positive, buy the full available amount.
-    // - If negative, sell the full available amount.
-    let trade_volume = if combined_signal > 0.0 {
-        // Maximum buy: position_limit minus current position.
-        let max_buy = position_limit - position;
-        max_buy
-    } else if combined_signal < 0.0 {
-        // Maximum sell: current position plus position_limit.
-        let max_sell = position + position_limit;
-        -max_sell
+async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    let use_tui =
+        cfg!(feature = "tui") && !cli.no_tui && cli.replay.is_none() && cli.command.is_none();
+    // Every simulated trade at info would bury the results
+    let default_level = if matches!(
+        cli.command,
+        Some(Command::Backtest(_) | Command::Tune(_) | Command::EvaluateRisk(_))
+    ) {
+        "warn"
+    } else if cli.single {
+        "debug"
     } else {
-        0
-    };
-
-    // Record for strategy optimization
-    let signal_data = SignalData {
-        conn_id,
-        timestamp: timestamp(),
-        momentum,
-        forecast,
-        combined_signal,
-        trade_volume,
-        position,
+        "info"
     };
+    let set_log_filter =
+        init_logging(cli.log_json, default_level, use_tui.then_some(TUI_LOG_PATH))?;
 
-    // Add to history with mutex protection
-    let mut history = shared_state.trade_history.lock().await;
-    if history.len() >= HISTORY_SIZE {
-        history.pop_front();
+    // Works on the file alone, no config needed
+    if let Some(Command::Analyze(AnalyzeArgs { path, json })) = &cli.command {
+        let report = analyze_journal(&load_journal(path)?);
+        if *json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            println!("Journal {}:", path.display());
+            print!("{}", format_report(&report));
+        }
+        return Ok(());
     }
-    history.push_back(signal_data);
 
-    trade_volume
-}
+    // Plain inputs, no config or connection needed either
+    if let Some(Command::Eval(args)) = &cli.command {
+        let params = match &args.params {
+            Some(path) => load_eval_params(path)?,
+            None => StrategyParams::default(),
+        };
+        let input = EvalInput {
+            momentum: args.momentum,
+            forecast: args.forecast,
+            position: args.position,
+            position_limit: args.limit,
+        };
+        let report = evaluate(input, &params);
+        if args.json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            print!("{}", format_eval(input, &params, &report));
+        }
+        return Ok(());
+    }
 
-// Strategy optimization
-async fn optimize_strategy(shared_state: &Arc<SharedState>) {
-    // Check if it's time to optimize
-    let current_time = timestamp();
-    {
-        let last_opt = *shared_state.last_optimization.read().await;
-        if current_time - last_opt < shared_state.optimization_interval {
-            return;
+    let config = match Config::load(&cli.config).and_then(|mut config| {
+        config.dry_run |= cli.dry_run;
+        config.puzzles &= !cli.no_puzzles;
+        config.optimizer.enabled &= !cli.no_optimize;
+        if let Some(Command::Backtest(args)) = &cli.command {
+            if let Some(games) = args.games {
+                config.backtest.games = games;
+            }
+            if let Some(seed) = args.seed {
+                config.backtest.seed = seed;
+            }
         }
-        
-        // Check if we have enough data
-        let perf_history = shared_state.performance_history.lock().await;
-        if perf_history.len() < 5 {
-            return;
+        if let Some(Command::Tune(args)) = &cli.command {
+            if let Some(path) = &args.transcript {
+                config.tune.transcript = Some(path.clone());
+            }
         }
+        if cli.single {
+            config.connections = 1;
+        } else if let Some(connections) = cli.connections {
+            config.connections = connections;
+        }
+        config.validate()?;
+        Ok(config)
+    }) {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            eprintln!("Error loading {}: {}", cli.config.display(), e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(level) = &config.log_level {
+        set_log_filter(Some(level))?;
     }
-    
-    // Update optimization timestamp
-    *shared_state.last_optimization.write().await = current_time;
-    
-    // Extract performance data
-    let performances: Vec<PerformanceData>;
-    {
-        let history = shared_state.performance_history.lock().await;
-        performances = history.iter().cloned().collect();
-    }
-    
-    if !performances.is_empty() {
-        // Calculate average profit
-        let pnl_changes: Vec<f64> = performances.iter()
-            .map(|p| p.pnl_change)
-            .collect();
-        let avg_profit = pnl_changes.mean();
-        
-        // Update strategy based on performance
-        let mut params = shared_state.strategy_params.write().await;
-        
-        if avg_profit > 5.0 {
-            // Strategy is working well
-            let mut momentum_correlations = Vec::new();
-            let mut forecast_correlations = Vec::new();
-            
-            for p in &performances {
-                if p.pnl_change > 0.0 && p.trade_volume != 0 {
-                    // Profitable trade - analyze signals
-                    if f64::abs(p.momentum) > f64::abs(p.forecast) {
-                        momentum_correlations.push(1.0);
-                        forecast_correlations.push(0.5);
-                    } else {
-                        momentum_correlations.push(0.5);
-                        forecast_correlations.push(1.0);
-                    }
-                }
+
+    if let Some(path) = &cli.replay {
+        println!("Replay of {}:", path.display());
+        let entries = read_transcript(path)?;
+        for account in config.accounts() {
+            let label = account_label(&account.account);
+            for (conn_id, report) in replay(&entries, Arc::new(account)).await {
+                println!(
+                    "  {}Connection {}: {} frames, {} trades",
+                    label,
+                    conn_id,
+                    report.frames,
+                    report.trades.len()
+                );
             }
-            
-            // Update weights if we have correlation data
-            if !momentum_correlations.is_empty() && !forecast_correlations.is_empty() {
-                let avg_momentum_corr = momentum_correlations.mean();
-                let avg_forecast_corr = forecast_correlations.mean();
-                let total = avg_momentum_corr + avg_forecast_corr;
-                
-                params.momentum_weight = avg_momentum_corr / total;
-                params.forecast_weight = avg_forecast_corr / total;
-                params.aggressive_factor = f64::min(2.0, params.aggressive_factor + 0.1);
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::EvaluateRisk(args)) = &cli.command {
+        let games = if args.journal.is_empty() {
+            println!(
+                "Risk settings over {} synthetic games, seed {}:",
+                config.risk_eval.games, config.backtest.seed
+            );
+            synthetic_games(&config).await
+        } else {
+            let mut games = Vec::new();
+            for path in &args.journal {
+                games.extend(journal_games(&load_journal(path)?.entries));
             }
-        } else if avg_profit < -5.0 {
-            // Strategy is losing money
-            params.momentum_weight = 0.5;
-            params.forecast_weight = 0.5;
-            params.aggressive_factor = f64::max(1.0, params.aggressive_factor - 0.2);
+            println!("Risk settings over {} recorded games:", games.len());
+            games
+        };
+        let outcomes = evaluate_risk(&games, &config.risk_eval.settings(&config.risk));
+        println!("  (PnL is the mean per game, as played and with the breaker)");
+        print!("{}", format_outcomes(&outcomes));
+        if let Some(path) = &args.csv {
+            write_outcomes_csv(path, &outcomes)?;
+            println!("Comparison written to {}", path.display());
         }
-        
-        println!("Optimized strategy parameters: momentum_weight={}, forecast_weight={}, aggressive_factor={}",
-                params.momentum_weight, params.forecast_weight, params.aggressive_factor);
+        return Ok(());
     }
-}
 
-// Handle single connection
-async fn handle_connection(conn_id: usize, shared_state: Arc<SharedState>) {
-    println!("Starting connection {}", conn_id);
-    
-    // Initialize connection performance
-    {
-        let mut performances = shared_state.connection_performance.lock().await;
-        if !performances.contains_key(&conn_id) {
-            performances.insert(conn_id, ConnectionPerformance {
-                last_pnl: 0.0,
-                trades_made: 0,
-                successful_trades: 0,
-            });
+    if let Some(Command::Backtest(args)) = &cli.command {
+        let backtest = &config.backtest;
+        let (reports, results) = run_backtest(&config).await;
+        println!(
+            "Backtest of {} games, {} updates each, seed {}:",
+            backtest.games, backtest.updates_per_game, backtest.seed
+        );
+        print!("{}", format_reports(&reports));
+        if let Some(path) = &args.csv {
+            write_results_csv(path, &results)?;
+            println!("Per-game results written to {}", path.display());
         }
+        return Ok(());
     }
-    
-    loop {
-        println!("Connection {}: Connecting to WebSocket", conn_id);
-        
-        match connect_async(URL).await {
-            Ok((mut ws_stream, _)) => {
-                println!("Connection {}: Connected to WebSocket", conn_id);
-                
-                // Send connection message
-                let conn_message = json!({
-                    "event": "connection",
-                    "player_id": "",
-                    "data": {
-                        "alias": format!("Aegizz-{}", conn_id),
-                        "player_id": PLAYER_ID,
-                        "token": ""
-                    }
-                });
-                
-                if let Err(e) = ws_stream.send(Message::Text(conn_message.to_string())).await {
-                    println!("Connection {}: Error sending connection message: {}", conn_id, e);
-                    task::sleep(Duration::from_secs(2)).await;
-                    continue;
-                }
-                
-                println!("Connection {}: Sent connection message", conn_id);
-                
-                // Message handling loop
-                while let Some(msg_result) = ws_stream.next().await {
-                    match msg_result {
-                        Ok(msg) => {
-                            if let Message::Text(text) = msg {
-                                match serde_json::from_str::<Value>(&text) {
-                                    Ok(response_data) => {
-                                        let event = response_data["event"].as_str().unwrap_or("");
-                                        
-                                        // Handle connection establishment
-                                        if event == "connection" && response_data.get("data").is_some() {
-                                            if let Some(player_id) = response_data["data"]["player_id"].as_str() {
-                                                if player_id == PLAYER_ID {
-                                                    println!("Connection {}: Established, sending start event...", conn_id);
-                                                    
-                                                    let start_message = json!({
-                                                        "event": "start",
-                                                        "player_id": "",
-                                                        "data": {
-                                                            "player_id": PLAYER_ID
-                                                        }
-                                                    });
-                                                    
-                                                    if let Err(e) = ws_stream.send(Message::Text(start_message.to_string())).await {
-                                                        println!("Connection {}: Error sending start message: {}", conn_id, e);
-                                                        break;
-                                                    }
-                                                }
-                                            }
-                                        }
-                                        // Handle state updates
-                                        else if event == "state" && response_data.get("data").is_some() {
-                                            let state_data = &response_data["data"];
-                                            
-                                            let forecast = state_data["price_forecast"].as_f64().unwrap_or(0.0);
-                                            let momentum = state_data["momentum"].as_f64().unwrap_or(0.0);
-                                            let position = state_data["position"].as_i64().unwrap_or(0) as i32;
-                                            let position_limit = state_data["position_limit"].as_i64().unwrap_or(3) as i32;
-                                            let current_price = state_data["price"].as_f64().unwrap_or(0.0);
-                                            let current_pnl = state_data["pnl"].as_f64().unwrap_or(0.0);
-                                            
-                                            // Calculate trade volume
-                                            let trade_volume = determine_trade_volume(
-                                                forecast,
-                                                momentum,
-                                                position,
-                                                position_limit,
-                                                conn_id,
-                                                &shared_state
-                                            ).await;
-                                            
-                                            // Track PnL changes
-                                            let pnl_change: f64;
-                                            {
-                                                let mut performances = shared_state.connection_performance.lock().await;
-                                                let perf = performances.get_mut(&conn_id).unwrap();
-                                                pnl_change = current_pnl - perf.last_pnl;
-                                                perf.last_pnl = current_pnl;
-                                                
-                                                // Record performance data if we've made trades
-                                                if perf.trades_made > 0 {
-                                                    let perf_data = PerformanceData {
-                                                        conn_id,
-                                                        timestamp: timestamp(),
-                                                        momentum,
-                                                        forecast,
-                                                        position,
-                                                        trade_volume,
-                                                        pnl_change,
-                                                        price: current_price,
-                                                        total_pnl: current_pnl,
-                                                    };
-                                                    
-                                                    let mut history = shared_state.performance_history.lock().await;
-                                                    if history.len() >= HISTORY_SIZE {
-                                                        history.pop_front();
-                                                    }
-                                                    history.push_back(perf_data);
-                                                }
-                                            }
-                                            
-                                            println!(
-                                                "Connection {}: Price=${}, Forecast={:.2}, Momentum={:.2}, Position={}/{}, PnL=${}",
-                                                conn_id, current_price, forecast, momentum, position, position_limit, current_pnl
-                                            );
-                                            
-                                            // Execute trade if needed
-                                            if trade_volume != 0 {
-                                                let trade_message = json!({
-                                                    "event": "trade",
-                                                    "player_id": PLAYER_ID,
-                                                    "data": {
-                                                        "volume": trade_volume
-                                                    }
-                                                });
-                                                
-                                                if let Err(e) = ws_stream.send(Message::Text(trade_message.to_string())).await {
-                                                    println!("Connection {}: Error sending trade message: {}", conn_id, e);
-                                                    break;
-                                                }
-                                                
-                                                println!(
-                                                    "Connection {}: Sent trade: {} {}",
-                                                    conn_id,
-                                                    if trade_volume > 0 { "BUY" } else { "SELL" },
-                                                    trade_volume.abs()
-                                                );
-                                                
-                                                // Update trade statistics
-                                                {
-                                                    let mut performances = shared_state.connection_performance.lock().await;
-                                                    let perf = performances.get_mut(&conn_id).unwrap();
-                                                    perf.trades_made += 1;
-                                                }
-                                            }
-                                            
-                                            // Optimize strategy periodically
-                                            optimize_strategy(&shared_state).await;
-                                        }
-                                        // Handle game end
-                                        else if event == "finish" && response_data.get("data").is_some() {
-                                            let final_pnl = response_data["data"]["pnl"].as_f64().unwrap_or(0.0);
-                                            println!("Connection {}: Game over! Final PnL: ${}", conn_id, final_pnl);
-                                            println!("Connection {}: Will reconnect shortly...", conn_id);
-                                            break;
-                                        }
-                                        // Handle puzzles
-                                        else if event == "puzzle" && response_data.get("data").is_some() {
-                                            let puzzle_data = &response_data["data"];
-                                            let puzzle_impact = handle_puzzle_impact(puzzle_data);
-                                            
-                                            // Trade based on puzzle impact
-                                            if puzzle_impact != 0 {
-                                                let trade_message = json!({
-                                                    "event": "trade",
-                                                    "player_id": PLAYER_ID,
-                                                    "data": {
-                                                        "volume": if puzzle_impact > 0 { 3 } else { -3 }
-                                                    }
-                                                });
-                                                
-                                                if let Err(e) = ws_stream.send(Message::Text(trade_message.to_string())).await {
-                                                    println!("Connection {}: Error sending puzzle trade: {}", conn_id, e);
-                                                    break;
-                                                }
-                                                
-                                                println!(
-                                                    "Connection {}: Sent puzzle trade: {} 3",
-                                                    conn_id,
-                                                    if puzzle_impact > 0 { "BUY" } else { "SELL" }
-                                                );
-                                            }
-                                            
-                                            // Skip to next stage
-                                            let skip_message = json!({
-                                                "event": "skip",
-                                                "player_id": "",
-                                                "data": {}
-                                            });
-                                            
-                                            if let Err(e) = ws_stream.send(Message::Text(skip_message.to_string())).await {
-                                                println!("Connection {}: Error sending skip message: {}", conn_id, e);
-                                                break;
-                                            }
-                                            
-                                            println!("Connection {}: Sent skip message", conn_id);
-                                        }
-                                    },
-                                    Err(e) => {
-                                        println!("Connection {}: JSON decode error: {}", conn_id, e);
-                                    }
-                                }
-                            }
-                        },
-                        Err(e) => {
-                            println!("Connection {}: WebSocket error: {}", conn_id, e);
-                            break;
-                        }
-                    }
-                }
-            },
-            Err(e) => {
-                println!("Connection {}: Failed to connect: {}", conn_id, e);
+
+    if let Some(Command::Tune(args)) = &cli.command {
+        let tune = &config.tune;
+        let source = match &tune.transcript {
+            Some(path) => TuneSource::Transcript(Arc::new(read_transcript(path)?)),
+            None => TuneSource::synthetic(&config),
+        };
+        match &tune.transcript {
+            Some(path) => println!(
+                "Tuning {} combinations of {} on {}:",
+                tune.grid.size(),
+                tune.strategy.build().name(),
+                path.display()
+            ),
+            None => println!(
+                "Tuning {} combinations of {} on {} games, seed {}:",
+                tune.grid.size(),
+                tune.strategy.build().name(),
+                tune.games,
+                config.backtest.seed
+            ),
+        }
+
+        // Every 5% or so, with a rough estimate of what's left
+        let started = Instant::now();
+        let mut reported = 0;
+        let results = run_tune(&config, source, |done, total| {
+            let percent = done * 100 / total;
+            if percent >= reported + 5 || done == total {
+                reported = percent;
+                let left = started.elapsed().as_secs_f64() / done as f64 * (total - done) as f64;
+                eprintln!(
+                    "  {}/{} evaluated ({}%), about {:.0}s left",
+                    done, total, percent, left
+                );
             }
+        })
+        .await;
+        print!("{}", format_results(&results, &tune.grid, tune.top));
+        if let (Some(path), Some(best)) = (&args.out, results.first()) {
+            save_params(path, &best.to_saved(timestamp())).await?;
+            println!("Best params written to {}", path.display());
+        }
+        return Ok(());
+    }
+
+    // The dashboard has the terminal, so there's no reading commands from it
+    let mut bot_config = Config::clone(&config);
+    if use_tui && bot_config.console.enabled {
+        warn!("The console can't share the terminal with the dashboard, turning it off");
+        bot_config.console.enabled = false;
+    }
+    let handle = TradingBot::from_config(bot_config)
+        .config_file(&cli.config)
+        .log_filter(set_log_filter)
+        .run()
+        .await?;
+
+    // Stop every connection cleanly on Ctrl-C
+    {
+        let shutdown = handle.shutdown_signal();
+        ctrlc::set_handler(move || {
+            info!("Received Ctrl-C, shutting down");
+            shutdown.trigger();
+        })?;
+    }
+
+    // The dashboard reads snapshots on its own thread and quits by triggering shutdown
+    #[cfg(feature = "tui")]
+    let dashboard = use_tui.then(|| {
+        let state = handle.shared_state();
+        let shutdown = handle.shutdown_signal();
+        rt::spawn_blocking(move || optiva_ws::tui::run(state, shutdown))
+    });
+
+    let summaries = handle.wait().await;
+
+    #[cfg(feature = "tui")]
+    if let Some(dashboard) = dashboard {
+        if let Err(e) = dashboard.await {
+            warn!(error = %e, "Dashboard failed");
         }
-        
-        println!("Connection {}: Closed, preparing to reconnect", conn_id);
-        
-        // Wait a few seconds before reconnecting
-        let delay = rand::thread_rng().gen_range(1..4);
-        task::sleep(Duration::from_secs(delay)).await;
     }
-}
 
-// Entry point
-#[async_std::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    println!("Starting trading bot with {} connections", NUM_CONNECTIONS);
-    
-    // Create shared state
-    let shared_state = Arc::new(SharedState::new());
-    
-    // Start multiple connections in parallel
-    let mut handles = Vec::new();
-    for i in 0..NUM_CONNECTIONS {
-        let state_clone = Arc::clone(&shared_state);
-        let handle = task::spawn(async move {
-            handle_connection(i, state_clone).await;
-        });
-        handles.push(handle);
+    if config.dry_run {
+        println!("Session summary (SIMULATED, no trades were sent):");
+    } else {
+        println!("Session summary:");
     }
-    
-    // Wait for all connections (this will run indefinitely)
-    futures::future::join_all(handles).await;
-    
+    for summary in &summaries {
+        let label = if summaries.len() > 1 {
+            account_label(&summary.account)
+        } else {
+            String::new()
+        };
+        print_summary(summary, &label);
+    }
+
     Ok(())
-}
\ No newline at end of file
+}
+
+// One account's connections and params, each line prefixed with the account's label
+fn print_summary(summary: &AccountSummary, label: &str) {
+    for (conn_id, perf) in &summary.connections {
+        println!(
+            "  {}Connection {}: trades={}, rejected={}, rate limited={}, over the trade budget={}, server errors={}, oversized frames={}, malformed frames={}, stale states skipped={}, states coalesced={}, invalid states dropped={}, repeated states skipped={}, stale trades dropped={}, over the outbound ceiling={}, drawdown halts={}, final PnL=${}",
+            label,
+            conn_id,
+            perf.trades_made,
+            perf.rejected_trades,
+            perf.rate_limited,
+            perf.over_budget,
+            perf.server_errors,
+            perf.oversized_frames,
+            perf.malformed_frames,
+            perf.stale_states,
+            perf.coalesced_states,
+            perf.invalid_states,
+            perf.duplicate_states,
+            perf.stale_trades,
+            perf.outbound_dropped.total(),
+            perf.breaker.trips,
+            perf.last_pnl
+        );
+        println!(
+            "  {}Connection {} sessions ended: {}",
+            label, conn_id, perf.disconnects
+        );
+    }
+
+    let params = &summary.params;
+    println!(
+        "  {}Final strategy parameters: momentum_weight={}, forecast_weight={}, aggressive_factor={}, sizing={:?}",
+        label,
+        params.momentum_weight, params.forecast_weight, params.aggressive_factor, params.sizing
+    );
+}
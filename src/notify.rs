@@ -0,0 +1,360 @@
+use async_channel::{Receiver, Sender, TrySendError};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::caps::{Cap, CapHits};
+use crate::rt;
+use crate::state::StrategyParams;
+
+// Alerts waiting on a slow webhook past this are dropped rather than queued
+const ALERT_QUEUE: usize = 32;
+
+// Slack or Discord webhook that big events are posted to
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotifyConfig {
+    // Alerts are off without one
+    pub webhook_url: Option<String>,
+    // Guessed from the URL when unset
+    pub service: Option<WebhookService>,
+    // Shortest gap between two messages about the same kind of event
+    pub min_interval_secs: f64,
+    // A connection down for longer than this is reported, once per outage
+    pub connection_down_secs: f64,
+    // Relative change in a tuned param that counts as large (0.25 = 25%)
+    pub param_change: f64,
+    // Per delivery attempt; a failed one is logged and dropped
+    pub timeout_secs: f64,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        NotifyConfig {
+            webhook_url: None,
+            service: None,
+            min_interval_secs: 30.0,
+            connection_down_secs: 60.0,
+            param_change: 0.25,
+            timeout_secs: 10.0,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookService {
+    Slack,
+    Discord,
+}
+
+impl WebhookService {
+    pub fn for_url(url: &str) -> WebhookService {
+        if url.contains("discord.com/") || url.contains("discordapp.com/") {
+            WebhookService::Discord
+        } else {
+            WebhookService::Slack
+        }
+    }
+
+    // Both take a JSON body, they just call the text something different
+    pub fn payload(self, text: &str) -> Value {
+        match self {
+            WebhookService::Slack => json!({ "text": text }),
+            WebhookService::Discord => json!({ "content": text }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlertKind {
+    GameFinished,
+    BreakerTripped,
+    ConnectionDown,
+    ParamsChanged,
+}
+
+// A tuned param before and after an optimizer pass
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamChange {
+    pub name: &'static str,
+    pub before: f64,
+    pub after: f64,
+}
+
+impl ParamChange {
+    // From zero any move is as large as it gets
+    pub fn relative(&self) -> f64 {
+        let change = (self.after - self.before).abs();
+        if self.before == 0.0 {
+            return if change == 0.0 { 0.0 } else { f64::INFINITY };
+        }
+        change / self.before.abs()
+    }
+}
+
+// The params the optimizer tunes that moved, in a fixed order
+pub fn param_changes(before: &StrategyParams, after: &StrategyParams) -> Vec<ParamChange> {
+    [
+        (
+            "momentum_weight",
+            before.momentum_weight,
+            after.momentum_weight,
+        ),
+        (
+            "forecast_weight",
+            before.forecast_weight,
+            after.forecast_weight,
+        ),
+        (
+            "aggressive_factor",
+            before.aggressive_factor,
+            after.aggressive_factor,
+        ),
+    ]
+    .into_iter()
+    .filter(|(_, before, after)| before != after)
+    .map(|(name, before, after)| ParamChange {
+        name,
+        before,
+        after,
+    })
+    .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Alert {
+    GameFinished {
+        conn_id: usize,
+        strategy: String,
+        pnl: Option<f64>,
+        trades: usize,
+        simulated: bool,
+    },
+    BreakerTripped {
+        conn_id: usize,
+        pnl: f64,
+        drawdown: f64,
+    },
+    ConnectionDown {
+        conn_id: usize,
+        secs: f64,
+    },
+    ParamsChanged {
+        changes: Vec<ParamChange>,
+    },
+}
+
+impl Alert {
+    pub fn kind(&self) -> AlertKind {
+        match self {
+            Alert::GameFinished { .. } => AlertKind::GameFinished,
+            Alert::BreakerTripped { .. } => AlertKind::BreakerTripped,
+            Alert::ConnectionDown { .. } => AlertKind::ConnectionDown,
+            Alert::ParamsChanged { .. } => AlertKind::ParamsChanged,
+        }
+    }
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Alert::GameFinished {
+                conn_id,
+                strategy,
+                pnl,
+                trades,
+                simulated,
+            } => {
+                let pnl = pnl.map_or("n/a".to_string(), |pnl| format!("${:.2}", pnl));
+                write!(
+                    f,
+                    "Game finished on connection {} ({}): final PnL {}, {} trades",
+                    conn_id, strategy, pnl, trades
+                )?;
+                if *simulated {
+                    write!(f, " (simulated)")?;
+                }
+                Ok(())
+            }
+            Alert::BreakerTripped {
+                conn_id,
+                pnl,
+                drawdown,
+            } => write!(
+                f,
+                "Drawdown breaker tripped on connection {}: down ${:.2} from peak, PnL ${:.2}, trading halted",
+                conn_id, drawdown, pnl
+            ),
+            Alert::ConnectionDown { conn_id, secs } => write!(
+                f,
+                "Connection {} has been down for {:.0}s and is still reconnecting",
+                conn_id, secs
+            ),
+            Alert::ParamsChanged { changes } => {
+                write!(f, "Optimizer made a large parameter change:")?;
+                for change in changes {
+                    write!(
+                        f,
+                        " {} {:.3} -> {:.3};",
+                        change.name, change.before, change.after
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// At most one message per kind of event in any window, so a reconnect storm
+// doesn't flood the channel
+#[derive(Debug, Default)]
+pub struct AlertLimiter {
+    last_sent: HashMap<AlertKind, f64>,
+}
+
+impl AlertLimiter {
+    pub fn allow(&mut self, kind: AlertKind, now: f64, min_interval: f64) -> bool {
+        if let Some(last) = self.last_sent.get(&kind) {
+            if now - last < min_interval {
+                return false;
+            }
+        }
+        self.last_sent.insert(kind, now);
+        true
+    }
+}
+
+// How long a connection has been down, so an outage is reported once it's long enough
+#[derive(Debug, Default)]
+pub struct Outage {
+    since: Option<f64>,
+    reported: bool,
+}
+
+impl Outage {
+    // Keeps the first time for an outage already under way
+    pub fn down(&mut self, now: f64) {
+        self.since.get_or_insert(now);
+    }
+
+    pub fn up(&mut self) {
+        *self = Outage::default();
+    }
+
+    // Seconds down, the first time that's past the threshold
+    pub fn due(&mut self, now: f64, threshold: f64) -> Option<f64> {
+        let secs = now - self.since?;
+        if self.reported || secs <= threshold {
+            return None;
+        }
+        self.reported = true;
+        Some(secs)
+    }
+}
+
+// Handle for posting alerts through the notifier task. Cheap to clone; the task
+// finishes once every handle has been dropped.
+#[derive(Clone)]
+pub struct Notifier {
+    sender: Sender<Alert>,
+    config: NotifyConfig,
+    cap_hits: Arc<CapHits>,
+}
+
+impl Notifier {
+    // Start the notifier task, if a webhook is configured
+    pub fn spawn(config: &NotifyConfig) -> Option<(Notifier, rt::JoinHandle<()>)> {
+        let url = config.webhook_url.clone()?;
+        let service = config
+            .service
+            .unwrap_or_else(|| WebhookService::for_url(&url));
+        let (sender, receiver) = async_channel::bounded(ALERT_QUEUE);
+        let handle = rt::spawn(deliver_alerts(receiver, url, service, config.clone()));
+        let notifier = Notifier {
+            sender,
+            config: config.clone(),
+            cap_hits: Arc::default(),
+        };
+        Some((notifier, handle))
+    }
+
+    // Count alerts dropped on a full queue there
+    pub fn with_cap_hits(mut self, cap_hits: Arc<CapHits>) -> Notifier {
+        self.cap_hits = cap_hits;
+        self
+    }
+
+    pub fn config(&self) -> &NotifyConfig {
+        &self.config
+    }
+
+    // Never waits on the webhook; dropped if the queue is full or the task has stopped
+    pub fn notify(&self, alert: Alert) {
+        match self.sender.try_send(alert) {
+            Ok(()) => {}
+            Err(TrySendError::Full(alert)) => {
+                self.cap_hits.hit(Cap::Alerts);
+                debug!(alert = %alert, "Alert queue full, dropping alert");
+            }
+            Err(TrySendError::Closed(alert)) => {
+                debug!(alert = %alert, "Notifier stopped, dropping alert")
+            }
+        }
+    }
+
+    // Alert when any tuned param moved by at least the configured share
+    pub fn params_changed(&self, before: &StrategyParams, after: &StrategyParams) {
+        let changes = param_changes(before, after);
+        if changes
+            .iter()
+            .any(|change| change.relative() >= self.config.param_change)
+        {
+            self.notify(Alert::ParamsChanged { changes });
+        }
+    }
+}
+
+async fn deliver_alerts(
+    receiver: Receiver<Alert>,
+    url: String,
+    service: WebhookService,
+    config: NotifyConfig,
+) {
+    let mut limiter = AlertLimiter::default();
+    let started = Instant::now();
+    let timeout = Duration::from_secs_f64(config.timeout_secs);
+    while let Ok(alert) = receiver.recv().await {
+        if !limiter.allow(
+            alert.kind(),
+            started.elapsed().as_secs_f64(),
+            config.min_interval_secs,
+        ) {
+            debug!(kind = ?alert.kind(), "Alert rate limited, dropping");
+            continue;
+        }
+        let text = alert.to_string();
+        let body = service.payload(&text);
+        let url = url.clone();
+        // ureq blocks, so it gets a thread of its own rather than stalling the executor
+        let result = rt::spawn_blocking(move || post(&url, body, timeout)).await;
+        match result {
+            Ok(()) => debug!(kind = ?alert.kind(), "Alert delivered"),
+            Err(e) => {
+                warn!(kind = ?alert.kind(), error = %e, alert = %text, "Error delivering alert, dropping it")
+            }
+        }
+    }
+}
+
+fn post(url: &str, body: Value, timeout: Duration) -> Result<(), String> {
+    ureq::post(url)
+        .timeout(timeout)
+        .send_json(body)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,447 @@
+use futures::future::{self, Either};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+
+use crate::clock::Monotonic;
+use crate::history::RunningStats;
+use crate::journal::Journal;
+use crate::notify::Notifier;
+use crate::persist::{save_params, PersistConfig, SavedParams};
+use crate::rt;
+use crate::shutdown::Shutdown;
+use crate::state::{PerformanceData, SharedState, StrategyParams};
+use crate::strategy::{
+    clamp_outliers, correlation, decayed_stats, forecast_signal, momentum_signal, OptimizerConfig,
+    MIN_OPTIMIZER_SAMPLES,
+};
+
+// Connections below this win rate have their aggressive_factor reduced
+const LOW_WIN_RATE: f64 = 0.4;
+const MIN_EVALUATED_TRADES: usize = 5;
+// Shortest wait between optimizer wake-ups, while there isn't enough history yet
+const MIN_OPTIMIZER_WAIT_SECS: f64 = 1.0;
+// A signal's correlation counts as at least this, so neither weight goes to zero
+const MIN_SIGNAL_CORRELATION: f64 = 0.01;
+// Whatever an optimization asks for, each weight stays in its range and the two sum
+// to 1, and the aggressive factor stays in its own
+pub use crate::state::{MAX_AGGRESSIVE_FACTOR, MAX_WEIGHT, MIN_AGGRESSIVE_FACTOR, MIN_WEIGHT};
+// Changes smaller than this are rounding in the renormalizing, not worth a warning
+const BOUND_TOLERANCE: f64 = 1e-9;
+
+// Move the params according to how the window scored
+pub fn adjust_params(
+    params: &mut StrategyParams,
+    performances: &[PerformanceData],
+    sharpe: f64,
+    optimizer: &OptimizerConfig,
+) {
+    if sharpe >= optimizer.good_sharpe {
+        // Strategy is working well. Weigh each signal by how well it called the moves
+        // the PnL changes came from, each change taken as if long so a signal that got
+        // the direction right correlates whichever side it put us on.
+        let (momentum, forecast): (Vec<_>, Vec<_>) = performances
+            .iter()
+            .filter(|p| p.position != 0)
+            .map(|p| {
+                let move_up = p.pnl_change * f64::from(p.position.signum());
+                (
+                    (momentum_signal(p.momentum, params), move_up),
+                    (forecast_signal(p.forecast, params), move_up),
+                )
+            })
+            .unzip();
+        let momentum_corr = correlation(&momentum);
+        let forecast_corr = correlation(&forecast);
+
+        // Update weights if either signal varied enough to correlate
+        if momentum_corr.is_some() || forecast_corr.is_some() {
+            let momentum_part = momentum_corr.unwrap_or(0.0).max(MIN_SIGNAL_CORRELATION);
+            let forecast_part = forecast_corr.unwrap_or(0.0).max(MIN_SIGNAL_CORRELATION);
+            let total = momentum_part + forecast_part;
+
+            params.momentum_weight = momentum_part / total;
+            params.forecast_weight = forecast_part / total;
+        }
+        params.aggressive_factor = f64::min(2.0, params.aggressive_factor + 0.1);
+    } else if sharpe <= optimizer.bad_sharpe {
+        // Strategy is losing money
+        params.momentum_weight = 0.5;
+        params.forecast_weight = 0.5;
+        params.aggressive_factor = f64::max(1.0, params.aggressive_factor - 0.2);
+    }
+}
+
+// Momentum's share of the weights once each is in range, None when they aren't numbers
+fn momentum_share(params: &StrategyParams) -> Option<f64> {
+    let momentum = params.momentum_weight;
+    let forecast = params.forecast_weight;
+    if !(momentum.is_finite() && forecast.is_finite()) {
+        return None;
+    }
+    let momentum = momentum.clamp(MIN_WEIGHT, MAX_WEIGHT);
+    let forecast = forecast.clamp(MIN_WEIGHT, MAX_WEIGHT);
+    Some(momentum / (momentum + forecast))
+}
+
+// Hold what an optimization wrote to the invariants: the weights in range, summing to 1
+// and at most `max_weight_step` from where they were, and the aggressive factor in
+// range. Whatever had to be held back is logged with what was attempted. A weak signal
+// landing on the floor, or a big move held to the step, is routine and only debug; a
+// weight that isn't a number, or params that were out of range to start with, warn.
+pub fn bound_update(before: &StrategyParams, params: &mut StrategyParams, max_weight_step: f64) {
+    if params == before {
+        return;
+    }
+
+    let previous = momentum_share(before).unwrap_or(0.5);
+    let momentum = momentum_share(params)
+        .unwrap_or(previous)
+        .clamp(previous - max_weight_step, previous + max_weight_step)
+        .clamp(MIN_WEIGHT, MAX_WEIGHT);
+    let forecast = 1.0 - momentum;
+    let numbers = params.momentum_weight.is_finite() && params.forecast_weight.is_finite();
+    let clamped = !numbers
+        || (momentum - params.momentum_weight).abs() > BOUND_TOLERANCE
+        || (forecast - params.forecast_weight).abs() > BOUND_TOLERANCE;
+    if clamped && (!numbers || before.within_bounds().is_err()) {
+        warn!(
+            attempted_momentum_weight = params.momentum_weight,
+            attempted_forecast_weight = params.forecast_weight,
+            momentum_weight = momentum,
+            forecast_weight = forecast,
+            max_weight_step,
+            "Optimizer weights out of bounds, clamped"
+        );
+    } else if clamped {
+        debug!(
+            attempted_momentum_weight = params.momentum_weight,
+            attempted_forecast_weight = params.forecast_weight,
+            momentum_weight = momentum,
+            forecast_weight = forecast,
+            max_weight_step,
+            "Optimizer weights held to their bounds"
+        );
+    }
+    params.momentum_weight = momentum;
+    params.forecast_weight = forecast;
+
+    let attempted = params.aggressive_factor;
+    let factor = if attempted.is_finite() {
+        attempted
+    } else {
+        before.aggressive_factor
+    }
+    .clamp(MIN_AGGRESSIVE_FACTOR, MAX_AGGRESSIVE_FACTOR);
+    if factor != attempted {
+        warn!(
+            attempted,
+            aggressive_factor = factor,
+            "Optimizer aggressive_factor out of bounds, clamped"
+        );
+        params.aggressive_factor = factor;
+    }
+}
+
+// Strategy optimization. Returns true when the global params were adjusted.
+pub async fn optimize_strategy(shared_state: &SharedState) -> bool {
+    // Check if it's time to optimize
+    let current_time = shared_state.monotonic();
+    let last_opt = *shared_state.last_optimization.read().await;
+    if current_time - last_opt < shared_state.optimization_interval {
+        return false;
+    }
+    // The window this close to a finish is mostly the game that's ending, so its passes
+    // wait for the one over the whole game after it
+    let freeze_fraction = shared_state.optimizer.freeze_fraction;
+    if freeze_fraction > 0.0 && shared_state.in_final_stretch(freeze_fraction).await {
+        debug!(
+            freeze_fraction,
+            "Near the end of the game, holding the params"
+        );
+        return false;
+    }
+    optimize_window(shared_state, None).await
+}
+
+// One pass over a finished game's data, whenever the last one ran. Every connection in
+// the game asks at its finish, and only the first to ask since the game started runs
+// it. Returns the params before and after when they were adjusted.
+pub async fn optimize_after_game(
+    shared_state: &SharedState,
+    started: Monotonic,
+) -> Option<(StrategyParams, StrategyParams)> {
+    {
+        let mut last_pass = shared_state.last_game_optimization.lock().await;
+        if last_pass.is_some_and(|last_pass| last_pass >= started) {
+            return None;
+        }
+        *last_pass = Some(shared_state.monotonic());
+    }
+    let before = shared_state.strategy_params.read().await.clone();
+    if !optimize_window(shared_state, Some(started)).await {
+        return None;
+    }
+    let after = shared_state.strategy_params.read().await.clone();
+    Some((before, after))
+}
+
+// Pass on adjusted params: alert on them, journal them and, given somewhere to, keep
+// them for the next run
+pub async fn publish_params(
+    shared_state: &SharedState,
+    persist: Option<&PersistConfig>,
+    notifier: Option<&Notifier>,
+    journal: Option<&Journal>,
+    before: &StrategyParams,
+    after: &StrategyParams,
+) {
+    if let Some(notifier) = notifier {
+        notifier.params_changed(before, after);
+    }
+    if let Some(journal) = journal {
+        journal.params_changed(shared_state.now(), before, after);
+    }
+    if let Some(persist) = persist.filter(|persist| persist.enabled) {
+        let saved = SavedParams::snapshot(shared_state).await;
+        if let Err(e) = save_params(&persist.path, &saved).await {
+            error!(path = %persist.path.display(), error = %e, "Error saving params");
+        }
+    }
+}
+
+// Score the window and adjust the params, over only what came in from `since` on when
+// it's given
+async fn optimize_window(shared_state: &SharedState, since: Option<Monotonic>) -> bool {
+    let current_time = shared_state.monotonic();
+    let optimizer = &shared_state.optimizer;
+    let in_window = |perf: &PerformanceData| since.is_none_or(|since| perf.timestamp >= since);
+    {
+        // Check if we have enough data, not counting any too old to go on
+        let mut perf_history = shared_state.performance_history.lock().await;
+        perf_history.retain(|perf| current_time - perf.timestamp <= optimizer.max_age_secs);
+        let samples = match since {
+            Some(_) => perf_history.iter().filter(|perf| in_window(perf)).count(),
+            None => perf_history.stats().count,
+        };
+        if samples < MIN_OPTIMIZER_SAMPLES {
+            return false;
+        }
+    }
+
+    // Update optimization timestamp
+    *shared_state.last_optimization.write().await = current_time;
+
+    // With arms configured, drawing them again stands in for nudging the weights
+    {
+        let mut bandit = shared_state.bandit.lock().await;
+        if bandit.is_enabled() {
+            for (arm, stats) in bandit.posteriors() {
+                info!(
+                    %arm,
+                    samples = stats.count,
+                    mean_pnl_change = stats.mean,
+                    wins = stats.wins,
+                    "Strategy arm so far"
+                );
+            }
+            for (conn_id, arm) in bandit.redraw() {
+                info!(conn_id, %arm, "Drew a strategy arm");
+            }
+            return true;
+        }
+    }
+
+    // The window's running stats, and the entries themselves for the age weighting and
+    // the correlation analysis
+    let (stats, mut performances): (RunningStats, Vec<PerformanceData>) = {
+        let history = shared_state.performance_history.lock().await;
+        let window: Vec<&PerformanceData> = history.iter().filter(|p| in_window(p)).collect();
+        let finite: Vec<PerformanceData> = window
+            .iter()
+            .filter(|p| p.is_finite())
+            .map(|&p| p.clone())
+            .collect();
+        if finite.len() < window.len() {
+            warn!(
+                skipped = window.len() - finite.len(),
+                "Skipping non-finite performance data"
+            );
+        }
+        let stats = match since {
+            Some(_) => {
+                let mut stats = RunningStats::default();
+                for p in &finite {
+                    stats.add(p.pnl_change);
+                }
+                stats
+            }
+            None => *history.stats(),
+        };
+        (stats, finite)
+    };
+
+    // One huge change, from a puzzle windfall or a bad baseline, would otherwise swamp
+    // the rest. Only this copy is clamped; the journal keeps what really happened.
+    if optimizer.outlier_mads > 0.0 {
+        let mut changes: Vec<f64> = performances.iter().map(|p| p.pnl_change).collect();
+        let clamped = clamp_outliers(&mut changes, optimizer.outlier_mads);
+        if clamped > 0 {
+            let largest = performances
+                .iter()
+                .map(|p| p.pnl_change)
+                .max_by(|a, b| a.abs().total_cmp(&b.abs()));
+            info!(
+                clamped,
+                largest,
+                outlier_mads = optimizer.outlier_mads,
+                "Clamped outlying PnL changes before optimizing"
+            );
+            for (p, change) in performances.iter_mut().zip(changes) {
+                p.pnl_change = change;
+            }
+        }
+    }
+
+    // Risk-adjusted return, so a few lucky swings don't read as a working strategy.
+    // Recent changes weigh most, so a new regime shows through quickly.
+    let pnl_changes: Vec<(f64, f64)> = performances
+        .iter()
+        .map(|p| (current_time - p.timestamp, p.pnl_change))
+        .collect();
+    let forecast = shared_state.forecast_accuracy().await;
+    let sharpe =
+        decayed_stats(&pnl_changes, optimizer.half_life_secs).and_then(|stats| stats.sharpe);
+    let optimized = match sharpe {
+        Some(sharpe) => {
+            let mut explorer = shared_state.explorer.lock().await;
+            let mut params = shared_state.strategy_params.write().await;
+            let before = params.clone();
+            // A cycle after exploring only judges the change, so what's kept or
+            // reverted isn't mixed up with a fresh update
+            let explored = if let Some(exploration) = explorer.take_pending() {
+                if sharpe < exploration.sharpe_before {
+                    exploration.revert(&mut params);
+                    info!(
+                        exploratory = true,
+                        param = exploration.param,
+                        reverted_to = exploration.from,
+                        sharpe,
+                        sharpe_before = exploration.sharpe_before,
+                        "Sharpe got worse, reverting exploratory change"
+                    );
+                } else {
+                    info!(
+                        exploratory = true,
+                        param = exploration.param,
+                        kept = exploration.to,
+                        sharpe,
+                        sharpe_before = exploration.sharpe_before,
+                        "Sharpe held up, keeping exploratory change"
+                    );
+                }
+                true
+            } else if explorer.roll() {
+                let exploration = explorer.explore(&mut params, sharpe);
+                info!(
+                    exploratory = true,
+                    param = exploration.param,
+                    from = exploration.from,
+                    to = exploration.to,
+                    sharpe,
+                    "Optimized strategy parameters"
+                );
+                true
+            } else {
+                adjust_params(&mut params, &performances, sharpe, optimizer);
+                false
+            };
+            // Every way an optimization moves the params lands here, so none of them
+            // gets past the bounds
+            bound_update(&before, &mut params, optimizer.max_weight_step);
+            if explored {
+                return true;
+            }
+            info!(
+                exploratory = false,
+                sharpe,
+                samples = stats.count,
+                mean_pnl_change = stats.mean,
+                win_rate = stats.win_rate(),
+                momentum_weight = params.momentum_weight,
+                forecast_weight = params.forecast_weight,
+                forecast_scale = forecast.weight_scale(&shared_state.forecast),
+                forecast_hit_rate = forecast.hit_rate,
+                forecast_correlation = forecast.correlation,
+                aggressive_factor = params.aggressive_factor,
+                "Optimized strategy parameters"
+            );
+            true
+        }
+        None => {
+            debug!("No PnL variance in the performance window, skipping optimization");
+            false
+        }
+    };
+
+    // Connections that keep losing get less aggressive on their own
+    let global_factor = shared_state.strategy_params.read().await.aggressive_factor;
+    let mut connections = shared_state.connection_performance.lock().await;
+    for (conn_id, perf) in connections.iter_mut() {
+        if perf.evaluated_trades < MIN_EVALUATED_TRADES {
+            continue;
+        }
+        if let Some(win_rate) = perf.win_rate() {
+            if win_rate < LOW_WIN_RATE {
+                let factor = perf.aggressive_factor.unwrap_or(global_factor);
+                let reduced = f64::max(1.0, factor - 0.2);
+                perf.aggressive_factor = Some(reduced);
+                info!(
+                    conn_id,
+                    win_rate,
+                    aggressive_factor = reduced,
+                    "Low win rate, reducing aggressive_factor"
+                );
+            }
+        }
+    }
+    optimized
+}
+
+// Optimize on a timer, off the trade path, keeping what it learns for the next run.
+// Connections only ever read the params. Runs until shutdown.
+pub async fn run_optimizer(
+    shared_state: Arc<SharedState>,
+    persist: PersistConfig,
+    notifier: Option<Notifier>,
+    journal: Option<Journal>,
+    shutdown: Shutdown,
+) {
+    loop {
+        let due = *shared_state.last_optimization.read().await + shared_state.optimization_interval;
+        let wait = (due - shared_state.monotonic()).max(MIN_OPTIMIZER_WAIT_SECS);
+        let woken = future::select(
+            Box::pin(rt::sleep(Duration::from_secs_f64(wait))),
+            Box::pin(shutdown.wait()),
+        )
+        .await;
+        if let Either::Right(_) = woken {
+            return;
+        }
+
+        let before = shared_state.strategy_params.read().await.clone();
+        if !optimize_strategy(&shared_state).await {
+            continue;
+        }
+        let after = shared_state.strategy_params.read().await.clone();
+        publish_params(
+            &shared_state,
+            Some(&persist),
+            notifier.as_ref(),
+            journal.as_ref(),
+            &before,
+            &after,
+        )
+        .await;
+    }
+}
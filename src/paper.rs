@@ -0,0 +1,55 @@
+use serde::Deserialize;
+
+// What a fill is assumed to cost beyond the price: a fee per unit traded, plus half the
+// bid-ask spread crossed to get it. The reported PnL doesn't know about either, so
+// they're taken off the changes credited to our own trades.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct CostConfig {
+    pub fee_per_unit: f64,
+    pub half_spread: f64,
+}
+
+impl CostConfig {
+    // Dollars a trade of this volume costs, either way
+    pub fn cost(&self, volume: i32) -> f64 {
+        volume.unsigned_abs() as f64 * (self.fee_per_unit + self.half_spread)
+    }
+}
+
+// Shadow book for dry runs: trades fill immediately at the last reported price and
+// PnL is marked against the latest price, so nothing has to be sent to the server.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PaperBook {
+    pub position: i32,
+    // Cash spent (negative) or received (positive) by fills so far
+    pub cash: f64,
+    pub last_price: Option<f64>,
+    pub fills: usize,
+}
+
+impl PaperBook {
+    pub fn mark(&mut self, price: f64) {
+        self.last_price = Some(price);
+    }
+
+    // Fill at the last marked price. Returns false when there's no price to fill at yet.
+    pub fn fill(&mut self, volume: i32) -> bool {
+        let Some(price) = self.last_price else {
+            return false;
+        };
+        self.position += volume;
+        self.cash -= volume as f64 * price;
+        self.fills += 1;
+        true
+    }
+
+    // Mark-to-market PnL at the last price
+    pub fn pnl(&self) -> f64 {
+        self.cash + self.position as f64 * self.last_price.unwrap_or(0.0)
+    }
+
+    pub fn reset(&mut self) {
+        *self = PaperBook::default();
+    }
+}
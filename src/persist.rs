@@ -0,0 +1,118 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::bandit::ArmStats;
+use crate::rt::fs;
+use crate::state::{SharedState, StrategyParams};
+
+// Where the optimized params are kept between runs
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PersistConfig {
+    pub enabled: bool,
+    pub path: PathBuf,
+    // Saved params older than this are ignored at startup
+    pub max_age_secs: f64,
+}
+
+impl Default for PersistConfig {
+    fn default() -> Self {
+        PersistConfig {
+            enabled: true,
+            path: PathBuf::from("params.json"),
+            max_age_secs: 24.0 * 60.0 * 60.0,
+        }
+    }
+}
+
+// The performance window the params were last judged on
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WindowStats {
+    pub samples: usize,
+    pub mean_pnl_change: f64,
+    pub sharpe: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SavedParams {
+    pub saved_at: f64,
+    pub params: StrategyParams,
+    pub stats: WindowStats,
+    // How each bandit arm has paid, by name; empty without arms
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub arms: BTreeMap<String, ArmStats>,
+}
+
+impl SavedParams {
+    // Current global params and the window behind them
+    pub async fn snapshot(shared_state: &SharedState) -> SavedParams {
+        let stats = shared_state.performance_stats().await;
+        SavedParams {
+            saved_at: shared_state.now(),
+            params: shared_state.strategy_params.read().await.clone(),
+            stats: WindowStats {
+                samples: stats.count,
+                mean_pnl_change: stats.mean,
+                sharpe: stats.sharpe(),
+            },
+            arms: shared_state.bandit.lock().await.posteriors(),
+        }
+    }
+
+    pub fn is_stale(&self, now: f64, max_age_secs: f64) -> bool {
+        now - self.saved_at > max_age_secs
+    }
+}
+
+// Written to a temporary file first so a crash mid-write can't leave a partial file
+pub async fn save_params(path: &Path, saved: &SavedParams) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(saved).expect("saved params always serialize");
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    fs::write(&temporary, json).await?;
+    fs::rename(&temporary, path).await
+}
+
+pub fn load_params(path: &Path) -> io::Result<SavedParams> {
+    let text = std::fs::read_to_string(path)?;
+    serde_json::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// Saved params to start from, or None for the configured defaults. A missing, stale or
+// unreadable file, or params outside the bounds the optimizer keeps to, are logged and
+// ignored rather than stopping startup.
+pub fn restore_params(config: &PersistConfig, now: f64) -> Option<SavedParams> {
+    let path = config.path.display();
+    match load_params(&config.path) {
+        Ok(saved) if saved.is_stale(now, config.max_age_secs) => {
+            info!(%path, saved_at = saved.saved_at, "Saved params are stale, using defaults");
+            None
+        }
+        Ok(saved) => match saved.params.within_bounds() {
+            Err(e) => {
+                warn!(%path, error = %e, "Saved params out of bounds, using defaults");
+                None
+            }
+            Ok(()) => {
+                info!(
+                    %path,
+                    saved_at = saved.saved_at,
+                    sharpe = saved.stats.sharpe,
+                    momentum_weight = saved.params.momentum_weight,
+                    forecast_weight = saved.params.forecast_weight,
+                    aggressive_factor = saved.params.aggressive_factor,
+                    "Restored strategy params"
+                );
+                Some(saved)
+            }
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => {
+            warn!(%path, error = %e, "Ignoring saved params, using defaults");
+            None
+        }
+    }
+}
@@ -0,0 +1,404 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+pub mod outgoing;
+
+pub use outgoing::{
+    AnswerData, ClientEvent, ClientMessage, ConnectionData, SkipData, StartData, TradeData,
+};
+
+// Events received from the game server
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", content = "data", rename_all = "lowercase")]
+pub enum ServerEvent {
+    Connection(ConnectionAck),
+    State(StateUpdate),
+    Puzzle(PuzzleData),
+    Finish(FinishData),
+    Error(ServerError),
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConnectionAck {
+    pub player_id: String,
+    // Handed out by servers that authenticate, to be sent back with everything after
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<Token>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+}
+
+impl ConnectionAck {
+    pub fn accepting(player_id: &str) -> Self {
+        ConnectionAck {
+            player_id: player_id.to_string(),
+            token: None,
+            session_id: None,
+        }
+    }
+
+    // What to authenticate the rest of the session with, if the server gave anything
+    pub fn session(&self) -> Option<Session> {
+        match (&self.token, &self.session_id) {
+            (None, None) => None,
+            (token, session_id) => Some(Session {
+                token: token.clone().unwrap_or_default(),
+                session_id: session_id.clone(),
+            }),
+        }
+    }
+}
+
+// An auth token. Goes over the wire as it is but is masked wherever it's printed.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Token(String);
+
+impl Token {
+    pub fn new(token: &str) -> Self {
+        Token(token.to_string())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // Enough of a long token to tell two apart, none of a short one
+    pub fn masked(&self) -> String {
+        match self.0.char_indices().nth(4) {
+            _ if self.0.is_empty() => String::new(),
+            Some((at, _)) if self.0.len() > 8 => format!("{}****", &self.0[..at]),
+            _ => "****".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.masked())
+    }
+}
+
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Token({})", self.masked())
+    }
+}
+
+// Credentials from the connection ack, kept per connection for its later messages
+// and the next handshake
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub token: Token,
+    pub session_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StateUpdate {
+    pub price: f64,
+    pub price_forecast: f64,
+    pub momentum: f64,
+    pub position: i32,
+    #[serde(default = "default_position_limit")]
+    pub position_limit: i32,
+    pub pnl: f64,
+    // Not sent by every version of the game; counted against wind_down.game_length otherwise
+    #[serde(
+        default,
+        alias = "ticks_remaining",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub updates_remaining: Option<u32>,
+    // Only sent when the game has more than one instrument, and sent back with trades
+    #[serde(default, alias = "symbol", skip_serializing_if = "Option::is_none")]
+    pub instrument: Option<String>,
+    // Which stage the game is in, from servers that say
+    #[serde(
+        default,
+        alias = "status",
+        alias = "phase",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub stage: Option<String>,
+    // Counts up with each update, from servers that number them
+    #[serde(
+        default,
+        alias = "seq",
+        alias = "tick",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub sequence: Option<u64>,
+}
+
+// A position further past the limit than this many times over is a garbled update
+pub const MAX_POSITION_LIMIT_MULTIPLE: i64 = 10;
+
+// A state update with a number the math can't be trusted with
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidState {
+    pub field: &'static str,
+    pub reason: String,
+}
+
+impl fmt::Display for InvalidState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` {}", self.field, self.reason)
+    }
+}
+
+impl std::error::Error for InvalidState {}
+
+impl StateUpdate {
+    // NaN or infinite floats would run through the signal and into the optimizer's
+    // stats for good, so they're turned away along with impossible prices and positions
+    pub fn validate(&self) -> Result<(), InvalidState> {
+        let invalid = |field, reason: String| Err(InvalidState { field, reason });
+        for (field, value) in [
+            ("price", self.price),
+            ("price_forecast", self.price_forecast),
+            ("momentum", self.momentum),
+            ("pnl", self.pnl),
+        ] {
+            if !value.is_finite() {
+                return invalid(field, format!("is {}", value));
+            }
+        }
+        if self.price <= 0.0 {
+            return invalid("price", format!("is {}, not positive", self.price));
+        }
+        if self.position_limit <= 0 {
+            return invalid(
+                "position_limit",
+                format!("is {}, not positive", self.position_limit),
+            );
+        }
+        let max_position = i64::from(self.position_limit) * MAX_POSITION_LIMIT_MULTIPLE;
+        if i64::from(self.position).abs() > max_position {
+            return invalid(
+                "position",
+                format!("is {}, past ±{}", self.position, max_position),
+            );
+        }
+        Ok(())
+    }
+
+    // Equal for the same update sent twice. The sequence number says it all when the
+    // server sends one; otherwise every field goes in.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        match self.sequence {
+            Some(sequence) => sequence.hash(&mut hasher),
+            None => {
+                for value in [self.price, self.price_forecast, self.momentum, self.pnl] {
+                    value.to_bits().hash(&mut hasher);
+                }
+                self.position.hash(&mut hasher);
+                self.position_limit.hash(&mut hasher);
+                self.updates_remaining.hash(&mut hasher);
+                self.instrument.hash(&mut hasher);
+                self.stage.hash(&mut hasher);
+            }
+        }
+        hasher.finish()
+    }
+}
+
+// The game has always used a limit of 3 when it doesn't say otherwise
+fn default_position_limit() -> i32 {
+    3
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PuzzleData {
+    #[serde(default)]
+    pub impact: Option<f64>,
+    #[serde(
+        default,
+        alias = "status",
+        alias = "phase",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub stage: Option<String>,
+    // The question some puzzles ask, for a solver to answer
+    #[serde(
+        default,
+        alias = "prompt",
+        alias = "expression",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub question: Option<String>,
+    // Sent back with the answer, from servers that tell puzzles apart
+    #[serde(default, alias = "puzzle_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct FinishData {
+    #[serde(default)]
+    pub pnl: Option<f64>,
+    // When the next game starts, from servers that say: seconds from now, or a unix time
+    #[serde(default, alias = "restart_in", skip_serializing_if = "Option::is_none")]
+    pub next_game_in: Option<f64>,
+    #[serde(
+        default,
+        alias = "restart_at",
+        alias = "next_game_starts_at",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub next_game_at: Option<f64>,
+}
+
+impl FinishData {
+    // Seconds until the next game starts, if the server gave a usable hint
+    pub fn next_game_after(&self, now: f64) -> Option<f64> {
+        self.next_game_in
+            .or(self.next_game_at.map(|at| at - now))
+            .filter(|secs| secs.is_finite())
+            .map(|secs| secs.max(0.0))
+    }
+}
+
+// The server's complaint about something we sent. Sent either as a bare string or
+// as an object with a message.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(from = "RawServerError")]
+pub struct ServerError {
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawServerError {
+    Text(String),
+    Detail {
+        #[serde(alias = "error", alias = "detail", alias = "reason")]
+        message: String,
+    },
+}
+
+impl From<RawServerError> for ServerError {
+    fn from(raw: RawServerError) -> Self {
+        match raw {
+            RawServerError::Text(message) | RawServerError::Detail { message } => {
+                ServerError { message }
+            }
+        }
+    }
+}
+
+// What a server error is about, going by its message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerErrorKind {
+    // The trade was too big for the position limit
+    Volume,
+    // The server doesn't accept us as a player
+    Auth,
+    // Sent at the wrong point in the game
+    Stage,
+    Other,
+}
+
+impl ServerError {
+    pub fn kind(&self) -> ServerErrorKind {
+        let message = self.message.to_lowercase();
+        let mentions = |words: &[&str]| words.iter().any(|word| message.contains(word));
+        if mentions(&["volume", "limit", "position"]) {
+            ServerErrorKind::Volume
+        } else if mentions(&["auth", "player", "token", "forbidden"]) {
+            ServerErrorKind::Auth
+        } else if mentions(&["stage", "not in trading"]) {
+            ServerErrorKind::Stage
+        } else {
+            ServerErrorKind::Other
+        }
+    }
+}
+
+// A server message that couldn't be turned into a ServerEvent
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub event: Option<String>,
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.event {
+            Some(event) => write!(
+                f,
+                "bad '{}' event at `{}`: {}",
+                event, self.field, self.message
+            ),
+            None => write!(f, "bad message at `{}`: {}", self.field, self.message),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Deserialize)]
+struct RawEvent {
+    event: String,
+    #[serde(default)]
+    data: Option<Value>,
+}
+
+impl ServerEvent {
+    // Parse a text frame, reporting which field was at fault when it doesn't match
+    pub fn parse(text: &str) -> Result<ServerEvent, ParseError> {
+        let raw = serde_path_to_error::deserialize::<_, RawEvent>(
+            &mut serde_json::Deserializer::from_str(text),
+        )
+        .map_err(|e| ParseError {
+            event: None,
+            field: e.path().to_string(),
+            message: e.inner().to_string(),
+        })?;
+
+        match raw.event.as_str() {
+            "connection" => parse_data(raw, ServerEvent::Connection),
+            "state" => parse_data(raw, ServerEvent::State),
+            "puzzle" => parse_data(raw, ServerEvent::Puzzle),
+            "finish" => parse_data(raw, ServerEvent::Finish),
+            "error" => parse_data(raw, ServerEvent::Error),
+            _ => Ok(ServerEvent::Unknown),
+        }
+    }
+}
+
+fn parse_data<T: DeserializeOwned>(
+    raw: RawEvent,
+    variant: fn(T) -> ServerEvent,
+) -> Result<ServerEvent, ParseError> {
+    let data = raw.data.ok_or_else(|| ParseError {
+        event: Some(raw.event.clone()),
+        field: "data".to_string(),
+        message: "missing field `data`".to_string(),
+    })?;
+
+    serde_path_to_error::deserialize(data)
+        .map(variant)
+        .map_err(|e| {
+            let path = e.path().to_string();
+            ParseError {
+                event: Some(raw.event),
+                field: if path == "." {
+                    "data".to_string()
+                } else {
+                    format!("data.{}", path)
+                },
+                message: e.inner().to_string(),
+            }
+        })
+}
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{Session, Token};
+
+// Messages sent to the game server
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ClientMessage {
+    pub player_id: String,
+    // Whatever the server handed out in its connection ack
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<Token>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    #[serde(flatten)]
+    pub event: ClientEvent,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", content = "data", rename_all = "lowercase")]
+pub enum ClientEvent {
+    Connection(ConnectionData),
+    Start(StartData),
+    Trade(TradeData),
+    Skip(SkipData),
+    Answer(AnswerData),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConnectionData {
+    pub alias: String,
+    pub player_id: String,
+    pub token: Token,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StartData {
+    pub player_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TradeData {
+    pub volume: i32,
+    // The instrument of the state update the trade came from, when it named one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instrument: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SkipData {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AnswerData {
+    pub answer: Value,
+    // The puzzle's id, when it came with one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Value>,
+}
+
+impl ClientMessage {
+    pub fn new(player_id: &str, event: ClientEvent) -> Self {
+        ClientMessage {
+            player_id: player_id.to_string(),
+            token: None,
+            session_id: None,
+            event,
+        }
+    }
+
+    // Sent with the session's credentials, when the server has given us any
+    pub fn authenticated(mut self, session: Option<&Session>) -> Self {
+        if let Some(session) = session {
+            self.token = Some(session.token.clone());
+            self.session_id = session.session_id.clone();
+        }
+        self
+    }
+
+    // A trade for the given instrument; other messages are left as they are
+    pub fn for_instrument(mut self, instrument: Option<&str>) -> Self {
+        if let ClientEvent::Trade(data) = &mut self.event {
+            data.instrument = instrument.map(str::to_string);
+        }
+        self
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("client messages always serialize")
+    }
+
+    // As to_json but with any token masked, for logging
+    pub fn to_log_json(&self) -> String {
+        let mut masked = self.clone();
+        let mask = |token: &mut Token| *token = Token::new(&token.masked());
+        if let Some(token) = &mut masked.token {
+            mask(token);
+        }
+        if let ClientEvent::Connection(data) = &mut masked.event {
+            mask(&mut data.token);
+        }
+        masked.to_json()
+    }
+}
+
+// The connection message that opens a session. The envelope's player_id stays empty
+// until the server has accepted us.
+pub fn connection(alias: &str, player_id: &str, token: &str) -> ClientMessage {
+    ClientMessage::new(
+        "",
+        ClientEvent::Connection(ConnectionData {
+            alias: alias.to_string(),
+            player_id: player_id.to_string(),
+            token: Token::new(token),
+        }),
+    )
+}
+
+// Sent once the server acknowledges the connection
+pub fn start(player_id: &str) -> ClientMessage {
+    ClientMessage::new(
+        "",
+        ClientEvent::Start(StartData {
+            player_id: player_id.to_string(),
+        }),
+    )
+}
+
+pub fn trade(player_id: &str, volume: i32) -> ClientMessage {
+    ClientMessage::new(
+        player_id,
+        ClientEvent::Trade(TradeData {
+            volume,
+            instrument: None,
+        }),
+    )
+}
+
+// Moves the game on to the next stage
+pub fn skip() -> ClientMessage {
+    ClientMessage::new("", ClientEvent::Skip(SkipData {}))
+}
+
+// Our answer to a puzzle's question, sent ahead of the skip
+pub fn answer(answer: Value, id: Option<Value>) -> ClientMessage {
+    ClientMessage::new("", ClientEvent::Answer(AnswerData { answer, id }))
+}
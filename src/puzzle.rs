@@ -0,0 +1,72 @@
+use crate::strategy::PuzzleImpact;
+
+// State updates to wait for the puzzle's move before unwinding anyway
+pub const PUZZLE_MAX_HOLD_UPDATES: usize = 5;
+// Share of the announced move that counts as the impact having happened
+pub const IMPACT_REALIZED_FRACTION: f64 = 0.5;
+
+// Position taken on a puzzle, held until its price impact shows up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeldPuzzle {
+    pub direction: i32,
+    pub magnitude: f64,
+    pub entry_price: Option<f64>,
+    pub updates_seen: usize,
+}
+
+// Per-connection puzzle position: go to the limit on the puzzle, unwind once it plays out
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PuzzleTracker {
+    pub held: Option<HeldPuzzle>,
+}
+
+impl PuzzleTracker {
+    pub fn is_holding(&self) -> bool {
+        self.held.is_some()
+    }
+
+    // Volume to trade on a new puzzle, moving to the limit in the impact direction
+    pub fn on_puzzle(
+        &mut self,
+        impact: PuzzleImpact,
+        position: i32,
+        position_limit: i32,
+        price: Option<f64>,
+    ) -> i32 {
+        let volume = impact.direction * position_limit - position;
+        // Already at the limit, so there's nothing extra to hold or unwind
+        if volume == 0 {
+            return 0;
+        }
+
+        self.held = Some(HeldPuzzle {
+            direction: impact.direction,
+            magnitude: impact.magnitude,
+            entry_price: price,
+            updates_seen: 0,
+        });
+        volume
+    }
+
+    // While holding, returns the volume that replaces the strategy's decision for this
+    // update: an unwind to flat once the impact is realized, otherwise whatever keeps
+    // us at the (possibly changed) limit.
+    pub fn on_state(&mut self, price: f64, position: i32, position_limit: i32) -> Option<i32> {
+        let held = self.held.as_mut()?;
+        held.updates_seen += 1;
+
+        let entry_price = *held.entry_price.get_or_insert(price);
+        let moved = (price - entry_price) * held.direction as f64;
+        let realized = moved >= held.magnitude * IMPACT_REALIZED_FRACTION;
+
+        if realized || held.updates_seen >= PUZZLE_MAX_HOLD_UPDATES {
+            self.held = None;
+            return Some(-position);
+        }
+        Some(held.direction * position_limit - position)
+    }
+
+    pub fn reset(&mut self) {
+        self.held = None;
+    }
+}
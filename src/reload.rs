@@ -0,0 +1,256 @@
+use futures::future::{self, Either};
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::{error, info, warn};
+
+use crate::config::{Config, ConfigError};
+use crate::control::changed_params;
+use crate::rt;
+use crate::shutdown::Shutdown;
+use crate::state::{SharedState, StrategyParams};
+
+// Top-level settings a reload applies to the running bot. A change anywhere else
+// needs a restart.
+pub const LIVE_SETTINGS: &[&str] = &["strategy", "rate_limit", "risk", "log_level"];
+
+// Watch the config file and apply what can change without reconnecting
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReloadConfig {
+    pub enabled: bool,
+    // How often the file's modified time is checked
+    pub poll_secs: f64,
+}
+
+impl Default for ReloadConfig {
+    fn default() -> Self {
+        ReloadConfig {
+            enabled: true,
+            poll_secs: 3.0,
+        }
+    }
+}
+
+// Swaps the log filter for one in RUST_LOG syntax, or back to the one from startup
+pub type SetLogFilter = Box<dyn Fn(Option<&str>) -> Result<(), String> + Send + Sync>;
+
+// One setting that differs between the file as it was and as it is, dotted from the
+// top of the file, e.g. "rate_limit.burst". Values are as written in TOML.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingChange {
+    pub setting: String,
+    pub old: String,
+    pub new: String,
+}
+
+impl SettingChange {
+    pub fn is_live(&self) -> bool {
+        let section = self.setting.split('.').next().unwrap_or_default();
+        LIVE_SETTINGS.contains(&section)
+    }
+}
+
+// What a reload did with each change it found
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Reload {
+    pub applied: Vec<SettingChange>,
+    pub requires_restart: Vec<SettingChange>,
+}
+
+// Re-reads the config file when it changes and applies the live settings to every
+// account. A file that doesn't load or validate changes nothing.
+pub struct Reloader {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    // The file as last applied, to tell what a new version changes
+    file: toml::Value,
+    loaded: Config,
+    // Each account's running config and state
+    accounts: Vec<(Arc<Config>, Arc<SharedState>)>,
+    log_filter: Option<SetLogFilter>,
+}
+
+impl Reloader {
+    pub fn new(
+        path: &Path,
+        accounts: &[(Arc<Config>, Arc<SharedState>)],
+    ) -> Result<Reloader, ConfigError> {
+        let modified = modified(path);
+        let (file, loaded) = read(path)?;
+        Ok(Reloader {
+            path: path.to_path_buf(),
+            modified,
+            file,
+            loaded,
+            accounts: accounts.to_vec(),
+            log_filter: None,
+        })
+    }
+
+    pub fn with_log_filter(mut self, log_filter: SetLogFilter) -> Self {
+        self.log_filter = Some(log_filter);
+        self
+    }
+
+    // Reload if the file's modified time has moved since the last look
+    pub async fn check(&mut self) -> Option<Result<Reload, ConfigError>> {
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+        // A broken file is reported once, not on every poll until it's fixed
+        self.modified = modified;
+        Some(self.reload().await)
+    }
+
+    pub async fn reload(&mut self) -> Result<Reload, ConfigError> {
+        let (file, loaded) = read(&self.path)?;
+        let mut changes = Vec::new();
+        diff("", Some(&self.file), Some(&file), &mut changes);
+        let (applied, requires_restart): (Vec<_>, Vec<_>) =
+            changes.into_iter().partition(SettingChange::is_live);
+
+        let params_changed = changed_params(&self.loaded.strategy, &loaded.strategy);
+        for (running, shared_state) in self.accounts.iter_mut() {
+            let mut next = Config::clone(running);
+            next.strategy = loaded.strategy.clone();
+            next.rate_limit = loaded.rate_limit.clone();
+            next.risk = loaded.risk.clone();
+            next.log_level = loaded.log_level.clone();
+            *running = Arc::new(next);
+            *shared_state.reloaded_config.write().await = Some(Arc::clone(running));
+            if !params_changed.is_empty() {
+                apply_params(&running.account, shared_state, &params_changed).await;
+            }
+        }
+        if applied.iter().any(|change| change.setting == "log_level") {
+            if let Some(set_log_filter) = &self.log_filter {
+                if let Err(e) = set_log_filter(loaded.log_level.as_deref()) {
+                    warn!(error = %e, "Error changing the log filter");
+                }
+            }
+        }
+
+        // Strategy params are logged per account, against what they'd got to
+        for change in applied
+            .iter()
+            .filter(|change| !change.setting.starts_with("strategy."))
+        {
+            info!(
+                setting = change.setting,
+                old = change.old,
+                new = change.new,
+                "Config change applied"
+            );
+        }
+        for change in &requires_restart {
+            warn!(
+                setting = change.setting,
+                old = change.old,
+                new = change.new,
+                "Config change requires a restart, not applied"
+            );
+        }
+        self.file = file;
+        self.loaded = loaded;
+        Ok(Reload {
+            applied,
+            requires_restart,
+        })
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn read(path: &Path) -> Result<(toml::Value, Config), ConfigError> {
+    let text = std::fs::read_to_string(path).map_err(ConfigError::Io)?;
+    let config = Config::parse(&text)?;
+    let file = toml::from_str(&text).map_err(ConfigError::Parse)?;
+    Ok((file, config))
+}
+
+// Every setting that differs, tables walked key by key and anything else compared whole
+fn diff(
+    prefix: &str,
+    old: Option<&toml::Value>,
+    new: Option<&toml::Value>,
+    changes: &mut Vec<SettingChange>,
+) {
+    if let (Some(toml::Value::Table(old)), Some(toml::Value::Table(new))) = (old, new) {
+        let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+        keys.sort_unstable();
+        keys.dedup();
+        for key in keys {
+            let setting = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", prefix, key)
+            };
+            diff(&setting, old.get(key), new.get(key), changes);
+        }
+    } else if old != new {
+        let show =
+            |value: Option<&toml::Value>| value.map_or("unset".to_string(), |v| v.to_string());
+        changes.push(SettingChange {
+            setting: prefix.to_string(),
+            old: show(old),
+            new: show(new),
+        });
+    }
+}
+
+// Only the params the file changed are set, so the rest keep what the optimizer has
+// learned since
+async fn apply_params(
+    account: &str,
+    shared_state: &SharedState,
+    changed: &[(String, Value, Value)],
+) {
+    let mut params = shared_state.strategy_params.write().await;
+    let Ok(Value::Object(mut merged)) = serde_json::to_value(&*params) else {
+        return;
+    };
+    for (param, _, new) in changed {
+        merged.insert(param.clone(), new.clone());
+    }
+    let updated: StrategyParams = match serde_json::from_value(Value::Object(merged)) {
+        Ok(updated) => updated,
+        Err(e) => {
+            error!(account, error = %e, "Error applying reloaded strategy params");
+            return;
+        }
+    };
+    for (param, old, new) in changed_params(&params, &updated) {
+        info!(account, param, %old, %new, "Strategy param changed by config reload");
+    }
+    *params = updated;
+}
+
+// Check the file every poll_secs until shutdown
+pub async fn run_reloader(mut reloader: Reloader, poll: Duration, shutdown: Shutdown) {
+    loop {
+        let wait = Box::pin(rt::sleep(poll));
+        if let Either::Right(_) = future::select(wait, Box::pin(shutdown.wait())).await {
+            return;
+        }
+        match reloader.check().await {
+            Some(Ok(reload)) if reload.applied.is_empty() && reload.requires_restart.is_empty() => {
+                info!(path = %reloader.path.display(), "Config file touched, nothing changed")
+            }
+            Some(Ok(_)) => info!(path = %reloader.path.display(), "Config reloaded"),
+            Some(Err(e)) => error!(
+                path = %reloader.path.display(),
+                error = %e,
+                "New config not applied, still running the old one"
+            ),
+            None => {}
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::{info, info_span, Instrument};
+
+use crate::clock::ManualClock;
+use crate::config::Config;
+use crate::handler::ConnectionHandler;
+use crate::protocol::{ClientEvent, ClientMessage};
+use crate::state::SharedState;
+use crate::transcript::{read_transcript, Direction, TranscriptEntry};
+
+// Trades the current strategy would have made for one connection
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplayReport {
+    pub frames: usize,
+    pub trades: Vec<(f64, i32)>,
+    // Final PnL of each game the connection finished, as the server reported it (or
+    // the paper book, replaying as a dry run)
+    pub game_pnls: Vec<f64>,
+}
+
+pub async fn replay_file(
+    path: &Path,
+    config: Arc<Config>,
+) -> io::Result<BTreeMap<usize, ReplayReport>> {
+    let entries = read_transcript(path)?;
+    Ok(replay(&entries, config).await)
+}
+
+// Feed recorded inbound frames through the live handler, driving the clock from the
+// recorded timestamps. Outbound connection frames mark where a session started. Frames
+// labelled with another account than the config's are left out.
+pub async fn replay(
+    entries: &[TranscriptEntry],
+    config: Arc<Config>,
+) -> BTreeMap<usize, ReplayReport> {
+    let start = entries.first().map_or(0.0, |entry| entry.timestamp);
+    let clock = Arc::new(ManualClock::new(start));
+    let shared_state = Arc::new(SharedState::with_clock(&config, clock.clone()));
+
+    let mut handlers: BTreeMap<usize, ConnectionHandler> = BTreeMap::new();
+    let mut reports: BTreeMap<usize, ReplayReport> = BTreeMap::new();
+
+    let ours = |entry: &&TranscriptEntry| {
+        entry.account.is_empty() || config.account.is_empty() || entry.account == config.account
+    };
+    for entry in entries.iter().filter(ours) {
+        clock.set(entry.timestamp);
+        let conn_id = entry.conn_id;
+        let handler = match handlers.entry(conn_id) {
+            Entry::Occupied(slot) => slot.into_mut(),
+            Entry::Vacant(slot) => {
+                let mut handler =
+                    ConnectionHandler::new(conn_id, Arc::clone(&config), Arc::clone(&shared_state));
+                handler
+                    .start_session()
+                    .instrument(info_span!("connection", conn_id))
+                    .await;
+                slot.insert(handler)
+            }
+        };
+
+        match entry.direction {
+            Direction::Out => {
+                let opens_session = serde_json::from_str::<ClientMessage>(&entry.frame)
+                    .is_ok_and(|message| matches!(message.event, ClientEvent::Connection(_)));
+                if opens_session {
+                    handler
+                        .start_session()
+                        .instrument(info_span!("connection", conn_id))
+                        .await;
+                }
+            }
+            Direction::In => {
+                let mut outbox = Vec::new();
+                handler
+                    .handle_text(&entry.frame, &mut outbox)
+                    .instrument(info_span!("connection", conn_id))
+                    .await;
+
+                let report = reports.entry(conn_id).or_default();
+                report.frames += 1;
+                for message in outbox {
+                    if let ClientEvent::Trade(trade) = message.event {
+                        info!(
+                            conn_id,
+                            t = entry.timestamp - start,
+                            volume = trade.volume,
+                            "Replay would trade"
+                        );
+                        report.trades.push((entry.timestamp, trade.volume));
+                    }
+                }
+                if let Some(pnl) = handler.take_summary().and_then(|summary| summary.final_pnl) {
+                    report.game_pnls.push(pnl);
+                }
+            }
+        }
+    }
+
+    reports
+}
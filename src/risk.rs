@@ -0,0 +1,847 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+use crate::protocol::ClientEvent;
+
+// Drawdown limits, all optional so the breaker is off unless configured
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RiskConfig {
+    // Halt when PnL falls this many dollars below its peak
+    pub max_drawdown: Option<f64>,
+    // Halt when PnL falls this fraction below a positive peak (0.25 = 25%)
+    pub max_drawdown_pct: Option<f64>,
+    // Seconds to stay halted before trading resumes
+    pub cooldown_secs: f64,
+    // Close out the position when the breaker trips instead of just holding it
+    pub flatten_on_halt: bool,
+    // A trip on any connection turns the kill switch on for all of them, until it's
+    // turned off from the control endpoint
+    pub kill_switch_on_trip: bool,
+}
+
+impl Default for RiskConfig {
+    fn default() -> Self {
+        RiskConfig {
+            max_drawdown: None,
+            max_drawdown_pct: None,
+            cooldown_secs: 60.0,
+            flatten_on_halt: false,
+            kill_switch_on_trip: false,
+        }
+    }
+}
+
+// Token bucket on strategy trades per connection. Puzzle trades aren't limited.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    // One trade's worth of budget comes back every interval
+    pub interval_secs: f64,
+    // Trades that can go out back to back after a quiet spell
+    pub burst: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            enabled: true,
+            interval_secs: 2.0,
+            burst: 3,
+        }
+    }
+}
+
+// Sit out the first updates of a game, whose momentum the server worked out over
+// too few prices to mean much
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WarmUpConfig {
+    // State updates after a game starts or a reconnect that are watched but not traded
+    // on, 0 to trade from the first
+    pub updates: u32,
+}
+
+impl Default for WarmUpConfig {
+    fn default() -> Self {
+        WarmUpConfig { updates: 5 }
+    }
+}
+
+// Close out ahead of the finish rather than gamble on the last few ticks
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindDownConfig {
+    // Wind down over this many final updates, 0 to hold positions to the end
+    pub updates: u32,
+    // State updates in a full game, for counting down when the server doesn't say
+    pub game_length: Option<u32>,
+}
+
+impl Default for WindDownConfig {
+    fn default() -> Self {
+        WindDownConfig {
+            updates: 10,
+            game_length: None,
+        }
+    }
+}
+
+impl WindDownConfig {
+    // Updates left after this one, as reported or else counted from `seen` so far.
+    // None when there's no way of telling.
+    pub fn remaining(&self, reported: Option<u32>, seen: u32) -> Option<u32> {
+        reported.or_else(|| self.game_length.map(|length| length.saturating_sub(seen)))
+    }
+
+    pub fn is_due(&self, remaining: Option<u32>) -> bool {
+        self.updates > 0 && remaining.is_some_and(|remaining| remaining < self.updates)
+    }
+}
+
+// Trading on a state update the read loop fell behind on means trading on a market
+// that has already moved
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StalenessConfig {
+    // A decision made this long after its state update arrived is dropped
+    pub max_age_secs: f64,
+}
+
+impl Default for StalenessConfig {
+    fn default() -> Self {
+        StalenessConfig { max_age_secs: 0.5 }
+    }
+}
+
+// A state update repeating the one before it, as a reconnect race can deliver, would
+// send the same trade twice
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DuplicateConfig {
+    pub enabled: bool,
+    // Repeats skipped in a row before the next is taken as a real, unchanged update
+    pub max_skips: u32,
+    // Warn each time a connection has skipped this many more
+    pub warn_every: usize,
+}
+
+impl Default for DuplicateConfig {
+    fn default() -> Self {
+        DuplicateConfig {
+            enabled: true,
+            max_skips: 1,
+            warn_every: 10,
+        }
+    }
+}
+
+// Remembers only the last update's fingerprint, so nothing older is ever matched
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DuplicateFilter {
+    last: Option<u64>,
+    skipped_in_a_row: u32,
+}
+
+impl DuplicateFilter {
+    // Whether an update with this fingerprint repeats the last one and should be skipped
+    pub fn is_repeat(&mut self, config: &DuplicateConfig, fingerprint: u64) -> bool {
+        let repeat = config.enabled
+            && self.last == Some(fingerprint)
+            && self.skipped_in_a_row < config.max_skips;
+        self.skipped_in_a_row = if repeat { self.skipped_in_a_row + 1 } else { 0 };
+        self.last = Some(fingerprint);
+        repeat
+    }
+}
+
+// Get out of a position the signal has stopped backing, rather than sit in it to the
+// end of the game
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct HoldConfig {
+    // A position held this long without the signal re-confirming it goes back to flat.
+    // Unset holds for as long as the strategy likes.
+    pub max_secs: Option<f64>,
+    // Signal this strong in the position's direction re-confirms it
+    pub reconfirm_signal: f64,
+}
+
+impl Default for HoldConfig {
+    fn default() -> Self {
+        HoldConfig {
+            max_secs: Some(30.0),
+            reconfirm_signal: 0.5,
+        }
+    }
+}
+
+// How long the current position has gone without the signal backing it
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HoldTimer {
+    position: i32,
+    since: Option<f64>,
+}
+
+impl HoldTimer {
+    // Returns how long the position was held when it's been held too long. Restarts
+    // whenever the position changes or the signal re-confirms it, and after expiring.
+    pub fn update(
+        &mut self,
+        config: &HoldConfig,
+        now: f64,
+        position: i32,
+        signal: f64,
+    ) -> Option<f64> {
+        let reconfirmed = signal * f64::from(position.signum()) >= config.reconfirm_signal;
+        let since = match self.since {
+            Some(since) if position == self.position && !reconfirmed => since,
+            _ => {
+                self.position = position;
+                self.since = (position != 0).then_some(now);
+                return None;
+            }
+        };
+        let held = now - since;
+        if config.max_secs.is_some_and(|max_secs| held > max_secs) {
+            self.since = Some(now);
+            Some(held)
+        } else {
+            None
+        }
+    }
+}
+
+// Volume that takes the position as far as `volume` would without passing the limit.
+// Already past it, that's at least what gets back inside.
+pub fn clamp_to_limit(position: i32, volume: i32, position_limit: i32) -> i32 {
+    let position_limit = position_limit.max(0);
+    (position + volume).clamp(-position_limit, position_limit) - position
+}
+
+// Starts full, so the first `burst` trades always go out
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TradeLimiter {
+    tokens: f64,
+    last_refill: Option<f64>,
+}
+
+impl TradeLimiter {
+    // Take a token for one trade, returning false when the bucket is empty. `now` is
+    // monotonic seconds, like every interval here.
+    pub fn try_take(&mut self, config: &RateLimitConfig, now: f64) -> bool {
+        if !config.enabled {
+            return true;
+        }
+        let burst = config.burst as f64;
+        self.tokens = match self.last_refill {
+            Some(last) => f64::min(burst, self.tokens + (now - last) / config.interval_secs),
+            None => burst,
+        };
+        self.last_refill = Some(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// Trades each connection may send in one game. Once they're spent only trades back
+// toward flat go out, until the finish gives a fresh budget.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TradeBudgetConfig {
+    // Unset for no budget
+    pub trades_per_game: Option<u32>,
+    // A budget of its own for puzzle trades, so churn earlier in the game can't starve
+    // them. Unset and they draw from trades_per_game.
+    pub puzzle_trades_per_game: Option<u32>,
+}
+
+// Whether a trade only takes the position back toward flat, without going past it
+pub fn is_reducing(position: i32, volume: i32) -> bool {
+    volume.signum() == -position.signum() && volume.abs() <= position.abs()
+}
+
+// Trades one connection has sent this game, against its budgets
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TradeBudget {
+    trades: u32,
+    puzzle_trades: u32,
+}
+
+impl TradeBudget {
+    // Whether the trade may go out. One back toward flat always may.
+    pub fn allows(
+        &self,
+        config: &TradeBudgetConfig,
+        position: i32,
+        volume: i32,
+        puzzle: bool,
+    ) -> bool {
+        is_reducing(position, volume) || self.left(config, puzzle) != Some(0)
+    }
+
+    // Count a trade that went out
+    pub fn spend(&mut self, config: &TradeBudgetConfig, puzzle: bool) {
+        if puzzle && config.puzzle_trades_per_game.is_some() {
+            self.puzzle_trades += 1;
+        } else {
+            self.trades += 1;
+        }
+    }
+
+    // Trades left in the budget a trade would draw from, None when it has none
+    pub fn left(&self, config: &TradeBudgetConfig, puzzle: bool) -> Option<u32> {
+        match self.puzzle_left(config) {
+            Some(left) if puzzle => Some(left),
+            _ => self.trades_left(config),
+        }
+    }
+
+    pub fn trades_left(&self, config: &TradeBudgetConfig) -> Option<u32> {
+        config
+            .trades_per_game
+            .map(|budget| budget.saturating_sub(self.trades))
+    }
+
+    // Only when puzzle trades have a budget of their own
+    pub fn puzzle_left(&self, config: &TradeBudgetConfig) -> Option<u32> {
+        config
+            .puzzle_trades_per_game
+            .map(|budget| budget.saturating_sub(self.puzzle_trades))
+    }
+}
+
+// Ceiling on every message sent to the server, across all of an account's
+// connections, so a bug that floods the server can't get the player banned
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutboundConfig {
+    // Client messages in any minute. Unset for no ceiling.
+    pub max_per_minute: Option<u32>,
+}
+
+impl Default for OutboundConfig {
+    fn default() -> Self {
+        OutboundConfig {
+            max_per_minute: Some(300),
+        }
+    }
+}
+
+// What the outbound counts and ceiling are over
+pub const OUTBOUND_WINDOW_SECS: f64 = 60.0;
+// Share of the ceiling a skip repeating the connection's last message can use
+const DUPLICATE_SKIP_SHARE: f64 = 0.5;
+// Share the other non-trade messages can use, leaving the rest for trades
+const NON_TRADE_SHARE: f64 = 0.9;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutboundKind {
+    Connection,
+    Start,
+    Trade,
+    Skip,
+    Answer,
+}
+
+impl OutboundKind {
+    pub fn of(event: &ClientEvent) -> OutboundKind {
+        match event {
+            ClientEvent::Connection(_) => OutboundKind::Connection,
+            ClientEvent::Start(_) => OutboundKind::Start,
+            ClientEvent::Trade(_) => OutboundKind::Trade,
+            ClientEvent::Skip(_) => OutboundKind::Skip,
+            ClientEvent::Answer(_) => OutboundKind::Answer,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutboundCounts {
+    pub connection: usize,
+    pub start: usize,
+    pub trade: usize,
+    pub skip: usize,
+    pub answer: usize,
+}
+
+impl OutboundCounts {
+    pub fn add(&mut self, kind: OutboundKind) {
+        match kind {
+            OutboundKind::Connection => self.connection += 1,
+            OutboundKind::Start => self.start += 1,
+            OutboundKind::Trade => self.trade += 1,
+            OutboundKind::Skip => self.skip += 1,
+            OutboundKind::Answer => self.answer += 1,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.connection + self.start + self.trade + self.skip + self.answer
+    }
+}
+
+// Client messages sent over the last minute, across an account's connections. Near
+// the ceiling, duplicate skips are refused first and trades last.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OutboundLimiter {
+    // When, by which connection and what, oldest first
+    sent: VecDeque<(f64, usize, OutboundKind)>,
+    // Each connection's last message sent, to tell a repeated skip
+    last_sent: HashMap<usize, OutboundKind>,
+}
+
+impl OutboundLimiter {
+    // Count a message about to be sent, or return false if it has to be dropped. `now`
+    // is monotonic seconds.
+    pub fn admit(
+        &mut self,
+        config: &OutboundConfig,
+        conn_id: usize,
+        kind: OutboundKind,
+        now: f64,
+    ) -> bool {
+        while self
+            .sent
+            .front()
+            .is_some_and(|(sent_at, _, _)| now - sent_at >= OUTBOUND_WINDOW_SECS)
+        {
+            self.sent.pop_front();
+        }
+        if let Some(max) = config.max_per_minute {
+            let share = match kind {
+                OutboundKind::Trade => 1.0,
+                OutboundKind::Skip if self.last_sent.get(&conn_id) == Some(&kind) => {
+                    DUPLICATE_SKIP_SHARE
+                }
+                _ => NON_TRADE_SHARE,
+            };
+            if self.sent.len() as f64 >= (max as f64 * share).ceil() {
+                return false;
+            }
+        }
+        self.sent.push_back((now, conn_id, kind));
+        self.last_sent.insert(conn_id, kind);
+        true
+    }
+
+    // Messages sent over the last minute, every connection together
+    pub fn total(&self, now: f64) -> usize {
+        self.in_window(now).count()
+    }
+
+    // One connection's messages over the last minute
+    pub fn counts(&self, conn_id: usize, now: f64) -> OutboundCounts {
+        let mut counts = OutboundCounts::default();
+        for (_, _, kind) in self.in_window(now).filter(|(_, id, _)| *id == conn_id) {
+            counts.add(*kind);
+        }
+        counts
+    }
+
+    fn in_window(&self, now: f64) -> impl Iterator<Item = &(f64, usize, OutboundKind)> {
+        self.sent
+            .iter()
+            .filter(move |(sent_at, _, _)| now - sent_at < OUTBOUND_WINDOW_SECS)
+    }
+}
+
+// Ceiling on the net position of an account's connections together, since each going
+// to its own limit adds up to more than the account may want to hold
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExposureConfig {
+    // Largest net position long or short. Unset for no ceiling.
+    pub max_net_position: Option<u32>,
+}
+
+// A trade a connection wants, as the exposure ceiling sees it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExposureRequest {
+    // Where the connection's position stands, counting its trades not yet filled
+    pub position: i32,
+    pub position_limit: i32,
+    pub volume: i32,
+    // The signal behind it. A stronger one has first call on the room left.
+    pub signal: f64,
+}
+
+// What one connection adds to the account's position
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Holding {
+    // None while disconnected mid-game, when it could be anywhere within the limit
+    position: Option<i32>,
+    position_limit: i32,
+    strength: f64,
+    // Volume it wanted and was refused at its last decision, kept for it while no
+    // weaker signal may take the room
+    unmet: i32,
+}
+
+// How much of the exposure ceiling is in use, in the snapshot
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct ExposureUsage {
+    // Sum of the positions that are known
+    pub net_position: i32,
+    // Furthest long and short the account could be, counting each connection whose
+    // position is unknown at its limit that way
+    pub worst_long: i32,
+    pub worst_short: i32,
+    pub unknown: usize,
+    pub max_net_position: Option<u32>,
+    // The worse of the two over the ceiling, when there is one
+    pub utilization: Option<f64>,
+}
+
+// Every connection's position in the current game, for the exposure ceiling
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExposureBook {
+    holdings: HashMap<usize, Holding>,
+}
+
+impl ExposureBook {
+    // Keep up with a position reported in a state update
+    pub fn observe(&mut self, conn_id: usize, position: i32, position_limit: i32) {
+        let holding = self.holdings.entry(conn_id).or_insert(Holding {
+            position: None,
+            position_limit,
+            strength: 0.0,
+            unmet: 0,
+        });
+        holding.position = Some(position);
+        holding.position_limit = position_limit;
+    }
+
+    // Shrink the trade to what fits under the ceiling and book it as gone out
+    pub fn admit(
+        &mut self,
+        config: &ExposureConfig,
+        conn_id: usize,
+        request: ExposureRequest,
+    ) -> i32 {
+        let volume = self.fit(config, conn_id, request);
+        self.book(conn_id, request, volume, volume);
+        volume
+    }
+
+    // What of the trade fits under the ceiling, leaving room a stronger signal was
+    // refused for it. Nothing is booked, so a trade held back later costs no room. A
+    // trade back toward flat always fits, so a position can be closed out whatever the
+    // others hold.
+    pub fn fit(&self, config: &ExposureConfig, conn_id: usize, request: ExposureRequest) -> i32 {
+        let strength = request.signal.abs();
+        let direction = request.volume.signum();
+        let toward_flat = request.position.signum() == -direction
+            && request.volume.abs() <= request.position.abs();
+        match config.max_net_position {
+            Some(max) if direction != 0 && !toward_flat => {
+                let exposure: i32 = self
+                    .holdings
+                    .iter()
+                    .filter(|(&id, _)| id != conn_id)
+                    .map(|(_, holding)| match holding.position {
+                        Some(position) => position * direction,
+                        None => holding.position_limit.abs(),
+                    })
+                    .sum::<i32>()
+                    + request.position * direction;
+                let reserved: i32 = self
+                    .holdings
+                    .iter()
+                    .filter(|(&id, holding)| {
+                        id != conn_id
+                            && holding.unmet.signum() == direction
+                            && holding.strength > strength
+                    })
+                    .map(|(_, holding)| holding.unmet.abs())
+                    .sum();
+                let room = (max as i32 - exposure - reserved).max(0);
+                direction * request.volume.abs().min(room)
+            }
+            _ => request.volume,
+        }
+    }
+
+    // Book the volume that went out of a trade `fit` allowed `allowed` of. What the
+    // ceiling refused stays claimed for it until its next decision.
+    pub fn book(&mut self, conn_id: usize, request: ExposureRequest, allowed: i32, sent: i32) {
+        self.holdings.insert(
+            conn_id,
+            Holding {
+                position: Some(request.position + sent),
+                position_limit: request.position_limit,
+                strength: request.signal.abs(),
+                unmet: request.volume - allowed,
+            },
+        );
+    }
+
+    // Disconnected mid-game: the position is whatever it was, or whatever a trade
+    // still in flight made it, so it counts at the limit until it's reported again
+    pub fn lose_track(&mut self, conn_id: usize) {
+        if let Some(holding) = self.holdings.get_mut(&conn_id) {
+            holding.position = None;
+            holding.unmet = 0;
+        }
+    }
+
+    // The connection's game is over, and its position with it
+    pub fn close(&mut self, conn_id: usize) {
+        self.holdings.remove(&conn_id);
+    }
+
+    pub fn usage(&self, config: &ExposureConfig) -> ExposureUsage {
+        let mut usage = ExposureUsage {
+            max_net_position: config.max_net_position,
+            ..ExposureUsage::default()
+        };
+        for holding in self.holdings.values() {
+            match holding.position {
+                Some(position) => {
+                    usage.net_position += position;
+                    usage.worst_long += position;
+                    usage.worst_short -= position;
+                }
+                None => {
+                    usage.unknown += 1;
+                    usage.worst_long += holding.position_limit.abs();
+                    usage.worst_short += holding.position_limit.abs();
+                }
+            }
+        }
+        usage.utilization = config
+            .max_net_position
+            .map(|max| usage.worst_long.max(usage.worst_short).max(0) as f64 / max as f64);
+        usage
+    }
+}
+
+// One decision of a recorded or simulated game, as the risk checks replay it offline
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RiskEvent {
+    // Seconds, only ever compared with each other
+    pub timestamp: f64,
+    pub price: f64,
+    // None for a puzzle or manual trade between state updates, which the breaker holds
+    // back while halted without being fed a PnL
+    pub pnl: Option<f64>,
+    // Position held coming into the decision, and the one the bot went to
+    pub position: i32,
+    pub target: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BreakerEvent {
+    Tripped { peak: f64, drawdown: f64 },
+    Lifted,
+}
+
+// Per-connection drawdown circuit breaker
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DrawdownBreaker {
+    pub peak_pnl: Option<f64>,
+    pub halted_since: Option<f64>,
+    pub trips: usize,
+}
+
+impl DrawdownBreaker {
+    pub fn is_halted(&self) -> bool {
+        self.halted_since.is_some()
+    }
+
+    // Feed the latest PnL at `now` (monotonic seconds), returning a transition if the
+    // breaker changed state
+    pub fn update(&mut self, config: &RiskConfig, now: f64, pnl: f64) -> Option<BreakerEvent> {
+        if let Some(since) = self.halted_since {
+            if now - since < config.cooldown_secs {
+                return None;
+            }
+            // Start measuring drawdown afresh so we don't trip again immediately
+            self.halted_since = None;
+            self.peak_pnl = Some(pnl);
+            return Some(BreakerEvent::Lifted);
+        }
+
+        let peak = self.peak_pnl.map_or(pnl, |peak| f64::max(peak, pnl));
+        self.peak_pnl = Some(peak);
+
+        let drawdown = peak - pnl;
+        let over_dollars = config.max_drawdown.is_some_and(|max| drawdown > max);
+        let over_pct = config
+            .max_drawdown_pct
+            .is_some_and(|max| peak > 0.0 && drawdown / peak > max);
+
+        if over_dollars || over_pct {
+            self.halted_since = Some(now);
+            self.trips += 1;
+            return Some(BreakerEvent::Tripped { peak, drawdown });
+        }
+        None
+    }
+
+    // The whole check on one state update's decision: feed the PnL, then hold the
+    // position, or flatten it, while halted. Returns the volume that may go out.
+    pub fn check(
+        &mut self,
+        config: &RiskConfig,
+        now: f64,
+        pnl: f64,
+        position: i32,
+        volume: i32,
+    ) -> (i32, Option<BreakerEvent>) {
+        let event = self.update(config, now, pnl);
+        let volume = if !self.is_halted() {
+            volume
+        } else if config.flatten_on_halt {
+            -position
+        } else {
+            0
+        };
+        (volume, event)
+    }
+
+    // New game: lift any halt and forget the old peak
+    pub fn reset(&mut self) -> Option<BreakerEvent> {
+        self.peak_pnl = None;
+        self.halted_since.take().map(|_| BreakerEvent::Lifted)
+    }
+}
+
+// Trade outcomes kept per connection for the quarantine's rolling PnL
+pub const RECENT_TRADE_OUTCOMES: usize = 100;
+
+// Takes a connection that keeps losing while the others make money off trading for a
+// while, in case it's the one seeing bad fills
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuarantineConfig {
+    pub enabled: bool,
+    // Judged over the PnL of this many of the connection's latest trades
+    pub trades: usize,
+    // Quarantined when that PnL is below this while every other connection's is positive
+    pub threshold: f64,
+    // Seconds spent observing only, still journaling but not trading
+    pub cooldown_secs: f64,
+    // Share of the position limit used afterwards, until `trades` more trades are judged
+    pub reduced_size: f64,
+}
+
+impl Default for QuarantineConfig {
+    fn default() -> Self {
+        QuarantineConfig {
+            enabled: false,
+            trades: 10,
+            threshold: -5.0,
+            cooldown_secs: 120.0,
+            reduced_size: 0.5,
+        }
+    }
+}
+
+impl QuarantineConfig {
+    // The limit a connection on probation trades to
+    pub fn reduced_limit(&self, position_limit: i32) -> i32 {
+        (position_limit as f64 * self.reduced_size).round() as i32
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum QuarantineState {
+    #[default]
+    Trading,
+    // Not trading since this monotonic time
+    Observing {
+        since: f64,
+    },
+    // Back at reduced size until this many more trades are judged
+    Probation {
+        trades_left: usize,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuarantineEvent {
+    Started { rolling_pnl: f64 },
+    Probation,
+    Cleared,
+}
+
+// Per-connection quarantine, fed the PnL of each trade once it's judged
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Quarantine {
+    pub state: QuarantineState,
+    pub quarantines: usize,
+    // Realized PnL of the latest judged trades, oldest first
+    recent_trades: VecDeque<f64>,
+}
+
+impl Quarantine {
+    pub fn is_observing(&self) -> bool {
+        matches!(self.state, QuarantineState::Observing { .. })
+    }
+
+    pub fn is_on_probation(&self) -> bool {
+        matches!(self.state, QuarantineState::Probation { .. })
+    }
+
+    pub fn record_trade(&mut self, pnl: f64) {
+        if self.recent_trades.len() >= RECENT_TRADE_OUTCOMES {
+            self.recent_trades.pop_front();
+        }
+        self.recent_trades.push_back(pnl);
+        if let QuarantineState::Probation { trades_left } = &mut self.state {
+            *trades_left = trades_left.saturating_sub(1);
+        }
+    }
+
+    // PnL over the latest `trades` judged trades, None until there are that many
+    pub fn rolling_pnl(&self, trades: usize) -> Option<f64> {
+        (trades > 0 && self.recent_trades.len() >= trades)
+            .then(|| self.recent_trades.iter().rev().take(trades).sum())
+    }
+
+    // Check at `now` (monotonic seconds), given whether every other connection is
+    // making money, returning a transition if there was one
+    pub fn update(
+        &mut self,
+        config: &QuarantineConfig,
+        now: f64,
+        others_profitable: bool,
+    ) -> Option<QuarantineEvent> {
+        if !config.enabled {
+            return None;
+        }
+        match self.state {
+            QuarantineState::Observing { since } => {
+                if now - since < config.cooldown_secs {
+                    return None;
+                }
+                // Judged afresh on the trades made from here on
+                self.recent_trades.clear();
+                self.state = QuarantineState::Probation {
+                    trades_left: config.trades,
+                };
+                return Some(QuarantineEvent::Probation);
+            }
+            QuarantineState::Probation { trades_left: 0 } => {
+                self.state = QuarantineState::Trading;
+                return Some(QuarantineEvent::Cleared);
+            }
+            QuarantineState::Probation { .. } | QuarantineState::Trading => {}
+        }
+        let rolling_pnl = self.rolling_pnl(config.trades)?;
+        if rolling_pnl < config.threshold && others_profitable {
+            self.state = QuarantineState::Observing { since: now };
+            self.quarantines += 1;
+            return Some(QuarantineEvent::Started { rolling_pnl });
+        }
+        None
+    }
+}
@@ -0,0 +1,329 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::backtest::{game_seeds, record_games, SyntheticGame};
+use crate::config::Config;
+use crate::journal::JournalEntry;
+use crate::risk::{BreakerEvent, DrawdownBreaker, RiskConfig, RiskEvent};
+
+// Settings `evaluate-risk` replays recorded or synthetic games under
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RiskEvalConfig {
+    // Synthetic games played when no journal is given, drawn from [backtest]
+    pub games: usize,
+    // Every combination of these is replayed. A list left empty keeps the [risk] setting.
+    pub max_drawdown: Vec<f64>,
+    pub max_drawdown_pct: Vec<f64>,
+    pub cooldown_secs: Vec<f64>,
+    pub flatten_on_halt: Vec<bool>,
+}
+
+impl Default for RiskEvalConfig {
+    fn default() -> Self {
+        RiskEvalConfig {
+            games: 200,
+            max_drawdown: vec![5.0, 10.0, 20.0],
+            max_drawdown_pct: Vec::new(),
+            cooldown_secs: vec![30.0, 60.0, 120.0],
+            flatten_on_halt: Vec::new(),
+        }
+    }
+}
+
+impl RiskEvalConfig {
+    pub fn is_valid(&self) -> bool {
+        let positive = |values: &[f64]| values.iter().all(|v| *v > 0.0 && v.is_finite());
+        self.games > 0
+            && positive(&self.max_drawdown)
+            && positive(&self.max_drawdown_pct)
+            && self
+                .cooldown_secs
+                .iter()
+                .all(|secs| *secs >= 0.0 && secs.is_finite())
+    }
+
+    // The grid over `base`, in the order listed
+    pub fn settings(&self, base: &RiskConfig) -> Vec<RiskConfig> {
+        let limits = |values: &[f64], base: Option<f64>| -> Vec<Option<f64>> {
+            if values.is_empty() {
+                vec![base]
+            } else {
+                values.iter().copied().map(Some).collect()
+            }
+        };
+        let or_base = |values: &[f64], base: f64| {
+            if values.is_empty() {
+                vec![base]
+            } else {
+                values.to_vec()
+            }
+        };
+        let flatten = if self.flatten_on_halt.is_empty() {
+            vec![base.flatten_on_halt]
+        } else {
+            self.flatten_on_halt.clone()
+        };
+
+        let mut settings = Vec::new();
+        for max_drawdown in limits(&self.max_drawdown, base.max_drawdown) {
+            for max_drawdown_pct in limits(&self.max_drawdown_pct, base.max_drawdown_pct) {
+                for &cooldown_secs in &or_base(&self.cooldown_secs, base.cooldown_secs) {
+                    for &flatten_on_halt in &flatten {
+                        settings.push(RiskConfig {
+                            max_drawdown,
+                            max_drawdown_pct,
+                            cooldown_secs,
+                            flatten_on_halt,
+                            ..base.clone()
+                        });
+                    }
+                }
+            }
+        }
+        settings
+    }
+}
+
+// Each connection's sessions in a journal, a session starting at a state update with
+// no PnL change. A reconnect mid-game starts one too, so the breaker is replayed afresh
+// there where live it would have carried on.
+pub fn journal_games(entries: &[JournalEntry]) -> Vec<Vec<RiskEvent>> {
+    let mut open: BTreeMap<(&str, usize), Vec<RiskEvent>> = BTreeMap::new();
+    let mut games = Vec::new();
+    for entry in entries {
+        let key = (entry.account.as_str(), entry.conn_id);
+        let state_update = entry.mode.is_strategy();
+        if state_update && entry.pnl_change.is_none() {
+            games.extend(open.remove(&key));
+        }
+        open.entry(key).or_default().push(RiskEvent {
+            timestamp: entry.timestamp,
+            price: entry.price,
+            pnl: state_update.then_some(entry.pnl),
+            position: entry.position_before,
+            target: if entry.sent {
+                entry.position_after
+            } else {
+                entry.position_before
+            },
+        });
+    }
+    games.extend(open.into_values());
+    games
+}
+
+// Synthetic games from [backtest], played by the first strategy with the breaker off,
+// so every setting is replayed over the same decisions
+pub async fn synthetic_games(config: &Config) -> Vec<Vec<RiskEvent>> {
+    let mut config = config.clone();
+    config.risk.max_drawdown = None;
+    config.risk.max_drawdown_pct = None;
+    config.backtest.games = config.risk_eval.games;
+    let games: Vec<SyntheticGame> = game_seeds(&config.backtest)
+        .into_iter()
+        .map(|seed| SyntheticGame::generate(&config.backtest, seed))
+        .collect();
+    record_games(Arc::new(config), &games, true).await
+}
+
+// Deepest fall from the highest PnL so far
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Drawdown {
+    peak: Option<f64>,
+    worst: f64,
+}
+
+impl Drawdown {
+    fn observe(&mut self, pnl: f64) {
+        let peak = self.peak.map_or(pnl, |peak| f64::max(peak, pnl));
+        self.peak = Some(peak);
+        self.worst = self.worst.max(peak - pnl);
+    }
+}
+
+// How one game went as recorded and how it would have gone with the breaker
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GameReplay {
+    pub trips: usize,
+    pub updates: usize,
+    pub halted_updates: usize,
+    // PnL at the game's last state update
+    pub pnl_without: f64,
+    pub pnl_with: f64,
+    pub drawdown_without: f64,
+    pub drawdown_with: f64,
+}
+
+// Replay one game's decisions through the breaker. Outside a halt the replay heads for
+// whatever position the recorded bot went to, filled at the recorded price, so its PnL
+// only comes apart from the recorded one by what the positions differed by as prices
+// moved. Costs on the extra trades are left out.
+pub fn replay_game(config: &RiskConfig, events: &[RiskEvent]) -> GameReplay {
+    let mut breaker = DrawdownBreaker::default();
+    let mut replay = GameReplay::default();
+    let mut without = Drawdown::default();
+    let mut with = Drawdown::default();
+    let mut held: Option<i32> = None;
+    let mut last_price: Option<f64> = None;
+    // Replayed PnL less recorded PnL
+    let mut gap = 0.0;
+    for event in events {
+        let position = held.unwrap_or(event.position);
+        if let Some(last_price) = last_price {
+            gap += f64::from(position - event.position) * (event.price - last_price);
+        }
+        last_price = Some(event.price);
+        let volume = event.target - position;
+        let volume = match event.pnl {
+            Some(pnl) => {
+                let (volume, transition) =
+                    breaker.check(config, event.timestamp, pnl + gap, position, volume);
+                if matches!(transition, Some(BreakerEvent::Tripped { .. })) {
+                    replay.trips += 1;
+                }
+                replay.updates += 1;
+                replay.halted_updates += usize::from(breaker.is_halted());
+                without.observe(pnl);
+                with.observe(pnl + gap);
+                replay.pnl_without = pnl;
+                replay.pnl_with = pnl + gap;
+                volume
+            }
+            None if breaker.is_halted() => 0,
+            None => volume,
+        };
+        held = Some(position + volume);
+    }
+    replay.drawdown_without = without.worst;
+    replay.drawdown_with = with.worst;
+    replay
+}
+
+// One risk setting over every game
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RiskOutcome {
+    pub max_drawdown: Option<f64>,
+    pub max_drawdown_pct: Option<f64>,
+    pub cooldown_secs: f64,
+    pub flatten_on_halt: bool,
+    pub games: usize,
+    pub trips: usize,
+    // Games the breaker tripped in at least once
+    pub games_tripped: usize,
+    // Share of state updates spent halted
+    pub halted_share: f64,
+    // Final PnL per game, as recorded and with the breaker
+    pub mean_pnl_without: f64,
+    pub mean_pnl_with: f64,
+    // Deepest fall from a peak in any one game
+    pub worst_drawdown_without: f64,
+    pub worst_drawdown_with: f64,
+}
+
+pub fn evaluate_risk(games: &[Vec<RiskEvent>], settings: &[RiskConfig]) -> Vec<RiskOutcome> {
+    settings
+        .iter()
+        .map(|config| {
+            let replays: Vec<GameReplay> = games
+                .iter()
+                .map(|events| replay_game(config, events))
+                .collect();
+            let n = replays.len().max(1) as f64;
+            let updates: usize = replays.iter().map(|replay| replay.updates).sum();
+            let halted: usize = replays.iter().map(|replay| replay.halted_updates).sum();
+            let worst =
+                |drawdown: fn(&GameReplay) -> f64| replays.iter().map(drawdown).fold(0.0, f64::max);
+            RiskOutcome {
+                max_drawdown: config.max_drawdown,
+                max_drawdown_pct: config.max_drawdown_pct,
+                cooldown_secs: config.cooldown_secs,
+                flatten_on_halt: config.flatten_on_halt,
+                games: replays.len(),
+                trips: replays.iter().map(|replay| replay.trips).sum(),
+                games_tripped: replays.iter().filter(|replay| replay.trips > 0).count(),
+                halted_share: halted as f64 / updates.max(1) as f64,
+                mean_pnl_without: replays.iter().map(|replay| replay.pnl_without).sum::<f64>() / n,
+                mean_pnl_with: replays.iter().map(|replay| replay.pnl_with).sum::<f64>() / n,
+                worst_drawdown_without: worst(|replay| replay.drawdown_without),
+                worst_drawdown_with: worst(|replay| replay.drawdown_with),
+            }
+        })
+        .collect()
+}
+
+fn limit(value: Option<f64>) -> String {
+    value.map_or("-".to_string(), |value| value.to_string())
+}
+
+// Aligned table of the outcomes, one setting per row
+pub fn format_outcomes(outcomes: &[RiskOutcome]) -> String {
+    let mut table = format!(
+        "  {:>8} {:>8} {:>8} {:>7} {:>10} {:>8} {:>7} {:>9} {:>9} {:>9} {:>9}\n",
+        "max dd",
+        "max dd%",
+        "cooldown",
+        "flatten",
+        "trips/game",
+        "tripped%",
+        "halted%",
+        "pnl",
+        "with",
+        "worst dd",
+        "with"
+    );
+    for outcome in outcomes {
+        let n = outcome.games.max(1) as f64;
+        let _ = writeln!(
+            table,
+            "  {:>8} {:>8} {:>8} {:>7} {:>10.2} {:>8.1} {:>7.1} {:>9.2} {:>9.2} {:>9.2} {:>9.2}",
+            limit(outcome.max_drawdown),
+            limit(outcome.max_drawdown_pct),
+            outcome.cooldown_secs,
+            outcome.flatten_on_halt,
+            outcome.trips as f64 / n,
+            outcome.games_tripped as f64 / n * 100.0,
+            outcome.halted_share * 100.0,
+            outcome.mean_pnl_without,
+            outcome.mean_pnl_with,
+            outcome.worst_drawdown_without,
+            outcome.worst_drawdown_with
+        );
+    }
+    table
+}
+
+// One row per setting, limits left empty when unset
+pub fn write_outcomes_csv(path: &Path, outcomes: &[RiskOutcome]) -> io::Result<()> {
+    let mut csv = String::from(
+        "max_drawdown,max_drawdown_pct,cooldown_secs,flatten_on_halt,games,trips,games_tripped,\
+         halted_share,mean_pnl_without,mean_pnl_with,worst_drawdown_without,worst_drawdown_with\n",
+    );
+    for outcome in outcomes {
+        let _ = writeln!(
+            csv,
+            "{},{},{},{},{},{},{},{},{},{},{},{}",
+            outcome
+                .max_drawdown
+                .map_or(String::new(), |max| max.to_string()),
+            outcome
+                .max_drawdown_pct
+                .map_or(String::new(), |max| max.to_string()),
+            outcome.cooldown_secs,
+            outcome.flatten_on_halt,
+            outcome.games,
+            outcome.trips,
+            outcome.games_tripped,
+            outcome.halted_share,
+            outcome.mean_pnl_without,
+            outcome.mean_pnl_with,
+            outcome.worst_drawdown_without,
+            outcome.worst_drawdown_with
+        );
+    }
+    std::fs::write(path, csv)
+}
@@ -0,0 +1,50 @@
+// The async runtime, and the only module that names one. Built on async-std by default,
+// or on tokio with the runtime-tokio feature (which wins when both are on), so the
+// crate can be embedded without starting a second runtime.
+//
+// Runtime-agnostic, and used directly everywhere else:
+// - std::sync::Arc
+// - async_channel's channels
+// - the futures crate: select and other combinators, StreamExt, the io traits
+//   (AsyncRead, AsyncWrite, AsyncBufRead and their Ext traits) and futures::io's
+//   BufReader, BufWriter and Cursor
+// - async_tungstenite's WebSocketStream over any of the io traits
+//
+// Anything that needs the runtime goes through here instead: spawning, sleeping and
+// timeouts, the Mutex and RwLock, files, sockets, stdin and stdout, the websocket
+// connector and the test attribute. Don't reach for async_std:: or tokio:: outside this
+// module; add what's missing here, for both runtimes.
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+#[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+mod async_std;
+#[cfg(feature = "runtime-tokio")]
+mod tokio;
+
+#[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+pub use self::async_std::*;
+#[cfg(feature = "runtime-tokio")]
+pub use self::tokio::*;
+
+#[cfg(not(any(feature = "runtime-async-std", feature = "runtime-tokio")))]
+compile_error!("build with the runtime-async-std or runtime-tokio feature");
+
+// A future that didn't finish within its timeout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimedOut;
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("timed out")
+    }
+}
+
+impl Error for TimedOut {}
+
+impl From<TimedOut> for io::Error {
+    fn from(e: TimedOut) -> Self {
+        io::Error::new(io::ErrorKind::TimedOut, e)
+    }
+}
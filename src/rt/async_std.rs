@@ -0,0 +1,109 @@
+use async_tungstenite::async_std::ConnectStream;
+use async_tungstenite::WebSocketStream;
+use std::future::Future;
+use std::time::Duration;
+
+use super::TimedOut;
+
+pub use async_std::sync::{Mutex, RwLock};
+pub use async_std::task::JoinHandle;
+// For tests, as #[optiva_ws::rt::test]
+pub use async_std::test;
+pub use async_tungstenite::async_std::connect_async;
+
+// A websocket client connection, as connect_async opens it
+pub type WsStream = WebSocketStream<ConnectStream>;
+
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    async_std::task::spawn(future)
+}
+
+// Run blocking work off the async threads
+pub fn spawn_blocking<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    async_std::task::spawn_blocking(f)
+}
+
+// Start the runtime and run a future to completion on it, for main. Not to be called
+// from inside the runtime.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    async_std::task::block_on(future)
+}
+
+pub async fn sleep(duration: Duration) {
+    async_std::task::sleep(duration).await
+}
+
+pub async fn timeout<F: Future>(duration: Duration, future: F) -> Result<F::Output, TimedOut> {
+    async_std::future::timeout(duration, future)
+        .await
+        .map_err(|_| TimedOut)
+}
+
+pub mod fs {
+    use std::io;
+    use std::path::Path;
+
+    pub use async_std::fs::{create_dir_all, metadata, rename, write};
+
+    // Written through futures' AsyncWrite
+    pub type File = async_std::fs::File;
+
+    // Open a file to add to, creating it if needed
+    pub async fn append(path: &Path) -> io::Result<File> {
+        async_std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+    }
+}
+
+// Read and written through futures' AsyncRead and AsyncWrite
+pub type Stdin = async_std::io::Stdin;
+pub type Stdout = async_std::io::Stdout;
+
+pub fn stdin() -> Stdin {
+    async_std::io::stdin()
+}
+
+pub fn stdout() -> Stdout {
+    async_std::io::stdout()
+}
+
+pub mod net {
+    use std::io;
+    use std::net::SocketAddr;
+
+    // Read and written through futures' AsyncRead and AsyncWrite
+    pub type TcpStream = async_std::net::TcpStream;
+
+    pub async fn connect(addr: SocketAddr) -> io::Result<TcpStream> {
+        TcpStream::connect(addr).await
+    }
+
+    pub struct TcpListener(async_std::net::TcpListener);
+
+    impl TcpListener {
+        pub async fn bind(addr: &str) -> io::Result<TcpListener> {
+            async_std::net::TcpListener::bind(addr)
+                .await
+                .map(TcpListener)
+        }
+
+        pub async fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+            self.0.accept().await
+        }
+
+        pub fn local_addr(&self) -> io::Result<SocketAddr> {
+            self.0.local_addr()
+        }
+    }
+}
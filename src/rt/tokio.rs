@@ -0,0 +1,136 @@
+use async_tungstenite::tokio::ConnectStream;
+use async_tungstenite::WebSocketStream;
+use std::future::Future;
+use std::panic;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+
+use super::TimedOut;
+
+pub use async_tungstenite::tokio::connect_async;
+pub use tokio::sync::{Mutex, RwLock};
+// For tests, as #[optiva_ws::rt::test]
+pub use tokio::test;
+
+// A websocket client connection, as connect_async opens it
+pub type WsStream = WebSocketStream<ConnectStream>;
+
+// Resolves to what the task returned, like async-std's. A panic in the task carries on
+// in whoever awaits it.
+pub struct JoinHandle<T>(tokio::task::JoinHandle<T>);
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        Pin::new(&mut self.0).poll(cx).map(|joined| match joined {
+            Ok(output) => output,
+            Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+            Err(e) => panic!("task didn't finish: {}", e),
+        })
+    }
+}
+
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    JoinHandle(tokio::task::spawn(future))
+}
+
+// Run blocking work off the async threads
+pub fn spawn_blocking<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    JoinHandle(tokio::task::spawn_blocking(f))
+}
+
+// Start the runtime and run a future to completion on it, for main. Not to be called
+// from inside the runtime.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("the tokio runtime starts")
+        .block_on(future)
+}
+
+pub async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await
+}
+
+pub async fn timeout<F: Future>(duration: Duration, future: F) -> Result<F::Output, TimedOut> {
+    tokio::time::timeout(duration, future)
+        .await
+        .map_err(|_| TimedOut)
+}
+
+pub mod fs {
+    use std::io;
+    use std::path::Path;
+    use tokio_util::compat::{Compat, TokioAsyncWriteCompatExt};
+
+    pub use tokio::fs::{create_dir_all, metadata, rename, write};
+
+    // Written through futures' AsyncWrite
+    pub type File = Compat<tokio::fs::File>;
+
+    // Open a file to add to, creating it if needed
+    pub async fn append(path: &Path) -> io::Result<File> {
+        tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+            .map(TokioAsyncWriteCompatExt::compat_write)
+    }
+}
+
+// Read and written through futures' AsyncRead and AsyncWrite
+pub type Stdin = Compat<tokio::io::Stdin>;
+pub type Stdout = Compat<tokio::io::Stdout>;
+
+pub fn stdin() -> Stdin {
+    tokio::io::stdin().compat()
+}
+
+pub fn stdout() -> Stdout {
+    tokio::io::stdout().compat_write()
+}
+
+pub mod net {
+    use std::io;
+    use std::net::SocketAddr;
+    use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
+
+    // Read and written through futures' AsyncRead and AsyncWrite
+    pub type TcpStream = Compat<tokio::net::TcpStream>;
+
+    pub async fn connect(addr: SocketAddr) -> io::Result<TcpStream> {
+        tokio::net::TcpStream::connect(addr)
+            .await
+            .map(TokioAsyncReadCompatExt::compat)
+    }
+
+    pub struct TcpListener(tokio::net::TcpListener);
+
+    impl TcpListener {
+        pub async fn bind(addr: &str) -> io::Result<TcpListener> {
+            tokio::net::TcpListener::bind(addr).await.map(TcpListener)
+        }
+
+        pub async fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+            let (stream, peer) = self.0.accept().await?;
+            Ok((stream.compat(), peer))
+        }
+
+        pub fn local_addr(&self) -> io::Result<SocketAddr> {
+            self.0.local_addr()
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use serde::Deserialize;
+
+use crate::state::{Sizing, StrategyParams};
+
+// Params that change as a game goes on, laid over the base params. The optimizer only
+// ever tunes the base underneath.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScheduleConfig {
+    // Every phase the game has reached applies, in order, so a later one wins where
+    // they set the same param
+    pub phases: Vec<PhaseConfig>,
+}
+
+// One overlay and the point in the game it starts from. Exactly one of the starts is
+// set.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PhaseConfig {
+    pub name: String,
+    // Share of the game gone, from 0 to 1. Needs the game's length, as reported or
+    // from `[wind_down]` `game_length`.
+    pub from_fraction: Option<f64>,
+    // Seconds since the game's first state update
+    pub from_secs: Option<f64>,
+    pub momentum_weight: Option<f64>,
+    pub forecast_weight: Option<f64>,
+    pub aggressive_factor: Option<f64>,
+    pub deadband: Option<f64>,
+    pub strong_momentum_threshold: Option<f64>,
+    pub medium_momentum_threshold: Option<f64>,
+    pub sizing: Option<Sizing>,
+}
+
+// Where a decision falls in its game
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GameClock {
+    // None when the game's length isn't known
+    pub fraction: Option<f64>,
+    pub elapsed_secs: Option<f64>,
+}
+
+impl PhaseConfig {
+    pub fn has_started(&self, clock: GameClock) -> bool {
+        match (self.from_fraction, self.from_secs) {
+            (Some(from), _) => clock.fraction.is_some_and(|fraction| fraction >= from),
+            (None, Some(from)) => clock.elapsed_secs.is_some_and(|elapsed| elapsed >= from),
+            (None, None) => false,
+        }
+    }
+
+    pub fn apply(&self, params: &mut StrategyParams) {
+        let overrides = [
+            (self.momentum_weight, &mut params.momentum_weight),
+            (self.forecast_weight, &mut params.forecast_weight),
+            (self.aggressive_factor, &mut params.aggressive_factor),
+            (self.deadband, &mut params.deadband),
+            (
+                self.strong_momentum_threshold,
+                &mut params.strong_momentum_threshold,
+            ),
+            (
+                self.medium_momentum_threshold,
+                &mut params.medium_momentum_threshold,
+            ),
+        ];
+        for (value, param) in overrides {
+            if let Some(value) = value {
+                *param = value;
+            }
+        }
+        if let Some(sizing) = self.sizing {
+            params.sizing = sizing;
+        }
+    }
+}
+
+impl ScheduleConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.phases.is_empty()
+    }
+
+    // Lay every phase the game has reached over the params, returning the name of the
+    // last, which is the one in force
+    pub fn apply(&self, params: &mut StrategyParams, clock: GameClock) -> Option<&str> {
+        let mut active = None;
+        for phase in self.phases.iter().filter(|phase| phase.has_started(clock)) {
+            phase.apply(params);
+            active = Some(phase.name.as_str());
+        }
+        active
+    }
+}
@@ -0,0 +1,43 @@
+use async_channel::{Receiver, Sender};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// Shutdown signal shared by every connection task
+#[derive(Clone)]
+pub struct Shutdown {
+    triggered: Arc<AtomicBool>,
+    sender: Sender<()>,
+    receiver: Receiver<()>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (sender, receiver) = async_channel::bounded(1);
+        Shutdown {
+            triggered: Arc::new(AtomicBool::new(false)),
+            sender,
+            receiver,
+        }
+    }
+
+    // Closing the channel wakes everyone waiting in wait()
+    pub fn trigger(&self) {
+        self.triggered.store(true, Ordering::SeqCst);
+        self.sender.close();
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.triggered.load(Ordering::SeqCst)
+    }
+
+    // Resolves once trigger() has been called
+    pub async fn wait(&self) {
+        while self.receiver.recv().await.is_ok() {}
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
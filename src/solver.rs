@@ -0,0 +1,185 @@
+use serde_json::Value;
+use std::fmt;
+
+use crate::protocol::PuzzleData;
+
+// What a solver worked out a puzzle's question to be
+#[derive(Debug, Clone, PartialEq)]
+pub enum PuzzleAnswer {
+    Number(f64),
+    Text(String),
+}
+
+impl PuzzleAnswer {
+    // As it goes out in the answer message, whole numbers without a decimal point
+    pub fn to_value(&self) -> Value {
+        match self {
+            PuzzleAnswer::Number(number)
+                if number.fract() == 0.0 && number.abs() < MAX_EXACT_INTEGER =>
+            {
+                Value::from(*number as i64)
+            }
+            PuzzleAnswer::Number(number) => Value::from(*number),
+            PuzzleAnswer::Text(text) => Value::from(text.as_str()),
+        }
+    }
+}
+
+impl fmt::Display for PuzzleAnswer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_value())
+    }
+}
+
+// Past this a float no longer holds every integer
+const MAX_EXACT_INTEGER: f64 = 9_007_199_254_740_992.0;
+
+// Answers the question a puzzle asks, or passes on it. The first solver to answer wins,
+// and a puzzle nobody answers is traded on its impact instead.
+pub trait PuzzleSolver: Send + Sync {
+    fn solve(&self, data: &PuzzleData) -> Option<PuzzleAnswer>;
+}
+
+// The solvers a connection starts with
+pub fn default_solvers() -> Vec<Box<dyn PuzzleSolver>> {
+    vec![Box::new(ArithmeticSolver)]
+}
+
+// Works out a question like "What is (12 + 4) * 3?". The longest run of numbers,
+// operators and brackets in the question is taken as the expression.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ArithmeticSolver;
+
+impl PuzzleSolver for ArithmeticSolver {
+    fn solve(&self, data: &PuzzleData) -> Option<PuzzleAnswer> {
+        let expression = find_expression(data.question.as_deref()?)?;
+        let value = evaluate(&expression)?;
+        value.is_finite().then_some(PuzzleAnswer::Number(value))
+    }
+}
+
+fn is_expression_char(c: char) -> bool {
+    c.is_ascii_digit() || c.is_whitespace() || "+-*/().×÷".contains(c)
+}
+
+// The longest stretch of expression characters with a digit in it, without the full
+// stop that might end the sentence
+fn find_expression(question: &str) -> Option<String> {
+    question
+        .split(|c: char| !is_expression_char(c))
+        .map(|run| run.trim().trim_end_matches('.').trim())
+        .filter(|run| run.chars().any(|c| c.is_ascii_digit()))
+        .max_by_key(|run| run.len())
+        .map(str::to_string)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token {
+    Number(f64),
+    Op(char),
+    Open,
+    Close,
+}
+
+fn tokenize(expression: &str) -> Option<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let mut number = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit() || **c == '.') {
+                    number.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Number(number.parse().ok()?));
+            }
+            '(' | ')' => {
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+                chars.next();
+            }
+            '×' => {
+                tokens.push(Token::Op('*'));
+                chars.next();
+            }
+            '÷' => {
+                tokens.push(Token::Op('/'));
+                chars.next();
+            }
+            '+' | '-' | '*' | '/' => {
+                tokens.push(Token::Op(c));
+                chars.next();
+            }
+            _ => return None,
+        }
+    }
+    Some(tokens)
+}
+
+// The usual precedence, with brackets and unary minus. None for anything malformed
+// or a division by zero.
+fn evaluate(expression: &str) -> Option<f64> {
+    let tokens = tokenize(expression)?;
+    let mut parser = Parser { tokens, at: 0 };
+    let value = parser.sum()?;
+    (parser.at == parser.tokens.len()).then_some(value)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    at: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<Token> {
+        self.tokens.get(self.at).copied()
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek();
+        self.at += 1;
+        token
+    }
+
+    fn sum(&mut self) -> Option<f64> {
+        let mut value = self.product()?;
+        while let Some(Token::Op(op @ ('+' | '-'))) = self.peek() {
+            self.at += 1;
+            let rhs = self.product()?;
+            value = if op == '+' { value + rhs } else { value - rhs };
+        }
+        Some(value)
+    }
+
+    fn product(&mut self) -> Option<f64> {
+        let mut value = self.factor()?;
+        while let Some(Token::Op(op @ ('*' | '/'))) = self.peek() {
+            self.at += 1;
+            let rhs = self.factor()?;
+            value = if op == '*' {
+                value * rhs
+            } else if rhs == 0.0 {
+                return None;
+            } else {
+                value / rhs
+            };
+        }
+        Some(value)
+    }
+
+    fn factor(&mut self) -> Option<f64> {
+        match self.next()? {
+            Token::Number(number) => Some(number),
+            Token::Op('-') => self.factor().map(|value| -value),
+            Token::Op('+') => self.factor(),
+            Token::Open => {
+                let value = self.sum()?;
+                (self.next()? == Token::Close).then_some(value)
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,1324 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::bandit::{Bandit, BanditConfig};
+use crate::caps::{Cap, CapCounts, CapHits};
+use crate::clock::{Clock, Monotonic, SystemClock};
+use crate::config::Config;
+use crate::console::ManualDesk;
+use crate::curve::PnlCurve;
+use crate::divergence::Reconnects;
+use crate::error::{BotError, DisconnectCounts, DisconnectReason};
+#[cfg(feature = "auto-optimize")]
+use crate::explore::Explorer;
+use crate::forecast::{ForecastAccuracy, ForecastConfig, ForecastSample};
+use crate::history::{BoundedHistory, RunningStats, Sampled};
+use crate::latency::{DecisionLatency, LatencySummary};
+#[cfg(feature = "puzzles")]
+use crate::puzzle::PuzzleTracker;
+use crate::risk::{
+    DrawdownBreaker, ExposureBook, ExposureConfig, ExposureUsage, HoldTimer, OutboundConfig,
+    OutboundCounts, OutboundKind, OutboundLimiter, Quarantine, QuarantineState, TradeBudget,
+    TradeBudgetConfig, TradeLimiter,
+};
+use crate::rt::{Mutex, RwLock};
+use crate::schedule::{GameClock, ScheduleConfig};
+use crate::strategy::{
+    median, AgreementConfig, DecisionMode, Driver, EnsembleConfig, OptimizerConfig, SignalMode,
+    Stance,
+};
+
+// State updates after a fill over which a trade's outcome is judged
+pub const TRADE_EVALUATION_WINDOW: usize = 3;
+// Reported PnLs kept per connection for display
+pub const RECENT_PNL_SIZE: usize = 60;
+
+// State structures
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SignalData {
+    pub conn_id: usize,
+    // Counts up from 1 over the connection's run
+    pub decision_id: u64,
+    pub timestamp: Monotonic,
+    // Name of the strategy that made the decision
+    pub strategy: &'static str,
+    pub momentum: f64,
+    pub forecast: f64,
+    pub combined_signal: f64,
+    pub trade_volume: i32,
+    pub position: i32,
+    // Whether the decision followed the momentum or faded it
+    pub mode: DecisionMode,
+    // Whether the momentum and forecast signals passed the agreement check, whether or
+    // not it's required
+    pub signals_agree: bool,
+    // What the agreement veto held back of the volume the strategy wanted
+    pub vetoed_volume: i32,
+    // What the account's exposure ceiling held back of what was left
+    pub capped_volume: i32,
+    // The strategy schedule's phase the params were in
+    pub schedule_phase: Option<String>,
+    // Which weighted signal did more to the combined one, and whether a held puzzle
+    // position traded in its place
+    pub driver: Driver,
+    pub puzzle_driven: bool,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PerformanceData {
+    pub conn_id: usize,
+    pub timestamp: Monotonic,
+    // Server momentum and forecast at the decision this PnL change is credited to
+    pub momentum: f64,
+    pub forecast: f64,
+    pub position: i32,
+    pub trade_volume: i32,
+    pub pnl_change: f64,
+    pub price: f64,
+    pub total_pnl: f64,
+    // Signal behind the position this PnL change was made on
+    pub signal: f64,
+    // The decision this PnL change is credited to, and what drove it
+    pub decision_id: u64,
+    pub driver: Driver,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionPerformance {
+    pub last_pnl: f64,
+    // False until the first state update of a session has set last_pnl
+    pub baseline_set: bool,
+    pub trades_made: usize,
+    pub successful_trades: usize,
+    pub rejected_trades: usize,
+    // Trades whose outcome has been judged, the denominator of the win rate
+    pub evaluated_trades: usize,
+    pub open_trade: Option<TradeOutcome>,
+    // Per-connection override set when this connection's win rate is poor
+    pub aggressive_factor: Option<f64>,
+    pub breaker: DrawdownBreaker,
+    // Observing only after losing while the other connections made money
+    pub quarantine: Quarantine,
+    pub limiter: TradeLimiter,
+    // Strategy trades held back by the rate limiter
+    pub rate_limited: usize,
+    // Trades sent this game, and trades away from flat held back once they were spent
+    pub budget: TradeBudget,
+    pub over_budget: usize,
+    // Error events the server sent us
+    pub server_errors: usize,
+    // Frames dropped for their size, and frames that didn't parse
+    pub oversized_frames: usize,
+    pub malformed_frames: usize,
+    // Frames waiting on the writer, as of the last enqueue or send
+    pub queued_messages: usize,
+    // Trades that sat in the queue too long and were dropped unsent
+    pub stale_trades: usize,
+    // State updates too old to trade on
+    pub stale_states: usize,
+    // State updates passed over for a newer one already queued
+    pub coalesced_states: usize,
+    // State updates dropped for NaN, infinite or impossible values
+    pub invalid_states: usize,
+    // State updates skipped for repeating the one before
+    pub duplicate_states: usize,
+    // Stage of the game as last seen from the server
+    pub phase: GamePhase,
+    // Trades held back because the game wasn't in a trading stage
+    pub held_out_of_phase: usize,
+    // Positions taken back to flat after being held too long without the signal
+    pub holds_expired: usize,
+    // Puzzles whose question a solver answered, and those only skipped or traded on
+    pub puzzles_solved: usize,
+    pub puzzles_skipped: usize,
+    // Client messages dropped at the account's outbound ceiling
+    pub outbound_dropped: OutboundCounts,
+    // The url this connection last connected to, tried first after a restart
+    pub url: Option<String>,
+    // How its sessions have ended
+    pub disconnects: DisconnectCounts,
+    // Decisions made over the run, the id of the latest
+    pub decisions: u64,
+    pub session: SessionContext,
+    // Latest reported PnLs, oldest first
+    pub recent_pnl: VecDeque<f64>,
+    // Change in the reported PnL on the latest update, None on a session's first
+    pub last_pnl_change: Option<f64>,
+    pub last_trade: Option<LastTrade>,
+    pub health: Health,
+    // Out of line with the other connections, by the divergence monitor
+    pub suspect: bool,
+    // From a frame arriving to the trade it led to being written to the socket
+    pub latency: DecisionLatency,
+    // This game's PnL over time, kept across reconnects until the finish
+    pub curve: PnlCurve,
+}
+
+// How one server url has been doing, across every connection using it
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct EndpointHealth {
+    // Connections on it right now
+    pub live: usize,
+    pub connects: usize,
+    pub connect_failures: usize,
+    // Sessions that ended in an error after connecting
+    pub session_failures: usize,
+    // Times a connection gave up on it for the next url
+    pub failovers: usize,
+    pub last_connected: Option<Monotonic>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndpointEvent {
+    Connected { conn_id: usize },
+    ConnectFailed,
+    Disconnected { failed: bool },
+    FailedOver,
+}
+
+// Where a connection's task is in its life, as set by the task and its supervisor
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Health {
+    #[default]
+    Connecting,
+    Live,
+    // Waiting to reconnect
+    Backoff,
+    // Stopped, or waiting out a quarantine before it's restarted
+    Dead,
+}
+
+impl fmt::Display for Health {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Health::Connecting => "connecting",
+            Health::Live => "live",
+            Health::Backoff => "backoff",
+            Health::Dead => "dead",
+        })
+    }
+}
+
+// What stage of the game a connection believes it's in. Trades only go out while
+// Trading; anything else just earns server errors.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GamePhase {
+    // Connected, waiting for the game to send a state
+    #[default]
+    Lobby,
+    Trading,
+    Puzzle,
+    Finished,
+    // A trade was turned down while we thought we were trading, so nothing goes out
+    // until the next state update says where the game is
+    Unknown,
+}
+
+impl GamePhase {
+    // Reads a stage or status field from the server, None when it's no word we know
+    pub fn from_stage(stage: &str) -> Option<GamePhase> {
+        let stage = stage.to_lowercase();
+        let words: Vec<&str> = stage
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect();
+        let mentions = |candidates: &[&str]| words.iter().any(|word| candidates.contains(word));
+        if mentions(&["puzzle", "puzzles"]) {
+            Some(GamePhase::Puzzle)
+        } else if mentions(&[
+            "finish",
+            "finished",
+            "end",
+            "ended",
+            "over",
+            "closed",
+            "complete",
+            "completed",
+            "done",
+        ]) {
+            Some(GamePhase::Finished)
+        } else if mentions(&[
+            "not", "lobby", "waiting", "pending", "idle", "starting", "paused",
+        ]) {
+            Some(GamePhase::Lobby)
+        } else if mentions(&[
+            "trading", "trade", "active", "open", "running", "live", "started",
+        ]) {
+            Some(GamePhase::Trading)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_trading(self) -> bool {
+        self == GamePhase::Trading
+    }
+}
+
+impl fmt::Display for GamePhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            GamePhase::Lobby => "lobby",
+            GamePhase::Trading => "trading",
+            GamePhase::Puzzle => "puzzle",
+            GamePhase::Finished => "finished",
+            GamePhase::Unknown => "unknown",
+        })
+    }
+}
+
+// The last trade a connection sent
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct LastTrade {
+    pub timestamp: Monotonic,
+    pub volume: i32,
+}
+
+// What a connection believes about the game it's in. Cleared for a new game; after a
+// reconnect mid-game it's kept until the first state update reconciles it.
+#[derive(Debug, Clone, Default)]
+pub struct SessionContext {
+    // Latest market view from state updates
+    pub position: Option<i32>,
+    pub position_limit: Option<i32>,
+    pub last_price: Option<f64>,
+    #[cfg(feature = "puzzles")]
+    pub puzzle: PuzzleTracker,
+    // Side the strategy last committed to, for the deadband's hysteresis
+    pub stance: Stance,
+    // Set by a reconnect mid-game until the server reports the position again
+    pub resync: Option<Resync>,
+    // State updates seen this game, for the wind-down countdown
+    pub updates_seen: u32,
+    // How far through the game the last update was, from 0 to 1, when its length is
+    // known
+    pub progress: Option<f64>,
+    // Where the latest decision fell in the game, and the strategy schedule's phase in
+    // force then
+    pub clock: GameClock,
+    pub schedule_phase: Option<String>,
+    // Only closing trades from here to the finish
+    pub winding_down: bool,
+    // How long the position has gone without the signal backing it
+    pub hold: HoldTimer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Resync {
+    // Where we thought we were when the connection dropped, if we knew
+    pub expected_position: Option<i32>,
+    // Volume of trades sent but never confirmed before the drop
+    pub unconfirmed_volume: i32,
+}
+
+// What the first state update after a reconnect changed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reconciliation {
+    pub expected_position: Option<i32>,
+    pub unconfirmed_volume: i32,
+    pub reported_position: i32,
+    pub dropped_puzzle: bool,
+    pub dropped_stance: Stance,
+}
+
+impl Reconciliation {
+    // Matching either side of an unconfirmed trade is fine, since it may not have filled
+    pub fn contradicts(&self) -> bool {
+        self.expected_position.is_some_and(|expected| {
+            self.reported_position != expected
+                && self.reported_position != expected - self.unconfirmed_volume
+        })
+    }
+}
+
+impl SessionContext {
+    pub fn reset(&mut self) {
+        *self = SessionContext::default();
+    }
+
+    // Hold what we believed until the server confirms or contradicts it
+    pub fn begin_resync(&mut self, pending: Option<&PendingTrade>) {
+        let (expected_position, unconfirmed_volume) = match pending {
+            Some(pending) => (Some(pending.expected_position()), pending.volume),
+            None => (self.position, 0),
+        };
+        self.resync = Some(Resync {
+            expected_position,
+            unconfirmed_volume,
+        });
+    }
+
+    pub fn is_resyncing(&self) -> bool {
+        self.resync.is_some()
+    }
+
+    // Note the limit from a state update, returning the previous one if the server changed it
+    pub fn observe_limit(&mut self, position_limit: i32) -> Option<i32> {
+        let previous = self.position_limit.replace(position_limit)?;
+        (previous != position_limit).then_some(previous)
+    }
+
+    // First state update after a reconnect: drop any intent formed on the old
+    // connection so nothing trades on stale assumptions
+    pub fn reconcile(&mut self, reported_position: i32) -> Option<Reconciliation> {
+        let resync = self.resync.take()?;
+        let reconciliation = Reconciliation {
+            expected_position: resync.expected_position,
+            unconfirmed_volume: resync.unconfirmed_volume,
+            reported_position,
+            #[cfg(feature = "puzzles")]
+            dropped_puzzle: self.puzzle.is_holding(),
+            #[cfg(not(feature = "puzzles"))]
+            dropped_puzzle: false,
+            dropped_stance: self.stance,
+        };
+        #[cfg(feature = "puzzles")]
+        self.puzzle.reset();
+        self.stance = Stance::Flat;
+        Some(reconciliation)
+    }
+}
+
+// Whether every other connection with `trades` judged trades made money over them,
+// and there's at least one such connection to go by
+pub fn others_profitable(
+    performances: &HashMap<usize, ConnectionPerformance>,
+    conn_id: usize,
+    trades: usize,
+) -> bool {
+    let mut others = performances
+        .iter()
+        .filter(|(&other, _)| other != conn_id)
+        .filter_map(|(_, perf)| perf.quarantine.rolling_pnl(trades))
+        .peekable();
+    others.peek().is_some() && others.all(|pnl| pnl > 0.0)
+}
+
+impl ConnectionPerformance {
+    pub fn next_decision_id(&mut self) -> u64 {
+        self.decisions += 1;
+        self.decisions
+    }
+
+    // PnL change since the last update, or None on the first update of a session
+    // since the reported PnL then includes everything earned before we connected
+    pub fn track_pnl(&mut self, pnl: f64) -> Option<f64> {
+        let change = self.baseline_set.then_some(pnl - self.last_pnl);
+        self.last_pnl = pnl;
+        self.baseline_set = true;
+        if self.recent_pnl.len() >= RECENT_PNL_SIZE {
+            self.recent_pnl.pop_front();
+        }
+        self.recent_pnl.push_back(pnl);
+        self.last_pnl_change = change;
+        change
+    }
+
+    // Called on reconnect and at the end of a game
+    pub fn reset_pnl_baseline(&mut self) {
+        self.baseline_set = false;
+    }
+
+    // Forget everything about the game just over: its PnL, its position and stance, and
+    // the trade still being judged, whose outcome went with the game
+    pub fn end_game(&mut self) {
+        self.reset_pnl_baseline();
+        self.last_pnl = 0.0;
+        self.recent_pnl.clear();
+        self.last_pnl_change = None;
+        self.open_trade = None;
+        self.session.reset();
+        self.curve = PnlCurve::default();
+        self.budget = TradeBudget::default();
+    }
+
+    // Start judging a newly filled trade, closing out the previous one first
+    pub fn open_trade(&mut self, position: i32) {
+        if let Some(outcome) = self.open_trade.take() {
+            self.record_outcome(&outcome);
+        }
+        self.open_trade = Some(TradeOutcome::new(position));
+    }
+
+    // Attribute a state update's PnL change to the trade being judged
+    pub fn observe_pnl(&mut self, pnl_change: f64, position: i32) {
+        if let Some(outcome) = self.open_trade.as_mut() {
+            if outcome.observe(pnl_change, position).is_some() {
+                let outcome = *outcome;
+                self.open_trade = None;
+                self.record_outcome(&outcome);
+            }
+        }
+    }
+
+    fn record_outcome(&mut self, outcome: &TradeOutcome) {
+        self.evaluated_trades += 1;
+        if outcome.is_win() {
+            self.successful_trades += 1;
+        }
+        self.quarantine.record_trade(outcome.realized_pnl);
+    }
+
+    pub fn win_rate(&self) -> Option<f64> {
+        if self.evaluated_trades == 0 {
+            return None;
+        }
+        Some(self.successful_trades as f64 / self.evaluated_trades as f64)
+    }
+}
+
+// PnL realized after a fill, until the window runs out or the position is reduced
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TradeOutcome {
+    pub position: i32,
+    pub realized_pnl: f64,
+    pub updates_seen: usize,
+}
+
+impl TradeOutcome {
+    pub fn new(position: i32) -> Self {
+        TradeOutcome {
+            position,
+            realized_pnl: 0.0,
+            updates_seen: 0,
+        }
+    }
+
+    // Returns whether the trade won once it has been fully judged
+    pub fn observe(&mut self, pnl_change: f64, position: i32) -> Option<bool> {
+        self.realized_pnl += pnl_change;
+        self.updates_seen += 1;
+
+        let reduced = position.abs() < self.position.abs();
+        if reduced || self.updates_seen >= TRADE_EVALUATION_WINDOW {
+            Some(self.is_win())
+        } else {
+            None
+        }
+    }
+
+    pub fn is_win(&self) -> bool {
+        self.realized_pnl > 0.0
+    }
+}
+
+// What a connection has sent and is waiting to see in the reported position. Every
+// decision sizes from the position plus this, so a server slow to show fills doesn't
+// get the same trade again on each state update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingTrade {
+    pub position_before: i32,
+    pub volume: i32,
+    pub trades: usize,
+    // State updates since it was sent in which the position didn't move
+    pub updates_waited: usize,
+}
+
+// What a state update says about the pending trades
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Settlement {
+    // The position moved by all of it
+    Filled(PendingTrade),
+    // Not all there yet, and still worth waiting for
+    Waiting(PendingTrade),
+    // Given up on: the position sat still too long, or went somewhere it wasn't sent
+    Unfilled(PendingTrade),
+}
+
+impl PendingTrade {
+    pub fn new(position_before: i32, volume: i32) -> Self {
+        PendingTrade {
+            position_before,
+            volume,
+            trades: 1,
+            updates_waited: 0,
+        }
+    }
+
+    // Fold another trade sent before the position was reported
+    pub fn add(&mut self, volume: i32) {
+        self.volume += volume;
+        self.trades += 1;
+    }
+
+    // Swap a trade the server rejected for what was sent in its place, if anything
+    pub fn revise(&mut self, rejected: i32, resent: i32) {
+        self.volume += resent - rejected;
+        if resent == 0 {
+            self.trades = self.trades.saturating_sub(1);
+        }
+    }
+
+    pub fn filled_by(&self, position: i32) -> bool {
+        position == self.position_before + self.volume
+    }
+
+    // Where the position stands once everything pending fills
+    pub fn expected_position(&self) -> i32 {
+        self.position_before + self.volume
+    }
+
+    // Check against a state update's position. A partial fill leaves the rest pending
+    // from the new position; a position that hasn't moved is waited on for up to
+    // max_updates state updates.
+    pub fn settle(mut self, position: i32, max_updates: usize) -> Settlement {
+        let filled = position - self.position_before;
+        if filled == self.volume {
+            return Settlement::Filled(self);
+        }
+        if filled == 0 {
+            self.updates_waited += 1;
+            return if self.updates_waited >= max_updates {
+                Settlement::Unfilled(self)
+            } else {
+                Settlement::Waiting(self)
+            };
+        }
+        if filled.signum() == self.volume.signum() && filled.abs() < self.volume.abs() {
+            self.position_before = position;
+            self.volume -= filled;
+            return Settlement::Waiting(self);
+        }
+        Settlement::Unfilled(self)
+    }
+}
+
+// The range each weight and the aggressive factor are held to, by the optimizer and
+// wherever else the live params are set from
+pub const MIN_WEIGHT: f64 = 0.05;
+pub const MAX_WEIGHT: f64 = 0.95;
+pub const MIN_AGGRESSIVE_FACTOR: f64 = 0.5;
+pub const MAX_AGGRESSIVE_FACTOR: f64 = 3.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StrategyParams {
+    pub momentum_weight: f64,
+    pub forecast_weight: f64,
+    // Signals are tanh(momentum / momentum_divisor) and tanh(forecast * forecast_multiplier)
+    pub momentum_divisor: f64,
+    pub forecast_multiplier: f64,
+    pub strong_momentum_threshold: f64,
+    pub medium_momentum_threshold: f64,
+    // Momentum beyond this that the forecast disagrees with is faded by blend_fade
+    pub extreme_momentum_threshold: f64,
+    pub aggressive_factor: f64,
+    // Share of the base size taken when momentum is below the medium threshold (0 skips)
+    pub weak_momentum_fraction: f64,
+    pub sizing: Sizing,
+    // Signals within this distance of zero don't trade or flip the current stance
+    pub deadband: f64,
+    // Realized volatility above which positions shrink in proportion (unset never shrinks)
+    pub max_volatility: Option<f64>,
+    // Share of the full Kelly fraction taken with Kelly sizing (0.5 = half Kelly)
+    pub kelly_multiplier: f64,
+    // Samples needed in a signal bucket before Kelly sizing replaces proportional
+    pub kelly_min_samples: usize,
+}
+
+// How a signal is turned into a position
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Sizing {
+    // Any nonzero signal goes to the full position limit
+    #[default]
+    AllIn,
+    // Target position scales with the signal strength
+    Proportional,
+    // Fractional Kelly on the win rate and payoff seen at similar signal strengths,
+    // proportional until there's enough history to estimate them
+    Kelly,
+}
+
+impl StrategyParams {
+    // Checked for the config file and for every live update
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.momentum_weight >= 0.0 && self.forecast_weight >= 0.0) {
+            return Err("momentum_weight and forecast_weight must be non-negative".to_string());
+        }
+        if !(self.momentum_divisor > 0.0 && self.forecast_multiplier > 0.0) {
+            return Err("momentum_divisor and forecast_multiplier must be positive".to_string());
+        }
+        if !(self.medium_momentum_threshold >= 0.0
+            && self.medium_momentum_threshold <= self.strong_momentum_threshold)
+        {
+            return Err(format!(
+                "medium_momentum_threshold ({}) must be between 0 and strong_momentum_threshold ({})",
+                self.medium_momentum_threshold, self.strong_momentum_threshold
+            ));
+        }
+        if self.extreme_momentum_threshold.is_nan()
+            || self.extreme_momentum_threshold <= self.strong_momentum_threshold
+        {
+            return Err(format!(
+                "extreme_momentum_threshold ({}) must be above strong_momentum_threshold ({})",
+                self.extreme_momentum_threshold, self.strong_momentum_threshold
+            ));
+        }
+        if !(self.aggressive_factor > 0.0
+            && self.deadband >= 0.0
+            && self.kelly_multiplier >= 0.0
+            && (0.0..=1.0).contains(&self.weak_momentum_fraction)
+            && self.max_volatility.is_none_or(|max| max > 0.0))
+        {
+            return Err(
+                "aggressive_factor and max_volatility must be positive, deadband and \
+                 kelly_multiplier non-negative, and weak_momentum_fraction between 0 and 1"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    // Checked for params about to become the live ones, from the config file, the
+    // control endpoint or a previous run
+    pub fn within_bounds(&self) -> Result<(), String> {
+        let weights = MIN_WEIGHT..=MAX_WEIGHT;
+        if !(weights.contains(&self.momentum_weight)
+            && weights.contains(&self.forecast_weight)
+            && (MIN_AGGRESSIVE_FACTOR..=MAX_AGGRESSIVE_FACTOR).contains(&self.aggressive_factor))
+        {
+            return Err(format!(
+                "momentum_weight and forecast_weight must be between {} and {}, and \
+                 aggressive_factor between {} and {}",
+                MIN_WEIGHT, MAX_WEIGHT, MIN_AGGRESSIVE_FACTOR, MAX_AGGRESSIVE_FACTOR
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for StrategyParams {
+    fn default() -> Self {
+        StrategyParams {
+            momentum_weight: 0.6,
+            forecast_weight: 0.4,
+            momentum_divisor: 10.0,
+            forecast_multiplier: 2.0,
+            strong_momentum_threshold: 10.0,
+            medium_momentum_threshold: 5.0,
+            extreme_momentum_threshold: 20.0,
+            aggressive_factor: 1.5,
+            weak_momentum_fraction: 0.5,
+            sizing: Sizing::AllIn,
+            deadband: 0.15,
+            max_volatility: None,
+            kelly_multiplier: 0.5,
+            kelly_min_samples: 10,
+        }
+    }
+}
+
+// Consistent point-in-time copy of the shared state, for the dashboard and anything
+// else reading from outside the trade path
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct StateSnapshot {
+    // On the same clock as the history and last trade timestamps
+    pub taken_at: Monotonic,
+    pub params: StrategyParams,
+    // False while the kill switch is on
+    pub trading_enabled: bool,
+    // Ordered by conn_id
+    pub connections: Vec<ConnectionSnapshot>,
+    // Sum of every connection's latest PnL
+    pub total_pnl: f64,
+    // Client messages over the last minute, against the outbound ceiling
+    pub outbound_per_minute: usize,
+    // Rolling stats of the combined signal
+    pub trade_history: HistorySummary<SignalData>,
+    // Rolling stats of the PnL change
+    pub performance_history: HistorySummary<PerformanceData>,
+    // Health of each url, to spot one that's flapping
+    pub endpoints: BTreeMap<String, EndpointHealth>,
+    // Entries dropped to stay within each cap, over every account
+    pub cap_hits: CapCounts,
+    // The account's net position against its exposure ceiling
+    pub exposure: ExposureUsage,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ConnectionSnapshot {
+    pub conn_id: usize,
+    pub price: Option<f64>,
+    pub position: Option<i32>,
+    pub position_limit: Option<i32>,
+    pub pnl: f64,
+    pub recent_pnl: Vec<f64>,
+    pub last_trade: Option<LastTrade>,
+    // Latest combined signal the connection published
+    pub signal: Option<f64>,
+    // Latest decision the connection recorded
+    pub last_decision: Option<SignalData>,
+    pub trades: usize,
+    pub rejected_trades: usize,
+    pub rate_limited: usize,
+    // Trades left in this game's budgets, when there are any
+    pub trade_budget_left: Option<u32>,
+    pub puzzle_budget_left: Option<u32>,
+    pub over_budget: usize,
+    pub server_errors: usize,
+    pub oversized_frames: usize,
+    pub malformed_frames: usize,
+    pub queued_messages: usize,
+    pub stale_trades: usize,
+    pub stale_states: usize,
+    pub coalesced_states: usize,
+    pub invalid_states: usize,
+    pub duplicate_states: usize,
+    pub phase: GamePhase,
+    pub held_out_of_phase: usize,
+    // Client messages sent over the last minute
+    pub outbound: OutboundCounts,
+    pub outbound_dropped: OutboundCounts,
+    // The url last connected to
+    pub url: Option<String>,
+    pub disconnects: DisconnectCounts,
+    // The strategy schedule's phase at the latest decision
+    pub schedule_phase: Option<String>,
+    pub win_rate: Option<f64>,
+    pub aggressive_factor: Option<f64>,
+    pub halted: bool,
+    pub quarantine: QuarantineState,
+    pub health: Health,
+    // Out of line with the other connections' price or PnL
+    pub suspect: bool,
+    // Over the latest trades
+    pub latency: Option<LatencySummary>,
+}
+
+// A history boiled down rather than copied
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct HistorySummary<T> {
+    pub len: usize,
+    pub last: Option<T>,
+    pub mean: Option<f64>,
+    pub std_dev: Option<f64>,
+    // Share of entries above zero
+    pub win_rate: Option<f64>,
+}
+
+impl<T: Clone + Sampled> HistorySummary<T> {
+    fn of(history: &BoundedHistory<T>) -> Self {
+        let stats = history.stats();
+        HistorySummary {
+            len: history.len(),
+            last: history.back().cloned(),
+            mean: (stats.count > 0).then_some(stats.mean),
+            std_dev: stats.std_dev(),
+            win_rate: stats.win_rate(),
+        }
+    }
+}
+
+impl Sampled for SignalData {
+    fn sample(&self) -> f64 {
+        self.combined_signal
+    }
+}
+
+impl PerformanceData {
+    // A NaN or infinity here would poison the window's running stats for good
+    pub fn is_finite(&self) -> bool {
+        [
+            self.momentum,
+            self.forecast,
+            self.pnl_change,
+            self.price,
+            self.total_pnl,
+            self.signal,
+        ]
+        .iter()
+        .all(|value| value.is_finite())
+    }
+}
+
+impl Sampled for PerformanceData {
+    fn sample(&self) -> f64 {
+        self.pnl_change
+    }
+}
+
+// Shared state.
+//
+// Code holding more than one of these locks at a time takes them in this order, and
+// no other, so nothing can deadlock: explorer, strategy_params, connection_performance,
+// performance_history, trade_history, latest_signals, forecast_samples, endpoints,
+// bandit, outbound_limiter, exposure_book.
+// last_optimization, last_game_optimization and reloaded_config are only ever held
+// on their own, and trading_enabled isn't a lock.
+pub struct SharedState {
+    pub strategy_params: RwLock<StrategyParams>,
+    pub trade_history: Mutex<BoundedHistory<SignalData>>,
+    pub performance_history: Mutex<BoundedHistory<PerformanceData>>,
+    pub connection_performance: Mutex<HashMap<usize, ConnectionPerformance>>,
+    #[cfg(feature = "auto-optimize")]
+    pub last_optimization: RwLock<Monotonic>,
+    #[cfg(feature = "auto-optimize")]
+    pub optimization_interval: f64,
+    // When the last pass over a finished game ran, so a game gets only one
+    #[cfg(feature = "auto-optimize")]
+    pub last_game_optimization: Mutex<Option<Monotonic>>,
+    pub optimizer: OptimizerConfig,
+    // Exploratory param changes the optimizer has yet to judge
+    #[cfg(feature = "auto-optimize")]
+    pub explorer: Mutex<Explorer>,
+    // The strategy arm each connection trades, when arms are configured
+    pub bandit: Mutex<Bandit>,
+    pub schedule: ScheduleConfig,
+    pub ensemble: EnsembleConfig,
+    pub agreement: AgreementConfig,
+    // Each connection's latest combined signal, for the ensemble consensus
+    pub latest_signals: Mutex<HashMap<usize, PublishedSignal>>,
+    pub forecast: ForecastConfig,
+    // How past forecasts played out, oldest first, across all connections
+    pub forecast_samples: Mutex<VecDeque<ForecastSample>>,
+    // Health of each url connections have tried, by url
+    pub endpoints: Mutex<BTreeMap<String, EndpointHealth>>,
+    // Kill switch, turned off and on again from the control endpoint. Shared by every
+    // account in the process.
+    pub trading_enabled: Arc<AtomicBool>,
+    pub outbound: OutboundConfig,
+    // Every client message this account sent over the last minute
+    pub outbound_limiter: Mutex<OutboundLimiter>,
+    pub exposure: ExposureConfig,
+    // Each connection's position this game, against the account's exposure ceiling
+    pub exposure_book: Mutex<ExposureBook>,
+    pub trade_budget: TradeBudgetConfig,
+    // The account's config with what a reload changed, once the file has been reloaded
+    pub reloaded_config: RwLock<Option<Arc<Config>>>,
+    // Orders typed at the console, waiting on each connection
+    pub manual: ManualDesk,
+    // Connections the divergence monitor wants to start a fresh session
+    pub reconnects: Reconnects,
+    // Shared by every account, like the kill switch
+    pub cap_hits: Arc<CapHits>,
+    pub clock: Arc<dyn Clock>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PublishedSignal {
+    pub signal: f64,
+    pub timestamp: Monotonic,
+}
+
+impl SharedState {
+    pub fn new(config: &Config) -> Self {
+        Self::with_clock(config, Arc::new(SystemClock::default()))
+    }
+
+    pub fn with_clock(config: &Config, clock: Arc<dyn Clock>) -> Self {
+        SharedState {
+            strategy_params: RwLock::new(config.strategy.clone()),
+            trade_history: Mutex::new(BoundedHistory::new(config.history.size)),
+            performance_history: Mutex::new(BoundedHistory::new(config.history.size)),
+            connection_performance: Mutex::new(HashMap::with_capacity(config.connections)),
+            #[cfg(feature = "auto-optimize")]
+            last_optimization: RwLock::new(clock.monotonic()),
+            #[cfg(feature = "auto-optimize")]
+            optimization_interval: 30.0,
+            #[cfg(feature = "auto-optimize")]
+            last_game_optimization: Mutex::new(None),
+            optimizer: config.optimizer.clone(),
+            #[cfg(feature = "auto-optimize")]
+            explorer: Mutex::new(Explorer::new(&config.optimizer)),
+            // Arms only take turns while the optimizer runs
+            bandit: Mutex::new(if config.optimizer_enabled() {
+                Bandit::new(&config.bandit, config.optimizer.seed)
+            } else {
+                Bandit::new(&BanditConfig::default(), None)
+            }),
+            schedule: config.schedule.clone(),
+            ensemble: config.ensemble.clone(),
+            agreement: config.agreement.clone(),
+            latest_signals: Mutex::new(HashMap::with_capacity(config.connections)),
+            forecast: config.forecast.clone(),
+            forecast_samples: Mutex::new(VecDeque::with_capacity(config.forecast.window)),
+            endpoints: Mutex::new(BTreeMap::new()),
+            trading_enabled: Arc::new(AtomicBool::new(config.trading_enabled)),
+            outbound: config.outbound.clone(),
+            outbound_limiter: Mutex::new(OutboundLimiter::default()),
+            exposure: config.exposure.clone(),
+            exposure_book: Mutex::new(ExposureBook::default()),
+            trade_budget: config.trade_budget.clone(),
+            reloaded_config: RwLock::new(None),
+            manual: ManualDesk::new(config.connections),
+            reconnects: Reconnects::new(config.connections),
+            cap_hits: Arc::new(CapHits::default()),
+            clock,
+        }
+    }
+
+    pub fn now(&self) -> f64 {
+        self.clock.now()
+    }
+
+    pub fn monotonic(&self) -> Monotonic {
+        self.clock.monotonic()
+    }
+
+    // Share another state's kill switch, so stopping one account stops them all
+    pub fn with_kill_switch(mut self, kill_switch: Arc<AtomicBool>) -> Self {
+        self.trading_enabled = kill_switch;
+        self
+    }
+
+    // Count another state's cap hits along with this one's
+    pub fn with_cap_hits(mut self, cap_hits: Arc<CapHits>) -> Self {
+        self.cap_hits = cap_hits;
+        self
+    }
+
+    pub fn kill_switch(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.trading_enabled)
+    }
+
+    pub fn trading_enabled(&self) -> bool {
+        self.trading_enabled.load(Ordering::SeqCst)
+    }
+
+    // Returns whether trading was enabled before
+    pub fn set_trading_enabled(&self, enabled: bool) -> bool {
+        self.trading_enabled.swap(enabled, Ordering::SeqCst)
+    }
+
+    // Snapshot of the params with the connection's arm and any per-connection
+    // overrides applied, and the forecast weighted by how well the forecast has been doing
+    // The params as of the connection's latest decision
+    pub async fn params_for(&self, conn_id: usize) -> StrategyParams {
+        let clock = if self.schedule.is_enabled() {
+            self.connection_performance
+                .lock()
+                .await
+                .get(&conn_id)
+                .map(|perf| perf.session.clock)
+                .unwrap_or_default()
+        } else {
+            GameClock::default()
+        };
+        self.scheduled_params(conn_id, clock).await
+    }
+
+    // The params for a decision at this point in the game, with the schedule's phases
+    // laid over the base before anything else. The phase in force is kept on the
+    // connection for the decision to be attributed to.
+    pub async fn scheduled_params(&self, conn_id: usize, clock: GameClock) -> StrategyParams {
+        let mut params = self.strategy_params.read().await.clone();
+        if self.schedule.is_enabled() {
+            let phase = self.schedule.apply(&mut params, clock).map(str::to_string);
+            let mut performances = self.connection_performance.lock().await;
+            let session = &mut performances.entry(conn_id).or_default().session;
+            session.clock = clock;
+            session.schedule_phase = phase;
+        }
+        if let Some(arm) = self.bandit.lock().await.arm_for(conn_id) {
+            arm.apply(&mut params);
+        }
+        {
+            let performances = self.connection_performance.lock().await;
+            if let Some(factor) = performances.get(&conn_id).and_then(|p| p.aggressive_factor) {
+                params.aggressive_factor = factor;
+            }
+        }
+        params.forecast_weight *= self.forecast_accuracy().await.weight_scale(&self.forecast);
+        params
+    }
+
+    pub async fn record_forecast(&self, sample: ForecastSample) {
+        let mut samples = self.forecast_samples.lock().await;
+        if samples.len() >= self.forecast.window && samples.pop_front().is_some() {
+            self.cap_hits.hit(Cap::ForecastSamples);
+        }
+        samples.push_back(sample);
+    }
+
+    pub async fn forecast_accuracy(&self) -> ForecastAccuracy {
+        ForecastAccuracy::from_samples(self.forecast_samples.lock().await.iter())
+    }
+
+    // Add to history, dropping the oldest entry once full
+    pub async fn record_signal(&self, signal_data: SignalData) {
+        if self.trade_history.lock().await.push(signal_data).is_some() {
+            self.cap_hits.hit(Cap::TradeHistory);
+        }
+    }
+
+    // The connection's most recent decision still in the history
+    pub async fn last_decision(&self, conn_id: usize) -> Option<SignalData> {
+        self.trade_history
+            .lock()
+            .await
+            .iter()
+            .rev()
+            .find(|signal| signal.conn_id == conn_id)
+            .cloned()
+    }
+
+    // Publish this connection's signal. In ensemble mode, returns the median of every
+    // fresh signal once a quorum has published; None means trade on our own.
+    pub async fn ensemble_signal(&self, conn_id: usize, signal: f64) -> Option<f64> {
+        let now = self.monotonic();
+        let mut latest = self.latest_signals.lock().await;
+        latest.insert(
+            conn_id,
+            PublishedSignal {
+                signal,
+                timestamp: now,
+            },
+        );
+        if self.ensemble.mode != SignalMode::Ensemble {
+            return None;
+        }
+
+        latest.retain(|_, published| now - published.timestamp <= self.ensemble.max_age_secs);
+        if latest.len() < self.ensemble.quorum {
+            return None;
+        }
+        let mut signals: Vec<f64> = latest.values().map(|published| published.signal).collect();
+        median(&mut signals)
+    }
+
+    // Takes every lock it reads, in the documented order, and holds them together so
+    // the copy isn't torn. Only small values are cloned while they're held.
+    pub async fn snapshot(&self) -> StateSnapshot {
+        let params = self.strategy_params.read().await;
+        let performances = self.connection_performance.lock().await;
+        let performance_history = self.performance_history.lock().await;
+        let trade_history = self.trade_history.lock().await;
+        let latest = self.latest_signals.lock().await;
+        let outbound = self.outbound_limiter.lock().await;
+        let now = self.monotonic().secs();
+
+        let mut connections: Vec<ConnectionSnapshot> = performances
+            .iter()
+            .map(|(&conn_id, perf)| ConnectionSnapshot {
+                conn_id,
+                price: perf.session.last_price,
+                position: perf.session.position,
+                position_limit: perf.session.position_limit,
+                pnl: perf.last_pnl,
+                recent_pnl: perf.recent_pnl.iter().copied().collect(),
+                last_trade: perf.last_trade,
+                signal: latest.get(&conn_id).map(|published| published.signal),
+                last_decision: trade_history
+                    .iter()
+                    .rev()
+                    .find(|signal| signal.conn_id == conn_id)
+                    .cloned(),
+                trades: perf.trades_made,
+                rejected_trades: perf.rejected_trades,
+                rate_limited: perf.rate_limited,
+                trade_budget_left: perf.budget.trades_left(&self.trade_budget),
+                puzzle_budget_left: perf.budget.puzzle_left(&self.trade_budget),
+                over_budget: perf.over_budget,
+                server_errors: perf.server_errors,
+                oversized_frames: perf.oversized_frames,
+                malformed_frames: perf.malformed_frames,
+                queued_messages: perf.queued_messages,
+                stale_trades: perf.stale_trades,
+                stale_states: perf.stale_states,
+                coalesced_states: perf.coalesced_states,
+                invalid_states: perf.invalid_states,
+                duplicate_states: perf.duplicate_states,
+                phase: perf.phase,
+                held_out_of_phase: perf.held_out_of_phase,
+                outbound: outbound.counts(conn_id, now),
+                outbound_dropped: perf.outbound_dropped,
+                url: perf.url.clone(),
+                disconnects: perf.disconnects,
+                schedule_phase: perf.session.schedule_phase.clone(),
+                win_rate: perf.win_rate(),
+                aggressive_factor: perf.aggressive_factor,
+                halted: perf.breaker.is_halted(),
+                quarantine: perf.quarantine.state,
+                health: perf.health,
+                suspect: perf.suspect,
+                latency: perf.latency.recent(),
+            })
+            .collect();
+        let params_copy = params.clone();
+        let trade_summary = HistorySummary::of(&trade_history);
+        let performance_summary = HistorySummary::of(&performance_history);
+        let outbound_per_minute = outbound.total(now);
+        drop((
+            outbound,
+            latest,
+            trade_history,
+            performance_history,
+            performances,
+            params,
+        ));
+
+        connections.sort_unstable_by_key(|connection| connection.conn_id);
+        let endpoints = self.endpoints.lock().await.clone();
+        let exposure = self.exposure_book.lock().await.usage(&self.exposure);
+        StateSnapshot {
+            taken_at: self.monotonic(),
+            params: params_copy,
+            trading_enabled: self.trading_enabled(),
+            total_pnl: connections.iter().map(|connection| connection.pnl).sum(),
+            outbound_per_minute,
+            connections,
+            trade_history: trade_summary,
+            performance_history: performance_summary,
+            endpoints,
+            cap_hits: self.cap_hits.counts(),
+            exposure,
+        }
+    }
+
+    pub async fn record_performance(&self, perf_data: PerformanceData) {
+        // Invalid updates are dropped before this, but nothing non-finite gets in regardless
+        if !perf_data.is_finite() {
+            return;
+        }
+        let (conn_id, pnl_change) = (perf_data.conn_id, perf_data.pnl_change);
+        if self
+            .performance_history
+            .lock()
+            .await
+            .push(perf_data)
+            .is_some()
+        {
+            self.cap_hits.hit(Cap::PerformanceHistory);
+        }
+        self.bandit.lock().await.observe(conn_id, pnl_change);
+    }
+
+    // Name of the arm the connection is trading, if it's been given one
+    pub async fn arm_name(&self, conn_id: usize) -> Option<String> {
+        let bandit = self.bandit.lock().await;
+        bandit.assigned(conn_id).map(|arm| arm.name.clone())
+    }
+
+    // Running stats over the PnL changes in the performance window
+    pub async fn performance_stats(&self) -> RunningStats {
+        *self.performance_history.lock().await.stats()
+    }
+
+    pub async fn set_queue_depth(&self, conn_id: usize, depth: usize) {
+        let mut performances = self.connection_performance.lock().await;
+        performances.entry(conn_id).or_default().queued_messages = depth;
+    }
+
+    pub async fn record_stale_trade(&self, conn_id: usize) {
+        let mut performances = self.connection_performance.lock().await;
+        performances.entry(conn_id).or_default().stale_trades += 1;
+    }
+
+    // Count a client message about to be sent, or return false if the account is at
+    // its outbound ceiling and it has to be dropped
+    pub async fn admit_outbound(&self, conn_id: usize, kind: OutboundKind) -> bool {
+        let now = self.monotonic().secs();
+        let admitted = self
+            .outbound_limiter
+            .lock()
+            .await
+            .admit(&self.outbound, conn_id, kind, now);
+        if !admitted {
+            let mut performances = self.connection_performance.lock().await;
+            performances
+                .entry(conn_id)
+                .or_default()
+                .outbound_dropped
+                .add(kind);
+        }
+        admitted
+    }
+
+    pub async fn record_latency(&self, conn_id: usize, latency: Duration) {
+        let mut performances = self.connection_performance.lock().await;
+        let perf = performances.entry(conn_id).or_default();
+        if perf.latency.record(latency) {
+            self.cap_hits.hit(Cap::GameLatencies);
+        }
+    }
+
+    pub async fn record_endpoint(&self, url: &str, event: EndpointEvent) {
+        if let EndpointEvent::Connected { conn_id } = event {
+            let mut performances = self.connection_performance.lock().await;
+            performances.entry(conn_id).or_default().url = Some(url.to_string());
+        }
+        let mut endpoints = self.endpoints.lock().await;
+        let endpoint = endpoints.entry(url.to_string()).or_default();
+        match event {
+            EndpointEvent::Connected { .. } => {
+                endpoint.live += 1;
+                endpoint.connects += 1;
+                endpoint.last_connected = Some(self.monotonic());
+            }
+            EndpointEvent::ConnectFailed => endpoint.connect_failures += 1,
+            EndpointEvent::Disconnected { failed } => {
+                endpoint.live = endpoint.live.saturating_sub(1);
+                endpoint.session_failures += usize::from(failed);
+            }
+            EndpointEvent::FailedOver => endpoint.failovers += 1,
+        }
+    }
+
+    // The url a connection last got through to, if it ever has
+    pub async fn connection_url(&self, conn_id: usize) -> Option<String> {
+        let performances = self.connection_performance.lock().await;
+        performances.get(&conn_id).and_then(|perf| perf.url.clone())
+    }
+
+    pub async fn record_disconnect(
+        &self,
+        conn_id: usize,
+        outcome: &Result<DisconnectReason, BotError>,
+    ) {
+        self.connection_performance
+            .lock()
+            .await
+            .entry(conn_id)
+            .or_default()
+            .disconnects
+            .record(outcome);
+    }
+
+    pub async fn record_coalesced(&self, conn_id: usize) {
+        self.connection_performance
+            .lock()
+            .await
+            .entry(conn_id)
+            .or_default()
+            .coalesced_states += 1;
+    }
+
+    pub async fn set_health(&self, conn_id: usize, health: Health) {
+        self.connection_performance
+            .lock()
+            .await
+            .entry(conn_id)
+            .or_default()
+            .health = health;
+        // Whatever it held is out of sight until it reports again
+        if health != Health::Live {
+            self.exposure_book.lock().await.lose_track(conn_id);
+        }
+    }
+
+    // Whether any connection trading is in the last `fraction` of its game
+    pub async fn in_final_stretch(&self, fraction: f64) -> bool {
+        self.connection_performance
+            .lock()
+            .await
+            .values()
+            .filter(|perf| perf.health == Health::Live && perf.phase.is_trading())
+            .filter_map(|perf| perf.session.progress)
+            .any(|progress| progress >= 1.0 - fraction)
+    }
+
+    // Mark the connections the divergence monitor suspects, and only those
+    pub async fn set_suspects(&self, suspects: &[usize]) {
+        let mut performances = self.connection_performance.lock().await;
+        for (conn_id, perf) in performances.iter_mut() {
+            perf.suspect = suspects.contains(conn_id);
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use rusqlite::{params, Connection, Transaction};
+use std::path::Path;
+
+use crate::journal::{ConnectionEvent, JournalEntry, ParamChangeEntry, Record};
+use crate::summary::GameSummary;
+
+// Applied in order to take a database from one user_version to the next. Only ever
+// append to this; a database already past a step never sees it again.
+const MIGRATIONS: &[&str] = &[CREATE_TABLES, ADD_DECISION_DRIVERS];
+
+const CREATE_TABLES: &str = "CREATE TABLE decisions (
+        id INTEGER PRIMARY KEY,
+        timestamp REAL NOT NULL,
+        account TEXT NOT NULL,
+        conn_id INTEGER NOT NULL,
+        strategy TEXT NOT NULL,
+        price REAL NOT NULL,
+        forecast REAL NOT NULL,
+        momentum REAL NOT NULL,
+        combined_signal REAL NOT NULL,
+        position_before INTEGER NOT NULL,
+        position_after INTEGER NOT NULL,
+        volume INTEGER NOT NULL,
+        sent INTEGER NOT NULL,
+        pnl REAL NOT NULL,
+        pnl_change REAL,
+        mode TEXT NOT NULL,
+        arm TEXT
+    );
+    CREATE INDEX decisions_by_time ON decisions (timestamp);
+    CREATE TABLE games (
+        id INTEGER PRIMARY KEY,
+        account TEXT NOT NULL,
+        conn_id INTEGER NOT NULL,
+        strategy TEXT NOT NULL,
+        started_at REAL NOT NULL,
+        finished_at REAL NOT NULL,
+        trades INTEGER NOT NULL,
+        win_rate REAL,
+        final_pnl REAL,
+        simulated INTEGER NOT NULL,
+        summary TEXT NOT NULL
+    );
+    CREATE TABLE param_changes (
+        id INTEGER PRIMARY KEY,
+        timestamp REAL NOT NULL,
+        account TEXT NOT NULL,
+        param TEXT NOT NULL,
+        before REAL NOT NULL,
+        after REAL NOT NULL
+    );
+    CREATE TABLE connection_events (
+        id INTEGER PRIMARY KEY,
+        timestamp REAL NOT NULL,
+        account TEXT NOT NULL,
+        conn_id INTEGER NOT NULL,
+        event TEXT NOT NULL,
+        detail TEXT
+    );";
+
+const ADD_DECISION_DRIVERS: &str = "ALTER TABLE decisions ADD COLUMN decision_id INTEGER;
+    ALTER TABLE decisions ADD COLUMN driver TEXT;";
+
+// The journal as a SQLite database, for history that can be queried across runs
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    // Open or create the database and bring its schema up to date. A file that isn't a
+    // database fails here rather than on the first write.
+    pub fn open(path: &Path) -> rusqlite::Result<SqliteStore> {
+        let mut conn = Connection::open(path)?;
+        migrate(&mut conn)?;
+        Ok(SqliteStore { conn })
+    }
+
+    // All of a batch goes in one transaction, or none of it does
+    pub fn write(&mut self, records: &[Record]) -> rusqlite::Result<()> {
+        let tx = self.conn.transaction()?;
+        for record in records {
+            match record {
+                Record::Decision(entry) => insert_decision(&tx, entry)?,
+                Record::Game(summary) => insert_game(&tx, summary)?,
+                Record::ParamChange(change) => insert_param_change(&tx, change)?,
+                Record::Connection(event) => insert_connection_event(&tx, event)?,
+            }
+        }
+        tx.commit()
+    }
+}
+
+fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version >= MIGRATIONS.len() {
+        return Ok(());
+    }
+    let tx = conn.transaction()?;
+    for migration in &MIGRATIONS[version..] {
+        tx.execute_batch(migration)?;
+    }
+    tx.pragma_update(None, "user_version", MIGRATIONS.len())?;
+    tx.commit()
+}
+
+fn insert_decision(tx: &Transaction, entry: &JournalEntry) -> rusqlite::Result<()> {
+    tx.prepare_cached(
+        "INSERT INTO decisions (timestamp, account, conn_id, strategy, price, forecast,
+            momentum, combined_signal, position_before, position_after, volume, sent, pnl,
+            pnl_change, mode, arm, decision_id, driver)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17,
+            ?18)",
+    )?
+    .execute(params![
+        entry.timestamp,
+        entry.account,
+        entry.conn_id,
+        entry.strategy,
+        entry.price,
+        entry.forecast,
+        entry.momentum,
+        entry.combined_signal,
+        entry.position_before,
+        entry.position_after,
+        entry.volume,
+        entry.sent,
+        entry.pnl,
+        entry.pnl_change,
+        entry.mode.to_string(),
+        entry.arm,
+        entry.decision_id,
+        entry.driver.map(|driver| driver.to_string()),
+    ])?;
+    Ok(())
+}
+
+fn insert_game(tx: &Transaction, summary: &GameSummary) -> rusqlite::Result<()> {
+    let json = serde_json::to_string(summary).expect("game summaries always serialize");
+    tx.prepare_cached(
+        "INSERT INTO games (account, conn_id, strategy, started_at, finished_at, trades,
+            win_rate, final_pnl, simulated, summary)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+    )?
+    .execute(params![
+        summary.account,
+        summary.conn_id,
+        summary.strategy,
+        summary.started_at,
+        summary.finished_at,
+        summary.trades,
+        summary.win_rate,
+        summary.final_pnl,
+        summary.simulated,
+        json,
+    ])?;
+    Ok(())
+}
+
+fn insert_param_change(tx: &Transaction, change: &ParamChangeEntry) -> rusqlite::Result<()> {
+    tx.prepare_cached(
+        "INSERT INTO param_changes (timestamp, account, param, before, after)
+        VALUES (?1, ?2, ?3, ?4, ?5)",
+    )?
+    .execute(params![
+        change.timestamp,
+        change.account,
+        change.param,
+        change.before,
+        change.after,
+    ])?;
+    Ok(())
+}
+
+fn insert_connection_event(tx: &Transaction, event: &ConnectionEvent) -> rusqlite::Result<()> {
+    tx.prepare_cached(
+        "INSERT INTO connection_events (timestamp, account, conn_id, event, detail)
+        VALUES (?1, ?2, ?3, ?4, ?5)",
+    )?
+    .execute(params![
+        event.timestamp,
+        event.account,
+        event.conn_id,
+        event.kind.to_string(),
+        event.detail,
+    ])?;
+    Ok(())
+}
+
+// Every decision in the database, oldest first, with the rows that can't be read
+// counted rather than failing the lot
+pub fn read_decisions(path: &Path) -> rusqlite::Result<(Vec<JournalEntry>, usize)> {
+    let conn = Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut statement = conn.prepare(
+        "SELECT timestamp, account, conn_id, strategy, price, forecast, momentum,
+            combined_signal, position_before, position_after, volume, sent, pnl, pnl_change,
+            mode, arm, decision_id, driver
+        FROM decisions ORDER BY id",
+    )?;
+    let rows = statement.query_map([], |row| {
+        let mode: String = row.get(14)?;
+        let driver: Option<String> = row.get(17)?;
+        Ok(JournalEntry {
+            timestamp: row.get(0)?,
+            account: row.get(1)?,
+            conn_id: row.get(2)?,
+            strategy: row.get(3)?,
+            price: row.get(4)?,
+            forecast: row.get(5)?,
+            momentum: row.get(6)?,
+            combined_signal: row.get(7)?,
+            position_before: row.get(8)?,
+            position_after: row.get(9)?,
+            volume: row.get(10)?,
+            sent: row.get(11)?,
+            pnl: row.get(12)?,
+            pnl_change: row.get(13)?,
+            mode: mode.parse().map_err(|e: String| {
+                rusqlite::Error::FromSqlConversionFailure(14, rusqlite::types::Type::Text, e.into())
+            })?,
+            arm: row.get(15)?,
+            decision_id: row.get(16)?,
+            driver: driver
+                .map(|driver| driver.parse())
+                .transpose()
+                .map_err(|e: String| {
+                    rusqlite::Error::FromSqlConversionFailure(
+                        17,
+                        rusqlite::types::Type::Text,
+                        e.into(),
+                    )
+                })?,
+        })
+    })?;
+    let mut entries = Vec::new();
+    let mut malformed = 0;
+    for row in rows {
+        match row {
+            Ok(entry) => entries.push(entry),
+            Err(rusqlite::Error::FromSqlConversionFailure(..))
+            | Err(rusqlite::Error::InvalidColumnType(..)) => malformed += 1,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok((entries, malformed))
+}
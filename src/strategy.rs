@@ -0,0 +1,924 @@
+use serde::{Deserialize, Serialize};
+use statrs::statistics::Statistics;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use tracing::debug;
+#[cfg(feature = "puzzles")]
+use tracing::{info, warn};
+
+use crate::history::Indicators;
+#[cfg(feature = "puzzles")]
+use crate::protocol::PuzzleData;
+use crate::risk::ExposureRequest;
+use crate::state::{PerformanceData, SharedState, SignalData, Sizing, StrategyParams};
+
+// PnL std dev below which the window is treated as having no variance (no trading)
+pub const MIN_PNL_STD_DEV: f64 = 1e-6;
+// PnL changes in the window before the optimizer acts on them
+pub const MIN_OPTIMIZER_SAMPLES: usize = 5;
+
+// Sharpe thresholds the optimizer reacts to, and how it weighs older history
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct OptimizerConfig {
+    // Off (or --no-optimize) keeps the params as they started, as does a build
+    // without the auto-optimize feature
+    pub enabled: bool,
+    // At or above this the weights move toward whichever signal was paying
+    pub good_sharpe: f64,
+    // At or below this the weights reset and the aggressive factor drops
+    pub bad_sharpe: f64,
+    // A PnL change this old counts half as much as one just made
+    pub half_life_secs: f64,
+    // Older PnL changes are dropped from the history altogether
+    pub max_age_secs: f64,
+    // Chance each optimization tries a random change to one param instead, kept only
+    // if the Sharpe hasn't got worse by the next
+    pub epsilon: f64,
+    // For the exploration RNG; from entropy when unset
+    pub seed: Option<u64>,
+    // PnL changes further than this many median absolute deviations from the median
+    // are clamped to that distance before they're scored. 0 scores them as they are.
+    pub outlier_mads: f64,
+    // No timed passes over this last fraction of a game, whose window is only partly
+    // filled. One pass over the whole game runs after its finish instead.
+    pub freeze_fraction: f64,
+    // Furthest the weights move in one optimization, however sure the update is
+    pub max_weight_step: f64,
+}
+
+impl Default for OptimizerConfig {
+    fn default() -> Self {
+        OptimizerConfig {
+            enabled: true,
+            good_sharpe: 0.5,
+            bad_sharpe: -0.5,
+            half_life_secs: 30.0,
+            max_age_secs: 120.0,
+            epsilon: 0.0,
+            seed: None,
+            outlier_mads: 5.0,
+            freeze_fraction: 0.1,
+            max_weight_step: 0.1,
+        }
+    }
+}
+
+// Whether connections trade on their own signal or on the consensus of all of them
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SignalMode {
+    #[default]
+    Independent,
+    Ensemble,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnsembleConfig {
+    pub mode: SignalMode,
+    // Signals published longer ago than this are left out of the consensus
+    pub max_age_secs: f64,
+    // Fresh signals needed, our own included, before the consensus is used
+    pub quorum: usize,
+}
+
+impl Default for EnsembleConfig {
+    fn default() -> Self {
+        EnsembleConfig {
+            mode: SignalMode::Independent,
+            max_age_secs: 2.0,
+            quorum: 2,
+        }
+    }
+}
+
+// Only trade when momentum and forecast point the same way. Otherwise the blend is a
+// small net signal that all-in sizing would still take to the limit.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AgreementConfig {
+    pub required: bool,
+    // A tanh signal this close to 0 has no say either way
+    pub negligible: f64,
+    pub on_conflict: Conflict,
+}
+
+impl Default for AgreementConfig {
+    fn default() -> Self {
+        AgreementConfig {
+            required: false,
+            negligible: 0.05,
+            on_conflict: Conflict::Reduce,
+        }
+    }
+}
+
+// What a decision vetoed for disagreeing signals does instead
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Conflict {
+    // Only the part of the trade that brings the position toward flat goes out
+    #[default]
+    Reduce,
+    // Close out whatever is held
+    Flatten,
+}
+
+// Whether the tanh momentum and forecast signals point the same way, or one of them
+// is too weak to count
+pub fn signals_agree(momentum: f64, forecast: f64, negligible: f64) -> bool {
+    momentum.abs() < negligible
+        || forecast.abs() < negligible
+        || momentum.signum() == forecast.signum()
+}
+
+// The volume a vetoed decision is left with
+pub fn veto(volume: i32, position: i32, on_conflict: Conflict) -> i32 {
+    match on_conflict {
+        Conflict::Reduce if volume.signum() == -position.signum() => {
+            volume.signum() * volume.abs().min(position.abs())
+        }
+        Conflict::Reduce => 0,
+        Conflict::Flatten => -position,
+    }
+}
+
+// Middle value, or the mean of the middle two
+pub fn median(values: &mut [f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    Some(if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    })
+}
+
+// Clamp values lying more than `mads` median absolute deviations from the median to
+// that distance, returning how many were. Deviations of exactly zero are left out of
+// the MAD, since a flat position makes many changes exactly the median.
+pub fn clamp_outliers(values: &mut [f64], mads: f64) -> usize {
+    let Some(middle) = median(&mut values.to_vec()) else {
+        return 0;
+    };
+    let mut deviations: Vec<f64> = values
+        .iter()
+        .map(|value| (value - middle).abs())
+        .filter(|deviation| *deviation > 0.0)
+        .collect();
+    let Some(mad) = median(&mut deviations) else {
+        return 0;
+    };
+    let (low, high) = (middle - mads * mad, middle + mads * mad);
+    let mut clamped = 0;
+    for value in values.iter_mut() {
+        if *value < low || *value > high {
+            *value = value.clamp(low, high);
+            clamped += 1;
+        }
+    }
+    clamped
+}
+
+// Mean over std dev of the PnL changes, or None when there's no variance to scale by
+pub fn sharpe_ratio(pnl_changes: &[f64]) -> Option<f64> {
+    if pnl_changes.len() < 2 {
+        return None;
+    }
+    let std_dev = pnl_changes.std_dev();
+    (std_dev > MIN_PNL_STD_DEV).then(|| pnl_changes.mean() / std_dev)
+}
+
+// Pearson correlation of the pairs, or None when either side doesn't vary
+pub fn correlation(pairs: &[(f64, f64)]) -> Option<f64> {
+    if pairs.len() < 2 {
+        return None;
+    }
+    let (xs, ys): (Vec<f64>, Vec<f64>) = pairs.iter().copied().unzip();
+    let denominator = xs.iter().std_dev() * ys.iter().std_dev();
+    (denominator > f64::EPSILON).then(|| xs.iter().covariance(ys.iter()) / denominator)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecayedStats {
+    pub mean: f64,
+    pub std_dev: f64,
+    // None when there's no variance to scale by
+    pub sharpe: Option<f64>,
+}
+
+// Mean, std dev and Sharpe of values given as (age in seconds, value), each weighted by
+// 0.5^(age / half_life) so the latest count most. The variance is corrected for the
+// weights the way the sample variance is for n, so with equal ages this matches
+// sharpe_ratio. None with fewer than two values.
+pub fn decayed_stats(samples: &[(f64, f64)], half_life_secs: f64) -> Option<DecayedStats> {
+    if samples.len() < 2 {
+        return None;
+    }
+    let weights: Vec<f64> = samples
+        .iter()
+        .map(|&(age, _)| 0.5f64.powf(age.max(0.0) / half_life_secs))
+        .collect();
+    let total: f64 = weights.iter().sum();
+    let total_squared: f64 = weights.iter().map(|weight| weight * weight).sum();
+    let effective = total - total_squared / total;
+    if effective.is_nan() || effective <= 0.0 {
+        return None;
+    }
+    let mean = samples
+        .iter()
+        .zip(&weights)
+        .map(|(&(_, value), weight)| weight * value)
+        .sum::<f64>()
+        / total;
+    let variance = samples
+        .iter()
+        .zip(&weights)
+        .map(|(&(_, value), weight)| weight * (value - mean).powi(2))
+        .sum::<f64>()
+        / effective;
+    let std_dev = variance.sqrt();
+    Some(DecayedStats {
+        mean,
+        std_dev,
+        sharpe: (std_dev > MIN_PNL_STD_DEV).then(|| mean / std_dev),
+    })
+}
+
+// Price move a puzzle tells us is coming
+#[cfg(feature = "puzzles")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PuzzleImpact {
+    // +1 for a rise, -1 for a fall
+    pub direction: i32,
+    pub magnitude: f64,
+}
+
+// Handle puzzle impact
+#[cfg(feature = "puzzles")]
+pub fn handle_puzzle_impact(puzzle_data: &PuzzleData) -> Option<PuzzleImpact> {
+    let impact = match puzzle_data.impact {
+        Some(impact) if impact.is_finite() => impact,
+        Some(impact) => {
+            warn!(impact, "Puzzle impact is not a number, not trading");
+            return None;
+        }
+        None => {
+            warn!("Puzzle has no impact, not trading");
+            return None;
+        }
+    };
+
+    if impact == 0.0 {
+        return None; // No trade
+    }
+    info!(impact, "Puzzle says the stock will move");
+
+    Some(PuzzleImpact {
+        direction: impact.signum() as i32,
+        magnitude: impact.abs(),
+    })
+}
+
+// Weighted combination of the tanh-smoothed signals (values in (-1, 1))
+pub fn combined_signal(forecast: f64, momentum: f64, params: &StrategyParams) -> f64 {
+    (momentum_signal(momentum, params) * params.momentum_weight)
+        + (forecast_signal(forecast, params) * params.forecast_weight)
+}
+
+// Whichever weighted signal does more to the combined one, momentum on a tie
+pub fn dominant_driver(forecast: f64, momentum: f64, params: &StrategyParams) -> Driver {
+    let momentum_part = (momentum_signal(momentum, params) * params.momentum_weight).abs();
+    let forecast_part = (forecast_signal(forecast, params) * params.forecast_weight).abs();
+    if forecast_part > momentum_part {
+        Driver::Forecast
+    } else {
+        Driver::Momentum
+    }
+}
+
+pub fn momentum_signal(momentum: f64, params: &StrategyParams) -> f64 {
+    f64::tanh(momentum / params.momentum_divisor)
+}
+
+pub fn forecast_signal(forecast: f64, params: &StrategyParams) -> f64 {
+    f64::tanh(forecast * params.forecast_multiplier)
+}
+
+// How much of the base size to take given the momentum band
+pub fn momentum_scale(momentum: f64, params: &StrategyParams) -> f64 {
+    let strength = momentum.abs();
+    if strength > params.strong_momentum_threshold {
+        params.aggressive_factor
+    } else if strength >= params.medium_momentum_threshold {
+        1.0
+    } else {
+        params.weak_momentum_fraction
+    }
+}
+
+// Volume needed to move from the current position to where the signal wants us
+pub fn size_trade(
+    combined_signal: f64,
+    momentum: f64,
+    position: i32,
+    position_limit: i32,
+    params: &StrategyParams,
+) -> i32 {
+    let scale = momentum_scale(momentum, params);
+    size_to_target(
+        combined_signal,
+        scale,
+        position,
+        position_limit,
+        params.sizing,
+    )
+}
+
+// Share of the size to keep when our own volatility reading is above the configured maximum
+pub fn volatility_scale(volatility: Option<f64>, params: &StrategyParams) -> f64 {
+    match (volatility, params.max_volatility) {
+        (Some(volatility), Some(max)) if volatility > max => max / volatility,
+        _ => 1.0,
+    }
+}
+
+// Volume to reach the position a signal in [-1, 1] asks for, scaled and clamped to the limit
+pub fn size_to_target(
+    signal: f64,
+    scale: f64,
+    position: i32,
+    position_limit: i32,
+    sizing: Sizing,
+) -> i32 {
+    if signal == 0.0 || scale == 0.0 {
+        return 0;
+    }
+
+    let base = match sizing {
+        // In risky mode: if there is any signal, go all-in.
+        // - If the signal is positive, buy up to the limit.
+        // - If negative, sell down to the negative limit.
+        Sizing::AllIn => signal.signum() * position_limit as f64,
+        // Scale the limit by the signal
+        Sizing::Proportional | Sizing::Kelly => signal * position_limit as f64,
+    };
+
+    // Clamped so neither rounding nor the aggressive factor can overshoot the limit
+    let target = (base * scale).round() as i32;
+    target.clamp(-position_limit, position_limit) - position
+}
+
+// Signal strengths are judged in buckets of this width
+pub const KELLY_BUCKET_WIDTH: f64 = 0.25;
+
+// Win rate and payoff at one signal strength, and the position size they justify
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KellyEstimate {
+    pub samples: usize,
+    pub win_probability: f64,
+    pub average_win: f64,
+    pub average_loss: f64,
+    // Of the position limit, after the multiplier, in [0, 1]
+    pub fraction: f64,
+}
+
+fn kelly_bucket(signal: f64) -> usize {
+    ((signal.abs() / KELLY_BUCKET_WIDTH) as usize).min((1.0 / KELLY_BUCKET_WIDTH) as usize - 1)
+}
+
+impl KellyEstimate {
+    // From the PnL changes made while holding a position on a signal in the same bucket.
+    // None until the bucket has kelly_min_samples of them.
+    pub fn from_history(
+        history: &[PerformanceData],
+        signal: f64,
+        params: &StrategyParams,
+    ) -> Option<KellyEstimate> {
+        let bucket = kelly_bucket(signal);
+        let outcomes: Vec<f64> = history
+            .iter()
+            .filter(|p| p.position != 0 && p.pnl_change != 0.0 && kelly_bucket(p.signal) == bucket)
+            .map(|p| p.pnl_change)
+            .collect();
+        if outcomes.is_empty() || outcomes.len() < params.kelly_min_samples {
+            return None;
+        }
+
+        let wins: Vec<f64> = outcomes.iter().copied().filter(|&c| c > 0.0).collect();
+        let losses: Vec<f64> = outcomes.iter().filter(|&&c| c < 0.0).map(|c| -c).collect();
+        let win_probability = wins.len() as f64 / outcomes.len() as f64;
+        let average_win = if wins.is_empty() {
+            0.0
+        } else {
+            wins.iter().sum::<f64>() / wins.len() as f64
+        };
+        let average_loss = if losses.is_empty() {
+            0.0
+        } else {
+            losses.iter().sum::<f64>() / losses.len() as f64
+        };
+
+        // f* = p - (1 - p) / b with b the win/loss payoff ratio
+        let full = if losses.is_empty() {
+            1.0
+        } else if wins.is_empty() {
+            0.0
+        } else {
+            win_probability - (1.0 - win_probability) * average_loss / average_win
+        };
+        Some(KellyEstimate {
+            samples: outcomes.len(),
+            win_probability,
+            average_win,
+            average_loss,
+            fraction: (full * params.kelly_multiplier).clamp(0.0, 1.0),
+        })
+    }
+}
+
+// Which side the last actionable signal put us on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Stance {
+    #[default]
+    Flat,
+    Long,
+    Short,
+}
+
+impl Stance {
+    // Hysteresis around the deadband: only a signal beyond it sets or flips the stance,
+    // so once long it takes a signal below -deadband to go short and vice versa.
+    // Returns the new stance and whether this signal is strong enough to act on.
+    pub fn next(self, signal: f64, deadband: f64) -> (Stance, bool) {
+        if signal > deadband {
+            (Stance::Long, true)
+        } else if signal < -deadband {
+            (Stance::Short, true)
+        } else {
+            (self, false)
+        }
+    }
+}
+
+// Everything a strategy gets to look at for one state update
+#[derive(Debug, Clone, Copy)]
+pub struct MarketContext<'a> {
+    pub forecast: f64,
+    pub momentum: f64,
+    pub position: i32,
+    pub position_limit: i32,
+    // Oldest first, including the price of this update
+    pub recent_prices: &'a [f64],
+    pub indicators: Indicators,
+    pub params: &'a StrategyParams,
+}
+
+// Whether a decision went with the momentum or against it, traded a puzzle, or was
+// typed in at the console
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DecisionMode {
+    #[default]
+    Follow,
+    Fade,
+    Puzzle,
+    Manual,
+}
+
+impl DecisionMode {
+    // Decided by the strategy on a state update, rather than for a puzzle or by hand
+    pub fn is_strategy(self) -> bool {
+        matches!(self, DecisionMode::Follow | DecisionMode::Fade)
+    }
+}
+
+impl fmt::Display for DecisionMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            DecisionMode::Follow => "follow",
+            DecisionMode::Fade => "fade",
+            DecisionMode::Puzzle => "puzzle",
+            DecisionMode::Manual => "manual",
+        })
+    }
+}
+
+impl FromStr for DecisionMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "follow" => Ok(DecisionMode::Follow),
+            "fade" => Ok(DecisionMode::Fade),
+            "puzzle" => Ok(DecisionMode::Puzzle),
+            "manual" => Ok(DecisionMode::Manual),
+            other => Err(format!("unknown decision mode '{}'", other)),
+        }
+    }
+}
+
+// What a decision's PnL is put down to: the signal that led it, or the puzzle whose
+// held position took its place
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Driver {
+    #[default]
+    Momentum,
+    Forecast,
+    Puzzle,
+}
+
+impl fmt::Display for Driver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Driver::Momentum => "momentum",
+            Driver::Forecast => "forecast",
+            Driver::Puzzle => "puzzle",
+        })
+    }
+}
+
+impl FromStr for Driver {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "momentum" => Ok(Driver::Momentum),
+            "forecast" => Ok(Driver::Forecast),
+            "puzzle" => Ok(Driver::Puzzle),
+            other => Err(format!("unknown driver '{}'", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TradeDecision {
+    // The signal the decision was based on, recorded for optimization
+    pub signal: f64,
+    pub volume: i32,
+    // Share of the limit Kelly sizing went for, when it was used
+    pub kelly_fraction: Option<f64>,
+    pub mode: DecisionMode,
+}
+
+pub trait Strategy: Send + Sync {
+    fn name(&self) -> &'static str;
+    // Where the strategy wants to be, in [-1, 1]
+    fn signal(&self, ctx: &MarketContext) -> f64;
+    // Share of the size a signal asks for that the strategy will actually take
+    fn scale(&self, ctx: &MarketContext) -> f64;
+
+    // Volume for a signal, which in ensemble mode needn't be this strategy's own
+    fn size(&self, signal: f64, ctx: &MarketContext) -> i32 {
+        size_to_target(
+            signal,
+            self.scale(ctx),
+            ctx.position,
+            ctx.position_limit,
+            ctx.params.sizing,
+        )
+    }
+
+    // Which way this update's signal leans relative to the momentum
+    fn mode(&self, _ctx: &MarketContext) -> DecisionMode {
+        DecisionMode::Follow
+    }
+
+    fn decide(&self, ctx: &MarketContext) -> TradeDecision {
+        let signal = self.signal(ctx);
+        TradeDecision {
+            signal,
+            volume: self.size(signal, ctx),
+            kelly_fraction: None,
+            mode: self.mode(ctx),
+        }
+    }
+}
+
+// One strategy shared by every connection, such as one handed to the TradingBot
+impl Strategy for Arc<dyn Strategy> {
+    fn name(&self) -> &'static str {
+        self.as_ref().name()
+    }
+
+    fn signal(&self, ctx: &MarketContext) -> f64 {
+        self.as_ref().signal(ctx)
+    }
+
+    fn scale(&self, ctx: &MarketContext) -> f64 {
+        self.as_ref().scale(ctx)
+    }
+
+    fn size(&self, signal: f64, ctx: &MarketContext) -> i32 {
+        self.as_ref().size(signal, ctx)
+    }
+
+    fn mode(&self, ctx: &MarketContext) -> DecisionMode {
+        self.as_ref().mode(ctx)
+    }
+
+    fn decide(&self, ctx: &MarketContext) -> TradeDecision {
+        self.as_ref().decide(ctx)
+    }
+}
+
+// Strategies that can be picked per connection in the config
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StrategyKind {
+    #[default]
+    Blend,
+    ForecastOnly,
+    MeanReversion,
+    BlendFade,
+}
+
+impl StrategyKind {
+    pub fn build(self) -> Box<dyn Strategy> {
+        match self {
+            StrategyKind::Blend => Box::new(BlendStrategy),
+            StrategyKind::ForecastOnly => Box::new(ForecastOnlyStrategy),
+            StrategyKind::MeanReversion => Box::new(MeanReversionStrategy),
+            StrategyKind::BlendFade => Box::new(BlendFadeStrategy),
+        }
+    }
+}
+
+// Weighted momentum + forecast blend, sized by momentum band
+pub struct BlendStrategy;
+
+impl Strategy for BlendStrategy {
+    fn name(&self) -> &'static str {
+        "blend"
+    }
+
+    fn signal(&self, ctx: &MarketContext) -> f64 {
+        combined_signal(ctx.forecast, ctx.momentum, ctx.params)
+    }
+
+    fn scale(&self, ctx: &MarketContext) -> f64 {
+        momentum_scale(ctx.momentum, ctx.params)
+            * volatility_scale(ctx.indicators.volatility, ctx.params)
+    }
+}
+
+// Follows the server's forecast alone at full size
+pub struct ForecastOnlyStrategy;
+
+impl Strategy for ForecastOnlyStrategy {
+    fn name(&self) -> &'static str {
+        "forecast_only"
+    }
+
+    fn signal(&self, ctx: &MarketContext) -> f64 {
+        forecast_signal(ctx.forecast, ctx.params)
+    }
+
+    fn scale(&self, ctx: &MarketContext) -> f64 {
+        volatility_scale(ctx.indicators.volatility, ctx.params)
+    }
+}
+
+// Fades momentum beyond the strong threshold, expecting the move to revert
+pub struct MeanReversionStrategy;
+
+impl Strategy for MeanReversionStrategy {
+    fn name(&self) -> &'static str {
+        "mean_reversion"
+    }
+
+    fn signal(&self, ctx: &MarketContext) -> f64 {
+        if ctx.momentum.abs() > ctx.params.strong_momentum_threshold {
+            -momentum_signal(ctx.momentum, ctx.params)
+        } else {
+            0.0
+        }
+    }
+
+    fn scale(&self, ctx: &MarketContext) -> f64 {
+        volatility_scale(ctx.indicators.volatility, ctx.params)
+    }
+
+    fn mode(&self, ctx: &MarketContext) -> DecisionMode {
+        if self.signal(ctx) != 0.0 {
+            DecisionMode::Fade
+        } else {
+            DecisionMode::Follow
+        }
+    }
+}
+
+// Signal against momentum past the extreme threshold when the forecast says the move
+// won't last, growing with how far past it is. None when there's nothing to fade.
+pub fn fade_signal(forecast: f64, momentum: f64, params: &StrategyParams) -> Option<f64> {
+    let excess = momentum.abs() - params.extreme_momentum_threshold;
+    let disagrees = forecast * momentum < 0.0;
+    (excess > 0.0 && disagrees).then(|| -momentum.signum() * momentum_signal(excess, params))
+}
+
+// The blend, except that an overshoot the forecast disagrees with is faded, sized in
+// proportion to the fade signal rather than all-in
+pub struct BlendFadeStrategy;
+
+impl Strategy for BlendFadeStrategy {
+    fn name(&self) -> &'static str {
+        "blend_fade"
+    }
+
+    fn signal(&self, ctx: &MarketContext) -> f64 {
+        fade_signal(ctx.forecast, ctx.momentum, ctx.params)
+            .unwrap_or_else(|| combined_signal(ctx.forecast, ctx.momentum, ctx.params))
+    }
+
+    fn scale(&self, ctx: &MarketContext) -> f64 {
+        BlendStrategy.scale(ctx)
+    }
+
+    fn mode(&self, ctx: &MarketContext) -> DecisionMode {
+        match fade_signal(ctx.forecast, ctx.momentum, ctx.params) {
+            Some(_) => DecisionMode::Fade,
+            None => DecisionMode::Follow,
+        }
+    }
+
+    fn decide(&self, ctx: &MarketContext) -> TradeDecision {
+        let Some(signal) = fade_signal(ctx.forecast, ctx.momentum, ctx.params) else {
+            return BlendStrategy.decide(ctx);
+        };
+        TradeDecision {
+            signal,
+            volume: size_to_target(
+                signal,
+                volatility_scale(ctx.indicators.volatility, ctx.params),
+                ctx.position,
+                ctx.position_limit,
+                Sizing::Proportional,
+            ),
+            kelly_fraction: None,
+            mode: DecisionMode::Fade,
+        }
+    }
+}
+
+// A decision once Kelly sizing and the agreement check have had their say, and what
+// the check made of it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RefinedDecision {
+    pub decision: TradeDecision,
+    pub signals_agree: bool,
+    pub vetoed_volume: i32,
+}
+
+// Everything about a decision that only needs its inputs: Kelly sizing on the history
+// given, then the veto when momentum and forecast disagree. Nothing shared is read or
+// recorded, so it can be run on plain inputs.
+pub fn refine_decision(
+    mut decision: TradeDecision,
+    ctx: &MarketContext,
+    history: &[PerformanceData],
+    agreement: &AgreementConfig,
+) -> RefinedDecision {
+    // Resize by how signals this strong have paid off, once there's enough to go on
+    if ctx.params.sizing == Sizing::Kelly && decision.signal != 0.0 {
+        if let Some(kelly) = KellyEstimate::from_history(history, decision.signal, ctx.params) {
+            decision.volume = size_to_target(
+                decision.signal.signum() * kelly.fraction,
+                1.0,
+                ctx.position,
+                ctx.position_limit,
+                Sizing::Proportional,
+            );
+            decision.kelly_fraction = Some(kelly.fraction);
+        }
+    }
+
+    // Momentum and forecast pulling opposite ways leave nothing worth trading on
+    let signals_agree = signals_agree(
+        momentum_signal(ctx.momentum, ctx.params),
+        forecast_signal(ctx.forecast, ctx.params),
+        agreement.negligible,
+    );
+    let mut vetoed_volume = 0;
+    if agreement.required && !signals_agree {
+        let volume = veto(decision.volume, ctx.position, agreement.on_conflict);
+        if volume != decision.volume {
+            debug!(
+                momentum = ctx.momentum,
+                forecast = ctx.forecast,
+                wanted = decision.volume,
+                volume,
+                "Momentum and forecast disagree, vetoing the trade"
+            );
+            vetoed_volume = decision.volume - volume;
+            decision.volume = volume;
+        }
+    }
+    RefinedDecision {
+        decision,
+        signals_agree,
+        vetoed_volume,
+    }
+}
+
+// Ask the strategy for a decision and record it, whichever strategy it is. In ensemble
+// mode the consensus signal, once there is one, is sized in place of our own.
+pub async fn determine_trade_volume(
+    strategy: &dyn Strategy,
+    ctx: &MarketContext<'_>,
+    conn_id: usize,
+    shared_state: &SharedState,
+) -> TradeDecision {
+    let own = strategy.decide(ctx);
+    let decision = match shared_state.ensemble_signal(conn_id, own.signal).await {
+        Some(consensus) => {
+            debug!(
+                own = own.signal,
+                consensus, "Trading on the ensemble signal"
+            );
+            TradeDecision {
+                signal: consensus,
+                volume: strategy.size(consensus, ctx),
+                kelly_fraction: None,
+                mode: DecisionMode::Follow,
+            }
+        }
+        None => own,
+    };
+
+    // Only Kelly sizing looks at the history
+    let history: Vec<PerformanceData> =
+        if ctx.params.sizing == Sizing::Kelly && decision.signal != 0.0 {
+            shared_state
+                .performance_history
+                .lock()
+                .await
+                .iter()
+                .cloned()
+                .collect()
+        } else {
+            Vec::new()
+        };
+    let RefinedDecision {
+        decision,
+        signals_agree: agree,
+        vetoed_volume,
+    } = refine_decision(decision, ctx, &history, &shared_state.agreement);
+
+    // What the account's exposure ceiling would hold back as things stand. It's only
+    // applied, and the trade booked against it, once the handler is about to send.
+    let capped_volume = decision.volume
+        - shared_state.exposure_book.lock().await.fit(
+            &shared_state.exposure,
+            conn_id,
+            ExposureRequest {
+                position: ctx.position,
+                position_limit: ctx.position_limit,
+                volume: decision.volume,
+                signal: decision.signal,
+            },
+        );
+
+    // Record for strategy optimization, numbered so the PnL it goes on to make can be
+    // credited back to it
+    let (decision_id, schedule_phase, puzzle_driven) = {
+        let mut performances = shared_state.connection_performance.lock().await;
+        let perf = performances.entry(conn_id).or_default();
+        // A held puzzle position replaces whatever was decided here
+        #[cfg(feature = "puzzles")]
+        let puzzle_driven = perf.session.puzzle.is_holding();
+        #[cfg(not(feature = "puzzles"))]
+        let puzzle_driven = false;
+        (
+            perf.next_decision_id(),
+            perf.session.schedule_phase.clone(),
+            puzzle_driven,
+        )
+    };
+    let signal_data = SignalData {
+        conn_id,
+        decision_id,
+        timestamp: shared_state.monotonic(),
+        strategy: strategy.name(),
+        momentum: ctx.momentum,
+        forecast: ctx.forecast,
+        combined_signal: decision.signal,
+        trade_volume: decision.volume,
+        position: ctx.position,
+        mode: decision.mode,
+        signals_agree: agree,
+        vetoed_volume,
+        capped_volume,
+        schedule_phase,
+        driver: dominant_driver(ctx.forecast, ctx.momentum, ctx.params),
+        puzzle_driven,
+    };
+    shared_state.record_signal(signal_data).await;
+
+    decision
+}
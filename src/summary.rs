@@ -0,0 +1,433 @@
+use futures::io::AsyncWriteExt;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::curve::{sparkline, SPARKLINE_WIDTH};
+use crate::latency::LatencySummary;
+use crate::notify::ParamChange;
+use crate::rt::{fs, Mutex};
+use crate::state::{ConnectionPerformance, GamePhase, StrategyParams};
+use crate::strategy::Driver;
+
+// What happened on one connection over one game
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GameSummary {
+    // Which of the process's accounts played the game
+    #[serde(default)]
+    pub account: String,
+    pub conn_id: usize,
+    pub strategy: String,
+    pub started_at: f64,
+    pub finished_at: f64,
+    pub trades: usize,
+    pub rejected_trades: usize,
+    // Strategy trades the rate limiter held back
+    #[serde(default)]
+    pub rate_limited: usize,
+    // Trades left in the game's budgets at the finish, when it had them, and the trades
+    // away from flat held back once they were spent
+    #[serde(default)]
+    pub trade_budget_left: Option<u32>,
+    #[serde(default)]
+    pub puzzle_budget_left: Option<u32>,
+    #[serde(default)]
+    pub over_budget: usize,
+    // Stage the game was in when the finish came, and the trades held back between stages
+    #[serde(default)]
+    pub phase: GamePhase,
+    #[serde(default)]
+    pub held_out_of_phase: usize,
+    // Positions taken back to flat after being held too long without the signal
+    #[serde(default)]
+    pub holds_expired: usize,
+    // Puzzles answered by a solver, and those left to their impact or skipped
+    #[serde(default)]
+    pub puzzles_solved: usize,
+    #[serde(default)]
+    pub puzzles_skipped: usize,
+    pub win_rate: Option<f64>,
+    // Largest absolute position held
+    pub max_position: i32,
+    pub peak_pnl: Option<f64>,
+    pub low_pnl: Option<f64>,
+    // Signed change with the largest magnitude
+    pub largest_pnl_change: Option<f64>,
+    pub final_pnl: Option<f64>,
+    // Modelled fees and spread on the game's trades, and the final PnL once they're
+    // taken off, which is what the optimizer was credited
+    #[serde(default)]
+    pub costs: f64,
+    #[serde(default)]
+    pub attributed_pnl: Option<f64>,
+    // The PnL changes the strategy was credited with, by what drove the decision each
+    // was credited to
+    #[serde(default)]
+    pub attribution: Attribution,
+    pub params: StrategyParams,
+    // Dry run, so trades and PnL come from the paper book
+    #[serde(default)]
+    pub simulated: bool,
+    // From a frame arriving to the trade it led to going out, over the game's trades
+    #[serde(default)]
+    pub latency: Option<LatencySummary>,
+    // (seconds into the game, PnL) at each state update, written to its own CSV
+    #[serde(skip)]
+    pub curve: Vec<(f32, f32)>,
+    // What the optimizer's pass over the game moved once it finished
+    #[serde(default)]
+    pub param_changes: Vec<ParamDiff>,
+}
+
+// PnL credited to the decisions one driver led, and how those that traded did by the
+// PnL change straight after
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct DriverPnl {
+    pub pnl: f64,
+    pub trades: usize,
+    pub wins: usize,
+}
+
+impl DriverPnl {
+    pub fn win_rate(&self) -> Option<f64> {
+        (self.trades > 0).then(|| self.wins as f64 / self.trades as f64)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct Attribution {
+    pub momentum: DriverPnl,
+    pub forecast: DriverPnl,
+    pub puzzle: DriverPnl,
+}
+
+impl Attribution {
+    // Credit a PnL change to a decision, counting it as a trade when one went out on it
+    pub fn credit(&mut self, driver: Driver, pnl_change: f64, traded: bool) {
+        let stats = self.get_mut(driver);
+        stats.pnl += pnl_change;
+        if traded {
+            stats.trades += 1;
+            stats.wins += usize::from(pnl_change > 0.0);
+        }
+    }
+
+    pub fn get(&self, driver: Driver) -> &DriverPnl {
+        match driver {
+            Driver::Momentum => &self.momentum,
+            Driver::Forecast => &self.forecast,
+            Driver::Puzzle => &self.puzzle,
+        }
+    }
+
+    fn get_mut(&mut self, driver: Driver) -> &mut DriverPnl {
+        match driver {
+            Driver::Momentum => &mut self.momentum,
+            Driver::Forecast => &mut self.forecast,
+            Driver::Puzzle => &mut self.puzzle,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Attribution::default()
+    }
+}
+
+impl fmt::Display for Attribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let drivers: Vec<String> = [Driver::Momentum, Driver::Forecast, Driver::Puzzle]
+            .into_iter()
+            .map(|driver| {
+                let stats = self.get(driver);
+                format!(
+                    "{} {} over {} trades ({} won)",
+                    driver,
+                    dollars(Some(stats.pnl)),
+                    stats.trades,
+                    stats
+                        .win_rate()
+                        .map_or("n/a".to_string(), |rate| format!("{:.0}%", rate * 100.0))
+                )
+            })
+            .collect();
+        f.write_str(&drivers.join(", "))
+    }
+}
+
+// One param the end-of-game pass moved
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ParamDiff {
+    pub param: String,
+    pub before: f64,
+    pub after: f64,
+}
+
+impl From<ParamChange> for ParamDiff {
+    fn from(change: ParamChange) -> Self {
+        ParamDiff {
+            param: change.name.to_string(),
+            before: change.before,
+            after: change.after,
+        }
+    }
+}
+
+// Every game finished so far, across connections, for whoever ran the bot to collect
+pub type FinishedGames = Arc<Mutex<Vec<GameSummary>>>;
+
+// Prefix for lines about one of several accounts, nothing when there's no account
+pub fn account_label(account: &str) -> String {
+    if account.is_empty() {
+        String::new()
+    } else {
+        format!("[{}] ", account)
+    }
+}
+
+fn dollars(value: Option<f64>) -> String {
+    value.map_or("n/a".to_string(), |value| format!("${:.2}", value))
+}
+
+impl fmt::Display for GameSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{}Connection {}: {}Game summary ({} strategy, {:.0}s)",
+            account_label(&self.account),
+            self.conn_id,
+            if self.simulated { "SIMULATED " } else { "" },
+            self.strategy,
+            self.finished_at - self.started_at
+        )?;
+        writeln!(
+            f,
+            "  Trades:         {} ({} rejected, {} rate limited)",
+            self.trades, self.rejected_trades, self.rate_limited
+        )?;
+        if let Some(left) = self.trade_budget_left {
+            writeln!(
+                f,
+                "  Budget:         {} trades left, {} held back over it",
+                left, self.over_budget
+            )?;
+        }
+        if let Some(left) = self.puzzle_budget_left {
+            writeln!(f, "  Puzzle budget:  {} trades left", left)?;
+        }
+        writeln!(
+            f,
+            "  Phase:          {} at the finish, {} trades held between stages",
+            self.phase, self.held_out_of_phase
+        )?;
+        if self.puzzles_solved + self.puzzles_skipped > 0 {
+            writeln!(
+                f,
+                "  Puzzles:        {} solved, {} skipped",
+                self.puzzles_solved, self.puzzles_skipped
+            )?;
+        }
+        writeln!(
+            f,
+            "  Win rate:       {}",
+            self.win_rate
+                .map_or("n/a".to_string(), |rate| format!("{:.0}%", rate * 100.0))
+        )?;
+        writeln!(
+            f,
+            "  Max position:   {} ({} holds expired)",
+            self.max_position, self.holds_expired
+        )?;
+        writeln!(
+            f,
+            "  PnL:            final {}, peak {}, low {}",
+            dollars(self.final_pnl),
+            dollars(self.peak_pnl),
+            dollars(self.low_pnl)
+        )?;
+        if self.costs > 0.0 {
+            writeln!(
+                f,
+                "  After costs:    {} ({} in fees and spread)",
+                dollars(self.attributed_pnl),
+                dollars(Some(self.costs))
+            )?;
+        }
+        writeln!(f, "  Largest change: {}", dollars(self.largest_pnl_change))?;
+        if !self.attribution.is_empty() {
+            writeln!(f, "  By driver:      {}", self.attribution)?;
+        }
+        if !self.curve.is_empty() {
+            writeln!(
+                f,
+                "  PnL curve:      {}",
+                sparkline(&self.curve, SPARKLINE_WIDTH)
+            )?;
+        }
+        if let Some(latency) = &self.latency {
+            writeln!(
+                f,
+                "  Latency:        p50 {:.1}ms, p95 {:.1}ms, max {:.1}ms over {} trades",
+                latency.p50_ms, latency.p95_ms, latency.max_ms, latency.samples
+            )?;
+        }
+        write!(
+            f,
+            "  Params:         momentum_weight={}, forecast_weight={}, aggressive_factor={}, sizing={:?}",
+            self.params.momentum_weight,
+            self.params.forecast_weight,
+            self.params.aggressive_factor,
+            self.params.sizing
+        )?;
+        if !self.param_changes.is_empty() {
+            let changes: Vec<String> = self
+                .param_changes
+                .iter()
+                .map(|change| format!("{} {} -> {}", change.param, change.before, change.after))
+                .collect();
+            write!(f, "\n  Tuned after:    {}", changes.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+// Collects a GameSummary over one game. Trade counts are kept as the difference from
+// the connection's running totals when the game started.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GameAccumulator {
+    started_at: f64,
+    trades_before: usize,
+    rejected_before: usize,
+    rate_limited_before: usize,
+    over_budget_before: usize,
+    held_out_of_phase_before: usize,
+    holds_expired_before: usize,
+    puzzles_solved_before: usize,
+    puzzles_skipped_before: usize,
+    evaluated_before: usize,
+    successful_before: usize,
+    max_position: i32,
+    peak_pnl: Option<f64>,
+    low_pnl: Option<f64>,
+    largest_pnl_change: Option<f64>,
+    last_pnl: Option<f64>,
+    costs: f64,
+    // Costs of trades sent since the last PnL change, which they come out of
+    unbilled: f64,
+    attribution: Attribution,
+}
+
+impl GameAccumulator {
+    pub fn start(started_at: f64, perf: &ConnectionPerformance) -> Self {
+        GameAccumulator {
+            started_at,
+            trades_before: perf.trades_made,
+            rejected_before: perf.rejected_trades,
+            rate_limited_before: perf.rate_limited,
+            over_budget_before: perf.over_budget,
+            held_out_of_phase_before: perf.held_out_of_phase,
+            holds_expired_before: perf.holds_expired,
+            puzzles_solved_before: perf.puzzles_solved,
+            puzzles_skipped_before: perf.puzzles_skipped,
+            evaluated_before: perf.evaluated_trades,
+            successful_before: perf.successful_trades,
+            ..GameAccumulator::default()
+        }
+    }
+
+    pub fn observe(&mut self, position: i32, pnl: f64, pnl_change: Option<f64>) {
+        self.max_position = self.max_position.max(position.abs());
+        self.peak_pnl = Some(self.peak_pnl.map_or(pnl, |peak| peak.max(pnl)));
+        self.low_pnl = Some(self.low_pnl.map_or(pnl, |low| low.min(pnl)));
+        if let Some(change) = pnl_change {
+            if self
+                .largest_pnl_change
+                .is_none_or(|largest| change.abs() > largest.abs())
+            {
+                self.largest_pnl_change = Some(change);
+            }
+        }
+        self.last_pnl = Some(pnl);
+    }
+
+    // A trade's modelled cost, counted when it's sent. Negative for one that didn't fill.
+    pub fn charge(&mut self, cost: f64) {
+        self.costs += cost;
+        self.unbilled += cost;
+    }
+
+    // A PnL change the strategy was credited with, put down to what drove the decision
+    pub fn credit(&mut self, driver: Driver, pnl_change: f64, traded: bool) {
+        self.attribution.credit(driver, pnl_change, traded);
+    }
+
+    // The costs to take off the PnL change just seen
+    pub fn bill(&mut self) -> f64 {
+        std::mem::take(&mut self.unbilled)
+    }
+
+    // The server's final PnL wins over the last one we saw, when it sends one
+    pub fn finish(
+        &self,
+        conn_id: usize,
+        strategy: &str,
+        finished_at: f64,
+        final_pnl: Option<f64>,
+        perf: &ConnectionPerformance,
+        params: StrategyParams,
+    ) -> GameSummary {
+        let evaluated = perf.evaluated_trades.saturating_sub(self.evaluated_before);
+        let successful = perf
+            .successful_trades
+            .saturating_sub(self.successful_before);
+        let final_pnl = final_pnl.or(self.last_pnl);
+        GameSummary {
+            account: String::new(),
+            conn_id,
+            strategy: strategy.to_string(),
+            started_at: self.started_at,
+            finished_at,
+            trades: perf.trades_made.saturating_sub(self.trades_before),
+            rejected_trades: perf.rejected_trades.saturating_sub(self.rejected_before),
+            rate_limited: perf.rate_limited.saturating_sub(self.rate_limited_before),
+            trade_budget_left: None,
+            puzzle_budget_left: None,
+            over_budget: perf.over_budget.saturating_sub(self.over_budget_before),
+            phase: perf.phase,
+            held_out_of_phase: perf
+                .held_out_of_phase
+                .saturating_sub(self.held_out_of_phase_before),
+            holds_expired: perf.holds_expired.saturating_sub(self.holds_expired_before),
+            puzzles_solved: perf
+                .puzzles_solved
+                .saturating_sub(self.puzzles_solved_before),
+            puzzles_skipped: perf
+                .puzzles_skipped
+                .saturating_sub(self.puzzles_skipped_before),
+            win_rate: (evaluated > 0).then(|| successful as f64 / evaluated as f64),
+            max_position: self.max_position,
+            peak_pnl: self.peak_pnl,
+            low_pnl: self.low_pnl,
+            largest_pnl_change: self.largest_pnl_change,
+            final_pnl,
+            costs: self.costs,
+            attributed_pnl: final_pnl.map(|pnl| pnl - self.costs),
+            attribution: self.attribution,
+            params,
+            simulated: false,
+            latency: perf.latency.game(),
+            curve: Vec::new(),
+            param_changes: Vec::new(),
+        }
+    }
+}
+
+// Add one summary as a JSON line, creating the file if needed
+pub async fn append_summary(path: &Path, summary: &GameSummary) -> io::Result<()> {
+    let mut line = serde_json::to_string(summary).expect("summaries always serialize");
+    line.push('\n');
+    let mut file = fs::append(path).await?;
+    file.write_all(line.as_bytes()).await?;
+    file.flush().await
+}
@@ -0,0 +1,140 @@
+use futures::future;
+use futures::FutureExt;
+use serde::Deserialize;
+use std::any::Any;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+use crate::rt;
+use crate::shutdown::Shutdown;
+use crate::state::{Health, SharedState};
+
+// How a connection task that panicked is brought back
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SupervisorConfig {
+    // Restarts allowed within the window before the connection is quarantined
+    pub max_restarts: usize,
+    pub window_secs: f64,
+    pub restart_delay_secs: f64,
+    pub quarantine_secs: f64,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        SupervisorConfig {
+            max_restarts: 3,
+            window_secs: 60.0,
+            restart_delay_secs: 1.0,
+            quarantine_secs: 300.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Restart {
+    After(Duration),
+    Quarantine(Duration),
+}
+
+// Restarts within the last window, to tell a one-off crash from a crash loop
+#[derive(Debug, Default)]
+pub struct RestartTracker {
+    recent: VecDeque<f64>,
+}
+
+impl RestartTracker {
+    // Record a restart at `now` seconds and say how long to wait before it. A
+    // quarantine starts the count afresh.
+    pub fn record(&mut self, config: &SupervisorConfig, now: f64) -> Restart {
+        while self
+            .recent
+            .front()
+            .is_some_and(|&at| now - at >= config.window_secs)
+        {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(now);
+        if self.recent.len() > config.max_restarts {
+            self.recent.clear();
+            Restart::Quarantine(Duration::from_secs_f64(config.quarantine_secs))
+        } else {
+            Restart::After(Duration::from_secs_f64(config.restart_delay_secs))
+        }
+    }
+}
+
+// What a panic was raised with, when it's a message
+pub fn panic_reason(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+// Run the task `start` makes for a connection, starting a new one whenever it panics.
+// A task that returns by itself has shut down or given up on something retrying can't
+// fix, so it's left stopped. Performance is kept in the shared state across restarts.
+pub async fn supervise<F, Fut>(
+    conn_id: usize,
+    config: SupervisorConfig,
+    shared_state: Arc<SharedState>,
+    shutdown: Shutdown,
+    mut start: F,
+) where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let mut restarts = RestartTracker::default();
+    loop {
+        let exit = AssertUnwindSafe(start()).catch_unwind().await;
+        let payload = match exit {
+            Ok(()) => return,
+            Err(payload) => payload,
+        };
+        shared_state.set_health(conn_id, Health::Dead).await;
+        let reason = panic_reason(payload.as_ref());
+        if shutdown.is_triggered() {
+            error!(
+                conn_id,
+                reason, "Connection task panicked while shutting down"
+            );
+            return;
+        }
+
+        let delay = match restarts.record(&config, shared_state.monotonic().secs()) {
+            Restart::After(delay) => {
+                error!(
+                    conn_id,
+                    reason,
+                    ?delay,
+                    "Connection task panicked, restarting"
+                );
+                delay
+            }
+            Restart::Quarantine(delay) => {
+                error!(conn_id, reason, "Connection task panicked");
+                warn!(
+                    conn_id,
+                    restarts = config.max_restarts + 1,
+                    window_secs = config.window_secs,
+                    ?delay,
+                    "Connection restarting too often, quarantining"
+                );
+                delay
+            }
+        };
+        future::select(Box::pin(rt::sleep(delay)), Box::pin(shutdown.wait())).await;
+        if shutdown.is_triggered() {
+            return;
+        }
+        info!(conn_id, "Restarting connection task");
+    }
+}
@@ -0,0 +1,82 @@
+use futures::io::{AsyncWriteExt, BufWriter};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::error;
+
+use crate::rt::{fs, Mutex};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    In,
+    Out,
+}
+
+// One websocket frame as it went over the wire
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TranscriptEntry {
+    pub timestamp: f64,
+    // Which of the process's accounts the connection plays for
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub account: String,
+    pub conn_id: usize,
+    pub direction: Direction,
+    pub frame: String,
+}
+
+// Append-only JSONL record of every frame in a run
+pub struct Transcript {
+    path: PathBuf,
+    writer: Mutex<BufWriter<fs::File>>,
+}
+
+impl Transcript {
+    // Open a new transcript file for this run inside dir
+    pub async fn create(dir: &Path, started_at: f64) -> io::Result<Transcript> {
+        fs::create_dir_all(dir).await?;
+        let path = dir.join(format!("transcript-{}.jsonl", started_at as u64));
+        let file = fs::append(&path).await?;
+        Ok(Transcript {
+            path,
+            writer: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Flushed per frame so a crash never loses what led up to it. Failures are
+    // logged rather than returned since a transcript problem shouldn't stop trading.
+    pub async fn record(&self, entry: &TranscriptEntry) {
+        let mut line = serde_json::to_string(entry).expect("transcript entries always serialize");
+        line.push('\n');
+
+        let mut writer = self.writer.lock().await;
+        let result = match writer.write_all(line.as_bytes()).await {
+            Ok(()) => writer.flush().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            error!(path = %self.path.display(), error = %e, "Error writing transcript");
+        }
+    }
+}
+
+// Load a transcript, failing on the first line that isn't a valid entry
+pub fn read_transcript(path: &Path) -> io::Result<Vec<TranscriptEntry>> {
+    let text = std::fs::read_to_string(path)?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| {
+            serde_json::from_str(line).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {}", number + 1, e),
+                )
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,173 @@
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Cell, Paragraph, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::clock::Monotonic;
+use crate::risk::QuarantineState;
+use crate::shutdown::Shutdown;
+use crate::state::{ConnectionSnapshot, Health, SharedState, StateSnapshot};
+
+// About 4 redraws a second
+const REFRESH: Duration = Duration::from_millis(250);
+const SPARK_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+// Widest sparkline drawn, taking the latest values
+const SPARK_WIDTH: usize = 30;
+
+// Draw the dashboard until shutdown, which 'q' or Ctrl-C triggers. Blocks, so run it
+// on its own thread; it only touches the shared state through snapshot().
+pub fn run(shared_state: Arc<SharedState>, shutdown: Shutdown) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let result = draw_until_shutdown(&mut terminal, &shared_state, &shutdown);
+    ratatui::restore();
+    result
+}
+
+fn draw_until_shutdown(
+    terminal: &mut DefaultTerminal,
+    shared_state: &SharedState,
+    shutdown: &Shutdown,
+) -> io::Result<()> {
+    while !shutdown.is_triggered() {
+        let snapshot = futures::executor::block_on(shared_state.snapshot());
+        terminal.draw(|frame| draw(frame, &snapshot))?;
+
+        if event::poll(REFRESH)? {
+            if let Event::Key(key) = event::read()? {
+                let ctrl_c =
+                    key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                if key.kind == KeyEventKind::Press && (key.code == KeyCode::Char('q') || ctrl_c) {
+                    shutdown.trigger();
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn draw(frame: &mut Frame, snapshot: &StateSnapshot) {
+    let [table_area, footer_area] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(3)]).areas(frame.area());
+
+    let header = Row::new([
+        "Conn",
+        "Health",
+        "Price",
+        "Position",
+        "PnL",
+        "Last trade",
+        "Signal",
+        "Queue",
+        "Recent PnL",
+    ])
+    .style(Style::default().add_modifier(Modifier::BOLD));
+    let rows = snapshot
+        .connections
+        .iter()
+        .map(|connection| connection_row(connection, snapshot.taken_at));
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(5),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(9),
+            Constraint::Length(11),
+            Constraint::Length(14),
+            Constraint::Length(7),
+            Constraint::Length(6),
+            Constraint::Min(10),
+        ],
+    )
+    .header(header)
+    .block(Block::default().borders(Borders::ALL).title(format!(
+        " Connections, total PnL {:.2}{} (q to quit) ",
+        snapshot.total_pnl,
+        if snapshot.trading_enabled {
+            ""
+        } else {
+            ", TRADING HALTED"
+        }
+    )));
+    frame.render_widget(table, table_area);
+
+    let params = &snapshot.params;
+    let footer = Paragraph::new(Line::from(format!(
+        "momentum_weight={:.2}  forecast_weight={:.2}  aggressive_factor={:.2}  sizing={:?}  deadband={:.2}",
+        params.momentum_weight,
+        params.forecast_weight,
+        params.aggressive_factor,
+        params.sizing,
+        params.deadband
+    )))
+    .block(Block::default().borders(Borders::ALL).title(" Strategy "));
+    frame.render_widget(footer, footer_area);
+}
+
+fn connection_row(connection: &ConnectionSnapshot, now: Monotonic) -> Row<'static> {
+    let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    let position = match (connection.position, connection.position_limit) {
+        (Some(position), Some(limit)) => format!("{}/{}", position, limit),
+        _ => "-".to_string(),
+    };
+    let pnl_colour = if connection.pnl >= 0.0 {
+        Color::Green
+    } else {
+        Color::Red
+    };
+    let conn = if connection.halted {
+        format!("{}!", connection.conn_id)
+    } else if matches!(connection.quarantine, QuarantineState::Observing { .. }) {
+        format!("{}q", connection.conn_id)
+    } else {
+        connection.conn_id.to_string()
+    };
+    let health_colour = match connection.health {
+        Health::Live => Color::Green,
+        Health::Connecting | Health::Backoff => Color::Yellow,
+        Health::Dead => Color::Red,
+    };
+    Row::new([
+        Cell::from(conn),
+        Cell::from(connection.health.to_string()).style(Style::default().fg(health_colour)),
+        Cell::from(or_dash(
+            connection.price.map(|price| format!("{:.2}", price)),
+        )),
+        Cell::from(position),
+        Cell::from(format!("{:.2}", connection.pnl)).style(Style::default().fg(pnl_colour)),
+        Cell::from(or_dash(connection.last_trade.map(|trade| {
+            format!("{:+} {:.0}s ago", trade.volume, now - trade.timestamp)
+        }))),
+        Cell::from(or_dash(
+            connection.signal.map(|signal| format!("{:+.2}", signal)),
+        )),
+        Cell::from(connection.queued_messages.to_string()),
+        Cell::from(sparkline(&connection.recent_pnl)),
+    ])
+}
+
+// Latest values as block characters scaled between their min and max
+fn sparkline(values: &[f64]) -> String {
+    let values = &values[values.len().saturating_sub(SPARK_WIDTH)..];
+    let (low, high) = values
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), &value| {
+            (low.min(value), high.max(value))
+        });
+    values
+        .iter()
+        .map(|&value| {
+            let level = if high > low {
+                ((value - low) / (high - low) * (SPARK_LEVELS.len() - 1) as f64).round() as usize
+            } else {
+                0
+            };
+            SPARK_LEVELS[level]
+        })
+        .collect()
+}
@@ -0,0 +1,345 @@
+use futures::stream::{self, StreamExt};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::backtest::{game_seeds, play_games, SyntheticGame};
+use crate::config::Config;
+use crate::persist::{SavedParams, WindowStats};
+use crate::replay::replay;
+use crate::rt;
+use crate::state::StrategyParams;
+use crate::strategy::{sharpe_ratio, StrategyKind};
+use crate::transcript::TranscriptEntry;
+
+// Largest grid the tuner will take on
+pub const MAX_COMBINATIONS: usize = 100_000;
+
+// Grid search over the strategy params for the tune subcommand
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TuneConfig {
+    // Synthetic games per combination, drawn from [backtest] and the same for every one
+    pub games: usize,
+    // Evaluate against a recorded transcript, replayed on a paper book, instead
+    pub transcript: Option<PathBuf>,
+    pub strategy: StrategyKind,
+    // Combinations printed, best first
+    pub top: usize,
+    pub rank_by: RankBy,
+    // Evaluations run at once, 0 for one per CPU
+    pub workers: usize,
+    pub grid: GridConfig,
+}
+
+impl Default for TuneConfig {
+    fn default() -> Self {
+        TuneConfig {
+            games: 200,
+            transcript: None,
+            strategy: StrategyKind::Blend,
+            top: 10,
+            rank_by: RankBy::Mean,
+            workers: 0,
+            grid: GridConfig::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RankBy {
+    // Mean final PnL per game
+    #[default]
+    Mean,
+    // Mean over standard deviation of final PnL per game
+    Sharpe,
+}
+
+// Values from min to max inclusive, step apart
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ParamRange {
+    pub min: f64,
+    pub max: f64,
+    pub step: f64,
+}
+
+impl ParamRange {
+    pub fn is_valid(&self) -> bool {
+        self.step > 0.0 && self.min <= self.max && self.min.is_finite() && self.max.is_finite()
+    }
+
+    pub fn values(&self) -> Vec<f64> {
+        // A little slack so float steps don't fall just short of max
+        let steps = ((self.max - self.min) / self.step + 1e-9).floor() as usize;
+        (0..=steps)
+            .map(|i| self.min + i as f64 * self.step)
+            .collect()
+    }
+}
+
+// Params left out keep their [strategy] value
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct GridConfig {
+    pub momentum_weight: Option<ParamRange>,
+    pub forecast_weight: Option<ParamRange>,
+    pub strong_momentum_threshold: Option<ParamRange>,
+    pub medium_momentum_threshold: Option<ParamRange>,
+    pub extreme_momentum_threshold: Option<ParamRange>,
+    pub aggressive_factor: Option<ParamRange>,
+    pub deadband: Option<ParamRange>,
+}
+
+impl Default for GridConfig {
+    fn default() -> Self {
+        let weights = ParamRange {
+            min: 0.2,
+            max: 0.8,
+            step: 0.2,
+        };
+        GridConfig {
+            momentum_weight: Some(weights),
+            forecast_weight: Some(weights),
+            strong_momentum_threshold: None,
+            medium_momentum_threshold: None,
+            extreme_momentum_threshold: None,
+            aggressive_factor: None,
+            deadband: None,
+        }
+    }
+}
+
+// One swept param and how to read and write it
+struct Axis {
+    name: &'static str,
+    range: ParamRange,
+    get: fn(&StrategyParams) -> f64,
+    set: fn(&mut StrategyParams, f64),
+}
+
+macro_rules! axis {
+    ($grid:expr, $field:ident) => {
+        $grid.$field.map(|range| Axis {
+            name: stringify!($field),
+            range,
+            get: |params| params.$field,
+            set: |params, value| params.$field = value,
+        })
+    };
+}
+
+impl GridConfig {
+    fn axes(&self) -> Vec<Axis> {
+        [
+            axis!(self, momentum_weight),
+            axis!(self, forecast_weight),
+            axis!(self, strong_momentum_threshold),
+            axis!(self, medium_momentum_threshold),
+            axis!(self, extreme_momentum_threshold),
+            axis!(self, aggressive_factor),
+            axis!(self, deadband),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    // Names of the params being swept, in grid order
+    pub fn names(&self) -> Vec<&'static str> {
+        self.axes().iter().map(|axis| axis.name).collect()
+    }
+
+    // The first range that can't produce any values, by param name
+    pub fn invalid_range(&self) -> Option<&'static str> {
+        self.axes()
+            .iter()
+            .find(|axis| !axis.range.is_valid())
+            .map(|axis| axis.name)
+    }
+
+    // Saturates rather than overflowing, so a huge grid is caught by validation
+    pub fn size(&self) -> usize {
+        self.axes()
+            .iter()
+            .map(|axis| axis.range.values().len())
+            .fold(1, usize::saturating_mul)
+    }
+
+    // Every combination, the first axis varying slowest
+    pub fn combinations(&self, base: &StrategyParams) -> Vec<StrategyParams> {
+        let mut combinations = vec![base.clone()];
+        for axis in self.axes() {
+            let values = axis.range.values();
+            combinations = combinations
+                .iter()
+                .flat_map(|params| {
+                    values.iter().map(|&value| {
+                        let mut params = params.clone();
+                        (axis.set)(&mut params, value);
+                        params
+                    })
+                })
+                .collect();
+        }
+        combinations
+    }
+}
+
+// What each combination is scored on
+#[derive(Debug, Clone)]
+pub enum TuneSource {
+    Synthetic(Arc<Vec<SyntheticGame>>),
+    Transcript(Arc<Vec<TranscriptEntry>>),
+}
+
+impl TuneSource {
+    // The configured number of seeded games from [backtest]
+    pub fn synthetic(config: &Config) -> Self {
+        let mut backtest = config.backtest.clone();
+        backtest.games = config.tune.games;
+        let games = game_seeds(&backtest)
+            .into_iter()
+            .map(|seed| SyntheticGame::generate(&backtest, seed))
+            .collect();
+        TuneSource::Synthetic(Arc::new(games))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TuneResult {
+    pub params: StrategyParams,
+    pub games: usize,
+    pub mean_pnl: f64,
+    pub sharpe: Option<f64>,
+}
+
+impl TuneResult {
+    fn from_pnls(params: StrategyParams, pnls: &[f64]) -> Self {
+        TuneResult {
+            params,
+            games: pnls.len(),
+            mean_pnl: if pnls.is_empty() {
+                0.0
+            } else {
+                pnls.iter().sum::<f64>() / pnls.len() as f64
+            },
+            sharpe: sharpe_ratio(pnls),
+        }
+    }
+
+    // In the format the live bot restores its params from
+    pub fn to_saved(&self, saved_at: f64) -> SavedParams {
+        SavedParams {
+            saved_at,
+            params: self.params.clone(),
+            stats: WindowStats {
+                samples: self.games,
+                mean_pnl_change: self.mean_pnl,
+                sharpe: self.sharpe,
+            },
+            arms: BTreeMap::new(),
+        }
+    }
+}
+
+// Score one combination with the params held fixed: no optimizer, no learned overrides
+pub async fn evaluate(
+    config: Arc<Config>,
+    source: TuneSource,
+    params: StrategyParams,
+) -> TuneResult {
+    let mut config = Arc::unwrap_or_clone(config);
+    config.strategies = vec![config.tune.strategy];
+    config.strategy = params.clone();
+    let pnls: Vec<f64> = match source {
+        TuneSource::Synthetic(games) => play_games(Arc::new(config), &games, false)
+            .await
+            .iter()
+            .map(|result| result.final_pnl)
+            .collect(),
+        TuneSource::Transcript(entries) => {
+            config.dry_run = true;
+            replay(&entries, Arc::new(config))
+                .await
+                .into_values()
+                .flat_map(|report| report.game_pnls)
+                .collect()
+        }
+    };
+    TuneResult::from_pnls(params, &pnls)
+}
+
+// Evaluate every combination in the grid, `workers` at a time, calling `progress` with
+// the number done after each. Best first by the configured ranking.
+pub async fn run_tune(
+    config: &Config,
+    source: TuneSource,
+    mut progress: impl FnMut(usize, usize),
+) -> Vec<TuneResult> {
+    let tune = &config.tune;
+    let workers = match tune.workers {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        workers => workers,
+    };
+    let combinations = tune.grid.combinations(&config.strategy);
+    let total = combinations.len();
+    let shared = Arc::new(config.clone());
+
+    // Each evaluation is its own task so they spread across the executor's threads
+    let mut evaluations = stream::iter(combinations.into_iter().enumerate())
+        .map(|(index, params)| {
+            let evaluation = evaluate(Arc::clone(&shared), source.clone(), params);
+            async move { (index, rt::spawn(evaluation).await) }
+        })
+        .buffer_unordered(workers);
+
+    let mut results = Vec::with_capacity(total);
+    while let Some(result) = evaluations.next().await {
+        results.push(result);
+        progress(results.len(), total);
+    }
+
+    // Grid order breaks ties, so a run is repeatable whatever order tasks finished in
+    results.sort_by(|(a_index, a), (b_index, b)| {
+        rank_key(b, tune.rank_by)
+            .total_cmp(&rank_key(a, tune.rank_by))
+            .then(a_index.cmp(b_index))
+    });
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+fn rank_key(result: &TuneResult, rank_by: RankBy) -> f64 {
+    match rank_by {
+        RankBy::Mean => result.mean_pnl,
+        RankBy::Sharpe => result.sharpe.unwrap_or(f64::NEG_INFINITY),
+    }
+}
+
+// Aligned table of the best `top` results, one column per swept param
+pub fn format_results(results: &[TuneResult], grid: &GridConfig, top: usize) -> String {
+    let axes = grid.axes();
+    let mut table = String::from("  rank");
+    for axis in &axes {
+        let _ = write!(table, " {:>25}", axis.name);
+    }
+    let _ = writeln!(table, " {:>9} {:>7} {:>6}", "mean", "sharpe", "games");
+    for (rank, result) in results.iter().take(top).enumerate() {
+        let _ = write!(table, "  {:>4}", rank + 1);
+        for axis in &axes {
+            let _ = write!(table, " {:>25.3}", (axis.get)(&result.params));
+        }
+        let sharpe = result
+            .sharpe
+            .map_or("n/a".to_string(), |sharpe| format!("{:.3}", sharpe));
+        let _ = writeln!(
+            table,
+            " {:>9.2} {:>7} {:>6}",
+            result.mean_pnl, sharpe, result.games
+        );
+    }
+    table
+}
@@ -0,0 +1,232 @@
+#![cfg_attr(not(feature = "puzzles"), allow(dead_code, unused_imports))]
+
+mod common;
+
+use common::{state_frame, temp_dir, test_config};
+use optiva_ws::analysis::{analyze_journal, format_report};
+use optiva_ws::handler::ConnectionHandler;
+use optiva_ws::journal::{load_journal, Journal, JournalConfig, JournalEntry, JournalFormat};
+use optiva_ws::state::SharedState;
+use optiva_ws::strategy::{DecisionMode, Driver};
+use serde_json::json;
+use std::sync::Arc;
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-9
+}
+
+// A decision at `timestamp` on conn 0, reporting `pnl_change` since the last one
+fn decision(timestamp: f64, signal: f64, sent: bool, pnl_change: Option<f64>) -> JournalEntry {
+    JournalEntry {
+        timestamp,
+        conn_id: 0,
+        strategy: "blend".to_string(),
+        price: 100.0,
+        forecast: 0.0,
+        momentum: 0.0,
+        combined_signal: signal,
+        position_before: 0,
+        position_after: if sent { 1 } else { 0 },
+        volume: 1,
+        sent,
+        pnl: 0.0,
+        pnl_change,
+        mode: DecisionMode::Follow,
+        account: String::new(),
+        arm: None,
+        decision_id: None,
+        driver: None,
+    }
+}
+
+fn puzzle(timestamp: f64) -> JournalEntry {
+    JournalEntry {
+        mode: DecisionMode::Puzzle,
+        combined_signal: 0.0,
+        ..decision(timestamp, 0.0, true, None)
+    }
+}
+
+fn journal(entries: Vec<JournalEntry>) -> optiva_ws::journal::LoadedJournal {
+    optiva_ws::journal::LoadedJournal {
+        entries,
+        malformed: 0,
+    }
+}
+
+#[test]
+fn trades_are_judged_by_the_next_pnl_change_on_their_connection() {
+    let report = analyze_journal(&journal(vec![
+        decision(3600.0, 0.1, true, None),
+        decision(3601.0, 0.6, true, Some(2.0)),
+        puzzle(3602.0),
+        // Another connection in between doesn't count as what followed
+        JournalEntry {
+            conn_id: 1,
+            ..decision(3603.0, 0.9, false, Some(-50.0))
+        },
+        decision(7200.0, -0.9, false, Some(-1.0)),
+        decision(7201.0, 0.3, true, Some(4.0)),
+        // A new session, so the last trade has nothing to be judged by
+        decision(7202.0, 0.3, true, None),
+    ]));
+
+    assert_eq!(report.decisions, 7);
+    // 0.1 then 0.6 (won and lost), 0.3 at the end of the session never judged
+    let buckets: Vec<(usize, usize)> = report
+        .signal_buckets
+        .iter()
+        .map(|bucket| (bucket.trades, bucket.wins))
+        .collect();
+    assert_eq!(buckets, [(1, 1), (0, 0), (1, 0), (0, 0)]);
+    assert_eq!(report.signal_buckets[3].max, None);
+
+    // The puzzle and the 0.6 trade before it are both followed by the -1 update
+    assert_eq!(report.after_puzzle_trades.trades, 1);
+    assert_eq!(report.after_puzzle_trades.mean_pnl_change, Some(-1.0));
+    assert_eq!(report.after_regular_trades.trades, 2);
+    assert_eq!(report.after_regular_trades.mean_pnl_change, Some(0.5));
+
+    // (0.1, 2), (0.6, -1), (-0.9, 4): a bigger signal did worse here
+    assert!(report.signal_correlation.unwrap() < 0.0);
+
+    let hours: Vec<(i64, usize, f64)> = report
+        .hours
+        .iter()
+        .map(|hour| (hour.hour, hour.decisions, hour.pnl_change))
+        .collect();
+    assert_eq!(hours, [(3600, 4, -48.0), (7200, 3, 3.0)]);
+    assert_eq!(report.connections.len(), 2);
+    assert!(close(report.connections[0].stats.total_pnl_change, 5.0));
+}
+
+#[test]
+fn pnl_changes_are_credited_once_to_the_decision_just_before() {
+    let led_by = |driver: Driver, entry: JournalEntry| JournalEntry {
+        driver: Some(driver),
+        ..entry
+    };
+    let report = analyze_journal(&journal(vec![
+        led_by(Driver::Momentum, decision(1.0, 0.6, true, None)),
+        led_by(Driver::Forecast, decision(2.0, 0.2, false, Some(2.0))),
+        // The forecast decision is passed over, what follows is the puzzle's
+        led_by(Driver::Puzzle, puzzle(3.0)),
+        led_by(Driver::Momentum, decision(4.0, 0.6, true, Some(-1.0))),
+        // A manual trade has no driver, so the change after it goes to no one
+        JournalEntry {
+            mode: DecisionMode::Manual,
+            ..decision(5.0, 0.0, true, None)
+        },
+        // Only holding, so its 3.0 counts to the PnL but not as a trade
+        led_by(Driver::Momentum, decision(6.0, 0.6, false, Some(5.0))),
+        led_by(Driver::Forecast, decision(7.0, 0.6, false, Some(3.0))),
+    ]));
+
+    let drivers = &report.drivers;
+    assert!(close(drivers.momentum.pnl, 5.0));
+    assert_eq!((drivers.momentum.trades, drivers.momentum.wins), (1, 1));
+    assert!(close(drivers.forecast.pnl, 0.0));
+    assert!(close(drivers.puzzle.pnl, -1.0));
+    assert_eq!((drivers.puzzle.trades, drivers.puzzle.wins), (1, 0));
+    assert_eq!(drivers.puzzle.win_rate(), Some(0.0));
+
+    let table = format_report(&report);
+    assert!(table.contains("PnL by driver:"), "{}", table);
+    assert!(table.contains("momentum"), "{}", table);
+}
+
+#[test]
+fn the_report_prints_as_a_table_and_as_json() {
+    let report = analyze_journal(&journal(vec![
+        decision(1_700_000_000.0, 0.5, true, None),
+        decision(1_700_000_001.0, 0.5, true, Some(1.0)),
+    ]));
+    let table = format_report(&report);
+    assert!(table.contains("2023-11-14 22:00"), "{}", table);
+    assert!(table.contains("0.50-0.75"), "{}", table);
+    assert!(table.contains("0.75+"), "{}", table);
+
+    let value = serde_json::to_value(&report).unwrap();
+    assert_eq!(value["decisions"], json!(2));
+    assert_eq!(value["connections"][0]["trades_sent"], json!(2));
+    assert_eq!(value["signal_buckets"][2]["win_rate"], json!(1.0));
+    assert_eq!(value["after_puzzle_trades"]["mean_pnl_change"], json!(null));
+}
+
+#[test]
+fn calibration_covers_strategy_decisions_only() {
+    let report = analyze_journal(&journal(vec![
+        JournalEntry {
+            momentum: 25.0,
+            forecast: 0.1,
+            ..decision(1.0, 0.97, true, None)
+        },
+        JournalEntry {
+            momentum: -2.0,
+            forecast: -1.5,
+            ..decision(2.0, -0.6, false, Some(1.0))
+        },
+        puzzle(3.0),
+    ]));
+    let calibration = &report.calibration;
+    assert_eq!(calibration.decisions, 2);
+    // tanh(25 / 10) and tanh(1.5 * 2) are both past 0.95
+    assert_eq!(calibration.momentum_saturated, 1);
+    assert_eq!(calibration.forecast_saturated, 1);
+    assert_eq!(calibration.signal_saturated, 1);
+    assert!(format_report(&report).contains("Signal calibration over 2 decisions"));
+}
+
+#[test]
+fn malformed_lines_are_counted_and_skipped() {
+    let path = temp_dir("analysis-malformed").join("journal.jsonl");
+    let good = serde_json::to_string(&decision(1000.0, 0.5, true, None)).unwrap();
+    std::fs::write(
+        &path,
+        format!("{}\nnot json\n\n{{\"timestamp\": 1}}\n{}\n", good, good),
+    )
+    .unwrap();
+
+    let loaded = load_journal(&path).unwrap();
+    assert_eq!(loaded.entries.len(), 2);
+    assert_eq!(loaded.malformed, 2);
+    assert_eq!(analyze_journal(&loaded).malformed, 2);
+
+    let path = temp_dir("analysis-malformed-csv").join("journal.csv");
+    let row = decision(1000.0, 0.5, true, Some(1.0)).to_csv_row();
+    std::fs::write(&path, format!("{}\n1,2,3\n{}\n", row, row)).unwrap();
+    let loaded = load_journal(&path).unwrap();
+    assert_eq!((loaded.entries.len(), loaded.malformed), (2, 1));
+}
+
+#[cfg(feature = "puzzles")]
+#[optiva_ws::rt::test]
+async fn puzzle_trades_are_journaled() {
+    let config = JournalConfig {
+        enabled: true,
+        path: temp_dir("analysis-puzzle").join("journal.jsonl"),
+        format: JournalFormat::Jsonl,
+        flush_secs: 0.05,
+    };
+    let (journal, writer) = Journal::spawn(&config).await.unwrap();
+    let bot_config = Arc::new(test_config());
+    let state = Arc::new(SharedState::new(&bot_config));
+    let mut handler = ConnectionHandler::new(0, bot_config, state).with_journal(journal);
+    common::join(&mut handler).await;
+
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&state_frame(100.0, 0.0, 0.0, 0, 0.0), &mut outbox)
+        .await;
+    let puzzle = json!({ "event": "puzzle", "data": { "impact": -2.0 } }).to_string();
+    handler.handle_text(&puzzle, &mut outbox).await;
+    drop(handler);
+    writer.await;
+
+    let loaded = load_journal(&config.path).unwrap();
+    let entry = loaded.entries.last().unwrap();
+    assert_eq!(entry.mode, DecisionMode::Puzzle);
+    assert!(entry.sent);
+    assert!(entry.position_after < entry.position_before);
+    assert_eq!(entry.pnl_change, None);
+}
@@ -0,0 +1,151 @@
+mod common;
+
+use optiva_ws::backtest::{
+    game_seeds, run_backtest, BacktestConfig, GameResult, StrategyReport, SyntheticGame,
+};
+use optiva_ws::config::Config;
+use optiva_ws::paper::CostConfig;
+use optiva_ws::strategy::StrategyKind;
+
+fn backtest_config(seed: u64) -> Config {
+    let mut config = common::test_config();
+    config.backtest = BacktestConfig {
+        games: 20,
+        updates_per_game: 50,
+        seed,
+        puzzle_chance: 0.1,
+        ..BacktestConfig::default()
+    };
+    config
+}
+
+#[test]
+fn games_are_generated_from_the_seed() {
+    let config = BacktestConfig::default();
+    let seed = game_seeds(&config)[0];
+    let game = SyntheticGame::generate(&config, seed);
+    assert_eq!(game.ticks.len(), config.updates_per_game);
+    assert_eq!(game.ticks[0].price, config.start_price);
+    assert_eq!(game.ticks[0].momentum, 0.0);
+    assert_eq!(game, SyntheticGame::generate(&config, seed));
+    assert_ne!(game, SyntheticGame::generate(&config, seed + 1));
+}
+
+#[test]
+fn puzzles_move_the_price_their_way() {
+    let config = BacktestConfig {
+        volatility: 0.0,
+        forecast_noise: 0.0,
+        puzzle_chance: 1.0,
+        updates_per_game: 10,
+        ..BacktestConfig::default()
+    };
+    let game = SyntheticGame::generate(&config, 7);
+    let first = game.ticks[0].puzzle.unwrap();
+    // The forecast sees the move coming and the price follows
+    assert!(game.ticks[0].forecast * first > 0.0);
+    assert!((game.ticks[1].price - game.ticks[0].price) * first > 0.0);
+}
+
+#[optiva_ws::rt::test]
+async fn same_seed_gives_the_same_results() {
+    let (reports, results) = run_backtest(&backtest_config(7)).await;
+    let (again, again_results) = run_backtest(&backtest_config(7)).await;
+    assert_eq!(reports, again);
+    assert_eq!(results, again_results);
+
+    let (_, other) = run_backtest(&backtest_config(8)).await;
+    assert_ne!(results, other);
+}
+
+#[optiva_ws::rt::test]
+async fn every_strategy_plays_every_game() {
+    let config = backtest_config(7);
+    let (reports, results) = run_backtest(&config).await;
+    let names: Vec<_> = reports.iter().map(|report| report.strategy).collect();
+    assert_eq!(
+        names,
+        ["blend", "forecast_only", "mean_reversion", "blend_fade"]
+    );
+    assert!(reports.iter().all(|report| report.games == 20));
+    assert_eq!(results.len(), 80);
+    // The same games, in the same order, for each
+    assert_eq!(results[0].seed, results[20].seed);
+    assert!(results.iter().any(|result| result.trades > 0));
+}
+
+#[optiva_ws::rt::test]
+async fn strategies_can_be_picked() {
+    let mut config = backtest_config(7);
+    config.backtest.strategies = vec![StrategyKind::ForecastOnly];
+    let (reports, _) = run_backtest(&config).await;
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].strategy, "forecast_only");
+}
+
+#[test]
+fn report_summarizes_the_pnl_distribution() {
+    let results: Vec<GameResult> = (0..=20)
+        .map(|game| GameResult {
+            strategy: "blend",
+            game,
+            seed: game as u64,
+            final_pnl: game as f64 - 10.0,
+            costs: 0.0,
+            trades: 2,
+            rejected: 0,
+        })
+        .collect();
+    let report = StrategyReport::from_results("blend", &results);
+    assert_eq!(report.games, 21);
+    assert_eq!(report.mean, 0.0);
+    assert_eq!(report.min, -10.0);
+    assert_eq!(report.p5, -9.0);
+    assert_eq!(report.median, 0.0);
+    assert_eq!(report.p95, 9.0);
+    assert_eq!(report.max, 10.0);
+    assert!((report.win_rate - 10.0 / 21.0).abs() < 1e-9);
+    assert_eq!(report.mean_trades, 2.0);
+}
+
+#[test]
+fn backtest_section_is_validated() {
+    let err = Config::parse(
+        r#"
+        url = "wss://example.com"
+        player_id = "abc"
+
+        [backtest]
+        puzzle_chance = 1.5
+        "#,
+    );
+    assert!(err.is_err());
+}
+
+#[optiva_ws::rt::test]
+async fn results_are_after_trading_costs() {
+    let mut config = backtest_config(7);
+    config.backtest.strategies = vec![StrategyKind::Blend];
+    config.costs = CostConfig {
+        fee_per_unit: 0.25,
+        half_spread: 0.0,
+    };
+    let (_, results) = run_backtest(&config).await;
+    assert!(results.iter().any(|result| result.trades > 0));
+    for result in &results {
+        // Every trade is at least one unit
+        assert!(result.costs >= 0.25 * result.trades as f64, "{:?}", result);
+        assert_eq!(result.costs == 0.0, result.trades == 0);
+    }
+}
+
+#[optiva_ws::rt::test]
+async fn a_backtest_leaves_the_saved_params_alone() {
+    let dir = common::temp_dir("backtest-persist");
+    let mut config = backtest_config(7);
+    config.persist.enabled = true;
+    config.persist.path = dir.join("params.json");
+    config.backtest.updates_per_game = 200;
+    run_backtest(&config).await;
+    assert!(!config.persist.path.exists());
+}
@@ -0,0 +1,196 @@
+#![cfg_attr(not(feature = "auto-optimize"), allow(dead_code, unused_imports))]
+
+mod common;
+
+use optiva_ws::bandit::{ArmConfig, ArmStats, Bandit, BanditConfig, Posterior};
+use optiva_ws::clock::Monotonic;
+use optiva_ws::config::Config;
+use optiva_ws::state::{PerformanceData, SharedState, Sizing, StrategyParams};
+use optiva_ws::strategy::Driver;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+fn arm(name: &str, momentum_weight: f64) -> ArmConfig {
+    ArmConfig {
+        name: name.to_string(),
+        momentum_weight: Some(momentum_weight),
+        ..ArmConfig::default()
+    }
+}
+
+fn bandit(posterior: Posterior, seed: u64) -> Bandit {
+    let config = BanditConfig {
+        posterior,
+        arms: vec![
+            arm("momentum", 0.8),
+            arm("balanced", 0.5),
+            arm("forecast", 0.2),
+        ],
+    };
+    Bandit::new(&config, Some(seed))
+}
+
+fn assignments(bandit: &mut Bandit) -> Vec<String> {
+    (0..8)
+        .map(|conn_id| bandit.arm_for(conn_id).unwrap().name.clone())
+        .collect()
+}
+
+#[test]
+fn arms_only_replace_the_params_they_set() {
+    let mut params = StrategyParams::default();
+    let arm = ArmConfig {
+        name: "proportional".to_string(),
+        deadband: Some(0.2),
+        sizing: Some(Sizing::Proportional),
+        ..ArmConfig::default()
+    };
+    arm.apply(&mut params);
+    assert_eq!(params.deadband, 0.2);
+    assert_eq!(params.sizing, Sizing::Proportional);
+    assert_eq!(
+        params.momentum_weight,
+        StrategyParams::default().momentum_weight
+    );
+}
+
+#[test]
+fn draws_repeat_under_the_same_seed() {
+    let first = assignments(&mut bandit(Posterior::Normal, 7));
+    assert_eq!(first, assignments(&mut bandit(Posterior::Normal, 7)));
+
+    let mut a = bandit(Posterior::Beta, 7);
+    let mut b = bandit(Posterior::Beta, 7);
+    assignments(&mut a);
+    assignments(&mut b);
+    assert_eq!(a.redraw(), b.redraw());
+}
+
+#[test]
+fn a_connection_keeps_its_arm_until_the_redraw() {
+    let mut bandit = bandit(Posterior::Normal, 3);
+    assert!(bandit.assigned(0).is_none());
+    let name = bandit.arm_for(0).unwrap().name.clone();
+    for _ in 0..5 {
+        assert_eq!(bandit.arm_for(0).unwrap().name, name);
+    }
+    assert_eq!(bandit.assigned(0).unwrap().name, name);
+    let redrawn = bandit.redraw();
+    assert_eq!(redrawn.len(), 1);
+    assert_eq!(bandit.assigned(0).unwrap().name, redrawn[0].1);
+}
+
+// The balanced arm pays and the others lose, so the redraws should come to favour it
+fn train(bandit: &mut Bandit) {
+    for conn_id in 0..3 {
+        bandit.arm_for(conn_id);
+    }
+    for round in 0..30 {
+        for conn_id in 0..3 {
+            let arm = bandit.assigned(conn_id).unwrap().name.clone();
+            let pnl_change = if arm == "balanced" { 2.0 } else { -2.0 };
+            bandit.observe(conn_id, pnl_change + (round % 3) as f64 - 1.0);
+        }
+        bandit.redraw();
+    }
+}
+
+#[test]
+fn the_arm_that_pays_is_drawn_most() {
+    for posterior in [Posterior::Normal, Posterior::Beta] {
+        let mut bandit = bandit(posterior, 11);
+        train(&mut bandit);
+        let drawn = bandit
+            .redraw()
+            .into_iter()
+            .filter(|(_, arm)| arm == "balanced")
+            .count();
+        assert!(drawn >= 2, "{:?} drew balanced {} of 3", posterior, drawn);
+        let posteriors = bandit.posteriors();
+        assert!(posteriors["balanced"].count > posteriors["momentum"].count);
+        assert!(posteriors["balanced"].mean > 0.0);
+    }
+}
+
+#[test]
+fn stats_follow_the_pnl_changes() {
+    let mut stats = ArmStats::default();
+    assert_eq!(stats.variance(), None);
+    for pnl_change in [1.0, 3.0, -1.0] {
+        stats.observe(pnl_change);
+    }
+    assert_eq!(stats.count, 3);
+    assert_eq!(stats.wins, 2);
+    assert!((stats.mean - 1.0).abs() < 1e-9);
+    assert!((stats.variance().unwrap() - 4.0).abs() < 1e-9);
+}
+
+#[test]
+fn saved_posteriors_come_back_by_name() {
+    let mut trained = bandit(Posterior::Normal, 5);
+    train(&mut trained);
+    let saved = trained.posteriors();
+
+    let mut restored = bandit(Posterior::Normal, 5);
+    let mut renamed = saved.clone();
+    let momentum = renamed.remove("momentum").unwrap();
+    renamed.insert("retired".to_string(), momentum);
+    restored.restore(&renamed);
+    let posteriors = restored.posteriors();
+    assert_eq!(posteriors["balanced"], saved["balanced"]);
+    assert_eq!(posteriors["momentum"], ArmStats::default());
+    assert!(!posteriors.contains_key("retired"));
+    assert_eq!(
+        BTreeMap::new(),
+        Bandit::new(&BanditConfig::default(), None).posteriors()
+    );
+}
+
+fn bandit_config() -> Config {
+    let mut config = common::test_config();
+    config.optimizer.seed = Some(1);
+    config.bandit.arms = vec![arm("momentum", 0.9), arm("forecast", 0.1)];
+    config
+}
+
+#[cfg(feature = "auto-optimize")]
+#[optiva_ws::rt::test]
+async fn connections_trade_their_arm_and_are_credited_with_its_pnl() {
+    let state = SharedState::new(&bandit_config());
+    assert_eq!(state.arm_name(0).await, None);
+    let params = state.params_for(0).await;
+    let name = state.arm_name(0).await.unwrap();
+    let expected = if name == "momentum" { 0.9 } else { 0.1 };
+    assert_eq!(params.momentum_weight, expected);
+    // The global params are left as they were
+    assert_eq!(state.strategy_params.read().await.momentum_weight, 0.6);
+
+    state
+        .record_performance(PerformanceData {
+            conn_id: 0,
+            timestamp: Monotonic(0.0),
+            momentum: 1.0,
+            forecast: 1.0,
+            position: 1,
+            trade_volume: 1,
+            pnl_change: 4.0,
+            price: 100.0,
+            total_pnl: 4.0,
+            signal: 0.5,
+            decision_id: 0,
+            driver: Driver::Momentum,
+        })
+        .await;
+    let posteriors = state.bandit.lock().await.posteriors();
+    assert_eq!(posteriors[&name].count, 1);
+    assert_eq!(posteriors[&name].mean, 4.0);
+}
+
+#[optiva_ws::rt::test]
+async fn without_the_optimizer_the_arms_stay_out() {
+    let mut config = bandit_config();
+    config.optimizer.enabled = false;
+    let state = Arc::new(SharedState::new(&config));
+    assert_eq!(state.params_for(0).await.momentum_weight, 0.6);
+    assert_eq!(state.arm_name(0).await, None);
+}
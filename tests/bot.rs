@@ -0,0 +1,67 @@
+mod common;
+
+use common::server::{event_name, MockServer, Scenario};
+use common::test_config;
+use optiva_ws::bot::TradingBot;
+use optiva_ws::protocol::ClientEvent;
+use optiva_ws::strategy::{MarketContext, Strategy};
+
+// Always wants to be fully short, whatever the server forecasts
+struct AlwaysShort;
+
+impl Strategy for AlwaysShort {
+    fn name(&self) -> &'static str {
+        "always_short"
+    }
+
+    fn signal(&self, _ctx: &MarketContext) -> f64 {
+        -1.0
+    }
+
+    fn scale(&self, _ctx: &MarketContext) -> f64 {
+        1.0
+    }
+}
+
+#[optiva_ws::rt::test]
+async fn a_bot_run_from_code_trades_its_strategy_and_reports_on_shutdown() {
+    let server = MockServer::bind().await;
+    let mut config = test_config();
+    config.transcript.enabled = false;
+    config.summary.enabled = false;
+    config.curve.enabled = false;
+    let handle = TradingBot::from_config(config)
+        .url(server.url.clone())
+        .connections(1)
+        .strategy(Box::new(AlwaysShort))
+        .run()
+        .await
+        .unwrap();
+
+    let mut connection = server.accept().await;
+    connection.play(&Scenario::load("bot_game")).await;
+    let trades: Vec<i32> = connection
+        .received
+        .iter()
+        .filter_map(|message| match &message.event {
+            ClientEvent::Trade(trade) => Some(trade.volume),
+            _ => None,
+        })
+        .collect();
+    // The forecast says buy, but the strategy given to the builder sells
+    assert_eq!(trades, vec![-3]);
+    assert!(connection
+        .received
+        .iter()
+        .any(|message| event_name(message) == "connection"));
+
+    let snapshot = handle.snapshot().await;
+    assert_eq!(snapshot.connections.len(), 1);
+
+    let summaries = handle.shutdown().await;
+    assert_eq!(summaries.len(), 1);
+    let games = &summaries[0].games;
+    assert_eq!(games.len(), 1);
+    assert_eq!(games[0].final_pnl, Some(6.0));
+    assert_eq!(summaries[0].connections.len(), 1);
+}
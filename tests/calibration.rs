@@ -0,0 +1,43 @@
+use optiva_ws::calibration::{format_calibration, Calibration, Histogram};
+use optiva_ws::state::StrategyParams;
+
+#[test]
+fn histogram_buckets_by_absolute_value_with_an_open_last_bucket() {
+    let mut histogram = Histogram::new(&[1.0, 2.0]);
+    for value in [0.5, -0.5, 1.0, -1.5, 7.0, f64::NAN] {
+        histogram.add(value);
+    }
+    assert_eq!(histogram.counts, [2, 2, 1]);
+    assert_eq!(histogram.total, 5);
+    assert_eq!(histogram.max_abs, 7.0);
+    assert_eq!(histogram.mean_abs(), Some(2.1));
+    assert_eq!(Histogram::new(&[1.0]).mean_abs(), None);
+}
+
+#[test]
+fn saturation_is_judged_with_the_params_scaling() {
+    let mut calibration = Calibration::default();
+    let params = StrategyParams::default();
+    // tanh(20 / 10) is 0.964, tanh(0.5 * 2) 0.762
+    calibration.observe(20.0, 0.5, 0.9, &params);
+    assert_eq!(calibration.momentum_saturated, 1);
+    assert_eq!(calibration.forecast_saturated, 0);
+
+    // Scaled down, the same momentum no longer saturates
+    let scaled = StrategyParams {
+        momentum_divisor: 30.0,
+        ..StrategyParams::default()
+    };
+    calibration.observe(20.0, 0.5, 0.9, &scaled);
+    assert_eq!(calibration.momentum_saturated, 1);
+    assert_eq!(calibration.momentum_divisor, 30.0);
+
+    let report = format_calibration(&calibration);
+    assert!(report.contains("momentum / 30"), "{}", report);
+    assert!(
+        report.contains("momentum 50%, forecast 0%, combined_signal 0%"),
+        "{}",
+        report
+    );
+    assert!(report.contains("20-30"), "{}", report);
+}
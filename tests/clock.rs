@@ -0,0 +1,111 @@
+mod common;
+
+use optiva_ws::clock::{Clock, ManualClock, Monotonic, NonDecreasing, SystemClock};
+use optiva_ws::handler::ConnectionHandler;
+#[cfg(feature = "auto-optimize")]
+use optiva_ws::optimizer::optimize_strategy;
+#[cfg(feature = "auto-optimize")]
+use optiva_ws::state::PerformanceData;
+use optiva_ws::state::SharedState;
+#[cfg(feature = "auto-optimize")]
+use optiva_ws::strategy::Driver;
+use std::sync::Arc;
+
+#[test]
+fn non_decreasing_holds_the_latest_reading() {
+    let latest = NonDecreasing::new(10.0);
+    assert_eq!(latest.observe(12.0), 12.0);
+    assert_eq!(latest.observe(11.0), 12.0);
+    assert_eq!(latest.observe(-5.0), 12.0);
+    assert_eq!(latest.observe(13.5), 13.5);
+    assert_eq!(latest.get(), 13.5);
+}
+
+#[test]
+fn setting_a_manual_clock_back_only_moves_the_wall_clock() {
+    let clock = ManualClock::new(1000.0);
+    clock.set(900.0);
+    assert_eq!(clock.now(), 900.0);
+    assert_eq!(clock.monotonic(), Monotonic(1000.0));
+
+    // Time has to catch up before intervals move again
+    clock.advance(50.0);
+    assert_eq!(clock.monotonic(), Monotonic(1000.0));
+    clock.advance(60.0);
+    assert_eq!(clock.monotonic(), Monotonic(1010.0));
+}
+
+#[test]
+fn system_clock_never_reads_backwards() {
+    let clock = SystemClock::default();
+    let (mut wall, mut monotonic) = (clock.now(), clock.monotonic());
+    for _ in 0..1000 {
+        let (next_wall, next_monotonic) = (clock.now(), clock.monotonic());
+        assert!(next_wall >= wall);
+        assert!(next_monotonic >= monotonic);
+        (wall, monotonic) = (next_wall, next_monotonic);
+    }
+}
+
+#[test]
+fn monotonic_arithmetic_is_in_seconds() {
+    let start = Monotonic(100.0);
+    assert_eq!(start + 2.5, Monotonic(102.5));
+    assert_eq!(Monotonic(102.5) - start, 2.5);
+}
+
+#[optiva_ws::rt::test]
+async fn clock_going_backwards_mid_game_keeps_intervals_sane() {
+    let config = Arc::new(common::test_config());
+    let clock = Arc::new(ManualClock::new(1000.0));
+    let state = Arc::new(SharedState::with_clock(&config, clock.clone()));
+    let mut handler = ConnectionHandler::new(0, Arc::clone(&config), Arc::clone(&state));
+    common::join(&mut handler).await;
+
+    // An NTP correction pulls the wall clock back an hour partway through
+    let mut outbox = Vec::new();
+    for tick in 0..20 {
+        clock.advance(1.0);
+        if tick == 10 {
+            clock.set(clock.now() - 3600.0);
+        }
+        let pnl = if tick % 2 == 0 { 5.0 } else { -3.0 };
+        let frame = common::state_frame(100.0 + tick as f64, 1.0, 1.0, tick % 3, pnl);
+        handler.handle_text(&frame, &mut outbox).await;
+    }
+
+    let history = state.trade_history.lock().await;
+    assert!(!history.is_empty());
+    assert!(history
+        .iter()
+        .zip(history.iter().skip(1))
+        .all(|(earlier, later)| later.timestamp >= earlier.timestamp));
+    drop(history);
+
+    #[cfg(feature = "auto-optimize")]
+    {
+        // The optimizer's interval hasn't passed on the monotonic clock, whatever the wall says
+        for _ in 0..5 {
+            state
+                .performance_history
+                .lock()
+                .await
+                .push(PerformanceData {
+                    conn_id: 0,
+                    timestamp: state.monotonic(),
+                    momentum: 1.0,
+                    forecast: 1.0,
+                    position: 1,
+                    trade_volume: 1,
+                    pnl_change: -10.0,
+                    price: 100.0,
+                    total_pnl: 0.0,
+                    signal: 1.0,
+                    decision_id: 0,
+                    driver: Driver::Momentum,
+                });
+        }
+        assert!(!optimize_strategy(&state).await);
+        assert!(state.monotonic() - *state.last_optimization.read().await >= 0.0);
+    }
+}
@@ -0,0 +1,87 @@
+#![allow(dead_code)]
+
+use optiva_ws::config::Config;
+use optiva_ws::handler::ConnectionHandler;
+use optiva_ws::protocol::{ClientEvent, ClientMessage, ConnectionAck, ServerEvent};
+use serde_json::json;
+
+pub const PLAYER_ID: &str = "test-player";
+
+pub fn test_config() -> Config {
+    Config::parse(&format!(
+        r#"
+        url = "ws://localhost:9000"
+        player_id = "{}"
+
+        [persist]
+        enabled = false
+
+        # Nothing a test runs should land in the repo's own journal.jsonl
+        [journal]
+        enabled = false
+
+        [warm_up]
+        updates = 0
+
+        # Tests send the same frame again for a market that hasn't moved
+        [duplicates]
+        enabled = false
+        "#,
+        PLAYER_ID
+    ))
+    .unwrap()
+}
+
+// A state frame as the server sends it
+pub fn state_frame(price: f64, forecast: f64, momentum: f64, position: i32, pnl: f64) -> String {
+    json!({
+        "event": "state",
+        "data": {
+            "price": price,
+            "price_forecast": forecast,
+            "momentum": momentum,
+            "position": position,
+            "position_limit": 3,
+            "pnl": pnl
+        }
+    })
+    .to_string()
+}
+
+// A fresh directory under the system temp dir for tests that write files
+pub fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("optiva-ws-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub mod server;
+
+// Open a session and have the server accept it, so the handler takes game traffic
+pub async fn join(handler: &mut ConnectionHandler) {
+    handler.start_session().await;
+    handler
+        .handle_event(
+            ServerEvent::Connection(ConnectionAck::accepting(PLAYER_ID)),
+            &mut Vec::new(),
+        )
+        .await;
+}
+
+pub fn trade_volumes(outbox: &[ClientMessage]) -> Vec<i32> {
+    outbox
+        .iter()
+        .filter_map(|message| match &message.event {
+            ClientEvent::Trade(trade) => Some(trade.volume),
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn trades(outbox: &[ClientMessage]) -> usize {
+    outbox
+        .iter()
+        .filter(|message| matches!(message.event, ClientEvent::Trade(_)))
+        .count()
+}
@@ -0,0 +1,265 @@
+// A stand-in for the game server, so the connection lifecycle can be tested on localhost
+use async_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use async_tungstenite::tungstenite::protocol::CloseFrame;
+use async_tungstenite::tungstenite::Message;
+use async_tungstenite::WebSocketStream;
+use futures::{AsyncWriteExt, SinkExt, StreamExt};
+use optiva_ws::protocol::{ClientEvent, ClientMessage};
+use optiva_ws::rt;
+use optiva_ws::rt::net::{TcpListener, TcpStream};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::path::Path;
+use std::time::Duration;
+
+// Longest the server waits for the bot before failing the test
+const EXPECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// One step of a scripted game, played after the connection handshake
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Step {
+    // Send a server event exactly as written
+    Send(Value),
+    // Send several events in one write, so they arrive queued together
+    Burst(Vec<Value>),
+    // Read messages until the bot sends one with this event name
+    Expect(String),
+    // Ping with this payload and read until the bot pongs it back
+    Ping(String),
+    // Close the connection with this code
+    Close(u16),
+    // Read until the bot closes the connection
+    ExpectClose,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Scenario {
+    pub steps: Vec<Step>,
+}
+
+impl Scenario {
+    // Load tests/scenarios/<name>.json
+    pub fn load(name: &str) -> Scenario {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/scenarios")
+            .join(format!("{}.json", name));
+        let text = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("reading {}: {}", path.display(), e));
+        serde_json::from_str(&text).unwrap_or_else(|e| panic!("parsing {}: {}", path.display(), e))
+    }
+}
+
+pub struct MockServer {
+    listener: TcpListener,
+    pub url: String,
+}
+
+pub fn event_name(message: &ClientMessage) -> &'static str {
+    match message.event {
+        ClientEvent::Connection(_) => "connection",
+        ClientEvent::Start(_) => "start",
+        ClientEvent::Trade(_) => "trade",
+        ClientEvent::Skip(_) => "skip",
+        ClientEvent::Answer(_) => "answer",
+    }
+}
+
+impl MockServer {
+    pub async fn bind() -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        MockServer { listener, url }
+    }
+
+    // Accept one connection, ack its connection message with the player_id it sent and
+    // play the scenario. Returns every message received up to the end of the script.
+    pub async fn play(self, scenario: &Scenario) -> Vec<ClientMessage> {
+        let mut connection = self.accept().await;
+        connection.play(scenario).await;
+        connection.received
+    }
+
+    // Accept the next connection and ack its connection message with the player_id it sent
+    pub async fn accept(&self) -> MockConnection {
+        let mut connection = self.accept_unacked().await;
+        let player_id = match &connection.received.last().unwrap().event {
+            ClientEvent::Connection(data) => data.player_id.clone(),
+            _ => unreachable!(),
+        };
+        connection
+            .send(json!({ "event": "connection", "data": { "player_id": player_id } }))
+            .await;
+        connection
+    }
+
+    // Accept the next connection and read its connection message, leaving it unanswered
+    pub async fn accept_unacked(&self) -> MockConnection {
+        let (stream, _) = rt::timeout(EXPECT_TIMEOUT, self.listener.accept())
+            .await
+            .expect("timed out waiting for the bot to connect")
+            .unwrap();
+        let ws = async_tungstenite::accept_async(stream).await.unwrap();
+        let mut connection = MockConnection {
+            ws,
+            received: Vec::new(),
+        };
+
+        expect(&mut connection.ws, &mut connection.received, "connection").await;
+        connection
+    }
+}
+
+// One accepted connection, past the handshake
+pub struct MockConnection {
+    ws: WebSocketStream<TcpStream>,
+    // Every message from the bot so far
+    pub received: Vec<ClientMessage>,
+}
+
+impl MockConnection {
+    pub async fn play(&mut self, scenario: &Scenario) {
+        let ws = &mut self.ws;
+        for step in &scenario.steps {
+            match step {
+                Step::Send(event) => send(ws, event.clone()).await,
+                Step::Burst(events) => {
+                    ws.get_mut().write_all(&burst(events)).await.unwrap();
+                }
+                Step::Expect(event) => expect(ws, &mut self.received, event).await,
+                Step::Ping(payload) => {
+                    ws.send(Message::Ping(payload.clone().into_bytes()))
+                        .await
+                        .unwrap();
+                    let pong = next_control(ws, &mut self.received, "pong").await;
+                    assert_eq!(pong, Message::Pong(payload.clone().into_bytes()));
+                }
+                Step::Close(code) => {
+                    ws.send(Message::Close(Some(CloseFrame {
+                        code: CloseCode::from(*code),
+                        reason: "scenario over".into(),
+                    })))
+                    .await
+                    .unwrap();
+                }
+                Step::ExpectClose => {
+                    next_control(ws, &mut self.received, "close").await;
+                }
+            }
+        }
+    }
+
+    pub async fn send(&mut self, event: Value) {
+        send(&mut self.ws, event).await;
+    }
+
+    // Send these events in one write, so the bot reads them all before handling any
+    pub async fn burst(&mut self, events: &[Value]) {
+        self.ws.get_mut().write_all(&burst(events)).await.unwrap();
+    }
+
+    // Send these events and a close in one write, so the bot has read them all by the
+    // time it sees the connection go
+    pub async fn burst_then_close(&mut self, events: &[Value]) {
+        let mut bytes = burst(events);
+        bytes.extend_from_slice(&close_frame(CloseCode::Normal));
+        self.ws.get_mut().write_all(&bytes).await.unwrap();
+    }
+
+    // A frame that needn't be JSON at all
+    pub async fn send_text(&mut self, text: &str) {
+        self.ws.send(Message::Text(text.to_string())).await.unwrap();
+    }
+
+    // Read messages until the bot sends one with this event name
+    pub async fn expect(&mut self, event: &str) {
+        expect(&mut self.ws, &mut self.received, event).await;
+    }
+
+    // Read until the bot closes the connection
+    pub async fn expect_close(&mut self) -> Message {
+        next_control(&mut self.ws, &mut self.received, "close").await
+    }
+}
+
+// Frames are written as they're sent, so build them by hand to have them all go out in
+// one write
+fn burst(events: &[Value]) -> Vec<u8> {
+    events
+        .iter()
+        .flat_map(|event| text_frame(&event.to_string()))
+        .collect()
+}
+
+// An unmasked close frame with no reason
+fn close_frame(code: CloseCode) -> Vec<u8> {
+    let mut frame = vec![0x88, 2];
+    frame.extend_from_slice(&u16::from(code).to_be_bytes());
+    frame
+}
+
+// An unmasked text frame, as a server sends it
+fn text_frame(text: &str) -> Vec<u8> {
+    let mut frame = vec![0x81];
+    match text.len() {
+        len if len < 126 => frame.push(len as u8),
+        len => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(text.as_bytes());
+    frame
+}
+
+async fn send<S>(ws: &mut WebSocketStream<S>, event: Value)
+where
+    S: futures::AsyncRead + futures::AsyncWrite + Unpin,
+{
+    ws.send(Message::Text(event.to_string())).await.unwrap();
+}
+
+async fn expect<S>(ws: &mut WebSocketStream<S>, received: &mut Vec<ClientMessage>, event: &str)
+where
+    S: futures::AsyncRead + futures::AsyncWrite + Unpin,
+{
+    loop {
+        let next = rt::timeout(EXPECT_TIMEOUT, ws.next())
+            .await
+            .unwrap_or_else(|_| panic!("timed out waiting for '{}', got {:?}", event, received));
+        let text = match next {
+            Some(Ok(Message::Text(text))) => text,
+            Some(Ok(_)) => continue,
+            other => panic!("connection ended waiting for '{}': {:?}", event, other),
+        };
+        let message: ClientMessage = serde_json::from_str(&text).unwrap();
+        let matched = event_name(&message) == event;
+        received.push(message);
+        if matched {
+            return;
+        }
+    }
+}
+
+// Read until the bot sends a pong or close frame, keeping any text messages on the way
+async fn next_control<S>(
+    ws: &mut WebSocketStream<S>,
+    received: &mut Vec<ClientMessage>,
+    kind: &str,
+) -> Message
+where
+    S: futures::AsyncRead + futures::AsyncWrite + Unpin,
+{
+    loop {
+        let next = rt::timeout(EXPECT_TIMEOUT, ws.next())
+            .await
+            .unwrap_or_else(|_| panic!("timed out waiting for a {}, got {:?}", kind, received));
+        match next {
+            Some(Ok(Message::Text(text))) => received.push(serde_json::from_str(&text).unwrap()),
+            Some(Ok(message @ Message::Pong(_))) if kind == "pong" => return message,
+            Some(Ok(message @ Message::Close(_))) if kind == "close" => return message,
+            Some(Ok(_)) => continue,
+            other => panic!("connection ended waiting for a {}: {:?}", kind, other),
+        }
+    }
+}
@@ -0,0 +1,943 @@
+use optiva_ws::bandit::Posterior;
+use optiva_ws::config::{Config, ConfigError};
+use optiva_ws::strategy::{Conflict, StrategyKind};
+use std::time::Duration;
+
+#[test]
+fn minimal_config_uses_defaults() {
+    let config = Config::parse(
+        r#"
+        url = "wss://example.com/ws/game"
+        player_id = "abc"
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(config.connections, 5);
+    assert_eq!(config.alias(2), "Aegizz-2");
+    assert_eq!(config.strategy.momentum_weight, 0.6);
+    assert_eq!(config.strategy.forecast_weight, 0.4);
+    assert_eq!(config.strategy_for(3), StrategyKind::Blend);
+}
+
+#[test]
+fn strategy_section_overrides_defaults() {
+    let config = Config::parse(
+        r#"
+        url = "wss://example.com/ws/game"
+        player_id = "abc"
+        alias_prefix = "team"
+        connections = 2
+
+        [strategy]
+        momentum_weight = 0.3
+        forecast_weight = 0.7
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(config.connections, 2);
+    assert_eq!(config.alias(0), "team-0");
+    assert_eq!(config.strategy.momentum_weight, 0.3);
+    assert_eq!(config.strategy.aggressive_factor, 1.5);
+}
+
+#[test]
+fn missing_player_id_is_rejected() {
+    let err = Config::parse(r#"url = "wss://example.com""#).unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid(_)));
+    assert!(err.to_string().contains("player_id"), "{}", err);
+}
+
+#[test]
+fn bad_url_is_rejected() {
+    let err = Config::parse(
+        r#"
+        url = "https://example.com"
+        player_id = "abc"
+        "#,
+    )
+    .unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid(_)));
+}
+
+#[test]
+fn a_url_list_is_used_in_place_of_url() {
+    let config = Config::parse(
+        r#"
+        urls = ["wss://one.example.com", "wss://two.example.com"]
+        player_id = "abc"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(
+        config.urls(),
+        ["wss://one.example.com", "wss://two.example.com"]
+    );
+
+    let config = Config::parse(
+        r#"
+        url = "wss://example.com"
+        player_id = "abc"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.urls(), ["wss://example.com"]);
+}
+
+#[test]
+fn url_and_urls_together_or_a_bad_entry_are_rejected() {
+    let err = Config::parse(
+        r#"
+        url = "wss://example.com"
+        urls = ["wss://two.example.com"]
+        player_id = "abc"
+        "#,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("urls"), "{}", err);
+
+    let err = Config::parse(
+        r#"
+        urls = ["wss://one.example.com", "https://two.example.com"]
+        player_id = "abc"
+        "#,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("two.example.com"), "{}", err);
+
+    let err = Config::parse(r#"player_id = "abc""#).unwrap_err();
+    assert!(err.to_string().contains("url"), "{}", err);
+}
+
+#[test]
+fn bandit_arms_need_unique_names_and_valid_params() {
+    let config = Config::parse(
+        r#"
+        url = "wss://example.com"
+        player_id = "abc"
+
+        [bandit]
+        posterior = "beta"
+
+        [[bandit.arms]]
+        name = "momentum"
+        momentum_weight = 0.8
+        forecast_weight = 0.2
+
+        [[bandit.arms]]
+        name = "proportional"
+        sizing = "proportional"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.bandit.arms.len(), 2);
+    assert_eq!(config.bandit.posterior, Posterior::Beta);
+
+    for arms in [
+        "[[bandit.arms]]\nname = \"a\"\n[[bandit.arms]]\nname = \"a\"",
+        "[[bandit.arms]]\nmomentum_weight = 0.5",
+        "[[bandit.arms]]\nname = \"a\"\ndeadband = -1.0",
+    ] {
+        let err = Config::parse(&format!(
+            "url = \"wss://example.com\"\nplayer_id = \"abc\"\n{}",
+            arms
+        ))
+        .unwrap_err();
+        assert!(err.to_string().contains("bandit arm"), "{}", err);
+    }
+}
+
+#[test]
+fn zero_connections_is_rejected() {
+    let err = Config::parse(
+        r#"
+        url = "wss://example.com"
+        player_id = "abc"
+        connections = 0
+        "#,
+    )
+    .unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid(_)));
+}
+
+#[test]
+fn strategies_cycle_across_connections() {
+    let config = Config::parse(
+        r#"
+        url = "wss://example.com"
+        player_id = "abc"
+        strategies = ["blend", "mean_reversion", "forecast_only"]
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.strategy_for(1), StrategyKind::MeanReversion);
+    assert_eq!(config.strategy_for(2), StrategyKind::ForecastOnly);
+    assert_eq!(config.strategy_for(3), StrategyKind::Blend);
+
+    let err = Config::parse(
+        r#"
+        url = "wss://example.com"
+        player_id = "abc"
+        strategies = []
+        "#,
+    )
+    .unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid(_)));
+
+    let err = Config::parse(
+        r#"
+        url = "wss://example.com"
+        player_id = "abc"
+        strategies = ["martingale"]
+        "#,
+    )
+    .unwrap_err();
+    assert!(matches!(err, ConfigError::Parse(_)));
+}
+
+#[test]
+fn example_config_parses() {
+    let text =
+        std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/bot.example.toml")).unwrap();
+    Config::parse(&text).unwrap();
+}
+
+#[test]
+fn watchdog_defaults_and_validation() {
+    let config = Config::parse(
+        r#"
+        url = "wss://example.com"
+        player_id = "abc"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.watchdog.read_timeout().as_secs(), 30);
+    assert_eq!(config.watchdog.ping_grace().as_secs(), 10);
+
+    let err = Config::parse(
+        r#"
+        url = "wss://example.com"
+        player_id = "abc"
+
+        [watchdog]
+        read_timeout_secs = 0
+        "#,
+    )
+    .unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid(_)));
+}
+
+#[test]
+fn optimizer_thresholds_must_be_ordered() {
+    let config = Config::parse(
+        r#"
+        url = "wss://example.com"
+        player_id = "abc"
+
+        [optimizer]
+        good_sharpe = 1.0
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.optimizer.good_sharpe, 1.0);
+    assert_eq!(config.optimizer.bad_sharpe, -0.5);
+
+    let err = Config::parse(
+        r#"
+        url = "wss://example.com"
+        player_id = "abc"
+
+        [optimizer]
+        good_sharpe = -1.0
+        "#,
+    )
+    .unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid(_)));
+}
+
+#[test]
+fn optimizer_freeze_fraction_must_leave_part_of_the_game() {
+    for optimizer in [
+        "freeze_fraction = -0.1",
+        "freeze_fraction = 1.0",
+        "freeze_fraction = nan",
+    ] {
+        let err = Config::parse(&format!(
+            r#"
+            url = "wss://example.com"
+            player_id = "abc"
+
+            [optimizer]
+            {}
+            "#,
+            optimizer
+        ))
+        .unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)), "{}", optimizer);
+    }
+}
+
+#[test]
+fn optimizer_outlier_mads_cant_be_negative() {
+    for optimizer in ["outlier_mads = -1.0", "outlier_mads = nan"] {
+        let err = Config::parse(&format!(
+            r#"
+            url = "wss://example.com"
+            player_id = "abc"
+
+            [optimizer]
+            {}
+            "#,
+            optimizer
+        ))
+        .unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)), "{}", optimizer);
+    }
+}
+
+#[test]
+fn optimizer_decay_must_be_positive() {
+    for optimizer in ["half_life_secs = 0.0", "max_age_secs = -5.0"] {
+        let err = Config::parse(&format!(
+            r#"
+            url = "wss://example.com"
+            player_id = "abc"
+
+            [optimizer]
+            {}
+            "#,
+            optimizer
+        ))
+        .unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)), "{}", optimizer);
+    }
+}
+
+#[test]
+fn forecast_needs_a_window_that_can_warm_up() {
+    let err = Config::parse(
+        r#"
+        url = "wss://example.com"
+        player_id = "abc"
+
+        [forecast]
+        window = 10
+        min_samples = 20
+        "#,
+    )
+    .unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid(_)));
+}
+
+#[test]
+fn notify_webhook_must_be_http() {
+    let err = Config::parse(
+        r#"
+        url = "wss://example.com"
+        player_id = "abc"
+
+        [notify]
+        webhook_url = "hooks.slack.com/services/x"
+        "#,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("webhook_url"), "{}", err);
+}
+
+#[test]
+fn connection_count_is_bounded() {
+    for connections in [0, 33] {
+        let err = Config::parse(&format!(
+            r#"
+            url = "wss://example.com/ws/game"
+            player_id = "abc"
+            connections = {}
+            "#,
+            connections
+        ))
+        .unwrap_err();
+        assert!(err.to_string().contains("connections"), "{}", err);
+    }
+}
+
+#[test]
+fn explicit_aliases_come_before_the_template() {
+    let config = Config::parse(
+        r#"
+        url = "wss://example.com/ws/game"
+        player_id = "abc"
+        connections = 3
+        alias_template = "team-{id}-bot"
+        aliases = ["alice", "bob"]
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(config.alias(0), "alice");
+    assert_eq!(config.alias(1), "bob");
+    assert_eq!(config.alias(2), "team-2-bot");
+}
+
+#[test]
+fn run_tag_goes_into_the_alias_template() {
+    let config = Config::parse(
+        r#"
+        url = "wss://example.com/ws/game"
+        player_id = "abc"
+        connections = 2
+        alias_template = "team-{run}-{id}"
+        "#,
+    )
+    .unwrap();
+    // Without a tag, as in replay and tests, the placeholder just goes away
+    assert_eq!(config.alias(1), "team--1");
+
+    let config = config.with_run_tag();
+    assert_eq!(config.run_tag.len(), 4);
+    assert_eq!(config.alias(0), format!("team-{}-0", config.run_tag));
+    assert_eq!(config.alias(1), format!("team-{}-1", config.run_tag));
+}
+
+#[test]
+fn quarantine_is_validated() {
+    let config = Config::parse(
+        r#"
+        url = "wss://example.com"
+        player_id = "abc"
+        "#,
+    )
+    .unwrap();
+    assert!(!config.quarantine.enabled);
+
+    for section in [
+        "trades = 0",
+        "trades = 1000",
+        "reduced_size = 0",
+        "reduced_size = 1.5",
+    ] {
+        let err = Config::parse(&format!(
+            r#"
+            url = "wss://example.com"
+            player_id = "abc"
+
+            [quarantine]
+            {}
+            "#,
+            section
+        ))
+        .unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)), "{}", section);
+    }
+}
+
+#[test]
+fn costs_are_validated() {
+    let config = Config::parse(
+        r#"
+        url = "wss://example.com"
+        player_id = "abc"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.costs.cost(-4), 0.0);
+
+    for section in [
+        "fee_per_unit = -0.1",
+        "half_spread = nan",
+        "half_spread = inf",
+    ] {
+        let err = Config::parse(&format!(
+            r#"
+            url = "wss://example.com"
+            player_id = "abc"
+
+            [costs]
+            {}
+            "#,
+            section
+        ))
+        .unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)), "{}", section);
+    }
+}
+
+#[test]
+fn duplicates_are_validated() {
+    for section in ["max_skips = 0", "warn_every = 0"] {
+        let err = Config::parse(&format!(
+            r#"
+            url = "wss://example.com"
+            player_id = "abc"
+
+            [duplicates]
+            {}
+            "#,
+            section
+        ))
+        .unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)), "{}", section);
+    }
+}
+
+#[test]
+fn divergence_is_validated() {
+    for section in [
+        "checks = 0",
+        "interval_secs = 0.0",
+        "max_price_gap = -0.1",
+        "max_pnl_change_gap = 0.0",
+    ] {
+        let err = Config::parse(&format!(
+            r#"
+            url = "wss://example.com"
+            player_id = "abc"
+
+            [divergence]
+            {}
+            "#,
+            section
+        ))
+        .unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)), "{}", section);
+    }
+}
+
+#[test]
+fn handshake_timeouts_must_be_positive() {
+    for section in ["ack_timeout_secs = 0.0", "start_timeout_secs = -1.0"] {
+        let err = Config::parse(&format!(
+            r#"
+            url = "wss://example.com"
+            player_id = "abc"
+
+            [handshake]
+            {}
+            "#,
+            section
+        ))
+        .unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)), "{}", section);
+    }
+}
+
+#[test]
+fn exposure_ceiling_must_be_positive() {
+    let err = Config::parse(
+        r#"
+        url = "wss://example.com"
+        player_id = "abc"
+
+        [exposure]
+        max_net_position = 0
+        "#,
+    )
+    .unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid(_)));
+}
+
+#[test]
+fn trade_budgets_must_be_positive() {
+    for budget in ["trades_per_game = 0", "puzzle_trades_per_game = 0"] {
+        let err = Config::parse(&format!(
+            r#"
+            url = "wss://example.com"
+            player_id = "abc"
+
+            [trade_budget]
+            {}
+            "#,
+            budget
+        ))
+        .unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)), "{}", budget);
+    }
+}
+
+#[test]
+fn risk_eval_settings_must_make_sense() {
+    for section in [
+        "games = 0",
+        "max_drawdown = [10.0, 0.0]",
+        "max_drawdown_pct = [-0.1]",
+        "cooldown_secs = [-1.0]",
+    ] {
+        let err = Config::parse(&format!(
+            r#"
+            url = "wss://example.com"
+            player_id = "abc"
+
+            [risk_eval]
+            {}
+            "#,
+            section
+        ))
+        .unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)), "{}", section);
+    }
+}
+
+#[test]
+fn the_optimizer_weight_step_is_a_fraction() {
+    for step in ["0.0", "-0.1", "1.5", "nan"] {
+        let err = Config::parse(&format!(
+            r#"
+            url = "wss://example.com"
+            player_id = "abc"
+
+            [optimizer]
+            max_weight_step = {}
+            "#,
+            step
+        ))
+        .unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)), "{}", step);
+    }
+}
+
+#[test]
+fn strategy_params_stay_within_the_optimizer_bounds() {
+    for strategy in [
+        "momentum_weight = 0.99",
+        "forecast_weight = 0.0",
+        "aggressive_factor = 0.1",
+        "aggressive_factor = 5.0",
+    ] {
+        let err = Config::parse(&format!(
+            r#"
+            url = "wss://example.com"
+            player_id = "abc"
+
+            [strategy]
+            {}
+            "#,
+            strategy
+        ))
+        .unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)), "{}", strategy);
+    }
+}
+
+#[test]
+fn frame_limits_must_be_positive() {
+    for frames in ["max_bytes = 0", "max_errors_per_minute = 0"] {
+        let err = Config::parse(&format!(
+            r#"
+            url = "wss://example.com"
+            player_id = "abc"
+
+            [frames]
+            {}
+            "#,
+            frames
+        ))
+        .unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)), "{}", frames);
+    }
+}
+
+#[test]
+fn schedule_phases_are_validated() {
+    let phases = [
+        // No start
+        "name = \"early\"",
+        // Both starts
+        "name = \"early\"\nfrom_fraction = 0.0\nfrom_secs = 0.0",
+        "name = \"early\"\nfrom_fraction = 1.0",
+        "name = \"early\"\nfrom_secs = -1.0",
+        "name = \"\"\nfrom_fraction = 0.5",
+        "name = \"early\"\nfrom_fraction = 0.5\ndeadband = -1.0",
+    ];
+    for phase in phases {
+        let err = Config::parse(&format!(
+            r#"
+            url = "wss://example.com"
+            player_id = "abc"
+
+            [[schedule.phases]]
+            {}
+            "#,
+            phase
+        ))
+        .unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)), "{}", phase);
+    }
+
+    let config = Config::parse(
+        r#"
+        url = "wss://example.com"
+        player_id = "abc"
+
+        [[schedule.phases]]
+        name = "early"
+        from_fraction = 0.0
+        momentum_weight = 0.9
+
+        [[schedule.phases]]
+        name = "late"
+        from_fraction = 0.34
+        forecast_weight = 0.8
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.schedule.phases.len(), 2);
+}
+
+#[test]
+fn agreement_is_validated() {
+    for negligible in ["-0.1", "1.0"] {
+        let err = Config::parse(&format!(
+            r#"
+            url = "wss://example.com"
+            player_id = "abc"
+
+            [agreement]
+            required = true
+            negligible = {}
+            "#,
+            negligible
+        ))
+        .unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)), "{}", negligible);
+    }
+
+    let config = Config::parse(
+        r#"
+        url = "wss://example.com"
+        player_id = "abc"
+
+        [agreement]
+        required = true
+        on_conflict = "flatten"
+        "#,
+    )
+    .unwrap();
+    assert!(config.agreement.required);
+    assert_eq!(config.agreement.on_conflict, Conflict::Flatten);
+}
+
+#[test]
+fn startup_is_staggered_by_connection() {
+    let config = Config::parse(
+        r#"
+        url = "wss://example.com"
+        player_id = "abc"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.startup.delay(0, 0.0), Duration::ZERO);
+    assert_eq!(config.startup.delay(2, 0.0), Duration::from_millis(600));
+    assert_eq!(config.startup.delay(2, 0.5), Duration::from_millis(700));
+
+    let err = Config::parse(
+        r#"
+        url = "wss://example.com"
+        player_id = "abc"
+
+        [startup]
+        jitter_ms = -1
+        "#,
+    )
+    .unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid(_)));
+}
+
+#[test]
+fn duplicate_aliases_are_rejected() {
+    let err = Config::parse(
+        r#"
+        url = "wss://example.com/ws/game"
+        player_id = "abc"
+        connections = 2
+        alias_template = "same"
+        "#,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("'same'"), "{}", err);
+
+    let mut config = Config::parse(
+        r#"
+        url = "wss://example.com/ws/game"
+        player_id = "abc"
+        connections = 2
+        aliases = ["a", "b"]
+        "#,
+    )
+    .unwrap();
+    // A command line override is checked the same way
+    config.aliases = vec!["a".to_string(), "a".to_string()];
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn ensemble_quorum_must_fit_the_connections() {
+    let parse = |quorum: usize| {
+        Config::parse(&format!(
+            r#"
+            url = "wss://example.com/ws/game"
+            player_id = "abc"
+            connections = 3
+
+            [ensemble]
+            mode = "ensemble"
+            quorum = {}
+            "#,
+            quorum
+        ))
+    };
+    assert!(parse(3).is_ok());
+    assert!(parse(0).is_err());
+    assert!(parse(4).is_err());
+}
+
+#[test]
+fn tune_grid_is_validated() {
+    let config = Config::parse(
+        r#"
+        url = "wss://example.com"
+        player_id = "abc"
+
+        [tune.grid]
+        aggressive_factor = { min = 1.0, max = 2.0, step = 0.5 }
+        "#,
+    )
+    .unwrap();
+    // The default weight ranges are kept alongside
+    assert_eq!(config.tune.grid.size(), 4 * 4 * 3);
+
+    let err = Config::parse(
+        r#"
+        url = "wss://example.com"
+        player_id = "abc"
+
+        [tune.grid]
+        deadband = { min = 0.5, max = 0.1, step = 0.1 }
+        "#,
+    )
+    .unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid(_)));
+
+    let err = Config::parse(
+        r#"
+        url = "wss://example.com"
+        player_id = "abc"
+
+        [tune.grid]
+        aggressive_factor = { min = 0.0, max = 100.0, step = 0.001 }
+        "#,
+    )
+    .unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid(_)));
+}
+
+#[test]
+fn a_single_account_is_labelled_with_the_alias_prefix() {
+    let config = Config::parse(
+        r#"
+        url = "wss://example.com/ws/game"
+        player_id = "abc"
+        "#,
+    )
+    .unwrap();
+    let accounts = config.accounts();
+    assert_eq!(accounts.len(), 1);
+    assert_eq!(accounts[0].account, "Aegizz");
+    assert_eq!(accounts[0].player_id, "abc");
+    assert_eq!(accounts[0].persist.path, config.persist.path);
+}
+
+#[test]
+fn each_account_gets_its_own_player_url_aliases_and_params_file() {
+    let config = Config::parse(
+        r#"
+        url = "wss://example.com/ws/game"
+        connections = 2
+
+        [[accounts]]
+        player_id = "abc"
+        alias = "alpha"
+
+        [[accounts]]
+        player_id = "def"
+        alias = "beta"
+        url = "wss://other.example.com/ws/game"
+        "#,
+    )
+    .unwrap();
+    let accounts = config.accounts();
+    assert_eq!(accounts.len(), 2);
+
+    let (alpha, beta) = (&accounts[0], &accounts[1]);
+    assert_eq!(alpha.account, "alpha");
+    assert_eq!(alpha.player_id, "abc");
+    assert_eq!(alpha.url, "wss://example.com/ws/game");
+    assert_eq!(alpha.alias(1), "alpha-1");
+    assert_eq!(
+        alpha.persist.path,
+        std::path::PathBuf::from("params-alpha.json")
+    );
+    assert!(alpha.accounts.is_empty());
+
+    assert_eq!(beta.player_id, "def");
+    assert_eq!(beta.url, "wss://other.example.com/ws/game");
+    assert_eq!(beta.alias(0), "beta-0");
+    assert_eq!(
+        beta.persist.path,
+        std::path::PathBuf::from("params-beta.json")
+    );
+}
+
+#[test]
+fn accounts_are_validated() {
+    let parse = |extra: &str| {
+        Config::parse(&format!(
+            r#"
+            url = "wss://example.com/ws/game"
+            {}
+            "#,
+            extra
+        ))
+    };
+    for bad in [
+        // Top-level player settings would be ambiguous
+        "player_id = \"abc\"\n[[accounts]]\nplayer_id = \"def\"\nalias = \"a\"",
+        "aliases = [\"x\"]\n[[accounts]]\nplayer_id = \"def\"\nalias = \"a\"",
+        "[[accounts]]\nplayer_id = \"\"\nalias = \"a\"",
+        "[[accounts]]\nplayer_id = \"abc\"\nalias = \" \"",
+        "[[accounts]]\nplayer_id = \"abc\"\nalias = \"a\"\n[[accounts]]\nplayer_id = \"def\"\nalias = \"a\"",
+        "[[accounts]]\nplayer_id = \"abc\"\nalias = \"a\"\nurl = \"http://example.com\"",
+    ] {
+        assert!(
+            matches!(parse(bad), Err(ConfigError::Invalid(_))),
+            "{}",
+            bad
+        );
+    }
+}
+
+#[test]
+fn runtime_switches_follow_the_built_features() {
+    let config = Config::parse(
+        r#"
+        url = "wss://example.com/ws/game"
+        player_id = "abc"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.puzzles_enabled(), cfg!(feature = "puzzles"));
+    assert_eq!(config.optimizer_enabled(), cfg!(feature = "auto-optimize"));
+
+    let config = Config::parse(
+        r#"
+        url = "wss://example.com/ws/game"
+        player_id = "abc"
+        puzzles = false
+
+        [optimizer]
+        enabled = false
+        "#,
+    )
+    .unwrap();
+    assert!(!config.puzzles_enabled());
+    assert!(!config.optimizer_enabled());
+}
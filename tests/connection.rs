@@ -0,0 +1,668 @@
+mod common;
+
+use async_tungstenite::tungstenite::error::UrlError;
+use async_tungstenite::tungstenite::http::Response;
+use async_tungstenite::tungstenite::{Error as WsError, Message};
+use common::server::{event_name, MockServer, Scenario};
+use optiva_ws::config::Config;
+use optiva_ws::connection::{
+    handle_connection, is_stale_trade, reconnect_policy, Reconnect, SendPriority, SendQueue, Sinks,
+    UrlRotation, URL_FAILURES_BEFORE_FAILOVER,
+};
+use optiva_ws::error::{BotError, DisconnectCounts, DisconnectReason};
+use optiva_ws::protocol::{outgoing, ClientEvent, ClientMessage};
+use optiva_ws::rt;
+use optiva_ws::shutdown::Shutdown;
+use optiva_ws::state::{Health, SharedState};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Run one connection against the mock server until the scenario has played out
+async fn run(scenario: &str) -> Vec<ClientMessage> {
+    run_with_state(scenario).await.0
+}
+
+async fn run_with_state(scenario: &str) -> (Vec<ClientMessage>, Arc<SharedState>) {
+    run_with_config(scenario, common::test_config()).await
+}
+
+async fn run_with_config(scenario: &str, config: Config) -> (Vec<ClientMessage>, Arc<SharedState>) {
+    let scenario = Scenario::load(scenario);
+    let server = MockServer::bind().await;
+    let client = Client::spawn_with(&server, config);
+    let received = server.play(&scenario).await;
+    (received, client.stop().await)
+}
+
+// One connection to the mock server, running until stopped
+struct Client {
+    state: Arc<SharedState>,
+    shutdown: Shutdown,
+    task: rt::JoinHandle<()>,
+}
+
+impl Client {
+    fn spawn(server: &MockServer) -> Client {
+        Client::spawn_with(server, common::test_config())
+    }
+
+    fn spawn_with(server: &MockServer, mut config: Config) -> Client {
+        config.url = server.url.clone();
+        config.transcript.enabled = false;
+        config.summary.enabled = false;
+        config.curve.enabled = false;
+        let config = Arc::new(config);
+        let state = Arc::new(SharedState::new(&config));
+        let shutdown = Shutdown::new();
+        let task = rt::spawn(handle_connection(
+            0,
+            config,
+            Arc::clone(&state),
+            shutdown.clone(),
+            Sinks::default(),
+        ));
+        Client {
+            state,
+            shutdown,
+            task,
+        }
+    }
+
+    // Waits for the connection to wind down
+    async fn stop(self) -> Arc<SharedState> {
+        self.shutdown.trigger();
+        self.task.await;
+        self.state
+    }
+}
+
+fn events(received: &[ClientMessage]) -> Vec<&'static str> {
+    received.iter().map(event_name).collect()
+}
+
+#[optiva_ws::rt::test]
+async fn sends_start_after_the_connection_ack() {
+    let received = run("handshake").await;
+    assert_eq!(events(&received), ["connection", "start"]);
+    match &received[1].event {
+        ClientEvent::Start(start) => assert_eq!(start.player_id, common::PLAYER_ID),
+        other => panic!("expected start, got {:?}", other),
+    }
+}
+
+#[optiva_ws::rt::test]
+async fn buys_on_a_strongly_positive_forecast() {
+    let received = run("strong_forecast").await;
+    match &received.last().unwrap().event {
+        ClientEvent::Trade(trade) => assert!(trade.volume > 0),
+        other => panic!("expected trade, got {:?}", other),
+    }
+}
+
+#[optiva_ws::rt::test]
+async fn skips_after_each_puzzle() {
+    let received = run("puzzles").await;
+    let skips = events(&received)
+        .into_iter()
+        .filter(|event| *event == "skip")
+        .count();
+    assert_eq!(skips, 2);
+}
+
+#[optiva_ws::rt::test]
+async fn only_the_newest_of_a_burst_of_states_trades() {
+    let (received, state) = run_with_state("state_burst").await;
+    let trades: Vec<i32> = received
+        .iter()
+        .filter_map(|message| match &message.event {
+            ClientEvent::Trade(trade) => Some(trade.volume),
+            _ => None,
+        })
+        .collect();
+    // The older updates all wanted to sell
+    assert_eq!(trades.len(), 1, "{:?}", received);
+    assert!(trades[0] > 0);
+    let snapshot = state.snapshot().await;
+    assert_eq!(snapshot.connections[0].coalesced_states, 3);
+}
+
+#[optiva_ws::rt::test]
+async fn a_puzzle_in_a_burst_is_still_handled() {
+    let (received, state) = run_with_state("burst_with_puzzle").await;
+    // What the burst led to goes out together, the trade ahead of the puzzle's skip
+    assert_eq!(
+        events(&received),
+        ["connection", "start", "trade", "skip", "skip"]
+    );
+    match &received[2].event {
+        ClientEvent::Trade(trade) => assert!(trade.volume > 0),
+        other => panic!("expected trade, got {:?}", other),
+    }
+    let snapshot = state.snapshot().await;
+    assert_eq!(snapshot.connections[0].coalesced_states, 1);
+}
+
+#[optiva_ws::rt::test]
+async fn a_flood_of_skips_is_held_under_the_outbound_ceiling() {
+    let mut config = common::test_config();
+    config.outbound.max_per_minute = Some(10);
+    let (received, state) = run_with_config("skip_flood", config).await;
+
+    // The trade goes out ahead of the skips, which stop once repeats of them reach half
+    // the ceiling
+    assert_eq!(
+        events(&received),
+        ["connection", "start", "trade", "skip", "skip"]
+    );
+    let snapshot = state.snapshot().await;
+    let connection = &snapshot.connections[0];
+    assert_eq!(connection.outbound.skip, 2);
+    assert_eq!(connection.outbound.total(), 5);
+    assert_eq!(connection.outbound_dropped.skip, 4);
+    assert_eq!(connection.outbound_dropped.trade, 0);
+    assert_eq!(snapshot.outbound_per_minute, 5);
+}
+
+#[cfg(feature = "puzzles")]
+#[optiva_ws::rt::test]
+async fn a_puzzle_answer_goes_out_ahead_of_the_skips_queued_before_it() {
+    let received = run("answer_behind_skips").await;
+    assert_eq!(
+        events(&received),
+        ["connection", "start", "answer", "skip", "skip", "skip"]
+    );
+}
+
+fn network_error() -> Result<DisconnectReason, BotError> {
+    Err(BotError::Connect(Box::new(WsError::ConnectionClosed)))
+}
+
+#[test]
+fn network_errors_retry_at_once_then_back_off() {
+    assert_eq!(
+        reconnect_policy(&network_error(), 1),
+        Reconnect::After(Duration::ZERO)
+    );
+    assert_eq!(
+        reconnect_policy(&network_error(), 2),
+        Reconnect::After(Duration::from_secs(1))
+    );
+    assert_eq!(
+        reconnect_policy(&network_error(), 4),
+        Reconnect::After(Duration::from_secs(4))
+    );
+    assert_eq!(
+        reconnect_policy(&network_error(), 50),
+        Reconnect::After(Duration::from_secs(30))
+    );
+}
+
+#[test]
+fn auth_failures_wait_longer_and_config_errors_give_up() {
+    let response = Response::builder().status(403).body(None).unwrap();
+    let auth = Err(BotError::from_connect(WsError::Http(response)));
+    assert!(matches!(auth, Err(BotError::Auth(_))));
+    assert_eq!(
+        reconnect_policy(&auth, 1),
+        Reconnect::After(Duration::from_secs(60))
+    );
+    assert_eq!(
+        reconnect_policy(&auth, 2),
+        Reconnect::After(Duration::from_secs(120))
+    );
+
+    let bad_url = Err(BotError::from_connect(WsError::Url(UrlError::NoHostName)));
+    assert!(matches!(bad_url, Err(BotError::Config(_))));
+    assert_eq!(reconnect_policy(&bad_url, 1), Reconnect::GiveUp);
+}
+
+#[test]
+fn clean_disconnects_reconnect_unless_shutting_down() {
+    assert_eq!(
+        reconnect_policy(&Ok(DisconnectReason::GameFinished), 0),
+        Reconnect::After(Duration::ZERO)
+    );
+    // A rejected token doesn't wait out the auth backoff
+    assert_eq!(
+        reconnect_policy(&Ok(DisconnectReason::TokenRejected), 0),
+        Reconnect::After(Duration::from_secs(1))
+    );
+    assert_eq!(
+        reconnect_policy(&Ok(DisconnectReason::ServerClosed), 0),
+        Reconnect::After(Duration::ZERO)
+    );
+    assert_eq!(
+        reconnect_policy(&Ok(DisconnectReason::Shutdown), 0),
+        Reconnect::GiveUp
+    );
+}
+
+#[test]
+fn repeated_protocol_errors_back_off_for_longer() {
+    let protocol = Err(BotError::Protocol("bad frame".to_string()));
+    assert_eq!(
+        reconnect_policy(&protocol, 1),
+        Reconnect::After(Duration::ZERO)
+    );
+    assert_eq!(
+        reconnect_policy(&protocol, 3),
+        Reconnect::After(Duration::from_secs(10))
+    );
+    assert_eq!(
+        reconnect_policy(&protocol, 50),
+        Reconnect::After(Duration::from_secs(300))
+    );
+    // A connection poisoned with unreadable frames waits from the first time
+    assert_eq!(
+        reconnect_policy(&Ok(DisconnectReason::ParseStorm), 1),
+        Reconnect::After(Duration::from_secs(5))
+    );
+    assert_eq!(
+        reconnect_policy(&Ok(DisconnectReason::ParseStorm), 2),
+        Reconnect::After(Duration::from_secs(10))
+    );
+    assert!(DisconnectReason::ParseStorm.is_fault());
+    assert!(!DisconnectReason::ServerClosed.is_fault());
+}
+
+#[test]
+fn disconnects_are_counted_by_reason() {
+    let mut counts = DisconnectCounts::default();
+    assert_eq!(counts.to_string(), "none");
+    counts.record(&Ok(DisconnectReason::GameFinished));
+    counts.record(&Ok(DisconnectReason::GameFinished));
+    counts.record(&network_error());
+    counts.record(&Err(BotError::Protocol("bad frame".to_string())));
+    assert_eq!(counts.game_finished, 2);
+    assert_eq!(counts.ws_error, 1);
+    assert_eq!(counts.protocol_error, 1);
+    assert_eq!(counts.total(), 4);
+    assert_eq!(
+        counts.to_string(),
+        "game finished=2, websocket error=1, protocol error=1"
+    );
+}
+
+#[optiva_ws::rt::test]
+async fn pings_from_the_server_are_ponged_with_their_payload() {
+    // The mock server checks the payload comes back
+    let received = run("ping").await;
+    assert_eq!(events(&received), ["connection", "start", "trade"]);
+}
+
+#[optiva_ws::rt::test]
+async fn a_finished_game_closes_the_connection() {
+    let received = run("finish").await;
+    assert_eq!(events(&received), ["connection", "start"]);
+}
+
+#[optiva_ws::rt::test]
+async fn a_close_from_the_server_reconnects_straight_away() {
+    let server = MockServer::bind().await;
+    let client = Client::spawn(&server);
+
+    let mut first = server.accept().await;
+    first.play(&Scenario::load("server_close")).await;
+    // Back within the reconnect jitter, with a fresh handshake
+    let mut second = server.accept().await;
+    second.play(&Scenario::load("handshake")).await;
+    assert_eq!(events(&second.received), ["connection", "start"]);
+    let state = client.stop().await;
+    let disconnects = state.snapshot().await.connections[0].disconnects;
+    assert_eq!(disconnects.server_closed, 1);
+    assert_eq!(disconnects.shutdown, 1);
+    assert_eq!(disconnects.total(), 2);
+}
+
+#[optiva_ws::rt::test]
+async fn a_storm_of_unreadable_frames_drops_the_session() {
+    let server = MockServer::bind().await;
+    let client = Client::spawn(&server);
+
+    let mut first = server.accept().await;
+    first.play(&Scenario::load("handshake")).await;
+    for _ in 0..20 {
+        first.send(serde_json::json!("not an event")).await;
+    }
+    assert!(matches!(first.expect_close().await, Message::Close(_)));
+    let state = client.stop().await;
+    let snapshot = state.snapshot().await;
+    assert_eq!(snapshot.connections[0].malformed_frames, 20);
+    assert_eq!(snapshot.connections[0].disconnects.parse_storm, 1);
+}
+
+#[optiva_ws::rt::test]
+async fn oversized_and_truncated_frames_are_dropped_and_trading_goes_on() {
+    let mut config = common::test_config();
+    config.frames.max_bytes = 1024;
+    let server = MockServer::bind().await;
+    let client = Client::spawn_with(&server, config);
+
+    let mut connection = server.accept().await;
+    connection.play(&Scenario::load("handshake")).await;
+    // Both would sell if they were read
+    let mut oversized: serde_json::Value =
+        serde_json::from_str(&common::state_frame(100.0, -1.0, -10.0, 0, 0.0)).unwrap();
+    oversized["data"]["padding"] = "x".repeat(2048).into();
+    connection.send(oversized).await;
+    let truncated = common::state_frame(100.0, -1.0, -10.0, 0, 0.0);
+    connection
+        .send_text(&truncated[..truncated.len() / 2])
+        .await;
+    connection
+        .send(serde_json::from_str(&common::state_frame(101.0, 1.0, 10.0, 0, 0.0)).unwrap())
+        .await;
+    connection.expect("trade").await;
+    match &connection.received.last().unwrap().event {
+        ClientEvent::Trade(trade) => assert!(trade.volume > 0),
+        other => panic!("expected trade, got {:?}", other),
+    }
+
+    let state = client.stop().await;
+    let snapshot = state.snapshot().await;
+    assert_eq!(snapshot.connections[0].oversized_frames, 1);
+    assert_eq!(snapshot.connections[0].malformed_frames, 1);
+    assert_eq!(snapshot.connections[0].disconnects.parse_storm, 0);
+}
+
+#[optiva_ws::rt::test]
+async fn the_next_game_starts_clean_at_the_hinted_time() {
+    let mut config = common::test_config();
+    // A swing as wild as the first game's would shrink the trade to nothing
+    config.strategy.max_volatility = Some(0.01);
+    let server = MockServer::bind().await;
+    let client = Client::spawn_with(&server, config);
+
+    let mut first = server.accept().await;
+    first.play(&Scenario::load("first_game")).await;
+    let closed = std::time::Instant::now();
+    let mut second = server.accept().await;
+    // Sooner than any reconnect delay the bot would pick on its own
+    assert!(closed.elapsed() < Duration::from_millis(900));
+    second.play(&Scenario::load("second_game")).await;
+    match &second.received.last().unwrap().event {
+        ClientEvent::Trade(trade) => assert_eq!(trade.volume, 3),
+        other => panic!("expected trade, got {:?}", other),
+    }
+    let state = client.stop().await;
+    let perf = state.connection_performance.lock().await[&0].clone();
+    assert_eq!(perf.session.updates_seen, 1);
+}
+
+#[optiva_ws::rt::test]
+async fn shutting_down_closes_the_connection() {
+    let server = MockServer::bind().await;
+    let client = Client::spawn(&server);
+
+    let mut connection = server.accept().await;
+    connection.play(&Scenario::load("handshake")).await;
+    let stopping = rt::spawn(client.stop());
+    assert!(matches!(connection.expect_close().await, Message::Close(_)));
+    let state = stopping.await;
+    assert_eq!(state.snapshot().await.connections[0].health, Health::Dead);
+}
+
+#[optiva_ws::rt::test]
+async fn frames_still_queued_when_a_session_drops_are_not_sent_in_the_next() {
+    let server = MockServer::bind().await;
+    let client = Client::spawn(&server);
+
+    let mut first = server.accept().await;
+    first.play(&Scenario::load("handshake")).await;
+    // Gone with the skips for these puzzles still waiting to go out
+    let puzzle = serde_json::json!({ "event": "puzzle", "data": {} });
+    first
+        .burst_then_close(&[puzzle.clone(), puzzle.clone(), puzzle])
+        .await;
+    let mut second = server.accept().await;
+    second.play(&Scenario::load("strong_forecast")).await;
+    assert_eq!(events(&second.received), ["connection", "start", "trade"]);
+    client.stop().await;
+}
+
+#[cfg(feature = "puzzles")]
+#[optiva_ws::rt::test]
+async fn a_finish_still_closes_the_session_with_the_queue_full() {
+    let server = MockServer::bind().await;
+    let client = Client::spawn(&server);
+
+    let mut connection = server.accept().await;
+    connection.play(&Scenario::load("handshake")).await;
+    // More skips than the queue holds, all queued before any goes out
+    let puzzle = serde_json::json!({ "event": "puzzle", "data": {} });
+    let mut burst = vec![puzzle; 40];
+    burst.push(serde_json::json!({ "event": "finish", "data": { "pnl": 0.0 } }));
+    connection.burst(&burst).await;
+    assert!(matches!(connection.expect_close().await, Message::Close(_)));
+    let skips = events(&connection.received)
+        .into_iter()
+        .filter(|event| *event == "skip")
+        .count();
+    assert!(skips <= 32);
+    client.stop().await;
+}
+
+#[optiva_ws::rt::test]
+async fn a_reconnect_asked_for_starts_a_fresh_session() {
+    let server = MockServer::bind().await;
+    let client = Client::spawn(&server);
+
+    let mut first = server.accept().await;
+    first.play(&Scenario::load("handshake")).await;
+    assert!(client.state.reconnects.request(0));
+    assert!(matches!(first.expect_close().await, Message::Close(_)));
+    let mut second = server.accept().await;
+    second.play(&Scenario::load("handshake")).await;
+    assert_eq!(events(&second.received), ["connection", "start"]);
+    client.stop().await;
+}
+
+#[optiva_ws::rt::test]
+async fn a_connection_never_acked_is_started_over() {
+    let mut config = common::test_config();
+    config.handshake.ack_timeout_secs = 0.2;
+    let server = MockServer::bind().await;
+    let client = Client::spawn_with(&server, config);
+
+    let mut first = server.accept_unacked().await;
+    assert!(matches!(first.expect_close().await, Message::Close(_)));
+    let mut second = server.accept().await;
+    second.play(&Scenario::load("handshake")).await;
+    assert_eq!(events(&second.received), ["connection", "start"]);
+    client.stop().await;
+}
+
+#[optiva_ws::rt::test]
+async fn a_state_before_the_ack_is_not_traded() {
+    let server = MockServer::bind().await;
+    let client = Client::spawn(&server);
+
+    let mut connection = server.accept_unacked().await;
+    connection
+        .send(serde_json::from_str(&common::state_frame(100.0, 0.9, 12.0, 0, 0.0)).unwrap())
+        .await;
+    connection
+        .send(serde_json::json!({ "event": "connection", "data": { "player_id": common::PLAYER_ID } }))
+        .await;
+    connection.play(&Scenario::load("handshake")).await;
+    assert_eq!(events(&connection.received), ["connection", "start"]);
+    client.stop().await;
+}
+
+fn rotation(conn_id: usize) -> UrlRotation {
+    let urls = ["ws://a", "ws://b", "ws://c"].map(String::from).to_vec();
+    UrlRotation::new(urls, conn_id)
+}
+
+#[test]
+fn connections_start_round_robin_across_the_urls() {
+    let starts: Vec<String> = (0..4)
+        .map(|conn_id| rotation(conn_id).current().to_string())
+        .collect();
+    assert_eq!(starts, ["ws://a", "ws://b", "ws://c", "ws://a"]);
+}
+
+#[test]
+fn repeated_connect_failures_move_on_to_the_next_url() {
+    let mut urls = rotation(2);
+    for _ in 1..URL_FAILURES_BEFORE_FAILOVER {
+        assert_eq!(urls.connect_failed(), None);
+    }
+    assert_eq!(urls.connect_failed(), Some("ws://a"));
+    assert_eq!(urls.current(), "ws://a");
+
+    // A success in between starts the count again
+    urls.connect_failed();
+    urls.connected();
+    for _ in 1..URL_FAILURES_BEFORE_FAILOVER {
+        assert_eq!(urls.connect_failed(), None);
+    }
+    assert_eq!(urls.current(), "ws://a");
+}
+
+#[test]
+fn a_url_that_worked_before_is_preferred_when_still_listed() {
+    let mut urls = rotation(0);
+    urls.prefer("ws://c");
+    assert_eq!(urls.current(), "ws://c");
+    urls.prefer("ws://gone");
+    assert_eq!(urls.current(), "ws://c");
+
+    let mut single = UrlRotation::new(vec!["ws://only".to_string()], 3);
+    for _ in 0..URL_FAILURES_BEFORE_FAILOVER * 2 {
+        assert_eq!(single.connect_failed(), None);
+    }
+}
+
+#[optiva_ws::rt::test]
+async fn endpoint_health_follows_the_connection() {
+    let server = MockServer::bind().await;
+    let client = Client::spawn(&server);
+
+    let mut connection = server.accept().await;
+    connection.play(&Scenario::load("handshake")).await;
+    let snapshot = client.state.snapshot().await;
+    assert_eq!(
+        snapshot.connections[0].url.as_deref(),
+        Some(server.url.as_str())
+    );
+    let endpoint = snapshot.endpoints[&server.url];
+    assert_eq!((endpoint.live, endpoint.connects), (1, 1));
+    assert!(endpoint.last_connected.is_some());
+
+    let state = client.stop().await;
+    let endpoint = state.snapshot().await.endpoints[&server.url];
+    assert_eq!(endpoint.live, 0);
+    assert_eq!(endpoint.connect_failures, 0);
+}
+
+#[test]
+fn only_trades_go_stale() {
+    let trade = outgoing::trade("abc", 2);
+    let skip = outgoing::skip();
+    assert!(!is_stale_trade(&trade, Duration::from_millis(500)));
+    assert!(is_stale_trade(&trade, Duration::from_secs(3)));
+    assert!(!is_stale_trade(&skip, Duration::from_secs(3)));
+}
+
+#[test]
+fn messages_are_ranked_by_how_urgent_they_are() {
+    let trade = outgoing::trade("abc", 2).event;
+    assert_eq!(SendPriority::of(&trade, true), SendPriority::Puzzle);
+    assert_eq!(SendPriority::of(&trade, false), SendPriority::Trade);
+    for message in [
+        outgoing::skip(),
+        outgoing::start("abc"),
+        outgoing::connection("alias", "abc", ""),
+    ] {
+        assert_eq!(
+            SendPriority::of(&message.event, true),
+            SendPriority::Session
+        );
+    }
+    let answer = outgoing::answer(serde_json::json!(42), None).event;
+    assert_eq!(SendPriority::of(&answer, true), SendPriority::Puzzle);
+    assert_eq!(SendPriority::of(&answer, false), SendPriority::Puzzle);
+}
+
+#[test]
+fn the_most_urgent_frame_goes_first_and_each_priority_in_order() {
+    let mut queue = SendQueue::default();
+    for (priority, frame) in [
+        (SendPriority::Session, "skip"),
+        (SendPriority::Other, "ping"),
+        (SendPriority::Trade, "trade 1"),
+        (SendPriority::Puzzle, "puzzle trade 1"),
+        (SendPriority::Session, "start"),
+        (SendPriority::Trade, "trade 2"),
+        (SendPriority::Puzzle, "puzzle trade 2"),
+        (SendPriority::Other, "close"),
+    ] {
+        queue.push(priority, frame);
+    }
+    assert_eq!(queue.len(), 8);
+    let sent: Vec<&str> = std::iter::from_fn(|| queue.pop()).collect();
+    assert_eq!(
+        sent,
+        [
+            "puzzle trade 1",
+            "puzzle trade 2",
+            "trade 1",
+            "trade 2",
+            "skip",
+            "start",
+            "ping",
+            "close"
+        ]
+    );
+    assert!(queue.is_empty());
+
+    // A trade queued while skips wait still goes before them
+    queue.push(SendPriority::Session, "skip");
+    queue.push(SendPriority::Trade, "trade");
+    assert_eq!(queue.pop(), Some("trade"));
+}
+
+#[test]
+fn nothing_queued_in_one_session_is_sent_in_the_next() {
+    let mut queue = SendQueue::default();
+    queue.push(SendPriority::Trade, "old trade");
+    queue.push(SendPriority::Puzzle, "old puzzle trade");
+    queue.push(SendPriority::Session, "old skip");
+    assert_eq!(queue.discard(), 3);
+    assert_eq!(queue.pop(), None);
+
+    queue.push(SendPriority::Session, "connection");
+    assert_eq!(queue.pop(), Some("connection"));
+    assert_eq!(queue.discard(), 0);
+}
+
+#[optiva_ws::rt::test]
+async fn trades_are_timed_from_the_state_that_led_to_them() {
+    let (received, state) = run_with_state("strong_forecast").await;
+    let trades = received
+        .iter()
+        .filter(|message| matches!(message.event, ClientEvent::Trade(_)))
+        .count();
+    assert!(trades > 0);
+
+    let snapshot = state.snapshot().await;
+    let latency = snapshot.connections[0].latency.unwrap();
+    assert_eq!(latency.samples, trades);
+    assert!(latency.p50_ms <= latency.p95_ms && latency.p95_ms <= latency.max_ms);
+    // Stopped for good once shut down
+    assert_eq!(snapshot.connections[0].health, Health::Dead);
+}
+
+#[optiva_ws::rt::test]
+async fn queue_depth_and_stale_trades_show_in_the_snapshot() {
+    let config = common::test_config();
+    let state = SharedState::new(&config);
+    state.set_queue_depth(1, 7).await;
+    state.record_stale_trade(1).await;
+    state.record_stale_trade(1).await;
+
+    let snapshot = state.snapshot().await;
+    assert_eq!(snapshot.connections[0].queued_messages, 7);
+    assert_eq!(snapshot.connections[0].stale_trades, 2);
+}
@@ -0,0 +1,219 @@
+mod common;
+
+use common::{state_frame, temp_dir, test_config, trade_volumes};
+use futures::io::{BufReader, Cursor};
+use optiva_ws::console::{run_console, Command, Console, ManualOrder};
+use optiva_ws::handler::ConnectionHandler;
+use optiva_ws::journal::{read_journal, Journal, JournalConfig, JournalFormat};
+use optiva_ws::shutdown::Shutdown;
+use optiva_ws::state::SharedState;
+use optiva_ws::strategy::DecisionMode;
+use std::sync::Arc;
+
+#[test]
+fn commands_are_parsed() {
+    for (line, command) in [
+        (
+            "buy 2",
+            Command::Trade {
+                volume: 2,
+                conn_id: None,
+            },
+        ),
+        (
+            "  sell 1 1 ",
+            Command::Trade {
+                volume: -1,
+                conn_id: Some(1),
+            },
+        ),
+        ("flat", Command::Flat { conn_id: None }),
+        ("flat 0", Command::Flat { conn_id: Some(0) }),
+        ("halt", Command::Halt),
+        ("resume", Command::Resume),
+        ("params", Command::Params),
+        ("help", Command::Help),
+    ] {
+        assert_eq!(line.parse::<Command>(), Ok(command), "{}", line);
+    }
+}
+
+#[test]
+fn bad_commands_are_refused() {
+    for line in [
+        "",
+        "buy",
+        "buy 0",
+        "sell -2",
+        "buy two",
+        "buy 1 x",
+        "sell 1 2 3",
+        "halt now",
+        "jump",
+    ] {
+        assert!(line.parse::<Command>().is_err(), "{}", line);
+    }
+}
+
+fn two_connections() -> Arc<SharedState> {
+    let mut config = test_config();
+    config.connections = 2;
+    Arc::new(SharedState::new(&config))
+}
+
+#[optiva_ws::rt::test]
+async fn orders_are_queued_for_each_connection_asked_for() {
+    let state = two_connections();
+    let console = Console::new(Arc::clone(&state));
+
+    let reply = console.handle("buy 2").await;
+    assert!(reply.contains("connection 0") && reply.contains("connection 1"));
+    assert_eq!(state.manual.next(0).await, ManualOrder::Trade(2));
+    assert_eq!(state.manual.next(1).await, ManualOrder::Trade(2));
+
+    console.handle("flat 1").await;
+    assert_eq!(state.manual.clear(0), 0);
+    assert_eq!(state.manual.next(1).await, ManualOrder::Flat);
+
+    assert_eq!(console.handle("sell 1 5").await, "no connection 5");
+    assert!(console.handle("jump").await.starts_with("unknown command"));
+}
+
+#[optiva_ws::rt::test]
+async fn a_connection_behind_on_orders_turns_more_away() {
+    let state = two_connections();
+    let console = Console::new(Arc::clone(&state));
+    let mut replies = Vec::new();
+    for _ in 0..20 {
+        replies.push(console.handle("buy 1 0").await);
+    }
+    assert!(replies.last().unwrap().contains("too many orders queued"));
+    assert!(state.manual.clear(0) < 20);
+}
+
+#[optiva_ws::rt::test]
+async fn halt_resume_and_params_act_on_the_shared_state() {
+    let state = two_connections();
+    let console = Console::new(Arc::clone(&state));
+
+    assert_eq!(console.handle("halt").await, "trading halted");
+    assert!(!state.trading_enabled());
+    assert_eq!(console.handle("resume").await, "trading resumed");
+    assert!(state.trading_enabled());
+
+    let params = console.handle("params").await;
+    assert!(params
+        .lines()
+        .any(|line| line.starts_with("momentum_weight = ")));
+}
+
+#[optiva_ws::rt::test]
+async fn the_console_answers_each_line_until_input_ends() {
+    let state = two_connections();
+    let input = BufReader::new(Cursor::new(b"halt\n\nbuy 1 0\n".to_vec()));
+    let mut output = Vec::new();
+    run_console(
+        Console::new(Arc::clone(&state)),
+        input,
+        &mut output,
+        Shutdown::new(),
+    )
+    .await;
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "trading halted\nbuy 1 sent to connection 0\n"
+    );
+    assert_eq!(state.manual.next(0).await, ManualOrder::Trade(1));
+}
+
+#[optiva_ws::rt::test]
+async fn a_manual_order_before_any_position_is_ignored() {
+    let config = Arc::new(test_config());
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, state);
+    common::join(&mut handler).await;
+
+    let mut outbox = Vec::new();
+    handler
+        .handle_manual(ManualOrder::Trade(1), &mut outbox)
+        .await;
+    assert!(outbox.is_empty());
+}
+
+#[optiva_ws::rt::test]
+async fn manual_trades_stay_inside_the_limit_and_pause_the_strategy_until_flat() {
+    let journal_config = JournalConfig {
+        enabled: true,
+        path: temp_dir("console-manual").join("journal.jsonl"),
+        format: JournalFormat::Jsonl,
+        flush_secs: 0.05,
+    };
+    let (journal, writer) = Journal::spawn(&journal_config).await.unwrap();
+    let config = Arc::new(test_config());
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state)).with_journal(journal);
+    common::join(&mut handler).await;
+
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&state_frame(100.0, 0.0, 0.0, 0, 0.0), &mut outbox)
+        .await;
+
+    // Asked for more than the limit of 3 allows
+    outbox.clear();
+    handler
+        .handle_manual(ManualOrder::Trade(5), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), vec![3]);
+
+    // The strategy would sell, but the connection is in manual hands
+    for pnl in [1.0, -4.0] {
+        outbox.clear();
+        handler
+            .handle_text(&state_frame(100.0, 0.0, -8.0, 3, pnl), &mut outbox)
+            .await;
+        assert_eq!(trade_volumes(&outbox), Vec::<i32>::new());
+    }
+
+    // Halted, only a flat goes out
+    state.set_trading_enabled(false);
+    outbox.clear();
+    handler
+        .handle_manual(ManualOrder::Trade(-1), &mut outbox)
+        .await;
+    assert!(outbox.is_empty());
+    handler.handle_manual(ManualOrder::Flat, &mut outbox).await;
+    assert_eq!(trade_volumes(&outbox), vec![-3]);
+    state.set_trading_enabled(true);
+
+    // One more update for the position held by hand, then the strategy is back
+    for (expected, recorded) in [(vec![], 0), (vec![-3], 1)] {
+        outbox.clear();
+        handler
+            .handle_text(&state_frame(100.0, 0.0, -8.0, 0, 2.0), &mut outbox)
+            .await;
+        assert_eq!(trade_volumes(&outbox), expected);
+        // Nothing from the manual stretch reaches the optimizer's window
+        assert_eq!(state.performance_history.lock().await.len(), recorded);
+    }
+
+    drop(handler);
+    writer.await;
+    let manual: Vec<_> = read_journal(&journal_config.path)
+        .unwrap()
+        .into_iter()
+        .filter(|entry| entry.mode == DecisionMode::Manual)
+        .collect();
+    assert_eq!(manual.len(), 3);
+    // Journaled as sent, clamped to the limit
+    assert_eq!(
+        (manual[0].volume, manual[0].position_after, manual[0].sent),
+        (3, 3, true)
+    );
+    assert!(!manual[1].sent);
+    assert_eq!(
+        (manual[2].position_before, manual[2].position_after),
+        (3, 0)
+    );
+}
@@ -0,0 +1,232 @@
+mod common;
+
+use futures::io::{AsyncReadExt, AsyncWriteExt};
+use optiva_ws::config::{Config, ConfigError};
+use optiva_ws::control::{apply_update, changed_params, read_request, Control, Request};
+use optiva_ws::handler::ConnectionHandler;
+use optiva_ws::protocol::ClientEvent;
+use optiva_ws::rt;
+use optiva_ws::rt::net::TcpListener;
+use optiva_ws::shutdown::Shutdown;
+use optiva_ws::state::{SharedState, StrategyParams};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+fn request(method: &str, path: &str, token: Option<&str>, body: &str) -> Request {
+    Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        token: token.map(str::to_string),
+        body: body.as_bytes().to_vec(),
+    }
+}
+
+fn control(token: Option<&str>) -> (Control, Arc<SharedState>) {
+    let state = Arc::new(SharedState::new(&common::test_config()));
+    (
+        Control::new(Arc::clone(&state), token.map(str::to_string)),
+        state,
+    )
+}
+
+#[test]
+fn partial_updates_keep_the_other_params() {
+    let params = StrategyParams::default();
+    let updated =
+        apply_update(&params, br#"{ "momentum_weight": 0.3, "sizing": "kelly" }"#).unwrap();
+    assert_eq!(updated.momentum_weight, 0.3);
+    assert_eq!(updated.forecast_weight, params.forecast_weight);
+
+    let changes = changed_params(&params, &updated);
+    let names: Vec<&str> = changes.iter().map(|(name, _, _)| name.as_str()).collect();
+    assert_eq!(names, ["momentum_weight", "sizing"]);
+    assert_eq!(changes[0].1, json!(0.6));
+    assert_eq!(changes[0].2, json!(0.3));
+}
+
+#[test]
+fn bad_updates_are_refused() {
+    let params = StrategyParams::default();
+    for update in [
+        r#"{ "momentum_weight": -0.1 }"#,
+        // Past the bounds the optimizer keeps to
+        r#"{ "momentum_weight": 0.99 }"#,
+        r#"{ "aggressive_factor": 10.0 }"#,
+        r#"{ "medium_momentum_threshold": 12.0 }"#,
+        r#"{ "momentum_wieght": 0.3 }"#,
+        r#"{ "momentum_weight": "high" }"#,
+        r#"[0.3]"#,
+        "not json",
+    ] {
+        assert!(
+            apply_update(&params, update.as_bytes()).is_err(),
+            "{}",
+            update
+        );
+    }
+}
+
+#[optiva_ws::rt::test]
+async fn requests_need_the_token_when_one_is_set() {
+    let (control, _) = control(Some("secret"));
+    let response = control.handle(&request("GET", "/state", None, "")).await;
+    assert_eq!(response.status, 401);
+    let response = control
+        .handle(&request("GET", "/state", Some("guess"), ""))
+        .await;
+    assert_eq!(response.status, 401);
+    let response = control
+        .handle(&request("GET", "/state", Some("secret"), ""))
+        .await;
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body["trading_enabled"], json!(true));
+    assert_eq!(response.body["params"]["momentum_weight"], json!(0.6));
+}
+
+#[optiva_ws::rt::test]
+async fn params_are_updated_live() {
+    let (control, state) = control(None);
+    let response = control
+        .handle(&request("POST", "/params", None, r#"{ "deadband": 0.3 }"#))
+        .await;
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body["deadband"], json!(0.3));
+    assert_eq!(state.strategy_params.read().await.deadband, 0.3);
+
+    let response = control
+        .handle(&request("POST", "/params", None, r#"{ "deadband": -1.0 }"#))
+        .await;
+    assert_eq!(response.status, 400);
+    assert_eq!(state.strategy_params.read().await.deadband, 0.3);
+}
+
+#[optiva_ws::rt::test]
+async fn fixed_params_are_refused() {
+    let (control, state) = control(None);
+    let control = control.with_fixed_params();
+    let response = control
+        .handle(&request("POST", "/params", None, r#"{ "deadband": 0.3 }"#))
+        .await;
+    assert_eq!(response.status, 409);
+    assert_eq!(
+        *state.strategy_params.read().await,
+        StrategyParams::default()
+    );
+}
+
+#[optiva_ws::rt::test]
+async fn unknown_routes_and_methods() {
+    let (control, _) = control(None);
+    assert_eq!(
+        control
+            .handle(&request("GET", "/nope", None, ""))
+            .await
+            .status,
+        404
+    );
+    assert_eq!(
+        control
+            .handle(&request("GET", "/halt", None, ""))
+            .await
+            .status,
+        405
+    );
+}
+
+#[optiva_ws::rt::test]
+async fn halt_stops_trading_until_resumed() {
+    let (control, state) = control(None);
+    let config = Arc::new(common::test_config());
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+    let trades = |outbox: &[optiva_ws::protocol::ClientMessage]| {
+        outbox
+            .iter()
+            .filter(|message| matches!(message.event, ClientEvent::Trade(_)))
+            .count()
+    };
+
+    let response = control.handle(&request("POST", "/halt", None, "")).await;
+    assert_eq!(response.body, json!({ "trading_enabled": false }));
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&common::state_frame(100.0, 0.5, 8.0, 0, 0.0), &mut outbox)
+        .await;
+    let puzzle = json!({ "event": "puzzle", "data": { "impact": 2.0 } }).to_string();
+    handler.handle_text(&puzzle, &mut outbox).await;
+    assert_eq!(trades(&outbox), 0);
+    assert!(!state.snapshot().await.trading_enabled);
+
+    control.handle(&request("POST", "/resume", None, "")).await;
+    handler
+        .handle_text(&common::state_frame(100.0, 0.5, 8.0, 0, 0.0), &mut outbox)
+        .await;
+    assert_eq!(trades(&outbox), 1);
+}
+
+#[optiva_ws::rt::test]
+async fn requests_are_read_whole() {
+    let raw = b"POST /params?x=1 HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer abc\r\nContent-Length: 11\r\n\r\n{\"a\": 1.0}\n";
+    let request = read_request(&mut &raw[..]).await.unwrap();
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.path, "/params");
+    assert_eq!(request.token.as_deref(), Some("abc"));
+    assert_eq!(request.body, b"{\"a\": 1.0}\n");
+
+    // Cut short, or with a body bigger than allowed
+    assert!(read_request(&mut &raw[..raw.len() - 3]).await.is_err());
+    let huge = b"POST /params HTTP/1.1\r\nContent-Length: 1000000\r\n\r\n";
+    assert!(read_request(&mut &huge[..]).await.is_err());
+}
+
+#[optiva_ws::rt::test]
+async fn serves_over_tcp_until_shutdown() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (control, _) = control(None);
+    let shutdown = Shutdown::new();
+    let server = rt::spawn(control.serve(listener, shutdown.clone()));
+
+    let mut stream = rt::net::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /state HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    let (_, body) = response.split_once("\r\n\r\n").unwrap();
+    let body: Value = serde_json::from_str(body).unwrap();
+    assert_eq!(body["connections"], json!([]));
+
+    shutdown.trigger();
+    server.await;
+}
+
+#[test]
+fn control_outside_loopback_needs_a_token() {
+    let parse = |control: &str| {
+        Config::parse(&format!(
+            r#"
+            url = "wss://example.com"
+            player_id = "abc"
+
+            [control]
+            enabled = true
+            {}
+            "#,
+            control
+        ))
+    };
+    let config = parse("").unwrap();
+    assert_eq!(config.control.bind, "127.0.0.1:8787");
+    assert!(matches!(
+        parse(r#"bind = "0.0.0.0:8787""#),
+        Err(ConfigError::Invalid(_))
+    ));
+    parse("bind = \"0.0.0.0:8787\"\ntoken = \"secret\"").unwrap();
+    assert!(matches!(
+        parse(r#"bind = "localhost""#),
+        Err(ConfigError::Invalid(_))
+    ));
+}
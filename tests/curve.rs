@@ -0,0 +1,65 @@
+mod common;
+
+use common::temp_dir;
+use optiva_ws::clock::Monotonic;
+use optiva_ws::curve::{sparkline, write_curve, PnlCurve, MAX_CURVE_POINTS};
+
+#[test]
+fn elapsed_time_counts_from_the_first_record() {
+    let mut curve = PnlCurve::default();
+    curve.record(Monotonic(50.0), 1.0);
+    curve.record(Monotonic(52.5), -3.0);
+    assert_eq!(curve.points(), [(0.0, 1.0), (2.5, -3.0)]);
+
+    assert_eq!(curve.take().len(), 2);
+    assert!(curve.is_empty());
+    curve.record(Monotonic(90.0), 2.0);
+    assert_eq!(curve.points(), [(0.0, 2.0)]);
+}
+
+#[test]
+fn a_long_game_is_thinned_rather_than_growing() {
+    let mut curve = PnlCurve::default();
+    for tick in 0..MAX_CURVE_POINTS * 5 {
+        curve.record(Monotonic(tick as f64), tick as f64);
+    }
+    let points = curve.points();
+    assert!(points.len() <= MAX_CURVE_POINTS);
+    assert!(points.len() > MAX_CURVE_POINTS / 2);
+    assert_eq!(points[0], (0.0, 0.0));
+    assert!(points.windows(2).all(|pair| pair[1].0 > pair[0].0));
+    // Still reaches near the end of the game
+    assert!(points.last().unwrap().0 > (MAX_CURVE_POINTS * 5 - 16) as f32);
+}
+
+#[test]
+fn sparkline_goes_from_the_low_to_the_peak() {
+    let points = [(0.0, 0.0), (1.0, 10.0), (2.0, 5.0), (3.0, -10.0)];
+    let line = sparkline(&points, 4);
+    assert_eq!(line.len(), 4);
+    assert_eq!(&line[3..], "_");
+    assert_eq!(&line[1..2], "@");
+
+    // Gaps repeat the slice before, and a flat curve sits in the middle
+    assert_eq!(sparkline(&[(0.0, 1.0), (10.0, 1.0)], 5), "=====");
+    assert_eq!(sparkline(&[], 5), "");
+}
+
+#[optiva_ws::rt::test]
+async fn curves_are_written_as_csv_per_connection() {
+    let dir = temp_dir("curve-csv");
+    let path = write_curve(
+        &dir,
+        "alice",
+        2,
+        1_700_000_000.5,
+        &[(0.0, 0.0), (1.5, 2.25)],
+    )
+    .await
+    .unwrap();
+    assert_eq!(path, dir.join("curve-alice-conn2-1700000000.csv"));
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "elapsed_secs,pnl\n0.000,0\n1.500,2.25\n"
+    );
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use optiva_ws::divergence::{outliers, readings, DivergenceConfig, DivergenceMonitor, Reading};
+use optiva_ws::state::{GamePhase, Health, SharedState};
+
+fn reading(conn_id: usize, price: f64, pnl_change: Option<f64>) -> Reading {
+    Reading {
+        conn_id,
+        price,
+        pnl_change,
+    }
+}
+
+#[test]
+fn a_price_far_from_the_median_is_out_of_line() {
+    let config = DivergenceConfig::default();
+    let readings = [
+        reading(0, 100.0, None),
+        reading(1, 101.0, None),
+        reading(2, 130.0, None),
+        reading(3, 99.5, None),
+    ];
+    assert_eq!(outliers(&readings, &config), vec![2]);
+}
+
+#[test]
+fn two_connections_are_never_told_apart() {
+    let config = DivergenceConfig::default();
+    let readings = [reading(0, 100.0, None), reading(1, 200.0, None)];
+    assert!(outliers(&readings, &config).is_empty());
+}
+
+#[test]
+fn pnl_changes_are_only_compared_with_a_gap_set() {
+    let mut config = DivergenceConfig::default();
+    let readings = [
+        reading(0, 100.0, Some(1.0)),
+        reading(1, 100.0, Some(-2.0)),
+        reading(2, 100.0, Some(40.0)),
+        reading(3, 100.0, None),
+    ];
+    assert!(outliers(&readings, &config).is_empty());
+    config.max_pnl_change_gap = Some(10.0);
+    assert_eq!(outliers(&readings, &config), vec![2]);
+}
+
+#[test]
+fn a_connection_is_suspect_after_enough_checks_in_a_row() {
+    let config = DivergenceConfig::default();
+    let mut monitor = DivergenceMonitor::default();
+    let out = [
+        reading(0, 100.0, None),
+        reading(1, 100.0, None),
+        reading(2, 50.0, None),
+    ];
+    let back = [
+        reading(0, 100.0, None),
+        reading(1, 100.0, None),
+        reading(2, 100.0, None),
+    ];
+
+    assert!(monitor.check(&out, &config).is_empty());
+    assert!(monitor.check(&out, &config).is_empty());
+    // Back in line, so the count starts over
+    assert!(monitor.check(&back, &config).is_empty());
+    assert_eq!(monitor.strikes(2), 0);
+    for _ in 0..2 {
+        assert!(monitor.check(&out, &config).is_empty());
+    }
+    assert_eq!(monitor.check(&out, &config), vec![2]);
+    // Not compared for a check, say between games, also starts it over
+    assert!(monitor.check(&out[..2], &config).is_empty());
+    assert_eq!(monitor.strikes(2), 0);
+}
+
+#[optiva_ws::rt::test]
+async fn only_live_trading_connections_are_read() {
+    let mut config = common::test_config();
+    config.connections = 4;
+    let state = SharedState::new(&config);
+    {
+        let mut performances = state.connection_performance.lock().await;
+        for (conn_id, (health, phase, price)) in [
+            (Health::Live, GamePhase::Trading, Some(100.0)),
+            (Health::Live, GamePhase::Finished, Some(100.0)),
+            (Health::Backoff, GamePhase::Trading, Some(100.0)),
+            (Health::Live, GamePhase::Trading, None),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let perf = performances.entry(conn_id).or_default();
+            perf.health = health;
+            perf.phase = phase;
+            perf.session.last_price = price;
+        }
+    }
+    assert_eq!(readings(&state).await, vec![reading(0, 100.0, None)]);
+
+    state.set_suspects(&[2]).await;
+    let snapshot = state.snapshot().await;
+    let suspects: Vec<bool> = snapshot.connections.iter().map(|c| c.suspect).collect();
+    assert_eq!(suspects, vec![false, false, true, false]);
+}
+
+#[test]
+fn a_reconnect_is_asked_for_once_until_taken() {
+    let mut config = common::test_config();
+    config.connections = 2;
+    let state = SharedState::new(&config);
+    assert!(state.reconnects.request(1));
+    assert!(!state.reconnects.request(1));
+    assert!(!state.reconnects.request(5));
+    assert!(state.reconnects.clear(1));
+    assert!(!state.reconnects.clear(1));
+    assert!(state.reconnects.request(1));
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use std::collections::BTreeMap;
+
+use optiva_ws::eval::{evaluate, format_eval, load_eval_params, EvalInput};
+use optiva_ws::persist::{SavedParams, WindowStats};
+use optiva_ws::state::{Sizing, StrategyParams};
+use optiva_ws::strategy::{combined_signal, forecast_signal, momentum_signal};
+
+fn input(momentum: f64, forecast: f64, position: i32) -> EvalInput {
+    EvalInput {
+        momentum,
+        forecast,
+        position,
+        position_limit: 3,
+    }
+}
+
+#[test]
+fn eval_shows_the_signals_behind_the_decision() {
+    let params = StrategyParams::default();
+    let report = evaluate(input(7.2, -0.3, 1), &params);
+    assert_eq!(report.momentum_signal, momentum_signal(7.2, &params));
+    assert_eq!(report.forecast_signal, forecast_signal(-0.3, &params));
+    assert_eq!(report.combined_signal, combined_signal(-0.3, 7.2, &params));
+    assert!(!report.signals_agree);
+}
+
+#[test]
+fn eval_sizes_the_signal_every_way() {
+    let params = StrategyParams::default();
+    // Strong momentum both ways, so the full aggressive factor
+    let report = evaluate(input(30.0, 1.0, 1), &params);
+    let volumes: Vec<(Sizing, i32, i32)> = report
+        .sizes
+        .iter()
+        .map(|size| (size.sizing, size.volume, size.position_after))
+        .collect();
+    assert_eq!(
+        volumes,
+        [
+            (Sizing::AllIn, 2, 3),
+            (Sizing::Proportional, 2, 3),
+            // Proportional until there's history
+            (Sizing::Kelly, 2, 3),
+        ]
+    );
+    assert!(report.actionable);
+
+    // A weak signal takes a share of the limit in proportion, and all of it all in
+    let report = evaluate(input(1.0, 0.05, 0), &params);
+    let all_in = report.sizes[0].volume;
+    let proportional = report.sizes[1].volume;
+    assert!(proportional.abs() < all_in.abs(), "{:?}", report.sizes);
+}
+
+#[test]
+fn eval_is_the_same_as_text_and_json() {
+    let params = StrategyParams::default();
+    let input = input(7.2, -0.3, 1);
+    let report = evaluate(input, &params);
+    let text = format_eval(input, &params, &report);
+    assert!(text.contains("all_in"), "{}", text);
+    assert!(text.contains("signals disagree"), "{}", text);
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["sizes"][0]["sizing"], "all_in");
+    assert_eq!(json["signals_agree"], false);
+}
+
+#[test]
+fn eval_reads_saved_or_bare_params() {
+    let dir = common::temp_dir("eval");
+    let params = StrategyParams {
+        momentum_weight: 0.8,
+        forecast_weight: 0.2,
+        ..StrategyParams::default()
+    };
+
+    let saved = dir.join("saved.json");
+    let file = SavedParams {
+        saved_at: 1.0,
+        params: params.clone(),
+        stats: WindowStats {
+            samples: 0,
+            mean_pnl_change: 0.0,
+            sharpe: None,
+        },
+        arms: BTreeMap::new(),
+    };
+    std::fs::write(&saved, serde_json::to_string(&file).unwrap()).unwrap();
+    assert_eq!(load_eval_params(&saved).unwrap(), params);
+
+    let bare = dir.join("bare.json");
+    std::fs::write(
+        &bare,
+        r#"{ "momentum_weight": 0.8, "forecast_weight": 0.2 }"#,
+    )
+    .unwrap();
+    assert_eq!(load_eval_params(&bare).unwrap(), params);
+
+    let invalid = dir.join("invalid.json");
+    std::fs::write(&invalid, r#"{ "momentum_weight": -1.0 }"#).unwrap();
+    assert!(load_eval_params(&invalid).is_err());
+}
@@ -0,0 +1,178 @@
+#![cfg(feature = "auto-optimize")]
+
+mod common;
+
+use optiva_ws::clock::ManualClock;
+use optiva_ws::config::{Config, ConfigError};
+use optiva_ws::explore::Explorer;
+use optiva_ws::optimizer::optimize_strategy;
+use optiva_ws::state::{PerformanceData, SharedState, StrategyParams};
+use optiva_ws::strategy::{Driver, OptimizerConfig};
+use std::sync::Arc;
+
+fn seeded(epsilon: f64, seed: u64) -> OptimizerConfig {
+    OptimizerConfig {
+        epsilon,
+        seed: Some(seed),
+        ..OptimizerConfig::default()
+    }
+}
+
+// Which explorable params differ, with their old and new values
+fn changed(before: &StrategyParams, after: &StrategyParams) -> Vec<(&'static str, f64, f64)> {
+    [
+        (
+            "momentum_weight",
+            before.momentum_weight,
+            after.momentum_weight,
+        ),
+        (
+            "forecast_weight",
+            before.forecast_weight,
+            after.forecast_weight,
+        ),
+        (
+            "aggressive_factor",
+            before.aggressive_factor,
+            after.aggressive_factor,
+        ),
+        ("deadband", before.deadband, after.deadband),
+    ]
+    .into_iter()
+    .filter(|(_, old, new)| old != new)
+    .collect()
+}
+
+#[test]
+fn explorations_stay_in_bounds_and_repeat_with_the_seed() {
+    let mut first = Explorer::new(&seeded(0.5, 7));
+    let mut second = Explorer::new(&seeded(0.5, 7));
+    let mut params = StrategyParams::default();
+    let mut again = StrategyParams::default();
+    for _ in 0..200 {
+        let before = params.clone();
+        let exploration = first.explore(&mut params, 1.0);
+        assert_eq!(second.explore(&mut again, 1.0), exploration);
+        assert_eq!(first.roll(), second.roll());
+
+        // A weight takes the other with it, so they still sum to 1
+        let changes = changed(&before, &params);
+        assert!(
+            changes.contains(&(exploration.param, exploration.from, exploration.to)),
+            "{:?}",
+            changes
+        );
+        if exploration.param.ends_with("_weight") {
+            assert_eq!(changes.len(), 2, "{:?}", changes);
+            assert!((params.momentum_weight + params.forecast_weight - 1.0).abs() < 1e-9);
+            assert!((exploration.to - exploration.from).abs() <= 0.1 + 1e-9);
+        } else {
+            assert_eq!(changes.len(), 1, "{:?}", changes);
+        }
+        assert_ne!(exploration.from, exploration.to);
+        params.validate().unwrap();
+        assert!((0.05..=0.95).contains(&params.momentum_weight));
+        assert!((0.05..=0.95).contains(&params.forecast_weight));
+        assert!((1.0..=2.0).contains(&params.aggressive_factor));
+        assert!((0.0..=0.5).contains(&params.deadband));
+    }
+    assert_eq!(params, again);
+}
+
+#[test]
+fn epsilon_sets_how_often_to_explore() {
+    let mut never = Explorer::new(&seeded(0.0, 1));
+    let mut always = Explorer::new(&seeded(1.0, 1));
+    assert!((0..100).all(|_| !never.roll() && always.roll()));
+}
+
+fn exploring_state(seed: u64) -> (Arc<ManualClock>, SharedState) {
+    let clock = Arc::new(ManualClock::new(1000.0));
+    let mut config = common::test_config();
+    config.optimizer = seeded(1.0, seed);
+    let state = SharedState::with_clock(&config, clock.clone());
+    (clock, state)
+}
+
+async fn record(state: &SharedState, pnl_changes: &[f64]) {
+    for &pnl_change in pnl_changes {
+        state
+            .record_performance(PerformanceData {
+                conn_id: 0,
+                timestamp: state.monotonic(),
+                momentum: 8.0,
+                forecast: 0.2,
+                position: 0,
+                trade_volume: 1,
+                pnl_change,
+                price: 100.0,
+                total_pnl: 0.0,
+                signal: 0.0,
+                decision_id: 0,
+                driver: Driver::Momentum,
+            })
+            .await;
+    }
+}
+
+async fn cycle(clock: &ManualClock, state: &SharedState) {
+    clock.advance(state.optimization_interval);
+    assert!(optimize_strategy(state).await);
+}
+
+#[optiva_ws::rt::test]
+async fn a_change_that_makes_things_worse_is_reverted() {
+    let (clock, state) = exploring_state(3);
+    record(&state, &[8.0, 12.0, 10.0, 8.0, 12.0]).await;
+    cycle(&clock, &state).await;
+
+    let explored = state.strategy_params.read().await.clone();
+    let exploration = state.explorer.lock().await.pending().cloned().unwrap();
+    let changes = changed(&StrategyParams::default(), &explored);
+    assert!(
+        changes.contains(&(exploration.param, exploration.from, exploration.to)),
+        "{:?}",
+        changes
+    );
+
+    record(&state, &[-20.0, -18.0, -22.0, -20.0, -19.0]).await;
+    cycle(&clock, &state).await;
+    assert_eq!(
+        *state.strategy_params.read().await,
+        StrategyParams::default()
+    );
+    assert!(state.explorer.lock().await.pending().is_none());
+}
+
+#[optiva_ws::rt::test]
+async fn a_change_that_holds_up_is_kept() {
+    let (clock, state) = exploring_state(3);
+    record(&state, &[8.0, 12.0, 10.0, 8.0, 12.0]).await;
+    cycle(&clock, &state).await;
+    let explored = state.strategy_params.read().await.clone();
+
+    record(&state, &[10.0, 10.1, 10.0, 10.1, 10.0]).await;
+    cycle(&clock, &state).await;
+    assert_eq!(*state.strategy_params.read().await, explored);
+    assert!(state.explorer.lock().await.pending().is_none());
+}
+
+#[test]
+fn epsilon_is_a_probability() {
+    let parse = |epsilon: &str| {
+        Config::parse(&format!(
+            r#"
+            url = "wss://example.com"
+            player_id = "abc"
+
+            [optimizer]
+            epsilon = {}
+            seed = 11
+            "#,
+            epsilon
+        ))
+    };
+    assert_eq!(parse("0.1").unwrap().optimizer.seed, Some(11));
+    assert!(matches!(parse("1.5"), Err(ConfigError::Invalid(_))));
+    assert!(matches!(parse("-0.1"), Err(ConfigError::Invalid(_))));
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use optiva_ws::forecast::{ForecastAccuracy, ForecastConfig, ForecastSample, ForecastTracker};
+use optiva_ws::state::SharedState;
+
+fn sample(forecast: f64, change: f64) -> ForecastSample {
+    ForecastSample { forecast, change }
+}
+
+#[test]
+fn tracker_pairs_each_forecast_with_the_change_over_the_horizon() {
+    let mut tracker = ForecastTracker::default();
+    assert_eq!(tracker.observe(100.0, 1.0, 2), None);
+    assert_eq!(tracker.observe(101.0, -1.0, 2), None);
+    assert_eq!(tracker.observe(103.0, 0.5, 2), Some(sample(1.0, 3.0)));
+    assert_eq!(tracker.observe(102.0, 0.0, 2), Some(sample(-1.0, 1.0)));
+
+    tracker.clear();
+    assert_eq!(tracker.observe(50.0, 1.0, 2), None);
+}
+
+#[test]
+fn accuracy_of_a_forecast_that_calls_every_move() {
+    let samples: Vec<_> = (1..=10)
+        .map(|i| {
+            let forecast = if i % 2 == 0 { i as f64 } else { -(i as f64) };
+            sample(forecast, forecast * 2.0)
+        })
+        .collect();
+    let accuracy = ForecastAccuracy::from_samples(&samples);
+    assert_eq!(accuracy.samples, 10);
+    assert_eq!(accuracy.hit_rate, Some(1.0));
+    assert!((accuracy.correlation.unwrap() - 1.0).abs() < 1e-9);
+}
+
+#[test]
+fn uninformative_forecast_is_weighted_near_zero_once_warm() {
+    let config = ForecastConfig {
+        min_samples: 8,
+        ..ForecastConfig::default()
+    };
+    // Forecast says up every time while the price goes up and down regardless
+    let changes = [1.0, 1.0, -1.0, -1.0, 1.0, 1.0, -1.0, -1.0];
+    let samples: Vec<_> = changes
+        .iter()
+        .enumerate()
+        .map(|(i, &change)| sample(1.0 + (i % 2) as f64, change))
+        .collect();
+    let accuracy = ForecastAccuracy::from_samples(&samples);
+    assert_eq!(accuracy.hit_rate, Some(0.5));
+    assert!(accuracy.weight_scale(&config) < 0.1);
+
+    // Too few samples to judge keeps the configured weight
+    let cold = ForecastAccuracy::from_samples(&samples[..7]);
+    assert_eq!(cold.weight_scale(&config), 1.0);
+}
+
+#[test]
+fn forecast_against_the_move_gets_no_weight() {
+    let samples: Vec<_> = (1..=40).map(|i| sample(i as f64, -(i as f64))).collect();
+    let accuracy = ForecastAccuracy::from_samples(&samples);
+    assert_eq!(accuracy.hit_rate, Some(0.0));
+    assert_eq!(accuracy.weight_scale(&ForecastConfig::default()), 0.0);
+}
+
+#[optiva_ws::rt::test]
+async fn params_for_scales_the_forecast_weight_by_accuracy() {
+    let mut config = common::test_config();
+    config.forecast.window = 50;
+    config.forecast.min_samples = 40;
+    let state = SharedState::new(&config);
+    let configured = state.strategy_params.read().await.forecast_weight;
+
+    for i in 1..40 {
+        state.record_forecast(sample(i as f64, 1.0)).await;
+    }
+    assert_eq!(state.params_for(0).await.forecast_weight, configured);
+
+    // A constant change carries no information about the forecast
+    state.record_forecast(sample(40.0, 1.0)).await;
+    assert_eq!(state.params_for(0).await.forecast_weight, 0.0);
+
+    // The window only keeps the latest samples
+    for i in 0..60 {
+        state.record_forecast(sample(i as f64, i as f64)).await;
+    }
+    let accuracy = state.forecast_accuracy().await;
+    assert_eq!(accuracy.samples, 50);
+    assert!((state.params_for(0).await.forecast_weight - configured).abs() < 1e-9);
+}
@@ -0,0 +1,183 @@
+mod common;
+
+use common::{state_frame, test_config};
+use optiva_ws::handler::ConnectionHandler;
+use optiva_ws::history::{BoundedHistory, PriceHistory, RunningStats, Sampled, EMA_SPAN};
+use optiva_ws::state::SharedState;
+use optiva_ws::strategy::sharpe_ratio;
+use serde_json::json;
+use statrs::statistics::Statistics;
+use std::sync::Arc;
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-9
+}
+
+fn history(prices: &[f64]) -> PriceHistory {
+    let mut history = PriceHistory::default();
+    for &price in prices {
+        history.push(price);
+    }
+    history
+}
+
+#[test]
+fn keeps_only_the_most_recent_prices() {
+    let mut history = PriceHistory::new(3);
+    for price in [1.0, 2.0, 3.0, 4.0, 5.0] {
+        history.push(price);
+    }
+    assert_eq!(history.prices(), &[3.0, 4.0, 5.0]);
+}
+
+#[test]
+fn returns_and_momentum() {
+    let history = history(&[100.0, 110.0, 99.0]);
+    let returns = history.returns();
+    assert!(close(returns[0], 0.1));
+    assert!(close(returns[1], -0.1));
+    assert!(close(history.momentum().unwrap(), 0.0));
+
+    let rising = self::history(&[100.0, 102.0, 104.04]);
+    assert!(close(rising.momentum().unwrap(), 0.02));
+}
+
+#[test]
+fn ema_weights_recent_prices() {
+    // alpha = 2 / (3 + 1) = 0.5
+    let history = history(&[10.0, 20.0, 30.0]);
+    assert!(close(history.ema(3).unwrap(), 22.5));
+
+    let flat = self::history(&[5.0; 15]);
+    assert!(close(flat.ema(EMA_SPAN).unwrap(), 5.0));
+}
+
+#[test]
+fn volatility_is_sample_std_dev_of_returns() {
+    // Returns of +10%, -10%, +10%: mean 1/30, sample variance 0.04/3
+    let history = history(&[100.0, 110.0, 99.0, 108.9]);
+    let expected = (0.04f64 / 3.0).sqrt();
+    assert!(close(history.volatility().unwrap(), expected));
+
+    let steady = self::history(&[100.0, 101.0, 102.01]);
+    assert!(close(steady.volatility().unwrap(), 0.0));
+}
+
+#[test]
+fn indicators_need_enough_prices() {
+    let empty = PriceHistory::default().indicators();
+    assert_eq!(
+        (empty.momentum, empty.ema, empty.volatility),
+        (None, None, None)
+    );
+
+    let one = history(&[100.0]).indicators();
+    assert_eq!(one.ema, Some(100.0));
+    assert_eq!(one.momentum, None);
+
+    let two = history(&[100.0, 101.0]).indicators();
+    assert!(two.momentum.is_some());
+    assert_eq!(two.volatility, None);
+}
+
+#[optiva_ws::rt::test]
+async fn handler_history_resets_on_finish_and_reconnect() {
+    let config = Arc::new(test_config());
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, state);
+    common::join(&mut handler).await;
+
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&state_frame(100.0, 0.0, 0.0, 0, 0.0), &mut outbox)
+        .await;
+    handler
+        .handle_text(&state_frame(101.0, 0.0, 0.0, 0, 0.0), &mut outbox)
+        .await;
+    assert_eq!(handler.price_history().len(), 2);
+
+    let finish = json!({ "event": "finish", "data": {} }).to_string();
+    handler.handle_text(&finish, &mut outbox).await;
+    assert!(handler.price_history().is_empty());
+
+    handler
+        .handle_text(&state_frame(100.0, 0.0, 0.0, 0, 0.0), &mut outbox)
+        .await;
+    common::join(&mut handler).await;
+    assert!(handler.price_history().is_empty());
+}
+
+struct Sample(f64);
+
+impl Sampled for Sample {
+    fn sample(&self) -> f64 {
+        self.0
+    }
+}
+
+fn values(count: usize) -> Vec<f64> {
+    // Uneven, signed and with repeats, so rounding would show
+    (0..count)
+        .map(|i| ((i * 37 % 11) as f64 - 5.0) * 1.7 + (i % 3) as f64 * 0.01)
+        .collect()
+}
+
+fn assert_matches_batch(stats: &RunningStats, batch: &[f64]) {
+    assert_eq!(stats.count, batch.len());
+    assert!(
+        close(stats.mean, batch.mean()),
+        "{} vs {}",
+        stats.mean,
+        batch.mean()
+    );
+    assert!(close(stats.variance().unwrap(), batch.variance()));
+    assert!(close(stats.sharpe().unwrap(), sharpe_ratio(batch).unwrap()));
+    let wins = batch.iter().filter(|value| **value > 0.0).count();
+    assert_eq!(stats.wins, wins);
+    assert!(close(
+        stats.win_rate().unwrap(),
+        wins as f64 / batch.len() as f64
+    ));
+}
+
+#[test]
+fn running_stats_match_batch_computation() {
+    let values = values(200);
+    let mut stats = RunningStats::default();
+    assert_eq!(stats.variance(), None);
+    assert_eq!(stats.win_rate(), None);
+    for (i, value) in values.iter().enumerate() {
+        stats.add(*value);
+        if i >= 1 {
+            assert_matches_batch(&stats, &values[..=i]);
+        }
+    }
+
+    // Taking values back out leaves the stats of what's left
+    for i in 0..150 {
+        stats.remove(values[i]);
+        assert_matches_batch(&stats, &values[i + 1..]);
+    }
+}
+
+#[test]
+fn bounded_history_keeps_stats_over_what_it_holds() {
+    let values = values(100);
+    let mut history = BoundedHistory::new(30);
+    for value in &values {
+        history.push(Sample(*value));
+    }
+    assert_eq!(history.len(), 30);
+    assert_matches_batch(history.stats(), &values[70..]);
+    assert_eq!(history.back().unwrap().0, values[99]);
+
+    history.retain(|sample| sample.0 > 0.0);
+    let positive: Vec<f64> = values[70..].iter().copied().filter(|v| *v > 0.0).collect();
+    assert_eq!(history.len(), positive.len());
+    assert_matches_batch(history.stats(), &positive);
+    assert_eq!(history.stats().wins, positive.len());
+
+    history.retain(|_| false);
+    assert!(history.is_empty());
+    assert_eq!(*history.stats(), RunningStats::default());
+}
@@ -0,0 +1,161 @@
+mod common;
+
+use async_channel::{Receiver, Sender};
+use futures::future::BoxFuture;
+use optiva_ws::error::BotError;
+use optiva_ws::handler::ConnectionHandler;
+use optiva_ws::hooks::{FileLogger, HookRunner, Hooks, NoopHooks, HOOK_QUEUE};
+use optiva_ws::protocol::StateUpdate;
+use optiva_ws::state::{SharedState, SignalData};
+use optiva_ws::summary::GameSummary;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+// Names each event it's given, down a channel the test reads
+struct Recorder(Sender<String>);
+
+impl Recorder {
+    fn record(&self, event: String) -> BoxFuture<'static, ()> {
+        let sender = self.0.clone();
+        Box::pin(async move {
+            let _ = sender.send(event).await;
+        })
+    }
+}
+
+impl Hooks for Recorder {
+    fn on_state<'a>(&'a mut self, conn_id: usize, update: &'a StateUpdate) -> BoxFuture<'a, ()> {
+        self.record(format!("state {} {}", conn_id, update.price))
+    }
+
+    fn on_decision<'a>(&'a mut self, signal: &'a SignalData) -> BoxFuture<'a, ()> {
+        self.record(format!(
+            "decision {} {}",
+            signal.conn_id, signal.trade_volume
+        ))
+    }
+
+    fn on_trade_sent(&mut self, conn_id: usize, volume: i32) -> BoxFuture<'_, ()> {
+        self.record(format!("trade {} {}", conn_id, volume))
+    }
+
+    fn on_finish<'a>(&'a mut self, summary: &'a GameSummary) -> BoxFuture<'a, ()> {
+        self.record(format!(
+            "finish {} {:?}",
+            summary.conn_id, summary.final_pnl
+        ))
+    }
+
+    fn on_error<'a>(&'a mut self, conn_id: usize, error: &'a BotError) -> BoxFuture<'a, ()> {
+        self.record(format!("error {} {}", conn_id, error))
+    }
+}
+
+fn drain(receiver: &Receiver<String>) -> Vec<String> {
+    std::iter::from_fn(|| receiver.try_recv().ok()).collect()
+}
+
+#[optiva_ws::rt::test]
+async fn the_handler_calls_hooks_at_each_point() {
+    let (sender, receiver) = async_channel::unbounded();
+    let (runner, task) =
+        HookRunner::spawn(vec![Box::new(NoopHooks), Box::new(Recorder(sender))]).unwrap();
+
+    let config = Arc::new(common::test_config());
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(2, config, state).with_hooks(runner.clone());
+    common::join(&mut handler).await;
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&common::state_frame(100.0, 0.5, 8.0, 0, 0.0), &mut outbox)
+        .await;
+    let finish = json!({ "event": "finish", "data": { "pnl": 4.0 } }).to_string();
+    handler.handle_text(&finish, &mut outbox).await;
+    runner.error(2, BotError::Protocol("bad frame".to_string()));
+
+    // The task runs out the queue once the last handle is gone
+    drop(handler);
+    drop(runner);
+    task.await;
+    assert_eq!(
+        drain(&receiver),
+        [
+            "state 2 100",
+            "decision 2 3",
+            "trade 2 3",
+            "finish 2 Some(4.0)",
+            "error 2 protocol error: bad frame",
+        ]
+    );
+}
+
+// Holds the hook task up on its first event until the gate opens
+struct Stuck {
+    gate: Receiver<()>,
+    seen: Sender<i32>,
+}
+
+impl Hooks for Stuck {
+    fn on_trade_sent(&mut self, _conn_id: usize, volume: i32) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            let _ = self.gate.recv().await;
+            let _ = self.seen.send(volume).await;
+        })
+    }
+}
+
+#[optiva_ws::rt::test]
+async fn a_backed_up_queue_drops_events_rather_than_waiting() {
+    let (open, gate) = async_channel::unbounded();
+    let (seen, received) = async_channel::unbounded();
+    let (runner, task) = HookRunner::spawn(vec![Box::new(Stuck { gate, seen })]).unwrap();
+
+    // None of these wait on the stuck hook
+    let sent = HOOK_QUEUE as i32 + 50;
+    for volume in 0..sent {
+        runner.trade_sent(0, volume);
+    }
+    open.close();
+    drop(runner);
+    task.await;
+
+    let delivered: Vec<i32> = std::iter::from_fn(|| received.try_recv().ok()).collect();
+    assert!(delivered.len() <= HOOK_QUEUE + 1, "{}", delivered.len());
+    assert!(delivered.len() >= HOOK_QUEUE, "{}", delivered.len());
+    // What got through came in order, and the newest events were the ones dropped
+    assert!(delivered.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(*delivered.last().unwrap() < sent - 1);
+}
+
+#[test]
+fn no_hooks_means_no_task() {
+    assert!(HookRunner::spawn(Vec::new()).is_none());
+}
+
+#[optiva_ws::rt::test]
+async fn the_file_logger_writes_a_json_line_per_event() {
+    let path = common::temp_dir("hooks").join("hooks.jsonl");
+    let (runner, task) =
+        HookRunner::spawn(vec![Box::new(FileLogger::open(&path).await.unwrap())]).unwrap();
+    runner.trade_sent(1, -2);
+    runner.error(1, BotError::Auth("HTTP 401".to_string()));
+    drop(runner);
+    task.await;
+
+    let lines: Vec<Value> = std::fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            json!({ "hook": "trade_sent", "conn_id": 1, "volume": -2 }),
+            json!({
+                "hook": "error",
+                "conn_id": 1,
+                "error": "server rejected our credentials: HTTP 401"
+            }),
+        ]
+    );
+}
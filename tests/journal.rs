@@ -0,0 +1,234 @@
+mod common;
+
+use common::{state_frame, temp_dir, test_config};
+use optiva_ws::handler::ConnectionHandler;
+use optiva_ws::journal::{
+    analyze, read_journal, Journal, JournalConfig, JournalEntry, JournalFormat,
+};
+use optiva_ws::rt;
+use optiva_ws::state::SharedState;
+use optiva_ws::strategy::{DecisionMode, Driver};
+use std::sync::Arc;
+
+fn entry(conn_id: usize, signal: f64, before: i32, after: i32, pnl: f64) -> JournalEntry {
+    JournalEntry {
+        timestamp: 1000.0,
+        conn_id,
+        strategy: "blend".to_string(),
+        price: 100.0,
+        forecast: 0.5,
+        momentum: 8.0,
+        combined_signal: signal,
+        position_before: before,
+        position_after: after,
+        volume: after - before,
+        sent: after != before,
+        pnl,
+        pnl_change: (pnl != 0.0).then_some(pnl),
+        mode: DecisionMode::Follow,
+        account: String::new(),
+        arm: None,
+        decision_id: None,
+        driver: None,
+    }
+}
+
+fn journal_config(name: &str, file: &str, format: JournalFormat) -> JournalConfig {
+    JournalConfig {
+        enabled: true,
+        path: temp_dir(name).join(file),
+        format,
+        flush_secs: 0.05,
+    }
+}
+
+#[test]
+fn csv_rows_round_trip() {
+    let original = entry(2, -0.25, 3, -3, 0.0);
+    let row = original.to_csv_row();
+    assert_eq!(JournalEntry::from_csv_row(&row).unwrap(), original);
+
+    let err = JournalEntry::from_csv_row("1,2,3").unwrap_err();
+    assert!(err.contains("13 to 18 columns"), "{}", err);
+
+    let faded = JournalEntry {
+        mode: DecisionMode::Fade,
+        ..original
+    };
+    let row = faded.to_csv_row();
+    assert!(row.ends_with(",fade,,,,"));
+    assert_eq!(JournalEntry::from_csv_row(&row).unwrap(), faded);
+
+    let armed = JournalEntry {
+        arm: Some("steady".to_string()),
+        ..faded
+    };
+    let row = armed.to_csv_row();
+    assert!(row.ends_with(",fade,,steady,,"));
+    assert_eq!(JournalEntry::from_csv_row(&row).unwrap(), armed);
+
+    let tagged = JournalEntry {
+        decision_id: Some(7),
+        driver: Some(Driver::Forecast),
+        ..armed
+    };
+    let row = tagged.to_csv_row();
+    assert!(row.ends_with(",steady,7,forecast"));
+    assert_eq!(JournalEntry::from_csv_row(&row).unwrap(), tagged);
+}
+
+#[test]
+fn older_journals_without_a_mode_read_as_following() {
+    let row = "1000,2,blend,100,0.5,8,-0.25,3,-3,-6,true,0,";
+    assert_eq!(
+        JournalEntry::from_csv_row(row).unwrap().mode,
+        DecisionMode::Follow
+    );
+    let line = r#"{"timestamp":1000.0,"conn_id":2,"strategy":"blend","price":100.0,"forecast":0.5,"momentum":8.0,"combined_signal":-0.25,"position_before":3,"position_after":-3,"volume":-6,"sent":true,"pnl":0.0,"pnl_change":null}"#;
+    let entry: JournalEntry = serde_json::from_str(line).unwrap();
+    assert_eq!(entry.mode, DecisionMode::Follow);
+}
+
+#[optiva_ws::rt::test]
+async fn writer_appends_every_format_and_flushes_on_close() {
+    for (file, format) in [
+        ("journal.jsonl", JournalFormat::Jsonl),
+        ("journal.csv", JournalFormat::Csv),
+    ] {
+        let config = journal_config("journal", file, format);
+        let (journal, writer) = Journal::spawn(&config).await.unwrap();
+        journal.record(entry(0, 0.5, 0, 3, 0.0));
+        journal.record(entry(1, 0.1, 0, 0, 2.0));
+        drop(journal);
+        writer.await;
+
+        let entries = read_journal(&config.path).unwrap();
+        assert_eq!(
+            entries,
+            vec![entry(0, 0.5, 0, 3, 0.0), entry(1, 0.1, 0, 0, 2.0)]
+        );
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+#[optiva_ws::rt::test]
+async fn without_sqlite_a_database_journal_goes_to_jsonl() {
+    let config = journal_config("journal-no-sqlite", "journal.db", JournalFormat::Sqlite);
+    let (journal, writer) = Journal::spawn(&config).await.unwrap();
+    journal.record(entry(0, 0.5, 0, 3, 0.0));
+    drop(journal);
+    writer.await;
+
+    assert!(!config.path.exists());
+    let fallback = optiva_ws::journal::fallback_path(&config.path);
+    assert_eq!(read_journal(&fallback).unwrap().len(), 1);
+    assert!(read_journal(&config.path).is_err());
+}
+
+#[optiva_ws::rt::test]
+async fn writer_flushes_while_running() {
+    let config = journal_config("journal-flush", "journal.jsonl", JournalFormat::Jsonl);
+    let (journal, writer) = Journal::spawn(&config).await.unwrap();
+    journal.record(entry(0, 0.5, 0, 3, 0.0));
+
+    rt::sleep(std::time::Duration::from_millis(300)).await;
+    assert_eq!(read_journal(&config.path).unwrap().len(), 1);
+
+    drop(journal);
+    writer.await;
+}
+
+#[optiva_ws::rt::test]
+async fn handler_journals_decisions_without_trades_too() {
+    let config = journal_config("journal-handler", "journal.jsonl", JournalFormat::Jsonl);
+    let (journal, writer) = Journal::spawn(&config).await.unwrap();
+
+    let bot_config = Arc::new(test_config());
+    let state = Arc::new(SharedState::new(&bot_config));
+    let mut handler = ConnectionHandler::new(1, bot_config, state).with_journal(journal);
+    common::join(&mut handler).await;
+
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&state_frame(100.0, 0.5, 8.0, 0, 0.0), &mut outbox)
+        .await;
+    handler
+        .handle_text(&state_frame(101.0, 0.5, 8.0, 3, 3.0), &mut outbox)
+        .await;
+    drop(handler);
+    writer.await;
+
+    let entries = read_journal(&config.path).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert!(entries[0].sent);
+    assert_eq!(
+        (entries[0].position_before, entries[0].position_after),
+        (0, 3)
+    );
+    assert_eq!(entries[0].pnl_change, None);
+    assert!(!entries[1].sent);
+    assert_eq!(entries[1].pnl_change, Some(3.0));
+    let ids: Vec<Option<u64>> = entries.iter().map(|entry| entry.decision_id).collect();
+    assert_eq!(ids, [Some(1), Some(2)]);
+    assert!(entries.iter().all(|entry| entry.driver.is_some()));
+}
+
+#[test]
+fn analyze_totals_per_connection() {
+    let entries = vec![
+        entry(0, 0.5, 0, 3, 0.0),
+        entry(0, -0.5, 3, -3, 4.0),
+        entry(0, 0.2, -3, -3, 1.0),
+        entry(1, 0.1, 0, 0, 0.0),
+    ];
+    let stats = analyze(&entries);
+
+    let first = &stats[&(String::new(), 0)];
+    assert_eq!(first.decisions, 3);
+    assert_eq!(first.trades_sent, 2);
+    assert_eq!(first.volume_sent, 9);
+    assert_eq!(first.total_pnl_change, 5.0);
+    assert_eq!(first.last_pnl, Some(1.0));
+    assert!((first.mean_abs_signal - 0.4).abs() < 1e-9);
+
+    assert_eq!(stats[&(String::new(), 1)].trades_sent, 0);
+}
+
+#[test]
+fn accounts_are_told_apart() {
+    let mut other = entry(0, 0.5, 0, 3, 0.0);
+    other.account = "second".to_string();
+    let row = other.to_csv_row();
+    assert!(row.ends_with(",follow,second,,,"));
+    assert_eq!(JournalEntry::from_csv_row(&row).unwrap(), other);
+
+    let stats = analyze(&[entry(0, 0.5, 0, 3, 0.0), other]);
+    assert_eq!(stats.len(), 2);
+    assert_eq!(stats[&("second".to_string(), 0)].trades_sent, 1);
+}
+
+#[optiva_ws::rt::test]
+async fn kill_switch_still_journals_what_would_have_traded() {
+    let config = journal_config("journal-halted", "journal.jsonl", JournalFormat::Jsonl);
+    let (journal, writer) = Journal::spawn(&config).await.unwrap();
+
+    let mut bot_config = test_config();
+    bot_config.trading_enabled = false;
+    let bot_config = Arc::new(bot_config);
+    let state = Arc::new(SharedState::new(&bot_config));
+    let mut handler = ConnectionHandler::new(0, bot_config, state).with_journal(journal);
+    common::join(&mut handler).await;
+
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&state_frame(100.0, 0.5, 8.0, 0, 0.0), &mut outbox)
+        .await;
+    drop(handler);
+    writer.await;
+
+    assert!(outbox.is_empty());
+    let entries = read_journal(&config.path).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].volume, 3);
+    assert!(!entries[0].sent);
+}
@@ -0,0 +1,77 @@
+use optiva_ws::config::{Config, ConfigError};
+use optiva_ws::latency::{DecisionLatency, LatencySummary, LATENCY_WINDOW};
+use optiva_ws::state::ConnectionPerformance;
+use optiva_ws::summary::GameAccumulator;
+use std::time::Duration;
+
+#[test]
+fn percentiles_are_nearest_rank() {
+    assert_eq!(LatencySummary::of([]), None);
+    let summary = LatencySummary::of((1..=100).rev().map(f64::from)).unwrap();
+    assert_eq!(summary.samples, 100);
+    assert_eq!(summary.p50_ms, 50.0);
+    assert_eq!(summary.p95_ms, 95.0);
+    assert_eq!(summary.max_ms, 100.0);
+
+    let one = LatencySummary::of([3.0]).unwrap();
+    assert_eq!((one.p50_ms, one.p95_ms, one.max_ms), (3.0, 3.0, 3.0));
+}
+
+#[test]
+fn the_window_rolls_and_the_game_starts_over() {
+    let mut latency = DecisionLatency::default();
+    for ms in 0..LATENCY_WINDOW as u64 + 10 {
+        latency.record(Duration::from_millis(ms));
+    }
+    let recent = latency.recent().unwrap();
+    assert_eq!(recent.samples, LATENCY_WINDOW);
+    assert_eq!(recent.max_ms, (LATENCY_WINDOW + 9) as f64);
+    assert_eq!(latency.game().unwrap().samples, LATENCY_WINDOW + 10);
+
+    latency.start_game();
+    assert_eq!(latency.game(), None);
+    latency.record(Duration::from_micros(2500));
+    assert_eq!(latency.game().unwrap().max_ms, 2.5);
+    assert_eq!(latency.recent().unwrap().samples, LATENCY_WINDOW);
+}
+
+#[test]
+fn game_summaries_report_the_games_latency() {
+    let mut perf = ConnectionPerformance::default();
+    let game = GameAccumulator::start(0.0, &perf);
+    let summary = game.finish(0, "blend", 60.0, Some(1.0), &perf, Default::default());
+    assert_eq!(summary.latency, None);
+    assert!(!summary.to_string().contains("Latency"));
+
+    for ms in [4, 8, 30] {
+        perf.latency.record(Duration::from_millis(ms));
+    }
+    let summary = game.finish(0, "blend", 60.0, Some(1.0), &perf, Default::default());
+    let latency = summary.latency.unwrap();
+    assert_eq!((latency.p50_ms, latency.max_ms), (8.0, 30.0));
+    assert!(summary
+        .to_string()
+        .contains("p50 8.0ms, p95 30.0ms, max 30.0ms over 3 trades"));
+}
+
+#[test]
+fn the_budget_must_be_positive() {
+    let parse = |budget: &str| {
+        Config::parse(&format!(
+            r#"
+            url = "wss://example.com"
+            player_id = "abc"
+
+            [latency]
+            budget_ms = {}
+            "#,
+            budget
+        ))
+    };
+    assert_eq!(
+        parse("5.0").unwrap().latency.budget(),
+        Duration::from_millis(5)
+    );
+    assert!(matches!(parse("0.0"), Err(ConfigError::Invalid(_))));
+    assert!(matches!(parse("nan"), Err(ConfigError::Invalid(_))));
+}
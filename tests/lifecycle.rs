@@ -0,0 +1,159 @@
+use optiva_ws::clock::Monotonic;
+use optiva_ws::lifecycle::{HandshakeConfig, Lifecycle, LifecycleEvent, SessionLifecycle};
+use optiva_ws::protocol::{ClientEvent, Token};
+
+const PLAYER_ID: &str = "player";
+
+fn lifecycle() -> SessionLifecycle {
+    SessionLifecycle::new("alias", PLAYER_ID, HandshakeConfig::default())
+}
+
+fn open(lifecycle: &mut SessionLifecycle, now: f64) -> Vec<ClientEvent> {
+    lifecycle
+        .handle(LifecycleEvent::Opened {
+            token: Token::default(),
+            now: Monotonic(now),
+        })
+        .1
+}
+
+fn ack(lifecycle: &mut SessionLifecycle, player_id: &str, now: f64) -> Vec<ClientEvent> {
+    lifecycle
+        .handle(LifecycleEvent::Ack {
+            player_id,
+            now: Monotonic(now),
+        })
+        .1
+}
+
+fn tick(lifecycle: &mut SessionLifecycle, now: f64) -> Lifecycle {
+    lifecycle
+        .handle(LifecycleEvent::Tick {
+            now: Monotonic(now),
+        })
+        .0
+}
+
+#[test]
+fn the_handshake_runs_through_to_the_finish() {
+    let mut lifecycle = lifecycle();
+    assert_eq!(lifecycle.state(), Lifecycle::Connecting);
+
+    let sent = open(&mut lifecycle, 0.0);
+    assert!(matches!(&sent[..], [ClientEvent::Connection(data)] if data.alias == "alias"));
+    assert_eq!(
+        lifecycle.state(),
+        Lifecycle::AwaitingAck {
+            since: Monotonic(0.0)
+        }
+    );
+
+    let sent = ack(&mut lifecycle, PLAYER_ID, 1.0);
+    assert!(matches!(&sent[..], [ClientEvent::Start(data)] if data.player_id == PLAYER_ID));
+    assert_eq!(
+        lifecycle.state(),
+        Lifecycle::Starting {
+            since: Monotonic(1.0)
+        }
+    );
+
+    assert_eq!(
+        lifecycle.handle(LifecycleEvent::Game),
+        (Lifecycle::InGame, Vec::new())
+    );
+    assert_eq!(
+        lifecycle.handle(LifecycleEvent::Game),
+        (Lifecycle::InGame, Vec::new())
+    );
+    assert_eq!(
+        lifecycle.handle(LifecycleEvent::Finish),
+        (Lifecycle::Finished, Vec::new())
+    );
+}
+
+#[test]
+fn game_traffic_before_the_ack_is_turned_away() {
+    let mut lifecycle = lifecycle();
+    let (state, sent) = lifecycle.handle(LifecycleEvent::Game);
+    assert_eq!(state, Lifecycle::Connecting);
+    assert!(sent.is_empty());
+
+    open(&mut lifecycle, 0.0);
+    let (state, _) = lifecycle.handle(LifecycleEvent::Game);
+    assert!(matches!(state, Lifecycle::AwaitingAck { .. }));
+    let (state, _) = lifecycle.handle(LifecycleEvent::Finish);
+    assert!(matches!(state, Lifecycle::AwaitingAck { .. }));
+
+    // The ack still goes through afterwards
+    assert_eq!(ack(&mut lifecycle, PLAYER_ID, 1.0).len(), 1);
+}
+
+#[test]
+fn acks_out_of_turn_or_for_others_send_nothing() {
+    let mut lifecycle = lifecycle();
+    assert!(ack(&mut lifecycle, PLAYER_ID, 0.0).is_empty());
+    assert_eq!(lifecycle.state(), Lifecycle::Connecting);
+
+    open(&mut lifecycle, 0.0);
+    assert!(ack(&mut lifecycle, "someone-else", 0.5).is_empty());
+    assert!(matches!(lifecycle.state(), Lifecycle::AwaitingAck { .. }));
+
+    ack(&mut lifecycle, PLAYER_ID, 1.0);
+    lifecycle.handle(LifecycleEvent::Game);
+    // A second ack mid-game doesn't start the game again
+    assert!(ack(&mut lifecycle, PLAYER_ID, 2.0).is_empty());
+    assert_eq!(lifecycle.state(), Lifecycle::InGame);
+}
+
+#[test]
+fn a_missing_ack_times_out() {
+    let mut lifecycle = lifecycle();
+    open(&mut lifecycle, 100.0);
+    assert_eq!(lifecycle.deadline(), Some(Monotonic(110.0)));
+    assert!(matches!(
+        tick(&mut lifecycle, 109.9),
+        Lifecycle::AwaitingAck { .. }
+    ));
+    assert_eq!(tick(&mut lifecycle, 110.0), Lifecycle::Connecting);
+    assert_eq!(lifecycle.deadline(), None);
+}
+
+#[test]
+fn a_game_that_never_starts_times_out() {
+    let mut lifecycle = lifecycle();
+    open(&mut lifecycle, 0.0);
+    ack(&mut lifecycle, PLAYER_ID, 5.0);
+    assert_eq!(lifecycle.deadline(), Some(Monotonic(35.0)));
+    assert!(matches!(
+        tick(&mut lifecycle, 34.0),
+        Lifecycle::Starting { .. }
+    ));
+    assert_eq!(tick(&mut lifecycle, 35.0), Lifecycle::Connecting);
+}
+
+#[test]
+fn time_only_matters_during_the_handshake() {
+    let mut lifecycle = lifecycle();
+    open(&mut lifecycle, 0.0);
+    ack(&mut lifecycle, PLAYER_ID, 0.0);
+    lifecycle.handle(LifecycleEvent::Game);
+    assert_eq!(tick(&mut lifecycle, 1e6), Lifecycle::InGame);
+    lifecycle.handle(LifecycleEvent::Finish);
+    assert_eq!(tick(&mut lifecycle, 2e6), Lifecycle::Finished);
+}
+
+#[test]
+fn a_finished_session_takes_nothing_until_the_next_opens() {
+    let mut lifecycle = lifecycle();
+    open(&mut lifecycle, 0.0);
+    ack(&mut lifecycle, PLAYER_ID, 0.0);
+    lifecycle.handle(LifecycleEvent::Game);
+    lifecycle.handle(LifecycleEvent::Finish);
+
+    assert_eq!(
+        lifecycle.handle(LifecycleEvent::Game).0,
+        Lifecycle::Finished
+    );
+    assert_eq!(open(&mut lifecycle, 50.0).len(), 1);
+    assert!(matches!(lifecycle.state(), Lifecycle::AwaitingAck { .. }));
+}
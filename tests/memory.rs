@@ -0,0 +1,98 @@
+mod common;
+
+use common::{state_frame, test_config};
+use optiva_ws::caps::MAX_FINISHED_GAMES;
+use optiva_ws::curve::MAX_CURVE_POINTS;
+use optiva_ws::handler::ConnectionHandler;
+use optiva_ws::latency::{DecisionLatency, LATENCY_WINDOW, MAX_GAME_LATENCIES};
+use optiva_ws::rt::Mutex;
+use optiva_ws::state::{SharedState, RECENT_PNL_SIZE};
+use optiva_ws::summary::FinishedGames;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+
+const UPDATES: usize = 100_000;
+// State updates per game
+const GAME_LENGTH: usize = 40;
+
+// Hours of a session's state updates, and what's held in memory afterwards is no more
+// than the caps allow, with every entry dropped to get there counted
+#[optiva_ws::rt::test]
+async fn a_long_session_stays_within_every_cap() {
+    let mut config = test_config();
+    config.connections = 2;
+    config.history.size = 500;
+    config.forecast.window = 100;
+    let config = Arc::new(config);
+    let state = Arc::new(SharedState::new(&config));
+    let games: FinishedGames = Arc::new(Mutex::new(Vec::new()));
+    let mut handlers: Vec<ConnectionHandler> = (0..config.connections)
+        .map(|conn_id| {
+            ConnectionHandler::new(conn_id, Arc::clone(&config), Arc::clone(&state))
+                .with_finished_games(Arc::clone(&games))
+        })
+        .collect();
+    for handler in handlers.iter_mut() {
+        common::join(handler).await;
+    }
+
+    let finish = json!({ "event": "finish", "data": {} }).to_string();
+    let mut outbox = Vec::new();
+    for update in 0..UPDATES {
+        let handler = &mut handlers[update % 2];
+        let tick = update / 2;
+        if tick % GAME_LENGTH == GAME_LENGTH - 1 {
+            handler.handle_text(&finish, &mut outbox).await;
+            // The next game is on a fresh session
+            common::join(handler).await;
+        } else {
+            let wave = (tick as f64 / 7.0).sin();
+            let frame = state_frame(
+                100.0 + wave * 5.0,
+                100.0 + wave * 6.0,
+                wave,
+                (tick % 7) as i32 - 3,
+                wave * 20.0,
+            );
+            handler.handle_text(&frame, &mut outbox).await;
+        }
+        outbox.clear();
+    }
+
+    assert!(state.trade_history.lock().await.len() <= 500);
+    assert!(state.performance_history.lock().await.len() <= 500);
+    assert!(state.forecast_samples.lock().await.len() <= 100);
+    assert!(state.latest_signals.lock().await.len() <= config.connections);
+    {
+        let performances = state.connection_performance.lock().await;
+        assert!(performances.len() <= config.connections);
+        for perf in performances.values() {
+            assert!(perf.recent_pnl.len() <= RECENT_PNL_SIZE);
+            assert!(perf.curve.points().len() <= MAX_CURVE_POINTS);
+        }
+    }
+    let finished = games.lock().await.len();
+    assert_eq!(finished, MAX_FINISHED_GAMES);
+
+    let snapshot = state.snapshot().await;
+    let hits = snapshot.cap_hits;
+    assert!(hits.trade_history > 0);
+    assert!(hits.performance_history > 0);
+    assert!(hits.forecast_samples > 0);
+    assert_eq!(
+        hits.finished_games,
+        UPDATES / GAME_LENGTH - MAX_FINISHED_GAMES
+    );
+}
+
+#[test]
+fn a_game_keeps_only_its_latest_latencies() {
+    let mut latency = DecisionLatency::default();
+    let dropped = (0..MAX_GAME_LATENCIES + 10)
+        .filter(|_| latency.record(Duration::from_millis(1)))
+        .count();
+    assert_eq!(dropped, 10);
+    assert_eq!(latency.game().unwrap().samples, MAX_GAME_LATENCIES);
+    assert_eq!(latency.recent().unwrap().samples, LATENCY_WINDOW);
+}
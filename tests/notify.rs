@@ -0,0 +1,197 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use optiva_ws::notify::{
+    param_changes, Alert, AlertKind, AlertLimiter, Notifier, NotifyConfig, Outage, WebhookService,
+};
+use optiva_ws::rt;
+use optiva_ws::state::StrategyParams;
+
+fn breaker(conn_id: usize) -> Alert {
+    Alert::BreakerTripped {
+        conn_id,
+        pnl: -40.0,
+        drawdown: 55.5,
+    }
+}
+
+#[test]
+fn limiter_allows_one_alert_per_kind_per_interval() {
+    let mut limiter = AlertLimiter::default();
+    assert!(limiter.allow(AlertKind::ConnectionDown, 100.0, 30.0));
+    assert!(!limiter.allow(AlertKind::ConnectionDown, 110.0, 30.0));
+    // Other kinds have their own window
+    assert!(limiter.allow(AlertKind::GameFinished, 110.0, 30.0));
+    assert!(limiter.allow(AlertKind::ConnectionDown, 130.0, 30.0));
+}
+
+#[test]
+fn outage_is_reported_once_past_the_threshold() {
+    let mut outage = Outage::default();
+    assert_eq!(outage.due(100.0, 60.0), None);
+
+    outage.down(100.0);
+    outage.down(130.0);
+    assert_eq!(outage.due(150.0, 60.0), None);
+    assert_eq!(outage.due(170.0, 60.0), Some(70.0));
+    assert_eq!(outage.due(200.0, 60.0), None);
+
+    // Coming back up starts the next outage afresh
+    outage.up();
+    outage.down(300.0);
+    assert_eq!(outage.due(361.0, 60.0), Some(61.0));
+}
+
+#[test]
+fn messages_carry_the_details() {
+    let finished = Alert::GameFinished {
+        conn_id: 2,
+        strategy: "blend".to_string(),
+        pnl: Some(123.456),
+        trades: 17,
+        simulated: true,
+    };
+    assert_eq!(finished.kind(), AlertKind::GameFinished);
+    assert_eq!(
+        finished.to_string(),
+        "Game finished on connection 2 (blend): final PnL $123.46, 17 trades (simulated)"
+    );
+    assert!(breaker(1).to_string().contains("down $55.50 from peak"));
+    assert_eq!(
+        Alert::ConnectionDown {
+            conn_id: 0,
+            secs: 75.2
+        }
+        .to_string(),
+        "Connection 0 has been down for 75s and is still reconnecting"
+    );
+}
+
+#[test]
+fn only_tuned_params_that_moved_are_reported() {
+    let before = StrategyParams::default();
+    let mut after = before.clone();
+    after.momentum_weight = before.momentum_weight * 1.5;
+    after.deadband = before.deadband + 1.0;
+
+    let changes = param_changes(&before, &after);
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].name, "momentum_weight");
+    assert!((changes[0].relative() - 0.5).abs() < 1e-9);
+    assert!(param_changes(&before, &before).is_empty());
+}
+
+#[test]
+fn payload_matches_the_service() {
+    assert_eq!(
+        WebhookService::for_url("https://discord.com/api/webhooks/1/abc"),
+        WebhookService::Discord
+    );
+    assert_eq!(
+        WebhookService::for_url("https://hooks.slack.com/services/T/B/X"),
+        WebhookService::Slack
+    );
+    assert_eq!(
+        WebhookService::Discord.payload("hi"),
+        serde_json::json!({ "content": "hi" })
+    );
+    assert_eq!(
+        WebhookService::Slack.payload("hi"),
+        serde_json::json!({ "text": "hi" })
+    );
+}
+
+#[test]
+fn no_webhook_means_no_notifier() {
+    assert!(Notifier::spawn(&NotifyConfig::default()).is_none());
+}
+
+// Accepts webhook posts and passes each request body on
+fn webhook_server() -> (String, mpsc::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let (sender, bodies) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end().to_ascii_lowercase();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some(value) = line.strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            // Passed on before replying, so it's in by the time the post returns
+            let _ = sender.send(String::from_utf8(body).unwrap());
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .unwrap();
+        }
+    });
+    (url, bodies)
+}
+
+#[optiva_ws::rt::test]
+async fn notifier_posts_and_rate_limits_each_kind() {
+    let (url, bodies) = webhook_server();
+    let config = NotifyConfig {
+        webhook_url: Some(url),
+        ..NotifyConfig::default()
+    };
+    let (notifier, task) = Notifier::spawn(&config).unwrap();
+    notifier.notify(breaker(0));
+    // Same kind inside the window, so dropped
+    notifier.notify(breaker(1));
+    notifier.notify(Alert::ConnectionDown {
+        conn_id: 3,
+        secs: 61.0,
+    });
+    drop(notifier);
+    task.await;
+
+    let bodies: Vec<serde_json::Value> = bodies
+        .try_iter()
+        .map(|body| serde_json::from_str(&body).unwrap())
+        .collect();
+    assert_eq!(bodies.len(), 2);
+    assert!(bodies[0]["text"]
+        .as_str()
+        .unwrap()
+        .contains("breaker tripped on connection 0"));
+    assert!(bodies[1]["text"]
+        .as_str()
+        .unwrap()
+        .starts_with("Connection 3 has been down"));
+}
+
+#[optiva_ws::rt::test]
+async fn failed_deliveries_are_dropped() {
+    // Nothing listens on the port once the listener is gone
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let config = NotifyConfig {
+        webhook_url: Some(format!("http://127.0.0.1:{}/hook", port)),
+        timeout_secs: 1.0,
+        ..NotifyConfig::default()
+    };
+    let (notifier, task) = Notifier::spawn(&config).unwrap();
+    notifier.notify(breaker(0));
+    drop(notifier);
+    rt::timeout(Duration::from_secs(5), task)
+        .await
+        .expect("a failed delivery shouldn't hold up the notifier");
+}
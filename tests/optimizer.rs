@@ -0,0 +1,666 @@
+#![cfg(feature = "auto-optimize")]
+
+mod common;
+
+use std::sync::Arc;
+
+use common::server::{MockServer, Scenario};
+use optiva_ws::bandit::ArmConfig;
+use optiva_ws::clock::{ManualClock, Monotonic};
+use optiva_ws::connection::{handle_connection, Sinks};
+use optiva_ws::optimizer::{
+    adjust_params, bound_update, optimize_strategy, run_optimizer, MAX_AGGRESSIVE_FACTOR,
+    MAX_WEIGHT, MIN_AGGRESSIVE_FACTOR, MIN_WEIGHT,
+};
+use optiva_ws::persist::PersistConfig;
+use optiva_ws::rt;
+use optiva_ws::shutdown::Shutdown;
+use optiva_ws::state::{PerformanceData, SharedState, StrategyParams};
+use optiva_ws::strategy::{sharpe_ratio, Driver, OptimizerConfig};
+use optiva_ws::summary::{GameSummary, ParamDiff};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::time::Duration;
+
+fn state_at(time: f64) -> (Arc<ManualClock>, SharedState) {
+    let clock = Arc::new(ManualClock::new(time));
+    let state = SharedState::with_clock(&common::test_config(), clock.clone());
+    (clock, state)
+}
+
+async fn record_window(state: &SharedState, pnl_changes: &[f64], momentum: f64, forecast: f64) {
+    for &pnl_change in pnl_changes {
+        state
+            .record_performance(PerformanceData {
+                timestamp: state.monotonic(),
+                ..perf(pnl_change, momentum, forecast)
+            })
+            .await;
+    }
+}
+
+fn perf(pnl_change: f64, momentum: f64, forecast: f64) -> PerformanceData {
+    PerformanceData {
+        conn_id: 0,
+        timestamp: Monotonic(0.0),
+        momentum,
+        forecast,
+        position: 1,
+        trade_volume: 1,
+        pnl_change,
+        price: 100.0,
+        total_pnl: 0.0,
+        signal: 0.0,
+        decision_id: 0,
+        driver: Driver::Momentum,
+    }
+}
+
+#[optiva_ws::rt::test]
+async fn optimizer_waits_for_interval() {
+    let (clock, state) = state_at(1000.0);
+    record_window(&state, &[-18.0, -22.0, -20.0, -18.0, -22.0], 1.0, 1.0).await;
+
+    clock.advance(state.optimization_interval - 1.0);
+    optimize_strategy(&state).await;
+    assert_eq!(state.strategy_params.read().await.momentum_weight, 0.6);
+
+    clock.advance(1.0);
+    optimize_strategy(&state).await;
+    assert_eq!(state.strategy_params.read().await.momentum_weight, 0.5);
+}
+
+#[optiva_ws::rt::test]
+async fn optimizer_needs_enough_samples() {
+    let (clock, state) = state_at(1000.0);
+    record_window(&state, &[-18.0, -22.0, -20.0, -18.0], 1.0, 1.0).await;
+
+    clock.advance(state.optimization_interval);
+    optimize_strategy(&state).await;
+    assert_eq!(state.strategy_params.read().await.momentum_weight, 0.6);
+}
+
+#[optiva_ws::rt::test]
+async fn losing_strategy_resets_weights() {
+    let (clock, state) = state_at(1000.0);
+    // Mean -10, std dev ~1.4
+    record_window(&state, &[-8.0, -12.0, -10.0, -8.0, -12.0], 1.0, 1.0).await;
+
+    clock.advance(state.optimization_interval);
+    optimize_strategy(&state).await;
+
+    let params = state.strategy_params.read().await;
+    assert_eq!(params.momentum_weight, 0.5);
+    assert_eq!(params.forecast_weight, 0.5);
+    assert!((params.aggressive_factor - 1.3).abs() < 1e-9);
+}
+
+// How far the price moved at each step of a window
+const MOVES: [f64; 6] = [3.0, -1.0, 2.0, -3.0, 1.0, -2.0];
+// Signs with no correlation to MOVES at all
+const NOISE: [f64; 6] = [1.0, 1.0, -1.0, -1.0, -1.0, 1.0];
+
+// The PnL changes from MOVES, held alternately long and short, after decisions on
+// these inputs. The moves alternate too, so every change is a win.
+fn window(
+    momentum: impl Fn(usize) -> f64,
+    forecast: impl Fn(usize) -> f64,
+) -> Vec<PerformanceData> {
+    (0..MOVES.len())
+        .map(|i| {
+            let position = if i % 2 == 0 { 2 } else { -2 };
+            PerformanceData {
+                position,
+                ..perf(
+                    MOVES[i] * f64::from(position.signum()),
+                    momentum(i),
+                    forecast(i),
+                )
+            }
+        })
+        .collect()
+}
+
+fn good_sharpe(window: &[PerformanceData]) -> f64 {
+    let changes: Vec<_> = window.iter().map(|p| p.pnl_change).collect();
+    let sharpe = sharpe_ratio(&changes).unwrap();
+    assert!(sharpe >= OptimizerConfig::default().good_sharpe);
+    sharpe
+}
+
+#[test]
+fn a_perfectly_predictive_momentum_takes_the_weight() {
+    let window = window(|i| MOVES[i] * 2.0, |i| NOISE[i] * 0.5);
+    let mut params = StrategyParams::default();
+    adjust_params(
+        &mut params,
+        &window,
+        good_sharpe(&window),
+        &OptimizerConfig::default(),
+    );
+    assert!(params.momentum_weight > 0.95, "{:?}", params);
+    assert!(params.forecast_weight > 0.0);
+    assert!((params.momentum_weight + params.forecast_weight - 1.0).abs() < 1e-9);
+    assert!((params.aggressive_factor - 1.6).abs() < 1e-9);
+}
+
+#[test]
+fn a_perfectly_predictive_forecast_takes_the_weight() {
+    let window = window(|i| NOISE[i] * 5.0, |i| MOVES[i] * 0.1);
+    let mut params = StrategyParams::default();
+    adjust_params(
+        &mut params,
+        &window,
+        good_sharpe(&window),
+        &OptimizerConfig::default(),
+    );
+    assert!(params.forecast_weight > 0.95, "{:?}", params);
+    assert!(params.momentum_weight > 0.0);
+}
+
+#[optiva_ws::rt::test]
+async fn the_optimizer_weighs_signals_by_what_they_called() {
+    let (clock, state) = state_at(1000.0);
+    for p in window(|i| MOVES[i] * 2.0, |i| -MOVES[i] * 0.1) {
+        state
+            .record_performance(PerformanceData {
+                timestamp: state.monotonic(),
+                ..p
+            })
+            .await;
+    }
+
+    clock.advance(state.optimization_interval);
+    optimize_strategy(&state).await;
+
+    // A forecast calling every move the wrong way would get only the floor, but one
+    // optimization only goes a step toward that
+    let params = state.strategy_params.read().await;
+    assert!((params.momentum_weight - 0.7).abs() < 1e-9, "{:?}", params);
+    assert!((params.forecast_weight - 0.3).abs() < 1e-9, "{:?}", params);
+}
+
+// The invariants every optimization keeps to, from params that kept to them
+fn assert_bounded(before: &StrategyParams, after: &StrategyParams, max_weight_step: f64) {
+    assert!(
+        (MIN_WEIGHT..=MAX_WEIGHT).contains(&after.momentum_weight),
+        "{:?}",
+        after
+    );
+    assert!(
+        (MIN_WEIGHT..=MAX_WEIGHT).contains(&after.forecast_weight),
+        "{:?}",
+        after
+    );
+    assert!((after.momentum_weight + after.forecast_weight - 1.0).abs() < 1e-9);
+    assert!(
+        (after.momentum_weight - before.momentum_weight).abs() <= max_weight_step + 1e-9,
+        "{:?} -> {:?}",
+        before,
+        after
+    );
+    assert!(
+        (MIN_AGGRESSIVE_FACTOR..=MAX_AGGRESSIVE_FACTOR).contains(&after.aggressive_factor),
+        "{:?}",
+        after
+    );
+}
+
+// Anything an update could come up with, sense or not
+fn arbitrary(rng: &mut StdRng) -> f64 {
+    match rng.gen_range(0..8) {
+        0 => f64::NAN,
+        1 => f64::INFINITY,
+        2 => f64::NEG_INFINITY,
+        3 => rng.gen_range(-1e6..1e6),
+        _ => rng.gen_range(-2.0..4.0),
+    }
+}
+
+#[test]
+fn no_update_gets_the_params_past_their_bounds() {
+    let mut rng = StdRng::seed_from_u64(104);
+    for _ in 0..10_000 {
+        let momentum_weight = rng.gen_range(MIN_WEIGHT..=MAX_WEIGHT);
+        let before = StrategyParams {
+            momentum_weight,
+            forecast_weight: 1.0 - momentum_weight,
+            aggressive_factor: rng.gen_range(MIN_AGGRESSIVE_FACTOR..=MAX_AGGRESSIVE_FACTOR),
+            ..StrategyParams::default()
+        };
+        let mut after = StrategyParams {
+            momentum_weight: arbitrary(&mut rng),
+            forecast_weight: arbitrary(&mut rng),
+            aggressive_factor: arbitrary(&mut rng),
+            ..before.clone()
+        };
+        let max_weight_step = rng.gen_range(0.01..=1.0);
+        bound_update(&before, &mut after, max_weight_step);
+        assert_bounded(&before, &after, max_weight_step);
+    }
+}
+
+#[test]
+fn a_bounded_update_goes_one_step_toward_what_was_asked() {
+    let before = StrategyParams::default();
+    let mut after = StrategyParams {
+        momentum_weight: 0.67,
+        forecast_weight: 0.33,
+        aggressive_factor: 5.0,
+        ..before.clone()
+    };
+    bound_update(&before, &mut after, 0.05);
+    assert!((after.momentum_weight - 0.65).abs() < 1e-9, "{:?}", after);
+    assert!((after.forecast_weight - 0.35).abs() < 1e-9, "{:?}", after);
+    assert_eq!(after.aggressive_factor, MAX_AGGRESSIVE_FACTOR);
+
+    // Weights that don't sum to 1 are taken as shares, and one gone negative as the floor
+    let mut after = StrategyParams {
+        momentum_weight: 1.2,
+        forecast_weight: -0.3,
+        ..before.clone()
+    };
+    bound_update(&before, &mut after, 1.0);
+    assert!((after.momentum_weight - 0.95).abs() < 1e-9, "{:?}", after);
+
+    // Nothing changed, nothing to hold back
+    let mut same = StrategyParams {
+        momentum_weight: 0.8,
+        forecast_weight: 0.3,
+        ..before.clone()
+    };
+    bound_update(&same.clone(), &mut same, 0.1);
+    assert_eq!((same.momentum_weight, same.forecast_weight), (0.8, 0.3));
+}
+
+#[optiva_ws::rt::test]
+async fn no_window_gets_the_params_past_their_bounds() {
+    let mut rng = StdRng::seed_from_u64(1040);
+    for _ in 0..30 {
+        let clock = Arc::new(ManualClock::new(1000.0));
+        let mut config = common::test_config();
+        config.optimizer.epsilon = rng.gen_range(0.0..=0.5);
+        config.optimizer.seed = Some(rng.gen());
+        config.optimizer.max_weight_step = rng.gen_range(0.01..=0.3);
+        let state = SharedState::with_clock(&config, clock.clone());
+        for _ in 0..10 {
+            for _ in 0..rng.gen_range(5..20) {
+                state
+                    .record_performance(PerformanceData {
+                        timestamp: state.monotonic(),
+                        position: rng.gen_range(-3..=3),
+                        ..perf(
+                            rng.gen_range(-50.0..50.0),
+                            rng.gen_range(-30.0..30.0),
+                            rng.gen_range(-3.0..3.0),
+                        )
+                    })
+                    .await;
+            }
+            let before = state.strategy_params.read().await.clone();
+            clock.advance(state.optimization_interval);
+            optimize_strategy(&state).await;
+            let after = state.strategy_params.read().await.clone();
+            assert_bounded(&before, &after, config.optimizer.max_weight_step);
+        }
+    }
+}
+
+fn no_persist() -> PersistConfig {
+    PersistConfig {
+        enabled: false,
+        ..PersistConfig::default()
+    }
+}
+
+#[optiva_ws::rt::test]
+async fn optimizer_task_runs_once_due_and_stops_on_shutdown() {
+    let (clock, state) = state_at(1000.0);
+    let state = Arc::new(state);
+    record_window(&state, &[-18.0, -22.0, -20.0, -18.0, -22.0], 1.0, 1.0).await;
+    clock.advance(state.optimization_interval);
+
+    let shutdown = Shutdown::new();
+    let optimizer = rt::spawn(run_optimizer(
+        Arc::clone(&state),
+        no_persist(),
+        None,
+        None,
+        shutdown.clone(),
+    ));
+    rt::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(state.strategy_params.read().await.momentum_weight, 0.5);
+
+    shutdown.trigger();
+    rt::timeout(Duration::from_millis(500), optimizer)
+        .await
+        .expect("optimizer should stop on shutdown");
+}
+
+#[optiva_ws::rt::test]
+async fn optimizer_task_waits_out_the_interval() {
+    let (_clock, state) = state_at(1000.0);
+    let state = Arc::new(state);
+    record_window(&state, &[-18.0, -22.0, -20.0, -18.0, -22.0], 1.0, 1.0).await;
+
+    let shutdown = Shutdown::new();
+    let optimizer = rt::spawn(run_optimizer(
+        Arc::clone(&state),
+        no_persist(),
+        None,
+        None,
+        shutdown.clone(),
+    ));
+    rt::sleep(Duration::from_millis(200)).await;
+    shutdown.trigger();
+    optimizer.await;
+    assert_eq!(state.strategy_params.read().await.momentum_weight, 0.6);
+}
+
+#[optiva_ws::rt::test]
+async fn optimizer_reacts_to_the_recent_regime() {
+    let clock = Arc::new(ManualClock::new(1000.0));
+    let mut config = common::test_config();
+    config.optimizer.half_life_secs = 10.0;
+    let state = SharedState::with_clock(&config, clock.clone());
+    record_window(&state, &[-18.0, -22.0, -20.0, -18.0, -22.0], 0.2, 1.0).await;
+    clock.advance(80.0);
+    record_window(&state, &[8.0, 12.0, 10.0, 8.0, 12.0], 8.0, 0.2).await;
+
+    clock.advance(state.optimization_interval);
+    optimize_strategy(&state).await;
+
+    // Unweighted the window loses money; decayed, the recent wins count
+    let params = state.strategy_params.read().await;
+    assert!(params.aggressive_factor > StrategyParams::default().aggressive_factor);
+}
+
+#[optiva_ws::rt::test]
+async fn optimizer_purges_history_past_the_max_age() {
+    let (clock, state) = state_at(1000.0);
+    record_window(&state, &[-18.0, -22.0, -20.0, -18.0, -22.0], 1.0, 1.0).await;
+    clock.advance(state.optimizer.max_age_secs + state.optimization_interval);
+    record_window(&state, &[-18.0, -22.0], 1.0, 1.0).await;
+
+    optimize_strategy(&state).await;
+    assert_eq!(state.performance_history.lock().await.len(), 2);
+    assert_eq!(
+        *state.strategy_params.read().await,
+        StrategyParams::default()
+    );
+}
+
+// Three rounds of a window the momentum called, then one change 1000 times the size
+// made on a position only the forecast called
+async fn window_with_an_outlier(outlier_mads: f64) -> StrategyParams {
+    let mut config = common::test_config();
+    config.optimizer.outlier_mads = outlier_mads;
+    let clock = Arc::new(ManualClock::new(1000.0));
+    let state = SharedState::with_clock(&config, clock.clone());
+    let windfall = PerformanceData {
+        position: 2,
+        ..perf(3000.0, -6.0, 0.3)
+    };
+    let rounds = (0..3).flat_map(|_| window(|i| MOVES[i] * 2.0, |i| NOISE[i] * 0.5));
+    for p in rounds.chain([windfall]) {
+        state
+            .record_performance(PerformanceData {
+                timestamp: state.monotonic(),
+                ..p
+            })
+            .await;
+    }
+
+    clock.advance(state.optimization_interval);
+    optimize_strategy(&state).await;
+    let params = state.strategy_params.read().await.clone();
+    params
+}
+
+#[optiva_ws::rt::test]
+async fn one_huge_pnl_change_doesnt_decide_the_weights() {
+    // Taken as it is, the windfall is all the Sharpe sees: the window reads as noise
+    // and what the momentum called is never learned
+    let unclamped = window_with_an_outlier(0.0).await;
+    assert_eq!(unclamped, StrategyParams::default());
+
+    let clamped = window_with_an_outlier(OptimizerConfig::default().outlier_mads).await;
+    assert!(
+        clamped.momentum_weight > StrategyParams::default().momentum_weight,
+        "{:?}",
+        clamped
+    );
+    assert!(clamped.aggressive_factor > StrategyParams::default().aggressive_factor);
+}
+
+#[optiva_ws::rt::test]
+async fn optimizer_skips_windows_without_variance() {
+    let (clock, state) = state_at(1000.0);
+    record_window(&state, &[0.0; 5], 1.0, 1.0).await;
+
+    clock.advance(state.optimization_interval);
+    optimize_strategy(&state).await;
+    assert_eq!(
+        *state.strategy_params.read().await,
+        StrategyParams::default()
+    );
+}
+
+#[test]
+fn high_variance_window_with_small_mean_is_not_good() {
+    // Mean +1 but swinging by 20 either way: Sharpe ~0.05
+    let window: Vec<_> = [21.0, -19.0, 21.0, -19.0, 1.0]
+        .iter()
+        .map(|&change| perf(change, 8.0, 0.2))
+        .collect();
+    let changes: Vec<_> = window.iter().map(|p| p.pnl_change).collect();
+    let sharpe = sharpe_ratio(&changes).unwrap();
+
+    let mut params = StrategyParams::default();
+    adjust_params(&mut params, &window, sharpe, &OptimizerConfig::default());
+    assert_eq!(params, StrategyParams::default());
+}
+
+#[test]
+fn sharpe_thresholds_are_configurable() {
+    let window: Vec<_> = [1.0, 3.0, 1.0, 3.0]
+        .iter()
+        .map(|&change| perf(change, 0.2, 1.0))
+        .collect();
+    // Mean 2, std dev ~1.15: Sharpe ~1.7
+    let strict = OptimizerConfig {
+        good_sharpe: 2.0,
+        bad_sharpe: -2.0,
+        ..OptimizerConfig::default()
+    };
+    let mut params = StrategyParams::default();
+    adjust_params(&mut params, &window, 1.7, &strict);
+    assert_eq!(params, StrategyParams::default());
+
+    // Signals that never varied say nothing about the weights
+    adjust_params(&mut params, &window, 1.7, &OptimizerConfig::default());
+    assert_eq!(
+        params.forecast_weight,
+        StrategyParams::default().forecast_weight
+    );
+    assert!((params.aggressive_factor - 1.6).abs() < 1e-9);
+}
+
+#[optiva_ws::rt::test]
+async fn losing_connection_gets_its_own_aggressive_factor_reduced() {
+    let (clock, state) = state_at(1000.0);
+    record_window(&state, &[1.0; 5], 1.0, 1.0).await;
+    {
+        let mut connections = state.connection_performance.lock().await;
+        let loser = connections.entry(1).or_default();
+        loser.evaluated_trades = 10;
+        loser.successful_trades = 3;
+        let winner = connections.entry(2).or_default();
+        winner.evaluated_trades = 10;
+        winner.successful_trades = 6;
+    }
+
+    clock.advance(state.optimization_interval);
+    optimize_strategy(&state).await;
+
+    assert_eq!(state.strategy_params.read().await.aggressive_factor, 1.5);
+    assert!((state.params_for(1).await.aggressive_factor - 1.3).abs() < 1e-9);
+    assert_eq!(state.params_for(2).await.aggressive_factor, 1.5);
+}
+
+#[optiva_ws::rt::test]
+async fn non_finite_performance_data_is_skipped() {
+    let (clock, state) = state_at(1000.0);
+    record_window(&state, &[-18.0, -22.0, -20.0, -18.0, -22.0], 1.0, 1.0).await;
+    state
+        .record_performance(PerformanceData {
+            timestamp: state.monotonic(),
+            ..perf(f64::NAN, 1.0, 1.0)
+        })
+        .await;
+    assert_eq!(state.performance_history.lock().await.len(), 5);
+
+    // Even one that got into the window some other way is left out
+    state
+        .performance_history
+        .lock()
+        .await
+        .push(PerformanceData {
+            timestamp: state.monotonic(),
+            ..perf(1.0, f64::INFINITY, 1.0)
+        });
+    clock.advance(state.optimization_interval);
+    assert!(optimize_strategy(&state).await);
+    let params = state.strategy_params.read().await;
+    assert_eq!(params.momentum_weight, 0.5);
+    assert!(params.forecast_weight.is_finite());
+}
+
+#[optiva_ws::rt::test]
+async fn with_bandit_arms_the_optimizer_redraws_instead_of_nudging() {
+    let mut config = common::test_config();
+    config.optimizer.seed = Some(2);
+    config.bandit.arms = vec![
+        ArmConfig {
+            name: "steady".to_string(),
+            aggressive_factor: Some(1.0),
+            ..ArmConfig::default()
+        },
+        ArmConfig {
+            name: "bold".to_string(),
+            aggressive_factor: Some(2.0),
+            ..ArmConfig::default()
+        },
+    ];
+    let clock = Arc::new(ManualClock::new(1000.0));
+    let state = SharedState::with_clock(&config, clock.clone());
+    state.params_for(0).await;
+    record_window(&state, &[-18.0, -22.0, -20.0, -18.0, -22.0], 1.0, 1.0).await;
+
+    clock.advance(state.optimization_interval);
+    assert!(optimize_strategy(&state).await);
+    // A losing window would have reset the weights without the arms
+    assert_eq!(state.strategy_params.read().await.momentum_weight, 0.6);
+    let arm = state.arm_name(0).await.unwrap();
+    let factor = state.params_for(0).await.aggressive_factor;
+    assert_eq!(factor, if arm == "steady" { 1.0 } else { 2.0 });
+    let posteriors = state.bandit.lock().await.posteriors();
+    assert_eq!(
+        posteriors.values().map(|stats| stats.count).sum::<usize>(),
+        5
+    );
+}
+
+// A state update partway through a short game, holding a full long that keeps losing
+fn losing_update(ticks_remaining: u32, position: i32, pnl: f64) -> serde_json::Value {
+    serde_json::json!({
+        "event": "state",
+        "data": {
+            "price": 100.0,
+            "price_forecast": 1.0,
+            "momentum": 10.0,
+            "position": position,
+            "position_limit": 3,
+            "pnl": pnl,
+            "ticks_remaining": ticks_remaining
+        }
+    })
+}
+
+async fn wait_for_updates(state: &SharedState, updates: u32) {
+    for _ in 0..500 {
+        let seen = state
+            .connection_performance
+            .lock()
+            .await
+            .get(&0)
+            .map(|perf| perf.session.updates_seen);
+        if seen == Some(updates) {
+            return;
+        }
+        rt::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("the bot never saw {} updates", updates);
+}
+
+#[optiva_ws::rt::test]
+async fn the_end_of_a_game_is_optimized_once_after_the_finish() {
+    let dir = common::temp_dir("freeze");
+    let mut config = common::test_config();
+    config.optimizer.freeze_fraction = 0.4;
+    config.summary.enabled = true;
+    config.summary.path = dir.join("summaries.jsonl");
+    config.curve.enabled = false;
+    let server = MockServer::bind().await;
+    config.url = server.url.clone();
+    let config = Arc::new(config);
+    let clock = Arc::new(ManualClock::new(1000.0));
+    let state = Arc::new(SharedState::with_clock(&config, clock.clone()));
+    let shutdown = Shutdown::new();
+    let task = rt::spawn(handle_connection(
+        0,
+        Arc::clone(&config),
+        Arc::clone(&state),
+        shutdown.clone(),
+        Sinks::default(),
+    ));
+
+    let mut connection = server.accept().await;
+    connection.play(&Scenario::load("strong_forecast")).await;
+    // Seven of ten updates in, past the last 40% of the game. Each is waited on, or a
+    // backlog of them would only be decided on the newest.
+    for (seen, (ticks_remaining, pnl)) in
+        (2..).zip((3..9).rev().zip([-1.0, -3.0, -3.5, -5.5, -6.0, -8.0]))
+    {
+        connection
+            .send(losing_update(ticks_remaining, 3, pnl))
+            .await;
+        wait_for_updates(&state, seen).await;
+    }
+    clock.advance(60.0);
+    assert!(!optimize_strategy(&state).await);
+    assert_eq!(state.strategy_params.read().await.momentum_weight, 0.6);
+
+    for (seen, (ticks_remaining, pnl)) in (8..).zip((0..3).rev().zip([-9.5, -11.0, -11.5])) {
+        connection
+            .send(losing_update(ticks_remaining, 3, pnl))
+            .await;
+        wait_for_updates(&state, seen).await;
+    }
+    connection
+        .send(serde_json::json!({ "event": "finish", "data": { "pnl": -11.5 } }))
+        .await;
+    connection.expect_close().await;
+    shutdown.trigger();
+    task.await;
+
+    let params = state.strategy_params.read().await.clone();
+    assert_eq!(params.momentum_weight, 0.5);
+    let line = std::fs::read_to_string(dir.join("summaries.jsonl")).unwrap();
+    let summary: GameSummary = serde_json::from_str(line.lines().next().unwrap()).unwrap();
+    assert!(summary.param_changes.contains(&ParamDiff {
+        param: "momentum_weight".to_string(),
+        before: 0.6,
+        after: 0.5,
+    }));
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use optiva_ws::handler::ConnectionHandler;
+use optiva_ws::paper::{CostConfig, PaperBook};
+use optiva_ws::state::SharedState;
+use serde_json::json;
+use std::sync::Arc;
+
+#[test]
+fn paper_book_needs_a_price_to_fill() {
+    let mut book = PaperBook::default();
+    assert!(!book.fill(3));
+    assert_eq!(book.position, 0);
+    assert_eq!(book.fills, 0);
+}
+
+#[test]
+fn paper_book_marks_pnl_to_the_last_price() {
+    let mut book = PaperBook::default();
+    book.mark(100.0);
+    assert!(book.fill(3));
+    assert_eq!(book.pnl(), 0.0);
+
+    book.mark(102.0);
+    assert_eq!(book.pnl(), 6.0);
+    assert!(book.fill(-5));
+    assert_eq!(book.position, -2);
+
+    book.mark(101.0);
+    // +3 bought at 100, 5 sold at 102, 2 short marked at 101
+    assert_eq!(book.pnl(), 8.0);
+    assert_eq!(book.fills, 2);
+
+    book.reset();
+    assert_eq!(book, PaperBook::default());
+}
+
+#[optiva_ws::rt::test]
+async fn dry_run_fills_on_the_paper_book_instead_of_trading() {
+    let mut config = common::test_config();
+    config.dry_run = true;
+    let config = Arc::new(config);
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&common::state_frame(100.0, 0.5, 8.0, 0, 0.0), &mut outbox)
+        .await;
+    assert!(outbox.is_empty());
+    let position = handler.paper_book().unwrap().position;
+    assert!(position > 0);
+
+    // The server never saw a trade, so its position and PnL stay at zero
+    handler
+        .handle_text(&common::state_frame(104.0, 0.5, 8.0, 0, 0.0), &mut outbox)
+        .await;
+    let perf = state.connection_performance.lock().await[&0].clone();
+    assert_eq!(perf.session.position, Some(position));
+    assert_eq!(perf.last_pnl, 4.0 * position as f64);
+
+    let finish = json!({ "event": "finish", "data": { "pnl": 0.0 } }).to_string();
+    handler.handle_text(&finish, &mut outbox).await;
+    let summary = handler.take_summary().unwrap();
+    assert!(summary.simulated);
+    assert_eq!(summary.final_pnl, Some(4.0 * position as f64));
+    assert!(outbox.is_empty());
+    assert_eq!(handler.paper_book(), Some(&PaperBook::default()));
+}
+
+#[optiva_ws::rt::test]
+async fn trade_costs_come_out_of_the_pnl_they_are_credited_with() {
+    let mut config = common::test_config();
+    config.dry_run = true;
+    config.costs = CostConfig {
+        fee_per_unit: 0.1,
+        half_spread: 0.15,
+    };
+    let config = Arc::new(config);
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&common::state_frame(100.0, 0.5, 8.0, 0, 0.0), &mut outbox)
+        .await;
+    assert_eq!(handler.paper_book().unwrap().position, 3);
+    handler
+        .handle_text(&common::state_frame(104.0, 0.5, 8.0, 0, 0.0), &mut outbox)
+        .await;
+    // Up 12 on the book, less 3 units at 0.25 each
+    let history: Vec<_> = state
+        .performance_history
+        .lock()
+        .await
+        .iter()
+        .cloned()
+        .collect();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].pnl_change, 11.25);
+    assert_eq!(history[0].total_pnl, 12.0);
+
+    let finish = json!({ "event": "finish", "data": { "pnl": 0.0 } }).to_string();
+    handler.handle_text(&finish, &mut outbox).await;
+    let summary = handler.take_summary().unwrap();
+    assert_eq!(summary.final_pnl, Some(12.0));
+    assert_eq!(summary.costs, 0.75);
+    assert_eq!(summary.attributed_pnl, Some(11.25));
+    assert!(summary
+        .to_string()
+        .contains("After costs:    $11.25 ($0.75"));
+}
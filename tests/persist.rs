@@ -0,0 +1,116 @@
+mod common;
+
+use optiva_ws::bandit::ArmStats;
+use optiva_ws::clock::{ManualClock, Monotonic};
+use optiva_ws::persist::{
+    load_params, restore_params, save_params, PersistConfig, SavedParams, WindowStats,
+};
+use optiva_ws::state::{PerformanceData, SharedState, Sizing, StrategyParams};
+use optiva_ws::strategy::Driver;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+fn saved(saved_at: f64) -> SavedParams {
+    SavedParams {
+        saved_at,
+        params: StrategyParams {
+            momentum_weight: 0.7,
+            forecast_weight: 0.3,
+            aggressive_factor: 1.9,
+            sizing: Sizing::Proportional,
+            max_volatility: Some(2.5),
+            ..StrategyParams::default()
+        },
+        stats: WindowStats {
+            samples: 12,
+            mean_pnl_change: 0.4,
+            sharpe: Some(0.8),
+        },
+        arms: BTreeMap::from([(
+            "steady".to_string(),
+            ArmStats {
+                count: 4,
+                mean: 0.5,
+                m2: 1.2,
+                wins: 3,
+            },
+        )]),
+    }
+}
+
+fn persist_config(name: &str) -> PersistConfig {
+    PersistConfig {
+        enabled: true,
+        path: common::temp_dir(name).join("params.json"),
+        max_age_secs: 100.0,
+    }
+}
+
+#[optiva_ws::rt::test]
+async fn saved_params_round_trip() {
+    let config = persist_config("persist-round-trip");
+    save_params(&config.path, &saved(1000.0)).await.unwrap();
+    assert_eq!(load_params(&config.path).unwrap(), saved(1000.0));
+    assert_eq!(restore_params(&config, 1050.0), Some(saved(1000.0)));
+}
+
+#[optiva_ws::rt::test]
+async fn stale_params_are_ignored() {
+    let config = persist_config("persist-stale");
+    save_params(&config.path, &saved(1000.0)).await.unwrap();
+    assert_eq!(restore_params(&config, 1101.0), None);
+}
+
+#[optiva_ws::rt::test]
+async fn params_out_of_bounds_are_ignored() {
+    let config = persist_config("persist-bounds");
+    let mut out_of_bounds = saved(1000.0);
+    out_of_bounds.params.aggressive_factor = 8.0;
+    save_params(&config.path, &out_of_bounds).await.unwrap();
+    assert_eq!(restore_params(&config, 1050.0), None);
+}
+
+#[test]
+fn missing_or_corrupt_files_fall_back_to_defaults() {
+    let config = persist_config("persist-corrupt");
+    assert_eq!(restore_params(&config, 0.0), None);
+
+    // A write cut off halfway
+    let json = serde_json::to_string(&saved(0.0)).unwrap();
+    std::fs::write(&config.path, &json[..json.len() / 2]).unwrap();
+    assert!(load_params(&config.path).is_err());
+    assert_eq!(restore_params(&config, 0.0), None);
+}
+
+#[optiva_ws::rt::test]
+async fn snapshot_records_the_window_behind_the_params() {
+    let config = common::test_config();
+    let state = SharedState::with_clock(&config, Arc::new(ManualClock::new(500.0)));
+    for pnl_change in [1.0, 3.0] {
+        state
+            .performance_history
+            .lock()
+            .await
+            .push(PerformanceData {
+                conn_id: 0,
+                timestamp: Monotonic(0.0),
+                momentum: 0.0,
+                forecast: 0.0,
+                position: 0,
+                trade_volume: 0,
+                pnl_change,
+                price: 100.0,
+                total_pnl: 0.0,
+                signal: 0.0,
+                decision_id: 0,
+                driver: Driver::Momentum,
+            });
+    }
+
+    let snapshot = SavedParams::snapshot(&state).await;
+    assert_eq!(snapshot.saved_at, 500.0);
+    assert_eq!(snapshot.params, config.strategy);
+    assert_eq!(snapshot.stats.samples, 2);
+    assert_eq!(snapshot.stats.mean_pnl_change, 2.0);
+    assert!(snapshot.stats.sharpe.is_some());
+}
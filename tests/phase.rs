@@ -0,0 +1,147 @@
+mod common;
+
+use common::trade_volumes;
+use optiva_ws::handler::ConnectionHandler;
+use optiva_ws::protocol::{ServerError, ServerEvent, StateUpdate};
+use optiva_ws::state::{GamePhase, SharedState};
+use serde_json::json;
+use std::sync::Arc;
+
+fn update(momentum: f64, position: i32, stage: Option<&str>) -> ServerEvent {
+    ServerEvent::State(StateUpdate {
+        price: 100.0,
+        price_forecast: 0.5,
+        momentum,
+        position,
+        position_limit: 3,
+        pnl: 0.0,
+        updates_remaining: None,
+        instrument: None,
+        stage: stage.map(str::to_string),
+        sequence: None,
+    })
+}
+
+async fn started() -> (Arc<SharedState>, ConnectionHandler) {
+    let config = Arc::new(common::test_config());
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+    (state, handler)
+}
+
+async fn phase(state: &SharedState) -> GamePhase {
+    state.snapshot().await.connections[0].phase
+}
+
+#[test]
+fn stages_are_read_from_the_words_servers_use() {
+    for (stage, phase) in [
+        ("TRADING", Some(GamePhase::Trading)),
+        ("active", Some(GamePhase::Trading)),
+        ("puzzle_stage", Some(GamePhase::Puzzle)),
+        ("waiting-for-players", Some(GamePhase::Lobby)),
+        ("not trading", Some(GamePhase::Lobby)),
+        ("game over", Some(GamePhase::Finished)),
+        ("intermission", None),
+    ] {
+        assert_eq!(GamePhase::from_stage(stage), phase, "{}", stage);
+    }
+}
+
+#[optiva_ws::rt::test]
+async fn trades_wait_for_a_trading_stage() {
+    let (state, mut handler) = started().await;
+    assert_eq!(phase(&state).await, GamePhase::Lobby);
+
+    let mut outbox = Vec::new();
+    handler
+        .handle_event(update(8.0, 0, Some("lobby")), &mut outbox)
+        .await;
+    assert!(trade_volumes(&outbox).is_empty());
+    assert_eq!(phase(&state).await, GamePhase::Lobby);
+    assert_eq!(state.snapshot().await.connections[0].held_out_of_phase, 1);
+
+    handler
+        .handle_event(update(8.0, 0, Some("trading")), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [3]);
+    assert_eq!(phase(&state).await, GamePhase::Trading);
+}
+
+#[optiva_ws::rt::test]
+async fn states_without_a_stage_mean_trading() {
+    let (state, mut handler) = started().await;
+    let mut outbox = Vec::new();
+    handler
+        .handle_event(update(8.0, 0, None), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [3]);
+    assert_eq!(phase(&state).await, GamePhase::Trading);
+
+    // The status field works too, and a stage we can't read holds trades back
+    let frame = json!({
+        "event": "state",
+        "data": {
+            "price": 100.0, "price_forecast": -0.5, "momentum": -8.0,
+            "position": 3, "pnl": 0.0, "status": "intermission"
+        }
+    })
+    .to_string();
+    let mut outbox = Vec::new();
+    handler.handle_text(&frame, &mut outbox).await;
+    assert!(trade_volumes(&outbox).is_empty());
+    assert_eq!(phase(&state).await, GamePhase::Unknown);
+}
+
+#[optiva_ws::rt::test]
+async fn a_rejected_trade_holds_off_until_the_next_state() {
+    let (state, mut handler) = started().await;
+    let mut outbox = Vec::new();
+    handler
+        .handle_event(update(8.0, 0, None), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [3]);
+
+    let error = ServerEvent::Error(ServerError {
+        message: "Not in trading stage".to_string(),
+    });
+    handler.handle_event(error, &mut outbox).await;
+    assert_eq!(phase(&state).await, GamePhase::Unknown);
+
+    // The next state update says where the game is, and trading carries on
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&common::state_frame(100.0, -0.5, -8.0, 0, 0.0), &mut outbox)
+        .await;
+    assert_eq!(phase(&state).await, GamePhase::Trading);
+    assert!(trade_volumes(&outbox).iter().all(|&volume| volume < 0));
+    assert!(!trade_volumes(&outbox).is_empty());
+}
+
+#[optiva_ws::rt::test]
+async fn puzzles_and_the_finish_move_the_phase_along() {
+    let (state, mut handler) = started().await;
+    let mut outbox = Vec::new();
+    handler
+        .handle_event(update(0.0, 0, None), &mut outbox)
+        .await;
+
+    let puzzle = json!({ "event": "puzzle", "data": {} }).to_string();
+    handler.handle_text(&puzzle, &mut outbox).await;
+    assert_eq!(phase(&state).await, GamePhase::Puzzle);
+    handler
+        .handle_event(update(0.0, 0, None), &mut outbox)
+        .await;
+    assert_eq!(phase(&state).await, GamePhase::Trading);
+
+    let finish = json!({ "event": "finish", "data": { "pnl": 1.0 } }).to_string();
+    handler.handle_text(&finish, &mut outbox).await;
+    assert_eq!(phase(&state).await, GamePhase::Finished);
+    let summary = handler.take_summary().unwrap();
+    assert_eq!(summary.phase, GamePhase::Trading);
+    assert!(summary.to_string().contains("trading at the finish"));
+
+    common::join(&mut handler).await;
+    assert_eq!(phase(&state).await, GamePhase::Lobby);
+}
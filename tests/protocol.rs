@@ -0,0 +1,366 @@
+use optiva_ws::protocol::{
+    outgoing, ClientEvent, ClientMessage, ConnectionData, FinishData, PuzzleData, ServerError,
+    ServerErrorKind, ServerEvent, Session, StateUpdate, Token,
+};
+use serde_json::{json, Value};
+
+#[test]
+fn parses_state_update() {
+    let text = json!({
+        "event": "state",
+        "data": {
+            "price": 101.5,
+            "price_forecast": 0.25,
+            "momentum": -3.0,
+            "position": 2,
+            "position_limit": 5,
+            "pnl": 12.0
+        }
+    })
+    .to_string();
+
+    assert_eq!(
+        ServerEvent::parse(&text).unwrap(),
+        ServerEvent::State(StateUpdate {
+            price: 101.5,
+            price_forecast: 0.25,
+            momentum: -3.0,
+            position: 2,
+            position_limit: 5,
+            pnl: 12.0,
+            updates_remaining: None,
+            instrument: None,
+            stage: None,
+            sequence: None,
+        })
+    );
+}
+
+#[test]
+fn malformed_state_reports_field() {
+    let text = json!({
+        "event": "state",
+        "data": { "price": "oops", "price_forecast": 0.1, "momentum": 1.0, "position": 0, "pnl": 0.0 }
+    })
+    .to_string();
+
+    let err = ServerEvent::parse(&text).unwrap_err();
+    assert_eq!(err.event.as_deref(), Some("state"));
+    assert_eq!(err.field, "data.price");
+}
+
+#[test]
+fn missing_state_field_is_named() {
+    let text = json!({
+        "event": "state",
+        "data": { "price": 100.0, "price_forecast": 0.1, "position": 0, "pnl": 0.0 }
+    })
+    .to_string();
+
+    let err = ServerEvent::parse(&text).unwrap_err();
+    assert!(err.message.contains("momentum"), "{}", err);
+}
+
+#[test]
+fn optional_payload_fields() {
+    let puzzle = ServerEvent::parse(r#"{"event":"puzzle","data":{}}"#).unwrap();
+    assert_eq!(
+        puzzle,
+        ServerEvent::Puzzle(PuzzleData {
+            impact: None,
+            stage: None,
+            question: None,
+            id: None,
+        })
+    );
+
+    let finish = ServerEvent::parse(r#"{"event":"finish","data":{"pnl":-4.5}}"#).unwrap();
+    assert_eq!(
+        finish,
+        ServerEvent::Finish(FinishData {
+            pnl: Some(-4.5),
+            ..FinishData::default()
+        })
+    );
+}
+
+#[test]
+fn finish_hints_when_the_next_game_starts() {
+    let hint =
+        |data: &str| match ServerEvent::parse(&format!(r#"{{"event":"finish","data":{}}}"#, data))
+            .unwrap()
+        {
+            ServerEvent::Finish(finish) => finish.next_game_after(1000.0),
+            other => panic!("expected finish, got {:?}", other),
+        };
+    assert_eq!(hint(r#"{"pnl":1.0}"#), None);
+    assert_eq!(hint(r#"{"next_game_in":2.5}"#), Some(2.5));
+    assert_eq!(hint(r#"{"restart_in":2.5}"#), Some(2.5));
+    assert_eq!(hint(r#"{"next_game_at":1004.0}"#), Some(4.0));
+    // Already started
+    assert_eq!(hint(r#"{"restart_at":990.0}"#), Some(0.0));
+}
+
+#[test]
+fn error_events_parse_as_text_or_object() {
+    let text = ServerEvent::parse(r#"{"event":"error","data":"volume exceeds limit"}"#).unwrap();
+    let object =
+        ServerEvent::parse(r#"{"event":"error","data":{"error":"volume exceeds limit"}}"#).unwrap();
+    let expected = ServerEvent::Error(ServerError {
+        message: "volume exceeds limit".to_string(),
+    });
+    assert_eq!(text, expected);
+    assert_eq!(object, expected);
+}
+
+#[test]
+fn server_errors_are_classified_by_message() {
+    let kind = |message: &str| {
+        ServerError {
+            message: message.to_string(),
+        }
+        .kind()
+    };
+    assert_eq!(kind("Volume exceeds limit"), ServerErrorKind::Volume);
+    assert_eq!(kind("Unknown player id"), ServerErrorKind::Auth);
+    assert_eq!(kind("Not in trading stage"), ServerErrorKind::Stage);
+    assert_eq!(kind("Something went wrong"), ServerErrorKind::Other);
+}
+
+#[test]
+fn unknown_events_are_caught() {
+    let event = ServerEvent::parse(r#"{"event":"leaderboard","data":{"rank":1}}"#).unwrap();
+    assert_eq!(event, ServerEvent::Unknown);
+}
+
+// Exactly what goes over the wire, field order included
+#[test]
+fn outgoing_messages_match_the_golden_json() {
+    assert_eq!(
+        outgoing::connection("Aegizz-0", "p1", "").to_json(),
+        r#"{"player_id":"","event":"connection","data":{"alias":"Aegizz-0","player_id":"p1","token":""}}"#
+    );
+    assert_eq!(
+        outgoing::start("p1").to_json(),
+        r#"{"player_id":"","event":"start","data":{"player_id":"p1"}}"#
+    );
+    assert_eq!(
+        outgoing::trade("p1", -3).to_json(),
+        r#"{"player_id":"p1","event":"trade","data":{"volume":-3}}"#
+    );
+    assert_eq!(
+        outgoing::trade("", 2).to_json(),
+        r#"{"player_id":"","event":"trade","data":{"volume":2}}"#
+    );
+    assert_eq!(
+        outgoing::skip().to_json(),
+        r#"{"player_id":"","event":"skip","data":{}}"#
+    );
+    assert_eq!(
+        outgoing::answer(json!(42), Some(json!("q7"))).to_json(),
+        r#"{"player_id":"","event":"answer","data":{"answer":42,"id":"q7"}}"#
+    );
+}
+
+#[test]
+fn outgoing_messages_take_what_they_send_as_parameters() {
+    let ClientEvent::Connection(data) = outgoing::connection("desk-7", "p2", "t0k").event else {
+        panic!("not a connection message");
+    };
+    assert_eq!(
+        data,
+        ConnectionData {
+            alias: "desk-7".to_string(),
+            player_id: "p2".to_string(),
+            token: Token::new("t0k"),
+        }
+    );
+    assert_eq!(outgoing::trade("p2", 1).player_id, "p2");
+}
+
+#[test]
+fn acks_carry_session_credentials_when_the_server_gives_them() {
+    let parse = |data| match ServerEvent::parse(
+        &json!({ "event": "connection", "data": data }).to_string(),
+    ) {
+        Ok(ServerEvent::Connection(ack)) => ack,
+        other => panic!("not an ack: {:?}", other),
+    };
+    assert_eq!(parse(json!({ "player_id": "p1" })).session(), None);
+    let session =
+        parse(json!({ "player_id": "p1", "token": "s3cret-token", "session_id": "g-42" }))
+            .session()
+            .unwrap();
+    assert_eq!(session.token.expose(), "s3cret-token");
+    assert_eq!(session.session_id.as_deref(), Some("g-42"));
+}
+
+fn session() -> Session {
+    Session {
+        token: Token::new("s3cret-token"),
+        session_id: Some("g-42".to_string()),
+    }
+}
+
+#[test]
+fn authenticated_messages_carry_the_session() {
+    assert_eq!(
+        outgoing::trade("p1", -3)
+            .authenticated(Some(&session()))
+            .to_json(),
+        r#"{"player_id":"p1","token":"s3cret-token","session_id":"g-42","event":"trade","data":{"volume":-3}}"#
+    );
+    assert_eq!(
+        outgoing::skip().authenticated(None).to_json(),
+        r#"{"player_id":"","event":"skip","data":{}}"#
+    );
+}
+
+#[test]
+fn tokens_are_masked_when_printed() {
+    let token = Token::new("s3cret-token");
+    assert_eq!(token.to_string(), "s3cr****");
+    assert_eq!(format!("{:?}", token), "Token(s3cr****)");
+    assert_eq!(Token::new("short").to_string(), "****");
+
+    let message =
+        outgoing::connection("bot-0", "p1", "s3cret-token").authenticated(Some(&session()));
+    assert!(!message.to_log_json().contains("s3cret-token"));
+    assert!(!format!("{:?}", message).contains("s3cret-token"));
+    assert!(message.to_json().contains("s3cret-token"));
+}
+
+#[test]
+fn client_messages_round_trip() {
+    for message in [
+        outgoing::connection("bot-0", "p1", ""),
+        outgoing::start("p1"),
+        outgoing::trade("p1", -2),
+        outgoing::skip(),
+        outgoing::start("p1").authenticated(Some(&session())),
+    ] {
+        let parsed: ClientMessage = serde_json::from_str(&message.to_json()).unwrap();
+        assert_eq!(parsed, message);
+    }
+}
+
+#[test]
+fn state_reads_ticks_remaining_when_sent() {
+    let text = json!({
+        "event": "state",
+        "data": { "price": 100.0, "price_forecast": 0.0, "momentum": 0.0, "position": 0, "pnl": 0.0, "ticks_remaining": 7 }
+    })
+    .to_string();
+    match ServerEvent::parse(&text).unwrap() {
+        ServerEvent::State(update) => assert_eq!(update.updates_remaining, Some(7)),
+        other => panic!("expected state, got {:?}", other),
+    }
+}
+
+#[test]
+fn instrument_is_read_from_the_state_and_sent_back_with_trades() {
+    let text = json!({
+        "event": "state",
+        "data": {
+            "price": 100.0, "price_forecast": 0.1, "momentum": 1.0,
+            "position": 0, "pnl": 0.0, "symbol": "OPT"
+        }
+    })
+    .to_string();
+    let ServerEvent::State(update) = ServerEvent::parse(&text).unwrap() else {
+        panic!("expected a state update");
+    };
+    assert_eq!(update.instrument.as_deref(), Some("OPT"));
+
+    assert_eq!(
+        outgoing::trade("p1", 2)
+            .for_instrument(Some("OPT"))
+            .to_json(),
+        r#"{"player_id":"p1","event":"trade","data":{"volume":2,"instrument":"OPT"}}"#
+    );
+    // Only trades carry one
+    assert_eq!(
+        outgoing::skip().for_instrument(Some("OPT")),
+        outgoing::skip()
+    );
+}
+
+#[test]
+fn a_state_is_fingerprinted_by_its_sequence_when_it_has_one() {
+    let parse = |changes: Value| {
+        let mut data = json!({
+            "price": 100.0, "price_forecast": 0.5, "momentum": 1.0, "position": 0, "pnl": 0.0
+        });
+        data.as_object_mut()
+            .unwrap()
+            .extend(changes.as_object().unwrap().clone());
+        match ServerEvent::parse(&json!({ "event": "state", "data": data }).to_string()).unwrap() {
+            ServerEvent::State(update) => update.fingerprint(),
+            other => panic!("not a state: {:?}", other),
+        }
+    };
+    assert_eq!(parse(json!({})), parse(json!({})));
+    assert_ne!(parse(json!({})), parse(json!({ "pnl": 0.5 })));
+    assert_ne!(parse(json!({})), parse(json!({ "updates_remaining": 9 })));
+
+    // Numbered, the same number is the same update whatever else changed
+    assert_eq!(
+        parse(json!({ "seq": 4 })),
+        parse(json!({ "seq": 4, "pnl": 0.5 }))
+    );
+    assert_ne!(parse(json!({ "tick": 4 })), parse(json!({ "tick": 5 })));
+}
+
+#[test]
+fn state_updates_with_impossible_values_are_invalid() {
+    let valid = StateUpdate {
+        price: 100.0,
+        price_forecast: 0.1,
+        momentum: 1.0,
+        position: -30,
+        position_limit: 3,
+        pnl: 0.0,
+        updates_remaining: None,
+        instrument: None,
+        stage: None,
+        sequence: None,
+    };
+    assert_eq!(valid.validate(), Ok(()));
+
+    let field = |update: StateUpdate| update.validate().unwrap_err().field;
+    assert_eq!(
+        field(StateUpdate {
+            momentum: f64::NAN,
+            ..valid.clone()
+        }),
+        "momentum"
+    );
+    assert_eq!(
+        field(StateUpdate {
+            pnl: f64::NEG_INFINITY,
+            ..valid.clone()
+        }),
+        "pnl"
+    );
+    assert_eq!(
+        field(StateUpdate {
+            price: 0.0,
+            ..valid.clone()
+        }),
+        "price"
+    );
+    assert_eq!(
+        field(StateUpdate {
+            position: 31,
+            ..valid.clone()
+        }),
+        "position"
+    );
+    assert_eq!(
+        field(StateUpdate {
+            position_limit: 0,
+            ..valid
+        }),
+        "position_limit"
+    );
+}
@@ -0,0 +1,99 @@
+#![cfg(feature = "puzzles")]
+
+use optiva_ws::protocol::PuzzleData;
+use optiva_ws::puzzle::{PuzzleTracker, PUZZLE_MAX_HOLD_UPDATES};
+use optiva_ws::strategy::{handle_puzzle_impact, PuzzleImpact};
+
+fn impact(direction: i32, magnitude: f64) -> PuzzleImpact {
+    PuzzleImpact {
+        direction,
+        magnitude,
+    }
+}
+
+#[test]
+fn puzzle_moves_to_the_limit_in_the_impact_direction() {
+    let mut tracker = PuzzleTracker::default();
+    assert_eq!(tracker.on_puzzle(impact(1, 2.0), -1, 3, Some(100.0)), 4);
+    assert!(tracker.is_holding());
+
+    let mut tracker = PuzzleTracker::default();
+    assert_eq!(tracker.on_puzzle(impact(-1, 0.5), 2, 3, Some(100.0)), -5);
+}
+
+#[test]
+fn puzzle_at_the_limit_does_not_trade() {
+    let mut tracker = PuzzleTracker::default();
+    assert_eq!(tracker.on_puzzle(impact(1, 2.0), 3, 3, Some(100.0)), 0);
+    assert!(!tracker.is_holding());
+    assert_eq!(tracker.on_state(105.0, 3, 3), None);
+}
+
+#[test]
+fn puzzle_follows_a_changed_limit() {
+    let mut tracker = PuzzleTracker::default();
+    assert_eq!(tracker.on_puzzle(impact(1, 4.0), 0, 5, Some(100.0)), 5);
+
+    // Limit shrinks before the move shows up, so trim to the new limit
+    assert_eq!(tracker.on_state(100.5, 5, 3), Some(-2));
+    // And grows again
+    assert_eq!(tracker.on_state(100.5, 3, 4), Some(1));
+    assert!(tracker.is_holding());
+}
+
+#[test]
+fn puzzle_unwinds_once_the_impact_is_realized() {
+    let mut tracker = PuzzleTracker::default();
+    tracker.on_puzzle(impact(-1, 2.0), 0, 3, Some(100.0));
+
+    assert_eq!(tracker.on_state(99.5, -3, 3), Some(0));
+    assert_eq!(tracker.on_state(98.9, -3, 3), Some(3));
+    assert!(!tracker.is_holding());
+    assert_eq!(tracker.on_state(98.0, 0, 3), None);
+}
+
+#[test]
+fn puzzle_unwinds_after_holding_too_long() {
+    let mut tracker = PuzzleTracker::default();
+    tracker.on_puzzle(impact(1, 2.0), 0, 3, None);
+
+    // Entry price is taken from the first update when the puzzle came before any
+    for _ in 1..PUZZLE_MAX_HOLD_UPDATES {
+        assert_eq!(tracker.on_state(100.0, 3, 3), Some(0));
+    }
+    assert_eq!(tracker.on_state(100.0, 3, 3), Some(-3));
+    assert!(!tracker.is_holding());
+}
+
+fn puzzle(impact: Option<f64>) -> PuzzleData {
+    PuzzleData {
+        impact,
+        stage: None,
+        question: None,
+        id: None,
+    }
+}
+
+#[test]
+fn puzzle_impact_direction() {
+    let up = handle_puzzle_impact(&puzzle(Some(2.0))).unwrap();
+    assert_eq!((up.direction, up.magnitude), (1, 2.0));
+    let down = handle_puzzle_impact(&puzzle(Some(-3.0))).unwrap();
+    assert_eq!((down.direction, down.magnitude), (-1, 3.0));
+}
+
+#[test]
+fn sub_dollar_puzzle_impact_still_trades() {
+    let up = handle_puzzle_impact(&puzzle(Some(0.5))).unwrap();
+    assert_eq!((up.direction, up.magnitude), (1, 0.5));
+    let down = handle_puzzle_impact(&puzzle(Some(-0.5))).unwrap();
+    assert_eq!((down.direction, down.magnitude), (-1, 0.5));
+}
+
+#[test]
+fn zero_missing_or_nan_puzzle_impact_does_not_trade() {
+    assert_eq!(handle_puzzle_impact(&puzzle(Some(0.0))), None);
+    assert_eq!(handle_puzzle_impact(&puzzle(None)), None);
+    assert_eq!(handle_puzzle_impact(&puzzle(Some(f64::NAN))), None);
+    assert_eq!(handle_puzzle_impact(&puzzle(Some(f64::INFINITY))), None);
+}
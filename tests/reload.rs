@@ -0,0 +1,196 @@
+mod common;
+
+use common::{state_frame, temp_dir, trade_volumes, PLAYER_ID};
+use optiva_ws::config::Config;
+use optiva_ws::handler::ConnectionHandler;
+use optiva_ws::reload::{Reloader, SettingChange};
+use optiva_ws::state::SharedState;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+// A config file with `extra` between the settings every test needs
+fn write_config(path: &Path, extra: &str) {
+    let text = format!(
+        "url = \"ws://localhost:9000\"\nplayer_id = \"{}\"\n{}\n\n[persist]\nenabled = false\n\n\
+         [warm_up]\nupdates = 0\n\n[duplicates]\nenabled = false\n",
+        PLAYER_ID, extra
+    );
+    std::fs::write(path, text).unwrap();
+}
+
+type Accounts = Vec<(Arc<Config>, Arc<SharedState>)>;
+
+// The running accounts, as main sets them up from the file
+fn start(name: &str, extra: &str) -> (PathBuf, Accounts) {
+    let path = temp_dir(name).join("bot.toml");
+    write_config(&path, extra);
+    let accounts = Config::load(&path)
+        .unwrap()
+        .accounts()
+        .into_iter()
+        .map(|config| {
+            let state = Arc::new(SharedState::new(&config));
+            (Arc::new(config), state)
+        })
+        .collect();
+    (path, accounts)
+}
+
+fn settings(changes: &[SettingChange]) -> Vec<&str> {
+    changes
+        .iter()
+        .map(|change| change.setting.as_str())
+        .collect()
+}
+
+#[optiva_ws::rt::test]
+async fn live_settings_apply_and_the_rest_need_a_restart() {
+    let (path, accounts) = start(
+        "reload-live",
+        "[strategy]\nmomentum_weight = 0.6\n\n[rate_limit]\nburst = 3\n",
+    );
+    let state = Arc::clone(&accounts[0].1);
+    let mut reloader = Reloader::new(&path, &accounts).unwrap();
+    // As if the optimizer had moved it since the start
+    state.strategy_params.write().await.forecast_weight = 0.3;
+
+    write_config(
+        &path,
+        "connections = 2\n\n[strategy]\nmomentum_weight = 0.7\n\n[rate_limit]\nburst = 5\n",
+    );
+    let reload = reloader.reload().await.unwrap();
+    assert_eq!(
+        settings(&reload.applied),
+        ["rate_limit.burst", "strategy.momentum_weight"]
+    );
+    assert_eq!(
+        reload.applied[0],
+        SettingChange {
+            setting: "rate_limit.burst".to_string(),
+            old: "3".to_string(),
+            new: "5".to_string(),
+        }
+    );
+    assert_eq!(settings(&reload.requires_restart), ["connections"]);
+    assert_eq!(reload.requires_restart[0].old, "unset");
+
+    // Only the param the file changed moves; the rest keep what was learned
+    let params = state.strategy_params.read().await.clone();
+    assert_eq!(params.momentum_weight, 0.7);
+    assert_eq!(params.forecast_weight, 0.3);
+    let running = state.reloaded_config.read().await.clone().unwrap();
+    assert_eq!(running.rate_limit.burst, 5);
+    assert_eq!(running.connections, accounts[0].0.connections);
+}
+
+#[optiva_ws::rt::test]
+async fn an_invalid_config_changes_nothing() {
+    let (path, accounts) = start("reload-invalid", "[rate_limit]\nburst = 3\n");
+    let state = Arc::clone(&accounts[0].1);
+    let mut reloader = Reloader::new(&path, &accounts).unwrap();
+    let params = state.strategy_params.read().await.clone();
+
+    write_config(
+        &path,
+        "[strategy]\nmomentum_weight = 0.9\n\n[rate_limit]\nburst = 0\n",
+    );
+    assert!(reloader.reload().await.is_err());
+    write_config(&path, "[rate_limit]\nburst = 3\nbogus = 1\n");
+    assert!(reloader.reload().await.is_err());
+    // Nor past the bounds the optimizer keeps the params to
+    write_config(
+        &path,
+        "[strategy]\nmomentum_weight = 1.0\nforecast_weight = 0.0\n\n[rate_limit]\nburst = 3\n",
+    );
+    assert!(reloader.reload().await.is_err());
+    assert_eq!(*state.strategy_params.read().await, params);
+    assert!(state.reloaded_config.read().await.is_none());
+
+    // Fixed, it's compared against the last config that applied
+    write_config(&path, "[rate_limit]\nburst = 4\n");
+    let reload = reloader.reload().await.unwrap();
+    assert_eq!(settings(&reload.applied), ["rate_limit.burst"]);
+    assert_eq!(reload.applied[0].old, "3");
+}
+
+fn touch(path: &Path, secs: u64) {
+    std::fs::File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(secs))
+        .unwrap();
+}
+
+#[optiva_ws::rt::test]
+async fn the_file_is_only_read_again_once_it_changes() {
+    let (path, accounts) = start("reload-check", "[risk]\nmax_drawdown = 40.0\n");
+    let mut reloader = Reloader::new(&path, &accounts).unwrap();
+    assert!(reloader.check().await.is_none());
+
+    write_config(&path, "[risk]\nmax_drawdown = 50.0\n");
+    touch(&path, 10);
+    let reload = reloader.check().await.unwrap().unwrap();
+    assert_eq!(settings(&reload.applied), ["risk.max_drawdown"]);
+    assert!(reloader.check().await.is_none());
+
+    // A broken file is reported once, not every poll
+    std::fs::write(&path, "not = [valid").unwrap();
+    touch(&path, 20);
+    assert!(reloader.check().await.unwrap().is_err());
+    assert!(reloader.check().await.is_none());
+}
+
+#[optiva_ws::rt::test]
+async fn the_log_filter_follows_log_level() {
+    let (path, accounts) = start("reload-log", "");
+    let set: Arc<Mutex<Vec<Option<String>>>> = Arc::default();
+    let seen = Arc::clone(&set);
+    let mut reloader = Reloader::new(&path, &accounts)
+        .unwrap()
+        .with_log_filter(Box::new(move |level| {
+            seen.lock().unwrap().push(level.map(String::from));
+            Ok(())
+        }));
+
+    write_config(&path, "log_level = \"debug\"\n");
+    reloader.reload().await.unwrap();
+    write_config(&path, "log_level = \"info,[\"\n");
+    assert!(reloader.reload().await.is_err());
+    write_config(&path, "");
+    reloader.reload().await.unwrap();
+    assert_eq!(*set.lock().unwrap(), [Some("debug".to_string()), None]);
+}
+
+#[optiva_ws::rt::test]
+async fn a_running_handler_picks_up_a_new_rate_limit() {
+    let (path, accounts) = start(
+        "reload-handler",
+        "[rate_limit]\nburst = 1\ninterval_secs = 1000.0\n",
+    );
+    let (config, state) = accounts[0].clone();
+    let mut reloader = Reloader::new(&path, &accounts).unwrap();
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&state_frame(100.0, 0.5, 8.0, 0, 0.0), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [3]);
+    // The bucket is empty, so the sell is held back
+    outbox.clear();
+    handler
+        .handle_text(&state_frame(100.0, -0.5, -8.0, 3, 0.0), &mut outbox)
+        .await;
+    assert!(trade_volumes(&outbox).is_empty());
+
+    write_config(&path, "[rate_limit]\nenabled = false\n");
+    reloader.reload().await.unwrap();
+    handler
+        .handle_text(&state_frame(100.0, -0.5, -8.0, 3, 0.0), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [-6]);
+}
@@ -0,0 +1,115 @@
+#![cfg_attr(not(feature = "puzzles"), allow(dead_code, unused_imports))]
+
+mod common;
+
+use common::{state_frame, temp_dir, test_config, PLAYER_ID};
+use optiva_ws::protocol::outgoing;
+use optiva_ws::replay::{replay, replay_file};
+use optiva_ws::transcript::{read_transcript, Direction, Transcript, TranscriptEntry};
+use serde_json::json;
+use std::sync::Arc;
+
+fn entry(timestamp: f64, conn_id: usize, direction: Direction, frame: String) -> TranscriptEntry {
+    TranscriptEntry {
+        timestamp,
+        account: String::new(),
+        conn_id,
+        direction,
+        frame,
+    }
+}
+
+fn hello(conn_id: usize) -> String {
+    outgoing::connection(&format!("bot-{}", conn_id), PLAYER_ID, "").to_json()
+}
+
+fn recorded_game() -> Vec<TranscriptEntry> {
+    let ack = json!({ "event": "connection", "data": { "player_id": PLAYER_ID } }).to_string();
+    let puzzle = json!({ "event": "puzzle", "data": { "impact": -2.0 } }).to_string();
+    let finish = json!({ "event": "finish", "data": { "pnl": 4.0 } }).to_string();
+
+    vec![
+        entry(100.0, 0, Direction::Out, hello(0)),
+        entry(100.1, 0, Direction::In, ack),
+        entry(
+            101.0,
+            0,
+            Direction::In,
+            state_frame(100.0, 0.5, 8.0, 0, 0.0),
+        ),
+        entry(
+            102.0,
+            0,
+            Direction::In,
+            state_frame(101.0, 0.5, 8.0, 3, 3.0),
+        ),
+        entry(103.0, 0, Direction::In, puzzle),
+        entry(
+            104.0,
+            0,
+            Direction::In,
+            state_frame(99.0, -0.5, -8.0, -3, 4.0),
+        ),
+        entry(105.0, 0, Direction::In, finish),
+        entry(100.5, 1, Direction::In, "not json".to_string()),
+    ]
+}
+
+#[cfg(feature = "puzzles")]
+#[optiva_ws::rt::test]
+async fn replay_reports_trades_the_strategy_would_make() {
+    let reports = replay(&recorded_game(), Arc::new(test_config())).await;
+
+    let report = &reports[&0];
+    assert_eq!(report.frames, 6);
+    // Buy from flat, flip to the short limit on the puzzle, then unwind once the
+    // $2 drop has played out
+    assert_eq!(report.trades, vec![(101.0, 3), (103.0, -6), (104.0, 3)]);
+
+    // Garbage frames are counted but produce nothing
+    assert_eq!(reports[&1].frames, 1);
+    assert!(reports[&1].trades.is_empty());
+}
+
+#[cfg(feature = "puzzles")]
+#[optiva_ws::rt::test]
+async fn replay_only_plays_the_configs_account() {
+    let mut entries = recorded_game();
+    for entry in &mut entries {
+        entry.account = "other".to_string();
+    }
+    let mut config = test_config();
+    config.account = "mine".to_string();
+    assert!(replay(&entries, Arc::new(config.clone())).await.is_empty());
+
+    config.account = "other".to_string();
+    let reports = replay(&entries, Arc::new(config)).await;
+    assert_eq!(reports[&0].trades.len(), 3);
+}
+
+#[cfg(feature = "puzzles")]
+#[optiva_ws::rt::test]
+async fn transcript_round_trips_through_file() {
+    let dir = temp_dir("transcript");
+    let transcript = Transcript::create(&dir, 1234.0).await.unwrap();
+    for entry in recorded_game() {
+        transcript.record(&entry).await;
+    }
+
+    let path = transcript.path().to_path_buf();
+    assert_eq!(path.file_name().unwrap(), "transcript-1234.jsonl");
+    assert_eq!(read_transcript(&path).unwrap(), recorded_game());
+
+    let reports = replay_file(&path, Arc::new(test_config())).await.unwrap();
+    assert_eq!(reports[&0].trades.len(), 3);
+}
+
+#[test]
+fn malformed_transcript_line_is_reported() {
+    let dir = temp_dir("bad-transcript");
+    let path = dir.join("bad.jsonl");
+    std::fs::write(&path, "{\"timestamp\": 1.0}\n").unwrap();
+
+    let err = read_transcript(&path).unwrap_err();
+    assert!(err.to_string().contains("line 1"), "{}", err);
+}
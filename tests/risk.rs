@@ -0,0 +1,1264 @@
+#![cfg_attr(not(feature = "puzzles"), allow(dead_code, unused_imports))]
+
+mod common;
+
+use common::{trade_volumes, trades};
+use optiva_ws::clock::{ManualClock, Monotonic};
+use optiva_ws::console::ManualOrder;
+use optiva_ws::handler::{ConnectionHandler, Receipt};
+use optiva_ws::protocol::{ClientEvent, ClientMessage, ServerEvent, StateUpdate};
+use optiva_ws::risk::{
+    clamp_to_limit, BreakerEvent, DrawdownBreaker, DuplicateConfig, DuplicateFilter, ExposureBook,
+    ExposureConfig, ExposureRequest, HoldConfig, HoldTimer, OutboundConfig, OutboundCounts,
+    OutboundKind, OutboundLimiter, Quarantine, QuarantineConfig, QuarantineEvent, QuarantineState,
+    RateLimitConfig, RiskConfig, TradeBudget, TradeBudgetConfig, TradeLimiter, WindDownConfig,
+};
+use optiva_ws::state::{others_profitable, ConnectionPerformance, Health, SharedState};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+fn dollars(max: f64) -> RiskConfig {
+    RiskConfig {
+        max_drawdown: Some(max),
+        cooldown_secs: 30.0,
+        ..RiskConfig::default()
+    }
+}
+
+#[test]
+fn disabled_by_default() {
+    let mut breaker = DrawdownBreaker::default();
+    let config = RiskConfig::default();
+    assert_eq!(breaker.update(&config, 0.0, 100.0), None);
+    assert_eq!(breaker.update(&config, 1.0, -1000.0), None);
+    assert!(!breaker.is_halted());
+}
+
+#[test]
+fn trips_on_dollar_drawdown_from_peak() {
+    let mut breaker = DrawdownBreaker::default();
+    let config = dollars(20.0);
+
+    assert_eq!(breaker.update(&config, 0.0, 10.0), None);
+    assert_eq!(breaker.update(&config, 1.0, 40.0), None);
+    assert_eq!(breaker.update(&config, 2.0, 25.0), None);
+    assert_eq!(
+        breaker.update(&config, 3.0, 15.0),
+        Some(BreakerEvent::Tripped {
+            peak: 40.0,
+            drawdown: 25.0
+        })
+    );
+    assert!(breaker.is_halted());
+    assert_eq!(breaker.trips, 1);
+}
+
+#[test]
+fn trips_on_percentage_drawdown() {
+    let mut breaker = DrawdownBreaker::default();
+    let config = RiskConfig {
+        max_drawdown_pct: Some(0.5),
+        ..RiskConfig::default()
+    };
+
+    breaker.update(&config, 0.0, 100.0);
+    assert_eq!(breaker.update(&config, 1.0, 60.0), None);
+    assert!(matches!(
+        breaker.update(&config, 2.0, 40.0),
+        Some(BreakerEvent::Tripped { .. })
+    ));
+}
+
+#[test]
+fn lifts_after_cooldown_with_fresh_peak() {
+    let mut breaker = DrawdownBreaker::default();
+    let config = dollars(20.0);
+
+    breaker.update(&config, 0.0, 50.0);
+    breaker.update(&config, 1.0, 20.0);
+    assert!(breaker.is_halted());
+
+    assert_eq!(breaker.update(&config, 20.0, 0.0), None);
+    assert_eq!(
+        breaker.update(&config, 31.0, 0.0),
+        Some(BreakerEvent::Lifted)
+    );
+    // Measured from the PnL at the time of lifting, not the old peak
+    assert_eq!(breaker.update(&config, 32.0, -10.0), None);
+    assert!(!breaker.is_halted());
+}
+
+#[test]
+fn a_halt_holds_or_flattens_the_decision() {
+    let mut breaker = DrawdownBreaker::default();
+    let config = dollars(5.0);
+    assert_eq!(breaker.check(&config, 0.0, 10.0, 2, 1), (1, None));
+    assert_eq!(
+        breaker.check(&config, 1.0, 0.0, 2, 1),
+        (
+            0,
+            Some(BreakerEvent::Tripped {
+                peak: 10.0,
+                drawdown: 10.0
+            })
+        )
+    );
+
+    let config = RiskConfig {
+        flatten_on_halt: true,
+        ..config
+    };
+    assert_eq!(breaker.check(&config, 2.0, 0.0, 2, 1), (-2, None));
+    assert_eq!(
+        breaker.check(&config, 40.0, 0.0, 0, 1),
+        (1, Some(BreakerEvent::Lifted))
+    );
+}
+
+#[test]
+fn reset_lifts_halt_for_next_game() {
+    let mut breaker = DrawdownBreaker::default();
+    let config = dollars(5.0);
+
+    breaker.update(&config, 0.0, 10.0);
+    breaker.update(&config, 1.0, 0.0);
+    assert_eq!(breaker.reset(), Some(BreakerEvent::Lifted));
+    assert_eq!(breaker.reset(), None);
+    assert_eq!(breaker.peak_pnl, None);
+}
+
+#[test]
+fn limiter_allows_a_burst_then_refills_over_time() {
+    let config = RateLimitConfig::default();
+    let mut limiter = TradeLimiter::default();
+
+    assert!(limiter.try_take(&config, 0.0));
+    assert!(limiter.try_take(&config, 0.0));
+    assert!(limiter.try_take(&config, 0.0));
+    assert!(!limiter.try_take(&config, 1.0));
+
+    // Half a token came back by t=1, the other half by t=2
+    assert!(limiter.try_take(&config, 2.0));
+    assert!(!limiter.try_take(&config, 2.0));
+
+    // A long quiet spell only refills up to the burst
+    for _ in 0..3 {
+        assert!(limiter.try_take(&config, 100.0));
+    }
+    assert!(!limiter.try_take(&config, 100.0));
+}
+
+#[test]
+fn disabled_limiter_never_binds() {
+    let config = RateLimitConfig {
+        enabled: false,
+        ..RateLimitConfig::default()
+    };
+    let mut limiter = TradeLimiter::default();
+    assert!((0..10).all(|_| limiter.try_take(&config, 0.0)));
+}
+
+// How many of `count` messages of one kind the limiter lets through
+fn burst(
+    limiter: &mut OutboundLimiter,
+    config: &OutboundConfig,
+    conn_id: usize,
+    kind: OutboundKind,
+    count: usize,
+    now: f64,
+) -> usize {
+    (0..count)
+        .filter(|_| limiter.admit(config, conn_id, kind, now))
+        .count()
+}
+
+#[test]
+fn a_burst_drops_duplicate_skips_first_and_trades_last() {
+    use OutboundKind::*;
+    let config = OutboundConfig {
+        max_per_minute: Some(20),
+    };
+    let mut limiter = OutboundLimiter::default();
+
+    // A connection stuck skipping: the first skip is fine, the repeats stop at half
+    // the ceiling
+    assert_eq!(burst(&mut limiter, &config, 0, Connection, 1, 0.0), 1);
+    assert_eq!(burst(&mut limiter, &config, 0, Start, 1, 0.0), 1);
+    assert_eq!(burst(&mut limiter, &config, 0, Skip, 20, 1.0), 8);
+    assert_eq!(limiter.total(1.0), 10);
+
+    // Everything else but trades stops short of the ceiling
+    assert_eq!(burst(&mut limiter, &config, 1, Connection, 1, 2.0), 1);
+    assert_eq!(burst(&mut limiter, &config, 1, Start, 1, 2.0), 1);
+    assert_eq!(burst(&mut limiter, &config, 1, Trade, 6, 2.0), 6);
+    assert_eq!(burst(&mut limiter, &config, 2, Connection, 1, 3.0), 0);
+    assert_eq!(burst(&mut limiter, &config, 2, Skip, 1, 3.0), 0);
+
+    // Trades get the last of it
+    assert_eq!(burst(&mut limiter, &config, 2, Trade, 5, 3.0), 2);
+    assert_eq!(limiter.total(3.0), 20);
+    assert_eq!(
+        limiter.counts(0, 3.0),
+        OutboundCounts {
+            connection: 1,
+            start: 1,
+            trade: 0,
+            skip: 8,
+            answer: 0,
+        }
+    );
+    assert_eq!(limiter.counts(2, 3.0).total(), 2);
+
+    // A minute on, the early messages have aged out
+    assert_eq!(limiter.total(61.0), 10);
+    assert_eq!(burst(&mut limiter, &config, 2, Connection, 1, 61.0), 1);
+    assert_eq!(limiter.counts(0, 61.5), OutboundCounts::default());
+}
+
+#[test]
+fn no_outbound_ceiling_never_drops() {
+    let config = OutboundConfig {
+        max_per_minute: None,
+    };
+    let mut limiter = OutboundLimiter::default();
+    assert_eq!(
+        burst(&mut limiter, &config, 0, OutboundKind::Skip, 1000, 0.0),
+        1000
+    );
+    assert_eq!(limiter.counts(0, 0.0).skip, 1000);
+}
+
+#[cfg(feature = "puzzles")]
+#[optiva_ws::rt::test]
+async fn handler_holds_back_trades_beyond_the_rate_limit() {
+    let config = Arc::new(common::test_config());
+    let clock = Arc::new(ManualClock::new(0.0));
+    let state = Arc::new(SharedState::with_clock(&config, clock.clone()));
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+
+    // A strong signal that flips every update wants to trade every time
+    let mut outbox = Vec::new();
+    let mut position = 0;
+    for tick in 0..6 {
+        let sign = if tick % 2 == 0 { 1.0 } else { -1.0 };
+        let frame = common::state_frame(100.0, sign, sign * 10.0, position, 0.0);
+        let before = outbox.len();
+        handler.handle_text(&frame, &mut outbox).await;
+        if let Some(ClientEvent::Trade(trade)) = outbox.get(before).map(|m| m.event.clone()) {
+            position += trade.volume;
+        }
+        if tick == 3 {
+            clock.advance(2.0);
+        }
+    }
+
+    // Three from the burst, the fourth held back, then the fifth wanted is already
+    // the held position and the sixth uses the token refilled after two seconds
+    assert_eq!(trades(&outbox), 4);
+    let perf = state.connection_performance.lock().await[&0].clone();
+    assert_eq!(perf.rate_limited, 1);
+
+    // Puzzles trade regardless, from the short the last trade is still taking
+    let puzzle = json!({ "event": "puzzle", "data": { "impact": 2.0 } }).to_string();
+    handler.handle_text(&puzzle, &mut outbox).await;
+    assert_eq!(trades(&outbox), 5);
+}
+
+#[test]
+fn wind_down_counts_updates_when_the_server_doesnt_say() {
+    let config = WindDownConfig {
+        updates: 3,
+        game_length: Some(10),
+    };
+    assert_eq!(config.remaining(None, 6), Some(4));
+    assert!(!config.is_due(config.remaining(None, 7)));
+    assert!(config.is_due(config.remaining(None, 8)));
+    // Whatever the server reports wins
+    assert!(!config.is_due(config.remaining(Some(5), 9)));
+
+    let unknown = WindDownConfig::default();
+    assert_eq!(unknown.remaining(None, 1000), None);
+    assert!(!unknown.is_due(None));
+}
+
+// The last `remaining` updates of the game, as the server reports it
+fn closing_frame(forecast: f64, momentum: f64, position: i32, remaining: u32) -> String {
+    let mut frame: serde_json::Value = serde_json::from_str(&common::state_frame(
+        100.0, forecast, momentum, position, 0.0,
+    ))
+    .unwrap();
+    frame["data"]["updates_remaining"] = json!(remaining);
+    frame.to_string()
+}
+
+async fn winding_down_handler() -> ConnectionHandler {
+    let mut config = common::test_config();
+    config.rate_limit.enabled = false;
+    let config = Arc::new(config);
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, state);
+    common::join(&mut handler).await;
+    handler
+}
+
+#[optiva_ws::rt::test]
+async fn wind_down_never_adds_risk() {
+    // Well before the end the same signal buys
+    let mut handler = winding_down_handler().await;
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&closing_frame(0.5, 8.0, 0, 20), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [3]);
+
+    // Flat with a strong buy signal stays flat
+    let mut outbox = Vec::new();
+    let mut handler = winding_down_handler().await;
+    handler
+        .handle_text(&closing_frame(0.5, 8.0, 0, 5), &mut outbox)
+        .await;
+    assert!(trade_volumes(&outbox).is_empty());
+}
+
+#[optiva_ws::rt::test]
+async fn wind_down_reduces_a_long_position_whatever_the_signal() {
+    for (forecast, momentum) in [(0.5, 8.0), (-0.5, -8.0), (0.0, 0.0)] {
+        let mut handler = winding_down_handler().await;
+        let mut outbox = Vec::new();
+        handler
+            .handle_text(&closing_frame(forecast, momentum, 2, 5), &mut outbox)
+            .await;
+        assert_eq!(trade_volumes(&outbox), [-2]);
+    }
+}
+
+async fn warming_up_handler(updates: u32) -> (Arc<SharedState>, ConnectionHandler) {
+    let mut config = common::test_config();
+    config.rate_limit.enabled = false;
+    config.warm_up.updates = updates;
+    let config = Arc::new(config);
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+    (state, handler)
+}
+
+#[optiva_ws::rt::test]
+async fn the_first_updates_of_a_game_only_warm_up() {
+    let (state, mut handler) = warming_up_handler(3).await;
+    let frame = common::state_frame(100.0, 0.5, 8.0, 0, 0.0);
+    for _ in 0..3 {
+        let mut outbox = Vec::new();
+        handler.handle_text(&frame, &mut outbox).await;
+        assert!(trade_volumes(&outbox).is_empty());
+    }
+    // The signals were still recorded, and the prices seed the indicators
+    assert_eq!(state.snapshot().await.trade_history.len, 3);
+    assert_eq!(handler.price_history().len(), 3);
+
+    let mut outbox = Vec::new();
+    handler.handle_text(&frame, &mut outbox).await;
+    assert_eq!(trade_volumes(&outbox), [3]);
+
+    // A reconnect warms up again, and so does the next game
+    common::join(&mut handler).await;
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&common::state_frame(100.0, 0.5, 8.0, 0, 0.0), &mut outbox)
+        .await;
+    assert!(trade_volumes(&outbox).is_empty());
+    let finish = json!({ "event": "finish", "data": { "pnl": 0.0 } }).to_string();
+    handler.handle_text(&finish, &mut outbox).await;
+    handler.handle_text(&frame, &mut outbox).await;
+    assert!(trade_volumes(&outbox).is_empty());
+}
+
+#[optiva_ws::rt::test]
+async fn no_warm_up_trades_the_first_update() {
+    let (_, mut handler) = warming_up_handler(0).await;
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&common::state_frame(100.0, 0.5, 8.0, 0, 0.0), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [3]);
+}
+
+#[cfg(feature = "puzzles")]
+#[optiva_ws::rt::test]
+async fn puzzles_skip_the_warm_up() {
+    let (_, mut handler) = warming_up_handler(5).await;
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&common::state_frame(100.0, 0.0, 0.0, 0, 0.0), &mut outbox)
+        .await;
+    let puzzle = json!({ "event": "puzzle", "data": { "impact": 2.0 } }).to_string();
+    handler.handle_text(&puzzle, &mut outbox).await;
+    assert_eq!(trade_volumes(&outbox), [3]);
+}
+
+#[optiva_ws::rt::test]
+async fn puzzles_arent_traded_while_winding_down() {
+    let mut handler = winding_down_handler().await;
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&closing_frame(0.0, 0.0, 0, 5), &mut outbox)
+        .await;
+    let puzzle = json!({ "event": "puzzle", "data": { "impact": -2.0 } }).to_string();
+    handler.handle_text(&puzzle, &mut outbox).await;
+    assert!(trade_volumes(&outbox).is_empty());
+}
+
+#[optiva_ws::rt::test]
+async fn puzzles_turned_off_are_only_skipped() {
+    let config = Arc::new(optiva_ws::config::Config {
+        puzzles: false,
+        ..common::test_config()
+    });
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, state);
+    common::join(&mut handler).await;
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&common::state_frame(100.0, 0.0, 0.0, 0, 0.0), &mut outbox)
+        .await;
+    outbox.clear();
+
+    let puzzle = json!({ "event": "puzzle", "data": { "impact": -2.0 } }).to_string();
+    handler.handle_text(&puzzle, &mut outbox).await;
+    assert!(trade_volumes(&outbox).is_empty());
+    assert!(matches!(
+        outbox.as_slice(),
+        [ClientMessage {
+            event: ClientEvent::Skip(_),
+            ..
+        }]
+    ));
+}
+
+fn update(momentum: f64, position: i32, pnl: f64) -> ServerEvent {
+    ServerEvent::State(StateUpdate {
+        price: 100.0,
+        price_forecast: 0.5,
+        momentum,
+        position,
+        position_limit: 3,
+        pnl,
+        updates_remaining: None,
+        instrument: None,
+        stage: None,
+        sequence: None,
+    })
+}
+
+#[optiva_ws::rt::test]
+async fn non_finite_states_are_dropped_before_the_math() {
+    let config = Arc::new(common::test_config());
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+
+    // A filled trade and a PnL change, so ticks are being recorded
+    let mut outbox = Vec::new();
+    handler.handle_event(update(8.0, 0, 0.0), &mut outbox).await;
+    handler.handle_event(update(8.0, 3, 1.0), &mut outbox).await;
+    handler.handle_event(update(8.0, 3, 2.0), &mut outbox).await;
+    assert_eq!(trade_volumes(&outbox), [3]);
+    let recorded = state.performance_history.lock().await.len();
+    assert!(recorded > 0);
+
+    // Would sell everything on a valid update
+    let mut outbox = Vec::new();
+    handler
+        .handle_event(update(f64::NAN, 3, 2.0), &mut outbox)
+        .await;
+    handler
+        .handle_event(update(-8.0, 3, f64::INFINITY), &mut outbox)
+        .await;
+    assert!(outbox.is_empty());
+    assert_eq!(state.performance_history.lock().await.len(), recorded);
+    assert!(state.performance_stats().await.mean.is_finite());
+    let perf = state.connection_performance.lock().await[&0].clone();
+    assert_eq!(perf.invalid_states, 2);
+    assert_eq!(perf.last_pnl, 2.0);
+}
+
+#[optiva_ws::rt::test]
+async fn stale_states_are_skipped_but_still_counted() {
+    let config = Arc::new(common::test_config());
+    let clock = Arc::new(ManualClock::new(100.0));
+    let state = Arc::new(SharedState::with_clock(&config, clock.clone()));
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+    let frame = common::state_frame(100.0, 0.5, 8.0, 0, 0.0);
+
+    // Arrived a second ago, so the decision comes too late
+    let mut outbox = Vec::new();
+    let late = Receipt::at(Monotonic(99.0));
+    handler.handle_received(&frame, late, &mut outbox).await;
+    assert!(trade_volumes(&outbox).is_empty());
+
+    let perf = state.connection_performance.lock().await[&0].clone();
+    assert_eq!(perf.stale_states, 1);
+    assert_eq!(perf.session.updates_seen, 1);
+    drop(perf);
+
+    // Fresh, the same update trades
+    handler
+        .handle_received(&frame, Receipt::at(Monotonic(100.0)), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [3]);
+}
+
+#[test]
+fn only_a_repeat_of_the_last_update_is_skipped() {
+    let config = DuplicateConfig::default();
+    let mut filter = DuplicateFilter::default();
+    assert!(!filter.is_repeat(&config, 1));
+    assert!(filter.is_repeat(&config, 1));
+    // One skip in a row, then an unchanged market is taken at its word
+    assert!(!filter.is_repeat(&config, 1));
+    assert!(!filter.is_repeat(&config, 2));
+    // Nothing older than the last update is remembered
+    assert!(!filter.is_repeat(&config, 1));
+
+    let config = DuplicateConfig {
+        max_skips: 2,
+        ..DuplicateConfig::default()
+    };
+    let mut filter = DuplicateFilter::default();
+    let skipped: Vec<bool> = (0..4).map(|_| filter.is_repeat(&config, 7)).collect();
+    assert_eq!(skipped, [false, true, true, false]);
+
+    let config = DuplicateConfig {
+        enabled: false,
+        ..DuplicateConfig::default()
+    };
+    let mut filter = DuplicateFilter::default();
+    assert!(!filter.is_repeat(&config, 1));
+    assert!(!filter.is_repeat(&config, 1));
+}
+
+#[optiva_ws::rt::test]
+async fn a_replayed_state_is_skipped_and_counted() {
+    let mut config = common::test_config();
+    config.duplicates = DuplicateConfig::default();
+    let config = Arc::new(config);
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+    let frame = common::state_frame(100.0, 0.5, 8.0, 0, 0.0);
+
+    let mut outbox = Vec::new();
+    handler.handle_text(&frame, &mut outbox).await;
+    // Delivered again across a reconnect
+    common::join(&mut handler).await;
+    handler.handle_text(&frame, &mut outbox).await;
+    assert_eq!(trade_volumes(&outbox), [3]);
+
+    let perf = state.connection_performance.lock().await[&0].clone();
+    assert_eq!(perf.duplicate_states, 1);
+    assert_eq!(perf.session.updates_seen, 1);
+    drop(perf);
+
+    // A second repeat is an unchanged market, which goes through
+    handler.handle_text(&frame, &mut outbox).await;
+    let perf = state.connection_performance.lock().await[&0].clone();
+    assert_eq!(perf.duplicate_states, 1);
+    assert_eq!(perf.session.updates_seen, 2);
+}
+
+#[test]
+fn clamped_volume_stops_at_the_limit() {
+    assert_eq!(clamp_to_limit(1, 4, 3), 2);
+    assert_eq!(clamp_to_limit(-2, -5, 3), -1);
+    assert_eq!(clamp_to_limit(3, 2, 3), 0);
+    assert_eq!(clamp_to_limit(0, -2, 3), -2);
+    // Past the limit, at least the excess comes off
+    assert_eq!(clamp_to_limit(5, 0, 2), -3);
+    assert_eq!(clamp_to_limit(-4, 1, 2), 2);
+    assert_eq!(clamp_to_limit(2, 0, -1), -2);
+}
+
+fn limit_frame(forecast: f64, momentum: f64, position: i32, position_limit: i32) -> String {
+    let mut frame: serde_json::Value = serde_json::from_str(&common::state_frame(
+        100.0, forecast, momentum, position, 0.0,
+    ))
+    .unwrap();
+    frame["data"]["position_limit"] = json!(position_limit);
+    frame.to_string()
+}
+
+#[optiva_ws::rt::test]
+async fn raised_limit_is_traded_up_to() {
+    let config = Arc::new(common::test_config());
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&limit_frame(0.5, 8.0, 0, 3), &mut outbox)
+        .await;
+    handler
+        .handle_text(&limit_frame(0.5, 8.0, 3, 5), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [3, 2]);
+    let perf = state.connection_performance.lock().await[&0].clone();
+    assert_eq!(perf.session.position_limit, Some(5));
+}
+
+#[optiva_ws::rt::test]
+async fn lowered_limit_below_the_position_is_reduced_at_once() {
+    let mut config = common::test_config();
+    config.rate_limit = RateLimitConfig {
+        enabled: true,
+        burst: 1,
+        ..RateLimitConfig::default()
+    };
+    let config = Arc::new(config);
+    let state = Arc::new(SharedState::with_clock(
+        &config,
+        Arc::new(ManualClock::new(0.0)),
+    ));
+    let mut handler = ConnectionHandler::new(0, config, state);
+    common::join(&mut handler).await;
+
+    // The only token goes on getting long
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&limit_frame(0.5, 8.0, 0, 3), &mut outbox)
+        .await;
+    // Still bullish, but the limit drops to one: sell two, rate limit or not
+    handler
+        .handle_text(&limit_frame(0.5, 8.0, 3, 1), &mut outbox)
+        .await;
+    // Not sent again while the server is slow to show it
+    handler
+        .handle_text(&limit_frame(0.01, 0.0, 3, 1), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [3, -2]);
+    // Once it's given up on, a weak signal inside the deadband doesn't hold us over
+    // the limit either
+    handler
+        .handle_text(&limit_frame(0.01, 0.0, 3, 1), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [3, -2, -2]);
+}
+
+#[cfg(feature = "puzzles")]
+#[optiva_ws::rt::test]
+async fn held_puzzle_follows_a_lowered_limit() {
+    let mut config = common::test_config();
+    config.rate_limit.enabled = false;
+    let config = Arc::new(config);
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, state);
+    common::join(&mut handler).await;
+
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&limit_frame(0.0, 0.0, 0, 3), &mut outbox)
+        .await;
+    let puzzle = json!({ "event": "puzzle", "data": { "impact": 2.0 } }).to_string();
+    handler.handle_text(&puzzle, &mut outbox).await;
+    handler
+        .handle_text(&limit_frame(0.0, 0.0, 3, 2), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [3, -1]);
+}
+
+#[optiva_ws::rt::test]
+async fn a_trip_can_turn_the_kill_switch_on_everywhere() {
+    let mut config = common::test_config();
+    config.rate_limit.enabled = false;
+    config.risk = RiskConfig {
+        kill_switch_on_trip: true,
+        ..dollars(20.0)
+    };
+    let config = Arc::new(config);
+    let state = Arc::new(SharedState::new(&config));
+    let mut losing = ConnectionHandler::new(0, Arc::clone(&config), Arc::clone(&state));
+    let mut other = ConnectionHandler::new(1, Arc::clone(&config), Arc::clone(&state));
+    common::join(&mut losing).await;
+    common::join(&mut other).await;
+
+    let mut outbox = Vec::new();
+    for pnl in [50.0, 10.0] {
+        losing
+            .handle_text(&common::state_frame(100.0, 0.0, 0.0, 0, pnl), &mut outbox)
+            .await;
+    }
+    assert!(!state.trading_enabled());
+
+    // The very next update on another connection holds off
+    other
+        .handle_text(&common::state_frame(100.0, 0.5, 8.0, 0, 0.0), &mut outbox)
+        .await;
+    assert!(trade_volumes(&outbox).is_empty());
+
+    // Only turning it off again resumes trading
+    state.set_trading_enabled(true);
+    other
+        .handle_text(&common::state_frame(100.0, 0.5, 8.0, 0, 0.0), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [3]);
+}
+
+fn quarantine_config() -> QuarantineConfig {
+    QuarantineConfig {
+        enabled: true,
+        trades: 2,
+        threshold: -1.0,
+        cooldown_secs: 30.0,
+        reduced_size: 0.5,
+    }
+}
+
+#[test]
+fn quarantine_needs_the_others_to_be_making_money() {
+    let config = quarantine_config();
+    let mut quarantine = Quarantine::default();
+    quarantine.record_trade(-2.0);
+    // One trade isn't a window yet
+    assert_eq!(quarantine.update(&config, 0.0, true), None);
+    quarantine.record_trade(-1.0);
+    assert_eq!(quarantine.rolling_pnl(2), Some(-3.0));
+    assert_eq!(quarantine.update(&config, 0.0, false), None);
+    assert_eq!(
+        quarantine.update(&config, 1.0, true),
+        Some(QuarantineEvent::Started { rolling_pnl: -3.0 })
+    );
+    assert_eq!(quarantine.state, QuarantineState::Observing { since: 1.0 });
+
+    // Disabled, nothing happens whatever the PnL
+    let mut quarantine = Quarantine::default();
+    quarantine.record_trade(-5.0);
+    quarantine.record_trade(-5.0);
+    assert_eq!(
+        quarantine.update(&QuarantineConfig::default(), 0.0, true),
+        None
+    );
+}
+
+#[test]
+fn quarantine_ends_in_probation_judged_on_fresh_trades() {
+    let config = quarantine_config();
+    let mut quarantine = Quarantine::default();
+    quarantine.record_trade(-2.0);
+    quarantine.record_trade(-2.0);
+    quarantine.update(&config, 0.0, true);
+
+    assert_eq!(quarantine.update(&config, 29.0, true), None);
+    assert_eq!(
+        quarantine.update(&config, 30.0, true),
+        Some(QuarantineEvent::Probation)
+    );
+    // The losing window is forgotten, so it isn't quarantined again straight away
+    assert_eq!(quarantine.rolling_pnl(2), None);
+    assert_eq!(quarantine.update(&config, 31.0, true), None);
+
+    quarantine.record_trade(1.0);
+    quarantine.record_trade(0.5);
+    assert_eq!(
+        quarantine.state,
+        QuarantineState::Probation { trades_left: 0 }
+    );
+    assert_eq!(
+        quarantine.update(&config, 32.0, true),
+        Some(QuarantineEvent::Cleared)
+    );
+    assert_eq!(quarantine.quarantines, 1);
+    assert_eq!(config.reduced_limit(3), 2);
+}
+
+#[test]
+fn others_profitable_goes_by_connections_with_enough_trades() {
+    let mut performances: HashMap<usize, ConnectionPerformance> = HashMap::new();
+    performances
+        .entry(0)
+        .or_default()
+        .quarantine
+        .record_trade(-4.0);
+    assert!(!others_profitable(&performances, 0, 1));
+
+    performances
+        .entry(1)
+        .or_default()
+        .quarantine
+        .record_trade(2.0);
+    // Too new to count either way
+    performances.entry(2).or_default();
+    assert!(others_profitable(&performances, 0, 1));
+    assert!(!others_profitable(&performances, 1, 1));
+
+    performances
+        .entry(2)
+        .or_default()
+        .quarantine
+        .record_trade(-0.5);
+    assert!(!others_profitable(&performances, 0, 1));
+}
+
+#[optiva_ws::rt::test]
+async fn quarantined_connection_observes_then_trades_at_reduced_size() {
+    let mut config = common::test_config();
+    config.rate_limit.enabled = false;
+    config.quarantine = quarantine_config();
+    let config = Arc::new(config);
+    let clock = Arc::new(ManualClock::new(0.0));
+    let state = Arc::new(SharedState::with_clock(&config, clock.clone()));
+    {
+        let mut performances = state.connection_performance.lock().await;
+        for pnl in [-2.0, -2.0] {
+            performances
+                .entry(0)
+                .or_default()
+                .quarantine
+                .record_trade(pnl);
+            performances
+                .entry(1)
+                .or_default()
+                .quarantine
+                .record_trade(-pnl);
+        }
+        // Trades already made, so the ticks below would count towards the history
+        performances.entry(0).or_default().trades_made = 2;
+    }
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+
+    let mut outbox = Vec::new();
+    for pnl in [0.0, 1.0] {
+        handler
+            .handle_text(&common::state_frame(100.0, 0.5, 8.0, 0, pnl), &mut outbox)
+            .await;
+    }
+    assert!(trade_volumes(&outbox).is_empty());
+    assert_eq!(state.performance_history.lock().await.len(), 0);
+    let snapshot = state.snapshot().await;
+    assert_eq!(
+        snapshot.connections[0].quarantine,
+        QuarantineState::Observing { since: 0.0 }
+    );
+
+    // After the cooldown it's back, but only to half the limit
+    clock.advance(30.0);
+    handler
+        .handle_text(&common::state_frame(100.0, 0.5, 8.0, 0, 2.0), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [2]);
+    assert_eq!(state.performance_history.lock().await.len(), 1);
+}
+
+fn hold(max_secs: f64) -> HoldConfig {
+    HoldConfig {
+        max_secs: Some(max_secs),
+        reconfirm_signal: 0.5,
+    }
+}
+
+#[test]
+fn a_position_held_too_long_without_the_signal_expires() {
+    let config = hold(30.0);
+    let mut timer = HoldTimer::default();
+    assert_eq!(timer.update(&config, 0.0, 3, 0.1), None);
+    assert_eq!(timer.update(&config, 30.0, 3, 0.1), None);
+    assert_eq!(timer.update(&config, 31.0, 3, -0.2), Some(31.0));
+    // Counted again from the expiry rather than firing every update
+    assert_eq!(timer.update(&config, 32.0, 3, 0.1), None);
+    assert_eq!(timer.update(&config, 62.0, 3, 0.1), Some(31.0));
+
+    // Flat is never held
+    let mut flat = HoldTimer::default();
+    assert_eq!(flat.update(&config, 0.0, 0, 0.0), None);
+    assert_eq!(flat.update(&config, 100.0, 0, 0.0), None);
+
+    // Unset never expires
+    let mut unset = HoldTimer::default();
+    let config = HoldConfig {
+        max_secs: None,
+        ..hold(30.0)
+    };
+    unset.update(&config, 0.0, 3, 0.0);
+    assert_eq!(unset.update(&config, 1000.0, 3, 0.0), None);
+}
+
+#[test]
+fn a_new_position_or_a_strong_signal_restarts_the_hold() {
+    let config = hold(30.0);
+    let mut timer = HoldTimer::default();
+    timer.update(&config, 0.0, 3, 0.1);
+    // Re-confirmed in the position's direction
+    assert_eq!(timer.update(&config, 20.0, 3, 0.7), None);
+    assert_eq!(timer.update(&config, 45.0, 3, 0.1), None);
+    // A strong signal the other way doesn't back the position
+    assert_eq!(timer.update(&config, 51.0, 3, -0.9), Some(31.0));
+
+    let mut timer = HoldTimer::default();
+    timer.update(&config, 0.0, 3, 0.1);
+    assert_eq!(timer.update(&config, 20.0, -2, 0.1), None);
+    assert_eq!(timer.update(&config, 45.0, -2, -0.6), None);
+    assert_eq!(timer.update(&config, 76.0, -2, 0.0), Some(31.0));
+}
+
+#[optiva_ws::rt::test]
+async fn a_stale_position_is_taken_back_to_flat() {
+    let config = Arc::new(common::test_config());
+    let clock = Arc::new(ManualClock::new(1000.0));
+    let state = Arc::new(SharedState::with_clock(&config, clock.clone()));
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&common::state_frame(100.0, 0.5, 8.0, 0, 0.0), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [3]);
+
+    // The signal fades to nothing and the position just sits there, held from the
+    // first update that shows it
+    let mut outbox = Vec::new();
+    for _ in 0..4 {
+        clock.advance(10.0);
+        handler
+            .handle_text(&common::state_frame(100.0, 0.0, 0.0, 3, 0.0), &mut outbox)
+            .await;
+    }
+    assert!(trade_volumes(&outbox).is_empty());
+    clock.advance(1.0);
+    handler
+        .handle_text(&common::state_frame(100.0, 0.0, 0.0, 3, 0.0), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [-3]);
+
+    let finish = json!({ "event": "finish", "data": { "pnl": 0.0 } }).to_string();
+    handler.handle_text(&finish, &mut Vec::new()).await;
+    assert_eq!(handler.take_summary().unwrap().holds_expired, 1);
+}
+
+fn ceiling(max: u32) -> ExposureConfig {
+    ExposureConfig {
+        max_net_position: Some(max),
+    }
+}
+
+fn buy(position: i32, volume: i32, signal: f64) -> ExposureRequest {
+    ExposureRequest {
+        position,
+        position_limit: 3,
+        volume,
+        signal,
+    }
+}
+
+#[test]
+fn no_exposure_ceiling_never_shrinks() {
+    let mut book = ExposureBook::default();
+    let config = ExposureConfig::default();
+    for conn_id in 0..5 {
+        assert_eq!(book.admit(&config, conn_id, buy(0, 3, 0.9)), 3);
+    }
+    let usage = book.usage(&config);
+    assert_eq!(usage.net_position, 15);
+    assert_eq!(usage.utilization, None);
+}
+
+#[test]
+fn trades_shrink_to_the_room_left_under_the_ceiling() {
+    let mut book = ExposureBook::default();
+    let config = ceiling(4);
+    assert_eq!(book.admit(&config, 0, buy(0, 3, 0.9)), 3);
+    assert_eq!(book.admit(&config, 1, buy(0, 3, 0.9)), 1);
+    assert_eq!(book.admit(&config, 2, buy(0, 3, 0.9)), 0);
+    // Selling takes exposure off, so it always fits
+    assert_eq!(book.admit(&config, 2, buy(0, -3, -0.9)), -3);
+    assert_eq!(book.usage(&config).net_position, 1);
+    assert_eq!(book.admit(&config, 2, buy(-3, 3, 0.9)), 3);
+}
+
+#[test]
+fn a_stronger_signal_refused_keeps_its_claim_on_the_room() {
+    let mut book = ExposureBook::default();
+    let config = ceiling(4);
+    book.admit(&config, 0, buy(0, 3, 0.5));
+    assert_eq!(book.admit(&config, 1, buy(0, 3, 0.9)), 1);
+
+    // The first connection sells, but the room goes to the stronger signal still
+    // waiting on it rather than a weaker one asking first
+    book.admit(&config, 0, buy(3, -3, -0.5));
+    assert_eq!(book.admit(&config, 2, buy(0, 3, 0.2)), 1);
+    assert_eq!(book.admit(&config, 1, buy(1, 2, 0.9)), 2);
+}
+
+#[test]
+fn a_position_out_of_sight_counts_at_its_limit_either_way() {
+    let mut book = ExposureBook::default();
+    let config = ceiling(4);
+    book.observe(0, -1, 3);
+    book.lose_track(0);
+    let usage = book.usage(&config);
+    assert_eq!(
+        (usage.worst_long, usage.worst_short, usage.unknown),
+        (3, 3, 1)
+    );
+    assert_eq!(usage.net_position, 0);
+
+    assert_eq!(book.admit(&config, 1, buy(0, 3, 0.9)), 1);
+    // The long held offsets some of what it could be short
+    assert_eq!(book.admit(&config, 2, buy(0, -3, -0.9)), -2);
+    assert_eq!(book.admit(&config, 2, buy(-2, -1, -0.9)), 0);
+    // Reported again, it counts as it is
+    book.observe(0, -1, 3);
+    assert_eq!(book.admit(&config, 2, buy(-2, -1, -0.9)), -1);
+    // A finished game frees its room
+    book.close(1);
+    assert_eq!(book.usage(&config).worst_short, 4);
+}
+
+#[optiva_ws::rt::test]
+async fn connections_share_the_exposure_ceiling() {
+    let mut config = common::test_config();
+    config.exposure.max_net_position = Some(4);
+    let config = Arc::new(config);
+    let state = Arc::new(SharedState::new(&config));
+    let mut first = ConnectionHandler::new(0, Arc::clone(&config), Arc::clone(&state));
+    let mut second = ConnectionHandler::new(1, Arc::clone(&config), Arc::clone(&state));
+    common::join(&mut first).await;
+    common::join(&mut second).await;
+
+    let mut outbox = Vec::new();
+    let frame = common::state_frame(100.0, 0.5, 8.0, 0, 0.0);
+    first.handle_text(&frame, &mut outbox).await;
+    second.handle_text(&frame, &mut outbox).await;
+    assert_eq!(trade_volumes(&outbox), [3, 1]);
+    assert_eq!(state.last_decision(1).await.unwrap().capped_volume, 2);
+
+    let snapshot = state.snapshot().await;
+    assert_eq!(snapshot.exposure.net_position, 4);
+    assert_eq!(snapshot.exposure.utilization, Some(1.0));
+
+    // Dropped mid-game, the first connection's position could be anything
+    state.set_health(0, Health::Backoff).await;
+    let exposure = state.snapshot().await.exposure;
+    assert_eq!((exposure.unknown, exposure.worst_short), (1, 2));
+}
+
+#[optiva_ws::rt::test]
+async fn a_trade_held_back_takes_no_room_under_the_ceiling() {
+    let mut config = common::test_config();
+    config.exposure.max_net_position = Some(4);
+    let state = Arc::new(SharedState::new(&config));
+    let mut warming_up = config.clone();
+    warming_up.warm_up.updates = 1;
+    let mut first = ConnectionHandler::new(0, Arc::new(warming_up), Arc::clone(&state));
+    let mut second = ConnectionHandler::new(1, Arc::new(config), Arc::clone(&state));
+    common::join(&mut first).await;
+    common::join(&mut second).await;
+
+    // The first connection wants the whole limit but isn't trading yet
+    let mut outbox = Vec::new();
+    let frame = common::state_frame(100.0, 0.5, 8.0, 0, 0.0);
+    first.handle_text(&frame, &mut outbox).await;
+    second.handle_text(&frame, &mut outbox).await;
+    assert_eq!(trade_volumes(&outbox), [3]);
+    assert_eq!(state.snapshot().await.exposure.net_position, 3);
+}
+
+#[optiva_ws::rt::test]
+async fn a_manual_order_not_sent_leaves_the_room_claimed() {
+    let mut config = common::test_config();
+    config.exposure.max_net_position = Some(3);
+    let config = Arc::new(config);
+    let state = Arc::new(SharedState::new(&config));
+    let mut handlers = Vec::new();
+    for conn_id in 0..3 {
+        let mut handler = ConnectionHandler::new(conn_id, Arc::clone(&config), Arc::clone(&state));
+        common::join(&mut handler).await;
+        handlers.push(handler);
+    }
+
+    // The second connection is refused room the first has, and keeps it claimed once
+    // the first sells back out
+    let mut outbox = Vec::new();
+    let strong = common::state_frame(100.0, 0.5, 8.0, 0, 0.0);
+    handlers[0].handle_text(&strong, &mut outbox).await;
+    handlers[1].handle_text(&strong, &mut outbox).await;
+    handlers[0]
+        .handle_manual(ManualOrder::Trade(-3), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [3, -3]);
+
+    // Halted, its manual order goes nowhere and gives up nothing
+    state.set_trading_enabled(false);
+    handlers[1]
+        .handle_manual(ManualOrder::Trade(1), &mut outbox)
+        .await;
+    state.set_trading_enabled(true);
+    let weaker = common::state_frame(100.0, 0.2, 2.0, 0, 0.0);
+    handlers[2].handle_text(&weaker, &mut outbox).await;
+    assert_eq!(trade_volumes(&outbox), [3, -3]);
+}
+
+#[cfg(feature = "puzzles")]
+#[optiva_ws::rt::test]
+async fn one_puzzle_on_every_connection_stays_under_the_ceiling() {
+    let mut config = common::test_config();
+    config.exposure.max_net_position = Some(4);
+    let config = Arc::new(config);
+    let state = Arc::new(SharedState::new(&config));
+    let mut handlers = Vec::new();
+    for conn_id in 0..3 {
+        let mut handler = ConnectionHandler::new(conn_id, Arc::clone(&config), Arc::clone(&state));
+        common::join(&mut handler).await;
+        handler
+            .handle_text(
+                &common::state_frame(100.0, 0.0, 0.0, 0, 0.0),
+                &mut Vec::new(),
+            )
+            .await;
+        handlers.push(handler);
+    }
+
+    let mut outbox = Vec::new();
+    let puzzle = json!({ "event": "puzzle", "data": { "impact": 2.0 } }).to_string();
+    for handler in &mut handlers {
+        handler.handle_text(&puzzle, &mut outbox).await;
+    }
+    assert_eq!(trade_volumes(&outbox), [3, 1]);
+    assert_eq!(state.snapshot().await.exposure.net_position, 4);
+
+    // Nor does a trade typed at the console get past it, once trading again
+    let mut outbox = Vec::new();
+    handlers[2]
+        .handle_text(&common::state_frame(100.0, 0.0, 0.0, 0, 0.0), &mut outbox)
+        .await;
+    handlers[2]
+        .handle_manual(ManualOrder::Trade(2), &mut outbox)
+        .await;
+    assert!(trade_volumes(&outbox).is_empty());
+    // Selling takes exposure off, so it goes out whole
+    handlers[2]
+        .handle_manual(ManualOrder::Trade(-2), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [-2]);
+}
+
+fn budget(trades: u32, puzzle_trades: Option<u32>) -> TradeBudgetConfig {
+    TradeBudgetConfig {
+        trades_per_game: Some(trades),
+        puzzle_trades_per_game: puzzle_trades,
+    }
+}
+
+#[test]
+fn a_spent_budget_only_allows_trades_back_toward_flat() {
+    let config = budget(2, None);
+    let mut budget = TradeBudget::default();
+    assert_eq!(budget.trades_left(&config), Some(2));
+    budget.spend(&config, false);
+    assert!(budget.allows(&config, 0, 3, false));
+    budget.spend(&config, false);
+    assert_eq!(budget.trades_left(&config), Some(0));
+
+    assert!(!budget.allows(&config, 0, 3, false));
+    assert!(!budget.allows(&config, 3, 1, false));
+    assert!(budget.allows(&config, 3, -2, false));
+    assert!(budget.allows(&config, -3, 3, false));
+    // Going past flat adds risk on the other side
+    assert!(!budget.allows(&config, 3, -4, false));
+    // Puzzles draw from the same budget without one of their own
+    assert!(!budget.allows(&config, 0, 3, true));
+
+    assert!(TradeBudget::default().allows(&TradeBudgetConfig::default(), 0, 3, false));
+}
+
+#[test]
+fn puzzle_trades_can_have_a_budget_of_their_own() {
+    let config = budget(1, Some(1));
+    let mut budget = TradeBudget::default();
+    budget.spend(&config, false);
+    assert!(!budget.allows(&config, 0, 3, false));
+    assert!(budget.allows(&config, 0, 3, true));
+    budget.spend(&config, true);
+    assert!(!budget.allows(&config, 0, 3, true));
+    assert_eq!(
+        (budget.trades_left(&config), budget.puzzle_left(&config)),
+        (Some(0), Some(0))
+    );
+}
+
+#[optiva_ws::rt::test]
+async fn the_trade_budget_holds_back_churn_until_the_next_game() {
+    let mut config = common::test_config();
+    config.rate_limit.enabled = false;
+    config.trade_budget = budget(2, None);
+    let config = Arc::new(config);
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+
+    // Long, then short, then the budget is spent and the flip back is held
+    let mut outbox = Vec::new();
+    for (sign, position) in [(1.0, 0), (-1.0, 3), (1.0, -3)] {
+        let frame = common::state_frame(100.0, sign * 0.5, sign * 8.0, position, 0.0);
+        handler.handle_text(&frame, &mut outbox).await;
+    }
+    assert_eq!(trade_volumes(&outbox), [3, -6]);
+    // Back inside a lowered limit is toward flat, so it still goes
+    handler
+        .handle_text(&limit_frame(0.01, 0.0, -3, 1), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [3, -6, 2]);
+
+    let connection = state.snapshot().await.connections[0].clone();
+    assert_eq!(connection.trade_budget_left, Some(0));
+    assert_eq!(connection.puzzle_budget_left, None);
+    assert_eq!(connection.over_budget, 1);
+
+    let finish = json!({ "event": "finish", "data": { "pnl": 0.0 } }).to_string();
+    handler.handle_text(&finish, &mut Vec::new()).await;
+    let summary = handler.take_summary().unwrap();
+    assert_eq!(
+        (summary.trade_budget_left, summary.over_budget),
+        (Some(0), 1)
+    );
+
+    // A fresh budget for the next game
+    common::join(&mut handler).await;
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&common::state_frame(100.0, 0.5, 8.0, 0, 0.0), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [3]);
+    let connection = state.snapshot().await.connections[0].clone();
+    assert_eq!(connection.trade_budget_left, Some(1));
+}
+
+#[cfg(feature = "puzzles")]
+#[optiva_ws::rt::test]
+async fn puzzles_trade_from_their_own_budget_after_churn() {
+    let mut config = common::test_config();
+    config.trade_budget = budget(1, Some(1));
+    let config = Arc::new(config);
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&common::state_frame(100.0, 0.5, 8.0, 0, 0.0), &mut outbox)
+        .await;
+    // The strategy's budget is spent, the puzzle's isn't
+    let puzzle = json!({ "event": "puzzle", "data": { "impact": -2.0 } }).to_string();
+    handler.handle_text(&puzzle, &mut outbox).await;
+    assert_eq!(trade_volumes(&outbox), [3, -6]);
+    // Now it is
+    let puzzle = json!({ "event": "puzzle", "data": { "impact": 2.0 } }).to_string();
+    handler.handle_text(&puzzle, &mut outbox).await;
+    assert_eq!(trade_volumes(&outbox), [3, -6]);
+    let connection = state.snapshot().await.connections[0].clone();
+    assert_eq!(connection.puzzle_budget_left, Some(0));
+    assert_eq!(connection.over_budget, 1);
+}
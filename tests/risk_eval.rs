@@ -0,0 +1,214 @@
+mod common;
+
+use common::{temp_dir, test_config};
+use optiva_ws::journal::JournalEntry;
+use optiva_ws::risk::{RiskConfig, RiskEvent};
+use optiva_ws::risk_eval::{
+    evaluate_risk, format_outcomes, journal_games, replay_game, synthetic_games,
+    write_outcomes_csv, RiskEvalConfig,
+};
+use optiva_ws::strategy::DecisionMode;
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-9
+}
+
+// A state update a second apart, going from `position` to `target`
+fn update(t: f64, price: f64, pnl: f64, position: i32, target: i32) -> RiskEvent {
+    RiskEvent {
+        timestamp: t,
+        price,
+        pnl: Some(pnl),
+        position,
+        target,
+    }
+}
+
+fn breaker(max_drawdown: f64, cooldown_secs: f64, flatten_on_halt: bool) -> RiskConfig {
+    RiskConfig {
+        max_drawdown: Some(max_drawdown),
+        cooldown_secs,
+        flatten_on_halt,
+        ..RiskConfig::default()
+    }
+}
+
+// Long one from 100 as the price falls 10 an update, until sold if ever
+fn falling_long(sold_at: Option<usize>) -> Vec<RiskEvent> {
+    let mut position = 0;
+    let mut pnl = 0.0;
+    (0..5)
+        .map(|t| {
+            pnl -= 10.0 * f64::from(position);
+            let target = i32::from(sold_at.is_none_or(|sold_at| t < sold_at));
+            let event = update(t as f64, 100.0 - 10.0 * t as f64, pnl, position, target);
+            position = target;
+            event
+        })
+        .collect()
+}
+
+#[test]
+fn flattening_on_a_trip_saves_the_rest_of_the_fall() {
+    // Down 10 at t=1, past the 7 allowed, so flat from there
+    let replay = replay_game(&breaker(7.0, 100.0, true), &falling_long(None));
+    assert_eq!(replay.trips, 1);
+    assert!(close(replay.pnl_without, -40.0), "{:?}", replay);
+    assert!(close(replay.pnl_with, -10.0), "{:?}", replay);
+    assert!(close(replay.drawdown_without, 40.0), "{:?}", replay);
+    assert!(close(replay.drawdown_with, 10.0), "{:?}", replay);
+    assert_eq!((replay.updates, replay.halted_updates), (5, 4));
+}
+
+#[test]
+fn holding_on_a_trip_can_cost_more_than_it_saves() {
+    // The bot sold at t=2, but the halt holds the position through the rest of the fall
+    let replay = replay_game(&breaker(7.0, 100.0, false), &falling_long(Some(2)));
+    assert_eq!(replay.trips, 1);
+    assert!(close(replay.pnl_without, -20.0), "{:?}", replay);
+    assert!(close(replay.pnl_with, -40.0), "{:?}", replay);
+}
+
+#[test]
+fn trading_resumes_where_the_bot_was_once_the_cooldown_is_over() {
+    let events = vec![
+        update(0.0, 100.0, 0.0, 0, 1),
+        // Tripped and flat from here for 3 seconds
+        update(1.0, 90.0, -10.0, 1, 1),
+        update(2.0, 95.0, -5.0, 1, 1),
+        update(3.0, 100.0, 0.0, 1, 1),
+        // Lifted, and long again like the bot
+        update(4.0, 110.0, 10.0, 1, 1),
+        update(5.0, 120.0, 20.0, 1, 1),
+    ];
+    let replay = replay_game(&breaker(5.0, 3.0, true), &events);
+    assert_eq!(replay.trips, 1);
+    // Missed the climb from 90 to 110 while flat
+    assert!(close(replay.pnl_with, 0.0), "{:?}", replay);
+}
+
+#[test]
+fn no_breaker_replays_the_game_as_it_was() {
+    let replay = replay_game(&RiskConfig::default(), &falling_long(Some(2)));
+    assert_eq!(replay.trips, 0);
+    assert_eq!(replay.pnl_with, replay.pnl_without);
+    assert_eq!(replay.drawdown_with, replay.drawdown_without);
+}
+
+fn entry(conn_id: usize, t: f64, pnl_change: Option<f64>, mode: DecisionMode) -> JournalEntry {
+    JournalEntry {
+        timestamp: t,
+        conn_id,
+        strategy: "blend".to_string(),
+        price: 100.0 + t,
+        forecast: 0.0,
+        momentum: 0.0,
+        combined_signal: 0.0,
+        position_before: 0,
+        position_after: 2,
+        volume: 2,
+        sent: t < 2.0,
+        pnl: t,
+        pnl_change,
+        mode,
+        account: String::new(),
+        arm: None,
+        decision_id: None,
+        driver: None,
+    }
+}
+
+#[test]
+fn a_journal_is_split_into_each_connections_sessions() {
+    let games = journal_games(&[
+        entry(0, 0.0, None, DecisionMode::Follow),
+        entry(1, 0.5, None, DecisionMode::Follow),
+        entry(0, 1.0, Some(1.0), DecisionMode::Follow),
+        entry(0, 1.5, None, DecisionMode::Puzzle),
+        // A new session on conn 0
+        entry(0, 2.0, None, DecisionMode::Follow),
+        entry(0, 3.0, Some(1.0), DecisionMode::Fade),
+    ]);
+    let shape: Vec<Vec<(f64, bool)>> = games
+        .iter()
+        .map(|game| {
+            game.iter()
+                .map(|event| (event.timestamp, event.pnl.is_some()))
+                .collect()
+        })
+        .collect();
+    assert_eq!(
+        shape,
+        [
+            vec![(0.0, true), (1.0, true), (1.5, false)],
+            vec![(2.0, true), (3.0, true)],
+            vec![(0.5, true)],
+        ]
+    );
+    // Only a trade that went out moves the position
+    assert_eq!((games[0][0].position, games[0][0].target), (0, 2));
+    assert_eq!((games[1][0].position, games[1][0].target), (0, 0));
+}
+
+#[test]
+fn every_combination_is_evaluated_and_an_empty_list_keeps_the_risk_setting() {
+    let base = RiskConfig {
+        max_drawdown_pct: Some(0.5),
+        cooldown_secs: 45.0,
+        ..RiskConfig::default()
+    };
+    let settings = RiskEvalConfig::default().settings(&base);
+    assert_eq!(settings.len(), 9);
+    assert!(settings
+        .iter()
+        .all(|setting| setting.max_drawdown_pct == Some(0.5) && !setting.flatten_on_halt));
+
+    let config = RiskEvalConfig {
+        max_drawdown: Vec::new(),
+        cooldown_secs: Vec::new(),
+        flatten_on_halt: vec![false, true],
+        ..RiskEvalConfig::default()
+    };
+    let settings = config.settings(&base);
+    assert_eq!(settings.len(), 2);
+    assert_eq!(settings[1].cooldown_secs, 45.0);
+    assert!(settings[1].flatten_on_halt);
+}
+
+#[optiva_ws::rt::test]
+async fn synthetic_games_are_compared_setting_by_setting() {
+    let mut config = test_config();
+    config.risk_eval.games = 20;
+    config.backtest.updates_per_game = 50;
+    let games = synthetic_games(&config).await;
+    assert_eq!(games.len(), 20);
+    assert_eq!(games, synthetic_games(&config).await);
+
+    let mut settings = config.risk_eval.settings(&config.risk);
+    settings.push(RiskConfig::default());
+    let outcomes = evaluate_risk(&games, &settings);
+    assert_eq!(outcomes.len(), 10);
+    // With no limits the breaker never steps in
+    let untouched = outcomes.last().unwrap();
+    assert_eq!(untouched.trips, 0);
+    assert!(close(untouched.mean_pnl_with, untouched.mean_pnl_without));
+    // A tighter limit trips at least as often
+    assert!(outcomes[0].trips >= outcomes[8].trips, "{:?}", outcomes);
+    for outcome in &outcomes {
+        assert!(outcome.games_tripped <= outcome.games.min(outcome.trips));
+        assert!((0.0..=1.0).contains(&outcome.halted_share));
+    }
+
+    let table = format_outcomes(&outcomes);
+    assert_eq!(table.lines().count(), 11);
+    let path = temp_dir("risk-eval").join("risk.csv");
+    write_outcomes_csv(&path, &outcomes).unwrap();
+    let csv = std::fs::read_to_string(&path).unwrap();
+    assert!(csv.starts_with("max_drawdown,max_drawdown_pct,cooldown_secs"));
+    assert!(csv.lines().nth(1).unwrap().starts_with("5,,30,false,20,"));
+    assert!(csv
+        .lines()
+        .last()
+        .unwrap()
+        .starts_with(",,60,false,20,0,0,"));
+}
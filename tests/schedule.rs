@@ -0,0 +1,131 @@
+mod common;
+
+use optiva_ws::handler::ConnectionHandler;
+use optiva_ws::schedule::{GameClock, PhaseConfig, ScheduleConfig};
+use optiva_ws::state::{SharedState, StrategyParams};
+use std::sync::Arc;
+
+fn phase(name: &str, from_fraction: Option<f64>, from_secs: Option<f64>) -> PhaseConfig {
+    PhaseConfig {
+        name: name.to_string(),
+        from_fraction,
+        from_secs,
+        ..PhaseConfig::default()
+    }
+}
+
+// Momentum-heavy for the first third of the game, forecast-heavy after
+fn thirds() -> ScheduleConfig {
+    ScheduleConfig {
+        phases: vec![
+            PhaseConfig {
+                momentum_weight: Some(0.9),
+                forecast_weight: Some(0.1),
+                ..phase("early", Some(0.0), None)
+            },
+            PhaseConfig {
+                momentum_weight: Some(0.2),
+                forecast_weight: Some(0.8),
+                ..phase("late", Some(0.34), None)
+            },
+        ],
+    }
+}
+
+fn at(fraction: f64) -> GameClock {
+    GameClock {
+        fraction: Some(fraction),
+        elapsed_secs: None,
+    }
+}
+
+#[test]
+fn each_phase_applies_from_its_start() {
+    let schedule = thirds();
+    let mut params = StrategyParams::default();
+    assert_eq!(schedule.apply(&mut params, at(0.0)), Some("early"));
+    assert_eq!(params.momentum_weight, 0.9);
+
+    let mut params = StrategyParams::default();
+    assert_eq!(schedule.apply(&mut params, at(0.339)), Some("early"));
+    assert_eq!(params.forecast_weight, 0.1);
+
+    let mut params = StrategyParams::default();
+    assert_eq!(schedule.apply(&mut params, at(0.34)), Some("late"));
+    assert_eq!(params.momentum_weight, 0.2);
+    assert_eq!(params.forecast_weight, 0.8);
+}
+
+#[test]
+fn later_phases_win_and_earlier_ones_still_count() {
+    let schedule = ScheduleConfig {
+        phases: vec![
+            PhaseConfig {
+                momentum_weight: Some(0.9),
+                aggressive_factor: Some(1.0),
+                ..phase("early", Some(0.0), None)
+            },
+            PhaseConfig {
+                momentum_weight: Some(0.3),
+                ..phase("settled", None, Some(30.0))
+            },
+        ],
+    };
+    let mut params = StrategyParams::default();
+    let clock = GameClock {
+        fraction: Some(0.5),
+        elapsed_secs: Some(30.0),
+    };
+    assert_eq!(schedule.apply(&mut params, clock), Some("settled"));
+    assert_eq!(params.momentum_weight, 0.3);
+    // Set only by the earlier phase, so it stays
+    assert_eq!(params.aggressive_factor, 1.0);
+}
+
+#[test]
+fn a_phase_waits_for_a_clock_it_can_read() {
+    let schedule = thirds();
+    let mut params = StrategyParams::default();
+    // No length to the game, so no share of it gone either
+    let clock = GameClock {
+        fraction: None,
+        elapsed_secs: Some(100.0),
+    };
+    assert_eq!(schedule.apply(&mut params, clock), None);
+    assert_eq!(params, StrategyParams::default());
+}
+
+#[optiva_ws::rt::test]
+async fn decisions_are_attributed_to_the_phase_they_were_made_in() {
+    let mut config = common::test_config();
+    config.schedule = thirds();
+    config.wind_down.game_length = Some(6);
+    config.wind_down.updates = 0;
+    let config = Arc::new(config);
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+
+    // One, two and three updates of six in
+    let mut outbox = Vec::new();
+    for (price, phase) in [(100.0, "early"), (101.0, "early"), (102.0, "late")] {
+        handler
+            .handle_text(&common::state_frame(price, 0.0, 0.0, 0, 0.0), &mut outbox)
+            .await;
+        let decision = state.last_decision(0).await.unwrap();
+        assert_eq!(decision.schedule_phase.as_deref(), Some(phase), "{}", price);
+    }
+
+    // Anything else asking for the params gets them as the latest decision had them
+    assert_eq!(state.params_for(0).await.forecast_weight, 0.8);
+    let snapshot = state.snapshot().await;
+    assert_eq!(
+        snapshot.connections[0].schedule_phase.as_deref(),
+        Some("late")
+    );
+    // Only ever laid over the base, which is left for the optimizer
+    assert_eq!(
+        state.strategy_params.read().await.forecast_weight,
+        StrategyParams::default().forecast_weight
+    );
+}
@@ -0,0 +1,152 @@
+#![cfg(feature = "puzzles")]
+
+mod common;
+
+use optiva_ws::handler::ConnectionHandler;
+use optiva_ws::protocol::{ClientEvent, ClientMessage, PuzzleData, ServerEvent};
+use optiva_ws::solver::{ArithmeticSolver, PuzzleAnswer, PuzzleSolver};
+use optiva_ws::state::SharedState;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+// A puzzle payload as the server sends it
+fn puzzle(data: Value) -> PuzzleData {
+    match ServerEvent::parse(&json!({ "event": "puzzle", "data": data }).to_string()).unwrap() {
+        ServerEvent::Puzzle(puzzle) => puzzle,
+        other => panic!("not a puzzle: {:?}", other),
+    }
+}
+
+fn solve(data: Value) -> Option<PuzzleAnswer> {
+    ArithmeticSolver.solve(&puzzle(data))
+}
+
+#[test]
+fn arithmetic_in_the_question_is_worked_out() {
+    for (data, answer) in [
+        (json!({ "question": "What is 2 + 3?" }), 5.0),
+        (
+            json!({ "question": "Compute (12 + 4) * 3.", "impact": 1.0 }),
+            48.0,
+        ),
+        (json!({ "prompt": "Round 3: what is 10 / 4" }), 2.5),
+        (json!({ "expression": "-2 * (3 - 7.5)" }), 9.0),
+        (json!({ "question": "How much is 6 × 7 ÷ 2?" }), 21.0),
+        (json!({ "question": "1 + 2 * 3 - 4 / 2" }), 5.0),
+    ] {
+        assert_eq!(
+            solve(data.clone()),
+            Some(PuzzleAnswer::Number(answer)),
+            "{}",
+            data
+        );
+    }
+}
+
+#[test]
+fn questions_that_arent_arithmetic_go_unanswered() {
+    for data in [
+        json!({ "impact": 2.0 }),
+        json!({ "question": "Which way will the stock move?" }),
+        json!({ "question": "What is 4 / 0?" }),
+        json!({ "question": "What is (2 + 3?" }),
+        json!({ "question": "What is 2 + * 3?" }),
+        json!({ "question": "Pick 1.2.3" }),
+    ] {
+        assert_eq!(solve(data.clone()), None, "{}", data);
+    }
+}
+
+#[test]
+fn answers_go_out_as_json_numbers() {
+    assert_eq!(PuzzleAnswer::Number(48.0).to_value(), json!(48));
+    assert_eq!(PuzzleAnswer::Number(-2.5).to_value(), json!(-2.5));
+    assert_eq!(PuzzleAnswer::Text("up".into()).to_value(), json!("up"));
+    assert_eq!(PuzzleAnswer::Number(48.0).to_string(), "48");
+}
+
+fn handler() -> (Arc<SharedState>, ConnectionHandler) {
+    let config = Arc::new(common::test_config());
+    let state = Arc::new(SharedState::new(&config));
+    (Arc::clone(&state), ConnectionHandler::new(0, config, state))
+}
+
+fn events(outbox: &[ClientMessage]) -> Vec<&ClientEvent> {
+    outbox.iter().map(|message| &message.event).collect()
+}
+
+async fn play_puzzle(handler: &mut ConnectionHandler, data: Value) -> Vec<ClientMessage> {
+    let mut outbox = Vec::new();
+    let frame = json!({ "event": "puzzle", "data": data }).to_string();
+    handler.handle_text(&frame, &mut outbox).await;
+    outbox
+}
+
+#[optiva_ws::rt::test]
+async fn a_solved_puzzle_is_answered_before_the_skip_instead_of_traded() {
+    let (state, mut handler) = handler();
+    common::join(&mut handler).await;
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&common::state_frame(100.0, 0.0, 0.0, 0, 0.0), &mut outbox)
+        .await;
+
+    let outbox = play_puzzle(
+        &mut handler,
+        json!({ "impact": 2.0, "question": "What is 6 * 7?", "id": 12 }),
+    )
+    .await;
+    match events(&outbox).as_slice() {
+        [ClientEvent::Answer(answer), ClientEvent::Skip(_)] => {
+            assert_eq!(answer.answer, json!(42));
+            assert_eq!(answer.id, Some(json!(12)));
+        }
+        other => panic!("expected an answer then a skip, got {:?}", other),
+    }
+
+    // No question it can answer, so the impact is traded as before
+    let outbox = play_puzzle(
+        &mut handler,
+        json!({ "impact": 2.0, "question": "Up or down?" }),
+    )
+    .await;
+    assert!(matches!(
+        events(&outbox).as_slice(),
+        [ClientEvent::Trade(_), ClientEvent::Skip(_)]
+    ));
+
+    let perf = state.connection_performance.lock().await[&0].clone();
+    assert_eq!((perf.puzzles_solved, perf.puzzles_skipped), (1, 1));
+    drop(perf);
+
+    let finish = json!({ "event": "finish", "data": { "pnl": 1.0 } }).to_string();
+    handler.handle_text(&finish, &mut Vec::new()).await;
+    let summary = handler.take_summary().unwrap();
+    assert_eq!((summary.puzzles_solved, summary.puzzles_skipped), (1, 1));
+    assert!(summary.to_string().contains("1 solved, 1 skipped"));
+}
+
+// Answers every puzzle the same way
+struct AlwaysUp;
+
+impl PuzzleSolver for AlwaysUp {
+    fn solve(&self, _data: &PuzzleData) -> Option<PuzzleAnswer> {
+        Some(PuzzleAnswer::Text("up".to_string()))
+    }
+}
+
+#[optiva_ws::rt::test]
+async fn an_added_solver_is_tried_first() {
+    let (_, handler) = handler();
+    let mut handler = handler.with_puzzle_solver(Box::new(AlwaysUp));
+    common::join(&mut handler).await;
+
+    let outbox = play_puzzle(&mut handler, json!({ "question": "What is 1 + 1?" })).await;
+    match events(&outbox).as_slice() {
+        [ClientEvent::Answer(answer), ClientEvent::Skip(_)] => {
+            assert_eq!(answer.answer, json!("up"));
+            assert_eq!(answer.id, None);
+        }
+        other => panic!("expected an answer then a skip, got {:?}", other),
+    }
+}
@@ -0,0 +1,759 @@
+mod common;
+
+use common::trade_volumes;
+use optiva_ws::clock::ManualClock;
+use optiva_ws::handler::{ConnectionHandler, Flow};
+use optiva_ws::protocol::{ClientEvent, ClientMessage};
+use optiva_ws::rt;
+use optiva_ws::state::{
+    ConnectionPerformance, PendingTrade, Reconciliation, SessionContext, Settlement, SharedState,
+    TradeOutcome,
+};
+use optiva_ws::strategy::Stance;
+use serde_json::json;
+use std::sync::Arc;
+
+#[test]
+fn pending_trade_filled_when_position_moves_by_volume() {
+    let pending = PendingTrade::new(-1, 4);
+    assert!(pending.filled_by(3));
+    assert!(!pending.filled_by(-1));
+    assert!(!pending.filled_by(2));
+}
+
+#[test]
+fn pending_trades_accumulate_until_next_update() {
+    let mut pending = PendingTrade::new(0, 3);
+    pending.add(-3);
+    assert_eq!(pending.trades, 2);
+    assert!(pending.filled_by(0));
+    assert!(!pending.filled_by(3));
+}
+
+#[test]
+fn pending_trade_waits_a_few_updates_for_the_position_to_move() {
+    let pending = PendingTrade::new(0, 3);
+    let Settlement::Waiting(pending) = pending.settle(0, 2) else {
+        panic!("gave up after one update");
+    };
+    assert_eq!(pending.updates_waited, 1);
+    assert_eq!(pending.expected_position(), 3);
+    assert!(matches!(pending.settle(0, 2), Settlement::Unfilled(_)));
+    assert!(matches!(pending.settle(3, 2), Settlement::Filled(_)));
+}
+
+#[test]
+fn partly_filled_trade_keeps_the_rest_pending() {
+    let pending = PendingTrade::new(-1, 4);
+    let Settlement::Waiting(rest) = pending.settle(1, 2) else {
+        panic!("partial fill not kept");
+    };
+    assert_eq!((rest.position_before, rest.volume), (1, 2));
+    assert_eq!(rest.expected_position(), 3);
+    // Moving the wrong way, or past it, isn't the trade filling
+    assert!(matches!(pending.settle(-2, 2), Settlement::Unfilled(_)));
+    assert!(matches!(pending.settle(4, 2), Settlement::Unfilled(_)));
+}
+
+#[test]
+fn trade_outcome_judged_after_window() {
+    let mut outcome = TradeOutcome::new(3);
+    assert_eq!(outcome.observe(2.0, 3), None);
+    assert_eq!(outcome.observe(-1.0, 3), None);
+    assert_eq!(outcome.observe(0.5, 3), Some(true));
+}
+
+#[test]
+fn trade_outcome_judged_when_position_reduced() {
+    let mut outcome = TradeOutcome::new(-3);
+    assert_eq!(outcome.observe(-2.0, -1), Some(false));
+}
+
+#[test]
+fn win_rate_counts_judged_trades() {
+    let mut perf = ConnectionPerformance::default();
+    assert_eq!(perf.win_rate(), None);
+
+    perf.open_trade(3);
+    perf.observe_pnl(5.0, 0);
+    perf.open_trade(-3);
+    perf.observe_pnl(-1.0, -3);
+    // Opening a new trade closes out the one still being judged
+    perf.open_trade(3);
+
+    assert_eq!(perf.evaluated_trades, 2);
+    assert_eq!(perf.successful_trades, 1);
+    assert_eq!(perf.win_rate(), Some(0.5));
+    assert!(perf.open_trade.is_some());
+}
+
+#[test]
+fn first_update_only_sets_pnl_baseline() {
+    let mut perf = ConnectionPerformance::default();
+
+    // Joining a game already 250 up must not look like a 250 gain
+    assert_eq!(perf.track_pnl(250.0), None);
+    assert_eq!(perf.track_pnl(255.0), Some(5.0));
+    assert_eq!(perf.track_pnl(252.0), Some(-3.0));
+
+    // Reconnect mid-game
+    perf.reset_pnl_baseline();
+    assert_eq!(perf.track_pnl(300.0), None);
+    assert_eq!(perf.track_pnl(301.0), Some(1.0));
+
+    // Next game starts from zero again
+    perf.reset_pnl_baseline();
+    assert_eq!(perf.track_pnl(0.0), None);
+    assert_eq!(perf.track_pnl(-2.0), Some(-2.0));
+    assert_eq!(perf.last_pnl, -2.0);
+}
+
+#[test]
+fn ending_a_game_forgets_its_pnl_and_open_trade() {
+    let mut perf = ConnectionPerformance::default();
+    perf.track_pnl(10.0);
+    perf.open_trade(3);
+    perf.session.position = Some(3);
+    perf.end_game();
+
+    assert_eq!(perf.last_pnl, 0.0);
+    assert!(perf.recent_pnl.is_empty());
+    assert!(perf.open_trade.is_none());
+    assert_eq!(perf.session.position, None);
+    assert_eq!(perf.track_pnl(2.0), None);
+    // The trade still open at the end is never judged
+    assert_eq!(perf.evaluated_trades, 0);
+}
+
+#[optiva_ws::rt::test]
+async fn handler_survives_a_missing_performance_entry() {
+    let config = Arc::new(common::test_config());
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(4, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+    state.connection_performance.lock().await.clear();
+
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&common::state_frame(100.0, 0.5, 8.0, 0, 0.0), &mut outbox)
+        .await;
+    assert_eq!(outbox.len(), 1);
+    assert!(state.connection_performance.lock().await.contains_key(&4));
+}
+
+#[test]
+fn reconciliation_allows_either_side_of_an_unconfirmed_trade() {
+    let reconciliation = |expected_position, unconfirmed_volume, reported_position| {
+        Reconciliation {
+            expected_position,
+            unconfirmed_volume,
+            reported_position,
+            dropped_puzzle: false,
+            dropped_stance: Stance::Flat,
+        }
+        .contradicts()
+    };
+    assert!(!reconciliation(Some(3), 3, 3));
+    assert!(!reconciliation(Some(3), 3, 0));
+    assert!(reconciliation(Some(3), 3, -3));
+    assert!(reconciliation(Some(2), 0, 1));
+    assert!(!reconciliation(None, 0, 1));
+}
+
+#[test]
+fn session_resync_expects_pending_trades_to_fill() {
+    let mut session = SessionContext {
+        position: Some(-1),
+        stance: Stance::Long,
+        ..SessionContext::default()
+    };
+    session.begin_resync(Some(&PendingTrade::new(-1, 4)));
+    assert!(session.is_resyncing());
+
+    let reconciliation = session.reconcile(0).unwrap();
+    assert_eq!(reconciliation.expected_position, Some(3));
+    assert_eq!(reconciliation.dropped_stance, Stance::Long);
+    assert!(reconciliation.contradicts());
+    assert_eq!(session.stance, Stance::Flat);
+    assert_eq!(session.reconcile(0), None);
+}
+
+#[optiva_ws::rt::test]
+async fn reconnect_mid_game_trades_only_after_reconciling() {
+    let config = Arc::new(common::test_config());
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&common::state_frame(100.0, 0.5, 8.0, 0, 0.0), &mut outbox)
+        .await;
+    assert_eq!(outbox.len(), 1);
+
+    // Dropped before the trade was confirmed
+    common::join(&mut handler).await;
+    {
+        let performances = state.connection_performance.lock().await;
+        let resync = performances[&0].session.resync.unwrap();
+        assert_eq!(resync.expected_position, Some(3));
+        assert_eq!(resync.unconfirmed_volume, 3);
+    }
+
+    // Nothing to check a puzzle trade against yet
+    let mut outbox = Vec::new();
+    let puzzle = json!({ "event": "puzzle", "data": { "impact": -2.0 } }).to_string();
+    handler.handle_text(&puzzle, &mut outbox).await;
+    assert!(outbox
+        .iter()
+        .all(|message| !matches!(message.event, ClientEvent::Trade(_))));
+
+    handler
+        .handle_text(&common::state_frame(101.0, 0.5, 8.0, 3, 40.0), &mut outbox)
+        .await;
+    let performances = state.connection_performance.lock().await;
+    let perf = &performances[&0];
+    assert!(!perf.session.is_resyncing());
+    assert_eq!(perf.session.position, Some(3));
+    // The PnL made while away isn't credited to anything
+    assert_eq!(perf.last_pnl, 40.0);
+    assert!(perf.baseline_set);
+}
+
+#[optiva_ws::rt::test]
+async fn new_game_starts_without_resync() {
+    let config = Arc::new(common::test_config());
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+    handler
+        .handle_text(
+            &common::state_frame(100.0, 0.5, 8.0, 0, 0.0),
+            &mut Vec::new(),
+        )
+        .await;
+    let finish = json!({ "event": "finish", "data": {} }).to_string();
+    handler.handle_text(&finish, &mut Vec::new()).await;
+
+    common::join(&mut handler).await;
+    let performances = state.connection_performance.lock().await;
+    assert!(!performances[&0].session.is_resyncing());
+    assert_eq!(performances[&0].session.position, None);
+}
+
+#[optiva_ws::rt::test]
+async fn snapshot_copies_each_connection_in_order() {
+    let config = Arc::new(common::test_config());
+    let state = Arc::new(SharedState::new(&config));
+    for conn_id in [1, 0] {
+        let mut handler = ConnectionHandler::new(conn_id, Arc::clone(&config), Arc::clone(&state));
+        common::join(&mut handler).await;
+        for (position, pnl) in [(0, 0.0), (3, 12.0)] {
+            handler
+                .handle_text(
+                    &common::state_frame(100.0, 0.5, 8.0, position, pnl),
+                    &mut Vec::new(),
+                )
+                .await;
+        }
+    }
+
+    let snapshot = state.snapshot().await;
+    let conn_ids: Vec<usize> = snapshot.connections.iter().map(|c| c.conn_id).collect();
+    assert_eq!(conn_ids, [0, 1]);
+    let connection = &snapshot.connections[0];
+    assert_eq!(connection.price, Some(100.0));
+    assert_eq!(connection.position, Some(3));
+    assert_eq!(connection.recent_pnl, [0.0, 12.0]);
+    assert!(connection.last_trade.is_some_and(|trade| trade.volume > 0));
+    assert!(connection.signal.is_some_and(|signal| signal > 0.0));
+}
+
+#[optiva_ws::rt::test]
+async fn snapshot_summarizes_histories_and_totals_pnl() {
+    let config = Arc::new(common::test_config());
+    let state = Arc::new(SharedState::new(&config));
+    for (conn_id, forecast) in [(0, 0.5), (1, -0.5)] {
+        let mut handler = ConnectionHandler::new(conn_id, Arc::clone(&config), Arc::clone(&state));
+        common::join(&mut handler).await;
+        handler
+            .handle_text(
+                &common::state_frame(100.0, forecast, 0.0, 0, 5.0 + conn_id as f64),
+                &mut Vec::new(),
+            )
+            .await;
+    }
+
+    let snapshot = state.snapshot().await;
+    assert_eq!(snapshot.total_pnl, 11.0);
+    assert_eq!(snapshot.trade_history.len, 2);
+    assert_eq!(snapshot.trade_history.last.as_ref().unwrap().conn_id, 1);
+    // One buy and one sell of the same strength
+    assert!(snapshot.trade_history.mean.unwrap().abs() < 1e-9);
+    assert_eq!(snapshot.performance_history.len, 0);
+    assert_eq!(snapshot.performance_history.mean, None);
+
+    let decisions: Vec<_> = snapshot
+        .connections
+        .iter()
+        .map(|connection| connection.last_decision.as_ref().unwrap())
+        .collect();
+    assert_eq!(decisions[0].conn_id, 0);
+    assert!(decisions[0].combined_signal > 0.0);
+    assert_eq!(decisions[1].conn_id, 1);
+    assert!(decisions[1].combined_signal < 0.0);
+}
+
+#[optiva_ws::rt::test]
+async fn snapshots_alongside_trading_never_deadlock() {
+    let config = Arc::new(common::test_config());
+    let state = Arc::new(SharedState::new(&config));
+    let traders: Vec<_> = (0..4)
+        .map(|conn_id| {
+            let config = Arc::clone(&config);
+            let state = Arc::clone(&state);
+            rt::spawn(async move {
+                let mut handler = ConnectionHandler::new(conn_id, config, state);
+                common::join(&mut handler).await;
+                for tick in 0..50 {
+                    let sign = if tick % 2 == 0 { 1.0 } else { -1.0 };
+                    let frame = common::state_frame(100.0, sign, sign * 10.0, 0, tick as f64);
+                    handler.handle_text(&frame, &mut Vec::new()).await;
+                }
+            })
+        })
+        .collect();
+    let reader = {
+        let state = Arc::clone(&state);
+        rt::spawn(async move {
+            for _ in 0..200 {
+                state.snapshot().await;
+            }
+        })
+    };
+
+    rt::timeout(std::time::Duration::from_secs(10), async {
+        futures::future::join_all(traders).await;
+        reader.await;
+    })
+    .await
+    .expect("snapshot and trading deadlocked");
+    assert_eq!(state.snapshot().await.connections.len(), 4);
+}
+
+#[test]
+fn revised_pending_trade_drops_what_the_server_rejected() {
+    let mut pending = PendingTrade::new(0, 2);
+    pending.add(3);
+    pending.revise(3, 1);
+    assert_eq!(
+        pending,
+        PendingTrade {
+            position_before: 0,
+            volume: 3,
+            trades: 2,
+            updates_waited: 0
+        }
+    );
+    pending.revise(1, 0);
+    assert_eq!(
+        pending,
+        PendingTrade {
+            position_before: 0,
+            volume: 2,
+            trades: 1,
+            updates_waited: 0
+        }
+    );
+}
+
+fn server_error(message: &str) -> String {
+    json!({ "event": "error", "data": { "message": message } }).to_string()
+}
+
+fn limited_state_frame(forecast: f64, momentum: f64, position: i32, limit: i32) -> String {
+    let mut frame: serde_json::Value = serde_json::from_str(&common::state_frame(
+        100.0, forecast, momentum, position, 0.0,
+    ))
+    .unwrap();
+    frame["data"]["position_limit"] = json!(limit);
+    frame.to_string()
+}
+
+#[optiva_ws::rt::test]
+async fn trade_is_not_resent_while_the_server_is_slow_to_fill_it() {
+    let config = Arc::new(common::test_config());
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+
+    // A strong signal keeps asking to be long to the limit
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&limited_state_frame(1.0, 10.0, 0, 3), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [3]);
+
+    // The next update doesn't show it yet, so only the difference would go
+    handler
+        .handle_text(&limited_state_frame(1.0, 10.0, 0, 3), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [3]);
+
+    // After another update without a move it's given up on and sent again
+    handler
+        .handle_text(&limited_state_frame(1.0, 10.0, 0, 3), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [3, 3]);
+
+    // Part of it shows up, and the rest is still waited on
+    handler
+        .handle_text(&limited_state_frame(1.0, 10.0, 1, 3), &mut outbox)
+        .await;
+    handler
+        .handle_text(&limited_state_frame(1.0, 10.0, 3, 3), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [3, 3]);
+
+    let performances = state.connection_performance.lock().await;
+    assert_eq!(performances[&0].rejected_trades, 1);
+    assert_eq!(performances[&0].trades_made, 1);
+}
+
+#[optiva_ws::rt::test]
+async fn rejected_volume_is_resent_once_clamped_to_the_limit() {
+    let config = Arc::new(common::test_config());
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&limited_state_frame(1.0, 10.0, 0, 5), &mut outbox)
+        .await;
+    // The limit came down before the server got to the trade; a weak signal holds
+    handler
+        .handle_text(&limited_state_frame(0.0, 0.0, 0, 3), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [5]);
+
+    let flow = handler
+        .handle_text(&server_error("Volume exceeds limit"), &mut outbox)
+        .await;
+    assert_eq!(flow, Flow::Continue);
+    assert_eq!(trade_volumes(&outbox), [5, 3]);
+
+    // Only once
+    handler
+        .handle_text(&server_error("Volume exceeds limit"), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [5, 3]);
+    let performances = state.connection_performance.lock().await;
+    assert_eq!(performances[&0].server_errors, 2);
+}
+
+#[optiva_ws::rt::test]
+async fn a_resend_is_held_by_the_kill_switch() {
+    let config = Arc::new(common::test_config());
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&limited_state_frame(1.0, 10.0, 0, 5), &mut outbox)
+        .await;
+    handler
+        .handle_text(&limited_state_frame(0.0, 0.0, 0, 3), &mut outbox)
+        .await;
+    state.set_trading_enabled(false);
+    handler
+        .handle_text(&server_error("Volume exceeds limit"), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [5]);
+}
+
+#[optiva_ws::rt::test]
+async fn a_resend_stays_under_the_exposure_ceiling() {
+    let mut config = common::test_config();
+    config.exposure.max_net_position = Some(5);
+    let config = Arc::new(config);
+    let state = Arc::new(SharedState::new(&config));
+    let mut first = ConnectionHandler::new(0, Arc::clone(&config), Arc::clone(&state));
+    let mut second = ConnectionHandler::new(1, config, Arc::clone(&state));
+    common::join(&mut first).await;
+    common::join(&mut second).await;
+
+    let mut outbox = Vec::new();
+    first
+        .handle_text(&limited_state_frame(1.0, 10.0, 0, 5), &mut outbox)
+        .await;
+    first
+        .handle_text(&limited_state_frame(0.0, 0.0, 0, 3), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [5]);
+    // The other connection holds 3 by the time the server turns the trade down
+    let mut other = Vec::new();
+    second
+        .handle_text(&limited_state_frame(0.0, 0.0, 3, 3), &mut other)
+        .await;
+    assert!(trade_volumes(&other).is_empty());
+
+    first
+        .handle_text(&server_error("Volume exceeds limit"), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [5, 2]);
+    let usage = state.exposure_book.lock().await.usage(&state.exposure);
+    assert_eq!(usage.net_position, 5);
+}
+
+#[cfg(feature = "puzzles")]
+#[optiva_ws::rt::test]
+async fn rejected_trade_not_resent_is_dropped_from_the_pending_trade() {
+    let config = Arc::new(common::test_config());
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&limited_state_frame(1.0, 10.0, 2, 3), &mut outbox)
+        .await;
+    // The puzzle sells to the limit from where the pending buy leaves us. It fits
+    // the limit, so a rejection isn't resent.
+    let puzzle = json!({ "event": "puzzle", "data": { "impact": -2.0 } }).to_string();
+    handler.handle_text(&puzzle, &mut outbox).await;
+    assert_eq!(trade_volumes(&outbox), [1, -6]);
+
+    handler
+        .handle_text(&server_error("Volume exceeds limit"), &mut outbox)
+        .await;
+    assert_eq!(trade_volumes(&outbox), [1, -6]);
+
+    // Only the first trade is expected to have filled
+    handler
+        .handle_text(&limited_state_frame(0.0, 0.0, 3, 3), &mut outbox)
+        .await;
+    let performances = state.connection_performance.lock().await;
+    assert_eq!(performances[&0].trades_made, 1);
+    assert_eq!(performances[&0].rejected_trades, 1);
+}
+
+#[optiva_ws::rt::test]
+async fn repeated_auth_errors_end_the_session() {
+    let config = Arc::new(common::test_config());
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, state);
+    common::join(&mut handler).await;
+
+    let mut outbox = Vec::new();
+    for _ in 0..2 {
+        let flow = handler
+            .handle_text(&server_error("Invalid player id"), &mut outbox)
+            .await;
+        assert_eq!(flow, Flow::Continue);
+    }
+    let flow = handler
+        .handle_text(&server_error("Invalid player id"), &mut outbox)
+        .await;
+    assert_eq!(flow, Flow::Reconnect);
+
+    // A new session starts counting again
+    common::join(&mut handler).await;
+    let flow = handler
+        .handle_text(&server_error("Invalid player id"), &mut outbox)
+        .await;
+    assert_eq!(flow, Flow::Continue);
+}
+
+#[optiva_ws::rt::test]
+async fn the_acks_token_goes_with_everything_after_it() {
+    let config = Arc::new(common::test_config());
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, state);
+    let opening = handler.start_session().await;
+    assert_eq!(opening[0].token, None);
+
+    let ack = json!({
+        "event": "connection",
+        "data": { "player_id": common::PLAYER_ID, "token": "s3cret-token", "session_id": "g-42" }
+    })
+    .to_string();
+    let mut outbox = Vec::new();
+    handler.handle_text(&ack, &mut outbox).await;
+    handler
+        .handle_text(&common::state_frame(100.0, 0.5, 8.0, 0, 0.0), &mut outbox)
+        .await;
+    let puzzle = json!({ "event": "puzzle", "data": { "impact": 2.0 } }).to_string();
+    handler.handle_text(&puzzle, &mut outbox).await;
+    assert!(outbox.len() >= 3);
+    for message in &outbox {
+        assert_eq!(
+            message.token.as_ref().map(|token| token.expose()),
+            Some("s3cret-token")
+        );
+        assert_eq!(message.session_id.as_deref(), Some("g-42"));
+    }
+
+    // The reconnect handshake hands it back
+    let reconnect = handler.start_session().await;
+    let ClientEvent::Connection(data) = &reconnect[0].event else {
+        panic!("not a connection message");
+    };
+    assert_eq!(data.token.expose(), "s3cret-token");
+
+    // Turned down, it's dropped and the handshake starts from scratch
+    let flow = handler
+        .handle_text(&server_error("Invalid token"), &mut outbox)
+        .await;
+    assert_eq!(flow, Flow::Reauthenticate);
+    let fresh = handler.start_session().await;
+    let ClientEvent::Connection(data) = &fresh[0].event else {
+        panic!("not a connection message");
+    };
+    assert!(data.token.is_empty());
+    assert_eq!(fresh[0].token, None);
+}
+
+#[test]
+fn limit_changes_are_noticed() {
+    let mut session = SessionContext::default();
+    assert_eq!(session.observe_limit(3), None);
+    assert_eq!(session.observe_limit(3), None);
+    assert_eq!(session.observe_limit(5), Some(3));
+    assert_eq!(session.position_limit, Some(5));
+}
+
+fn instrument_frame(instrument: &str, signal: f64, position: i32) -> String {
+    json!({
+        "event": "state",
+        "data": {
+            "price": 100.0,
+            "price_forecast": signal * 0.5,
+            "momentum": signal * 8.0,
+            "position": position,
+            "position_limit": 3,
+            "pnl": 0.0,
+            "instrument": instrument
+        }
+    })
+    .to_string()
+}
+
+fn instrument_trades(outbox: &[ClientMessage]) -> Vec<(i32, Option<String>)> {
+    outbox
+        .iter()
+        .filter_map(|message| match &message.event {
+            ClientEvent::Trade(trade) => Some((trade.volume, trade.instrument.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+#[optiva_ws::rt::test]
+async fn instruments_are_tracked_and_traded_independently() {
+    let config = Arc::new(common::test_config());
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+
+    // Each goes to its own limit, neither sized from the other's pending trade
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&instrument_frame("A", 1.0, 0), &mut outbox)
+        .await;
+    handler
+        .handle_text(&instrument_frame("B", -1.0, 0), &mut outbox)
+        .await;
+    assert_eq!(
+        instrument_trades(&outbox),
+        [(3, Some("A".to_string())), (-3, Some("B".to_string()))]
+    );
+
+    // Both fills show up in their own positions, so nothing more goes out
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&instrument_frame("A", 1.0, 3), &mut outbox)
+        .await;
+    handler
+        .handle_text(&instrument_frame("B", -1.0, -3), &mut outbox)
+        .await;
+    assert!(instrument_trades(&outbox).is_empty());
+    {
+        let performances = state.connection_performance.lock().await;
+        let perf = &performances[&0];
+        assert_eq!(perf.trades_made, 2);
+        assert_eq!(perf.rejected_trades, 0);
+        assert_eq!(perf.session.position, Some(-3));
+    }
+
+    // A turns around from its own position
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&instrument_frame("A", -1.0, 3), &mut outbox)
+        .await;
+    assert_eq!(instrument_trades(&outbox), [(-6, Some("A".to_string()))]);
+}
+
+#[optiva_ws::rt::test]
+async fn a_single_named_instrument_trades_as_before() {
+    let config = Arc::new(common::test_config());
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+
+    let mut outbox = Vec::new();
+    for position in [0, 0, 3] {
+        handler
+            .handle_text(&instrument_frame("A", 1.0, position), &mut outbox)
+            .await;
+    }
+    assert_eq!(instrument_trades(&outbox), [(3, Some("A".to_string()))]);
+    let performances = state.connection_performance.lock().await;
+    assert_eq!(performances[&0].trades_made, 1);
+}
+
+#[optiva_ws::rt::test]
+async fn unreadable_frames_only_poison_the_connection_when_they_come_fast() {
+    let mut config = common::test_config();
+    config.frames.max_bytes = 256;
+    config.frames.max_errors_per_minute = 3;
+    let config = Arc::new(config);
+    let clock = Arc::new(ManualClock::new(100.0));
+    let state = Arc::new(SharedState::with_clock(&config, clock.clone()));
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+    let mut outbox = Vec::new();
+
+    let oversized = json!({ "event": "puzzle", "data": { "question": "x".repeat(300) } });
+    let unreadable = [
+        oversized.to_string(),
+        r#"{"event": "state", "da"#.to_string(),
+    ];
+    for frame in &unreadable {
+        assert_eq!(
+            handler.handle_text(frame, &mut outbox).await,
+            Flow::Continue
+        );
+    }
+    // Neither was taken for a puzzle or a state update
+    assert!(outbox.is_empty());
+
+    // A minute on, those two no longer count
+    clock.advance(60.0);
+    for frame in &unreadable {
+        assert_eq!(
+            handler.handle_text(frame, &mut outbox).await,
+            Flow::Continue
+        );
+    }
+    assert_eq!(
+        handler.handle_text("{", &mut outbox).await,
+        Flow::Unreadable
+    );
+
+    let perf = state.connection_performance.lock().await[&0].clone();
+    assert_eq!(perf.oversized_frames, 2);
+    assert_eq!(perf.malformed_frames, 3);
+    assert_eq!(perf.session.updates_seen, 0);
+}
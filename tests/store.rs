@@ -0,0 +1,147 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use common::{state_frame, temp_dir, test_config};
+use optiva_ws::handler::ConnectionHandler;
+use optiva_ws::journal::{
+    fallback_path, load_journal, read_journal, ConnectionEvent, ConnectionEventKind, Journal,
+    JournalConfig, JournalFormat,
+};
+use optiva_ws::rt;
+use optiva_ws::state::{SharedState, StrategyParams};
+use optiva_ws::store::SqliteStore;
+use rusqlite::Connection;
+use serde_json::json;
+use std::path::Path;
+use std::sync::Arc;
+
+fn database_config(name: &str) -> JournalConfig {
+    JournalConfig {
+        enabled: true,
+        path: temp_dir(name).join("journal.db"),
+        format: JournalFormat::Sqlite,
+        flush_secs: 0.05,
+    }
+}
+
+fn count(path: &Path, table: &str) -> usize {
+    let conn = Connection::open(path).unwrap();
+    conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+        row.get(0)
+    })
+    .unwrap()
+}
+
+// A game's decision and summary from the handler, plus what the optimizer and the
+// connection loop send
+async fn play(journal: &Journal) {
+    let config = Arc::new(test_config());
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, state).with_journal(journal.clone());
+    common::join(&mut handler).await;
+    let mut outbox = Vec::new();
+    handler
+        .handle_text(&state_frame(100.0, 0.5, 8.0, 0, 0.0), &mut outbox)
+        .await;
+    let finish = json!({ "event": "finish", "data": { "pnl": 6.0 } }).to_string();
+    handler.handle_text(&finish, &mut outbox).await;
+
+    let before = StrategyParams::default();
+    let after = StrategyParams {
+        momentum_weight: 0.8,
+        forecast_weight: 0.2,
+        ..before.clone()
+    };
+    journal
+        .clone()
+        .for_account("alpha")
+        .params_changed(1000.0, &before, &after);
+    journal.connection(ConnectionEvent {
+        timestamp: 1000.0,
+        account: String::new(),
+        conn_id: 0,
+        kind: ConnectionEventKind::Connected,
+        detail: Some("ws://localhost:9000".to_string()),
+    });
+}
+
+#[optiva_ws::rt::test]
+async fn every_kind_of_record_gets_its_table() {
+    let config = database_config("store");
+    let (journal, writer) = Journal::spawn(&config).await.unwrap();
+    play(&journal).await;
+    drop(journal);
+    writer.await;
+
+    assert_eq!(count(&config.path, "decisions"), 1);
+    assert_eq!(count(&config.path, "games"), 1);
+    assert_eq!(count(&config.path, "param_changes"), 2);
+    assert_eq!(count(&config.path, "connection_events"), 1);
+
+    let conn = Connection::open(&config.path).unwrap();
+    let (final_pnl, summary): (f64, String) = conn
+        .query_row("SELECT final_pnl, summary FROM games", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .unwrap();
+    assert_eq!(final_pnl, 6.0);
+    assert!(summary.contains("\"final_pnl\":6.0"), "{}", summary);
+    let account: String = conn
+        .query_row("SELECT account FROM param_changes LIMIT 1", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(account, "alpha");
+
+    // The analysis reads decisions back the same as from a file
+    let entries = read_journal(&config.path).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].position_after, 3);
+    assert!(entries[0].sent);
+    assert_eq!(entries[0].decision_id, Some(1));
+    assert!(entries[0].driver.is_some());
+    assert_eq!(load_journal(&config.path).unwrap().entries, entries);
+}
+
+#[optiva_ws::rt::test]
+async fn batches_are_written_while_running_and_kept_across_runs() {
+    let config = database_config("store-rerun");
+    for run in 1..=2 {
+        let (journal, writer) = Journal::spawn(&config).await.unwrap();
+        play(&journal).await;
+        rt::sleep(std::time::Duration::from_millis(300)).await;
+        assert_eq!(count(&config.path, "decisions"), run);
+        drop(journal);
+        writer.await;
+    }
+    // Opening a migrated database leaves the schema as it is
+    SqliteStore::open(&config.path).unwrap();
+    let conn = Connection::open(&config.path).unwrap();
+    let version: usize = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(version, 2);
+    assert_eq!(count(&config.path, "games"), 2);
+}
+
+#[optiva_ws::rt::test]
+async fn a_broken_database_falls_back_to_jsonl() {
+    let config = database_config("store-broken");
+    std::fs::write(&config.path, "not a database, not even close").unwrap();
+    assert!(SqliteStore::open(&config.path).is_err());
+
+    let (journal, writer) = Journal::spawn(&config).await.unwrap();
+    play(&journal).await;
+    drop(journal);
+    writer.await;
+
+    let fallback = fallback_path(&config.path);
+    assert_eq!(fallback.extension().unwrap(), "jsonl");
+    let entries = read_journal(&fallback).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(
+        std::fs::read_to_string(&config.path).unwrap(),
+        "not a database, not even close"
+    );
+}
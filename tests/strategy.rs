@@ -0,0 +1,781 @@
+mod common;
+
+use std::sync::Arc;
+
+use optiva_ws::clock::{ManualClock, Monotonic};
+use optiva_ws::handler::ConnectionHandler;
+use optiva_ws::history::Indicators;
+use optiva_ws::protocol::ClientEvent;
+use optiva_ws::state::{PerformanceData, SharedState, Sizing, StrategyParams};
+use optiva_ws::strategy::{
+    clamp_outliers, decayed_stats, determine_trade_volume, dominant_driver, median, sharpe_ratio,
+    signals_agree, size_trade, veto, AgreementConfig, BlendFadeStrategy, BlendStrategy, Conflict,
+    DecisionMode, Driver, ForecastOnlyStrategy, KellyEstimate, MarketContext,
+    MeanReversionStrategy, SignalMode, Stance, Strategy,
+};
+
+fn state_at(time: f64) -> (Arc<ManualClock>, SharedState) {
+    let clock = Arc::new(ManualClock::new(time));
+    let state = SharedState::with_clock(&common::test_config(), clock.clone());
+    (clock, state)
+}
+
+fn ctx(forecast: f64, momentum: f64, position: i32, params: &StrategyParams) -> MarketContext<'_> {
+    MarketContext {
+        forecast,
+        momentum,
+        position,
+        position_limit: 3,
+        recent_prices: &[],
+        indicators: Indicators::default(),
+        params,
+    }
+}
+
+// Run the default blend strategy through the recording path
+async fn blend_volume(
+    forecast: f64,
+    momentum: f64,
+    position: i32,
+    conn_id: usize,
+    params: &StrategyParams,
+    state: &SharedState,
+) -> i32 {
+    let ctx = ctx(forecast, momentum, position, params);
+    determine_trade_volume(&BlendStrategy, &ctx, conn_id, state)
+        .await
+        .volume
+}
+
+fn perf(pnl_change: f64, momentum: f64, forecast: f64) -> PerformanceData {
+    PerformanceData {
+        conn_id: 0,
+        timestamp: Monotonic(0.0),
+        momentum,
+        forecast,
+        position: 0,
+        trade_volume: 1,
+        pnl_change,
+        price: 100.0,
+        total_pnl: 0.0,
+        signal: 0.0,
+        decision_id: 0,
+        driver: Driver::Momentum,
+    }
+}
+
+#[optiva_ws::rt::test]
+async fn positive_signal_buys_up_to_limit() {
+    let (_, state) = state_at(1000.0);
+    let params = StrategyParams::default();
+
+    let volume = blend_volume(0.5, 8.0, -1, 0, &params, &state).await;
+    assert_eq!(volume, 4);
+}
+
+#[optiva_ws::rt::test]
+async fn negative_signal_sells_down_to_limit() {
+    let (_, state) = state_at(1000.0);
+    let params = StrategyParams::default();
+
+    let volume = blend_volume(-0.5, -8.0, 2, 0, &params, &state).await;
+    assert_eq!(volume, -5);
+}
+
+#[optiva_ws::rt::test]
+async fn zero_signal_does_not_trade() {
+    let (_, state) = state_at(1000.0);
+    let params = StrategyParams::default();
+
+    let volume = blend_volume(0.0, 0.0, 2, 0, &params, &state).await;
+    assert_eq!(volume, 0);
+}
+
+#[optiva_ws::rt::test]
+async fn decisions_are_recorded_with_clock_time() {
+    let (clock, state) = state_at(1000.0);
+    let params = StrategyParams::default();
+    let size = common::test_config().history.size;
+
+    for _ in 0..size + 5 {
+        clock.advance(1.0);
+        blend_volume(0.1, 6.0, 0, 2, &params, &state).await;
+    }
+
+    let history = state.trade_history.lock().await;
+    assert_eq!(history.len(), size);
+    let last = history.back().unwrap();
+    assert_eq!(last.conn_id, 2);
+    assert_eq!(last.timestamp, Monotonic(1000.0 + (size + 5) as f64));
+    assert_eq!(last.trade_volume, 3);
+    assert_eq!(last.strategy, "blend");
+}
+
+#[optiva_ws::rt::test]
+async fn decisions_are_numbered_per_connection_and_tagged_with_their_driver() {
+    let (_, state) = state_at(1000.0);
+    let params = StrategyParams::default();
+
+    blend_volume(0.0, 25.0, 0, 0, &params, &state).await;
+    blend_volume(2.0, 0.5, 0, 0, &params, &state).await;
+    blend_volume(0.0, 25.0, 0, 1, &params, &state).await;
+
+    let history = state.trade_history.lock().await;
+    let tags: Vec<(usize, u64, Driver, bool)> = history
+        .iter()
+        .map(|signal| {
+            (
+                signal.conn_id,
+                signal.decision_id,
+                signal.driver,
+                signal.puzzle_driven,
+            )
+        })
+        .collect();
+    assert_eq!(
+        tags,
+        [
+            (0, 1, Driver::Momentum, false),
+            (0, 2, Driver::Forecast, false),
+            (1, 1, Driver::Momentum, false),
+        ]
+    );
+}
+
+#[test]
+fn the_driver_is_the_bigger_weighted_signal() {
+    let params = StrategyParams::default();
+    assert_eq!(dominant_driver(0.1, 20.0, &params), Driver::Momentum);
+    assert_eq!(dominant_driver(-1.5, 2.0, &params), Driver::Forecast);
+    // Weighted, so the same inputs can go the other way
+    let forecast_heavy = StrategyParams {
+        momentum_weight: 0.1,
+        forecast_weight: 0.9,
+        ..params.clone()
+    };
+    assert_eq!(
+        dominant_driver(0.1, 20.0, &forecast_heavy),
+        Driver::Forecast
+    );
+    assert_eq!(dominant_driver(0.0, 0.0, &params), Driver::Momentum);
+}
+
+#[test]
+fn sharpe_ratio_of_known_window() {
+    // Mean 2, sample std dev 2
+    let sharpe = sharpe_ratio(&[0.0, 2.0, 4.0, 0.0, 4.0]).unwrap();
+    assert!((sharpe - 1.0).abs() < 1e-9);
+
+    // No variance, or not enough data, has no ratio
+    assert_eq!(sharpe_ratio(&[0.0; 5]), None);
+    assert_eq!(sharpe_ratio(&[3.0; 5]), None);
+    assert_eq!(sharpe_ratio(&[1.0]), None);
+}
+
+#[test]
+fn decayed_stats_with_equal_ages_match_the_plain_sharpe() {
+    let changes = [1.0, 3.0, 1.0, 3.0, 7.0];
+    let aged: Vec<(f64, f64)> = changes.iter().map(|&change| (4.0, change)).collect();
+    let stats = decayed_stats(&aged, 10.0).unwrap();
+    assert!((stats.mean - 3.0).abs() < 1e-9);
+    assert!((stats.sharpe.unwrap() - sharpe_ratio(&changes).unwrap()).abs() < 1e-9);
+    assert_eq!(decayed_stats(&aged[..1], 10.0), None);
+    assert_eq!(
+        decayed_stats(&[(0.0, 2.0), (5.0, 2.0)], 10.0)
+            .unwrap()
+            .sharpe,
+        None
+    );
+}
+
+#[test]
+fn decayed_stats_weigh_by_half_life() {
+    // Weights 1 and 0.25 two half-lives on
+    let stats = decayed_stats(&[(0.0, 10.0), (20.0, 0.0)], 10.0).unwrap();
+    assert!((stats.mean - 8.0).abs() < 1e-9);
+    // Sum w(x - mean)^2 = 4 + 16, over V1 - V2/V1 = 1.25 - 1.0625/1.25 = 0.4
+    assert!((stats.std_dev - 50f64.sqrt()).abs() < 1e-9);
+    assert!((stats.sharpe.unwrap() - 8.0 / 50f64.sqrt()).abs() < 1e-9);
+
+    // Old losses barely count against a run of recent wins
+    let mut history = vec![(100.0, -20.0), (95.0, -22.0), (90.0, -18.0)];
+    history.extend([(3.0, 9.0), (2.0, 11.0), (1.0, 10.0)]);
+    let decayed = decayed_stats(&history, 10.0).unwrap();
+    let plain: Vec<f64> = history.iter().map(|&(_, change)| change).collect();
+    assert!(decayed.mean > 9.0);
+    assert!(sharpe_ratio(&plain).unwrap() < 0.0);
+    assert!(decayed.sharpe.unwrap() > 0.5);
+}
+
+fn sizing(sizing: Sizing) -> StrategyParams {
+    StrategyParams {
+        sizing,
+        ..StrategyParams::default()
+    }
+}
+
+// Between the medium and strong thresholds, so the base size applies
+const MEDIUM: f64 = 7.0;
+
+#[test]
+fn all_in_sizing_goes_to_the_limit() {
+    let params = sizing(Sizing::AllIn);
+    assert_eq!(size_trade(0.01, MEDIUM, 0, 3, &params), 3);
+    assert_eq!(size_trade(-0.01, -MEDIUM, 1, 3, &params), -4);
+    assert_eq!(size_trade(0.5, MEDIUM, 3, 3, &params), 0);
+}
+
+#[test]
+fn proportional_sizing_trades_the_delta() {
+    let params = sizing(Sizing::Proportional);
+    // 0.5 * 4 = 2, already holding 1
+    assert_eq!(size_trade(0.5, MEDIUM, 1, 4, &params), 1);
+    // -0.4 * 5 = -2, currently long 2
+    assert_eq!(size_trade(-0.4, MEDIUM, 2, 5, &params), -4);
+    // A weak signal rounds to a flat target
+    assert_eq!(size_trade(0.1, MEDIUM, 2, 3, &params), -2);
+}
+
+#[test]
+fn zero_signal_leaves_position_alone() {
+    assert_eq!(
+        size_trade(0.0, MEDIUM, 2, 3, &sizing(Sizing::Proportional)),
+        0
+    );
+    assert_eq!(size_trade(0.0, MEDIUM, -3, 3, &sizing(Sizing::AllIn)), 0);
+}
+
+#[test]
+fn proportional_sizing_never_exceeds_limit() {
+    let params = sizing(Sizing::Proportional);
+    for limit in 1..=10 {
+        for position in -limit..=limit {
+            for step in -20..=20 {
+                let signal = step as f64 / 20.0;
+                for momentum in [0.0, MEDIUM, 50.0] {
+                    let volume = size_trade(signal, momentum, position, limit, &params);
+                    assert!((position + volume).abs() <= limit);
+                }
+            }
+        }
+    }
+    // Signals outside (-1, 1) still clamp to the limit
+    assert_eq!(size_trade(1.4, MEDIUM, 0, 3, &params), 3);
+}
+
+#[test]
+fn strong_momentum_scales_by_aggressive_factor() {
+    let params = sizing(Sizing::Proportional);
+    // 0.5 * 4 = 2, times 1.5
+    assert_eq!(size_trade(0.5, 12.0, 0, 4, &params), 3);
+    assert_eq!(size_trade(-0.5, -12.0, 0, 4, &params), -3);
+}
+
+#[test]
+fn strong_momentum_is_clamped_to_limit() {
+    let params = StrategyParams {
+        aggressive_factor: 3.0,
+        ..sizing(Sizing::Proportional)
+    };
+    assert_eq!(size_trade(0.8, 15.0, 0, 4, &params), 4);
+    assert_eq!(size_trade(0.8, 15.0, 0, 4, &sizing(Sizing::AllIn)), 4);
+}
+
+#[test]
+fn medium_momentum_uses_base_size() {
+    let params = sizing(Sizing::Proportional);
+    assert_eq!(size_trade(0.5, 5.0, 0, 4, &params), 2);
+    assert_eq!(size_trade(0.5, 10.0, 0, 4, &params), 2);
+}
+
+#[test]
+fn weak_momentum_takes_a_fraction() {
+    let params = sizing(Sizing::AllIn);
+    // Half of the limit of 4
+    assert_eq!(size_trade(0.5, 2.0, 0, 4, &params), 2);
+    assert_eq!(size_trade(-0.5, -2.0, 0, 4, &params), -2);
+}
+
+#[test]
+fn weak_momentum_can_be_skipped() {
+    let params = StrategyParams {
+        weak_momentum_fraction: 0.0,
+        ..sizing(Sizing::AllIn)
+    };
+    assert_eq!(size_trade(0.5, 2.0, -1, 4, &params), 0);
+}
+
+#[optiva_ws::rt::test]
+async fn proportional_mode_is_used_from_params() {
+    let (_, state) = state_at(1000.0);
+    let params = StrategyParams {
+        sizing: Sizing::Proportional,
+        ..StrategyParams::default()
+    };
+
+    // Strong agreeing signals round to the full limit
+    let volume = blend_volume(2.0, 30.0, 0, 0, &params, &state).await;
+    assert_eq!(volume, 3);
+}
+
+#[test]
+fn forecast_only_ignores_momentum() {
+    let params = StrategyParams::default();
+    let against = ForecastOnlyStrategy.decide(&ctx(0.5, -30.0, 0, &params));
+    assert!(against.signal > 0.0);
+    assert_eq!(against.volume, 3);
+
+    let flat = ForecastOnlyStrategy.decide(&ctx(0.0, 30.0, 1, &params));
+    assert_eq!(flat.volume, 0);
+}
+
+#[test]
+fn tanh_scaling_comes_from_the_params() {
+    let params = StrategyParams::default();
+    let signal = BlendStrategy.signal(&ctx(0.25, 5.0, 0, &params));
+    assert!((signal - (0.6 * (0.5f64).tanh() + 0.4 * (0.5f64).tanh())).abs() < 1e-9);
+
+    let rescaled = StrategyParams {
+        momentum_divisor: 5.0,
+        forecast_multiplier: 4.0,
+        ..StrategyParams::default()
+    };
+    let signal = BlendStrategy.signal(&ctx(0.25, 5.0, 0, &rescaled));
+    assert!((signal - (1.0f64).tanh()).abs() < 1e-9);
+
+    let zero = StrategyParams {
+        momentum_divisor: 0.0,
+        ..StrategyParams::default()
+    };
+    assert!(zero.validate().is_err());
+}
+
+#[test]
+fn mean_reversion_fades_strong_momentum_only() {
+    let params = StrategyParams::default();
+    let spike = MeanReversionStrategy.decide(&ctx(0.5, 15.0, 1, &params));
+    assert!(spike.signal < 0.0);
+    assert_eq!(spike.volume, -4);
+
+    let drop = MeanReversionStrategy.decide(&ctx(0.0, -15.0, 0, &params));
+    assert_eq!(drop.volume, 3);
+
+    // Within the strong threshold there's nothing to fade
+    let calm = MeanReversionStrategy.decide(&ctx(0.5, 8.0, 1, &params));
+    assert_eq!((calm.signal, calm.volume), (0.0, 0));
+}
+
+#[test]
+fn blend_fade_fades_an_extreme_move_the_forecast_disagrees_with() {
+    let params = StrategyParams::default();
+    // Way past the extreme threshold with the forecast pointing back down
+    let overshoot = BlendFadeStrategy.decide(&ctx(-0.5, 30.0, 3, &params));
+    assert_eq!(overshoot.mode, DecisionMode::Fade);
+    assert!(overshoot.signal < 0.0);
+    // Sized by how far past it is rather than all-in
+    assert_eq!(overshoot.volume, -5);
+    let barely = BlendFadeStrategy.decide(&ctx(-0.5, 22.0, 0, &params));
+    assert_eq!(barely.mode, DecisionMode::Fade);
+    assert_eq!(barely.volume, -1);
+
+    // The forecast agreeing, or momentum short of extreme, is left to the blend
+    for (forecast, momentum) in [(0.5, 30.0), (0.0, 30.0), (-0.5, 15.0)] {
+        let context = ctx(forecast, momentum, 0, &params);
+        let decision = BlendFadeStrategy.decide(&context);
+        assert_eq!(decision.mode, DecisionMode::Follow);
+        assert_eq!(decision, BlendStrategy.decide(&context));
+    }
+}
+
+#[test]
+fn extreme_threshold_must_be_beyond_strong() {
+    let params = StrategyParams {
+        extreme_momentum_threshold: 10.0,
+        ..StrategyParams::default()
+    };
+    assert!(params.validate().is_err());
+    assert!(StrategyParams::default().validate().is_ok());
+}
+
+#[optiva_ws::rt::test]
+async fn every_strategy_is_recorded_under_its_name() {
+    let (_, state) = state_at(1000.0);
+    let params = StrategyParams::default();
+    let strategies: [&dyn Strategy; 4] = [
+        &BlendStrategy,
+        &ForecastOnlyStrategy,
+        &MeanReversionStrategy,
+        &BlendFadeStrategy,
+    ];
+
+    for strategy in strategies {
+        determine_trade_volume(strategy, &ctx(-0.5, 25.0, 0, &params), 0, &state).await;
+    }
+
+    let history = state.trade_history.lock().await;
+    let names: Vec<_> = history.iter().map(|signal| signal.strategy).collect();
+    assert_eq!(
+        names,
+        ["blend", "forecast_only", "mean_reversion", "blend_fade"]
+    );
+    // And under the mode that made the decision
+    let modes: Vec<_> = history.iter().map(|signal| signal.mode).collect();
+    assert_eq!(
+        modes,
+        [
+            DecisionMode::Follow,
+            DecisionMode::Follow,
+            DecisionMode::Fade,
+            DecisionMode::Fade
+        ]
+    );
+}
+
+#[test]
+fn volatility_spike_shrinks_positions() {
+    let params = StrategyParams {
+        max_volatility: Some(0.01),
+        ..StrategyParams::default()
+    };
+    let mut calm = ctx(0.5, 8.0, 0, &params);
+    calm.indicators.volatility = Some(0.005);
+    assert_eq!(BlendStrategy.decide(&calm).volume, 3);
+
+    // Twice the allowed volatility halves the size
+    let mut spiking = ctx(0.5, 8.0, 0, &params);
+    spiking.indicators.volatility = Some(0.02);
+    assert_eq!(BlendStrategy.decide(&spiking).volume, 2);
+    assert_eq!(ForecastOnlyStrategy.decide(&spiking).volume, 2);
+
+    // Without a maximum set volatility is ignored
+    let defaults = StrategyParams::default();
+    let mut unlimited = ctx(0.5, 8.0, 0, &defaults);
+    unlimited.indicators.volatility = Some(1.0);
+    assert_eq!(BlendStrategy.decide(&unlimited).volume, 3);
+}
+
+// Direction changes over a signal sequence, as the stance sees them
+fn count_flips(signals: &[f64], deadband: f64) -> usize {
+    let mut stance = Stance::Flat;
+    let mut flips = 0;
+    for &signal in signals {
+        let (next, _) = stance.next(signal, deadband);
+        if next != stance && stance != Stance::Flat {
+            flips += 1;
+        }
+        stance = next;
+    }
+    flips
+}
+
+#[test]
+fn deadband_suppresses_flip_flopping() {
+    let oscillating = [0.05, -0.04, 0.1, -0.12, 0.08, -0.05, 0.3, 0.1, -0.1, -0.2];
+    assert_eq!(count_flips(&oscillating, 0.0), 7);
+    // Only the move to 0.3 and the later drop to -0.2 are beyond the band
+    assert_eq!(count_flips(&oscillating, 0.15), 1);
+}
+
+#[test]
+fn stance_holds_inside_the_band() {
+    assert_eq!(Stance::Flat.next(0.1, 0.15), (Stance::Flat, false));
+    assert_eq!(Stance::Flat.next(0.2, 0.15), (Stance::Long, true));
+    assert_eq!(Stance::Long.next(-0.1, 0.15), (Stance::Long, false));
+    assert_eq!(Stance::Long.next(-0.2, 0.15), (Stance::Short, true));
+    assert_eq!(Stance::Short.next(0.0, 0.0), (Stance::Short, false));
+}
+
+#[optiva_ws::rt::test]
+async fn handler_trades_less_on_an_oscillating_signal() {
+    let config = Arc::new(common::test_config());
+    let state = Arc::new(SharedState::new(&config));
+    let mut handler = ConnectionHandler::new(0, config, Arc::clone(&state));
+    common::join(&mut handler).await;
+
+    // Forecast wobbling around zero with no momentum, position following our trades
+    let mut position = 0;
+    let mut trades = 0;
+    for (i, forecast) in [0.05, -0.05, 0.04, -0.06, 0.2, -0.03, 0.05, -0.2]
+        .into_iter()
+        .enumerate()
+    {
+        let mut outbox = Vec::new();
+        let frame = common::state_frame(100.0, forecast, 0.0, position, i as f64);
+        handler.handle_text(&frame, &mut outbox).await;
+        for message in outbox {
+            if let ClientEvent::Trade(trade) = message.event {
+                position += trade.volume;
+                trades += 1;
+            }
+        }
+    }
+
+    // Only the two signals beyond the deadband trade, at half size on weak momentum
+    assert_eq!(trades, 2);
+    assert_eq!(position, -2);
+    assert_eq!(
+        state.connection_performance.lock().await[&0].session.stance,
+        Stance::Short
+    );
+
+    // A reconnect mid-game drops the stance once the next update reconciles
+    common::join(&mut handler).await;
+    let frame = common::state_frame(100.0, 0.0, 0.0, position, 8.0);
+    handler.handle_text(&frame, &mut Vec::new()).await;
+    assert_eq!(
+        state.connection_performance.lock().await[&0].session.stance,
+        Stance::Flat
+    );
+}
+
+fn ensemble_state(quorum: usize) -> (Arc<ManualClock>, SharedState) {
+    let mut config = common::test_config();
+    config.ensemble.mode = SignalMode::Ensemble;
+    config.ensemble.quorum = quorum;
+    let clock = Arc::new(ManualClock::new(0.0));
+    let state = SharedState::with_clock(&config, clock.clone());
+    (clock, state)
+}
+
+#[test]
+fn median_of_odd_and_even_counts() {
+    assert_eq!(median(&mut []), None);
+    assert_eq!(median(&mut [0.3, -0.9, 0.1]), Some(0.1));
+    assert_eq!(median(&mut [0.4, -0.2, 0.6, 0.0]), Some(0.2));
+}
+
+#[test]
+fn outliers_are_clamped_to_a_multiple_of_the_mad() {
+    // Median 2, MAD 1, so anything past 2 ± 5 is pulled in
+    let mut changes = [3.0, 1.0, 2.0, 3.0, 1.0, 2.0, 3000.0, -40.0];
+    assert_eq!(clamp_outliers(&mut changes, 5.0), 2);
+    assert_eq!(changes, [3.0, 1.0, 2.0, 3.0, 1.0, 2.0, 7.0, -3.0]);
+
+    // Mostly flat: the zeros don't shrink the MAD to nothing
+    let mut changes = [0.0, 0.0, 0.0, 0.0, 0.0, 1.0, -1.0, 2.0, 500.0];
+    assert_eq!(clamp_outliers(&mut changes, 5.0), 1);
+    assert_eq!(changes[8], 7.5);
+
+    let mut unchanged = [0.0; 4];
+    assert_eq!(clamp_outliers(&mut unchanged, 5.0), 0);
+    assert_eq!(clamp_outliers(&mut [], 5.0), 0);
+}
+
+#[optiva_ws::rt::test]
+async fn ensemble_waits_for_quorum_and_drops_stale_signals() {
+    let (clock, state) = ensemble_state(3);
+    assert_eq!(state.ensemble_signal(0, 0.9).await, None);
+    assert_eq!(state.ensemble_signal(1, -0.5).await, None);
+    assert_eq!(state.ensemble_signal(2, 0.1).await, Some(0.1));
+
+    // Connection 0 goes quiet, so the other two are no longer a quorum
+    clock.advance(1.5);
+    state.ensemble_signal(1, -0.5).await;
+    clock.advance(1.0);
+    assert_eq!(state.ensemble_signal(2, 0.2).await, None);
+    assert!(!state.latest_signals.lock().await.contains_key(&0));
+}
+
+#[optiva_ws::rt::test]
+async fn independent_mode_still_publishes_but_trades_alone() {
+    let (_, state) = state_at(0.0);
+    assert_eq!(state.ensemble_signal(0, 0.9).await, None);
+    assert_eq!(state.ensemble_signal(1, -0.9).await, None);
+    assert_eq!(state.latest_signals.lock().await.len(), 2);
+}
+
+#[optiva_ws::rt::test]
+async fn ensemble_sizes_by_the_consensus() {
+    let (_, state) = ensemble_state(2);
+    let params = StrategyParams::default();
+
+    // Alone, a strong positive reading buys to the limit
+    let alone =
+        determine_trade_volume(&BlendStrategy, &ctx(1.0, 12.0, 0, &params), 0, &state).await;
+    assert_eq!(alone.volume, 3);
+
+    // Two other connections read the market as falling
+    state.ensemble_signal(1, -0.8).await;
+    state.ensemble_signal(2, -0.6).await;
+    let outvoted =
+        determine_trade_volume(&BlendStrategy, &ctx(1.0, 12.0, 0, &params), 0, &state).await;
+    assert_eq!(outvoted.signal, -0.6);
+    assert_eq!(outvoted.volume, -3);
+}
+
+// A tick held on a signal of the given strength
+fn held(signal: f64, pnl_change: f64) -> PerformanceData {
+    PerformanceData {
+        position: 3,
+        signal,
+        ..perf(pnl_change, 0.0, 0.0)
+    }
+}
+
+// Six wins of 2 and four losses of 1 at a signal around 0.6
+fn sixty_percent_two_to_one() -> Vec<PerformanceData> {
+    let mut history: Vec<PerformanceData> = (0..6).map(|_| held(0.6, 2.0)).collect();
+    history.extend((0..4).map(|_| held(-0.55, -1.0)));
+    history
+}
+
+#[test]
+fn kelly_fraction_matches_the_formula() {
+    let params = StrategyParams::default();
+    let kelly = KellyEstimate::from_history(&sixty_percent_two_to_one(), 0.7, &params).unwrap();
+    assert_eq!(kelly.samples, 10);
+    assert_eq!(kelly.win_probability, 0.6);
+    assert_eq!(kelly.average_win, 2.0);
+    assert_eq!(kelly.average_loss, 1.0);
+    // Full Kelly is 0.6 - 0.4 / 2 = 0.4, halved by the default multiplier
+    assert!((kelly.fraction - 0.2).abs() < 1e-12);
+}
+
+#[test]
+fn kelly_is_floored_at_zero_without_an_edge() {
+    let params = StrategyParams::default();
+    let mut history: Vec<PerformanceData> = (0..4).map(|_| held(0.6, 1.0)).collect();
+    history.extend((0..6).map(|_| held(0.6, -1.0)));
+    let kelly = KellyEstimate::from_history(&history, 0.6, &params).unwrap();
+    assert_eq!(kelly.fraction, 0.0);
+}
+
+#[test]
+fn kelly_needs_enough_samples_in_the_same_bucket() {
+    let params = StrategyParams::default();
+    let history = sixty_percent_two_to_one();
+    // A weaker signal is judged on its own bucket, which is empty
+    assert_eq!(KellyEstimate::from_history(&history, 0.1, &params), None);
+    assert_eq!(
+        KellyEstimate::from_history(&history[..9], 0.6, &params),
+        None
+    );
+    // Flat ticks say nothing about the signal
+    let flat: Vec<PerformanceData> = history
+        .iter()
+        .map(|p| PerformanceData {
+            position: 0,
+            ..p.clone()
+        })
+        .collect();
+    assert_eq!(KellyEstimate::from_history(&flat, 0.6, &params), None);
+}
+
+#[optiva_ws::rt::test]
+async fn kelly_sizing_falls_back_to_proportional_until_estimated() {
+    let (_, state) = state_at(0.0);
+    let params = StrategyParams {
+        sizing: Sizing::Kelly,
+        ..StrategyParams::default()
+    };
+    let market = MarketContext {
+        position_limit: 10,
+        ..ctx(0.5, 7.0, 0, &params)
+    };
+
+    let proportional = determine_trade_volume(&BlendStrategy, &market, 0, &state).await;
+    assert_eq!(proportional.kelly_fraction, None);
+    assert_eq!(
+        proportional.volume,
+        (proportional.signal * 10.0).round() as i32
+    );
+
+    for record in sixty_percent_two_to_one() {
+        state.record_performance(record).await;
+    }
+    let kelly = determine_trade_volume(&BlendStrategy, &market, 0, &state).await;
+    assert!((kelly.kelly_fraction.unwrap() - 0.2).abs() < 1e-12);
+    assert_eq!(kelly.volume, 2);
+}
+
+fn agreement_state(on_conflict: Conflict) -> SharedState {
+    let mut config = common::test_config();
+    config.agreement = AgreementConfig {
+        required: true,
+        on_conflict,
+        ..AgreementConfig::default()
+    };
+    SharedState::with_clock(&config, Arc::new(ManualClock::new(0.0)))
+}
+
+#[test]
+fn opposite_signs_disagree() {
+    assert!(!signals_agree(0.6, -0.4, 0.05));
+    assert!(!signals_agree(-0.2, 0.3, 0.05));
+}
+
+#[test]
+fn a_negligible_signal_has_no_say() {
+    assert!(signals_agree(0.6, -0.01, 0.05));
+    assert!(signals_agree(0.0, -0.9, 0.05));
+}
+
+#[test]
+fn same_signs_agree() {
+    assert!(signals_agree(0.6, 0.4, 0.05));
+    assert!(signals_agree(-0.2, -0.3, 0.05));
+}
+
+#[test]
+fn reduce_keeps_only_the_part_toward_flat() {
+    assert_eq!(veto(4, -1, Conflict::Reduce), 1);
+    assert_eq!(veto(-5, 2, Conflict::Reduce), -2);
+    assert_eq!(veto(-1, 2, Conflict::Reduce), -1);
+    assert_eq!(veto(3, 0, Conflict::Reduce), 0);
+    assert_eq!(veto(2, 1, Conflict::Reduce), 0);
+}
+
+#[test]
+fn flatten_closes_the_position() {
+    assert_eq!(veto(4, -1, Conflict::Flatten), 1);
+    assert_eq!(veto(2, 1, Conflict::Flatten), -1);
+    assert_eq!(veto(0, 0, Conflict::Flatten), 0);
+}
+
+#[optiva_ws::rt::test]
+async fn conflicting_signals_are_vetoed_and_recorded() {
+    let state = agreement_state(Conflict::Reduce);
+    let params = StrategyParams::default();
+
+    // Momentum wins the blend, but the forecast says the other way
+    assert_eq!(blend_volume(-0.5, 12.0, 0, 0, &params, &state).await, 0);
+    assert_eq!(blend_volume(-0.5, 12.0, -2, 0, &params, &state).await, 2);
+
+    let history = state.trade_history.lock().await;
+    let last = history.back().unwrap();
+    assert!(!last.signals_agree);
+    assert_eq!(last.trade_volume, 2);
+    assert_eq!(last.vetoed_volume, 3);
+}
+
+#[optiva_ws::rt::test]
+async fn flatten_on_conflict_closes_out() {
+    let state = agreement_state(Conflict::Flatten);
+    let params = StrategyParams::default();
+
+    assert_eq!(blend_volume(-0.5, 12.0, 2, 0, &params, &state).await, -2);
+}
+
+#[optiva_ws::rt::test]
+async fn agreeing_or_negligible_signals_trade_as_usual() {
+    let state = agreement_state(Conflict::Reduce);
+    let params = StrategyParams::default();
+
+    assert_eq!(blend_volume(0.5, 8.0, -1, 0, &params, &state).await, 4);
+    assert_eq!(blend_volume(-0.001, 8.0, -1, 0, &params, &state).await, 4);
+
+    let history = state.trade_history.lock().await;
+    assert!(history.iter().all(|signal| signal.signals_agree));
+    assert!(history.iter().all(|signal| signal.vetoed_volume == 0));
+}
+
+#[optiva_ws::rt::test]
+async fn without_the_requirement_conflicts_still_trade() {
+    let (_, state) = state_at(0.0);
+    let params = StrategyParams::default();
+
+    assert_eq!(blend_volume(-0.5, 12.0, 0, 0, &params, &state).await, 3);
+    let history = state.trade_history.lock().await;
+    assert!(!history.back().unwrap().signals_agree);
+    assert_eq!(history.back().unwrap().vetoed_volume, 0);
+}
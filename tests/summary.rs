@@ -0,0 +1,187 @@
+mod common;
+
+use common::{state_frame, temp_dir, test_config};
+use optiva_ws::clock::ManualClock;
+use optiva_ws::handler::ConnectionHandler;
+use optiva_ws::state::{SharedState, StrategyParams};
+use optiva_ws::summary::{append_summary, GameSummary};
+use serde_json::json;
+use std::sync::Arc;
+
+fn handler_at(time: f64) -> (Arc<ManualClock>, ConnectionHandler) {
+    let config = Arc::new(test_config());
+    let clock = Arc::new(ManualClock::new(time));
+    let state = Arc::new(SharedState::with_clock(&config, clock.clone()));
+    (clock, ConnectionHandler::new(0, config, state))
+}
+
+async fn feed(handler: &mut ConnectionHandler, frame: String) {
+    handler.handle_text(&frame, &mut Vec::new()).await;
+}
+
+fn finish(pnl: f64) -> String {
+    json!({ "event": "finish", "data": { "pnl": pnl } }).to_string()
+}
+
+#[optiva_ws::rt::test]
+async fn summary_covers_one_game() {
+    let (clock, mut handler) = handler_at(1000.0);
+    common::join(&mut handler).await;
+
+    // Buys to the limit, the fill is confirmed, then PnL swings
+    feed(&mut handler, state_frame(100.0, 0.5, 8.0, 0, 10.0)).await;
+    feed(&mut handler, state_frame(101.0, 0.5, 8.0, 3, 13.0)).await;
+    feed(&mut handler, state_frame(97.0, 0.5, 8.0, 3, 1.0)).await;
+    clock.advance(60.0);
+    feed(&mut handler, finish(5.0)).await;
+
+    let summary = handler.take_summary().unwrap();
+    assert_eq!(summary.conn_id, 0);
+    assert_eq!(summary.strategy, "blend");
+    assert_eq!(summary.finished_at - summary.started_at, 60.0);
+    assert_eq!(summary.trades, 1);
+    assert_eq!(summary.max_position, 3);
+    assert_eq!(summary.peak_pnl, Some(13.0));
+    assert_eq!(summary.low_pnl, Some(1.0));
+    assert_eq!(summary.largest_pnl_change, Some(-12.0));
+    assert_eq!(summary.final_pnl, Some(5.0));
+    assert_eq!(summary.params, StrategyParams::default());
+    assert!(handler.take_summary().is_none());
+}
+
+#[optiva_ws::rt::test]
+async fn pnl_is_put_down_to_what_drove_each_decision() {
+    let (_, mut handler) = handler_at(1000.0);
+    common::join(&mut handler).await;
+
+    // Momentum buys to the limit and makes 3, then the position it holds loses 12
+    feed(&mut handler, state_frame(100.0, 0.0, 25.0, 0, 10.0)).await;
+    feed(&mut handler, state_frame(101.0, 0.0, 25.0, 3, 13.0)).await;
+    feed(&mut handler, state_frame(97.0, 0.0, 25.0, 3, 1.0)).await;
+    // The forecast outweighs the momentum and turns it round to short, which makes 4
+    feed(&mut handler, state_frame(97.0, -2.0, -5.0, 3, 1.0)).await;
+    feed(&mut handler, state_frame(95.0, -2.0, -5.0, -3, 5.0)).await;
+    feed(&mut handler, finish(5.0)).await;
+
+    let summary = handler.take_summary().unwrap();
+    let attribution = summary.attribution;
+    assert_eq!(attribution.momentum.pnl, -9.0);
+    assert_eq!(
+        (attribution.momentum.trades, attribution.momentum.wins),
+        (1, 1)
+    );
+    assert_eq!(attribution.forecast.pnl, 4.0);
+    assert_eq!(attribution.forecast.win_rate(), Some(1.0));
+    assert_eq!(attribution.puzzle, Default::default());
+    let text = summary.to_string();
+    assert!(
+        text.contains("By driver:      momentum $-9.00 over 1 trades (100% won)"),
+        "{}",
+        text
+    );
+}
+
+#[optiva_ws::rt::test]
+async fn next_game_starts_from_scratch() {
+    let (_, mut handler) = handler_at(1000.0);
+    common::join(&mut handler).await;
+    feed(&mut handler, state_frame(100.0, 0.5, 8.0, 0, 10.0)).await;
+    feed(&mut handler, state_frame(101.0, 0.5, 8.0, 3, 13.0)).await;
+    feed(&mut handler, finish(13.0)).await;
+    handler.take_summary().unwrap();
+
+    // A game that is cut short by a disconnect is dropped on reconnect
+    common::join(&mut handler).await;
+    feed(&mut handler, state_frame(100.0, 0.0, 0.0, -3, 50.0)).await;
+    common::join(&mut handler).await;
+
+    feed(&mut handler, state_frame(100.0, 0.0, 0.0, 1, 2.0)).await;
+    feed(&mut handler, finish(2.0)).await;
+
+    let summary = handler.take_summary().unwrap();
+    assert_eq!(summary.trades, 0);
+    assert_eq!(summary.win_rate, None);
+    assert_eq!(summary.max_position, 1);
+    assert_eq!(summary.peak_pnl, Some(2.0));
+    assert_eq!(summary.largest_pnl_change, None);
+}
+
+fn counting_down(price: f64, position: i32, pnl: f64, remaining: u32) -> String {
+    let mut frame: serde_json::Value =
+        serde_json::from_str(&state_frame(price, 0.0, 0.0, position, pnl)).unwrap();
+    frame["data"]["updates_remaining"] = json!(remaining);
+    frame.to_string()
+}
+
+#[optiva_ws::rt::test]
+async fn a_reconnect_into_the_next_game_closes_out_the_last() {
+    let (_, mut handler) = handler_at(1000.0);
+    common::join(&mut handler).await;
+    feed(&mut handler, counting_down(100.0, 2, 7.0, 5)).await;
+    feed(&mut handler, counting_down(101.0, 2, 9.0, 4)).await;
+
+    // Away for the finish; back mid-game as far as we know, but the count went up
+    common::join(&mut handler).await;
+    feed(&mut handler, counting_down(100.0, 0, 0.0, 50)).await;
+    let summary = handler.take_summary().unwrap();
+    assert_eq!(summary.final_pnl, Some(9.0));
+    // The new game's update is all it has seen
+    assert_eq!(handler.price_history().len(), 1);
+
+    feed(&mut handler, counting_down(100.0, 0, 0.0, 49)).await;
+    feed(&mut handler, finish(0.0)).await;
+    let summary = handler.take_summary().unwrap();
+    assert_eq!(summary.peak_pnl, Some(0.0));
+    assert_eq!(summary.max_position, 0);
+}
+
+#[optiva_ws::rt::test]
+async fn summaries_append_as_json_lines() {
+    let (_, mut handler) = handler_at(1000.0);
+    common::join(&mut handler).await;
+    feed(&mut handler, finish(0.0)).await;
+    let summary = handler.take_summary().unwrap();
+
+    let path = temp_dir("summaries").join("summaries.jsonl");
+    append_summary(&path, &summary).await.unwrap();
+    append_summary(&path, &summary).await.unwrap();
+
+    let text = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<GameSummary> = text
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines, vec![summary.clone(), summary]);
+}
+
+#[optiva_ws::rt::test]
+async fn pnl_curve_runs_from_the_first_update_across_a_reconnect() {
+    let (clock, mut handler) = handler_at(1000.0);
+    common::join(&mut handler).await;
+    clock.advance(5.0);
+    feed(&mut handler, state_frame(100.0, 0.0, 0.0, 0, 0.0)).await;
+    clock.advance(1.0);
+    feed(&mut handler, state_frame(100.0, 0.0, 0.0, 0, 4.0)).await;
+
+    // Dropped mid-game, and back a few seconds later
+    clock.advance(3.0);
+    common::join(&mut handler).await;
+    feed(&mut handler, state_frame(100.0, 0.0, 0.0, 0, -2.0)).await;
+    feed(&mut handler, finish(-2.0)).await;
+
+    let summary = handler.take_summary().unwrap();
+    assert_eq!(summary.curve, [(0.0, 0.0), (1.0, 4.0), (4.0, -2.0)]);
+    assert!(summary.to_string().contains("PnL curve:"));
+    // Not part of the JSON line
+    assert!(serde_json::to_value(&summary)
+        .unwrap()
+        .get("curve")
+        .is_none());
+
+    // The next game starts a new curve
+    common::join(&mut handler).await;
+    clock.advance(10.0);
+    feed(&mut handler, state_frame(100.0, 0.0, 0.0, 0, 1.0)).await;
+    feed(&mut handler, finish(1.0)).await;
+    assert_eq!(handler.take_summary().unwrap().curve, [(0.0, 1.0)]);
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use optiva_ws::config::{Config, ConfigError};
+use optiva_ws::rt;
+use optiva_ws::shutdown::Shutdown;
+use optiva_ws::state::{Health, SharedState};
+use optiva_ws::supervisor::{supervise, Restart, RestartTracker, SupervisorConfig};
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn quick(max_restarts: usize, quarantine_secs: f64) -> SupervisorConfig {
+    SupervisorConfig {
+        max_restarts,
+        window_secs: 60.0,
+        restart_delay_secs: 0.0,
+        quarantine_secs,
+    }
+}
+
+#[test]
+fn too_many_restarts_in_the_window_quarantine() {
+    let config = SupervisorConfig::default();
+    let mut tracker = RestartTracker::default();
+    let restart = Restart::After(Duration::from_secs(1));
+    assert_eq!(tracker.record(&config, 0.0), restart);
+    assert_eq!(tracker.record(&config, 10.0), restart);
+    assert_eq!(tracker.record(&config, 20.0), restart);
+    assert_eq!(
+        tracker.record(&config, 30.0),
+        Restart::Quarantine(Duration::from_secs(300))
+    );
+    // A quarantine starts the count again
+    assert_eq!(tracker.record(&config, 31.0), restart);
+}
+
+#[test]
+fn restarts_age_out_of_the_window() {
+    let config = SupervisorConfig::default();
+    let mut tracker = RestartTracker::default();
+    for at in [0.0, 30.0, 59.0, 60.0, 90.0, 119.5] {
+        assert!(matches!(tracker.record(&config, at), Restart::After(_)));
+    }
+}
+
+#[optiva_ws::rt::test]
+async fn a_panicked_task_is_restarted_with_its_performance_kept() {
+    let state = Arc::new(SharedState::new(&common::test_config()));
+    let starts = Arc::new(AtomicUsize::new(0));
+    let task_state = Arc::clone(&state);
+    let task_starts = Arc::clone(&starts);
+    supervise(
+        0,
+        quick(3, 60.0),
+        Arc::clone(&state),
+        Shutdown::new(),
+        move || {
+            let state = Arc::clone(&task_state);
+            let starts = Arc::clone(&task_starts);
+            async move {
+                state.set_health(0, Health::Live).await;
+                state.record_stale_trade(0).await;
+                if starts.fetch_add(1, Ordering::SeqCst) < 2 {
+                    panic!("lost the plot");
+                }
+            }
+        },
+    )
+    .await;
+
+    // Two panics, then a task that finished by itself and wasn't restarted
+    assert_eq!(starts.load(Ordering::SeqCst), 3);
+    let snapshot = state.snapshot().await;
+    assert_eq!(snapshot.connections[0].stale_trades, 3);
+    assert_eq!(snapshot.connections[0].health, Health::Live);
+}
+
+#[optiva_ws::rt::test]
+async fn a_crash_loop_is_quarantined_until_shutdown() {
+    let state = Arc::new(SharedState::new(&common::test_config()));
+    let shutdown = Shutdown::new();
+    let starts = Arc::new(AtomicUsize::new(0));
+    let task_starts = Arc::clone(&starts);
+    let supervisor = rt::spawn(supervise(
+        0,
+        quick(1, 60.0),
+        Arc::clone(&state),
+        shutdown.clone(),
+        move || {
+            let starts = Arc::clone(&task_starts);
+            async move {
+                starts.fetch_add(1, Ordering::SeqCst);
+                panic!("still broken");
+            }
+        },
+    ));
+
+    rt::sleep(Duration::from_millis(200)).await;
+    // Started, restarted once, then parked
+    assert_eq!(starts.load(Ordering::SeqCst), 2);
+    let snapshot = state.snapshot().await;
+    assert_eq!(snapshot.connections[0].health, Health::Dead);
+    assert_eq!(
+        serde_json::to_value(&snapshot).unwrap()["connections"][0]["health"],
+        json!("dead")
+    );
+
+    shutdown.trigger();
+    supervisor.await;
+    assert_eq!(starts.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn supervisor_settings_are_validated() {
+    let parse = |supervisor: &str| {
+        Config::parse(&format!(
+            r#"
+            url = "wss://example.com"
+            player_id = "abc"
+
+            [supervisor]
+            {}
+            "#,
+            supervisor
+        ))
+    };
+    assert_eq!(parse("").unwrap().supervisor, SupervisorConfig::default());
+    for bad in [
+        "max_restarts = 0",
+        "window_secs = 0.0",
+        "restart_delay_secs = -1.0",
+        "quarantine_secs = 0.5",
+    ] {
+        assert!(
+            matches!(parse(bad), Err(ConfigError::Invalid(_))),
+            "{}",
+            bad
+        );
+    }
+}
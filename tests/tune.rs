@@ -0,0 +1,153 @@
+mod common;
+
+use common::{state_frame, temp_dir, test_config, PLAYER_ID};
+use optiva_ws::backtest::BacktestConfig;
+use optiva_ws::config::Config;
+use optiva_ws::persist::{load_params, save_params};
+use optiva_ws::transcript::{Direction, TranscriptEntry};
+use optiva_ws::tune::{
+    evaluate, format_results, run_tune, GridConfig, ParamRange, RankBy, TuneResult, TuneSource,
+};
+use serde_json::json;
+use std::sync::Arc;
+
+fn range(min: f64, max: f64, step: f64) -> Option<ParamRange> {
+    Some(ParamRange { min, max, step })
+}
+
+fn tune_config() -> Config {
+    let mut config = test_config();
+    config.backtest = BacktestConfig {
+        updates_per_game: 30,
+        puzzle_chance: 0.1,
+        ..BacktestConfig::default()
+    };
+    config.tune.games = 5;
+    config.tune.workers = 2;
+    config.tune.grid = GridConfig {
+        momentum_weight: range(0.2, 0.6, 0.2),
+        forecast_weight: range(0.5, 1.0, 0.5),
+        ..GridConfig::default()
+    };
+    config
+}
+
+#[test]
+fn ranges_include_both_ends() {
+    assert_eq!(
+        ParamRange {
+            min: 0.2,
+            max: 0.8,
+            step: 0.2
+        }
+        .values()
+        .len(),
+        4
+    );
+    assert_eq!(range(1.0, 1.0, 0.5).unwrap().values(), vec![1.0]);
+    assert!(!range(1.0, 0.0, 0.5).unwrap().is_valid());
+    assert!(!range(0.0, 1.0, 0.0).unwrap().is_valid());
+}
+
+#[test]
+fn grid_covers_every_combination() {
+    let config = tune_config();
+    let grid = &config.tune.grid;
+    assert_eq!(grid.names(), vec!["momentum_weight", "forecast_weight"]);
+    assert_eq!(grid.size(), 6);
+
+    let combinations = grid.combinations(&config.strategy);
+    assert_eq!(combinations.len(), 6);
+    assert_eq!(combinations[0].momentum_weight, 0.2);
+    assert_eq!(combinations[0].forecast_weight, 0.5);
+    assert_eq!(combinations[1].forecast_weight, 1.0);
+    // Params outside the grid keep their configured value
+    assert!(combinations
+        .iter()
+        .all(|params| params.aggressive_factor == config.strategy.aggressive_factor));
+}
+
+#[optiva_ws::rt::test]
+async fn synthetic_tuning_is_repeatable_and_ranked() {
+    let config = tune_config();
+    let mut progress = Vec::new();
+    let results = run_tune(&config, TuneSource::synthetic(&config), |done, total| {
+        progress.push((done, total))
+    })
+    .await;
+    assert_eq!(results.len(), 6);
+    assert_eq!(progress.last(), Some(&(6, 6)));
+    assert!(results.iter().all(|result| result.games == 5));
+    assert!(results
+        .iter()
+        .zip(results.iter().skip(1))
+        .all(|(better, worse)| better.mean_pnl >= worse.mean_pnl));
+
+    let again = run_tune(&config, TuneSource::synthetic(&config), |_, _| {}).await;
+    assert_eq!(results, again);
+
+    let table = format_results(&results, &config.tune.grid, 3);
+    assert!(table.contains("momentum_weight"));
+    assert_eq!(table.lines().count(), 4);
+}
+
+#[optiva_ws::rt::test]
+async fn ranks_by_sharpe_when_asked() {
+    let mut config = tune_config();
+    config.tune.rank_by = RankBy::Sharpe;
+    let results = run_tune(&config, TuneSource::synthetic(&config), |_, _| {}).await;
+    let sharpes: Vec<f64> = results
+        .iter()
+        .map(|result| result.sharpe.unwrap_or(f64::NEG_INFINITY))
+        .collect();
+    assert!(sharpes.windows(2).all(|pair| pair[0] >= pair[1]));
+}
+
+#[optiva_ws::rt::test]
+async fn transcript_games_are_scored_by_their_final_pnl() {
+    let entry = |timestamp: f64, frame: String| TranscriptEntry {
+        timestamp,
+        account: String::new(),
+        conn_id: 0,
+        direction: Direction::In,
+        frame,
+    };
+    let ack = json!({ "event": "connection", "data": { "player_id": PLAYER_ID } }).to_string();
+    let finish = json!({ "event": "finish", "data": { "pnl": 4.0 } }).to_string();
+    let entries = vec![
+        entry(100.0, ack),
+        entry(101.0, state_frame(100.0, 0.5, 8.0, 0, 0.0)),
+        entry(102.0, state_frame(101.0, 0.5, 8.0, 3, 3.0)),
+        entry(103.0, finish),
+    ];
+
+    let config = Arc::new(tune_config());
+    let result = evaluate(
+        Arc::clone(&config),
+        TuneSource::Transcript(Arc::new(entries)),
+        config.strategy.clone(),
+    )
+    .await;
+    assert_eq!(result.games, 1);
+}
+
+#[optiva_ws::rt::test]
+async fn best_result_saves_as_restorable_params() {
+    let config = tune_config();
+    let mut params = config.strategy.clone();
+    params.momentum_weight = 0.4;
+    let result = TuneResult {
+        params,
+        games: 5,
+        mean_pnl: 12.5,
+        sharpe: Some(0.8),
+    };
+
+    let path = temp_dir("tune").join("tuned.json");
+    let saved = result.to_saved(1000.0);
+    save_params(&path, &saved).await.unwrap();
+    let loaded = load_params(&path).unwrap();
+    assert_eq!(loaded.params.momentum_weight, 0.4);
+    assert_eq!(loaded.stats.samples, 5);
+    assert_eq!(loaded.saved_at, 1000.0);
+}